@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustedgedb::keys::KeyDecoder;
+
+// Drive the decoder with a component sequence chosen by the first byte
+fuzz_target!(|data: &[u8]| {
+    let Some((&plan, key)) = data.split_first() else {
+        return;
+    };
+
+    let mut decoder = KeyDecoder::new(key);
+    for step in 0..4 {
+        let ok = match (plan >> (step * 2)) & 0b11 {
+            0 => decoder.u64().is_ok(),
+            1 => decoder.i64().is_ok(),
+            2 => decoder.bytes().is_ok(),
+            _ => decoder.str().is_ok(),
+        };
+        if !ok {
+            break;
+        }
+    }
+});
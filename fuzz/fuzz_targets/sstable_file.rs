@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustedgedb::sstable::SSTable;
+use std::io::Write;
+
+// Open arbitrary bytes as an SSTable and read everything it claims to hold
+fuzz_target!(|data: &[u8]| {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(data).unwrap();
+
+    if let Ok(sstable) = SSTable::open(file.path()) {
+        let keys: Vec<Vec<u8>> = sstable
+            .iter()
+            .filter_map(|entry| entry.ok().map(|entry| entry.key))
+            .collect();
+        for key in keys {
+            let _ = sstable.get(&key);
+        }
+    }
+
+    // Lazily opened tables validate their index on first use instead
+    if let Ok(sstable) = SSTable::open_lazy(file.path()) {
+        let _ = (sstable.first_key(), sstable.last_key());
+        let _ = sstable.get(b"key");
+        let _ = sstable.iter().count();
+    }
+});
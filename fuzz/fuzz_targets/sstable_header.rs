@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustedgedb::sstable::SSTableHeader;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = SSTableHeader::decode(data);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustedgedb::wal::WAL;
+
+// Decode records back to back until the input is exhausted or invalid
+fuzz_target!(|data: &[u8]| {
+    let mut rest = data;
+    while let Ok(Some((_, consumed))) = WAL::read_record_from_slice(rest) {
+        rest = &rest[consumed..];
+    }
+});
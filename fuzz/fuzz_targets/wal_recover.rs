@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustedgedb::memtable::MemTable;
+use rustedgedb::wal::WAL;
+
+// Replay an arbitrary file as a WAL, including the corruption-skipping path
+fuzz_target!(|data: &[u8]| {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("wal.log");
+    std::fs::write(&path, data).unwrap();
+
+    if let Ok(wal) = WAL::new(&path) {
+        let memtable = MemTable::new(usize::MAX);
+        let _ = wal.recover(&memtable);
+    }
+});
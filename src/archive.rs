@@ -0,0 +1,339 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::io_backend::sync_dir;
+
+/// Errors that can occur while writing or reading an archive
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Corrupt archive: {0}")]
+    Corrupt(String),
+    #[error("Unsupported archive version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("Archive checksum mismatch: expected {expected:08x}, got {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+/// Result type for archive operations
+pub type ArchiveResult<T> = Result<T, ArchiveError>;
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"RARC";
+const ARCHIVE_VERSION: u32 = 1;
+/// Magic and version
+const ARCHIVE_HEADER_SIZE: u64 = 8;
+/// CRC32 and length of the compressed payload, then the magic again
+const ARCHIVE_FOOTER_SIZE: u64 = 16;
+
+/// Counts and checksums bytes on their way to the file
+struct ChecksumWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a single-file archive of named files
+///
+/// Layout: `RARC`, a u32 version, a zstd-compressed payload, then a footer of
+/// the payload's CRC32, its length and `RARC` again. The payload is a run of
+/// `<u16 name length> <name> <u64 length> <bytes>` entries closed by an empty
+/// name, so a truncated payload is caught even if its checksum matched.
+/// The archive is built under a temporary name and renamed when finished.
+pub struct ArchiveWriter {
+    encoder: zstd::stream::write::Encoder<'static, ChecksumWriter<BufWriter<File>>>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl ArchiveWriter {
+    /// Start writing an archive at `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> ArchiveResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&tmp_path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+        let checksummed = ChecksumWriter {
+            inner: writer,
+            hasher: crc32fast::Hasher::new(),
+            len: 0,
+        };
+        Ok(Self {
+            encoder: zstd::stream::write::Encoder::new(checksummed, 0)?,
+            tmp_path,
+            path,
+        })
+    }
+
+    /// Add an entry holding `data`
+    pub fn add_bytes(&mut self, name: &str, data: &[u8]) -> ArchiveResult<()> {
+        self.write_entry_header(name, data.len() as u64)?;
+        self.encoder.write_all(data)?;
+        Ok(())
+    }
+
+    /// Add an entry holding the contents of the file at `path`
+    pub fn add_file(&mut self, name: &str, path: &Path) -> ArchiveResult<()> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        self.write_entry_header(name, len)?;
+        let copied = io::copy(&mut file.take(len), &mut self.encoder)?;
+        if copied != len {
+            return Err(ArchiveError::Corrupt(format!(
+                "{:?} shrank while being archived",
+                path
+            )));
+        }
+        Ok(())
+    }
+
+    fn write_entry_header(&mut self, name: &str, len: u64) -> ArchiveResult<()> {
+        validate_name(name)?;
+        self.encoder.write_all(&(name.len() as u16).to_le_bytes())?;
+        self.encoder.write_all(name.as_bytes())?;
+        self.encoder.write_all(&len.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Write the footer, sync, and move the archive into place
+    ///
+    /// Returns the size of the archive in bytes.
+    pub fn finish(mut self) -> ArchiveResult<u64> {
+        self.encoder.write_all(&0u16.to_le_bytes())?;
+        let checksummed = self.encoder.finish()?;
+        let checksum = checksummed.hasher.finalize();
+        let payload_len = checksummed.len;
+        let mut writer = checksummed.inner;
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.write_all(&payload_len.to_le_bytes())?;
+        writer.write_all(ARCHIVE_MAGIC)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            sync_dir(dir)?;
+        }
+        Ok(ARCHIVE_HEADER_SIZE + payload_len + ARCHIVE_FOOTER_SIZE)
+    }
+}
+
+/// Name and size of one archived file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub len: u64,
+}
+
+/// Reads an archive written by [`ArchiveWriter`]
+///
+/// Opening checks the header, footer and payload checksum before any entry
+/// is read.
+pub struct ArchiveReader {
+    file: File,
+    payload_len: u64,
+}
+
+impl ArchiveReader {
+    /// Open and verify an archive
+    pub fn open<P: AsRef<Path>>(path: P) -> ArchiveResult<Self> {
+        let mut file = File::open(path.as_ref())?;
+        let file_len = file.metadata()?.len();
+        if file_len < ARCHIVE_HEADER_SIZE + ARCHIVE_FOOTER_SIZE {
+            return Err(ArchiveError::Corrupt("file too short".to_string()));
+        }
+
+        let mut header = [0u8; ARCHIVE_HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        if &header[..4] != ARCHIVE_MAGIC {
+            return Err(ArchiveError::Corrupt("bad magic".to_string()));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+
+        let mut footer = [0u8; ARCHIVE_FOOTER_SIZE as usize];
+        file.seek(SeekFrom::End(-(ARCHIVE_FOOTER_SIZE as i64)))?;
+        file.read_exact(&mut footer)?;
+        let expected = u32::from_le_bytes(footer[0..4].try_into().unwrap());
+        let payload_len = u64::from_le_bytes(footer[4..12].try_into().unwrap());
+        if &footer[12..16] != ARCHIVE_MAGIC
+            || payload_len != file_len - ARCHIVE_HEADER_SIZE - ARCHIVE_FOOTER_SIZE
+        {
+            return Err(ArchiveError::Corrupt("bad footer".to_string()));
+        }
+
+        file.seek(SeekFrom::Start(ARCHIVE_HEADER_SIZE))?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut payload = (&mut file).take(payload_len);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = payload.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+        }
+        let actual = hasher.finalize();
+        if actual != expected {
+            return Err(ArchiveError::ChecksumMismatch { expected, actual });
+        }
+
+        Ok(Self { file, payload_len })
+    }
+
+    /// Pass each entry's name and contents to `visit`, in archive order
+    ///
+    /// Whatever `visit` leaves unread is skipped.
+    pub fn for_each_entry(
+        mut self,
+        mut visit: impl FnMut(&str, &mut dyn Read) -> ArchiveResult<()>,
+    ) -> ArchiveResult<()> {
+        self.file.seek(SeekFrom::Start(ARCHIVE_HEADER_SIZE))?;
+        let payload = BufReader::new(self.file.take(self.payload_len));
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(payload)?;
+        loop {
+            let mut name_len = [0u8; 2];
+            read_payload(&mut decoder, &mut name_len)?;
+            let name_len = u16::from_le_bytes(name_len) as usize;
+            if name_len == 0 {
+                return Ok(());
+            }
+            let mut name = vec![0u8; name_len];
+            read_payload(&mut decoder, &mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| ArchiveError::Corrupt("entry name is not UTF-8".to_string()))?;
+            validate_name(&name)?;
+            let mut len = [0u8; 8];
+            read_payload(&mut decoder, &mut len)?;
+            let len = u64::from_le_bytes(len);
+
+            let mut contents = (&mut decoder).take(len);
+            visit(&name, &mut contents)?;
+            io::copy(&mut contents, &mut io::sink())?;
+            if contents.limit() != 0 {
+                return Err(ArchiveError::Corrupt(format!(
+                    "entry {:?} is truncated",
+                    name
+                )));
+            }
+        }
+    }
+
+    /// Names and sizes of every entry
+    pub fn entries(self) -> ArchiveResult<Vec<ArchiveEntry>> {
+        let mut entries = Vec::new();
+        self.for_each_entry(|name, contents| {
+            let len = io::copy(contents, &mut io::sink())?;
+            entries.push(ArchiveEntry {
+                name: name.to_string(),
+                len,
+            });
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+}
+
+/// Fill a buffer from the payload, reporting a short read as corruption
+fn read_payload(reader: &mut impl Read, buf: &mut [u8]) -> ArchiveResult<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ArchiveError::Corrupt("payload is truncated".to_string()),
+        _ => e.into(),
+    })
+}
+
+/// Entry names are relative paths that can't leave the directory they unpack into
+fn validate_name(name: &str) -> ArchiveResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= u16::MAX as usize
+        && !name.starts_with('/')
+        && !name.contains('\\')
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(ArchiveError::Corrupt(format!(
+            "invalid entry name {:?}",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source.bin");
+        std::fs::write(&source, vec![7u8; 10_000]).unwrap();
+
+        let path = temp_dir.path().join("backup.rarc");
+        let mut writer = ArchiveWriter::create(&path).unwrap();
+        writer.add_bytes("MANIFEST", b"manifest").unwrap();
+        writer.add_file("blobs/source.bin", &source).unwrap();
+        assert!(writer.add_bytes("../escape", b"").is_err());
+        let size = writer.finish().unwrap();
+        assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+        assert!(size < 10_000);
+
+        let entries = ArchiveReader::open(&path).unwrap().entries().unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ArchiveEntry {
+                    name: "MANIFEST".to_string(),
+                    len: 8
+                },
+                ArchiveEntry {
+                    name: "blobs/source.bin".to_string(),
+                    len: 10_000
+                },
+            ]
+        );
+
+        // Any flipped byte in the payload fails the checksum
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[ARCHIVE_HEADER_SIZE as usize + 2] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            ArchiveReader::open(&path),
+            Err(ArchiveError::ChecksumMismatch { .. })
+        ));
+        std::fs::write(&path, b"not an archive at all").unwrap();
+        assert!(matches!(
+            ArchiveReader::open(&path),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+}
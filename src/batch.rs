@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use crate::expiry;
+
+/// One write in a [`WriteBatch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// A put that expires at `expires_at`, in seconds since the Unix epoch
+    PutWithTtl {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires_at: u64,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+impl BatchOp {
+    /// Key the operation writes
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Self::Put { key, .. } | Self::PutWithTtl { key, .. } | Self::Delete { key } => key,
+        }
+    }
+
+    /// Bytes of the operation's key and value
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            Self::Put { key, value } | Self::PutWithTtl { key, value, .. } => {
+                key.len() + value.len()
+            }
+            Self::Delete { key } => key.len(),
+        }
+    }
+}
+
+/// Caps on a single [`WriteBatch`], so one call can't flood the WAL and MemTable
+///
+/// Set through `EngineConfig::batch_limits`. Without limits a batch only has to
+/// fit in the MemTable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// Bytes of keys and values a batch may carry (None leaves it unbounded)
+    pub max_bytes: Option<usize>,
+    /// Operations a batch may carry (None leaves it unbounded)
+    pub max_entries: Option<usize>,
+    /// Commit an oversized batch as consecutive sub-batches that are each
+    /// atomic, instead of rejecting it; a crash may then keep only the first
+    /// sub-batches
+    pub split: bool,
+}
+
+impl BatchLimits {
+    /// Whether `batch` is over either limit
+    pub fn is_exceeded_by(&self, batch: &WriteBatch) -> bool {
+        self.max_entries.is_some_and(|max| batch.len() > max)
+            || self.max_bytes.is_some_and(|max| batch.size_bytes() > max)
+    }
+
+    /// Split `batch` into runs of consecutive operations within the limits
+    ///
+    /// Returns the first operation that exceeds `max_bytes` on its own when
+    /// no split can fit it.
+    pub fn split(&self, batch: WriteBatch) -> Result<Vec<WriteBatch>, BatchOp> {
+        let max_entries = self.max_entries.unwrap_or(usize::MAX).max(1);
+        let max_bytes = self.max_bytes.unwrap_or(usize::MAX);
+        let mut chunks = Vec::new();
+        let mut chunk = WriteBatch::new();
+        let mut chunk_bytes = 0;
+        for op in batch {
+            let op_bytes = op.size_bytes();
+            if op_bytes > max_bytes {
+                return Err(op);
+            }
+            if chunk.len() == max_entries || chunk_bytes + op_bytes > max_bytes {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_bytes = 0;
+            }
+            chunk_bytes += op_bytes;
+            chunk.ops.push(op);
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+}
+
+/// Puts and deletes applied together by [`Engine::write_batch`]
+///
+/// Either every operation reaches the WAL and MemTable or none does, so
+/// related keys, such as an object and its secondary index, never disagree
+/// after a crash. Operations apply in the order they were added; a later
+/// write to the same key replaces an earlier one.
+///
+/// [`Engine::write_batch`]: crate::engine::Engine::write_batch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a put of `value` at `key`
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        self
+    }
+
+    /// Add a put of `value` at `key` that reads as missing once `ttl` has passed
+    ///
+    /// The deadline is taken from the clock now, in whole seconds rounded up.
+    pub fn put_with_ttl(&mut self, key: &[u8], value: &[u8], ttl: Duration) -> &mut Self {
+        self.ops.push(BatchOp::PutWithTtl {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            expires_at: expiry::deadline_after(ttl),
+        });
+        self
+    }
+
+    /// Add a delete of `key`
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Delete { key: key.to_vec() });
+        self
+    }
+
+    /// Operations in the order they were added
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    /// Number of operations in the batch
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the batch has no operations
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Remove every operation, keeping the allocation for reuse
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    /// Bytes of keys and values in the batch
+    pub fn size_bytes(&self) -> usize {
+        self.ops.iter().map(BatchOp::size_bytes).sum()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = BatchOp;
+    type IntoIter = std::vec::IntoIter<BatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_keeps_order() {
+        let mut batch = WriteBatch::new();
+        batch
+            .put(b"user:1", b"alice")
+            .delete(b"user:2")
+            .put(b"user:1", b"bob");
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.size_bytes(), 6 + 5 + 6 + 6 + 3);
+        let keys: Vec<&[u8]> = batch.ops().iter().map(BatchOp::key).collect();
+        assert_eq!(keys, [&b"user:1"[..], b"user:2", b"user:1"]);
+        assert_eq!(
+            batch.clone().into_iter().last(),
+            Some(BatchOp::Put {
+                key: b"user:1".to_vec(),
+                value: b"bob".to_vec()
+            })
+        );
+
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_batch_limits_split() {
+        let mut batch = WriteBatch::new();
+        batch
+            .put(b"a", b"1234")
+            .put(b"b", b"1234")
+            .delete(b"c")
+            .put(b"d", b"12345678");
+        let unlimited = BatchLimits::default();
+        assert!(!unlimited.is_exceeded_by(&batch));
+
+        let limits = BatchLimits {
+            max_bytes: Some(9),
+            max_entries: Some(3),
+            split: true,
+        };
+        assert!(limits.is_exceeded_by(&batch));
+        let sizes: Vec<usize> = limits
+            .split(batch.clone())
+            .unwrap()
+            .iter()
+            .map(WriteBatch::len)
+            .collect();
+        assert_eq!(sizes, [1, 2, 1]);
+        let by_count = BatchLimits {
+            max_entries: Some(3),
+            ..Default::default()
+        };
+        let chunks = by_count.split(batch.clone()).unwrap();
+        assert_eq!(
+            chunks.iter().map(WriteBatch::len).collect::<Vec<_>>(),
+            [3, 1]
+        );
+
+        // An operation over the byte cap can't be split off
+        let limits = BatchLimits {
+            max_bytes: Some(8),
+            ..limits
+        };
+        assert_eq!(limits.split(batch).unwrap_err().key(), b"d");
+    }
+
+    #[test]
+    fn test_batch_put_with_ttl() {
+        let mut batch = WriteBatch::new();
+        let before = expiry::now_secs();
+        batch.put_with_ttl(b"session", b"token", Duration::from_millis(1500));
+        assert_eq!(batch.size_bytes(), 7 + 5);
+        let BatchOp::PutWithTtl { expires_at, .. } = batch.ops()[0] else {
+            panic!("expected a put with a TTL");
+        };
+        assert!(expires_at >= before + 2 && expires_at <= expiry::now_secs() + 2);
+    }
+}
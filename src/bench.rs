@@ -0,0 +1,287 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::engine::{Engine, EngineConfig, EngineError, EngineResult};
+
+/// YCSB-style workload mixes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Insert `record_count` records
+    Load,
+    /// 95% reads, 5% updates (YCSB B)
+    ReadHeavy,
+    /// 50% reads, 50% updates (YCSB A)
+    WriteHeavy,
+    /// 95% short range scans, 5% inserts (YCSB E)
+    Scan,
+}
+
+impl Workload {
+    /// Every workload, in the order `bench all` runs them
+    pub const ALL: [Workload; 4] = [
+        Workload::Load,
+        Workload::ReadHeavy,
+        Workload::WriteHeavy,
+        Workload::Scan,
+    ];
+
+    /// Name used on the command line and in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::Load => "load",
+            Workload::ReadHeavy => "read-heavy",
+            Workload::WriteHeavy => "write-heavy",
+            Workload::Scan => "scan",
+        }
+    }
+}
+
+impl FromStr for Workload {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Workload::ALL
+            .into_iter()
+            .find(|w| w.name() == s)
+            .ok_or_else(|| EngineError::InvalidConfig(format!("Unknown workload: {}", s)))
+    }
+}
+
+/// Benchmark parameters
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Workload to run
+    pub workload: Workload,
+    /// Records inserted by `load` and preloaded before the other workloads
+    pub record_count: usize,
+    /// Operations timed by the read, write and scan workloads
+    pub operation_count: usize,
+    /// Size of each value in bytes
+    pub value_size: usize,
+    /// Keys returned by each scan
+    pub scan_length: usize,
+    /// Seed for key and operation choice, so runs are repeatable
+    pub seed: u64,
+    /// Engine configuration, including the target data directory
+    pub engine_config: EngineConfig,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            workload: Workload::Load,
+            record_count: 100_000,
+            operation_count: 100_000,
+            value_size: 100,
+            scan_length: 50,
+            seed: 42,
+            engine_config: EngineConfig::default(),
+        }
+    }
+}
+
+/// Latency percentiles over the timed operations
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summarize a set of per-operation latencies
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() * p / 100).min(samples.len() - 1)];
+        Self {
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// Result of one benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub workload: Workload,
+    pub operations: usize,
+    pub elapsed: Duration,
+    pub latency: LatencySummary,
+}
+
+impl BenchReport {
+    /// Operations per second
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.operations as f64 / secs
+        }
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<12} {:>10} ops {:>9.2?} {:>12.0} ops/s  p50 {:?}  p95 {:?}  p99 {:?}  max {:?}",
+            self.workload.name(),
+            self.operations,
+            self.elapsed,
+            self.throughput(),
+            self.latency.p50,
+            self.latency.p95,
+            self.latency.p99,
+            self.latency.max,
+        )
+    }
+}
+
+/// Run one workload against the configured data directory
+///
+/// Non-load workloads first insert any missing records, untimed, so they can
+/// be pointed at a fresh directory or one left behind by an earlier `load`.
+pub async fn run(config: &BenchConfig) -> EngineResult<BenchReport> {
+    let mut engine = Engine::with_config(config.engine_config.clone()).await?;
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let value = vec![b'x'; config.value_size];
+
+    let report = match config.workload {
+        Workload::Load => {
+            let mut samples = Vec::with_capacity(config.record_count);
+            let started = Instant::now();
+            for i in 0..config.record_count {
+                let op_started = Instant::now();
+                engine.put(&record_key(i), &value).await?;
+                samples.push(op_started.elapsed());
+            }
+            BenchReport {
+                workload: config.workload,
+                operations: config.record_count,
+                elapsed: started.elapsed(),
+                latency: LatencySummary::from_samples(samples),
+            }
+        }
+        workload => {
+            preload(&mut engine, config.record_count, &value).await?;
+            let read_percent = match workload {
+                Workload::ReadHeavy => 95,
+                Workload::WriteHeavy => 50,
+                _ => 0,
+            };
+            let record_count = config.record_count.max(1);
+            let mut inserted = config.record_count;
+
+            let mut samples = Vec::with_capacity(config.operation_count);
+            let started = Instant::now();
+            for _ in 0..config.operation_count {
+                let roll = rng.gen_range(0..100);
+                let key = record_key(rng.gen_range(0..record_count));
+                let op_started = Instant::now();
+                match workload {
+                    Workload::Scan if roll < 95 => {
+                        engine
+                            .scan_page(key.as_slice().., config.scan_length.max(1), None)
+                            .await?;
+                    }
+                    Workload::Scan => {
+                        engine.put(&record_key(inserted), &value).await?;
+                        inserted += 1;
+                    }
+                    _ if roll < read_percent => {
+                        engine.get(&key).await?;
+                    }
+                    _ => {
+                        engine.put(&key, &value).await?;
+                    }
+                }
+                samples.push(op_started.elapsed());
+            }
+            BenchReport {
+                workload,
+                operations: config.operation_count,
+                elapsed: started.elapsed(),
+                latency: LatencySummary::from_samples(samples),
+            }
+        }
+    };
+
+    engine.close().await?;
+    info!("Benchmark finished: {}", report);
+    Ok(report)
+}
+
+/// Key of the `i`th benchmark record
+pub fn record_key(i: usize) -> Vec<u8> {
+    format!("user{:012}", i).into_bytes()
+}
+
+/// Insert the first `count` records unless an earlier run already did
+async fn preload(engine: &mut Engine, count: usize, value: &[u8]) -> EngineResult<()> {
+    if count == 0 || engine.get(&record_key(count - 1)).await?.is_some() {
+        return Ok(());
+    }
+    for i in 0..count {
+        engine.put(&record_key(i), value).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_latency_percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_samples(samples);
+        assert_eq!(summary.p50, Duration::from_millis(51));
+        assert_eq!(summary.p99, Duration::from_millis(100));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        assert_eq!(
+            LatencySummary::from_samples(Vec::new()),
+            LatencySummary::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_workloads_run_against_data_dir() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = BenchConfig {
+            record_count: 200,
+            operation_count: 100,
+            value_size: 16,
+            scan_length: 5,
+            engine_config: EngineConfig {
+                data_dir: temp_dir.path().to_path_buf(),
+                memtable_size: 4096,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for workload in Workload::ALL {
+            config.workload = workload;
+            let report = run(&config).await.unwrap();
+            assert_eq!(report.workload, workload);
+            assert!(report.latency.p50 <= report.latency.max);
+        }
+
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        assert!(engine.get(&record_key(199)).await.unwrap().is_some());
+        assert_eq!("scan".parse::<Workload>().unwrap(), Workload::Scan);
+        assert!("zipf".parse::<Workload>().is_err());
+    }
+}
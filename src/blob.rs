@@ -0,0 +1,452 @@
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{info, trace, warn};
+
+use crate::io_backend::{self, IoBackend};
+
+/// Errors that can occur during blob operations
+#[derive(Error, Debug)]
+pub enum BlobError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid blob pointer: {0}")]
+    InvalidPointer(String),
+}
+
+/// Result type for blob operations
+pub type BlobResult<T> = Result<T, BlobError>;
+
+/// Size at which the active value log file is sealed and a new one started
+pub const VALUE_LOG_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Location of a value stored outside the LSM tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobPointer {
+    pub file_id: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl BlobPointer {
+    /// Size of an encoded pointer: file_id (8) + offset (8) + len (8)
+    pub const ENCODED_LEN: usize = 24;
+
+    /// Encode the pointer for storage as an indirect value
+    pub fn encode(&self) -> Bytes {
+        let mut buf = Vec::with_capacity(Self::ENCODED_LEN);
+        buf.extend_from_slice(&self.file_id.to_le_bytes());
+        buf.extend_from_slice(&self.offset.to_le_bytes());
+        buf.extend_from_slice(&self.len.to_le_bytes());
+        Bytes::from(buf)
+    }
+
+    /// Decode a pointer stored as an indirect value
+    pub fn decode(bytes: &[u8]) -> BlobResult<Self> {
+        if bytes.len() != Self::ENCODED_LEN {
+            return Err(BlobError::InvalidPointer(format!(
+                "Expected {} bytes, got {}",
+                Self::ENCODED_LEN,
+                bytes.len()
+            )));
+        }
+
+        let field = |i: usize| u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+        Ok(Self {
+            file_id: field(0),
+            offset: field(1),
+            len: field(2),
+        })
+    }
+
+    /// Pointer to at most `len` bytes of this value starting at `offset`
+    ///
+    /// The range is clamped to the value, so it may come back shorter or empty.
+    pub fn slice(&self, offset: u64, len: u64) -> Self {
+        let offset = offset.min(self.len);
+        Self {
+            file_id: self.file_id,
+            offset: self.offset + offset,
+            len: len.min(self.len - offset),
+        }
+    }
+}
+
+/// Directory of blob files holding values kept out of the LSM tree
+///
+/// Files are either written whole by a [`BlobWriter`] or appended to as a shared
+/// value log; both are addressed by [`BlobPointer`].
+pub struct BlobStore {
+    dir: PathBuf,
+    next_file_id: AtomicU64,
+    /// Value log file currently accepting appends
+    active: Mutex<Option<ActiveLog>>,
+    /// Live [`BlobPin`]s, counted by the first file id each leaves unpinned
+    pins: Mutex<BTreeMap<u64, usize>>,
+}
+
+/// Open value log file and its current length
+struct ActiveLog {
+    file_id: u64,
+    file: File,
+    len: u64,
+}
+
+impl BlobStore {
+    /// Open (or create) a blob directory
+    pub fn open<P: AsRef<Path>>(dir: P) -> BlobResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        // Continue numbering after the highest existing blob file
+        let max_file_id = Self::list_file_ids(&dir)?.into_iter().max().unwrap_or(0);
+
+        info!("Blob store opened at {:?}", dir);
+        Ok(Self {
+            dir,
+            next_file_id: AtomicU64::new(max_file_id + 1),
+            active: Mutex::new(None),
+            pins: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Open an existing store only to read from it, creating nothing
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            next_file_id: AtomicU64::new(u64::MAX),
+            active: Mutex::new(None),
+            pins: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Ids of all blob files on disk
+    pub fn file_ids(&self) -> BlobResult<Vec<u64>> {
+        Self::list_file_ids(&self.dir)
+    }
+
+    fn list_file_ids(dir: &Path) -> BlobResult<Vec<u64>> {
+        let mut file_ids = Vec::new();
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let name = entry.file_name();
+            if let Some(file_id) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix("blob_"))
+                .and_then(|n| n.strip_suffix(".blob"))
+                .and_then(|n| n.parse::<u64>().ok())
+            {
+                file_ids.push(file_id);
+            }
+        }
+        file_ids.sort_unstable();
+        Ok(file_ids)
+    }
+
+    /// Size of a blob file in bytes
+    pub fn file_len(&self, file_id: u64) -> BlobResult<u64> {
+        Ok(std::fs::metadata(self.blob_path(file_id))?.len())
+    }
+
+    /// Delete a blob file that no longer holds live values
+    pub fn remove(&self, file_id: u64) -> BlobResult<()> {
+        std::fs::remove_file(self.blob_path(file_id))?;
+        trace!("Removed blob file {}", file_id);
+        Ok(())
+    }
+
+    /// Overwrite a blob file with zeros, then delete it
+    pub fn shred(&self, file_id: u64) -> BlobResult<()> {
+        io_backend::shred_file(&self.blob_path(file_id))?;
+        trace!("Shredded blob file {}", file_id);
+        Ok(())
+    }
+
+    /// Path of the blob file with the given id
+    pub fn blob_path(&self, file_id: u64) -> PathBuf {
+        self.dir.join(format!("blob_{:016}.blob", file_id))
+    }
+
+    /// Start writing a new blob file
+    pub fn create(&self) -> BlobResult<BlobWriter> {
+        let file_id = self.next_file_id.fetch_add(1, Ordering::SeqCst);
+        let path = self.blob_path(file_id);
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)?;
+
+        Ok(BlobWriter {
+            file_id,
+            path,
+            writer: BufWriter::new(file),
+            len: 0,
+            finished: false,
+        })
+    }
+
+    /// Append a value to the active value log, starting a new log file when it is full
+    pub fn append(&self, value: &[u8]) -> BlobResult<BlobPointer> {
+        let mut active = self.active.lock().unwrap();
+        let log = match active.take() {
+            Some(log) if log.len < VALUE_LOG_FILE_SIZE => log,
+            _ => {
+                let file_id = self.next_file_id.fetch_add(1, Ordering::SeqCst);
+                let file = OpenOptions::new()
+                    .create_new(true)
+                    .append(true)
+                    .open(self.blob_path(file_id))?;
+                trace!("Started value log file {}", file_id);
+                ActiveLog {
+                    file_id,
+                    file,
+                    len: 0,
+                }
+            }
+        };
+        let log = active.insert(log);
+        IoBackend::current().append(&log.file, value)?;
+        let pointer = BlobPointer {
+            file_id: log.file_id,
+            offset: log.len,
+            len: value.len() as u64,
+        };
+        log.len += value.len() as u64;
+        Ok(pointer)
+    }
+
+    /// Id of the value log file currently accepting appends, if any
+    pub fn active_file_id(&self) -> Option<u64> {
+        self.active.lock().unwrap().as_ref().map(|log| log.file_id)
+    }
+
+    /// Stop appending to the active value log so it can be garbage collected
+    pub fn seal_active(&self) {
+        self.active.lock().unwrap().take();
+    }
+
+    /// Keep every blob file that exists now from being collected until the
+    /// returned pin is dropped
+    ///
+    /// Taken by readers that hold blob pointers outside the current version,
+    /// such as iterators, which may follow them after garbage collection has
+    /// re-pointed or dropped the values. Files created later are not pinned.
+    pub fn pin(self: &Arc<Self>) -> BlobPin {
+        let below = self.next_file_id.load(Ordering::SeqCst);
+        *self.pins.lock().unwrap().entry(below).or_default() += 1;
+        BlobPin {
+            store: self.clone(),
+            below,
+        }
+    }
+
+    /// Whether a live [`BlobPin`] covers `file_id`
+    pub fn is_pinned(&self, file_id: u64) -> bool {
+        self.pins
+            .lock()
+            .unwrap()
+            .last_key_value()
+            .is_some_and(|(below, _)| file_id < *below)
+    }
+
+    /// Read the value a pointer refers to
+    pub fn read(&self, pointer: &BlobPointer) -> BlobResult<Bytes> {
+        let file = File::open(self.blob_path(pointer.file_id))?;
+        let mut buf = vec![0u8; pointer.len as usize];
+        IoBackend::current().read_exact_at(&file, &mut buf, pointer.offset)?;
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Guard keeping the blob files that existed when it was taken on disk
+///
+/// See [`BlobStore::pin`].
+pub struct BlobPin {
+    store: Arc<BlobStore>,
+    below: u64,
+}
+
+impl Drop for BlobPin {
+    fn drop(&mut self) {
+        let mut pins = self.store.pins.lock().unwrap();
+        if let Some(count) = pins.get_mut(&self.below) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.below);
+            }
+        }
+    }
+}
+
+/// Streams a single value into its own blob file
+///
+/// The file is removed if the writer is dropped without calling [`BlobWriter::finish`].
+pub struct BlobWriter {
+    file_id: u64,
+    path: PathBuf,
+    writer: BufWriter<File>,
+    len: u64,
+    finished: bool,
+}
+
+impl BlobWriter {
+    /// Number of bytes written so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Check if nothing has been written yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Flush and sync the blob, returning a pointer to its contents
+    pub fn finish(mut self) -> BlobResult<BlobPointer> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.finished = true;
+
+        trace!("Blob {} finished with {} bytes", self.file_id, self.len);
+        Ok(BlobPointer {
+            file_id: self.file_id,
+            offset: 0,
+            len: self.len,
+        })
+    }
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        // Abandoned before finish: the blob was never referenced, so remove it
+        if !self.finished
+            && let Err(e) = std::fs::remove_file(&self.path)
+        {
+            warn!("Failed to remove abandoned blob {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_blob_pointer_roundtrip() {
+        let pointer = BlobPointer {
+            file_id: 7,
+            offset: 4096,
+            len: 123,
+        };
+        assert_eq!(BlobPointer::decode(&pointer.encode()).unwrap(), pointer);
+        assert!(BlobPointer::decode(b"short").is_err());
+
+        let slice = pointer.slice(100, 50);
+        assert_eq!((slice.offset, slice.len), (4196, 23));
+        assert_eq!(pointer.slice(500, 10).len, 0);
+    }
+
+    #[test]
+    fn test_blob_write_and_read() {
+        let temp_dir = tempdir().unwrap();
+        let store = BlobStore::open(temp_dir.path()).unwrap();
+
+        let mut writer = store.create().unwrap();
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"blob").unwrap();
+        assert_eq!(writer.len(), 10);
+        let pointer = writer.finish().unwrap();
+
+        assert_eq!(pointer.len, 10);
+        assert_eq!(
+            store.read(&pointer).unwrap(),
+            Bytes::from_static(b"hello blob")
+        );
+
+        // Reopening continues numbering after existing blobs
+        let reopened = BlobStore::open(temp_dir.path()).unwrap();
+        let next = reopened.create().unwrap().file_id;
+        assert!(next > pointer.file_id);
+    }
+
+    #[test]
+    fn test_value_log_append() {
+        let temp_dir = tempdir().unwrap();
+        let store = BlobStore::open(temp_dir.path()).unwrap();
+
+        let first = store.append(b"first value").unwrap();
+        let second = store.append(b"second").unwrap();
+        assert_eq!(first.file_id, second.file_id);
+        assert_eq!(second.offset, first.len);
+        assert_eq!(store.active_file_id(), Some(first.file_id));
+
+        assert_eq!(
+            store.read(&first).unwrap(),
+            Bytes::from_static(b"first value")
+        );
+        assert_eq!(store.read(&second).unwrap(), Bytes::from_static(b"second"));
+
+        // Sealing starts a new log file on the next append
+        store.seal_active();
+        let third = store.append(b"third").unwrap();
+        assert_ne!(third.file_id, first.file_id);
+        assert_eq!(third.offset, 0);
+        assert_eq!(
+            store.file_ids().unwrap(),
+            vec![first.file_id, third.file_id]
+        );
+
+        store.remove(first.file_id).unwrap();
+        assert_eq!(store.file_ids().unwrap(), vec![third.file_id]);
+    }
+
+    #[test]
+    fn test_pins_cover_existing_files() {
+        let temp_dir = tempdir().unwrap();
+        let store = Arc::new(BlobStore::open(temp_dir.path()).unwrap());
+
+        let old = store.append(b"old").unwrap();
+        assert!(!store.is_pinned(old.file_id));
+        let pin = store.pin();
+        let second = store.pin();
+        store.seal_active();
+        let new = store.append(b"new").unwrap();
+        assert!(store.is_pinned(old.file_id));
+        assert!(!store.is_pinned(new.file_id));
+
+        drop(pin);
+        assert!(store.is_pinned(old.file_id));
+        drop(second);
+        assert!(!store.is_pinned(old.file_id));
+    }
+
+    #[test]
+    fn test_abandoned_blob_is_removed() {
+        let temp_dir = tempdir().unwrap();
+        let store = BlobStore::open(temp_dir.path()).unwrap();
+
+        let mut writer = store.create().unwrap();
+        writer.write_all(b"partial").unwrap();
+        let path = store.blob_path(writer.file_id);
+        assert!(path.exists());
+
+        drop(writer);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,281 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Default byte budget of the block cache
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 8 * 1024 * 1024;
+
+/// A cached block: the table it was read from and its offset in the file
+type BlockKey = (u64, u64);
+
+/// LRU cache of SSTable data blocks, shared by every table of an engine
+///
+/// Blocks are charged by their length against a byte budget, and the least
+/// recently used blocks are evicted to make room. Blocks of a table are
+/// dropped with the table.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: AtomicUsize,
+    state: Mutex<LruState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    next_table_id: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    /// Each block with the tick of its last use
+    blocks: HashMap<BlockKey, (Bytes, u64)>,
+    /// Blocks by last use, oldest first
+    order: BTreeMap<u64, BlockKey>,
+    tick: u64,
+    used: usize,
+}
+
+impl LruState {
+    fn touch(&mut self, key: BlockKey) -> Option<Bytes> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (block, last_used) = self.blocks.get_mut(&key)?;
+        self.order.remove(last_used);
+        *last_used = tick;
+        self.order.insert(tick, key);
+        Some(block.clone())
+    }
+
+    /// Evict the least recently used blocks until at most `budget` bytes remain
+    fn evict_to(&mut self, budget: usize) {
+        while self.used > budget {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.blocks.remove(&oldest) {
+                self.used -= evicted.len();
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &BlockKey) {
+        if let Some((block, last_used)) = self.blocks.remove(key) {
+            self.order.remove(&last_used);
+            self.used -= block.len();
+        }
+    }
+}
+
+/// Block cache counters, from [`crate::engine::EngineStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct BlockCacheStats {
+    /// Byte budget
+    pub capacity: usize,
+    /// Bytes of cached blocks
+    pub used_bytes: usize,
+    pub blocks: usize,
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads that went to disk
+    pub misses: u64,
+}
+
+impl BlockCacheStats {
+    /// Share of reads served from the cache, or 0 before any read
+    pub fn hit_rate(&self) -> f64 {
+        let reads = self.hits + self.misses;
+        if reads == 0 {
+            0.0
+        } else {
+            self.hits as f64 / reads as f64
+        }
+    }
+}
+
+impl BlockCache {
+    /// Create a cache holding at most `capacity` bytes of blocks
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: AtomicUsize::new(capacity),
+            state: Mutex::new(LruState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            next_table_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Id under which a table's blocks are cached, unique for this cache
+    pub fn new_table_id(&self) -> u64 {
+        self.next_table_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Look up the block at `offset` of `table`, counting a hit or miss
+    pub fn get(&self, table: u64, offset: u64) -> Option<Bytes> {
+        let block = self.state.lock().unwrap().touch((table, offset));
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    /// Cache the block at `offset` of `table`, evicting the least recently
+    /// used blocks to stay within the budget
+    ///
+    /// Blocks larger than the whole budget aren't cached.
+    pub fn insert(&self, table: u64, offset: u64, block: Bytes) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if block.len() > capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let key = (table, offset);
+        state.remove(&key);
+        state.evict_to(capacity - block.len());
+        state.tick += 1;
+        let tick = state.tick;
+        state.used += block.len();
+        state.order.insert(tick, key);
+        state.blocks.insert(key, (block, tick));
+    }
+
+    /// Return the cached block at `offset` of `table`, or read and cache it
+    pub fn get_or_read<E>(
+        &self,
+        table: u64,
+        offset: u64,
+        read: impl FnOnce() -> Result<Bytes, E>,
+    ) -> Result<Bytes, E> {
+        if let Some(block) = self.get(table, offset) {
+            return Ok(block);
+        }
+        let block = read()?;
+        self.insert(table, offset, block.clone());
+        Ok(block)
+    }
+
+    /// Change the byte budget, evicting the least recently used blocks that
+    /// no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.state.lock().unwrap().evict_to(capacity);
+    }
+
+    /// Drop every cached block of `table`
+    pub fn remove_table(&self, table: u64) {
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<BlockKey> = state
+            .blocks
+            .keys()
+            .filter(|(id, _)| *id == table)
+            .copied()
+            .collect();
+        for key in keys {
+            state.remove(&key);
+        }
+    }
+
+    /// Current usage and hit/miss counters
+    pub fn stats(&self) -> BlockCacheStats {
+        let state = self.state.lock().unwrap();
+        BlockCacheStats {
+            capacity: self.capacity.load(Ordering::Relaxed),
+            used_bytes: state.used,
+            blocks: state.blocks.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(len: usize) -> Bytes {
+        Bytes::from(vec![0u8; len])
+    }
+
+    #[test]
+    fn test_block_cache_evicts_least_recently_used() {
+        let cache = BlockCache::new(300);
+        let table = cache.new_table_id();
+        cache.insert(table, 0, block(100));
+        cache.insert(table, 100, block(100));
+        cache.insert(table, 200, block(100));
+
+        // Reading the first block makes the second the oldest
+        assert!(cache.get(table, 0).is_some());
+        cache.insert(table, 300, block(100));
+        assert!(cache.get(table, 100).is_none());
+        assert!(cache.get(table, 0).is_some());
+        assert!(cache.get(table, 300).is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.used_bytes, stats.blocks), (300, 3));
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+        assert_eq!(stats.hit_rate(), 0.75);
+
+        // Blocks over budget are read through without evicting anything
+        assert_eq!(
+            cache
+                .get_or_read(table, 400, || Ok::<_, ()>(block(301)))
+                .unwrap()
+                .len(),
+            301
+        );
+        assert_eq!(cache.stats().blocks, 3);
+    }
+
+    #[test]
+    fn test_block_cache_set_capacity() {
+        let cache = BlockCache::new(300);
+        let table = cache.new_table_id();
+        for offset in [0, 100, 200] {
+            cache.insert(table, offset, block(100));
+        }
+        assert!(cache.get(table, 0).is_some());
+
+        // Shrinking evicts the oldest blocks first
+        cache.set_capacity(150);
+        let stats = cache.stats();
+        assert_eq!((stats.capacity, stats.used_bytes), (150, 100));
+        assert!(cache.get(table, 0).is_some());
+
+        cache.set_capacity(0);
+        cache.insert(table, 300, block(1));
+        assert_eq!(cache.stats().blocks, 0);
+
+        cache.set_capacity(200);
+        cache.insert(table, 100, block(100));
+        cache.insert(table, 200, block(100));
+        assert_eq!(cache.stats().used_bytes, 200);
+    }
+
+    #[test]
+    fn test_block_cache_remove_table() {
+        let cache = BlockCache::new(1024);
+        let (a, b) = (cache.new_table_id(), cache.new_table_id());
+        assert_ne!(a, b);
+        cache.insert(a, 0, block(10));
+        cache.insert(b, 0, block(20));
+        cache.insert(a, 10, block(30));
+
+        cache.remove_table(a);
+        let stats = cache.stats();
+        assert_eq!((stats.used_bytes, stats.blocks), (20, 1));
+        assert!(cache.get(b, 0).is_some());
+
+        let mut reads = 0;
+        for _ in 0..2 {
+            cache
+                .get_or_read(a, 0, || {
+                    reads += 1;
+                    Ok::<_, ()>(block(10))
+                })
+                .unwrap();
+        }
+        assert_eq!(reads, 1);
+    }
+}
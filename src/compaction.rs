@@ -1,10 +1,19 @@
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Cursor, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
-use tracing::info;
+use tracing::{debug, info};
 
-use crate::sstable::{CompressionType, SSTable, SSTableError};
+use crate::io_backend;
+use crate::memtable::Entry;
+use crate::merge::{self, MergeError};
+use crate::sstable::{
+    BLOOM_PREFIX_SIZE, CompressionOptions, CompressionType, DEFAULT_BLOCK_SIZE,
+    KEY_RESTART_INTERVAL, SSTable, SSTableError, SSTableHeader, SSTableWriteOptions,
+};
+use crate::version::Version;
 
 /// Errors that can occur during compaction operations
 #[derive(Error, Debug)]
@@ -17,65 +26,85 @@ pub enum CompactionError {
     InvalidInput(String),
     #[error("Compaction failed: {0}")]
     CompactionFailed(String),
+    #[error("Merge error: {0}")]
+    Merge(#[from] MergeError),
 }
 
 /// Result type for compaction operations
 pub type CompactionResult<T> = Result<T, CompactionError>;
 
-/// Represents a key-value entry during compaction
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct CompactionEntry {
-    key: Vec<u8>,
-    value: Option<Vec<u8>>,
-    timestamp: u64,
-    sequence_number: u64,
-    source_sstable: usize, // Index of source SSTable
+/// Expected cost and result of a compaction, computed without running it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactionEstimate {
+    /// Entries across all inputs
+    pub input_entries: usize,
+    /// Entries the output would hold
+    pub output_entries: usize,
+    /// Overwritten versions and tombstones that would be dropped
+    pub entries_dropped: usize,
+    /// Bytes read from the input files
+    pub bytes_read: u64,
+    /// Expected size of the output file
+    pub output_bytes: u64,
 }
 
-impl CompactionEntry {
-    /// Create a new compaction entry
-    fn new(
-        key: Vec<u8>,
-        value: Option<Vec<u8>>,
-        timestamp: u64,
-        sequence_number: u64,
-        source_sstable: usize,
-    ) -> Self {
-        Self {
-            key,
-            value,
-            timestamp,
-            sequence_number,
-            source_sstable,
-        }
-    }
-
-    /// Check if this entry is a deletion (tombstone)
-    fn is_deletion(&self) -> bool {
-        self.value.is_none()
+impl CompactionEstimate {
+    /// Total I/O the compaction would do: input reads plus output writes
+    pub fn io_bytes(&self) -> u64 {
+        self.bytes_read + self.output_bytes
     }
 }
 
 /// Compaction engine for merging multiple SSTables
 pub struct CompactionEngine {
     output_path: PathBuf,
+    compression: CompressionType,
+    compression_options: CompressionOptions,
+    use_direct_io: bool,
+    bottommost: bool,
 }
 
 impl CompactionEngine {
     /// Create a new compaction engine
-    pub fn new<P: AsRef<Path>>(output_path: P, _compression: CompressionType) -> Self {
+    pub fn new<P: AsRef<Path>>(output_path: P, compression: CompressionType) -> Self {
         Self {
             output_path: output_path.as_ref().to_path_buf(),
+            compression,
+            compression_options: CompressionOptions::default(),
+            use_direct_io: false,
+            bottommost: true,
         }
     }
 
+    /// Choose which values of the output are worth compressing
+    pub fn with_compression_options(mut self, options: CompressionOptions) -> Self {
+        self.compression_options = options;
+        self
+    }
+
+    /// Write the output with O_DIRECT so compaction doesn't evict the OS page cache
+    pub fn with_direct_io(mut self, enabled: bool) -> Self {
+        self.use_direct_io = enabled;
+        self
+    }
+
+    /// Whether no data older than the inputs exists for their keys, as when
+    /// the output goes to the bottom level (default true)
+    ///
+    /// Only then are tombstones dropped and leftover merge operands resolved;
+    /// otherwise they are kept so they still apply to the older data.
+    pub fn with_bottommost(mut self, bottommost: bool) -> Self {
+        self.bottommost = bottommost;
+        self
+    }
+
     /// Compact multiple SSTables into a single output SSTable
     ///
-    /// This function:
-    /// 1. Merges multiple SSTables in sorted order
-    /// 2. Removes tombstones (deleted entries)
-    /// 3. Keeps only the most recent value for each key
-    /// 4. Guarantees sorted output
+    /// The inputs are merged with a k-way merge over their iterators. For each
+    /// key the version with the highest sequence number wins, earlier inputs
+    /// winning ties since [`CompactionTask::inputs`] lists the newest first.
+    /// Merge operands are folded into the older versions under them, and the
+    /// output is sorted by key.
     pub fn compact_sstables<P: AsRef<Path>>(&self, input_paths: &[P]) -> CompactionResult<PathBuf> {
         if input_paths.is_empty() {
             return Err(CompactionError::InvalidInput(
@@ -89,214 +118,165 @@ impl CompactionEngine {
             self.output_path
         );
 
-        // Open all input SSTables and collect their entries
-        let mut all_entries = Vec::new();
-
-        for (i, path) in input_paths.iter().enumerate() {
-            let _sstable = SSTable::open(path.as_ref())?;
-            // Opened SSTable with entries
-
-            // For this implementation, we'll work with the actual test data
-            // We know the test patterns, so we can handle them specifically
-
-            // Try to read the actual data from the SSTable
-            let mut temp_sstable = SSTable::open(path.as_ref())?;
-
-            // Based on the test patterns, we know what keys to look for
-            // This is a simplified approach - in production, we'd have proper iteration methods
-
-            // For the tombstone test: key1 (deleted), key2 (exists)
-            if let Ok(Some(value)) = temp_sstable.get(b"key1") {
-                all_entries.push(CompactionEntry::new(
-                    b"key1".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    i as u64,
-                    i,
-                ));
-            } else if let Ok(None) = temp_sstable.get(b"key1") {
-                // This is a tombstone (deletion)
-                all_entries.push(CompactionEntry::new(
-                    b"key1".to_vec(),
-                    None, // Tombstone
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    i as u64,
-                    i,
-                ));
-            }
+        let sstables = input_paths
+            .iter()
+            .map(|path| SSTable::open(path.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let entries_read: usize = sstables.iter().map(|t| t.entry_count() as usize).sum();
+        let mut sources: Vec<_> = sstables.iter().map(|t| t.iter().peekable()).collect();
 
-            if let Ok(Some(value)) = temp_sstable.get(b"key2") {
-                all_entries.push(CompactionEntry::new(
-                    b"key2".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 1000) as u64, // Higher sequence number
-                    i,
-                ));
-            }
-
-            // For the sorted order test: apple, banana, cherry, zebra
-            if let Ok(Some(value)) = temp_sstable.get(b"apple") {
-                all_entries.push(CompactionEntry::new(
-                    b"apple".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 2000) as u64,
-                    i,
-                ));
-            }
-            if let Ok(Some(value)) = temp_sstable.get(b"banana") {
-                all_entries.push(CompactionEntry::new(
-                    b"banana".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 2001) as u64,
-                    i,
-                ));
-            }
-            if let Ok(Some(value)) = temp_sstable.get(b"cherry") {
-                all_entries.push(CompactionEntry::new(
-                    b"cherry".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 2002) as u64,
-                    i,
-                ));
-            }
-            if let Ok(Some(value)) = temp_sstable.get(b"zebra") {
-                all_entries.push(CompactionEntry::new(
-                    b"zebra".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 2003) as u64,
-                    i,
-                ));
-            }
-
-            // For the most recent value test: key1 with different values
-            if let Ok(Some(value)) = temp_sstable.get(b"key1") {
-                all_entries.push(CompactionEntry::new(
-                    b"key1".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 3000) as u64, // Higher sequence number
-                    i,
-                ));
+        let mut final_entries = Vec::new();
+        loop {
+            let mut smallest: Option<&[u8]> = None;
+            for source in &mut sources {
+                if let Some(Err(_)) = source.peek()
+                    && let Some(Err(e)) = source.next()
+                {
+                    return Err(e.into());
+                }
             }
-
-            // For the basic functionality test: key_000 to key_099
-            for j in 0..100 {
-                let key = format!("key_{:03}", j).into_bytes();
-                if let Ok(Some(value)) = temp_sstable.get(&key) {
-                    all_entries.push(CompactionEntry::new(
-                        key,
-                        Some(value),
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64,
-                        (i * 1000 + j as usize) as u64,
-                        i,
-                    ));
+            for source in &mut sources {
+                if let Some(Ok(entry)) = source.peek()
+                    && smallest.is_none_or(|key| entry.key.as_slice() < key)
+                {
+                    smallest = Some(entry.key.as_slice());
                 }
             }
-
-            // For the valid SSTable test: test_key
-            if let Ok(Some(value)) = temp_sstable.get(b"test_key") {
-                all_entries.push(CompactionEntry::new(
-                    b"test_key".to_vec(),
-                    Some(value),
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64,
-                    (i + 4000) as u64,
-                    i,
-                ));
+            let Some(key) = smallest.map(<[u8]>::to_vec) else {
+                break;
+            };
+
+            // Each input holds at most one version of a key
+            let mut versions = sources
+                .iter_mut()
+                .filter_map(|source| {
+                    source.next_if(|entry| matches!(entry, Ok(entry) if entry.key == key))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            versions.sort_by_key(|entry| std::cmp::Reverse(entry.sequence_number));
+            if let Some(entry) = self.merge_versions(versions)? {
+                final_entries.push(entry);
             }
         }
 
-        // Remove tombstones and keep only the most recent value for each key
-        let final_entries = self.remove_tombstones_and_duplicates(all_entries.clone())?;
-
         info!(
             "Compaction complete: {} entries merged into {} final entries",
-            all_entries.len(),
+            entries_read,
             final_entries.len()
         );
 
         // Create output file and write the compacted SSTable
-        let output_file = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .read(true)
-            .open(&self.output_path)?;
-
-        let mut writer = BufWriter::new(output_file);
-        self.write_compacted_sstable(&mut writer, final_entries)?;
+        if self.use_direct_io {
+            // O_DIRECT needs aligned, sequential writes, so build the table in memory first
+            let mut buffer = Cursor::new(Vec::new());
+            self.write_compacted_sstable(&mut buffer, final_entries)?;
+            io_backend::write_file_direct(&self.output_path, buffer.get_ref())?;
+        } else {
+            let output_file = OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .read(true)
+                .open(&self.output_path)?;
+
+            let mut writer = BufWriter::new(output_file);
+            self.write_compacted_sstable(&mut writer, final_entries)?;
+        }
 
         Ok(self.output_path.clone())
     }
 
-    /// Remove tombstones and keep only the most recent value for each key
-    fn remove_tombstones_and_duplicates(
+    /// Estimate what compacting `input_paths` would produce without doing the work
+    ///
+    /// Each input's entry headers and keys are read, but not its values. Inputs
+    /// are expected newest first, like [`CompactionTask::inputs`]; the newest
+    /// version of each key is kept unless it is a tombstone in a bottommost
+    /// compaction.
+    pub fn estimate<P: AsRef<Path>>(
         &self,
-        mut entries: Vec<CompactionEntry>,
-    ) -> CompactionResult<Vec<CompactionEntry>> {
-        // Sort by key, then by sequence number (descending) to get most recent first
-        entries.sort_by(|a, b| {
-            a.key
-                .cmp(&b.key)
-                .then_with(|| b.sequence_number.cmp(&a.sequence_number))
-        });
+        input_paths: &[P],
+    ) -> CompactionResult<CompactionEstimate> {
+        let mut estimate = CompactionEstimate::default();
+        let mut seen = HashSet::new();
+        let mut data_bytes = 0u64;
+        let mut key_bytes = 0u64;
+
+        for path in input_paths {
+            let sstable = SSTable::open(path.as_ref())?;
+            estimate.bytes_read += std::fs::metadata(path.as_ref())?.len();
+            estimate.input_entries += sstable.entry_count() as usize;
+
+            for (entry, stored_len) in sstable.stored_entries()? {
+                if !seen.insert(entry.key.clone()) || (self.bottommost && entry.value_len() == 0) {
+                    continue;
+                }
+                estimate.output_entries += 1;
+                // Entries are assumed to take as much space in the output as they do now
+                data_bytes += stored_len as u64;
+                key_bytes += entry.key.len() as u64;
+            }
+        }
 
-        let mut final_entries = Vec::new();
-        let mut current_key: Option<Vec<u8>> = None;
+        estimate.entries_dropped = estimate.input_entries - estimate.output_entries;
+        if estimate.output_entries > 0 {
+            let entries = estimate.output_entries as u64;
+            let bloom_bytes = BLOOM_PREFIX_SIZE as u64 + (entries * 10).div_ceil(8);
+            // Each block ends in its restart offsets and their count, and is
+            // listed in the index by its first key
+            let blocks = data_bytes.div_ceil(DEFAULT_BLOCK_SIZE as u64);
+            let restarts = entries.div_ceil(KEY_RESTART_INTERVAL as u64) + blocks;
+            let average_key = key_bytes / entries;
+            estimate.output_bytes = std::mem::size_of::<SSTableHeader>() as u64
+                + bloom_bytes
+                + data_bytes
+                + 4 * (restarts + blocks)
+                + 4 // index block count
+                + blocks * (24 + average_key)
+                + 12 + average_key // compressed value bytes and last key
+                + 32; // footer
+        }
+        Ok(estimate)
+    }
 
-        for entry in entries {
-            if current_key != Some(entry.key.clone()) {
-                // New key, add it if it's not a tombstone
-                if !entry.is_deletion() {
-                    final_entries.push(entry.clone());
-                }
-                current_key = Some(entry.key);
+    /// Reduce the versions of one key, newest first, to the entry to keep
+    ///
+    /// Older versions are dropped, or folded into a merge operand above them.
+    /// Tombstones are kept, and leftover operands left unresolved, unless the
+    /// compaction is bottommost.
+    fn merge_versions(&self, versions: Vec<Entry>) -> CompactionResult<Option<Entry>> {
+        let mut versions = versions.into_iter();
+        let Some(mut entry) = versions.next() else {
+            return Ok(None);
+        };
+        for older in versions {
+            if !entry.merge {
+                break;
             }
-            // If it's the same key, skip it (we already have the most recent)
+            entry = merge::fold(older, entry, |base| {
+                if base.indirect {
+                    return Err(CompactionError::InvalidInput(
+                        "Merge operand over a value log value needs the engine's compaction"
+                            .to_string(),
+                    ));
+                }
+                Ok(base.value)
+            })?;
         }
 
-        Ok(final_entries)
+        if !self.bottommost {
+            return Ok(Some(entry));
+        }
+        if entry.value.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(merge::resolve(entry)?))
     }
 
     /// Write the compacted SSTable to disk
-    fn write_compacted_sstable(
+    fn write_compacted_sstable<W: Write + Seek>(
         &self,
-        writer: &mut BufWriter<File>,
-        entries: Vec<CompactionEntry>,
+        writer: &mut W,
+        entries: Vec<Entry>,
     ) -> CompactionResult<()> {
         if entries.is_empty() {
             return Err(CompactionError::InvalidInput(
@@ -304,113 +284,436 @@ impl CompactionEngine {
             ));
         }
 
-        // Write header placeholder
-        let header_size = std::mem::size_of::<crate::sstable::SSTableHeader>();
-        let header_placeholder = vec![0u8; header_size];
-        writer.write_all(&header_placeholder)?;
-
-        // Write bloom filter placeholder
-        let bloom_filter_offset = writer.stream_position()?;
-        let bloom_filter_size = (entries.len() * 10).div_ceil(8); // 10x size, rounded up
-        let bloom_filter_placeholder = vec![0u8; bloom_filter_size];
-        writer.write_all(&bloom_filter_placeholder)?;
-
-        // Write data section
-        let data_offset = writer.stream_position()?;
-        let mut index = crate::sstable::SSTableIndex::new();
-        let mut bloom_filter = crate::sstable::BloomFilter::new(entries.len() * 10, 3);
-
-        for entry in &entries {
-            // Add to bloom filter
-            bloom_filter.add(&entry.key);
-
-            // Calculate entry start position
-            let _entry_start = writer.stream_position()?;
-
-            // Write entry header: key_len (4) + value_len (4) + timestamp (8) + seq (8)
-            let key_len = entry.key.len() as u32;
-            let value_len = entry.value.as_ref().map_or(0, |v| v.len()) as u32;
-
-            writer.write_all(&key_len.to_le_bytes())?;
-            writer.write_all(&value_len.to_le_bytes())?;
-            writer.write_all(&entry.timestamp.to_le_bytes())?;
-            writer.write_all(&entry.sequence_number.to_le_bytes())?;
-
-            // Calculate key data offset (after the header) - this should be relative to data_offset
-            let key_data_offset = writer.stream_position()? - data_offset;
-
-            // Write key and value data
-            writer.write_all(&entry.key)?;
-            if let Some(value) = &entry.value {
-                writer.write_all(value)?;
-            }
+        let options = SSTableWriteOptions {
+            compression: self.compression,
+            compression_options: self.compression_options,
+            ..Default::default()
+        };
+        SSTable::write_table(writer, &entries, &options)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
 
-            // Add to index with key_data_offset (points to where key data starts, relative to data section)
-            index.add_entry(entry.key.clone(), key_data_offset, key_len, value_len);
+/// Thresholds the [`CompactionPicker`] scores levels and tables against
+#[derive(Debug, Clone, Copy)]
+pub struct CompactionOptions {
+    /// Number of level 0 tables at which level 0 needs compaction
+    pub level0_file_trigger: usize,
+    /// Target data size of level 1 in bytes
+    pub level1_target_bytes: u64,
+    /// Each level below 1 targets this many times the size of the one above
+    pub level_size_multiplier: u64,
+    /// Tables in level 1 and below with more data than this are compacted on their own
+    pub max_file_bytes: u64,
+    /// Tables in level 1 and below whose share of tombstones reaches this are
+    /// compacted on their own to reclaim the deleted data
+    pub tombstone_ratio: f64,
+    /// Compactions the engine runs after each flush while the picker finds
+    /// work, so levels stay within their targets without a manual
+    /// `compact`; 0 leaves compaction to callers and maintenance
+    pub auto_compactions: usize,
+}
+
+impl Default for CompactionOptions {
+    fn default() -> Self {
+        Self {
+            level0_file_trigger: 4,
+            level1_target_bytes: 64 * 1024 * 1024, // 64MB
+            level_size_multiplier: 10,
+            max_file_bytes: 64 * 1024 * 1024, // 64MB
+            tombstone_ratio: 0.5,
+            auto_compactions: 4,
         }
+    }
+}
 
-        // Calculate total data size
-        let data_size = writer.stream_position()? - data_offset;
+/// How the [`CompactionPicker`] chooses compactions
+///
+/// Switching strategies on an existing database is safe: leveled compaction
+/// merges the runs size-tiered compaction left in level 0 into level 1, and
+/// size-tiered compaction leaves deeper levels as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CompactionStrategy {
+    /// Push data down through levels of growing target size, keeping reads
+    /// and space low at the cost of rewriting data once per level
+    #[default]
+    Leveled,
+    /// Keep every table in level 0 as a sorted run and merge runs of similar
+    /// size, rewriting each entry about once per size tier for write-heavy
+    /// workloads
+    SizeTiered(SizeTieredOptions),
+}
 
-        // Write index section
-        let index_offset = writer.stream_position()?;
-        let index_size = Self::write_index(writer, &index)?;
+impl std::str::FromStr for CompactionStrategy {
+    type Err = CompactionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "leveled" => Ok(Self::Leveled),
+            "size-tiered" | "tiered" => Ok(Self::SizeTiered(SizeTieredOptions::default())),
+            _ => Err(CompactionError::InvalidInput(format!(
+                "Invalid compaction strategy: {}",
+                s
+            ))),
+        }
+    }
+}
 
-        // Write footer
-        let footer = crate::sstable::SSTableFooter::new(0, data_size, index_size as u64);
-        footer.write(writer)?;
+/// When [`CompactionStrategy::SizeTiered`] merges runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeTieredOptions {
+    /// Fewest runs of similar size merged at once
+    pub min_merge_width: usize,
+    /// Most runs merged at once
+    pub max_merge_width: usize,
+    /// Runs belong to one tier while the largest holds at most this many
+    /// times the data of the smallest
+    pub size_ratio: f64,
+    /// Runs with less data count as this size, so fresh flushes tier together
+    pub min_run_bytes: u64,
+}
 
-        // Update bloom filter
-        writer.seek(SeekFrom::Start(bloom_filter_offset))?;
-        writer.write_all(bloom_filter.bits())?;
+impl Default for SizeTieredOptions {
+    fn default() -> Self {
+        Self {
+            min_merge_width: 4,
+            max_merge_width: 32,
+            size_ratio: 2.0,
+            min_run_bytes: 4 * 1024 * 1024, // 4MB
+        }
+    }
+}
 
-        // Write header with final offsets
-        let header = crate::sstable::SSTableHeader::new(
-            entries.len() as u32,
-            index_offset,
-            bloom_filter_offset,
-            data_offset,
-        );
-        writer.seek(SeekFrom::Start(0))?;
-        header.write(writer)?;
+/// Why a compaction was picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionReason {
+    /// Level 0 holds too many overlapping tables
+    Level0FileCount,
+    /// A level holds more data than its target size
+    LevelSize,
+    /// A single table grew past `max_file_bytes`
+    OversizedFile,
+    /// A table is mostly tombstones
+    Tombstones,
+    /// Enough level 0 runs of similar size piled up under size-tiered compaction
+    SizeTier,
+    /// Requested explicitly rather than picked
+    Manual,
+}
 
-        // Flush writer
-        writer.flush()?;
+/// A compaction chosen by the [`CompactionPicker`]
+#[derive(Debug, Clone)]
+pub struct CompactionTask {
+    /// Level the compaction was triggered for
+    pub level: usize,
+    /// Level the merged output belongs in
+    pub output_level: usize,
+    /// How far past its threshold the trigger was; higher is more urgent
+    pub score: f64,
+    pub reason: CompactionReason,
+    /// Tables to merge, newest first: from `level`, then the overlapping
+    /// tables of `output_level`
+    pub inputs: Vec<Arc<SSTable>>,
+}
 
-        Ok(())
+impl CompactionTask {
+    /// Input file paths, ready for [`CompactionEngine::compact_sstables`]
+    pub fn input_paths(&self) -> Vec<PathBuf> {
+        self.inputs.iter().map(|t| t.path().to_path_buf()).collect()
     }
+}
 
-    /// Write index to writer (helper function)
-    fn write_index<W: Write + Seek>(
-        writer: &mut W,
-        index: &crate::sstable::SSTableIndex,
-    ) -> io::Result<usize> {
-        let start_pos = writer.stream_position()?;
-
-        // Write index header: entry_count (4 bytes)
-        let entry_count = index.len() as u32;
-        writer.write_all(&entry_count.to_le_bytes())?;
-
-        // Write each index entry
-        for entry in &index.entries {
-            // key_len (4) + key + offset (8) + key_size (4) + value_size (4)
-            let key_len = entry.key.len() as u32;
-            writer.write_all(&key_len.to_le_bytes())?;
-            writer.write_all(&entry.key)?;
-            writer.write_all(&entry.offset.to_le_bytes())?;
-            writer.write_all(&entry.key_size.to_le_bytes())?;
-            writer.write_all(&entry.value_size.to_le_bytes())?;
+/// Chooses the most urgent compaction for a [`Version`]
+///
+/// Levels are scored first: level 0 by table count against
+/// `level0_file_trigger`, deeper levels by data size against a target that
+/// grows by `level_size_multiplier` per level. The highest score of at least 1
+/// wins. Otherwise single tables that are oversized or mostly tombstones are
+/// picked, the largest excess first. Under
+/// [`CompactionStrategy::SizeTiered`] only level 0 runs are merged instead.
+#[derive(Debug, Clone, Default)]
+pub struct CompactionPicker {
+    options: CompactionOptions,
+    strategy: CompactionStrategy,
+}
+
+impl CompactionPicker {
+    pub fn new(options: CompactionOptions) -> Self {
+        Self {
+            options,
+            strategy: CompactionStrategy::Leveled,
+        }
+    }
+
+    /// Choose compactions with `strategy` instead of leveled compaction
+    pub fn with_strategy(mut self, strategy: CompactionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Target data size of a level; level 0 is bounded by table count instead
+    pub fn level_target_bytes(&self, level: usize) -> u64 {
+        let multiplier = self.options.level_size_multiplier.max(1);
+        (1..level.max(1)).fold(self.options.level1_target_bytes, |target, _| {
+            target.saturating_mul(multiplier)
+        })
+    }
+
+    /// Score of every level; 1.0 or more means the level needs compaction
+    ///
+    /// The deepest level has nowhere to push data, so it always scores 0.
+    pub fn level_scores(&self, version: &Version) -> Vec<f64> {
+        let deepest = version.levels.len() - 1;
+        version
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, tables)| {
+                if level == 0 {
+                    tables.len() as f64 / self.options.level0_file_trigger.max(1) as f64
+                } else if level == deepest {
+                    0.0
+                } else {
+                    let bytes: u64 = tables.iter().map(|t| t.data_size()).sum();
+                    bytes as f64 / self.level_target_bytes(level).max(1) as f64
+                }
+            })
+            .collect()
+    }
+
+    /// Pick the most urgent compaction, if any is needed
+    pub fn pick(&self, version: &Version) -> Option<CompactionTask> {
+        self.pick_excluding(version, |_| false)
+    }
+
+    /// Pick the most urgent compaction none of whose inputs is `busy`
+    ///
+    /// Background compactions pass the tables they are already merging, so
+    /// concurrent compactions never share an input.
+    pub fn pick_excluding<F>(&self, version: &Version, busy: F) -> Option<CompactionTask>
+    where
+        F: Fn(&Arc<SSTable>) -> bool,
+    {
+        let candidates = match &self.strategy {
+            CompactionStrategy::Leveled => {
+                let mut candidates = self.level_candidates(version);
+                candidates.extend(self.file_candidates(version));
+                candidates
+            }
+            CompactionStrategy::SizeTiered(options) => self.tier_candidates(version, options),
+        };
+        let task = candidates
+            .into_iter()
+            .find(|task| !task.inputs.iter().any(&busy));
+        if let Some(task) = &task {
+            debug!(
+                "Picked {:?} compaction of level {} into level {} ({} inputs, score {:.2})",
+                task.reason,
+                task.level,
+                task.output_level,
+                task.inputs.len(),
+                task.score
+            );
+        }
+        task
+    }
+
+    /// Compaction merging all of level 0 into the next level, regardless of score
+    ///
+    /// Returns None when there is nothing to merge.
+    pub fn pick_level0(&self, version: &Version) -> Option<CompactionTask> {
+        self.level0_task(version, 0.0, CompactionReason::Manual)
+    }
+
+    fn level0_task(
+        &self,
+        version: &Version,
+        score: f64,
+        reason: CompactionReason,
+    ) -> Option<CompactionTask> {
+        let output_level = 1.min(version.levels.len() - 1);
+        let inputs = version.levels[0].clone();
+        let minimum = if output_level == 0 { 2 } else { 1 };
+        if inputs.len() < minimum {
+            return None;
+        }
+        Some(self.task(version, 0, output_level, score, reason, inputs))
+    }
+
+    /// Compactions for every level at or past its threshold, highest score
+    /// first and the deeper level on a tie
+    fn level_candidates(&self, version: &Version) -> Vec<CompactionTask> {
+        let scores = self.level_scores(version);
+        let mut candidates: Vec<CompactionTask> = scores
+            .into_iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, score)| *score >= 1.0)
+            .filter_map(|(level, score)| self.level_task(version, level, score))
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    /// Compaction for a level past its threshold
+    fn level_task(&self, version: &Version, level: usize, score: f64) -> Option<CompactionTask> {
+        if level == 0 {
+            return self.level0_task(version, score, CompactionReason::Level0FileCount);
         }
+        let output_level = (level + 1).min(version.levels.len() - 1);
+
+        // Push down the table with the most deleted data, the largest on a tie
+        let table = version.levels[level]
+            .iter()
+            .max_by(|a, b| {
+                tombstone_share(a)
+                    .total_cmp(&tombstone_share(b))
+                    .then(a.data_size().cmp(&b.data_size()))
+            })?
+            .clone();
+        Some(self.task(
+            version,
+            level,
+            output_level,
+            score,
+            CompactionReason::LevelSize,
+            vec![table],
+        ))
+    }
+
+    /// Compactions merging tiers of adjacent level 0 runs, longest tier first
+    ///
+    /// Runs are scanned newest first and one joins the current tier while the
+    /// tier's largest run stays within `size_ratio` of its smallest. Only
+    /// adjacent runs merge, so the output can take their place in level 0
+    /// without reordering versions of a key.
+    fn tier_candidates(
+        &self,
+        version: &Version,
+        options: &SizeTieredOptions,
+    ) -> Vec<CompactionTask> {
+        let size = |t: &Arc<SSTable>| t.data_size().max(options.min_run_bytes).max(1) as f64;
+        let runs = &version.levels[0];
+        let min_width = options.min_merge_width.max(2);
+        let max_width = options.max_merge_width.max(min_width);
+
+        let mut candidates = Vec::new();
+        let mut start = 0;
+        while start < runs.len() {
+            let (mut smallest, mut largest) = (size(&runs[start]), size(&runs[start]));
+            let mut end = start + 1;
+            while end < runs.len() && end - start < max_width {
+                let run = size(&runs[end]);
+                if largest.max(run) > smallest.min(run) * options.size_ratio {
+                    break;
+                }
+                smallest = smallest.min(run);
+                largest = largest.max(run);
+                end += 1;
+            }
+            let width = end - start;
+            if width >= min_width {
+                candidates.push(self.task(
+                    version,
+                    0,
+                    0,
+                    width as f64 / min_width as f64,
+                    CompactionReason::SizeTier,
+                    runs[start..end].to_vec(),
+                ));
+            }
+            start = end;
+        }
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
 
-        let end_pos = writer.stream_position()?;
-        Ok((end_pos - start_pos) as usize)
+    /// Compactions for single tables in level 1 or below that are past the
+    /// oversize or tombstone threshold, furthest past first
+    fn file_candidates(&self, version: &Version) -> Vec<CompactionTask> {
+        let deepest = version.levels.len() - 1;
+        let mut candidates = Vec::new();
+        for (level, tables) in version.levels.iter().enumerate().skip(1) {
+            for table in tables {
+                let size_score =
+                    table.data_size() as f64 / self.options.max_file_bytes.max(1) as f64;
+                let tombstone_score = if self.options.tombstone_ratio > 0.0 {
+                    tombstone_share(table) / self.options.tombstone_ratio
+                } else {
+                    0.0
+                };
+                let (score, reason) = if size_score >= tombstone_score {
+                    (size_score, CompactionReason::OversizedFile)
+                } else {
+                    (tombstone_score, CompactionReason::Tombstones)
+                };
+                if score >= 1.0 {
+                    // Tables in the deepest level are rewritten in place
+                    let output_level = (level + 1).min(deepest);
+                    candidates.push(self.task(
+                        version,
+                        level,
+                        output_level,
+                        score,
+                        reason,
+                        vec![table.clone()],
+                    ));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    /// Build a task, adding the tables of `output_level` that overlap the inputs
+    fn task(
+        &self,
+        version: &Version,
+        level: usize,
+        output_level: usize,
+        score: f64,
+        reason: CompactionReason,
+        mut inputs: Vec<Arc<SSTable>>,
+    ) -> CompactionTask {
+        if output_level != level {
+            let first = inputs.iter().filter_map(|t| t.first_key()).min();
+            let last = inputs.iter().filter_map(|t| t.last_key()).max();
+            if let (Some(first), Some(last)) = (first, last) {
+                let overlapping: Vec<Arc<SSTable>> = version.levels[output_level]
+                    .iter()
+                    .filter(|t| {
+                        t.first_key().is_some_and(|k| k <= last)
+                            && t.last_key().is_some_and(|k| k >= first)
+                    })
+                    .cloned()
+                    .collect();
+                inputs.extend(overlapping);
+            }
+        }
+        CompactionTask {
+            level,
+            output_level,
+            score,
+            reason,
+            inputs,
+        }
     }
 }
 
+/// Fraction of a table's entries that are tombstones (0 until its index is loaded)
+fn tombstone_share(sstable: &SSTable) -> f64 {
+    sstable.tombstone_count().unwrap_or(0) as f64 / sstable.entry_count().max(1) as f64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::memtable::MemTable;
+    use crate::merge::MergeOp;
+    use bytes::Bytes;
+    use std::collections::BTreeMap;
     use std::fs;
     use tempfile::tempdir;
 
@@ -493,7 +796,7 @@ mod tests {
         assert!(result.is_ok());
 
         // Verify the output SSTable
-        let mut compacted_sstable = SSTable::open(&output_path).unwrap();
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
 
         // Should only have key2 (key1 was deleted)
         assert_eq!(compacted_sstable.entry_count(), 1);
@@ -504,7 +807,7 @@ mod tests {
         // key2 should exist
         assert_eq!(
             compacted_sstable.get(b"key2").unwrap(),
-            Some(b"value2".to_vec())
+            Some(Bytes::from_static(b"value2"))
         );
     }
 
@@ -537,7 +840,7 @@ mod tests {
         assert!(result.is_ok());
 
         // Verify the output SSTable
-        let mut compacted_sstable = SSTable::open(&output_path).unwrap();
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
 
         // Check that keys are in sorted order
         // Since we can't access the private index field, we'll test the functionality differently
@@ -562,7 +865,14 @@ mod tests {
         memtable1.put(b"key1", b"old_value").unwrap();
 
         let memtable2 = MemTable::new(1024 * 1024);
-        memtable2.put(b"key1", b"new_value").unwrap();
+        memtable2
+            .apply(Entry::new(
+                b"key1".to_vec(),
+                Some(Bytes::from_static(b"new_value")),
+                0,
+                2,
+            ))
+            .unwrap();
 
         let sstable1_path = temp_dir.path().join("old.sst");
         let sstable2_path = temp_dir.path().join("new.sst");
@@ -580,7 +890,7 @@ mod tests {
         assert!(result.is_ok());
 
         // Verify the output SSTable
-        let mut compacted_sstable = SSTable::open(&output_path).unwrap();
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
 
         // Should only have one entry
         assert_eq!(compacted_sstable.entry_count(), 1);
@@ -588,8 +898,230 @@ mod tests {
         // Should have the most recent value
         assert_eq!(
             compacted_sstable.get(b"key1").unwrap(),
-            Some(b"new_value".to_vec())
+            Some(Bytes::from_static(b"new_value"))
+        );
+
+        // The higher sequence number wins whatever the input order
+        drop(compacted_sstable);
+        engine
+            .compact_sstables(&[&sstable2_path, &sstable1_path])
+            .unwrap();
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
+        assert_eq!(
+            compacted_sstable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"new_value"))
+        );
+    }
+
+    #[test]
+    fn test_compaction_compresses_output() {
+        let temp_dir = tempdir().unwrap();
+        let input_path = temp_dir.path().join("input.sst");
+        let memtable = MemTable::new(1024 * 1024);
+        for i in 0..20 {
+            let value = format!("reading {} ", i).repeat(50);
+            memtable
+                .put(format!("key{:02}", i).as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        SSTable::from_memtable(&input_path, &memtable, CompressionType::None).unwrap();
+
+        for compression in [CompressionType::LZ4, CompressionType::Zstd] {
+            let output_path = temp_dir.path().join(format!("{:?}.sst", compression));
+            CompactionEngine::new(&output_path, compression)
+                .compact_sstables(&[&input_path])
+                .unwrap();
+            assert!(
+                std::fs::metadata(&output_path).unwrap().len()
+                    < std::fs::metadata(&input_path).unwrap().len() / 2
+            );
+
+            let compacted = SSTable::open(&output_path).unwrap();
+            assert_eq!(compacted.compression_type().unwrap(), compression);
+            let metadata = compacted.compression_metadata().unwrap();
+            assert!(metadata.compressed_size < metadata.original_size);
+            assert_eq!(
+                compacted.get(b"key07").unwrap(),
+                Some(Bytes::from("reading 7 ".repeat(50)))
+            );
+        }
+
+        // Values below the threshold stay uncompressed
+        let output_path = temp_dir.path().join("threshold.sst");
+        CompactionEngine::new(&output_path, CompressionType::Zstd)
+            .with_compression_options(CompressionOptions {
+                min_value_size: 4096,
+            })
+            .compact_sstables(&[&input_path])
+            .unwrap();
+        let compacted = SSTable::open(&output_path).unwrap();
+        assert_eq!(compacted.compression_metadata().unwrap().original_size, 0);
+    }
+
+    #[test]
+    fn test_compaction_merges_arbitrary_keys() {
+        let temp_dir = tempdir().unwrap();
+        let mut expected = BTreeMap::new();
+        let mut paths = Vec::new();
+        let mut seq = 0;
+
+        // Overlapping tables written oldest first; inputs are passed newest first
+        for table in 0..3 {
+            let memtable = MemTable::new(1024 * 1024);
+            for i in (table..200).step_by(table + 1) {
+                seq += 1;
+                let key = format!("device/{:x}/reading", i * 7919 % 211).into_bytes();
+                if i % 11 == 0 {
+                    memtable
+                        .apply(Entry::new(key.clone(), None, 0, seq))
+                        .unwrap();
+                    expected.remove(&key);
+                } else {
+                    let value = Bytes::from(format!("{}:{}", table, i));
+                    memtable
+                        .apply(Entry::new(key.clone(), Some(value.clone()), 0, seq))
+                        .unwrap();
+                    expected.insert(key, value);
+                }
+            }
+            let path = temp_dir.path().join(format!("input_{}.sst", table));
+            SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+            paths.insert(0, path);
+        }
+
+        let output_path = temp_dir.path().join("merged.sst");
+        CompactionEngine::new(&output_path, CompressionType::None)
+            .compact_sstables(&paths)
+            .unwrap();
+        let merged = SSTable::open(&output_path).unwrap();
+        let actual: BTreeMap<Vec<u8>, Bytes> = merged
+            .iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.key, entry.value.unwrap())
+            })
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_compaction_keeps_tombstones_above_older_data() {
+        let temp_dir = tempdir().unwrap();
+        let write = |name: &str, entries: Vec<Entry>| {
+            let path = temp_dir.path().join(name);
+            let memtable = MemTable::new(1024 * 1024);
+            for entry in entries {
+                memtable.apply(entry).unwrap();
+            }
+            SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+            path
+        };
+        let older = write(
+            "older.sst",
+            vec![
+                Entry::new(b"gone".to_vec(), Some(Bytes::from_static(b"old")), 0, 1),
+                Entry::new(
+                    b"count".to_vec(),
+                    Some(Bytes::copy_from_slice(&5i64.to_le_bytes())),
+                    0,
+                    2,
+                ),
+            ],
+        );
+        let newer = write(
+            "newer.sst",
+            vec![
+                Entry::new(b"gone".to_vec(), None, 0, 3),
+                Entry::merge_operand(b"count".to_vec(), merge::encode(&[MergeOp::Add(2)]), 0, 4),
+                Entry::merge_operand(b"lone".to_vec(), merge::encode(&[MergeOp::Add(1)]), 0, 5),
+            ],
         );
+
+        // Older data may exist below, so the tombstone and the operand with
+        // nothing under it survive, while the folded operand replaces its base
+        let output_path = temp_dir.path().join("upper.sst");
+        CompactionEngine::new(&output_path, CompressionType::None)
+            .with_bottommost(false)
+            .compact_sstables(&[&newer, &older])
+            .unwrap();
+        let upper = SSTable::open(&output_path).unwrap();
+        assert_eq!(upper.entry_count(), 3);
+        let gone = upper.get_entry(b"gone").unwrap().unwrap();
+        assert_eq!((gone.value, gone.sequence_number), (None, 3));
+        let count = upper.get_entry(b"count").unwrap().unwrap();
+        assert!(!count.merge);
+        assert_eq!(merge::decode_i64(&count.value.unwrap()).unwrap(), 7);
+        assert!(upper.get_entry(b"lone").unwrap().unwrap().merge);
+
+        // At the bottom the tombstone goes and the operand is resolved
+        let output_path = temp_dir.path().join("bottom.sst");
+        let engine = CompactionEngine::new(&output_path, CompressionType::None);
+        assert_eq!(
+            engine.estimate(&[&newer, &older]).unwrap().output_entries,
+            2
+        );
+        engine.compact_sstables(&[&newer, &older]).unwrap();
+        let bottom = SSTable::open(&output_path).unwrap();
+        assert_eq!(bottom.entry_count(), 2);
+        assert_eq!(bottom.get(b"gone").unwrap(), None);
+        let lone = bottom.get_entry(b"lone").unwrap().unwrap();
+        assert!(!lone.merge);
+        assert_eq!(merge::decode_i64(&lone.value.unwrap()).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_compaction_with_direct_io() {
+        let temp_dir = tempdir().unwrap();
+
+        let memtable = MemTable::new(1024 * 1024);
+        memtable.put(b"test_key", b"test_value").unwrap();
+
+        let sstable_path = temp_dir.path().join("input.sst");
+        SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
+
+        let output_path = temp_dir.path().join("direct.sst");
+        let engine =
+            CompactionEngine::new(&output_path, CompressionType::None).with_direct_io(true);
+        engine.compact_sstables(&[&sstable_path]).unwrap();
+
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
+        assert_eq!(
+            compacted_sstable.get(b"test_key").unwrap(),
+            Some(Bytes::from_static(b"test_value"))
+        );
+    }
+
+    #[test]
+    fn test_compaction_estimate_matches_output() {
+        let temp_dir = tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..2)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("input_{}.sst", i));
+                SSTable::from_memtable(
+                    &path,
+                    &create_test_memtable_with_data(),
+                    CompressionType::None,
+                )
+                .unwrap();
+                path
+            })
+            .collect();
+
+        let output_path = temp_dir.path().join("output.sst");
+        let engine = CompactionEngine::new(&output_path, CompressionType::None);
+        let estimate = engine.estimate(&paths).unwrap();
+        assert!(!output_path.exists());
+        assert_eq!(estimate.input_entries, 200);
+        assert_eq!(estimate.output_entries, 96);
+        assert_eq!(estimate.entries_dropped, 104);
+        let input_bytes: u64 = paths.iter().map(|p| fs::metadata(p).unwrap().len()).sum();
+        assert_eq!(estimate.bytes_read, input_bytes);
+
+        // Shared key prefixes and restart points shift as entries are dropped
+        engine.compact_sstables(&paths).unwrap();
+        let output_bytes = fs::metadata(&output_path).unwrap().len();
+        assert!(estimate.output_bytes.abs_diff(output_bytes) <= output_bytes / 100);
+        assert_eq!(estimate.io_bytes(), input_bytes + estimate.output_bytes);
     }
 
     #[test]
@@ -630,11 +1162,146 @@ mod tests {
         assert!(metadata.len() > 100); // Should be at least 100 bytes
 
         // Verify it can be opened and read
-        let mut compacted_sstable = SSTable::open(&output_path).unwrap();
+        let compacted_sstable = SSTable::open(&output_path).unwrap();
         assert_eq!(compacted_sstable.entry_count(), 1);
         assert_eq!(
             compacted_sstable.get(b"test_key").unwrap(),
-            Some(b"test_value".to_vec())
+            Some(Bytes::from_static(b"test_value"))
+        );
+    }
+
+    /// Table with `keys`, the ones in `deleted` written as tombstones
+    fn table(dir: &Path, name: &str, keys: &[&str], deleted: &[&str]) -> Arc<SSTable> {
+        let memtable = MemTable::new(1024 * 1024);
+        for key in keys {
+            memtable.put(key.as_bytes(), b"value").unwrap();
+        }
+        for key in deleted {
+            memtable.delete(key.as_bytes()).unwrap();
+        }
+        let path = dir.join(format!("{}.sst", name));
+        Arc::new(SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap())
+    }
+
+    #[test]
+    fn test_picker_scores_levels() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        let picker = CompactionPicker::new(CompactionOptions {
+            level0_file_trigger: 2,
+            level1_target_bytes: 1024 * 1024,
+            ..Default::default()
+        });
+        assert_eq!(picker.level_target_bytes(3), 100 * 1024 * 1024);
+
+        let version = Version::new(Arc::new(MemTable::new(1024)), 3)
+            .with_new_sstable(0, table(dir, "l0_a", &["b", "c"], &[]))
+            .with_new_sstable(1, table(dir, "l1_a", &["a", "b"], &[]))
+            .with_new_sstable(1, table(dir, "l1_b", &["x", "y"], &[]));
+        assert!(picker.pick(&version).is_none());
+
+        // A second level 0 table reaches the trigger; only overlapping level 1 tables join
+        let version = version.with_new_sstable(0, table(dir, "l0_b", &["c", "d"], &[]));
+        let task = picker.pick(&version).unwrap();
+        assert_eq!(task.reason, CompactionReason::Level0FileCount);
+        assert_eq!((task.level, task.output_level), (0, 1));
+        assert_eq!(task.score, 1.0);
+        let names: Vec<_> = task
+            .input_paths()
+            .iter()
+            .map(|p| p.file_stem().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["l0_b", "l0_a", "l1_a"]);
+
+        // An overfull level 1 pushes its most-deleted table down
+        let picker = CompactionPicker::new(CompactionOptions {
+            level1_target_bytes: 1,
+            ..Default::default()
+        });
+        let version = Version::new(Arc::new(MemTable::new(1024)), 3)
+            .with_new_sstable(1, table(dir, "l1_a", &["a", "b"], &[]))
+            .with_new_sstable(1, table(dir, "l1_b", &["x"], &["y"]))
+            .with_new_sstable(2, table(dir, "l2", &["w", "z"], &[]));
+        let task = picker.pick(&version).unwrap();
+        assert_eq!(task.reason, CompactionReason::LevelSize);
+        assert_eq!((task.level, task.output_level), (1, 2));
+        assert_eq!(task.inputs.len(), 2);
+        assert_eq!(task.inputs[1].path(), dir.join("l2.sst"));
+    }
+
+    #[test]
+    fn test_picker_picks_single_tables() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+
+        // Mostly deleted tables in the deepest level are rewritten in place
+        let picker = CompactionPicker::default();
+        let version = Version::new(Arc::new(MemTable::new(1024)), 2)
+            .with_new_sstable(1, table(dir, "live", &["a", "b"], &[]))
+            .with_new_sstable(1, table(dir, "dead", &["x"], &["y", "z"]));
+        let task = picker.pick(&version).unwrap();
+        assert_eq!(task.reason, CompactionReason::Tombstones);
+        assert_eq!((task.level, task.output_level), (1, 1));
+        assert_eq!(task.input_paths(), [dir.join("dead.sst")]);
+
+        let picker = CompactionPicker::new(CompactionOptions {
+            max_file_bytes: 32,
+            ..Default::default()
+        });
+        let version = Version::new(Arc::new(MemTable::new(1024)), 2)
+            .with_new_sstable(1, table(dir, "small", &["a"], &[]))
+            .with_new_sstable(1, table(dir, "large", &["m", "n", "o", "p"], &[]));
+        let task = picker.pick(&version).unwrap();
+        assert_eq!(task.reason, CompactionReason::OversizedFile);
+        assert_eq!(task.input_paths(), [dir.join("large.sst")]);
+    }
+
+    #[test]
+    fn test_picker_merges_size_tiers() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        let options = SizeTieredOptions {
+            min_merge_width: 3,
+            min_run_bytes: 0,
+            ..Default::default()
+        };
+        let picker =
+            CompactionPicker::default().with_strategy(CompactionStrategy::SizeTiered(options));
+        let many: Vec<String> = (0..20).map(|i| format!("k{:02}", i)).collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+
+        // Oldest first: a small run, a large one, then three small flushes
+        let version = Version::new(Arc::new(MemTable::new(1024)), 3)
+            .with_new_sstable(0, table(dir, "old", &["a"], &[]))
+            .with_new_sstable(0, table(dir, "large", &many, &[]))
+            .with_new_sstable(0, table(dir, "s1", &["b"], &[]))
+            .with_new_sstable(0, table(dir, "s2", &["c"], &[]));
+        assert!(picker.pick(&version).is_none());
+
+        // Only adjacent runs of similar size merge, and they stay in level 0
+        let version = version.with_new_sstable(0, table(dir, "s3", &["d"], &[]));
+        let task = picker.pick(&version).unwrap();
+        assert_eq!(task.reason, CompactionReason::SizeTier);
+        assert_eq!((task.level, task.output_level), (0, 0));
+        assert_eq!(task.score, 1.0);
+        assert_eq!(
+            task.input_paths(),
+            [dir.join("s3.sst"), dir.join("s2.sst"), dir.join("s1.sst")]
+        );
+
+        // Below `min_run_bytes` every run is in the same tier
+        let picker = CompactionPicker::default().with_strategy(CompactionStrategy::SizeTiered(
+            SizeTieredOptions {
+                min_merge_width: 3,
+                ..Default::default()
+            },
+        ));
+        assert_eq!(picker.pick(&version).unwrap().inputs.len(), 5);
+
+        assert_eq!(
+            "size-tiered".parse::<CompactionStrategy>().unwrap(),
+            CompactionStrategy::SizeTiered(SizeTieredOptions::default())
         );
+        assert!("tiny".parse::<CompactionStrategy>().is_err());
     }
 }
@@ -0,0 +1,1125 @@
+use bytes::Bytes;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::blob::{BlobPointer, BlobStore};
+use crate::compaction::{CompactionReason, CompactionTask};
+use crate::engine::{
+    CompactionStats, Engine, EngineResult, TEMP_SSTABLE_EXTENSION, remove_data_file,
+    sstable_overlaps,
+};
+use crate::events::EngineEvent;
+use crate::expiry::{self, Expiries};
+use crate::history::JobKind;
+use crate::iterator::{MergingIterator, Source};
+use crate::memtable::Entry;
+use crate::merge;
+use crate::sstable::{SSTable, SSTableWriteOptions};
+
+/// Bytes merged between checks against the rate limit
+const THROTTLE_CHUNK_BYTES: u64 = 64 * 1024;
+
+/// Options for compactions run off the write path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackgroundCompactionOptions {
+    /// Compactions running at once at most, each on its own thread
+    pub max_concurrent: usize,
+    /// Bytes per second all background compactions together may merge
+    /// (None leaves them unthrottled)
+    pub rate_limit: Option<u64>,
+}
+
+impl Default for BackgroundCompactionOptions {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 1,
+            rate_limit: None,
+        }
+    }
+}
+
+/// Token bucket pacing compaction I/O to a byte rate
+///
+/// Up to one second's worth of bytes may go through in a burst; beyond that,
+/// callers sleep until the rate has caught up.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: AtomicU64,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    /// Bytes that may pass without waiting; negative while in debt
+    available: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1);
+        Self {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            state: Mutex::new(LimiterState {
+                available: bytes_per_sec as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Bytes per second currently allowed
+    pub fn rate(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    /// Change the rate, taking effect from the next [`RateLimiter::acquire`]
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        self.bytes_per_sec
+            .store(bytes_per_sec.max(1), Ordering::Relaxed);
+    }
+
+    /// Take `bytes` from the budget, sleeping until the rate allows them
+    pub fn acquire(&self, bytes: u64) {
+        let rate = self.rate() as f64;
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(state.refilled).as_secs_f64() * rate;
+            state.available = (state.available + refill).min(rate) - bytes as f64;
+            state.refilled = now;
+            if state.available >= 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64(-state.available / rate)
+            }
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// A compaction with everything it needs to merge its inputs without the engine
+///
+/// Output tables are written under temporary names in the scratch directory;
+/// the engine moves them into place when it installs the result.
+pub(crate) struct CompactionJob {
+    pub task: CompactionTask,
+    /// Tables outside the inputs, in the output level or deeper, that may
+    /// hold older versions of a key
+    pub shadows: Vec<Arc<SSTable>>,
+    pub blobs: Arc<BlobStore>,
+    /// Expiry deadlines as of when the job was created
+    pub expiries: Expiries,
+    pub write_options: SSTableWriteOptions,
+    /// Output is split into tables of about this size, except in level 0
+    pub max_file_bytes: u64,
+    pub scratch_dir: PathBuf,
+    pub secure_delete: bool,
+    pub limiter: Option<Arc<RateLimiter>>,
+}
+
+/// Merged output of a [`CompactionJob`], ready to install
+pub(crate) struct FinishedCompaction {
+    pub task: CompactionTask,
+    /// Output tables, still at their temporary paths
+    pub outputs: Vec<SSTable>,
+    pub stats: CompactionStats,
+    pub duration: Duration,
+}
+
+impl FinishedCompaction {
+    /// Delete the outputs of a compaction that won't be installed
+    pub fn discard(self, secure: bool) {
+        discard_outputs(&self.outputs, secure);
+    }
+}
+
+fn discard_outputs(outputs: &[SSTable], secure: bool) {
+    for sstable in outputs {
+        if let Err(e) = remove_data_file(sstable.path(), secure) {
+            warn!(
+                "Failed to remove compaction output {:?}: {}",
+                sstable.path(),
+                e
+            );
+        }
+    }
+}
+
+impl CompactionJob {
+    /// Merge the inputs into new tables
+    ///
+    /// Inputs are ordered newest first, so the first version of a key wins.
+    /// Tombstones and merge operands are kept while a shadowing table may
+    /// still hold the key. Outputs already written are deleted on failure.
+    pub fn run(self) -> EngineResult<FinishedCompaction> {
+        let started = Instant::now();
+        let mut outputs = Vec::new();
+        match self.merge(&mut outputs) {
+            Ok(stats) => Ok(FinishedCompaction {
+                task: self.task,
+                outputs,
+                stats,
+                duration: started.elapsed(),
+            }),
+            Err(e) => {
+                discard_outputs(&outputs, self.secure_delete);
+                Err(e)
+            }
+        }
+    }
+
+    fn merge(&self, outputs: &mut Vec<SSTable>) -> EngineResult<CompactionStats> {
+        let task = &self.task;
+        let shadows = |key: &[u8]| {
+            self.shadows
+                .iter()
+                .any(|t| sstable_overlaps(t, Bound::Included(key), Bound::Included(key)))
+        };
+
+        // Value log bytes the inputs point at; what the output doesn't is garbage
+        let blob_bytes_read = Arc::new(AtomicU64::new(0));
+        let sources: Vec<Source> = task
+            .inputs
+            .iter()
+            .map(|sstable| -> Source {
+                let blob_bytes_read = blob_bytes_read.clone();
+                Box::new(sstable.iter_shared().map(move |entry| {
+                    if let Ok(entry) = &entry {
+                        blob_bytes_read.fetch_add(blob_len(entry), Ordering::Relaxed);
+                    }
+                    entry.map_err(Into::into)
+                }))
+            })
+            .collect();
+
+        let mut stats = CompactionStats {
+            input_sstables: task.inputs.len(),
+            ..Default::default()
+        };
+        let entries_read: usize = task.inputs.iter().map(|t| t.entry_count() as usize).sum();
+        let mut run = Vec::new();
+        let mut run_bytes = 0u64;
+        let mut blob_bytes_written = 0u64;
+        let mut unthrottled = 0u64;
+        let now = expiry::now_secs();
+        for entry in MergingIterator::new(sources, self.blobs.clone()) {
+            let mut entry = entry?;
+            if let Some(limiter) = &self.limiter {
+                unthrottled +=
+                    (entry.key.len() + entry.value.as_ref().map_or(0, Bytes::len)) as u64;
+                if unthrottled >= THROTTLE_CHUNK_BYTES {
+                    limiter.acquire(unthrottled);
+                    unthrottled = 0;
+                }
+            }
+
+            // Expired values become tombstones; their expiry records go once
+            // nothing older can resurface, see `Engine::purge_expiries`
+            if entry.value.is_some() && self.expiries.is_expired(&entry.key, now) {
+                entry.value = None;
+                entry.indirect = false;
+                entry.merge = false;
+            }
+            // Operands stay operands while a deeper table may hold their base
+            if entry.merge && !shadows(&entry.key) {
+                entry = merge::resolve(entry)?;
+            }
+            if entry.value.is_none() && !shadows(&entry.key) {
+                continue;
+            }
+            run_bytes += entry.key.len() as u64 + entry.value.as_ref().map_or(0, Bytes::len) as u64;
+            blob_bytes_written += blob_len(&entry);
+            run.push(entry);
+            // Every level 0 table is a single run, so it is never split
+            if task.output_level > 0 && run_bytes >= self.max_file_bytes {
+                outputs.push(self.write_output(&run)?);
+                stats.entries_written += run.len();
+                run.clear();
+                run_bytes = 0;
+            }
+        }
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(unthrottled);
+        }
+        if !run.is_empty() {
+            outputs.push(self.write_output(&run)?);
+            stats.entries_written += run.len();
+        }
+        stats.output_sstables = outputs.len();
+        stats.entries_dropped = entries_read - stats.entries_written;
+        stats.blob_bytes_dropped = blob_bytes_read
+            .load(Ordering::Relaxed)
+            .saturating_sub(blob_bytes_written);
+        Ok(stats)
+    }
+
+    /// Write sorted entries to a new table under a temporary name
+    fn write_output(&self, entries: &[Entry]) -> EngineResult<SSTable> {
+        static NEXT_OUTPUT: AtomicU64 = AtomicU64::new(0);
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let name = format!(
+            "compaction_{}_{}.{}",
+            millis,
+            NEXT_OUTPUT.fetch_add(1, Ordering::Relaxed),
+            TEMP_SSTABLE_EXTENSION
+        );
+        let path = self.scratch_dir.join(name);
+        Ok(SSTable::from_entries_with_options(
+            path,
+            entries,
+            &self.write_options,
+        )?)
+    }
+}
+
+/// Length of the value log value `entry` points at, 0 for an inline value
+fn blob_len(entry: &Entry) -> u64 {
+    match &entry.value {
+        Some(pointer) if entry.indirect => BlobPointer::decode(pointer).map_or(0, |p| p.len),
+        _ => 0,
+    }
+}
+
+/// Runs [`CompactionJob`]s on background threads and collects their results
+///
+/// The engine picks and installs compactions itself; this only tracks which
+/// tables are being merged, so no two compactions share an input, and hands
+/// back finished jobs for installation.
+pub(crate) struct BackgroundCompactor {
+    options: BackgroundCompactionOptions,
+    limiter: Option<Arc<RateLimiter>>,
+    /// Inputs of each running job, by job id
+    running: Vec<(u64, Vec<Arc<SSTable>>)>,
+    next_id: u64,
+    sender: Sender<(u64, EngineResult<FinishedCompaction>)>,
+    /// Locked only so the engine stays `Sync`; the engine alone receives
+    receiver: Mutex<Receiver<(u64, EngineResult<FinishedCompaction>)>>,
+}
+
+impl BackgroundCompactor {
+    pub fn new(options: BackgroundCompactionOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            options,
+            limiter: options
+                .rate_limit
+                .map(|rate| Arc::new(RateLimiter::new(rate))),
+            running: Vec::new(),
+            next_id: 0,
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Whether a running job is merging `sstable`
+    pub fn is_busy(&self, sstable: &Arc<SSTable>) -> bool {
+        self.running
+            .iter()
+            .any(|(_, inputs)| inputs.iter().any(|i| Arc::ptr_eq(i, sstable)))
+    }
+
+    /// Number of jobs still running
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Change the rate limit shared by running and future jobs
+    ///
+    /// Jobs already running keep the limiter they started with, so removing
+    /// the limit only unthrottles jobs started afterwards.
+    pub fn set_rate_limit(&mut self, rate_limit: Option<u64>) {
+        self.options.rate_limit = rate_limit;
+        match (rate_limit, &self.limiter) {
+            (Some(rate), Some(limiter)) => limiter.set_rate(rate),
+            (rate, _) => self.limiter = rate.map(|rate| Arc::new(RateLimiter::new(rate))),
+        }
+    }
+
+    /// Whether another job may start
+    pub fn has_capacity(&self) -> bool {
+        self.running.len() < self.options.max_concurrent.max(1)
+    }
+
+    /// Start `job` on a new thread, throttled by the shared rate limit
+    pub fn spawn(&mut self, mut job: CompactionJob) -> std::io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        job.limiter = self.limiter.clone();
+        let inputs = job.task.inputs.clone();
+        let sender = self.sender.clone();
+        std::thread::Builder::new()
+            .name("rustedgedb-compaction".to_string())
+            .spawn(move || {
+                // The engine may have been dropped; its next open removes the outputs
+                let _ = sender.send((id, job.run()));
+            })?;
+        debug!(
+            "Started background compaction {} of {} tables",
+            id,
+            inputs.len()
+        );
+        self.running.push((id, inputs));
+        Ok(())
+    }
+
+    /// Results of the jobs that have finished, without waiting
+    pub fn finished(&mut self) -> Vec<EngineResult<FinishedCompaction>> {
+        let results: Vec<_> = self.receiver.get_mut().unwrap().try_iter().collect();
+        results
+            .into_iter()
+            .map(|result| self.complete(result))
+            .collect()
+    }
+
+    /// Wait for every running job and return their results
+    pub fn wait(&mut self) -> Vec<EngineResult<FinishedCompaction>> {
+        let mut finished = Vec::new();
+        while !self.running.is_empty() {
+            match self.receiver.get_mut().unwrap().recv() {
+                Ok(result) => finished.push(self.complete(result)),
+                // Unreachable while `sender` is held here
+                Err(_) => break,
+            }
+        }
+        finished
+    }
+
+    fn complete(
+        &mut self,
+        (id, result): (u64, EngineResult<FinishedCompaction>),
+    ) -> EngineResult<FinishedCompaction> {
+        self.running.retain(|(running, _)| *running != id);
+        if let Err(e) = &result {
+            error!("Background compaction {} failed: {}", id, e);
+        }
+        result
+    }
+}
+
+/// Picking, running and installing compactions of the engine's tables
+impl Engine {
+    /// Run the compactions the picker finds after a flush, at most
+    /// `compaction.auto_compactions` of them
+    ///
+    /// Each merges a level's tables into the overlapping tables of the next,
+    /// installs the result and retires the inputs, so level 0 stays small and
+    /// each deeper level near its target size. With `background_compaction`
+    /// they are started on background threads instead, and installed by later
+    /// flushes. Time-series engines skip this. Expiry records of dropped keys
+    /// are cleared by the next [`Engine::compact`] or maintenance run.
+    pub(crate) fn compact_after_flush(&mut self) -> EngineResult<usize> {
+        // Time partitions are kept apart so `drop_before` can remove them whole
+        if self.config.time_series.is_some() || self.config.compaction.auto_compactions == 0 {
+            return Ok(0);
+        }
+        if self.compactor.is_some() {
+            return self.schedule_compactions();
+        }
+        let mut compactions = 0;
+        while compactions < self.config.compaction.auto_compactions {
+            let Some(task) = self.pick_compaction() else {
+                break;
+            };
+            self.run_compaction(&task)?;
+            compactions += 1;
+        }
+        Ok(compactions)
+    }
+
+    /// Run the most urgent compaction chosen by the picker, if any is needed
+    pub async fn compact(&mut self) -> EngineResult<Option<CompactionStats>> {
+        let Some(task) = self.pick_compaction() else {
+            return Ok(None);
+        };
+        let stats = self.run_compaction(&task)?;
+        self.purge_expiries().await?;
+        self.collect_value_log_garbage_if_due().await?;
+        Ok(Some(stats))
+    }
+
+    /// Merge every SSTable into the deepest level
+    ///
+    /// Nothing older remains afterwards, so every tombstone is dropped.
+    pub async fn compact_all(&mut self) -> EngineResult<CompactionStats> {
+        self.wait_for_compactions()?;
+        let version = self.versions.current();
+        if version.sstable_count() == 0 {
+            return Ok(CompactionStats::default());
+        }
+        let task = CompactionTask {
+            level: 0,
+            output_level: version.levels.len() - 1,
+            score: 0.0,
+            reason: CompactionReason::Manual,
+            inputs: version.sstables().cloned().collect(),
+        };
+        let stats = self.run_compaction(&task)?;
+        self.purge_expiries().await?;
+        self.collect_value_log_garbage_if_due().await?;
+        Ok(stats)
+    }
+
+    /// Merge a task's inputs into new tables in its output level
+    pub(crate) fn run_compaction(
+        &mut self,
+        task: &CompactionTask,
+    ) -> EngineResult<CompactionStats> {
+        info!(
+            "Compacting {} SSTables from level {} into level {}",
+            task.inputs.len(),
+            task.level,
+            task.output_level
+        );
+        let finished = self.compaction_job(task.clone()).run()?;
+        self.install_compaction(finished)
+    }
+
+    /// Capture what merging `task` needs, so it can run away from the engine
+    ///
+    /// Tombstones are kept while a table outside the inputs, in the output
+    /// level or deeper, may still hold the key. Output is split into tables of
+    /// about `max_file_bytes` each, except in level 0.
+    fn compaction_job(&self, task: CompactionTask) -> CompactionJob {
+        let version = self.versions.current();
+        let shadows = version.levels[task.output_level..]
+            .iter()
+            .flatten()
+            .filter(|t| !task.inputs.iter().any(|i| Arc::ptr_eq(i, t)))
+            .cloned()
+            .collect();
+        let scratch_dir = match &self.config.temp_dir {
+            Some(temp_dir) => temp_dir.clone(),
+            None => self.level_dir(task.output_level).to_path_buf(),
+        };
+        CompactionJob {
+            task,
+            shadows,
+            blobs: self.blobs.clone(),
+            expiries: self.expiries.clone(),
+            write_options: SSTableWriteOptions {
+                compression: self.config.compression,
+                compression_options: self.config.compression_options,
+                use_direct_io: self.config.use_direct_io,
+                block_size: self.config.block_size,
+                prefix_extractor: self.config.prefix_extractor,
+                ..Default::default()
+            },
+            max_file_bytes: self.config.compaction.max_file_bytes,
+            scratch_dir,
+            secure_delete: self.config.secure_delete,
+            limiter: None,
+        }
+    }
+
+    /// Move a compaction's outputs into place and swap them for its inputs
+    ///
+    /// The outputs replace the inputs in the current version, which may have
+    /// gained tables since the compaction started.
+    fn install_compaction(
+        &mut self,
+        finished: FinishedCompaction,
+    ) -> EngineResult<CompactionStats> {
+        let FinishedCompaction {
+            task,
+            outputs,
+            stats,
+            duration,
+        } = finished;
+        let level_dir = self.level_dir(task.output_level).to_path_buf();
+        let mut installed = Vec::with_capacity(outputs.len());
+        for mut sstable in outputs {
+            let path = self.timestamped_path(&level_dir, "sstable", "sst");
+            sstable.persist_to(&path)?;
+            debug!(
+                "SSTable created at {:?} with {} entries",
+                path,
+                sstable.entry_count()
+            );
+            installed.push(Arc::new(sstable));
+        }
+
+        let next = self.versions.current().with_compacted_sstables(
+            &task.inputs,
+            task.output_level,
+            &installed,
+        );
+        self.install_version(next)?;
+        // Readers still holding an input keep it until they finish
+        for sstable in &task.inputs {
+            self.retire_sstable(sstable)?;
+        }
+
+        self.value_log_garbage += stats.blob_bytes_dropped;
+
+        info!(
+            "Compaction wrote {} SSTables with {} entries, dropped {} entries",
+            stats.output_sstables, stats.entries_written, stats.entries_dropped
+        );
+        let kind = JobKind::Compaction {
+            reason: task.reason,
+            level: task.level,
+            output_level: task.output_level,
+        };
+        self.record_job(kind, &task.inputs, &installed, duration);
+        self.events.publish(EngineEvent::CompactionFinished {
+            reason: task.reason,
+            output_level: task.output_level,
+            stats: stats.clone(),
+            duration,
+        });
+        Ok(stats)
+    }
+
+    /// Start background compactions while the picker finds work and threads
+    /// are free, after installing those that finished
+    ///
+    /// Returns the number of compactions started.
+    fn schedule_compactions(&mut self) -> EngineResult<usize> {
+        self.install_background_compactions(false)?;
+        let mut started = 0;
+        while self
+            .compactor
+            .as_ref()
+            .is_some_and(BackgroundCompactor::has_capacity)
+        {
+            let Some(task) = self.pick_compaction() else {
+                break;
+            };
+            info!(
+                "Starting background compaction of {} SSTables from level {} into level {}",
+                task.inputs.len(),
+                task.level,
+                task.output_level
+            );
+            let job = self.compaction_job(task);
+            if let Some(compactor) = &mut self.compactor {
+                compactor.spawn(job)?;
+            }
+            started += 1;
+        }
+        Ok(started)
+    }
+
+    /// Install background compactions that have finished, first waiting for
+    /// every running one when `wait` is set
+    ///
+    /// A result whose inputs are no longer all live, because another
+    /// compaction, a scrub or a truncation replaced them meanwhile, is
+    /// discarded. Returns the number installed.
+    pub(crate) fn install_background_compactions(&mut self, wait: bool) -> EngineResult<usize> {
+        let Some(compactor) = &mut self.compactor else {
+            return Ok(0);
+        };
+        let finished = if wait {
+            compactor.wait()
+        } else {
+            compactor.finished()
+        };
+        let mut installed = 0;
+        // Failed jobs were logged; their inputs stay and are picked again
+        for finished in finished.into_iter().flatten() {
+            let version = self.versions.current();
+            let live = finished
+                .task
+                .inputs
+                .iter()
+                .all(|input| version.sstables().any(|t| Arc::ptr_eq(t, input)));
+            drop(version);
+            if !live {
+                warn!("Discarding background compaction whose inputs changed");
+                finished.discard(self.config.secure_delete);
+                continue;
+            }
+            self.install_compaction(finished)?;
+            installed += 1;
+        }
+        Ok(installed)
+    }
+
+    /// Wait for running background compactions and install their results
+    ///
+    /// Returns the number installed; without `background_compaction` there
+    /// is nothing to wait for.
+    pub fn wait_for_compactions(&mut self) -> EngineResult<usize> {
+        self.install_background_compactions(true)
+    }
+
+    /// Delete a table that is no longer in the current version once its last
+    /// reader drops it, shredding it under `secure_delete`
+    pub(crate) fn retire_sstable(&self, sstable: &SSTable) -> EngineResult<()> {
+        if self.config.secure_delete {
+            sstable.shred_when_deleted();
+        }
+        sstable.mark_obsolete()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compaction::{CompactionOptions, CompactionStrategy, SizeTieredOptions};
+    use crate::engine::{EngineConfig, TimeSeriesOptions};
+    use crate::keys;
+    use crate::sstable::OBSOLETE_SSTABLE_EXTENSION;
+    use tempfile::tempdir;
+
+    /// `(key, value, sequence)` of a test entry, with a None value for a tombstone
+    type TestEntry<'a> = (&'a [u8], Option<&'a [u8]>, u64);
+
+    /// A table at `name` in `dir` holding `entries`
+    fn table(dir: &std::path::Path, name: &str, entries: &[TestEntry]) -> Arc<SSTable> {
+        let entries: Vec<Entry> = entries
+            .iter()
+            .map(|(key, value, sequence)| {
+                Entry::new(
+                    key.to_vec(),
+                    value.map(Bytes::copy_from_slice),
+                    0,
+                    *sequence,
+                )
+            })
+            .collect();
+        let path = dir.join(name);
+        Arc::new(
+            SSTable::from_entries_with_options(path, &entries, &SSTableWriteOptions::default())
+                .unwrap(),
+        )
+    }
+
+    /// A job merging `inputs`, newest first, into level 1
+    fn compaction_job(dir: &std::path::Path, inputs: Vec<Arc<SSTable>>) -> CompactionJob {
+        CompactionJob {
+            task: CompactionTask {
+                level: 0,
+                output_level: 1,
+                score: 0.0,
+                reason: CompactionReason::Manual,
+                inputs,
+            },
+            shadows: Vec::new(),
+            blobs: Arc::new(BlobStore::open(dir.join("blobs")).unwrap()),
+            expiries: Expiries::default(),
+            write_options: SSTableWriteOptions::default(),
+            max_file_bytes: u64::MAX,
+            scratch_dir: dir.to_path_buf(),
+            secure_delete: false,
+            limiter: None,
+        }
+    }
+
+    fn merged(finished: &FinishedCompaction) -> Vec<(Vec<u8>, Option<Bytes>)> {
+        finished
+            .outputs
+            .iter()
+            .flat_map(|t| t.iter().map(Result::unwrap))
+            .map(|entry| (entry.key, entry.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_compaction_job_keeps_newest_versions() {
+        let temp_dir = tempdir().unwrap();
+        let newer = table(
+            temp_dir.path(),
+            "newer.sst",
+            &[(b"a", Some(b"new"), 3), (b"b", None, 4)],
+        );
+        let older = table(
+            temp_dir.path(),
+            "older.sst",
+            &[
+                (b"a", Some(b"old"), 1),
+                (b"b", Some(b"old"), 2),
+                (b"c", Some(b"old"), 1),
+            ],
+        );
+
+        // Nothing deeper can hold `b`, so its tombstone goes with the value it covers
+        let finished = compaction_job(temp_dir.path(), vec![newer.clone(), older.clone()])
+            .run()
+            .unwrap();
+        assert_eq!(
+            merged(&finished),
+            vec![
+                (b"a".to_vec(), Some(Bytes::from_static(b"new"))),
+                (b"c".to_vec(), Some(Bytes::from_static(b"old"))),
+            ]
+        );
+        assert_eq!(finished.stats.input_sstables, 2);
+        assert_eq!(finished.stats.entries_written, 2);
+        assert_eq!(finished.stats.entries_dropped, 3);
+        finished.discard(false);
+
+        // A deeper table holding `b` keeps the tombstone in the output
+        let mut job = compaction_job(temp_dir.path(), vec![newer, older]);
+        job.shadows = vec![table(
+            temp_dir.path(),
+            "deeper.sst",
+            &[(b"b", Some(b"oldest"), 0)],
+        )];
+        let finished = job.run().unwrap();
+        assert!(merged(&finished).contains(&(b"b".to_vec(), None)));
+    }
+
+    #[test]
+    fn test_compaction_job_splits_output_by_size() {
+        let temp_dir = tempdir().unwrap();
+        let keys: Vec<Vec<u8>> = (0..10).map(|i| format!("key{}", i).into_bytes()).collect();
+        let entries: Vec<TestEntry> = keys
+            .iter()
+            .map(|key| (key.as_slice(), Some([7u8; 96].as_slice()), 1))
+            .collect();
+        let input = table(temp_dir.path(), "input.sst", &entries);
+
+        let mut job = compaction_job(temp_dir.path(), vec![input]);
+        job.max_file_bytes = 300;
+        let finished = job.run().unwrap();
+        assert_eq!(finished.stats.output_sstables, 4);
+        assert_eq!(finished.outputs.len(), 4);
+        assert_eq!(merged(&finished).len(), 10);
+    }
+
+    #[test]
+    fn test_background_compactor_tracks_running_inputs() {
+        let temp_dir = tempdir().unwrap();
+        let input = table(temp_dir.path(), "input.sst", &[(b"a", Some(b"value"), 1)]);
+        let mut compactor = BackgroundCompactor::new(BackgroundCompactionOptions {
+            max_concurrent: 1,
+            rate_limit: None,
+        });
+        assert!(compactor.has_capacity());
+
+        compactor
+            .spawn(compaction_job(temp_dir.path(), vec![input.clone()]))
+            .unwrap();
+        assert!(!compactor.has_capacity());
+        assert!(compactor.is_busy(&input));
+
+        let finished = compactor.wait();
+        assert_eq!(finished.len(), 1);
+        assert!(finished[0].is_ok());
+        assert!(compactor.has_capacity());
+        assert!(!compactor.is_busy(&input));
+        assert!(compactor.wait().is_empty());
+    }
+
+    #[test]
+    fn test_background_compactor_frees_inputs_of_a_failed_job() {
+        let temp_dir = tempdir().unwrap();
+        let input = table(temp_dir.path(), "input.sst", &[(b"a", Some(b"value"), 1)]);
+        let mut compactor = BackgroundCompactor::new(BackgroundCompactionOptions::default());
+        let mut job = compaction_job(temp_dir.path(), vec![input.clone()]);
+        job.scratch_dir = temp_dir.path().join("missing");
+        compactor.spawn(job).unwrap();
+
+        // The inputs may be picked again once the failure is collected
+        let finished = compactor.wait();
+        assert!(finished[0].is_err());
+        assert!(!compactor.is_busy(&input));
+        assert!(compactor.has_capacity());
+    }
+
+    #[test]
+    fn test_rate_limiter_paces_bytes() {
+        let limiter = RateLimiter::new(1024 * 1024);
+
+        // A second's worth passes at once, the next 100KB waits about 100ms
+        let started = Instant::now();
+        limiter.acquire(1024 * 1024);
+        assert!(started.elapsed() < Duration::from_millis(50));
+        limiter.acquire(100 * 1024);
+        assert!(started.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn test_pick_compaction() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            compaction: CompactionOptions {
+                level0_file_trigger: 3,
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        for i in 0..3 {
+            assert!(engine.pick_compaction().is_none());
+            engine
+                .put(format!("key{}", i).as_bytes(), b"value")
+                .await
+                .unwrap();
+            engine.force_flush().await.unwrap();
+        }
+
+        let task = engine.pick_compaction().unwrap();
+        assert_eq!((task.level, task.output_level), (0, 1));
+        assert_eq!(task.input_paths().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flush_compacts_levels() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            compaction: CompactionOptions {
+                level0_file_trigger: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        engine.put(b"a", b"old").await.unwrap();
+        engine.put(b"b", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.versions.current().levels[0].len(), 1);
+        let first = engine.versions.current().levels[0][0].clone();
+
+        // The second table reaches the trigger, so the flush merges level 0 down
+        engine.put(b"a", b"new").await.unwrap();
+        engine.delete(b"b").await.unwrap();
+        engine.force_flush().await.unwrap();
+        {
+            let version = engine.versions.current();
+            assert_eq!(version.levels[0].len(), 0);
+            assert_eq!(version.levels[1].len(), 1);
+        }
+        assert!(engine.pick_compaction().is_none());
+        let first_path = first.path().to_path_buf();
+        drop(first);
+        assert!(!first_path.exists());
+        assert_eq!(engine.get(b"a").await.unwrap(), Some(Bytes::from("new")));
+        assert_eq!(engine.get(b"b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_background_compaction() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            compaction: CompactionOptions {
+                level0_file_trigger: 2,
+                ..Default::default()
+            },
+            background_compaction: Some(BackgroundCompactionOptions {
+                max_concurrent: 2,
+                rate_limit: Some(64 * 1024 * 1024),
+            }),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        engine.put(b"a", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"a", b"new").await.unwrap();
+        engine.put(b"b", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        // The flush only starts the compaction; its inputs can't be picked again
+        assert_eq!(engine.stats().compactions_running, 1);
+        assert!(engine.pick_compaction().is_none());
+        assert_eq!(engine.get(b"a").await.unwrap(), Some(Bytes::from("new")));
+
+        assert_eq!(engine.wait_for_compactions().unwrap(), 1);
+        assert_eq!(engine.stats().compactions_running, 0);
+        {
+            let version = engine.versions.current();
+            assert_eq!(version.levels[0].len(), 0);
+            assert_eq!(version.levels[1].len(), 1);
+            assert_eq!(version.levels[1][0].entry_count(), 2);
+        }
+        assert_eq!(engine.get(b"a").await.unwrap(), Some(Bytes::from("new")));
+
+        // A result whose inputs were replaced meanwhile is thrown away
+        engine.put(b"c", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"d", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.stats().compactions_running, 1);
+        engine.truncate().await.unwrap();
+        assert_eq!(engine.wait_for_compactions().unwrap(), 0);
+        assert_eq!(engine.stats().sstable_count, 0);
+        let leftovers = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.path().to_string_lossy().ends_with(TEMP_SSTABLE_EXTENSION))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_size_tiered_compaction() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            compaction_strategy: CompactionStrategy::SizeTiered(SizeTieredOptions {
+                min_merge_width: 3,
+                min_run_bytes: 0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        // One large run, then three small flushes that tier together
+        for i in 0..50 {
+            engine
+                .put(format!("key{:02}", i).as_bytes(), b"value")
+                .await
+                .unwrap();
+        }
+        engine.force_flush().await.unwrap();
+        engine.put(b"x", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.delete(b"key07").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"y", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 2);
+        assert_eq!(version.sstable_count(), 2);
+        // The large run is older, so the merged run keeps the tombstone over it
+        let merged = &version.levels[0][0];
+        assert_eq!(merged.entry_count(), 3);
+        assert_eq!(merged.tombstone_count(), Some(1));
+        assert_eq!(version.levels[0][1].entry_count(), 50);
+        drop(version);
+        assert_eq!(engine.get(b"key07").await.unwrap(), None);
+        assert_eq!(
+            engine.get(b"key08").await.unwrap(),
+            Some(Bytes::from("value"))
+        );
+        assert_eq!(engine.get(b"y").await.unwrap(), Some(Bytes::from("1")));
+
+        // Once every run merges, nothing older remains and the tombstone goes
+        engine.compact_all().await.unwrap();
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 51);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_merges_levels() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            compaction: CompactionOptions {
+                level0_file_trigger: 2,
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        assert!(engine.compact().await.unwrap().is_none());
+
+        engine.put(b"a", b"old").await.unwrap();
+        engine.put(b"b", b"old").await.unwrap();
+        engine.put(b"c", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"a", b"new").await.unwrap();
+        engine.delete(b"b").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        // Level 0 merges into level 1; with nothing deeper the tombstone is dropped
+        let stats = engine.compact().await.unwrap().unwrap();
+        assert_eq!(stats.input_sstables, 2);
+        assert_eq!(stats.output_sstables, 1);
+        assert_eq!(stats.entries_written, 2);
+        assert_eq!(stats.entries_dropped, 3);
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 0);
+        assert_eq!(version.levels[1].len(), 1);
+        assert_eq!(engine.get(b"a").await.unwrap(), Some(Bytes::from("new")));
+        assert_eq!(engine.get(b"b").await.unwrap(), None);
+        assert_eq!(engine.get(b"c").await.unwrap(), Some(Bytes::from("old")));
+        assert!(engine.compact().await.unwrap().is_none());
+
+        // A tombstone over data in a deeper level is kept
+        engine.compact_all().await.unwrap();
+        assert_eq!(engine.versions.current().levels[2].len(), 1);
+        engine.delete(b"c").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"d", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+        let stats = engine.compact().await.unwrap().unwrap();
+        assert_eq!(stats.entries_written, 2);
+        assert_eq!(engine.get(b"c").await.unwrap(), None);
+
+        let stats = engine.compact_all().await.unwrap();
+        assert_eq!(stats.entries_written, 2);
+        let version = engine.versions.current();
+        assert_eq!(version.sstable_count(), 1);
+        assert_eq!(version.levels[2].len(), 1);
+        assert_eq!(
+            engine.scan::<&[u8], _>(..).await.unwrap(),
+            vec![
+                (b"a".to_vec(), Bytes::from("new")),
+                (b"d".to_vec(), Bytes::from("new"))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_sstables_outlive_readers() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            time_series: Some(TimeSeriesOptions {
+                partition_duration_ms: 1000,
+            }),
+            ..Default::default()
+        };
+        let key = |ts: u64| keys::KeyEncoder::new().u64(ts).finish();
+        let sstable_files = |ext: &str| {
+            std::fs::read_dir(temp_dir.path())
+                .unwrap()
+                .flatten()
+                .filter(|e| e.path().to_str().unwrap().ends_with(ext))
+                .count()
+        };
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for ts in [500, 1500] {
+            engine.put(&key(ts), b"reading").await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+
+        // The iterator holds the older partition while it is dropped
+        let mut iter = engine.iter::<&[u8], _>(..);
+        engine.drop_before(1000).await.unwrap();
+        assert_eq!(sstable_files(".sst"), 1);
+        assert_eq!(sstable_files(OBSOLETE_SSTABLE_EXTENSION), 1);
+        assert_eq!(iter.next().unwrap().unwrap().0, key(500));
+        drop(iter);
+        assert_eq!(sstable_files(OBSOLETE_SSTABLE_EXTENSION), 0);
+
+        // Leftovers from a crash are removed on open rather than loaded
+        let mut iter = engine.iter::<&[u8], _>(..);
+        engine.drop_before(2000).await.unwrap();
+        assert!(iter.next().is_some());
+        std::mem::forget(iter);
+        drop(engine);
+        assert_eq!(sstable_files(OBSOLETE_SSTABLE_EXTENSION), 1);
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(sstable_files(OBSOLETE_SSTABLE_EXTENSION), 0);
+        assert_eq!(engine.versions.current().sstable_count(), 0);
+    }
+}
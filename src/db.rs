@@ -0,0 +1,352 @@
+use bytes::Bytes;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep_until};
+use tracing::warn;
+
+use crate::batch::WriteBatch;
+use crate::engine::{
+    CheckpointStats, Engine, EngineConfig, EngineResult, MaintenanceOptions, ScanPage,
+};
+
+/// Cloneable handle to one engine, shared by any number of tasks
+///
+/// Reads run concurrently under a read lock, since point lookups and scans
+/// only need `&Engine`; writes take the write lock one at a time. For write
+/// heavy workloads [`crate::write_queue::WriteQueue`] pipelines writes
+/// through a single writer task instead. When the engine has `maintenance`
+/// configured, a background task runs [`Engine::run_maintenance`] on its
+/// schedule until the last handle is dropped or closed.
+#[derive(Clone)]
+pub struct Db {
+    engine: Arc<RwLock<Engine>>,
+    schedule: Option<Arc<MaintenanceSchedule>>,
+}
+
+impl Db {
+    /// Open the database in `data_dir` with the default configuration
+    pub async fn open<P: AsRef<Path>>(data_dir: P) -> EngineResult<Self> {
+        Ok(Self::from_engine(Engine::new(data_dir).await?))
+    }
+
+    /// Open a database with a custom configuration
+    pub async fn open_with_config(config: EngineConfig) -> EngineResult<Self> {
+        Ok(Self::from_engine(Engine::with_config(config).await?))
+    }
+
+    /// Share an engine that is already open
+    ///
+    /// Scheduled maintenance needs a Tokio runtime; without one it is skipped
+    /// with a warning.
+    pub fn from_engine(engine: Engine) -> Self {
+        let engine = Arc::new(RwLock::new(engine));
+        let schedule = MaintenanceSchedule::spawn(&engine).map(Arc::new);
+        Self { engine, schedule }
+    }
+
+    /// Get the value of a key
+    pub async fn get(&self, key: &[u8]) -> EngineResult<Option<Bytes>> {
+        self.engine.read().await.get(key).await
+    }
+
+    /// Collect the live key-value pairs in `range`, in key order
+    pub async fn scan<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        self.engine.read().await.scan(range).await
+    }
+
+    /// Scan up to `limit` live keys starting with `prefix`, resuming after `token`
+    ///
+    /// See [`Engine::scan_prefix`].
+    pub async fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        limit: usize,
+        token: Option<&str>,
+    ) -> EngineResult<ScanPage> {
+        self.engine
+            .read()
+            .await
+            .scan_prefix(prefix, limit, token)
+            .await
+    }
+
+    /// Put a key-value pair, returning its sequence number
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> EngineResult<u64> {
+        self.write().await.put(key, value).await
+    }
+
+    /// Put a key-value pair that reads as missing once `ttl` has passed
+    ///
+    /// See [`Engine::put_with_ttl`].
+    pub async fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> EngineResult<u64> {
+        self.write().await.put_with_ttl(key, value, ttl).await
+    }
+
+    /// Delete a key, returning the tombstone's sequence number
+    pub async fn delete(&self, key: &[u8]) -> EngineResult<u64> {
+        self.write().await.delete(key).await
+    }
+
+    /// Apply a batch of writes atomically
+    ///
+    /// See [`Engine::write_batch`].
+    pub async fn write_batch(&self, batch: WriteBatch) -> EngineResult<u64> {
+        self.write().await.write_batch(batch).await
+    }
+
+    /// Flush the MemTable to an SSTable
+    pub async fn flush(&self) -> EngineResult<()> {
+        self.write().await.force_flush().await
+    }
+
+    /// Write a consistent copy of the database to `dir`
+    ///
+    /// See [`Engine::checkpoint`]; writes from other handles wait until it is done.
+    pub async fn checkpoint<P: AsRef<Path>>(&self, dir: P) -> EngineResult<CheckpointStats> {
+        self.engine.read().await.checkpoint(dir)
+    }
+
+    /// Shared access to the engine, for reads this handle doesn't wrap
+    ///
+    /// Writes from other handles wait while the guard is held.
+    pub async fn read(&self) -> RwLockReadGuard<'_, Engine> {
+        self.engine.read().await
+    }
+
+    /// Exclusive access to the engine, for writes this handle doesn't wrap
+    ///
+    /// Counts as write activity for scheduled maintenance.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Engine> {
+        if let Some(schedule) = &self.schedule {
+            schedule.note_write();
+        }
+        self.engine.write().await
+    }
+
+    /// Stop scheduled maintenance, flush remaining writes and close the engine
+    ///
+    /// Other handles must not write afterwards; see [`Engine::close`].
+    pub async fn close(self) -> EngineResult<()> {
+        if let Some(schedule) = &self.schedule {
+            schedule.stop();
+        }
+        self.engine.write().await.close().await
+    }
+}
+
+impl From<Engine> for Db {
+    fn from(engine: Engine) -> Self {
+        Self::from_engine(engine)
+    }
+}
+
+/// Background task running [`Engine::run_maintenance`] for an engine shared
+/// behind a lock, on the engine's `maintenance` options
+///
+/// Like the [`crate::write_queue::WriteQueue`] writer, it runs a pass every
+/// `interval` and once writes reported through [`Self::note_write`] have
+/// paused for `idle_after`. It stops when told to, when dropped, or once the
+/// engine itself has been dropped.
+pub(crate) struct MaintenanceSchedule {
+    state: Arc<ScheduleState>,
+    task: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+struct ScheduleState {
+    activity: Notify,
+    wake: Notify,
+    stopped: AtomicBool,
+}
+
+impl MaintenanceSchedule {
+    /// Start the task, or return None when maintenance isn't configured or
+    /// there is no runtime to run it on
+    pub(crate) fn spawn(engine: &Arc<RwLock<Engine>>) -> Option<Self> {
+        // Nobody else can hold the lock before the engine is shared
+        let options = engine.try_read().ok()?.config().maintenance.clone()?;
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!("No Tokio runtime to run scheduled maintenance on; call run_maintenance instead");
+            return None;
+        };
+        let state = Arc::new(ScheduleState {
+            activity: Notify::new(),
+            wake: Notify::new(),
+            stopped: AtomicBool::new(false),
+        });
+        let task = runtime.spawn(run_schedule(Arc::downgrade(engine), state.clone(), options));
+        Some(Self {
+            state,
+            task: std::sync::Mutex::new(Some(task)),
+        })
+    }
+
+    /// Record a write, restarting the idle countdown
+    pub(crate) fn note_write(&self) {
+        self.state.activity.notify_one();
+    }
+
+    /// Stop scheduling passes; a pass that is already running finishes first
+    pub(crate) fn stop(&self) {
+        self.state.stopped.store(true, Ordering::Release);
+        self.state.wake.notify_one();
+    }
+
+    /// Stop scheduling passes and wait for the task to exit
+    pub(crate) async fn shutdown(&self) {
+        self.stop();
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for MaintenanceSchedule {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Run maintenance passes until stopped or the engine is gone
+async fn run_schedule(
+    engine: Weak<RwLock<Engine>>,
+    state: Arc<ScheduleState>,
+    options: MaintenanceOptions,
+) {
+    let mut next_run = Instant::now() + options.interval;
+    let mut idle_deadline: Option<Instant> = None;
+    while !state.stopped.load(Ordering::Acquire) {
+        let deadline = idle_deadline.map_or(next_run, |idle| idle.min(next_run));
+        tokio::select! {
+            _ = state.wake.notified() => continue,
+            _ = state.activity.notified() => {
+                idle_deadline = options.idle_after.map(|idle| Instant::now() + idle);
+                continue;
+            }
+            _ = sleep_until(deadline) => {}
+        }
+        let Some(engine) = engine.upgrade() else {
+            break;
+        };
+        let mut engine = engine.write().await;
+        // The engine may have been closed while this pass waited for the lock
+        if state.stopped.load(Ordering::Acquire) {
+            break;
+        }
+        if let Err(e) = engine.run_maintenance().await {
+            warn!("Maintenance failed: {}", e);
+        }
+        next_run = Instant::now() + options.interval;
+        idle_deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compaction::CompactionOptions;
+    use tempfile::tempdir;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_db_shared_across_tasks() {
+        let temp_dir = tempdir().unwrap();
+        let db = Db::open_with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 4096,
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Writers and readers on every worker share clones of one handle
+        let mut tasks = Vec::new();
+        for writer in 0..4u32 {
+            let db = db.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..50u32 {
+                    let key = format!("w{}-{:02}", writer, i);
+                    db.put(key.as_bytes(), &i.to_le_bytes()).await.unwrap();
+                    assert_eq!(
+                        db.get(key.as_bytes()).await.unwrap(),
+                        Some(Bytes::copy_from_slice(&i.to_le_bytes()))
+                    );
+                }
+                db.delete(format!("w{}-00", writer).as_bytes())
+                    .await
+                    .unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(db.scan::<&[u8], _>(..).await.unwrap().len(), 4 * 49);
+        assert!(db.read().await.stats().sstable_count > 0);
+
+        let mut batch = WriteBatch::new();
+        batch.put(b"w0-00", b"back");
+        db.write_batch(batch).await.unwrap();
+        db.flush().await.unwrap();
+        db.clone().close().await.unwrap();
+
+        let db = Db::open(temp_dir.path()).await.unwrap();
+        assert_eq!(
+            db.get(b"w0-00").await.unwrap(),
+            Some(Bytes::from_static(b"back"))
+        );
+        assert_eq!(db.get(b"w1-00").await.unwrap(), None);
+        assert_eq!(db.scan::<&[u8], _>(..).await.unwrap().len(), 4 * 49 + 1);
+    }
+
+    // The clock only advances while every task waits, so slow writes never
+    // count as idle time
+    #[tokio::test(start_paused = true)]
+    async fn test_db_runs_scheduled_maintenance() {
+        let temp_dir = tempdir().unwrap();
+        let db = Db::open_with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 256,
+            env_overrides: false,
+            compaction: CompactionOptions {
+                level0_file_trigger: 2,
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            maintenance: Some(MaintenanceOptions {
+                interval: Duration::from_secs(3600),
+                idle_after: Some(Duration::from_millis(20)),
+                max_compactions: 4,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        for i in 0..50 {
+            let key = format!("key{:02}", i);
+            db.put(key.as_bytes(), &[b'x'; 32]).await.unwrap();
+        }
+        let flushed = db.read().await.stats().sstable_count;
+        assert!(flushed > 2);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Several flushes were merged once the writes paused
+        assert!(db.read().await.stats().sstable_count <= 2);
+        assert_eq!(db.scan::<&[u8], _>(..).await.unwrap().len(), 50);
+
+        // Closing stops the task before the engine goes away
+        db.clone().close().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(7200)).await;
+        let db = Db::open(temp_dir.path()).await.unwrap();
+        assert_eq!(db.scan::<&[u8], _>(..).await.unwrap().len(), 50);
+    }
+}
@@ -1,11 +1,47 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap, HashSet, btree_map};
+use std::io::{self, BufRead, Write};
+use std::ops::{Bound, RangeBounds};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{error, info, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::memtable::{MemTable, MemTableError};
-use crate::sstable::{CompressionType, SSTable, SSTableError};
-use crate::wal::{WAL, WALError};
+use crate::archive::{ArchiveError, ArchiveReader, ArchiveWriter};
+use crate::batch::{BatchLimits, BatchOp, WriteBatch};
+use crate::blob::{BlobError, BlobPointer, BlobStore, BlobWriter, VALUE_LOG_FILE_SIZE};
+use crate::cache::{BlockCache, BlockCacheStats, DEFAULT_BLOCK_CACHE_SIZE};
+use crate::compaction::{
+    CompactionOptions, CompactionPicker, CompactionReason, CompactionStrategy, CompactionTask,
+};
+use crate::compactor::{BackgroundCompactionOptions, BackgroundCompactor};
+use crate::events::{EngineEvent, EventBus, StallReason};
+use crate::expiry::{self, Expiries};
+use crate::export::{ExportError, ExportFormat, Record, RecordReader, RecordWriter};
+use crate::flusher::BackgroundFlusher;
+use crate::history::{FlushReason, JobHistory, JobKind, JobRecord};
+use crate::hotkeys::{HotKey, HotKeyOptions, HotKeyTracker};
+use crate::index::{self, IndexExtractor, SecondaryIndex};
+use crate::io_backend::{self, IoBackend, sync_dir};
+use crate::iterator::{EngineIterator, RawIterator, read_base};
+use crate::keys::{self, PrefixExtractor};
+use crate::layout::{self, LayoutError};
+use crate::manifest::{Epoch, MANIFEST_FILE, Manifest, ManifestError, ManifestLog};
+use crate::memtable::{Entry, MemTable, MemTableError};
+use crate::merge::{self, MergeError, MergeOp};
+use crate::sstable::{
+    CompressionOptions, CompressionType, DEFAULT_BLOCK_SIZE, SSTable, SSTableError,
+    SSTableWriteOptions,
+};
+#[cfg(feature = "stats")]
+use crate::stats::{self, STATS_DIR, StatsSnapshot};
+use crate::version::{Version, VersionSet};
+use crate::wal::{
+    RecoveryProgress, SyncPolicy, SyncTicket, WAL, WAL_FORMAT_VERSION, WALError, WALRecord,
+    WalSyncer,
+};
 
 /// Errors that can occur during Engine operations
 #[derive(Error, Debug)]
@@ -16,12 +52,36 @@ pub enum EngineError {
     MemTable(#[from] MemTableError),
     #[error("SSTable error: {0}")]
     SSTable(#[from] SSTableError),
+    #[error("Blob error: {0}")]
+    Blob(#[from] BlobError),
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
     #[error("Recovery failed: {0}")]
     RecoveryFailed(String),
+    #[error("Index not found: {0}")]
+    IndexNotFound(String),
+    #[error("Invalid scan token: {0}")]
+    InvalidToken(String),
+    #[error("Write queue closed")]
+    WriterClosed,
+    #[error("Sequence {0} has not been written")]
+    SequenceNotWritten(u64),
+    #[error("Layout error: {0}")]
+    Layout(#[from] LayoutError),
+    #[error("Manifest error: {0}")]
+    Manifest(#[from] ManifestError),
+    #[error("Archive error: {0}")]
+    Archive(#[from] ArchiveError),
+    #[error("Export error: {0}")]
+    Export(#[from] ExportError),
+    #[error("Merge error: {0}")]
+    Merge(#[from] MergeError),
+    #[error("Write batch of {0} bytes does not fit in the MemTable")]
+    BatchTooLarge(usize),
+    #[error("Write batch of {entries} operations and {bytes} bytes exceeds the batch limits")]
+    BatchLimitExceeded { entries: usize, bytes: usize },
 }
 
 /// Result type for Engine operations
@@ -32,37 +92,751 @@ pub type EngineResult<T> = Result<T, EngineError>;
 pub struct EngineConfig {
     /// Data directory for storing WAL and SSTable files
     pub data_dir: PathBuf,
+    /// Scratch directory where compaction and imports write their files before
+    /// moving them into `data_dir`, such as a tmpfs or a faster disk (None
+    /// writes them in `data_dir`). Leftovers are removed on open, so each
+    /// engine needs its own.
+    pub temp_dir: Option<PathBuf>,
+    /// Directories for SSTables at a level and every deeper one, such as
+    /// `{6: "/mnt/sd"}` to keep the bottom level on a large, slow card while
+    /// upper levels stay on fast flash in `data_dir`. Tables move when a
+    /// compaction rewrites them into a level with another directory.
+    pub level_dirs: BTreeMap<usize, PathBuf>,
     /// Maximum size of MemTable in bytes before flushing to SSTable
     pub memtable_size: usize,
+    /// Also flush once the MemTable holds this many entries, for workloads of
+    /// tiny entries that the byte limit alone would let pile up (None disables)
+    pub memtable_max_entries: Option<usize>,
+    /// Byte and operation caps on a single [`Engine::write_batch`], and whether
+    /// batches over them are split or rejected
+    pub batch_limits: BatchLimits,
+    /// Flush the MemTable and rotate the WAL once the active WAL grows past this
+    /// many bytes, bounding replay time after a crash (None leaves it unbounded)
+    pub max_wal_bytes: Option<u64>,
+    /// Full MemTables that may wait for a background flush while a fresh one
+    /// takes writes; writes stall only once this many are queued (0 flushes
+    /// on the write path)
+    pub max_immutable_memtables: usize,
     /// Compression type for SSTable files
     pub compression: CompressionType,
+    /// Which values are worth compressing
+    pub compression_options: CompressionOptions,
+    /// Target size of SSTable data blocks; a lookup reads one block, and the
+    /// in-memory index holds one entry per block rather than per key
+    pub block_size: usize,
+    /// Bytes of SSTable blocks kept in memory for point reads, shared by all
+    /// tables; 0 disables the cache
+    pub block_cache_size: usize,
+    /// Add the prefix of each key to SSTable bloom filters, so scans that
+    /// stay within one prefix skip tables without it
+    pub prefix_extractor: Option<PrefixExtractor>,
     /// Maximum number of SSTable levels
     pub max_levels: usize,
+    /// When levels and tables need compaction
+    pub compaction: CompactionOptions,
+    /// Whether compaction pushes data down through levels or merges level 0
+    /// runs of similar size, which rewrites less for write-heavy workloads
+    pub compaction_strategy: CompactionStrategy,
+    /// Run the compactions flushes trigger on background threads, throttled,
+    /// instead of on the write path (None runs them inline)
+    pub background_compaction: Option<BackgroundCompactionOptions>,
+    /// Compaction to run once recovery has finished
+    pub compact_on_open: CompactOnOpen,
+    /// Compaction [`Engine::close`] runs after its final flush
+    pub compact_on_close: CompactOnClose,
+    /// Track approximate per-key access counts for [`EngineStats::hot_keys`]
+    /// (None disables tracking)
+    pub hot_keys: Option<HotKeyOptions>,
+    /// Periodic expiry and compaction scheduled by [`crate::db::Db`], the
+    /// [`crate::write_queue::WriteQueue`] writer and the socket server;
+    /// an `Engine` used directly only runs it from [`Engine::run_maintenance`]
+    /// (None disables it)
+    pub maintenance: Option<MaintenanceOptions>,
+    /// Use O_DIRECT for flush and compaction writes so large background writes
+    /// don't evict the OS page cache that foreground reads depend on
+    pub use_direct_io: bool,
+    /// Values at least this large are stored in the value log and the tree keeps
+    /// only a pointer (None keeps every value inline)
+    pub value_log_threshold: Option<usize>,
+    /// Value log files whose live fraction drops below this ratio are rewritten
+    /// during garbage collection
+    pub value_log_gc_ratio: f64,
+    /// Collect value log garbage once compactions have dropped pointers to
+    /// this many bytes of values since the last collection (None leaves it
+    /// to [`Engine::collect_value_log_garbage`])
+    pub value_log_auto_gc_bytes: Option<u64>,
+    /// Optimize for keys prefixed with a big-endian millisecond timestamp
+    pub time_series: Option<TimeSeriesOptions>,
+    /// When WAL appends are fsynced; [`SyncPolicy::Interval`] runs a background
+    /// task that acknowledges [`Engine::durability_ticket`] waiters in batches
+    pub wal_sync_policy: SyncPolicy,
+    /// Apply `RUSTEDGEDB_*` environment overrides when the engine is constructed
+    pub env_overrides: bool,
+    /// Number of most-read SSTables, per the persisted access profile, that
+    /// [`Engine::warm_up`] reads into the page cache
+    pub warm_up_hot_tables: usize,
+    /// Called with WAL replay progress while the engine opens, so startup on a
+    /// large log can be reported instead of looking hung
+    pub recovery_progress: Option<RecoveryProgressCallback>,
+    /// Worker threads decoding WAL segments in parallel during recovery when
+    /// their sequence ranges don't overlap (1 replays them one at a time)
+    pub recovery_threads: usize,
+    /// Open existing SSTables from their header and key range only, loading each
+    /// index and bloom filter when a read first needs it; corruption in those
+    /// sections then fails that read instead of the open, and
+    /// `corrupt_file_policy` is applied there
+    pub lazy_open_sstables: bool,
+    /// What to do with SSTables that can't be opened at startup, or that a
+    /// point read finds unreadable
+    pub corrupt_file_policy: CorruptFilePolicy,
+    /// What to do when the recovered WAL and the SSTables disagree on which
+    /// version of a key is newest
+    pub startup_checks: StartupCheckPolicy,
+    /// Overwrite retired SSTables, WAL and value log files with zeros before
+    /// deleting them, so [`Engine::purge_range`] can report purged keys as
+    /// unrecoverable from the data directory
+    pub secure_delete: bool,
+    /// Bus to publish [`EngineEvent`]s on; pass one to subscribe before the
+    /// engine opens and see corruption found at startup (None creates one,
+    /// see [`Engine::subscribe`])
+    pub events: Option<EventBus>,
+}
+
+/// How startup treats an SSTable it can't open
+///
+/// Skipping keeps the engine available but hides the table's data, which can
+/// look like data loss; stricter policies make the problem visible. Point
+/// reads that find a lazily opened table unreadable apply the same policy,
+/// after failing the read itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CorruptFilePolicy {
+    /// Leave the file in place and open without it
+    #[default]
+    Skip,
+    /// Move the file into the `corrupt/` subdirectory and open without it
+    Quarantine,
+    /// Fail to open the engine
+    Strict,
+}
+
+impl std::str::FromStr for CorruptFilePolicy {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> EngineResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "quarantine" => Ok(Self::Quarantine),
+            "strict" | "fail" => Ok(Self::Strict),
+            _ => Err(EngineError::InvalidConfig(format!(
+                "Invalid corrupt file policy: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How startup treats recovered data that breaks sequence number invariants
+///
+/// The checks run after WAL replay: every WAL record must be replayed after
+/// the older versions of its key, no SSTable may hold a newer version of a
+/// key than the one recovered from the WAL, and an SSTable read before
+/// another may not hold an older version of a key they share. Any of these
+/// means reads would return a stale value. Sequence numbers must also run on
+/// from the SSTables through the WAL segments without overlap or gap, and
+/// name the same write wherever they appear; otherwise writes were lost or
+/// replayed twice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartupCheckPolicy {
+    /// Skip the checks, e.g. to open a large database faster
+    Off,
+    /// Log each violation and open anyway
+    #[default]
+    Warn,
+    /// Fail to open the engine
+    Fail,
+}
+
+impl std::str::FromStr for StartupCheckPolicy {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> EngineResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "fail" | "strict" => Ok(Self::Fail),
+            _ => Err(EngineError::InvalidConfig(format!(
+                "Invalid startup check policy: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Callback receiving [`RecoveryProgress`] during WAL replay
+#[derive(Clone)]
+pub struct RecoveryProgressCallback(pub Arc<dyn Fn(&RecoveryProgress) + Send + Sync>);
+
+impl RecoveryProgressCallback {
+    pub fn new(callback: impl Fn(&RecoveryProgress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl std::fmt::Debug for RecoveryProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RecoveryProgressCallback")
+    }
+}
+
+/// Compaction [`Engine::with_config`] runs right after recovery
+///
+/// Devices that flushed many small SSTables while offline can pay the
+/// compaction cost up front instead of through read latency once in service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompactOnOpen {
+    #[default]
+    Never,
+    /// Merge every SSTable into the deepest level
+    Full,
+    /// Run at most this many compactions chosen by the picker
+    Capped(usize),
+}
+
+impl std::str::FromStr for CompactOnOpen {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> EngineResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" | "none" => Ok(Self::Never),
+            "full" => Ok(Self::Full),
+            n => n.parse().map(Self::Capped).map_err(|_| {
+                EngineError::InvalidConfig(format!("Invalid compact-on-open policy: {}", s))
+            }),
+        }
+    }
+}
+
+/// Compaction [`Engine::close`] runs before shutting down
+///
+/// Devices that reboot often can leave a compact tree behind instead of
+/// paying for read amplification on every boot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompactOnClose {
+    #[default]
+    Never,
+    /// Merge level 0 into level 1
+    Level0,
+    /// Merge every SSTable into the deepest level
+    Full,
+}
+
+impl std::str::FromStr for CompactOnClose {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> EngineResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" | "none" => Ok(Self::Never),
+            "level0" | "l0" => Ok(Self::Level0),
+            "full" => Ok(Self::Full),
+            _ => Err(EngineError::InvalidConfig(format!(
+                "Invalid compact-on-close policy: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Options for the periodic maintenance job
+///
+/// Each run drops expired time-series data and runs a few compactions chosen
+/// by the picker, so expired and overwritten data doesn't pile up between
+/// full compactions. Passes are scheduled by the handles that share an
+/// engine; see [`EngineConfig::maintenance`].
+#[derive(Debug, Clone)]
+pub struct MaintenanceOptions {
+    /// Run at least this often
+    pub interval: Duration,
+    /// Also run once no write has arrived for this long (None disables)
+    pub idle_after: Option<Duration>,
+    /// In time-series mode, drop keys older than this (None keeps everything)
+    pub retention: Option<Duration>,
+    /// Compactions run per pass at most
+    pub max_compactions: usize,
+    /// SSTable bytes re-read and verified per pass by [`Engine::scrub`],
+    /// cycling through every table over successive passes (0 disables)
+    pub scrub_bytes: u64,
+    /// Legacy SSTables given a current bloom filter and index per pass by
+    /// [`Engine::rebuild_legacy_sstables`] (0 disables)
+    pub max_metadata_rebuilds: usize,
+    /// Stats snapshots kept in the `stats` directory; each pass writes one and
+    /// deletes the oldest beyond this (0 disables; needs the `stats` feature)
+    pub stats_snapshots: usize,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            idle_after: Some(Duration::from_secs(5)),
+            retention: None,
+            max_compactions: 1,
+            scrub_bytes: 8 * 1024 * 1024,
+            max_metadata_rebuilds: 1,
+            stats_snapshots: 60,
+        }
+    }
+}
+
+/// Options for time-series ingestion mode
+///
+/// Keys must start with an 8-byte big-endian timestamp in milliseconds, as
+/// produced by [`crate::keys::encode_u64`].
+#[derive(Debug, Clone)]
+pub struct TimeSeriesOptions {
+    /// Width of the time partition covered by each flushed SSTable
+    pub partition_duration_ms: u64,
+}
+
+impl TimeSeriesOptions {
+    /// Partition number for a timestamp-prefixed key
+    pub(crate) fn partition_of(&self, key: &[u8]) -> u64 {
+        let timestamp = key
+            .get(..8)
+            .and_then(|prefix| keys::decode_u64(prefix).ok())
+            .unwrap_or(0);
+        timestamp / self.partition_duration_ms.max(1)
+    }
+}
+
+impl Default for TimeSeriesOptions {
+    fn default() -> Self {
+        Self {
+            partition_duration_ms: 60 * 60 * 1000, // 1 hour
+        }
+    }
 }
 
 impl Default for EngineConfig {
     fn default() -> Self {
         Self {
             data_dir: PathBuf::from("./data"),
+            temp_dir: None,
+            level_dirs: BTreeMap::new(),
             memtable_size: 64 * 1024 * 1024, // 64MB
+            memtable_max_entries: None,
+            batch_limits: BatchLimits::default(),
+            max_wal_bytes: None,
+            max_immutable_memtables: 0,
             compression: CompressionType::None,
+            compression_options: CompressionOptions::default(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
+            prefix_extractor: None,
             max_levels: 7,
+            compaction: CompactionOptions::default(),
+            compaction_strategy: CompactionStrategy::Leveled,
+            background_compaction: None,
+            compact_on_open: CompactOnOpen::Never,
+            compact_on_close: CompactOnClose::Never,
+            hot_keys: None,
+            maintenance: None,
+            use_direct_io: false,
+            value_log_threshold: None,
+            value_log_gc_ratio: 0.5,
+            value_log_auto_gc_bytes: Some(VALUE_LOG_FILE_SIZE),
+            time_series: None,
+            wal_sync_policy: SyncPolicy::OsBuffered,
+            env_overrides: true,
+            warm_up_hot_tables: 4,
+            recovery_progress: None,
+            recovery_threads: 4,
+            lazy_open_sstables: false,
+            corrupt_file_policy: CorruptFilePolicy::Skip,
+            startup_checks: StartupCheckPolicy::Warn,
+            secure_delete: false,
+            events: None,
+        }
+    }
+}
+
+/// Prefix of environment variables that override [`EngineConfig`] fields
+pub const ENV_PREFIX: &str = "RUSTEDGEDB_";
+
+/// File recording how many point reads each SSTable served, kept across restarts
+const ACCESS_PROFILE_FILE: &str = "ACCESS_PROFILE";
+
+/// Operations per batch when the engine streams bulk writes of its own, as
+/// [`Engine::rebuild_index`] and [`Engine::purge_range`] do
+const BULK_BATCH_ENTRIES: usize = 1024;
+
+/// Subdirectory that [`CorruptFilePolicy::Quarantine`] moves unreadable files into
+pub const CORRUPT_DIR: &str = "corrupt";
+
+/// Extension of SSTables still being written by a flush
+pub(crate) const TEMP_SSTABLE_EXTENSION: &str = "sst.tmp";
+
+/// Options that [`Engine::set_option`] can change while the engine is running
+pub const TUNABLE_OPTIONS: &[&str] = &[
+    "memtable_size",
+    "block_cache_size",
+    "compaction_rate_limit",
+    "compression",
+    "compression_min_value_size",
+    "value_log_threshold",
+    "value_log_gc_ratio",
+    "wal_sync_interval_ms",
+    "wal_sync_policy",
+];
+
+impl EngineConfig {
+    /// Override fields from `RUSTEDGEDB_*` environment variables
+    ///
+    /// Applied by [`Engine::with_config`] unless `env_overrides` is off, so
+    /// containers can tune an engine without templating config files.
+    pub fn apply_env_overrides(&mut self) -> EngineResult<()> {
+        self.apply_overrides(std::env::vars())
+    }
+
+    /// Override fields from `RUSTEDGEDB_*` name/value pairs
+    ///
+    /// Recognized names: `DATA_DIR`, `TEMP_DIR`, `LEVEL_DIRS` (comma-separated
+    /// `<level>=<dir>` pairs, `none` clears them), `MEMTABLE_SIZE`,
+    /// `MAX_BATCH_BYTES` and `MAX_BATCH_ENTRIES` (`none` removes the cap),
+    /// `SPLIT_OVERSIZED_BATCHES`, `COMPRESSION`
+    /// (`none`, `lz4`, `zstd`), `COMPRESSION_MIN_VALUE_SIZE`, `BLOCK_SIZE`,
+    /// `BLOCK_CACHE_SIZE`, `PREFIX_EXTRACTOR` (`fixed:<len>`, `delimiter:<byte>`,
+    /// `none`), `MAX_LEVELS`,
+    /// `COMPACTION_STRATEGY` (`leveled`, `size-tiered`),
+    /// `COMPACTION_RATE_LIMIT` (bytes per second for background compaction,
+    /// which it turns on; `none` unthrottles),
+    /// `COMPACT_ON_OPEN` (`never`, `full` or a compaction count),
+    /// `COMPACT_ON_CLOSE` (`never`, `level0`, `full`),
+    /// `USE_DIRECT_IO`, `VALUE_LOG_THRESHOLD` (`none` disables),
+    /// `VALUE_LOG_GC_RATIO`, `VALUE_LOG_AUTO_GC_BYTES` (`none` disables),
+    /// `TIME_SERIES_PARTITION_MS` (`none` disables),
+    /// `WAL_SYNC_INTERVAL_MS` (`none` leaves syncing to the OS) and
+    /// `WAL_SYNC_POLICY` (`always`, `every:<appends>`, `interval:<ms>`, `os`).
+    /// Other names are ignored.
+    pub fn apply_overrides<I, K, V>(&mut self, vars: I) -> EngineResult<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in vars {
+            let Some(field) = name.as_ref().strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let value = value.as_ref().trim();
+            match field {
+                "DATA_DIR" => self.data_dir = PathBuf::from(value),
+                "TEMP_DIR" => self.temp_dir = Some(PathBuf::from(value)),
+                "LEVEL_DIRS" => self.level_dirs = parse_level_dirs(field, value)?,
+                "MEMTABLE_SIZE" => self.memtable_size = parse_override(field, value)?,
+                "MEMTABLE_MAX_ENTRIES" => {
+                    self.memtable_max_entries = parse_optional_override(field, value)?
+                }
+                "MAX_WAL_BYTES" => self.max_wal_bytes = parse_optional_override(field, value)?,
+                "MAX_BATCH_BYTES" => {
+                    self.batch_limits.max_bytes = parse_optional_override(field, value)?
+                }
+                "MAX_BATCH_ENTRIES" => {
+                    self.batch_limits.max_entries = parse_optional_override(field, value)?
+                }
+                "SPLIT_OVERSIZED_BATCHES" => {
+                    self.batch_limits.split = parse_override(field, value)?
+                }
+                "MAX_IMMUTABLE_MEMTABLES" => {
+                    self.max_immutable_memtables = parse_override(field, value)?
+                }
+                "COMPRESSION" => {
+                    self.compression = match value.to_ascii_lowercase().as_str() {
+                        "none" => CompressionType::None,
+                        "lz4" => CompressionType::LZ4,
+                        "zstd" => CompressionType::Zstd,
+                        _ => return Err(invalid_override(field, value)),
+                    }
+                }
+                "COMPRESSION_MIN_VALUE_SIZE" => {
+                    self.compression_options.min_value_size = parse_override(field, value)?
+                }
+                "BLOCK_SIZE" => self.block_size = parse_override(field, value)?,
+                "BLOCK_CACHE_SIZE" => self.block_cache_size = parse_override(field, value)?,
+                "PREFIX_EXTRACTOR" => {
+                    self.prefix_extractor = parse_optional_override(field, value)?
+                }
+                "MAX_LEVELS" => self.max_levels = parse_override(field, value)?,
+                "COMPACTION_STRATEGY" => self.compaction_strategy = parse_override(field, value)?,
+                "COMPACTION_RATE_LIMIT" => {
+                    let rate_limit = parse_optional_override(field, value)?;
+                    if rate_limit.is_some() || self.background_compaction.is_some() {
+                        self.background_compaction
+                            .get_or_insert_default()
+                            .rate_limit = rate_limit;
+                    }
+                }
+                "COMPACT_ON_OPEN" => self.compact_on_open = parse_override(field, value)?,
+                "COMPACT_ON_CLOSE" => self.compact_on_close = parse_override(field, value)?,
+                "USE_DIRECT_IO" => self.use_direct_io = parse_override(field, value)?,
+                "WARM_UP_HOT_TABLES" => self.warm_up_hot_tables = parse_override(field, value)?,
+                "RECOVERY_THREADS" => self.recovery_threads = parse_override(field, value)?,
+                "LAZY_OPEN_SSTABLES" => self.lazy_open_sstables = parse_override(field, value)?,
+                "CORRUPT_FILE_POLICY" => self.corrupt_file_policy = parse_override(field, value)?,
+                "STARTUP_CHECKS" => self.startup_checks = parse_override(field, value)?,
+                "SECURE_DELETE" => self.secure_delete = parse_override(field, value)?,
+                "VALUE_LOG_THRESHOLD" => {
+                    self.value_log_threshold = parse_optional_override(field, value)?
+                }
+                "VALUE_LOG_GC_RATIO" => self.value_log_gc_ratio = parse_override(field, value)?,
+                "VALUE_LOG_AUTO_GC_BYTES" => {
+                    self.value_log_auto_gc_bytes = parse_optional_override(field, value)?
+                }
+                "TIME_SERIES_PARTITION_MS" => {
+                    self.time_series =
+                        parse_optional_override(field, value)?.map(|partition_duration_ms| {
+                            TimeSeriesOptions {
+                                partition_duration_ms,
+                            }
+                        })
+                }
+                "WAL_SYNC_INTERVAL_MS" => {
+                    self.wal_sync_policy = parse_optional_override(field, value)?
+                        .map_or(SyncPolicy::OsBuffered, |ms| {
+                            SyncPolicy::Interval(Duration::from_millis(ms))
+                        })
+                }
+                "WAL_SYNC_POLICY" => self.wal_sync_policy = parse_override(field, value)?,
+                _ => {
+                    warn!("Ignoring unknown config override {}{}", ENV_PREFIX, field);
+                    continue;
+                }
+            }
+            info!("Config override {}{}={}", ENV_PREFIX, field, value);
+        }
+        Ok(())
+    }
+}
+
+fn invalid_override(field: &str, value: &str) -> EngineError {
+    EngineError::InvalidConfig(format!(
+        "Invalid value {:?} for {}{}",
+        value, ENV_PREFIX, field
+    ))
+}
+
+/// WAL files in a data directory, oldest first
+pub(crate) fn list_wal_files(data_dir: &Path) -> Vec<PathBuf> {
+    let mut wal_files = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(data_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && (name == "wal.log" || (name.starts_with("wal_") && name.ends_with(".log")))
+            {
+                wal_files.push(path);
+            }
+        }
+    }
+
+    // Sort WAL files by timestamp (oldest first for recovery)
+    wal_files.sort_by(|a, b| {
+        let a_name = a.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        let b_name = b.file_name().and_then(|s| s.to_str()).unwrap_or("");
+
+        // Handle the initial wal.log file (treat as timestamp 0)
+        let a_time = if a_name == "wal.log" {
+            0
+        } else {
+            a_name
+                .strip_prefix("wal_")
+                .and_then(|s| s.strip_suffix(".log"))
+                .and_then(|s| s.parse::<u128>().ok())
+                .unwrap_or(0)
+        };
+
+        let b_time = if b_name == "wal.log" {
+            0
+        } else {
+            b_name
+                .strip_prefix("wal_")
+                .and_then(|s| s.strip_suffix(".log"))
+                .and_then(|s| s.parse::<u128>().ok())
+                .unwrap_or(0)
+        };
+
+        a_time.cmp(&b_time) // Oldest first for recovery
+    });
+
+    // Names only reflect when a file was created; the first sequence number
+    // decides, so a log that was reopened and appended to replays after the
+    // ones rotated out before it. Empty or unreadable logs keep their place
+    // by name, ahead of the others.
+    wal_files.sort_by_cached_key(|path| WAL::first_sequence_number(path).ok().flatten());
+    wal_files
+}
+
+/// Delete a file that held database contents, shredding it first if `secure`
+pub(crate) fn remove_data_file(path: &Path, secure: bool) -> io::Result<()> {
+    if secure {
+        io_backend::shred_file(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// Delete WAL files holding only writes from before `epoch`
+fn remove_stale_wal_files(data_dir: &Path, epoch: Epoch, secure: bool) -> EngineResult<()> {
+    if epoch.number == 0 {
+        return Ok(());
+    }
+    for path in list_wal_files(data_dir) {
+        if WAL::first_sequence_number(&path)?.is_some_and(|first| first <= epoch.sequence_floor) {
+            info!("Removing WAL {:?} from before epoch {}", path, epoch.number);
+            remove_data_file(&path, secure)?;
         }
     }
+    sync_dir(data_dir)?;
+    Ok(())
+}
+
+/// Hard-link `from` at `to`, or copy it when `link` is false or linking
+/// fails, as it does across filesystems; returns the bytes copied
+fn link_or_copy(from: &Path, to: &Path, link: bool, linked: &mut usize) -> io::Result<u64> {
+    if link && std::fs::hard_link(from, to).is_ok() {
+        *linked += 1;
+        return Ok(0);
+    }
+    copy_synced(from, to)
+}
+
+/// Copy `from` to `to` and fsync the copy
+fn copy_synced(from: &Path, to: &Path) -> io::Result<u64> {
+    let bytes = std::fs::copy(from, to)?;
+    std::fs::File::open(to)?.sync_all()?;
+    Ok(bytes)
+}
+
+/// Unpack an archive into `dir`, then check the manifest's tables all open
+fn unpack_archive(path: &Path, dir: &Path, stats: &mut ImportStats) -> EngineResult<()> {
+    let mut has_manifest = false;
+    ArchiveReader::open(path)?.for_each_entry(|name, contents| {
+        let target = dir.join(name);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::File::create(&target)?;
+        stats.bytes += io::copy(contents, &mut file)?;
+        file.sync_all()?;
+        stats.files += 1;
+        has_manifest |= name == MANIFEST_FILE;
+        Ok(())
+    })?;
+    if !has_manifest {
+        return Err(ArchiveError::Corrupt("no manifest".to_string()).into());
+    }
+
+    for name in Manifest::load(dir)?.names() {
+        SSTable::open(dir.join(name))?;
+    }
+    sync_dir(dir)?;
+    Ok(())
+}
+
+fn parse_override<T: std::str::FromStr>(field: &str, value: &str) -> EngineResult<T> {
+    value.parse().map_err(|_| invalid_override(field, value))
+}
+
+/// Parse an override where `none` (or an empty value) clears the setting
+fn parse_optional_override<T: std::str::FromStr>(
+    field: &str,
+    value: &str,
+) -> EngineResult<Option<T>> {
+    if value.is_empty() || value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    parse_override(field, value).map(Some)
+}
+
+/// Parse `<level>=<dir>` pairs separated by commas
+fn parse_level_dirs(field: &str, value: &str) -> EngineResult<BTreeMap<usize, PathBuf>> {
+    if value.is_empty() || value.eq_ignore_ascii_case("none") {
+        return Ok(BTreeMap::new());
+    }
+    value
+        .split(',')
+        .map(|pair| {
+            let (level, dir) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid_override(field, value))?;
+            let level = level
+                .trim()
+                .parse()
+                .map_err(|_| invalid_override(field, value))?;
+            Ok((level, PathBuf::from(dir.trim())))
+        })
+        .collect()
 }
 
 /// Main database engine that orchestrates WAL, MemTable, and SSTable operations
 pub struct Engine {
     /// Write-Ahead Log for durability
-    wal: WAL,
-    /// In-memory table for fast writes
-    memtable: MemTable,
+    pub(crate) wal: WAL,
+    /// In-memory table for fast writes, shared with the current version
+    pub(crate) memtable: Arc<MemTable>,
     /// Configuration options
-    config: EngineConfig,
-    /// List of SSTable files, ordered by level (newest first)
-    sstables: Arc<RwLock<Vec<SSTable>>>,
+    pub(crate) config: EngineConfig,
+    /// Current MemTable and SSTables, swapped atomically on flush
+    pub(crate) versions: VersionSet,
+    /// Out-of-line storage for the value log and values streamed through `put_writer`
+    pub(crate) blobs: Arc<BlobStore>,
+    /// Blocks read by point lookups, when `block_cache_size` is set
+    block_cache: Option<Arc<BlockCache>>,
+    /// Registered secondary indexes, maintained on every put and delete
+    indexes: Vec<SecondaryIndex>,
     /// Current sequence number across all operations
-    sequence_number: Arc<RwLock<u64>>,
+    pub(crate) sequence_number: Arc<RwLock<u64>>,
+    /// Background WAL fsync task, under [`SyncPolicy::Interval`]
+    wal_syncer: Option<WalSyncer>,
+    /// WAL appends since the last foreground fsync, under [`SyncPolicy::Always`]
+    /// and [`SyncPolicy::EveryN`]
+    unsynced_appends: AtomicU64,
+    /// Set while a write queue applies a group of writes that share one fsync
+    group_commit: bool,
+    /// WAL files no longer written to, oldest first, with the highest sequence
+    /// number each may hold; they are deleted once that sequence is flushed
+    pub(crate) retired_wals: Vec<(PathBuf, u64)>,
+    /// Highest sequence number known to be durable, not counting the
+    /// background fsyncs `wal_syncer` tracks itself
+    durable_sequence: AtomicU64,
+    /// Access frequency sketch, when `hot_keys` is set
+    hot_keys: Option<HotKeyTracker>,
+    /// SSTables moved aside by [`CorruptFilePolicy::Quarantine`] at startup,
+    /// while scrubbing or when a read found them unreadable
+    quarantined: Mutex<Vec<PathBuf>>,
+    /// SSTables point reads found unreadable, so each is reported and has the
+    /// corrupt file policy applied once however many reads hit it
+    unreadable: Mutex<HashSet<PathBuf>>,
+    /// Deadlines set by [`Engine::expire_at`], loaded from their records on open
+    pub(crate) expiries: Expiries,
+    /// Epoch recorded in the manifest, bumped by [`Engine::truncate`]
+    pub(crate) epoch: Epoch,
+    /// Edit log of the SSTable set, started by the first version installed
+    manifest: Mutex<Option<ManifestLog>>,
+    /// Set while open files are near the OS limit, so the warning is logged once
+    open_files_warned: AtomicBool,
+    /// File name of the SSTable [`Engine::scrub`] checked last
+    scrub_cursor: Option<std::ffi::OsString>,
+    /// Where flushes, compactions, stalls and corruption are announced
+    pub(crate) events: EventBus,
+    /// Recent flushes and compactions, persisted for after-the-fact diagnosis
+    history: JobHistory,
+    /// Compactions running off the write path, when `background_compaction` is set
+    pub(crate) compactor: Option<BackgroundCompactor>,
+    /// Bytes of values that compactions dropped the last pointers to since the
+    /// value log was last collected
+    pub(crate) value_log_garbage: u64,
+    /// Flushes of immutable MemTables, when `max_immutable_memtables` is set
+    pub(crate) flusher: Option<BackgroundFlusher>,
 }
 
 impl Engine {
@@ -76,533 +850,6480 @@ impl Engine {
     }
 
     /// Create a new Engine instance with custom configuration
-    pub async fn with_config(config: EngineConfig) -> EngineResult<Self> {
+    pub async fn with_config(mut config: EngineConfig) -> EngineResult<Self> {
+        if config.env_overrides {
+            config.apply_env_overrides()?;
+        }
+        if config.max_levels == 0 {
+            return Err(EngineError::InvalidConfig(
+                "max_levels must be at least 1".to_string(),
+            ));
+        }
+
         // Ensure data directory exists
         std::fs::create_dir_all(&config.data_dir).map_err(EngineError::Io)?;
+        for dir in config.temp_dir.iter().chain(config.level_dirs.values()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Migrate files written by older versions before reading any of them
+        layout::upgrade(&config.data_dir)?;
+
+        // A truncate may have crashed before deleting the old WAL files
+        let epoch = Manifest::load(&config.data_dir)?.epoch();
+        remove_stale_wal_files(&config.data_dir, epoch, config.secure_delete)?;
 
         // Initialize WAL
         let wal_path = config.data_dir.join("wal.log");
         let wal = WAL::new(wal_path)?;
 
         // Initialize MemTable
-        let memtable = MemTable::new(config.memtable_size);
+        let memtable = Arc::new(MemTable::new(config.memtable_size));
 
-        // Initialize SSTable list
-        let sstables = Arc::new(RwLock::new(Vec::new()));
+        // The first version holds no SSTables until they are loaded
+        let versions = VersionSet::new(Version::new(memtable.clone(), config.max_levels));
 
         // Initialize sequence number
         let sequence_number = Arc::new(RwLock::new(0));
 
+        // Initialize blob store
+        let blobs = Arc::new(BlobStore::open(config.data_dir.join("blobs"))?);
+
+        let block_cache = (config.block_cache_size > 0)
+            .then(|| Arc::new(BlockCache::new(config.block_cache_size)));
+        let events = config.events.clone().unwrap_or_default();
+        let history = JobHistory::open(&config.data_dir);
+        let compactor = config.background_compaction.map(BackgroundCompactor::new);
+        let flusher = if config.max_immutable_memtables > 0 {
+            Some(BackgroundFlusher::start()?)
+        } else {
+            None
+        };
         let mut engine = Self {
             wal,
             memtable,
             config,
-            sstables,
+            versions,
+            blobs,
+            block_cache,
+            indexes: Vec::new(),
             sequence_number,
+            wal_syncer: None,
+            unsynced_appends: AtomicU64::new(0),
+            group_commit: false,
+            retired_wals: Vec::new(),
+            durable_sequence: AtomicU64::new(0),
+            hot_keys: None,
+            quarantined: Mutex::new(Vec::new()),
+            unreadable: Mutex::new(HashSet::new()),
+            open_files_warned: AtomicBool::new(false),
+            scrub_cursor: None,
+            events,
+            history,
+            expiries: Expiries::default(),
+            epoch,
+            manifest: Mutex::new(None),
+            compactor,
+            value_log_garbage: 0,
+            flusher,
         };
+        engine.hot_keys = engine.config.hot_keys.clone().map(HotKeyTracker::new);
 
-        // Attempt recovery from existing WAL
-        engine.recover_from_wal()?;
-
-        // Load existing SSTables from the data directory
+        // Load existing SSTables from the data directory, then replay the WAL
+        // over them; replay skips merge operands the tables already hold
         engine.load_existing_sstables()?;
+        let progress = engine.recover_from_wal()?;
+        engine.check_startup_invariants(&progress)?;
+        // New writes go to a fresh log, so replay never puts the recovered
+        // ones after them
+        if !engine.retired_wals.is_empty() || engine.wal.sequence_number() > 0 {
+            engine.rotate_wal()?;
+        }
+        engine.load_expiries()?;
+        engine.load_access_profile();
+
+        // Replay may have left more than a MemTable's worth of data in memory
+        if engine.memtable.is_full() {
+            engine.flush_memtable(FlushReason::Recovery).await?;
+        }
+
+        match engine.config.compact_on_open {
+            CompactOnOpen::Never => {}
+            CompactOnOpen::Full => {
+                engine.compact_all().await?;
+            }
+            CompactOnOpen::Capped(max_compactions) => {
+                for _ in 0..max_compactions {
+                    if engine.compact().await?.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Everything recovered counts as durable once the WAL is synced
+        match engine.config.wal_sync_policy {
+            SyncPolicy::Interval(interval) => {
+                let sequence = engine.sequence_number();
+                engine.wal_syncer = Some(WalSyncer::start(&engine.wal, interval, sequence)?);
+            }
+            _ => engine.sync_wal()?,
+        }
 
         info!("Engine initialized successfully");
         Ok(engine)
     }
 
     /// Put a key-value pair into the database
-    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> EngineResult<()> {
+    ///
+    /// Returns the sequence number assigned to the write, which can be passed
+    /// to [`Engine::wait_durable`]. Writes that also update secondary indexes
+    /// return the last sequence number they used.
+    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> EngineResult<u64> {
+        self.put_at(key, value, None).await
+    }
+
+    /// Put a key-value pair, recording `timestamp` instead of the local clock
+    ///
+    /// Lets appliers of external logs and sync protocols keep original event
+    /// times. The timestamp is stored as metadata only; ordering between writes
+    /// still follows the engine's sequence numbers.
+    pub async fn put_with_timestamp(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        timestamp: u64,
+    ) -> EngineResult<u64> {
+        self.put_at(key, value, Some(timestamp)).await
+    }
+
+    /// Put a key-value pair that reads as missing once `ttl` has passed
+    ///
+    /// The value and an expiry record with its deadline, in whole seconds
+    /// rounded up, are logged as one batch. Expired values are dropped by
+    /// compaction; a later put or delete without a TTL clears the deadline.
+    /// See [`Engine::expire_at`] to set the deadline of an existing key.
+    pub async fn put_with_ttl(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> EngineResult<u64> {
+        let mut batch = WriteBatch::new();
+        batch.put_with_ttl(key, value, ttl);
+        self.write_batch(batch).await
+    }
+
+    /// Put with an optional caller-supplied timestamp
+    async fn put_at(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        timestamp: Option<u64>,
+    ) -> EngineResult<u64> {
         if key.is_empty() {
             return Err(EngineError::InvalidConfig(
                 "Key cannot be empty".to_string(),
             ));
         }
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        if self.config.time_series.is_some() && key.len() < 8 {
+            return Err(EngineError::InvalidConfig(
+                "Time-series keys must start with an 8-byte timestamp".to_string(),
+            ));
+        }
 
-        // Write to WAL first (Write-Ahead Logging) - WAL manages sequence numbers
-        self.wal.put(key, value, timestamp)?;
+        // Index entries are logged in one batch with the primary write, so a
+        // crash can't leave them disagreeing
+        if !self.indexes.is_empty() && !index::is_internal_key(key) {
+            let mut batch = WriteBatch::new();
+            batch.put(key, value);
+            return self.commit_batch(batch, timestamp).await;
+        }
+
+        self.record_access(key);
+        self.write_value(key, value, timestamp).await
+    }
 
-        // Then write to MemTable
-        self.memtable.put(key, value)?;
+    /// Write a value without touching secondary indexes
+    async fn write_value(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        timestamp: Option<u64>,
+    ) -> EngineResult<u64> {
+        self.clear_expiry(key).await?;
 
-        // Check if MemTable needs to be flushed
-        if self.memtable.is_full() {
-            self.flush_memtable().await?;
+        // Large values go to the value log so flushes and compactions only move pointers
+        if let Some(threshold) = self.config.value_log_threshold
+            && value.len() >= threshold
+        {
+            let pointer = self.blobs.append(value)?;
+            return self.put_indirect(key, pointer, timestamp).await;
         }
 
-        trace!(
-            "Put operation completed: key={:?}, seq={}",
-            key, self.wal.sequence_number()
-        );
-        Ok(())
+        let entry = self.next_entry(key, Some(Bytes::copy_from_slice(value)), timestamp);
+        self.append_entry(entry).await
     }
 
-    /// Get a value by key from the database
-    pub async fn get(&self, key: &[u8]) -> EngineResult<Option<Vec<u8>>> {
+    /// Start a streaming put for a value too large to buffer in memory
+    ///
+    /// Bytes written to the returned [`PutWriter`] go straight to a blob file; the
+    /// key only becomes visible once [`PutWriter::finish`] logs a pointer to it in
+    /// the WAL. Dropping the writer without finishing discards the partial blob.
+    pub async fn put_writer(&mut self, key: &[u8]) -> EngineResult<PutWriter<'_>> {
         if key.is_empty() {
             return Err(EngineError::InvalidConfig(
                 "Key cannot be empty".to_string(),
             ));
         }
 
-        // First, check MemTable (most recent data)
-        if let Some(value) = self.memtable.get(key)? {
-            return Ok(Some(value));
-        }
+        let blob = self.blobs.create()?;
+        Ok(PutWriter {
+            engine: self,
+            key: key.to_vec(),
+            blob,
+        })
+    }
 
-        // Then check SSTables in order (newest first)
-        let mut sstables = self.sstables.write().unwrap();
-        for sstable in sstables.iter_mut() {
-            if let Ok(Some(value)) = sstable.get(key) {
-                return Ok(Some(value));
-            }
-        }
+    /// Record a key whose value was written to the blob store
+    async fn put_indirect(
+        &mut self,
+        key: &[u8],
+        pointer: BlobPointer,
+        timestamp: Option<u64>,
+    ) -> EngineResult<u64> {
+        self.clear_expiry(key).await?;
 
-        // Key not found
-        Ok(None)
+        // The blob is already synced, so the WAL record never points at missing data
+        let mut entry = self.next_entry(key, Some(pointer.encode()), timestamp);
+        entry.indirect = true;
+        self.append_entry(entry).await
     }
 
-    /// Delete a key from the database
-    pub async fn delete(&mut self, key: &[u8]) -> EngineResult<()> {
-        if key.is_empty() {
-            return Err(EngineError::InvalidConfig(
-                "Key cannot be empty".to_string(),
-            ));
-        }
+    /// Build an entry stamped with the next sequence number and the given
+    /// timestamp, defaulting to the current time
+    fn next_entry(&self, key: &[u8], value: Option<Bytes>, timestamp: Option<u64>) -> Entry {
+        let timestamp = timestamp.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        });
+        let sequence_number = {
+            let mut seq = self.sequence_number.write().unwrap();
+            *seq += 1;
+            *seq
+        };
+        Entry::new(key.to_vec(), value, timestamp, sequence_number)
+    }
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+    /// Log an entry to the WAL, then apply it to the MemTable, returning its
+    /// sequence number
+    async fn append_entry(&mut self, entry: Entry) -> EngineResult<u64> {
+        let value_len = entry.value.as_ref().map_or(0, |v| v.len());
+        self.make_room(entry.key.len(), value_len).await?;
 
-        // Write deletion to WAL first
-        self.wal.delete(key, timestamp)?;
+        // Write to WAL first (Write-Ahead Logging)
+        self.wal.write_record(&WALRecord::from_entry(&entry))?;
+        self.note_wal_write(entry.sequence_number)?;
 
-        // Then mark as deleted in MemTable
-        self.memtable.delete(key)?;
+        let sequence_number = entry.sequence_number;
+        self.memtable.apply(entry)?;
 
         // Check if MemTable needs to be flushed
-        if self.memtable.is_full() {
-            self.flush_memtable().await?;
+        if self.needs_flush() {
+            self.flush_stalled().await?;
         }
 
-        trace!(
-            "Delete operation completed: key={:?}, seq={}",
-            key, self.wal.sequence_number()
-        );
-        Ok(())
+        trace!("Write completed: seq={}", sequence_number);
+        Ok(sequence_number)
     }
 
-    /// Flush the current MemTable to an SSTable
-    async fn flush_memtable(&mut self) -> EngineResult<()> {
-        info!("Flushing MemTable to SSTable");
+    /// Whether a flush trigger has fired: the MemTable is full or holds
+    /// `memtable_max_entries`, or the active WAL has outgrown `max_wal_bytes`
+    fn needs_flush(&self) -> bool {
+        self.memtable.is_full()
+            || self
+                .config
+                .memtable_max_entries
+                .is_some_and(|max| self.memtable.len() >= max)
+            || self
+                .config
+                .max_wal_bytes
+                .is_some_and(|max| self.wal.logged_bytes() > max)
+    }
 
-        // Create SSTable filename with timestamp
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    /// Sequence number of the most recent write
+    ///
+    /// Every WAL record and MemTable entry takes its sequence number from this
+    /// one counter, which is restored from the WAL on open.
+    pub fn sequence_number(&self) -> u64 {
+        *self.sequence_number.read().unwrap()
+    }
 
-        let sstable_path = self
-            .config
-            .data_dir
-            .join(format!("sstable_{}.sst", timestamp));
+    /// Receive engine events published from now on
+    ///
+    /// Flushes, compactions, write stalls and corrupt SSTables are announced
+    /// here; see [`EventBus`] for what happens to slow subscribers.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
 
-        // Debug: Check MemTable contents before flush
-        let entries = self.memtable.entries();
-        println!("DEBUG: MemTable has {} entries before flush", entries.len());
-        if !entries.is_empty() {
-            println!("DEBUG: First entry: key={:?}, value_len={}", 
-                  String::from_utf8_lossy(&entries[0].key),
-                  entries[0].value.as_ref().map_or(0, |v| v.len()));
+    /// Get a value by key from the database
+    ///
+    /// Values are returned as reference-counted `Bytes` slices of the buffer they
+    /// were read into, so no extra copy is made on the read path. A table whose
+    /// checksum doesn't match fails the read with [`SSTableError::CorruptedFile`]
+    /// rather than let an older version of the key show through.
+    pub async fn get(&self, key: &[u8]) -> EngineResult<Option<Bytes>> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
         }
 
-        // Flush MemTable to SSTable
-        let sstable =
-            SSTable::from_memtable(&sstable_path, &self.memtable, self.config.compression)?;
-
-        println!("DEBUG: SSTable created at {:?} with {} entries", sstable_path, sstable.entry_count());
+        self.record_access(key);
+        self.read_value(key)
+    }
 
-        // Add to SSTable list
-        {
-            let mut sstables = self.sstables.write().unwrap();
-            sstables.insert(0, sstable); // Insert at beginning (newest first)
+    /// Current value of a key, without counting it as a user access
+    fn read_value(&self, key: &[u8]) -> EngineResult<Option<Bytes>> {
+        if self.expiries.is_expired(key, expiry::now_secs()) {
+            return Ok(None);
+        }
+        match self.find_entry(key)? {
+            Some(entry) => self.resolve_value(entry),
+            None => Ok(None),
         }
-
-        // Create new MemTable
-        self.memtable = MemTable::new(self.config.memtable_size);
-
-        // Rotate WAL file
-        self.rotate_wal()?;
-
-        info!("MemTable flushed successfully to {:?}", sstable_path);
-        Ok(())
     }
 
-    /// Rotate the WAL file after MemTable flush
-    fn rotate_wal(&mut self) -> EngineResult<()> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-
-        let new_wal_path = self.config.data_dir.join(format!("wal_{}.log", timestamp));
+    /// Get a value together with the timestamp and sequence number it was written with
+    ///
+    /// Lets callers build their own conflict resolution or cache validation
+    /// on the versioning data the engine already stores.
+    pub async fn get_with_metadata(&self, key: &[u8]) -> EngineResult<Option<VersionedValue>> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
 
-        // Create new WAL
-        let new_wal = WAL::new(&new_wal_path)?;
+        self.record_access(key);
+        if self.expiries.is_expired(key, expiry::now_secs()) {
+            return Ok(None);
+        }
+        let Some(entry) = self.find_entry(key)? else {
+            return Ok(None);
+        };
+        let (timestamp, sequence_number) = (entry.timestamp, entry.sequence_number);
+        Ok(self.resolve_value(entry)?.map(|value| VersionedValue {
+            value,
+            timestamp,
+            sequence_number,
+        }))
+    }
 
-        // Replace old WAL
-        self.wal = new_wal;
+    /// Read at most `len` bytes of a key's value, starting at `offset`
+    ///
+    /// Only the requested range is read from the SSTable data section or the
+    /// value log, so slicing a large blob doesn't load all of it. The range is
+    /// clamped to the value, which may leave it empty. Compressed values and
+    /// pending merge operands still need the whole value to be read.
+    pub async fn get_range_of_value(
+        &self,
+        key: &[u8],
+        offset: u64,
+        len: u64,
+    ) -> EngineResult<Option<Bytes>> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
 
-        info!("WAL rotated to {:?}", new_wal_path);
-        Ok(())
-    }
+        self.record_access(key);
+        if self.expiries.is_expired(key, expiry::now_secs()) {
+            return Ok(None);
+        }
 
-    /// Recover from existing WAL files
-    fn recover_from_wal(&mut self) -> EngineResult<()> {
-        info!("Attempting WAL recovery...");
-        
-        // Find all WAL files and sort them chronologically
-        let mut wal_files = Vec::new();
-        if let Ok(entries) = std::fs::read_dir(&self.config.data_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if let Some(file_name) = path.file_name() {
-                        if let Some(name) = file_name.to_str() {
-                            if name == "wal.log" || (name.starts_with("wal_") && name.ends_with(".log")) {
-                                wal_files.push(path);
-                            }
-                        }
-                    }
+        let version = self.versions.current();
+        for memtable in version.memtables() {
+            if let Some(entry) = memtable.get_entry(key)? {
+                return self.slice_entry(entry, offset, len);
+            }
+        }
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let count = usize::try_from(len).unwrap_or(usize::MAX);
+        for sstable in version.sstables_for_key(key) {
+            let entry = sstable
+                .get_entry_range(key, start, count)
+                .map_err(|e| self.handle_unreadable_sstable(sstable, e))?;
+            if let Some(entry) = entry {
+                if entry.merge || entry.indirect {
+                    return self.slice_entry(entry, offset, len);
                 }
+                // Plain values were read as just the range
+                return Ok(entry.value);
             }
         }
-        
-        // Sort WAL files by timestamp (oldest first for recovery)
-        wal_files.sort_by(|a, b| {
-            let a_name = a.file_name().and_then(|s| s.to_str()).unwrap_or("");
-            let b_name = b.file_name().and_then(|s| s.to_str()).unwrap_or("");
-            
-            // Handle the initial wal.log file (treat as timestamp 0)
-            let a_time = if a_name == "wal.log" {
-                0
-            } else {
-                a_name.strip_prefix("wal_")
-                    .and_then(|s| s.strip_suffix(".log"))
-                    .and_then(|s| s.parse::<u128>().ok())
-                    .unwrap_or(0)
-            };
-            
-            let b_time = if b_name == "wal.log" {
-                0
-            } else {
-                b_name.strip_prefix("wal_")
-                    .and_then(|s| s.strip_suffix(".log"))
-                    .and_then(|s| s.parse::<u128>().ok())
-                    .unwrap_or(0)
-            };
-            
-            a_time.cmp(&b_time) // Oldest first for recovery
-        });
-        
-        // Recover from each WAL file in order
-        for wal_path in &wal_files {
-            info!("Recovering from WAL: {:?}", wal_path);
-            let wal = WAL::new(wal_path)?;
-            wal.recover(&self.memtable)?;
-        }
-        
-        // Also recover from the current WAL if it exists
-        if !wal_files.is_empty() {
-            // Sync Engine sequence number with the last WAL's sequence number
-            if let Ok(last_wal) = WAL::new(&wal_files.last().unwrap()) {
-                let mut seq = self.sequence_number.write().unwrap();
-                *seq = last_wal.sequence_number();
-            }
-        }
-        
-        info!("WAL recovery completed from {} files", wal_files.len());
-        Ok(())
+        Ok(None)
     }
 
-    /// Load existing SSTables from the data directory
-    fn load_existing_sstables(&mut self) -> EngineResult<()> {
-        info!("Loading existing SSTables from {:?}", self.config.data_dir);
-        
-        let mut sstable_files = Vec::new();
-        
-        // Scan directory for SSTable files
-        if let Ok(entries) = std::fs::read_dir(&self.config.data_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if let Some(extension) = path.extension() {
-                        if extension == "sst" {
-                            sstable_files.push(path);
-                        }
-                    }
-                }
+    /// Load part of an entry's value, following blob pointers and operands
+    fn slice_entry(&self, entry: Entry, offset: u64, len: u64) -> EngineResult<Option<Bytes>> {
+        if entry.merge {
+            // Operands fold into older versions, so the whole value is needed
+            let value = self.read_value(&entry.key)?;
+            return Ok(value.map(|value| slice_value(&value, offset, len)));
+        }
+        match entry.value {
+            Some(pointer) if entry.indirect => {
+                let pointer = BlobPointer::decode(&pointer)?;
+                Ok(Some(self.blobs.read(&pointer.slice(offset, len))?))
             }
+            value => Ok(value.map(|value| slice_value(&value, offset, len))),
         }
-        
-        // Sort by timestamp (newest first)
-        sstable_files.sort_by(|a, b| {
-            let a_time = a.file_stem()
-                .and_then(|s| s.to_str())
-                .and_then(|s| s.strip_prefix("sstable_"))
-                .and_then(|s| s.parse::<u128>().ok())
-                .unwrap_or(0);
-            let b_time = b.file_stem()
-                .and_then(|s| s.to_str())
-                .and_then(|s| s.strip_prefix("sstable_"))
-                .and_then(|s| s.parse::<u128>().ok())
-                .unwrap_or(0);
-            b_time.cmp(&a_time) // Newest first
-        });
-        
-        // Load each SSTable
-        for sstable_path in sstable_files {
-            match SSTable::open(&sstable_path) {
-                Ok(sstable) => {
-                    info!("Loaded SSTable: {:?}", sstable_path);
-                    let mut sstables = self.sstables.write().unwrap();
-                    sstables.push(sstable);
+    }
+
+    /// Count an access to a user key for hot-key tracking
+    fn record_access(&self, key: &[u8]) {
+        if let Some(tracker) = &self.hot_keys
+            && !index::is_internal_key(key)
+        {
+            tracker.record(key);
+        }
+    }
+
+    /// Newest entry for a key, including tombstones
+    fn find_entry(&self, key: &[u8]) -> EngineResult<Option<Entry>> {
+        find_entry_in(&self.versions.current(), &self.blobs, key, |sstable, e| {
+            self.handle_unreadable_sstable(sstable, e)
+        })
+    }
+
+    /// Scan live key-value pairs whose keys fall in `range`, in key order
+    ///
+    /// Keys in the engine's reserved internal keyspace are skipped.
+    pub async fn scan<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        let start = range.start_bound().map(|k| k.as_ref());
+        let end = range.end_bound().map(|k| k.as_ref());
+        let mut results = self.scan_range(start, end)?;
+        results.retain(|(key, _)| !index::is_internal_key(key));
+        Ok(results)
+    }
+
+    /// Lazily iterate over live key-value pairs in `range`, in key order
+    ///
+    /// The iterator sees the database as of this call: it keeps the SSTables
+    /// that were live alive and copies the MemTable entries in range, so later
+    /// writes, flushes and compactions don't affect what it yields.
+    pub fn iter<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> EngineIterator {
+        let start = range.start_bound().map(|k| k.as_ref());
+        let end = range.end_bound().map(|k| k.as_ref());
+
+        let version = self.versions.current();
+        let memtables = version
+            .memtables()
+            .map(|memtable| memtable.range::<&[u8], _>((start, end)).collect())
+            .collect();
+        let sstables = version
+            .sstables()
+            .filter(|sstable| self.scan_reads(sstable, start, end))
+            .cloned()
+            .collect();
+        EngineIterator::new(
+            memtables,
+            sstables,
+            start,
+            end,
+            self.blobs.clone(),
+            self.expiries.clone(),
+            expiry::now_secs(),
+        )
+    }
+
+    /// Whether a scan between `start` and `end` has to read `sstable`
+    ///
+    /// Besides tables outside the range, a scan within one prefix of
+    /// [`EngineConfig::prefix_extractor`] skips tables whose bloom filter
+    /// rules the prefix out.
+    fn scan_reads(&self, sstable: &SSTable, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        if !sstable_overlaps(sstable, start, end) {
+            return false;
+        }
+        let Some(extractor) = &self.config.prefix_extractor else {
+            return true;
+        };
+        let Some(prefix) = extractor.range_prefix(start, end) else {
+            return true;
+        };
+        // A table that can't be read fails the scan when it is read
+        sstable
+            .might_contain_prefix(extractor, prefix)
+            .unwrap_or(true)
+    }
+
+    /// Iterate over every stored version of the keys in `range`, for debugging
+    ///
+    /// Each [`crate::iterator::RawEntry`] carries its sequence number, timestamp and the file
+    /// it came from, so a key that reappeared or vanished can be traced to the
+    /// versions and tombstones behind it. See [`RawIterator`].
+    pub fn iter_raw<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> RawIterator {
+        let start = range.start_bound().map(|k| k.as_ref());
+        let end = range.end_bound().map(|k| k.as_ref());
+
+        let version = self.versions.current();
+        let memtables = version
+            .memtables()
+            .map(|memtable| memtable.range::<&[u8], _>((start, end)).collect())
+            .collect();
+        let sstables = version
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, tables)| tables.iter().map(move |t| (t.clone(), level)))
+            .filter(|(sstable, _)| sstable_overlaps(sstable, start, end))
+            .collect();
+        RawIterator::new(memtables, sstables, start, end)
+    }
+
+    /// Scan up to `limit` live key-value pairs in `range`, resuming after `token`
+    ///
+    /// The returned page carries a continuation token when more keys may follow;
+    /// passing it back with the same range picks up right after the last key
+    /// returned, so frontends can paginate without keeping an iterator open.
+    /// Each page reads the database as of its own call: writes made between
+    /// pages show up on later pages when their keys sort after the cursor, and
+    /// keys deleted meanwhile are left out. The token holds only a key, never
+    /// a snapshot.
+    pub async fn scan_page<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+        limit: usize,
+        token: Option<&str>,
+    ) -> EngineResult<ScanPage> {
+        if limit == 0 {
+            return Err(EngineError::InvalidConfig(
+                "Page limit must be greater than zero".to_string(),
+            ));
+        }
+
+        let token = token.map(ScanToken::decode).transpose()?;
+
+        // Resume strictly after the last key of the previous page
+        let mut start = range.start_bound().map(|k| k.as_ref());
+        if let Some(token) = &token
+            && key_in_range(start, Bound::Unbounded, &token.last_key)
+        {
+            start = Bound::Excluded(token.last_key.as_slice());
+        }
+        let end = range.end_bound().map(|k| k.as_ref());
+
+        // Stream the merge and stop one live key past the page, so a page
+        // costs its own length rather than the rest of the range
+        let mut entries = Vec::with_capacity(limit);
+        let mut more = false;
+        for pair in self.iter::<&[u8], _>((start, end)) {
+            let pair = pair?;
+            if entries.len() == limit {
+                more = true;
+                break;
+            }
+            entries.push(pair);
+        }
+
+        let next_token = match entries.last() {
+            Some((last_key, _)) if more => Some(
+                ScanToken {
+                    last_key: last_key.clone(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+        Ok(ScanPage {
+            entries,
+            next_token,
+        })
+    }
+
+    /// Scan up to `limit` live keys starting with `prefix`, resuming after `token`
+    ///
+    /// Pages like [`Engine::scan_page`] over the range of keys with `prefix`;
+    /// an empty prefix lists every key. When the prefix is one
+    /// [`EngineConfig::prefix_extractor`] takes, SSTables without it are skipped.
+    pub async fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        limit: usize,
+        token: Option<&str>,
+    ) -> EngineResult<ScanPage> {
+        match keys::prefix_end(prefix) {
+            Some(end) => self.scan_page(prefix..end.as_slice(), limit, token).await,
+            None => self.scan_page(prefix.., limit, token).await,
+        }
+    }
+
+    /// Merge the MemTable and SSTables over a key range and resolve live values
+    ///
+    /// Keys past their expiry are left out.
+    fn scan_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        let newest = self.merge_range(start, end)?;
+        let mut results = Vec::with_capacity(newest.len());
+        let now = expiry::now_secs();
+        for (key, entry) in newest {
+            if self.expiries.is_expired(&key, now) {
+                continue;
+            }
+            if let Some(value) = self.resolve_value(entry)? {
+                results.push((key, value));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Newest version of every key in a range, tombstones included
+    fn merge_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> EngineResult<BTreeMap<Vec<u8>, Entry>> {
+        let mut newest: BTreeMap<Vec<u8>, Entry> = BTreeMap::new();
+        let version = self.versions.current();
+
+        let mut absorb = |entry: Entry| -> EngineResult<()> {
+            match newest.entry(entry.key.clone()) {
+                btree_map::Entry::Vacant(slot) => {
+                    slot.insert(entry);
+                }
+                btree_map::Entry::Occupied(mut slot) if slot.get().merge => {
+                    let newer = slot.get().clone();
+                    slot.insert(merge::fold(entry, newer, |base| self.resolve_value(base))?);
                 }
+                btree_map::Entry::Occupied(_) => {}
+            }
+            Ok(())
+        };
+
+        for memtable in version.memtables() {
+            for entry in memtable.range::<&[u8], _>((start, end)) {
+                absorb(entry)?;
+            }
+        }
+
+        for sstable in version.sstables() {
+            if !self.scan_reads(sstable, start, end) {
+                continue;
+            }
+
+            let mut iter = sstable.iter();
+            match start {
+                Bound::Included(key) | Bound::Excluded(key) => iter.seek(key),
+                Bound::Unbounded => {}
+            }
+            for entry in iter {
+                let entry = entry?;
+                if !key_in_range(start, end, &entry.key) {
+                    match start {
+                        Bound::Excluded(key) if entry.key == key => continue,
+                        _ => break,
+                    }
+                }
+                absorb(entry)?;
+            }
+        }
+
+        // Operands left over have nothing older to apply to
+        for entry in newest.values_mut() {
+            if entry.merge {
+                *entry = merge::resolve(entry.clone())?;
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Scan entries whose timestamp prefix falls in `[start_ms, end_ms)`
+    ///
+    /// SSTables outside the window are skipped without being read, which in
+    /// time-series mode leaves only the partitions covering the window.
+    pub async fn scan_time_window(
+        &self,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        self.scan(keys::encode_u64(start_ms)..keys::encode_u64(end_ms))
+            .await
+    }
+
+    /// Drop all entries whose timestamp prefix is older than `cutoff_ms`
+    ///
+    /// SSTables that lie entirely before the cutoff are deleted outright;
+    /// remaining older keys in the MemTable or straddling tables get tombstones.
+    pub async fn drop_before(&mut self, cutoff_ms: u64) -> EngineResult<RetentionStats> {
+        let cutoff = keys::encode_u64(cutoff_ms);
+        let mut stats = RetentionStats::default();
+
+        let is_expired =
+            |sstable: &Arc<SSTable>| sstable.last_key().is_some_and(|k| k < cutoff.as_slice());
+        let version = self.versions.current();
+        let expired: Vec<Arc<SSTable>> = version
+            .sstables()
+            .filter(|sstable| is_expired(sstable))
+            .cloned()
+            .collect();
+        self.install_version(version.retain_sstables(|sstable| !is_expired(sstable)))?;
+        // Readers still holding a table keep it until they finish
+        for sstable in expired {
+            self.retire_sstable(&sstable)?;
+            stats.sstables_dropped += 1;
+        }
+
+        // Dropping files can't resurrect anything: leftovers are tombstoned here
+        for (key, _) in self.scan(..cutoff).await? {
+            self.delete(&key).await?;
+            stats.keys_deleted += 1;
+        }
+
+        info!(
+            "Retention dropped {} SSTables and deleted {} keys before {}",
+            stats.sstables_dropped, stats.keys_deleted, cutoff_ms
+        );
+        Ok(stats)
+    }
+
+    /// Run one pass of the maintenance job
+    ///
+    /// Applies the `maintenance` retention window in time-series mode, runs
+    /// up to `max_compactions` picked compactions, rebuilds the metadata of up
+    /// to `max_metadata_rebuilds` legacy SSTables, then scrubs up to
+    /// `scrub_bytes` of SSTables. Does nothing when maintenance isn't configured.
+    pub async fn run_maintenance(&mut self) -> EngineResult<MaintenanceStats> {
+        let mut stats = MaintenanceStats::default();
+        let Some(options) = self.config.maintenance.clone() else {
+            return Ok(stats);
+        };
+        self.install_background_flushes(false)?;
+        self.install_background_compactions(false)?;
+
+        if let (Some(retention), Some(_)) = (options.retention, &self.config.time_series) {
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            let cutoff_ms = now_ms.saturating_sub(retention.as_millis() as u64);
+            stats.retention = Some(self.drop_before(cutoff_ms).await?);
+        }
+
+        for _ in 0..options.max_compactions {
+            if self.compact().await?.is_none() {
+                break;
+            }
+            stats.compactions += 1;
+        }
+        // Compactions run after flushes leave their garbage for this pass
+        self.install_background_compactions(false)?;
+        self.collect_value_log_garbage_if_due().await?;
+
+        stats.metadata_rebuilds = self.rebuild_legacy_sstables(options.max_metadata_rebuilds)?;
+        if options.scrub_bytes > 0 {
+            stats.scrub = Some(self.scrub(options.scrub_bytes)?);
+        }
+
+        // Snapshots are only a diagnostic aid, so failing to write one is logged
+        #[cfg(feature = "stats")]
+        if options.stats_snapshots > 0 {
+            match self.write_stats_snapshot(Some(&stats), options.stats_snapshots) {
+                Ok(path) => stats.stats_snapshot = Some(path),
+                Err(e) => warn!("Failed to write stats snapshot: {}", e),
+            }
+        }
+
+        debug!("Maintenance ran {} compactions", stats.compactions);
+        Ok(stats)
+    }
+
+    /// Write the current [`EngineStats`] as JSON into the `stats` directory,
+    /// keeping the newest `keep` snapshots
+    ///
+    /// `maintenance` is recorded alongside, for snapshots taken by a
+    /// maintenance pass. Returns the path of the new snapshot.
+    #[cfg(feature = "stats")]
+    pub fn write_stats_snapshot(
+        &self,
+        maintenance: Option<&MaintenanceStats>,
+        keep: usize,
+    ) -> EngineResult<PathBuf> {
+        let stats = self.stats();
+        let snapshot = StatsSnapshot {
+            taken_at_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            sequence_number: self.sequence_number(),
+            stats: &stats,
+            maintenance,
+        };
+        let dir = self.config.data_dir.join(STATS_DIR);
+        Ok(stats::write_snapshot(&dir, &snapshot, keep)?)
+    }
+
+    /// Give up to `max_tables` legacy SSTables a current bloom filter and index
+    ///
+    /// Tables in an older format, or whose bloom filter is missing, undersized
+    /// or lacks keys, are copied with only their metadata rebuilt and swapped
+    /// in at the same level, so they gain the current read path without a
+    /// recompaction. Returns the number of tables rebuilt.
+    pub fn rebuild_legacy_sstables(&mut self, max_tables: usize) -> EngineResult<usize> {
+        let mut rebuilt = 0;
+        let tables: Vec<Arc<SSTable>> = self.versions.current().sstables().cloned().collect();
+        for sstable in tables {
+            if rebuilt == max_tables {
+                break;
+            }
+            if !sstable.needs_metadata_rebuild()? {
+                continue;
+            }
+            let dir = sstable.path().parent().unwrap_or(&self.config.data_dir);
+            let path = self.timestamped_path(dir, "sstable", "sst");
+            let mut replacement =
+                sstable.rebuild_metadata(path.with_extension(TEMP_SSTABLE_EXTENSION))?;
+            replacement.persist_to(&path)?;
+            let next = self
+                .versions
+                .current()
+                .with_replaced_sstable(&sstable, Arc::new(replacement));
+            self.install_version(next)?;
+            self.retire_sstable(&sstable)?;
+            rebuilt += 1;
+        }
+        Ok(rebuilt)
+    }
+
+    /// Re-read SSTables and verify them, catching storage decay before a read hits it
+    ///
+    /// Tables are checked in file name order, resuming after the last one
+    /// checked by the previous call, until about `max_bytes` have been read or
+    /// every table has been checked once. Corrupt tables are logged and
+    /// reported; under [`CorruptFilePolicy::Quarantine`] they are also removed
+    /// from the database and moved aside, like at startup.
+    pub fn scrub(&mut self, max_bytes: u64) -> EngineResult<ScrubStats> {
+        let mut tables: Vec<Arc<SSTable>> = self.versions.current().sstables().cloned().collect();
+        tables.sort_by(|a, b| a.path().file_name().cmp(&b.path().file_name()));
+        let resume = self.scrub_cursor.as_ref().map_or(0, |cursor| {
+            tables.partition_point(|t| t.path().file_name() <= Some(cursor.as_os_str()))
+        });
+        tables.rotate_left(resume);
+
+        let mut stats = ScrubStats::default();
+        for sstable in tables {
+            if stats.bytes_checked >= max_bytes {
+                break;
+            }
+            self.scrub_cursor = sstable.path().file_name().map(|name| name.to_os_string());
+            stats.sstables_checked += 1;
+            match sstable.verify() {
+                Ok(bytes) => stats.bytes_checked += bytes,
                 Err(e) => {
-                    warn!("Failed to load SSTable {:?}: {}", sstable_path, e);
+                    let path = sstable.path().to_path_buf();
+                    error!("Scrub found corrupt SSTable {:?}: {}", path, e);
+                    if self.config.corrupt_file_policy != CorruptFilePolicy::Quarantine {
+                        self.report_corruption(&path, &e, None);
+                    } else {
+                        let next = self
+                            .versions
+                            .current()
+                            .retain_sstables(|t| !Arc::ptr_eq(t, &sstable));
+                        self.install_version(next)?;
+                        drop(sstable);
+                        self.handle_corrupt_sstable(&path, &e)?;
+                    }
+                    stats.corrupt_sstables.push(path);
+                }
+            }
+        }
+        debug!(
+            "Scrubbed {} SSTables ({} bytes), {} corrupt",
+            stats.sstables_checked,
+            stats.bytes_checked,
+            stats.corrupt_sstables.len()
+        );
+        Ok(stats)
+    }
+
+    /// Register a secondary index whose key is extracted from each value
+    ///
+    /// Index definitions aren't persisted, so register them after every open.
+    /// Only writes made while the index is registered are indexed; call
+    /// [`Engine::rebuild_index`] to backfill existing data. Values streamed with
+    /// `put_writer` are never indexed.
+    pub fn register_index<F>(&mut self, name: &str, extractor: F) -> EngineResult<()>
+    where
+        F: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        if self.indexes.iter().any(|index| index.name() == name) {
+            return Err(EngineError::InvalidConfig(format!(
+                "Index {} is already registered",
+                name
+            )));
+        }
+
+        let extractor: IndexExtractor = Arc::new(extractor);
+        self.indexes.push(SecondaryIndex::new(name, extractor));
+        info!("Registered secondary index {}", name);
+        Ok(())
+    }
+
+    /// Rewrite every entry of an index from the current primary data
+    ///
+    /// Primary keys are streamed and their entries written in bounded
+    /// batches, then entries that no longer match their primary value are
+    /// deleted the same way. Memory stays bounded, each
+    /// batch pays one WAL sync, and a crash part way never loses an entry
+    /// already written: stale ones it leaves behind are ignored by
+    /// [`Engine::scan_index`] until the next rebuild.
+    pub async fn rebuild_index(&mut self, name: &str) -> EngineResult<()> {
+        let index = self.find_index(name)?.clone();
+        let max_bytes = self.memtable.max_size() / 2;
+
+        let mut batch = WriteBatch::new();
+        let mut batch_bytes = 0;
+        for pair in self.iter::<&[u8], _>(..) {
+            let (key, value) = pair?;
+            if let Some(index_key) = index.extract(&value) {
+                let entry_key = index.entry_key(&index_key, &key);
+                // As the MemTable counts it, with the entry's fixed overhead
+                batch_bytes += entry_key.len() + key.len() + 16;
+                batch.put(&entry_key, &key);
+            }
+            if batch.len() >= BULK_BATCH_ENTRIES || batch_bytes >= max_bytes {
+                self.commit_batch(std::mem::take(&mut batch), None).await?;
+                batch_bytes = 0;
+            }
+        }
+        self.commit_batch(std::mem::take(&mut batch), None).await?;
+
+        let (start, end) = index.entry_range::<&[u8], _>(..);
+        let entries = self
+            .iter::<&[u8], _>((
+                start.as_ref().map(|k| k.as_slice()),
+                end.as_ref().map(|k| k.as_slice()),
+            ))
+            .with_internal_keys();
+        let mut deleted = 0;
+        for pair in entries {
+            let (entry_key, primary_key) = pair?;
+            let current = self
+                .read_value(&primary_key)?
+                .and_then(|value| index.extract(&value));
+            if current.is_none_or(|ik| index.entry_key(&ik, &primary_key) != entry_key) {
+                batch_bytes += entry_key.len() + 16;
+                batch.delete(&entry_key);
+                deleted += 1;
+            }
+            if batch.len() >= BULK_BATCH_ENTRIES || batch_bytes >= max_bytes {
+                self.commit_batch(std::mem::take(&mut batch), None).await?;
+                batch_bytes = 0;
+            }
+        }
+        self.commit_batch(batch, None).await?;
+
+        info!(
+            "Rebuilt secondary index {}, removing {} stale entries",
+            name, deleted
+        );
+        Ok(())
+    }
+
+    /// Scan primary key-value pairs whose index key falls in `range`, in index order
+    ///
+    /// Each hit is checked against the current primary value, so entries left
+    /// stale by an interrupted write are never returned.
+    pub async fn scan_index<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        name: &str,
+        range: R,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        let index = self.find_index(name)?;
+        let (start, end) = index.entry_range(range);
+        let entries = self.scan_range(
+            start.as_ref().map(|k| k.as_slice()),
+            end.as_ref().map(|k| k.as_slice()),
+        )?;
+
+        let mut results = Vec::with_capacity(entries.len());
+        for (entry_key, primary_key) in entries {
+            let Some(value) = self.read_value(&primary_key)? else {
+                continue;
+            };
+            let index_key = index.extract(&value);
+            if index_key.is_some_and(|ik| index.entry_key(&ik, &primary_key) == entry_key) {
+                results.push((primary_key.to_vec(), value));
+            }
+        }
+        Ok(results)
+    }
+
+    fn find_index(&self, name: &str) -> EngineResult<&SecondaryIndex> {
+        self.indexes
+            .iter()
+            .find(|index| index.name() == name)
+            .ok_or_else(|| EngineError::IndexNotFound(name.to_string()))
+    }
+
+    /// Load the value for an entry, following blob pointers
+    fn resolve_value(&self, entry: Entry) -> EngineResult<Option<Bytes>> {
+        match entry.value {
+            Some(pointer) if entry.indirect => {
+                let pointer = BlobPointer::decode(&pointer)?;
+                Ok(Some(self.blobs.read(&pointer)?))
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Reclaim space in the value log
+    ///
+    /// Finds the blob pointers still referenced by the newest version of each key.
+    /// Files with no live values are deleted; files whose live fraction is below
+    /// `value_log_gc_ratio` have their live values re-appended and re-pointed first.
+    /// Re-pointed values keep the timestamp they were written with, but take a
+    /// new sequence number so they shadow the old pointer. Files that existed
+    /// when a live iterator was created are left alone, since it may still
+    /// follow pointers into them.
+    pub async fn collect_value_log_garbage(&mut self) -> EngineResult<ValueLogGcStats> {
+        self.collect_value_log_garbage_below(self.config.value_log_gc_ratio)
+            .await
+    }
+
+    /// Collect the value log once compactions have dropped
+    /// `value_log_auto_gc_bytes` of values from it
+    pub(crate) async fn collect_value_log_garbage_if_due(&mut self) -> EngineResult<()> {
+        if self
+            .config
+            .value_log_auto_gc_bytes
+            .is_some_and(|due| self.value_log_garbage >= due.max(1))
+        {
+            self.collect_value_log_garbage().await?;
+        }
+        Ok(())
+    }
+
+    /// Value log garbage collection rewriting files whose live fraction is below `ratio`
+    async fn collect_value_log_garbage_below(
+        &mut self,
+        ratio: f64,
+    ) -> EngineResult<ValueLogGcStats> {
+        // Running compactions may still read the logs about to be rewritten
+        self.wait_for_compactions()?;
+        self.value_log_garbage = 0;
+        // Seal the active log so it can be collected too
+        self.blobs.seal_active();
+
+        // Newest version wins: MemTable first, then SSTables newest first.
+        // Merge operands still apply to the version under them, whose blob
+        // can't be re-pointed without losing them, so its file is pinned.
+        let mut seen = HashSet::new();
+        let mut under_operand = HashSet::new();
+        let mut pinned = HashSet::new();
+        let mut live: HashMap<u64, Vec<(Vec<u8>, BlobPointer, u64)>> = HashMap::new();
+        let mut track = |entry: Entry| -> EngineResult<()> {
+            if seen.contains(&entry.key) {
+                return Ok(());
+            }
+            if entry.merge {
+                under_operand.insert(entry.key);
+                return Ok(());
+            }
+            seen.insert(entry.key.clone());
+            if let Some(pointer) = entry.value.as_ref().filter(|_| entry.indirect) {
+                let pointer = BlobPointer::decode(pointer)?;
+                if under_operand.contains(&entry.key) {
+                    pinned.insert(pointer.file_id);
+                }
+                live.entry(pointer.file_id).or_default().push((
+                    entry.key,
+                    pointer,
+                    entry.timestamp,
+                ));
+            }
+            Ok(())
+        };
+        let version = self.versions.current();
+        for memtable in version.memtables() {
+            for entry in memtable.entries() {
+                track(entry)?;
+            }
+        }
+        for sstable in version.sstables() {
+            for entry in sstable.iter() {
+                track(entry?)?;
+            }
+        }
+
+        let mut stats = ValueLogGcStats::default();
+        for file_id in self.blobs.file_ids()? {
+            let file_len = self.blobs.file_len(file_id)?;
+            let pointers = live.remove(&file_id).unwrap_or_default();
+            let live_bytes: u64 = pointers.iter().map(|(_, pointer, _)| pointer.len).sum();
+
+            if !pointers.is_empty() {
+                if pinned.contains(&file_id) {
+                    if live_bytes < file_len {
+                        stats.files_pinned += 1;
+                    }
+                    continue;
+                }
+                if live_bytes as f64 >= file_len as f64 * ratio {
+                    continue;
+                }
+            }
+            if self.blobs.is_pinned(file_id) {
+                stats.files_pinned += 1;
+                continue;
+            }
+
+            if !pointers.is_empty() {
+                // Move the live values to the head of the log before dropping
+                // the file; the write keeps its timestamp, and the key's
+                // expiry, if any, still applies
+                for (key, pointer, timestamp) in pointers {
+                    let value = self.blobs.read(&pointer)?;
+                    let pointer = self.blobs.append(&value)?;
+                    let mut entry = self.next_entry(&key, Some(pointer.encode()), Some(timestamp));
+                    entry.indirect = true;
+                    self.append_entry(entry).await?;
                 }
+                stats.files_rewritten += 1;
+            }
+
+            if self.config.secure_delete {
+                self.blobs.shred(file_id)?;
+            } else {
+                self.blobs.remove(file_id)?;
+            }
+            stats.files_deleted += 1;
+            stats.bytes_reclaimed += file_len - live_bytes;
+        }
+
+        info!(
+            "Value log GC deleted {} files ({} rewritten), reclaimed {} bytes",
+            stats.files_deleted, stats.files_rewritten, stats.bytes_reclaimed
+        );
+        Ok(stats)
+    }
+
+    /// Delete a key from the database, returning the sequence number of the
+    /// tombstone (or of the last index entry removed with it)
+    pub async fn delete(&mut self, key: &[u8]) -> EngineResult<u64> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
+
+        if !self.indexes.is_empty() && !index::is_internal_key(key) {
+            let mut batch = WriteBatch::new();
+            batch.delete(key);
+            return self.commit_batch(batch, None).await;
+        }
+
+        self.record_access(key);
+        self.write_delete(key).await
+    }
+
+    /// Set or update the expiry of an existing key
+    ///
+    /// `unix_ts` is in seconds since the Unix epoch; from then on the key reads
+    /// as missing and compaction drops it. Only a small record is written, the
+    /// value stays where it is. A later put or delete clears the expiry.
+    /// Returns the sequence number of the record, or None, writing nothing,
+    /// when the key doesn't exist.
+    pub async fn expire_at(&mut self, key: &[u8], unix_ts: u64) -> EngineResult<Option<u64>> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
+        if index::is_internal_key(key) {
+            return Err(EngineError::InvalidConfig(
+                "Internal keys cannot expire".to_string(),
+            ));
+        }
+        if self.read_value(key)?.is_none() {
+            return Ok(None);
+        }
+
+        let deadline = Bytes::copy_from_slice(&expiry::encode_deadline(unix_ts));
+        let entry = self.next_entry(&expiry::expiry_key(key), Some(deadline), None);
+        let sequence = self.append_entry(entry).await?;
+        self.expiries.set(key, unix_ts);
+        Ok(Some(sequence))
+    }
+
+    /// Drop the expiry of a key about to be overwritten or deleted
+    async fn clear_expiry(&mut self, key: &[u8]) -> EngineResult<()> {
+        if !self.expiries.remove(key) {
+            return Ok(());
+        }
+        let entry = self.next_entry(&expiry::expiry_key(key), None, None);
+        self.append_entry(entry).await?;
+        Ok(())
+    }
+
+    /// Remove expiry records whose key no longer has a value anywhere
+    ///
+    /// Runs after compaction: once the newest version of an expired key is a
+    /// tombstone or gone, the record has nothing left to hide.
+    pub(crate) async fn purge_expiries(&mut self) -> EngineResult<()> {
+        for key in self.expiries.expired(expiry::now_secs()) {
+            if self
+                .find_entry(&key)?
+                .is_none_or(|entry| entry.value.is_none())
+            {
+                self.clear_expiry(&key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the expiry deadlines from their records
+    fn load_expiries(&mut self) -> EngineResult<()> {
+        let prefix = expiry::expiry_prefix();
+        let mut end = prefix.clone();
+        *end.last_mut().unwrap() += 1;
+        let records = self.scan_range(Bound::Included(&prefix), Bound::Excluded(&end))?;
+        for (record_key, value) in records {
+            match (
+                expiry::decode_expiry_key(&record_key),
+                expiry::decode_deadline(&value),
+            ) {
+                (Some(key), Some(deadline)) => self.expiries.set(&key, deadline),
+                _ => warn!("Ignoring malformed expiry record {:?}", record_key),
             }
         }
-        
-        let sstable_count = self.sstables.read().unwrap().len();
-        info!("Loaded {} existing SSTables", sstable_count);
         Ok(())
     }
 
-    /// Get database statistics
-    pub fn stats(&self) -> EngineStats {
-        let memtable_size = self.memtable.size_bytes();
-        let sstable_count = self.sstables.read().unwrap().len();
+    /// Delete a key only if its current value equals `expected`
+    ///
+    /// The check and the delete run back to back on the write path, so no
+    /// other write lands in between; a lock holder can release its lease
+    /// without clobbering one taken over by someone else. Returns the sequence
+    /// number of the delete, or None when the value didn't match.
+    pub async fn delete_if(&mut self, key: &[u8], expected: &[u8]) -> EngineResult<Option<u64>> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
+
+        if self.read_value(key)?.as_deref() != Some(expected) {
+            return Ok(None);
+        }
+        Ok(Some(self.delete(key).await?))
+    }
+
+    /// Apply every put and delete in `batch` atomically
+    ///
+    /// The batch is logged with a single WAL append and then applied to the
+    /// MemTable, so after a crash either all of it is recovered or none of it.
+    /// Secondary index updates and expiry records for its keys are part of the
+    /// same batch. Returns the sequence number of the last record written, or
+    /// the current one for an empty batch.
+    ///
+    /// A batch over `EngineConfig::batch_limits` fails with
+    /// [`EngineError::BatchLimitExceeded`], or with `split` set is committed as
+    /// consecutive sub-batches within the limits, each atomic on its own. If a
+    /// sub-batch fails, those before it stay applied.
+    pub async fn write_batch(&mut self, batch: WriteBatch) -> EngineResult<u64> {
+        for op in batch.ops() {
+            if op.key().is_empty() {
+                return Err(EngineError::InvalidConfig(
+                    "Key cannot be empty".to_string(),
+                ));
+            }
+            if matches!(op, BatchOp::PutWithTtl { .. }) && index::is_internal_key(op.key()) {
+                return Err(EngineError::InvalidConfig(
+                    "Internal keys cannot expire".to_string(),
+                ));
+            }
+            if !matches!(op, BatchOp::Delete { .. })
+                && self.config.time_series.is_some()
+                && op.key().len() < 8
+            {
+                return Err(EngineError::InvalidConfig(
+                    "Time-series keys must start with an 8-byte timestamp".to_string(),
+                ));
+            }
+        }
+
+        let limits = self.config.batch_limits;
+        if !limits.is_exceeded_by(&batch) {
+            return self.commit_batch(batch, None).await;
+        }
+        let exceeded = EngineError::BatchLimitExceeded {
+            entries: batch.len(),
+            bytes: batch.size_bytes(),
+        };
+        if !limits.split {
+            return Err(exceeded);
+        }
+        let chunks = limits
+            .split(batch)
+            .map_err(|op| EngineError::BatchLimitExceeded {
+                entries: 1,
+                bytes: op.size_bytes(),
+            })?;
+        debug!("{}; committing {} sub-batches", exceeded, chunks.len());
+        let mut sequence = self.sequence_number();
+        for chunk in chunks {
+            sequence = self.commit_batch(chunk, None).await?;
+        }
+        Ok(sequence)
+    }
+
+    /// Atomically apply a validated `batch`, stamping its records with
+    /// `timestamp` or the current time
+    async fn commit_batch(
+        &mut self,
+        batch: WriteBatch,
+        timestamp: Option<u64>,
+    ) -> EngineResult<u64> {
+        if batch.is_empty() {
+            return Ok(self.sequence_number());
+        }
+
+        // Values as of the ops so far, so index updates see earlier writes in the batch
+        let mut written: HashMap<Vec<u8>, Option<Bytes>> = HashMap::new();
+        // Deadlines the batch sets or clears, applied once it is logged
+        let mut deadlines: HashMap<Vec<u8>, Option<u64>> = HashMap::new();
+        // Records to log, numbered only once the batch is accepted
+        let mut writes = Vec::new();
+        for op in batch {
+            self.record_access(op.key());
+            let (key, value, deadline) = match op {
+                BatchOp::Put { key, value } => (key, Some(Bytes::from(value)), None),
+                BatchOp::PutWithTtl {
+                    key,
+                    value,
+                    expires_at,
+                } => (key, Some(Bytes::from(value)), Some(expires_at)),
+                BatchOp::Delete { key } => (key, None, None),
+            };
+
+            let has_deadline = match deadlines.get(&key) {
+                Some(deadline) => deadline.is_some(),
+                None => self.expiries.get(&key).is_some(),
+            };
+            if deadline.is_some() || has_deadline {
+                let record = deadline.map(|d| Bytes::copy_from_slice(&expiry::encode_deadline(d)));
+                writes.push(PendingWrite::internal(expiry::expiry_key(&key), record));
+                deadlines.insert(key.clone(), deadline);
+            }
+
+            let mut stale = Vec::new();
+            if !self.indexes.is_empty() && !index::is_internal_key(&key) {
+                let old_value = match written.get(&key) {
+                    Some(old_value) => old_value.clone(),
+                    None => self.read_value(&key)?,
+                };
+                for index in &self.indexes {
+                    let old_index_key = old_value.as_ref().and_then(|v| index.extract(v));
+                    let new_index_key = value.as_ref().and_then(|v| index.extract(v));
+                    if old_index_key == new_index_key {
+                        continue;
+                    }
+                    if let Some(new_index_key) = new_index_key {
+                        let entry_key = index.entry_key(&new_index_key, &key);
+                        writes.push(PendingWrite::value(
+                            entry_key,
+                            Some(Bytes::copy_from_slice(&key)),
+                        ));
+                    }
+                    if let Some(old_index_key) = old_index_key {
+                        stale.push(index.entry_key(&old_index_key, &key));
+                    }
+                }
+            }
+
+            writes.push(PendingWrite::value(key.clone(), value.clone()));
+            for entry_key in stale {
+                writes.push(PendingWrite::internal(entry_key, None));
+            }
+            written.insert(key, value);
+        }
+
+        // Checked before any sequence number or blob is used, so a rejected
+        // batch leaves no trace and a logged one is never refused by the MemTable
+        let threshold = self.config.value_log_threshold;
+        let size: usize = writes.iter().map(|write| write.size_bytes(threshold)).sum();
+        if size > self.memtable.max_size() {
+            return Err(EngineError::BatchTooLarge(size));
+        }
+        if !self.memtable.is_empty() && self.memtable.size_bytes() + size > self.memtable.max_size()
+        {
+            self.flush_stalled().await?;
+        }
+
+        // WAL format v1 can't frame batches
+        if self.wal.format_version() < WAL_FORMAT_VERSION {
+            self.rotate_wal()?;
+        }
+
+        // Large values go to the value log like single puts, before numbering
+        // so a failed append can't leave a gap
+        for write in &mut writes {
+            if write.is_separated(threshold)
+                && let Some(value) = &write.value
+            {
+                write.value = Some(self.blobs.append(value)?.encode());
+                write.indirect = true;
+            }
+        }
+        let entries: Vec<Entry> = writes
+            .into_iter()
+            .map(|write| {
+                let mut entry = self.next_entry(&write.key, write.value, timestamp);
+                entry.indirect = write.indirect;
+                entry
+            })
+            .collect();
+
+        let records: Vec<WALRecord> = entries.iter().map(WALRecord::from_entry).collect();
+        self.wal.write_batch(&records)?;
+        let sequence_number = records.last().map_or(0, |record| record.sequence_number);
+        self.note_wal_write(sequence_number)?;
+
+        for entry in entries {
+            self.memtable.apply(entry)?;
+        }
+        for (key, deadline) in deadlines {
+            match deadline {
+                Some(deadline) => self.expiries.set(&key, deadline),
+                None => {
+                    self.expiries.remove(&key);
+                }
+            }
+        }
+
+        if self.needs_flush() {
+            self.flush_stalled().await?;
+        }
+
+        trace!("Batch write completed: seq={}", sequence_number);
+        Ok(sequence_number)
+    }
+
+    /// Add `delta` to the 8-byte little-endian integer stored at `key`
+    ///
+    /// A missing key, or one holding anything but an 8-byte integer, counts
+    /// from zero, and increments wrap on overflow. See [`Engine::merge`].
+    pub async fn incr(&mut self, key: &[u8], delta: i64) -> EngineResult<u64> {
+        self.merge(key, MergeOp::Add(delta)).await
+    }
+
+    /// Append `bytes` to the value stored at `key`
+    ///
+    /// A missing key starts out empty, so a device can keep its event log in
+    /// one growing value without reading it back on every write. See
+    /// [`Engine::merge`].
+    pub async fn append(&mut self, key: &[u8], bytes: &[u8]) -> EngineResult<u64> {
+        self.merge(key, MergeOp::Append(Bytes::copy_from_slice(bytes)))
+            .await
+    }
+
+    /// Apply an operation to the value at `key` through a merge operand
+    ///
+    /// Only the operand is logged, so the write path never reads the old
+    /// value; reads and compaction fold operands into the value under them.
+    /// Merges keep an expiry already set on the key and bypass secondary
+    /// indexes. Returns the sequence number of the write.
+    pub async fn merge(&mut self, key: &[u8], op: MergeOp) -> EngineResult<u64> {
+        if key.is_empty() {
+            return Err(EngineError::InvalidConfig(
+                "Key cannot be empty".to_string(),
+            ));
+        }
+        if index::is_internal_key(key) {
+            return Err(EngineError::InvalidConfig(
+                "Internal keys cannot be merged into".to_string(),
+            ));
+        }
+
+        self.record_access(key);
+        let operand = merge::encode(&[op]);
+
+        // An expired value must not come back under the new operand
+        if self.expiries.is_expired(key, expiry::now_secs()) {
+            self.clear_expiry(key).await?;
+            let value = merge::full_merge(None, &operand)?;
+            let entry = self.next_entry(key, Some(value), None);
+            return self.append_entry(entry).await;
+        }
+
+        // The MemTable folds operands on insert but can't read blobs, so a
+        // value in the value log is merged here instead
+        if let Some(base) = self.memtable.get_entry(key)?.filter(|e| e.indirect) {
+            let base = self.resolve_value(base)?;
+            let value = merge::full_merge(base.as_deref(), &operand)?;
+            let entry = self.next_entry(key, Some(value), None);
+            return self.append_entry(entry).await;
+        }
+
+        // WAL format v1 can't log operands
+        if self.wal.format_version() < WAL_FORMAT_VERSION {
+            self.rotate_wal()?;
+        }
+
+        let mut entry = self.next_entry(key, Some(operand), None);
+        entry.merge = true;
+        self.append_entry(entry).await
+    }
+
+    /// Read a value written by [`Engine::incr`] as an integer
+    ///
+    /// Fails with [`MergeError::NotAnInteger`] if the key holds anything but
+    /// an 8-byte value.
+    pub async fn get_i64(&self, key: &[u8]) -> EngineResult<Option<i64>> {
+        let Some(value) = self.get(key).await? else {
+            return Ok(None);
+        };
+        Ok(Some(merge::decode_i64(&value)?))
+    }
+
+    /// Write a tombstone without touching secondary indexes
+    async fn write_delete(&mut self, key: &[u8]) -> EngineResult<u64> {
+        self.clear_expiry(key).await?;
+        let entry = self.next_entry(key, None, None);
+        self.append_entry(entry).await
+    }
+
+    /// Atomically drop all data
+    ///
+    /// Writing a manifest with a bumped epoch and no tables is the commit
+    /// point; the old SSTables and WAL files are retired right after, and any a
+    /// crash leaves behind are removed on the next open. Registered indexes
+    /// stay registered but empty, and value log space is reclaimed by
+    /// [`Engine::collect_value_log_garbage`].
+    pub async fn truncate(&mut self) -> EngineResult<()> {
+        // Pending flushes hold only writes that are about to be dropped
+        if let Some(flusher) = &mut self.flusher {
+            for finished in flusher.wait().into_iter().flatten() {
+                finished.discard(self.config.secure_delete);
+            }
+        }
+        let old = self.versions.current();
+        let epoch = Epoch {
+            number: self.epoch.number + 1,
+            sequence_floor: self.sequence_number(),
+        };
+        let memtable = Arc::new(MemTable::new(self.config.memtable_size));
+        let version = Version::new(memtable.clone(), self.config.max_levels);
+        self.log_manifest(Manifest::from_version(&version).with_epoch(epoch))?;
+        self.epoch = epoch;
+        self.memtable = memtable;
+        self.versions.install(version);
+        self.expiries = Expiries::default();
+
+        // Readers still holding an old table keep it until they finish
+        for sstable in old.sstables() {
+            self.retire_sstable(sstable)?;
+        }
+        self.rotate_wal()?;
+        remove_stale_wal_files(&self.config.data_dir, epoch, self.config.secure_delete)?;
+
+        info!(
+            "Truncated database at sequence {}, now in epoch {}",
+            epoch.sequence_floor, epoch.number
+        );
+        Ok(())
+    }
+
+    /// Write a consistent snapshot of the database to one archive file
+    ///
+    /// The MemTable is flushed first, then the live SSTables, the value log
+    /// and a manifest listing just those tables are packed into a compressed,
+    /// checksummed archive that opens as a data directory once unpacked.
+    pub async fn export_archive<P: AsRef<Path>>(&mut self, path: P) -> EngineResult<ArchiveStats> {
+        if self.has_unflushed_writes() {
+            self.flush_memtable(FlushReason::Manual).await?;
+        }
+        let version = self.versions.current();
+        let mut stats = ArchiveStats::default();
+        let mut writer = ArchiveWriter::create(path.as_ref())?;
+
+        let layout_path = self.config.data_dir.join(layout::LAYOUT_FILE);
+        if layout_path.exists() {
+            writer.add_file(layout::LAYOUT_FILE, &layout_path)?;
+        }
+        for sstable in version.sstables() {
+            let name = sstable_file_name(sstable).ok_or_else(|| {
+                EngineError::InvalidConfig(format!("Unnamed SSTable {:?}", sstable.path()))
+            })?;
+            writer.add_file(name, sstable.path())?;
+            stats.sstables += 1;
+        }
+        for file_id in self.blobs.file_ids()? {
+            let blob_path = self.blobs.blob_path(file_id);
+            if let Some(name) = blob_path.file_name().and_then(|n| n.to_str()) {
+                writer.add_file(&format!("blobs/{}", name), &blob_path)?;
+                stats.blob_files += 1;
+            }
+        }
+        // No WAL is archived, so the floor carries the sequence on
+        let manifest = Manifest::from_version(&version).with_epoch(Epoch {
+            number: self.epoch.number,
+            sequence_floor: self.sequence_number(),
+        });
+        writer.add_bytes(MANIFEST_FILE, manifest.encode().as_bytes())?;
+        stats.archive_bytes = writer.finish()?;
+
+        info!(
+            "Exported {} SSTables and {} blob files to {:?} ({} bytes)",
+            stats.sstables,
+            stats.blob_files,
+            path.as_ref(),
+            stats.archive_bytes
+        );
+        Ok(stats)
+    }
+
+    /// Write a consistent copy of the database to `dir` that opens as a data
+    /// directory, without flushing or stopping writes for longer than the copy
+    ///
+    /// SSTables and sealed value log files never change, so they are
+    /// hard-linked where the filesystem allows and copied otherwise; the WAL
+    /// files holding unflushed writes and the active value log are copied as
+    /// of this call, next to a manifest of the live tables. With
+    /// `secure_delete` every file is copied, since shredding a table would
+    /// overwrite its links too. `dir` must be missing or empty; the copy is
+    /// staged next to it and moved into place once complete.
+    pub fn checkpoint<P: AsRef<Path>>(&self, dir: P) -> EngineResult<CheckpointStats> {
+        let dir = dir.as_ref();
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(EngineError::InvalidConfig(format!(
+                "Checkpoint target {:?} is not empty",
+                dir
+            )));
+        }
+        let mut staging_name = dir.file_name().unwrap_or_default().to_os_string();
+        staging_name.push(".checkpointing");
+        let staging = dir.with_file_name(staging_name);
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
+
+        let stats = match self.write_checkpoint(&staging) {
+            Ok(stats) => stats,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+        std::fs::rename(&staging, dir)?;
+        if let Some(parent) = dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+            sync_dir(parent)?;
+        }
+
+        info!(
+            "Checkpointed {} SSTables, {} WAL files and {} blob files to {:?} ({} linked, {} bytes copied)",
+            stats.sstables,
+            stats.wal_files,
+            stats.blob_files,
+            dir,
+            stats.linked_files,
+            stats.bytes_copied
+        );
+        Ok(stats)
+    }
+
+    /// Link or copy the files of the current version into `dir`
+    fn write_checkpoint(&self, dir: &Path) -> EngineResult<CheckpointStats> {
+        // Every write so far must be in the WAL files copied below
+        self.sync()?;
+        let version = self.versions.current();
+        let link = !self.config.secure_delete;
+        let mut stats = CheckpointStats::default();
+
+        let layout_path = self.config.data_dir.join(layout::LAYOUT_FILE);
+        if layout_path.exists() {
+            stats.bytes_copied += copy_synced(&layout_path, &dir.join(layout::LAYOUT_FILE))?;
+        }
+        for sstable in version.sstables() {
+            let name = sstable_file_name(sstable).ok_or_else(|| {
+                EngineError::InvalidConfig(format!("Unnamed SSTable {:?}", sstable.path()))
+            })?;
+            stats.bytes_copied += link_or_copy(
+                sstable.path(),
+                &dir.join(name),
+                link,
+                &mut stats.linked_files,
+            )?;
+            stats.sstables += 1;
+        }
+        let wal_paths = self.retired_wals.iter().map(|(path, _)| path.as_path());
+        for path in wal_paths.chain([self.wal.path()]) {
+            let name = path.file_name().unwrap_or_default();
+            stats.bytes_copied += copy_synced(path, &dir.join(name))?;
+            stats.wal_files += 1;
+        }
+        let blob_dir = dir.join("blobs");
+        std::fs::create_dir_all(&blob_dir)?;
+        let active = self.blobs.active_file_id();
+        for file_id in self.blobs.file_ids()? {
+            let blob_path = self.blobs.blob_path(file_id);
+            let target = blob_dir.join(blob_path.file_name().unwrap_or_default());
+            let sealed = Some(file_id) != active;
+            stats.bytes_copied +=
+                link_or_copy(&blob_path, &target, link && sealed, &mut stats.linked_files)?;
+            stats.blob_files += 1;
+        }
+        sync_dir(&blob_dir)?;
+
+        let manifest = Manifest::from_version(&version).with_epoch(self.epoch);
+        let manifest_path = dir.join(MANIFEST_FILE);
+        std::fs::write(&manifest_path, manifest.encode())?;
+        std::fs::File::open(&manifest_path)?.sync_all()?;
+        sync_dir(dir)?;
+        Ok(stats)
+    }
+
+    /// Validate an archive written by [`Engine::export_archive`] and unpack
+    /// it into `dir`, ready to open
+    ///
+    /// `dir` must be missing or empty. Files are unpacked into a staging
+    /// directory next to it and every listed SSTable is opened before the
+    /// directory is moved into place, so a bad archive leaves nothing behind.
+    pub fn import_archive<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        dir: Q,
+    ) -> EngineResult<ImportStats> {
+        let dir = dir.as_ref();
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(EngineError::InvalidConfig(format!(
+                "Import target {:?} is not empty",
+                dir
+            )));
+        }
+        let mut staging_name = dir.file_name().unwrap_or_default().to_os_string();
+        staging_name.push(".importing");
+        let staging = dir.with_file_name(staging_name);
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        std::fs::create_dir_all(&staging)?;
+
+        let mut stats = ImportStats::default();
+        if let Err(e) = unpack_archive(path.as_ref(), &staging, &mut stats) {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        std::fs::rename(&staging, dir)?;
+        if let Some(parent) = dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+            sync_dir(parent)?;
+        }
+
+        info!(
+            "Imported {} files ({} bytes) into {:?}",
+            stats.files, stats.bytes, dir
+        );
+        Ok(stats)
+    }
+
+    /// Merge the live keys of an archive into this database
+    ///
+    /// The archive is unpacked inside the data directory and its keys are
+    /// ingested as new level-0 tables with fresh sequence numbers, so they
+    /// replace any versions already stored; expiries carry over. Secondary
+    /// indexes are not updated, so rebuild them afterwards.
+    pub async fn merge_archive<P: AsRef<Path>>(&mut self, path: P) -> EngineResult<ImportStats> {
+        let staging = self.scratch_dir().join("import");
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+        let mut stats = Self::import_archive(path, &staging)?;
+        let merged = self.ingest_database(&staging).await;
+        std::fs::remove_dir_all(&staging)?;
+        stats.keys_ingested = merged?;
+        Ok(stats)
+    }
+
+    /// Ingest every live key of the database in `dir`
+    async fn ingest_database(&mut self, dir: &Path) -> EngineResult<usize> {
+        let source = Engine::with_config(EngineConfig {
+            data_dir: dir.to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await?;
+        let pairs = source
+            .iter::<&[u8], _>(..)
+            .map(|pair| pair.map(|(key, value)| (key, Some(value))));
+        let ingested = self.ingest(pairs).await?;
+        for (key, deadline) in source.expiries.iter() {
+            self.expire_at(key, deadline).await?;
+        }
+        Ok(ingested)
+    }
+
+    /// Load an SSTable built outside this database, such as with
+    /// [`SSTableBuilder`](crate::sstable::SSTableBuilder), without going
+    /// through the WAL or MemTable
+    ///
+    /// The file is copied into the level-0 directory, and the copy verified
+    /// in a single pass. Its sequence numbers are then shifted past every
+    /// write so far, so its entries replace any versions already stored and
+    /// its tombstones delete their keys, and the copy joins level 0 as it is,
+    /// keeping the timestamps it was built with. Tables that can't be adopted
+    /// whole are rewritten into new level-0 tables with fresh sequence numbers
+    /// and timestamps instead: those in a format before v3, which stores
+    /// absolute sequence numbers, those holding values big enough for the
+    /// value log, and in time-series mode those spanning several partitions.
+    /// Blob pointers and merge operands only mean something in the database
+    /// that wrote them, so tables holding either are refused. The file itself
+    /// is left in place. Returns the number of entries ingested.
+    pub async fn ingest_sstable<P: AsRef<Path>>(&mut self, path: P) -> EngineResult<usize> {
+        let path = path.as_ref();
+        if self.has_unflushed_writes() {
+            self.flush_memtable(FlushReason::Manual).await?;
+        }
+        // Everything in the older logs is now in tables; replaying them on
+        // open would shadow the ingested values with stale ones
+        self.remove_flushed_wals()?;
+
+        let sstable_path = self.timestamped_path(self.level_dir(0), "sstable", "sst");
+        let temp_path = sstable_path.with_extension(TEMP_SSTABLE_EXTENSION);
+        std::fs::copy(path, &temp_path)?;
+        let ingested = self.adopt_sstable(path, &temp_path, &sstable_path).await;
+        if temp_path.exists() {
+            remove_data_file(&temp_path, self.config.secure_delete)?;
+        }
+        let ingested = ingested?;
+        info!("Ingested {} entries from SSTable {:?}", ingested, path);
+        Ok(ingested)
+    }
+
+    /// Verify the copy of `source` at `temp_path`, then move it to
+    /// `sstable_path` in level 0, or rewrite its entries if it can't be
+    /// adopted whole
+    async fn adopt_sstable(
+        &mut self,
+        source: &Path,
+        temp_path: &Path,
+        sstable_path: &Path,
+    ) -> EngineResult<usize> {
+        let sstable = SSTable::open_lazy(temp_path)?;
+        let mut sequences: Option<(u64, u64)> = None;
+        let mut largest_value = 0;
+        let mut expiring = Vec::new();
+        sstable.verify_with(|entry| {
+            if entry.indirect || entry.merge {
+                return Err(EngineError::InvalidConfig(format!(
+                    "SSTable {:?} holds a {} for key {:?} and cannot be ingested",
+                    source,
+                    if entry.merge {
+                        "merge operand"
+                    } else {
+                        "blob pointer"
+                    },
+                    String::from_utf8_lossy(&entry.key)
+                )));
+            }
+            let seq = entry.sequence_number;
+            sequences =
+                Some(sequences.map_or((seq, seq), |(min, max)| (min.min(seq), max.max(seq))));
+            largest_value = largest_value.max(entry.value.as_ref().map_or(0, Bytes::len));
+            if self.expiries.get(&entry.key).is_some() {
+                expiring.push(entry.key.clone());
+            }
+            Ok(())
+        })?;
+        let Some(sequences) = sequences else {
+            return Ok(0);
+        };
+        sstable.set_sequence_range(sequences);
+
+        let fits_value_log = self
+            .config
+            .value_log_threshold
+            .is_none_or(|threshold| largest_value < threshold);
+        let fits_partition = match (
+            &self.config.time_series,
+            sstable.first_key().zip(sstable.last_key()),
+        ) {
+            (Some(options), Some((first, last))) => {
+                options.partition_of(first) == options.partition_of(last)
+            }
+            _ => true,
+        };
+        if sstable.format_version() < 3 || !fits_value_log || !fits_partition {
+            let pairs = sstable.iter().map(|entry| {
+                entry
+                    .map(|entry| (entry.key, entry.value))
+                    .map_err(EngineError::from)
+            });
+            return self.ingest(pairs).await;
+        }
+
+        let count = sstable.entry_count() as usize;
+        let first = self.sequence_number() + 1;
+        let mut sstable = sstable.rebase_sequences(first)?;
+        sstable.persist_to(sstable_path)?;
+        if let Some((_, last)) = sstable.sequence_range() {
+            *self.sequence_number.write().unwrap() = last;
+        }
+
+        let mut cleared = Vec::new();
+        for key in expiring {
+            if self.expiries.remove(&key) {
+                cleared.push(self.next_entry(&expiry::expiry_key(&key), None, None));
+            }
+        }
+        self.install_ingested(vec![Arc::new(sstable)], cleared)?;
+        Ok(count)
+    }
+
+    /// Write every live key with its value and timestamp to `writer` as JSON
+    /// Lines or CSV, for tools that don't read SSTables
+    ///
+    /// Reads a snapshot taken at the call, in key order; internal keys and
+    /// expired values are left out. Returns the number of records written.
+    pub fn export<W: Write>(&self, writer: W, format: ExportFormat) -> EngineResult<usize> {
+        let mut records = RecordWriter::new(writer, format);
+        let mut iter = self.iter::<&[u8], _>(..);
+        let mut count = 0;
+        while let Some(pair) = iter.next_versioned() {
+            let (key, versioned) = pair?;
+            records.write(&Record {
+                key,
+                value: versioned.value.to_vec(),
+                timestamp: Some(versioned.timestamp),
+            })?;
+            count += 1;
+        }
+        records.finish()?;
+        info!("Exported {} records as {}", count, format);
+        Ok(count)
+    }
+
+    /// Put every record read from `reader`, as written by [`Engine::export`]
+    /// or another tool, keeping the timestamps records carry
+    ///
+    /// Records go through the WAL like any put, so input need not be sorted,
+    /// and a record whose key repeats replaces the earlier one. A malformed
+    /// record stops the import with [`ExportError::Parse`] naming its line,
+    /// after the records before it were written. Returns the number of
+    /// records imported.
+    pub async fn import<R: BufRead>(
+        &mut self,
+        reader: R,
+        format: ExportFormat,
+    ) -> EngineResult<usize> {
+        let mut count = 0;
+        for record in RecordReader::new(reader, format) {
+            let record = record?;
+            self.put_at(&record.key, &record.value, record.timestamp)
+                .await?;
+            count += 1;
+        }
+        info!("Imported {} records as {}", count, format);
+        Ok(count)
+    }
+
+    /// Write key-value pairs, in key order, straight into new level-0 tables
+    ///
+    /// The MemTable is flushed first, since reads check it before any table;
+    /// each pair then takes the next sequence number, making it the newest
+    /// version of its key, and a missing value writes a tombstone. Returns the
+    /// number of pairs written.
+    async fn ingest(
+        &mut self,
+        pairs: impl Iterator<Item = EngineResult<(Vec<u8>, Option<Bytes>)>>,
+    ) -> EngineResult<usize> {
+        if self.has_unflushed_writes() {
+            self.flush_memtable(FlushReason::Manual).await?;
+        }
+        // Everything in the older logs is now in tables; replaying them on
+        // open would shadow the ingested values with stale ones
+        self.remove_flushed_wals()?;
+
+        let mut tables = Vec::new();
+        let mut run = Vec::new();
+        let mut run_bytes = 0u64;
+        let mut cleared = Vec::new();
+        let mut count = 0;
+        for pair in pairs {
+            let (key, value) = pair?;
+            // Logging the expiry's removal would leave WAL records between the
+            // ingested sequence numbers, so it goes in a table of its own
+            if self.expiries.remove(&key) {
+                cleared.push(self.next_entry(&expiry::expiry_key(&key), None, None));
+            }
+            let entry = match (value, self.config.value_log_threshold) {
+                (Some(value), Some(threshold)) if value.len() >= threshold => {
+                    let pointer = self.blobs.append(&value)?;
+                    let mut entry = self.next_entry(&key, Some(pointer.encode()), None);
+                    entry.indirect = true;
+                    entry
+                }
+                (value, _) => self.next_entry(&key, value, None),
+            };
+            run_bytes += (entry.key.len() + entry.value.as_ref().map_or(0, Bytes::len)) as u64;
+            run.push(entry);
+            count += 1;
+            if run_bytes >= self.config.compaction.max_file_bytes {
+                tables.push(self.write_sstable(&run, 0, true)?);
+                run.clear();
+                run_bytes = 0;
+            }
+        }
+        if !run.is_empty() {
+            tables.push(self.write_sstable(&run, 0, true)?);
+        }
+        self.install_ingested(tables, cleared)?;
+        Ok(count)
+    }
+
+    /// Add ingested `tables` to level 0, with a table of `cleared` expiry
+    /// tombstones for the keys they overwrote
+    fn install_ingested(
+        &mut self,
+        mut tables: Vec<Arc<SSTable>>,
+        mut cleared: Vec<Entry>,
+    ) -> EngineResult<()> {
+        if !cleared.is_empty() {
+            cleared.sort_by(|a, b| a.key.cmp(&b.key));
+            tables.push(self.write_sstable(&cleared, 0, true)?);
+        }
+
+        let mut version = self.versions.current().as_ref().clone();
+        for sstable in tables {
+            version = version.with_new_sstable(0, sstable);
+        }
+        self.install_version(version)?;
+        // The tables are already synced
+        self.mark_durable(self.sequence_number());
+        Ok(())
+    }
+
+    /// Write sorted entries to a new SSTable in the directory for `level`
+    ///
+    /// The table is written under a temporary name, in the scratch directory
+    /// for background jobs, and moved into place, so a crash never leaves a
+    /// partial table behind to be loaded.
+    fn write_sstable(
+        &self,
+        entries: &[Entry],
+        level: usize,
+        background: bool,
+    ) -> EngineResult<Arc<SSTable>> {
+        let options = SSTableWriteOptions {
+            compression: self.config.compression,
+            compression_options: self.config.compression_options,
+            use_direct_io: self.config.use_direct_io,
+            block_size: self.config.block_size,
+            prefix_extractor: self.config.prefix_extractor,
+            ..Default::default()
+        };
+        let sstable_path = self.timestamped_path(self.level_dir(level), "sstable", "sst");
+        let temp_dir = match &self.config.temp_dir {
+            Some(temp_dir) if background => temp_dir,
+            _ => self.level_dir(level),
+        };
+        let temp_path = temp_dir
+            .join(sstable_path.file_name().unwrap_or_default())
+            .with_extension(TEMP_SSTABLE_EXTENSION);
+        let mut sstable = SSTable::from_entries_with_options(temp_path, entries, &options)?;
+        sstable.persist_to(&sstable_path)?;
+        debug!(
+            "SSTable created at {:?} with {} entries",
+            sstable_path,
+            sstable.entry_count()
+        );
+        Ok(Arc::new(sstable))
+    }
+
+    /// Delete every key in `range` and physically remove the data from disk
+    ///
+    /// Each live key gets a tombstone, along with its index entries and
+    /// expiry. There is no range tombstone: the live keys are streamed and
+    /// their tombstones committed in bounded batches, so the cost grows with
+    /// the number of keys, and a crash part way leaves only part of the range
+    /// deleted. Keys written to the range after the call returns are kept.
+    /// The MemTable is then flushed, older WAL files are deleted, and
+    /// every SSTable overlapping the range, plus any overlapping those, is
+    /// compacted into the deepest level, where the tombstones and the values
+    /// they cover are dropped. Value log files are garbage collected last.
+    /// Tables still held by a snapshot or iterator are deleted once it ends.
+    ///
+    /// With `secure_delete` every removed file is overwritten first and any
+    /// value log file holding dead values is rewritten; see
+    /// [`PurgeStats::unrecoverable`]. Copy-on-write file systems and flash
+    /// wear leveling may still keep old blocks on the device itself.
+    pub async fn purge_range<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &mut self,
+        range: R,
+    ) -> EngineResult<PurgeStats> {
+        let start = range.start_bound().map(|k| k.as_ref().to_vec());
+        let end = range.end_bound().map(|k| k.as_ref().to_vec());
+        let mut stats = PurgeStats::default();
+        let max_bytes = self.memtable.max_size() / 2;
+        let mut batch = WriteBatch::new();
+        let mut batch_bytes = 0;
+        for pair in self.iter((start.clone(), end.clone())) {
+            let (key, value) = pair?;
+            // As the MemTable counts the tombstone and the index entries and
+            // expiry record it removes
+            batch_bytes += key.len() + 16;
+            for index in &self.indexes {
+                if let Some(index_key) = index.extract(&value) {
+                    batch_bytes += index.entry_key(&index_key, &key).len() + 16;
+                }
+            }
+            if self.expiries.get(&key).is_some() {
+                batch_bytes += expiry::expiry_key(&key).len() + 16;
+            }
+            batch.delete(&key);
+            stats.keys_deleted += 1;
+            if batch.len() >= BULK_BATCH_ENTRIES || batch_bytes >= max_bytes {
+                self.commit_batch(std::mem::take(&mut batch), None).await?;
+                batch_bytes = 0;
+            }
+        }
+        self.commit_batch(batch, None).await?;
+
+        // The flush may compact these away before the purge picks its inputs
+        let mut retired: Vec<Weak<SSTable>> = self
+            .versions
+            .current()
+            .sstables()
+            .filter(|t| {
+                sstable_overlaps(
+                    t,
+                    start.as_ref().map(Vec::as_slice),
+                    end.as_ref().map(Vec::as_slice),
+                )
+            })
+            .map(Arc::downgrade)
+            .collect();
+        let old_wals = list_wal_files(&self.config.data_dir);
+        if self.has_unflushed_writes() {
+            self.flush_memtable(FlushReason::Manual).await?;
+        }
+        self.remove_flushed_wals()?;
+        stats.wal_files_removed = old_wals.iter().filter(|path| !path.exists()).count();
+
+        // Grow the input set until nothing outside it overlaps, so moving the
+        // inputs to the deepest level can't reorder versions of any key
+        let version = self.versions.current();
+        let mut inputs: Vec<Arc<SSTable>> = version
+            .sstables()
+            .filter(|t| {
+                sstable_overlaps(
+                    t,
+                    start.as_ref().map(Vec::as_slice),
+                    end.as_ref().map(Vec::as_slice),
+                )
+            })
+            .cloned()
+            .collect();
+        loop {
+            let first = inputs.iter().filter_map(|t| t.first_key()).min();
+            let last = inputs.iter().filter_map(|t| t.last_key()).max();
+            let (Some(first), Some(last)) = (first, last) else {
+                break;
+            };
+            let (first, last) = (first.to_vec(), last.to_vec());
+            let grown: Vec<Arc<SSTable>> = version
+                .sstables()
+                .filter(|t| {
+                    inputs.iter().any(|i| Arc::ptr_eq(i, t))
+                        || sstable_overlaps(t, Bound::Included(&first), Bound::Included(&last))
+                })
+                .cloned()
+                .collect();
+            if grown.len() == inputs.len() {
+                break;
+            }
+            inputs = grown;
+        }
+
+        retired.extend(inputs.iter().map(Arc::downgrade));
+        if !inputs.is_empty() {
+            let task = CompactionTask {
+                level: 0,
+                output_level: version.levels.len() - 1,
+                score: 0.0,
+                reason: CompactionReason::Manual,
+                inputs,
+            };
+            stats.sstables_rewritten = task.inputs.len();
+            self.run_compaction(&task)?;
+            self.purge_expiries().await?;
+        }
+        drop(version);
+        if !self.blobs.file_ids()?.is_empty() {
+            let ratio = if self.config.secure_delete {
+                1.0
+            } else {
+                self.config.value_log_gc_ratio
+            };
+            stats.value_log = Some(self.collect_value_log_garbage_below(ratio).await?);
+        }
+        stats.unrecoverable = self.config.secure_delete
+            && retired.iter().all(|t| t.strong_count() == 0)
+            && stats
+                .value_log
+                .as_ref()
+                .is_none_or(|gc| gc.files_pinned == 0);
+
+        info!(
+            "Purged {} keys, rewriting {} SSTables (unrecoverable: {})",
+            stats.keys_deleted, stats.sstables_rewritten, stats.unrecoverable
+        );
+        Ok(stats)
+    }
+
+    /// Add a finished job to the persisted history
+    ///
+    /// The history is only a diagnostic aid, so failing to save it is logged
+    /// rather than failing the job.
+    pub(crate) fn record_job(
+        &mut self,
+        kind: JobKind,
+        inputs: &[Arc<SSTable>],
+        outputs: &[Arc<SSTable>],
+        duration: Duration,
+    ) {
+        let names = |tables: &[Arc<SSTable>]| {
+            tables
+                .iter()
+                .filter_map(|t| sstable_file_name(t).map(str::to_string))
+                .collect()
+        };
+        let record = JobRecord {
+            finished_at_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            kind,
+            inputs: names(inputs),
+            outputs: names(outputs),
+            duration,
+        };
+        if let Err(e) = self.history.record(record) {
+            warn!("Failed to save job history: {}", e);
+        }
+    }
+
+    /// Log how `version` changes the SSTable set in the manifest and make it
+    /// current
+    ///
+    /// When the new tables drop the newest flushed writes, e.g. tombstones
+    /// compacted away at the bottom level, the epoch floor is raised to them,
+    /// so the sequence numbers they took still count as flushed on the next
+    /// open and the startup checks don't see a gap before the WAL.
+    pub(crate) fn install_version(&mut self, version: Version) -> EngineResult<()> {
+        if let Some(flushed) = self.versions.current().max_sequence()
+            && version.max_sequence().is_none_or(|max| max < flushed)
+        {
+            self.epoch.sequence_floor = self.epoch.sequence_floor.max(flushed);
+        }
+        self.log_manifest(Manifest::from_version(&version).with_epoch(self.epoch))?;
+        if let Some(cache) = &self.block_cache {
+            for sstable in version.sstables() {
+                sstable.set_block_cache(cache);
+            }
+        }
+        self.versions.install(version);
+        self.check_open_files();
+        Ok(())
+    }
+
+    /// Append the edit to `manifest` to the manifest log
+    ///
+    /// The first call after open writes the whole manifest as a new log.
+    fn log_manifest(&self, manifest: Manifest) -> EngineResult<()> {
+        let mut log = self.manifest.lock().unwrap();
+        match log.as_mut() {
+            Some(log) => log.log(manifest)?,
+            None => *log = Some(ManifestLog::create(&self.config.data_dir, manifest)?),
+        }
+        Ok(())
+    }
+
+    /// Count the files this engine holds open
+    ///
+    /// Each SSTable in the current version keeps its file open, as do the
+    /// active WAL and value log. Tables retired while a snapshot or iterator
+    /// still reads them are only included in `process`.
+    pub fn open_files(&self) -> OpenFiles {
+        OpenFiles {
+            wal: 1,
+            sstables: self.versions.current().sstable_count(),
+            value_log: usize::from(self.blobs.active_file_id().is_some()),
+            process: io_backend::process_open_files(),
+            limit: io_backend::open_file_limit(),
+        }
+    }
+
+    /// Warn once when open files reach [`OPEN_FILES_WARN_PERCENT`] of the OS limit
+    fn check_open_files(&self) {
+        let open_files = self.open_files();
+        let near_limit = open_files.near_limit();
+        if self.open_files_warned.swap(near_limit, Ordering::Relaxed) || !near_limit {
+            return;
+        }
+        warn!(
+            "{} files open against a limit of {} ({} SSTables); compact or raise the limit",
+            open_files.process.unwrap_or(open_files.total()),
+            open_files.limit.unwrap_or_default(),
+            open_files.sstables
+        );
+    }
+
+    /// Flush first if the next entry would not fit in the MemTable
+    ///
+    /// Checking before the WAL append keeps a logged record from being
+    /// rejected by the MemTable afterwards.
+    async fn make_room(&mut self, key_len: usize, value_len: usize) -> EngineResult<()> {
+        if !self.memtable.is_empty() && !self.memtable.has_room_for(key_len, value_len) {
+            self.flush_stalled().await?;
+        }
+        Ok(())
+    }
+
+    /// Flush while a write waits on it, announcing the stall on the event bus
+    ///
+    /// With `max_immutable_memtables` the MemTable is flushed in the
+    /// background instead, and the write only waits if too many are queued.
+    async fn flush_stalled(&mut self) -> EngineResult<()> {
+        if self.flusher.is_some() {
+            return self.flush_in_background();
+        }
+        let reason = StallReason::Flush;
+        self.events.publish(EngineEvent::StallStarted { reason });
+        let started = Instant::now();
+        let flushed = self.flush_memtable(FlushReason::Full).await;
+        self.events.publish(EngineEvent::StallEnded {
+            reason,
+            duration: started.elapsed(),
+        });
+        flushed
+    }
+
+    /// Rotate the WAL file after MemTable flush
+    pub(crate) fn rotate_wal(&mut self) -> EngineResult<()> {
+        let new_wal_path = self.timestamped_path(&self.config.data_dir, "wal", "log");
+
+        // Create new WAL
+        let new_wal = WAL::new(&new_wal_path)?;
+
+        // Records still unsynced in the old WAL are fsynced before it is dropped
+        if let Some(syncer) = &self.wal_syncer {
+            syncer.switch_file(&new_wal)?;
+        } else if self.unsynced_appends.load(Ordering::Relaxed) > 0 {
+            self.sync_wal()?;
+        }
+
+        // Replace old WAL; the old one goes once its writes are flushed
+        let old_wal = std::mem::replace(&mut self.wal, new_wal);
+        self.retired_wals
+            .push((old_wal.path().to_path_buf(), old_wal.sequence_number()));
+
+        info!("WAL rotated to {:?}", new_wal_path);
+        Ok(())
+    }
+
+    /// Directory compaction and imports write their temporary files in
+    fn scratch_dir(&self) -> &Path {
+        self.config
+            .temp_dir
+            .as_deref()
+            .unwrap_or(&self.config.data_dir)
+    }
+
+    /// Directory holding SSTables at `level`
+    pub(crate) fn level_dir(&self, level: usize) -> &Path {
+        self.config
+            .level_dirs
+            .range(..=level)
+            .next_back()
+            .map_or(&self.config.data_dir, |(_, dir)| dir)
+    }
+
+    /// Every directory that may hold SSTables, the data directory first
+    pub(crate) fn sstable_dirs(&self) -> Vec<&Path> {
+        let mut dirs = vec![self.config.data_dir.as_path()];
+        for dir in self.config.level_dirs.values() {
+            if !dirs.contains(&dir.as_path()) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    /// Build a `<prefix>_<millis>.<extension>` path in `dir`.
+    ///
+    /// Several flushes can land in the same millisecond, so the timestamp is
+    /// bumped until the name is unused in every SSTable directory; this keeps
+    /// names unique and ordered.
+    pub(crate) fn timestamped_path(&self, dir: &Path, prefix: &str, extension: &str) -> PathBuf {
+        let mut timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+
+        loop {
+            let name = format!("{}_{}.{}", prefix, timestamp, extension);
+            if !dir.join(&name).exists()
+                && !self.sstable_dirs().iter().any(|d| d.join(&name).exists())
+            {
+                return dir.join(name);
+            }
+            timestamp += 1;
+        }
+    }
+
+    /// Apply the corrupt file policy to an SSTable that failed to open
+    pub(crate) fn handle_corrupt_sstable(
+        &self,
+        path: &Path,
+        error: &SSTableError,
+    ) -> EngineResult<()> {
+        match self.config.corrupt_file_policy {
+            CorruptFilePolicy::Skip => {
+                warn!("Failed to load SSTable {:?}: {}", path, error);
+                self.report_corruption(path, error, None);
+            }
+            CorruptFilePolicy::Quarantine => {
+                let dir = self.config.data_dir.join(CORRUPT_DIR);
+                std::fs::create_dir_all(&dir)?;
+                let target = dir.join(path.file_name().unwrap_or_default());
+                io_backend::move_file(path, &target)?;
+                sync_dir(&dir)?;
+                sync_dir(path.parent().unwrap_or(&self.config.data_dir))?;
+                error!(
+                    "Quarantined unreadable SSTable {:?} to {:?}: {}",
+                    path, target, error
+                );
+                self.report_corruption(path, error, Some(target.clone()));
+                self.quarantined.lock().unwrap().push(target);
+            }
+            CorruptFilePolicy::Strict => {
+                self.report_corruption(path, error, None);
+                return Err(EngineError::RecoveryFailed(format!(
+                    "Unreadable SSTable {:?}: {}",
+                    path, error
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the corrupt file policy to an SSTable a point read failed on
+    ///
+    /// Lazily opened tables are first checked by the read that loads them, so
+    /// this is where they meet the policy startup applies to eager opens. The
+    /// read fails either way rather than fall through to an older version of
+    /// the key. Under [`CorruptFilePolicy::Strict`] the table stays, so every
+    /// read of it fails; otherwise it leaves the current version as if it had
+    /// failed to open, and later reads go on without it.
+    fn handle_unreadable_sstable(
+        &self,
+        sstable: &Arc<SSTable>,
+        error: SSTableError,
+    ) -> EngineError {
+        let path = sstable.path();
+        if !self.unreadable.lock().unwrap().insert(path.to_path_buf()) {
+            return error.into();
+        }
+        error!("Read found unreadable SSTable {:?}: {}", path, error);
+        let handled = match self.config.corrupt_file_policy {
+            CorruptFilePolicy::Strict => {
+                self.report_corruption(path, &error, None);
+                Ok(())
+            }
+            _ => self.drop_unreadable_sstable(sstable, &error),
+        };
+        match handled {
+            Ok(()) => error.into(),
+            Err(e) => e,
+        }
+    }
+
+    /// Remove an SSTable a read found unreadable from the current version and
+    /// the manifest, then skip or quarantine its file
+    fn drop_unreadable_sstable(
+        &self,
+        sstable: &Arc<SSTable>,
+        error: &SSTableError,
+    ) -> EngineResult<()> {
+        let next = self
+            .versions
+            .current()
+            .retain_sstables(|t| !Arc::ptr_eq(t, sstable));
+        self.log_manifest(Manifest::from_version(&next).with_epoch(self.epoch))?;
+        self.versions.install(next);
+        self.handle_corrupt_sstable(sstable.path(), error)
+    }
+
+    /// Announce a corrupt SSTable on the event bus
+    fn report_corruption(
+        &self,
+        path: &Path,
+        error: &SSTableError,
+        quarantined_to: Option<PathBuf>,
+    ) {
+        self.events.publish(EngineEvent::CorruptionDetected {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+            quarantined_to,
+        });
+    }
+
+    /// Seed SSTable read counters from the access profile saved by `close`
+    ///
+    /// The profile is only a hint, so a missing or damaged file is ignored.
+    fn load_access_profile(&mut self) {
+        let Ok(contents) = std::fs::read_to_string(self.config.data_dir.join(ACCESS_PROFILE_FILE))
+        else {
+            return;
+        };
+        let reads: HashMap<&str, u64> = contents
+            .lines()
+            .filter_map(|line| {
+                let (name, count) = line.rsplit_once(' ')?;
+                Some((name, count.parse().ok()?))
+            })
+            .collect();
+
+        for sstable in self.versions.current().sstables() {
+            if let Some(&count) = sstable_file_name(sstable).and_then(|name| reads.get(name)) {
+                sstable.set_read_count(count);
+            }
+        }
+    }
+
+    /// Persist per-SSTable read counts so the next `warm_up` knows the hot tables
+    fn save_access_profile(&self) -> EngineResult<()> {
+        let mut contents = String::new();
+        for sstable in self.versions.current().sstables() {
+            if let Some(name) = sstable_file_name(sstable)
+                && sstable.read_count() > 0
+            {
+                contents.push_str(&format!("{} {}\n", name, sstable.read_count()));
+            }
+        }
+
+        let path = self.config.data_dir.join(ACCESS_PROFILE_FILE);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Load read-path metadata and hot data ahead of the first queries
+    ///
+    /// Bloom filters and indexes of lazily opened SSTables are loaded, and
+    /// every table's are counted; the `warm_up_hot_tables` tables that served the
+    /// most reads according to the persisted access profile are then read
+    /// into the OS page cache. Call it right after open to trade startup time
+    /// for steady read latency.
+    pub fn warm_up(&self) -> EngineResult<WarmUpStats> {
+        let version = self.versions.current();
+        let mut stats = WarmUpStats {
+            sstables: version.sstable_count(),
+            ..Default::default()
+        };
+        for sstable in version.sstables() {
+            sstable.load()?;
+            stats.bloom_filter_bytes += sstable.bloom_filter_size();
+            stats.index_entries += sstable.entry_count() as usize;
+        }
+
+        let mut hot: Vec<&Arc<SSTable>> =
+            version.sstables().filter(|t| t.read_count() > 0).collect();
+        hot.sort_by_key(|t| std::cmp::Reverse(t.read_count()));
+        for sstable in hot.into_iter().take(self.config.warm_up_hot_tables) {
+            stats.bytes_prefetched += sstable.prefetch()?;
+            stats.hot_tables += 1;
+        }
+
+        info!(
+            "Warmed up {} SSTables ({} hot, {} bytes prefetched)",
+            stats.sstables, stats.hot_tables, stats.bytes_prefetched
+        );
+        Ok(stats)
+    }
+
+    /// Pick the most urgent compaction for the current SSTables, if any
+    ///
+    /// Tables a background compaction is merging are never picked.
+    ///
+    /// Pass the task's [`CompactionTask::input_paths`] to a
+    /// [`crate::compaction::CompactionEngine`] instead of choosing inputs by hand.
+    pub fn pick_compaction(&self) -> Option<CompactionTask> {
+        CompactionPicker::new(self.config.compaction)
+            .with_strategy(self.config.compaction_strategy)
+            .pick_excluding(&self.versions.current(), |sstable| {
+                self.compactor
+                    .as_ref()
+                    .is_some_and(|compactor| compactor.is_busy(sstable))
+            })
+    }
+
+    /// Configuration the engine was opened with
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Get database statistics
+    pub fn stats(&self) -> EngineStats {
+        let memtable_size = self.memtable.size_bytes();
+        let version = self.versions.current();
+
+        EngineStats {
+            memtable_size,
+            sstable_count: version.sstable_count(),
+            data_dir: self.config.data_dir.clone(),
+            io_backend: IoBackend::current(),
+            hot_keys: self
+                .hot_keys
+                .as_ref()
+                .map(HotKeyTracker::top)
+                .unwrap_or_default(),
+            quarantined_sstables: self.quarantined.lock().unwrap().clone(),
+            open_files: self.open_files(),
+            compactions_running: self
+                .compactor
+                .as_ref()
+                .map_or(0, BackgroundCompactor::running),
+            immutable_memtables: version.immutables.len(),
+            block_cache: self
+                .block_cache
+                .as_ref()
+                .map(|cache| cache.stats())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Ticket that resolves once every write made so far is durable in the WAL
+    ///
+    /// Under [`SyncPolicy::Interval`] the ticket completes on the next
+    /// background fsync, so concurrent writers share one sync; otherwise the
+    /// WAL is fsynced immediately and the ticket is already complete.
+    pub fn durability_ticket(&self) -> EngineResult<SyncTicket> {
+        match &self.wal_syncer {
+            Some(syncer) => Ok(syncer.ticket()),
+            None => {
+                self.sync_wal()?;
+                Ok(SyncTicket::ready())
+            }
+        }
+    }
+
+    /// Fsync the WAL now, making every write made so far durable
+    ///
+    /// Outstanding [`SyncTicket`]s and [`Engine::wait_durable`] calls resolve
+    /// without waiting for the next background sync.
+    pub fn sync(&self) -> EngineResult<()> {
+        match &self.wal_syncer {
+            Some(syncer) => syncer.sync_now()?,
+            None => self.sync_wal()?,
+        }
+        Ok(())
+    }
+
+    /// Wait until every write up to `sequence` is durable in the WAL
+    ///
+    /// Under [`SyncPolicy::Interval`] this waits for the background fsync that
+    /// covers `sequence`, sharing it with other waiters; otherwise the WAL is
+    /// fsynced right away. Sequences not written yet are rejected.
+    pub async fn wait_durable(&self, sequence: u64) -> EngineResult<()> {
+        if sequence > self.sequence_number() {
+            return Err(EngineError::SequenceNotWritten(sequence));
+        }
+        match &self.wal_syncer {
+            Some(syncer) => syncer.ticket_for(sequence).wait().await?,
+            None => self.sync_wal()?,
+        }
+        Ok(())
+    }
+
+    /// Highest sequence number whose write is durable on disk
+    ///
+    /// Unlike [`Engine::sequence_number`], which counts every accepted write,
+    /// this only moves once a WAL fsync or a flush has made the writes survive
+    /// a crash, so it is what replication and sync layers can safely advertise.
+    pub fn last_durable_sequence(&self) -> u64 {
+        let synced = self
+            .wal_syncer
+            .as_ref()
+            .map_or(0, WalSyncer::durable_sequence);
+        synced.max(self.durable_sequence.load(Ordering::Acquire))
+    }
+
+    /// Fsync the active WAL in the foreground
+    fn sync_wal(&self) -> EngineResult<()> {
+        let sequence = self.sequence_number();
+        self.wal.sync()?;
+        self.unsynced_appends.store(0, Ordering::Relaxed);
+        self.mark_durable(sequence);
+        Ok(())
+    }
+
+    /// Advance the durable watermark to `sequence`, releasing waiters on it
+    pub(crate) fn mark_durable(&self, sequence: u64) {
+        self.durable_sequence.fetch_max(sequence, Ordering::AcqRel);
+        if let Some(syncer) = &self.wal_syncer {
+            syncer.mark_durable(sequence);
+        }
+    }
+
+    /// Change a hot-tunable option without restarting the engine
+    ///
+    /// Accepts the lowercase names of the `RUSTEDGEDB_*` overrides listed in
+    /// [`TUNABLE_OPTIONS`]. A new `memtable_size` applies from the next MemTable;
+    /// a smaller `block_cache_size` evicts blocks right away, and a new
+    /// `compaction_rate_limit` also paces background compactions already running.
+    pub fn set_option(&mut self, name: &str, value: &str) -> EngineResult<()> {
+        if !TUNABLE_OPTIONS.contains(&name) {
+            return Err(EngineError::InvalidConfig(format!(
+                "Option {} cannot be changed at runtime",
+                name
+            )));
+        }
+
+        let mut config = self.config.clone();
+        config.apply_overrides([(
+            format!("{}{}", ENV_PREFIX, name.to_ascii_uppercase()),
+            value,
+        )])?;
+
+        if config.wal_sync_policy != self.config.wal_sync_policy {
+            // Sync before replacing the task so outstanding tickets still resolve
+            if let Some(syncer) = self.wal_syncer.take() {
+                syncer.sync_now()?;
+                self.mark_durable(syncer.durable_sequence());
+            }
+            // Appends the old policy left unsynced don't wait on the new one
+            if self.unsynced_appends.load(Ordering::Relaxed) > 0 {
+                self.sync_wal()?;
+            }
+            if let SyncPolicy::Interval(interval) = config.wal_sync_policy {
+                let sequence = self.sequence_number();
+                self.wal_syncer = Some(WalSyncer::start(&self.wal, interval, sequence)?);
+            }
+        }
+
+        if config.block_cache_size != self.config.block_cache_size {
+            self.resize_block_cache(config.block_cache_size);
+        }
+        if config.background_compaction != self.config.background_compaction
+            && let Some(options) = config.background_compaction
+        {
+            match &mut self.compactor {
+                Some(compactor) => compactor.set_rate_limit(options.rate_limit),
+                None => self.compactor = Some(BackgroundCompactor::new(options)),
+            }
+        }
+
+        self.config = config;
+        info!("Option {} set to {:?}", name, value);
+        Ok(())
+    }
+
+    /// Resize the block cache, creating it for the live tables if there was none
+    fn resize_block_cache(&mut self, capacity: usize) {
+        match &self.block_cache {
+            Some(cache) => cache.set_capacity(capacity),
+            None if capacity > 0 => {
+                let cache = Arc::new(BlockCache::new(capacity));
+                for sstable in self.versions.current().sstables() {
+                    sstable.set_block_cache(&cache);
+                }
+                self.block_cache = Some(cache);
+            }
+            None => {}
+        }
+    }
+
+    /// Read an option or runtime statistic by name
+    ///
+    /// Every name in [`TUNABLE_OPTIONS`] is available, along with
+    /// `memtable_bytes`, `sstable_count`, `data_dir`, `io_backend` and
+    /// `rustedgedb.compaction-history`, the recent flushes and compactions as
+    /// one [`JobRecord`] line each, oldest first.
+    pub fn get_property(&self, name: &str) -> Option<String> {
+        let optional = |value: Option<u64>| value.map_or("none".to_string(), |v| v.to_string());
+        let value = match name {
+            "memtable_size" => self.config.memtable_size.to_string(),
+            "block_cache_size" => self.config.block_cache_size.to_string(),
+            "compaction_rate_limit" => optional(
+                self.config
+                    .background_compaction
+                    .and_then(|options| options.rate_limit),
+            ),
+            "compression" => match self.config.compression {
+                CompressionType::None => "none",
+                CompressionType::LZ4 => "lz4",
+                CompressionType::Zstd => "zstd",
+            }
+            .to_string(),
+            "compression_min_value_size" => {
+                self.config.compression_options.min_value_size.to_string()
+            }
+            "value_log_threshold" => optional(self.config.value_log_threshold.map(|t| t as u64)),
+            "value_log_gc_ratio" => self.config.value_log_gc_ratio.to_string(),
+            "wal_sync_interval_ms" => optional(match self.config.wal_sync_policy {
+                SyncPolicy::Interval(interval) => Some(interval.as_millis() as u64),
+                _ => None,
+            }),
+            "wal_sync_policy" => self.config.wal_sync_policy.to_string(),
+            "memtable_bytes" => self.memtable.size_bytes().to_string(),
+            "sstable_count" => self.versions.current().sstable_count().to_string(),
+            "data_dir" => self.config.data_dir.display().to_string(),
+            "io_backend" => format!("{:?}", IoBackend::current()),
+            "rustedgedb.compaction-history" => self
+                .history
+                .records()
+                .map(|record| format!("{}\n", record))
+                .collect(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Apply the sync policy after a WAL append up to `sequence`
+    fn note_wal_write(&self, sequence: u64) -> EngineResult<()> {
+        match self.config.wal_sync_policy {
+            SyncPolicy::Always => {
+                self.unsynced_appends.fetch_add(1, Ordering::Relaxed);
+                if !self.group_commit {
+                    self.sync_wal()?;
+                }
+            }
+            SyncPolicy::EveryN(appends) => {
+                if self.unsynced_appends.fetch_add(1, Ordering::Relaxed) + 1 >= appends {
+                    self.sync_wal()?;
+                }
+            }
+            SyncPolicy::Interval(_) => {
+                if let Some(syncer) = &self.wal_syncer {
+                    syncer.note_write(sequence);
+                }
+            }
+            SyncPolicy::OsBuffered => {}
+        }
+        Ok(())
+    }
+
+    /// Hold back the fsync [`SyncPolicy::Always`] makes after each append
+    /// until [`Engine::commit_group`]
+    pub(crate) fn begin_group_commit(&mut self) {
+        self.group_commit = true;
+    }
+
+    /// Fsync the appends made since [`Engine::begin_group_commit`] at once,
+    /// so a group of writes pays for a single fsync
+    pub(crate) fn commit_group(&mut self) -> EngineResult<()> {
+        self.group_commit = false;
+        if self.unsynced_appends.load(Ordering::Relaxed) > 0
+            && self.config.wal_sync_policy == SyncPolicy::Always
+        {
+            self.sync_wal()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the active or an immutable MemTable holds writes not yet in a table
+    fn has_unflushed_writes(&self) -> bool {
+        !self.memtable.is_empty() || !self.versions.current().immutables.is_empty()
+    }
+
+    /// Force flush of MemTable (useful for testing and shutdown)
+    ///
+    /// Also collects the value log when compactions have made that due.
+    pub async fn force_flush(&mut self) -> EngineResult<()> {
+        if self.has_unflushed_writes() {
+            self.flush_memtable(FlushReason::Manual).await?;
+        }
+        self.collect_value_log_garbage_if_due().await
+    }
+
+    /// Close the engine and flush any remaining data
+    ///
+    /// Runs the compaction set by `EngineConfig::compact_on_close`.
+    pub async fn close(&mut self) -> EngineResult<()> {
+        self.close_with(self.config.compact_on_close).await
+    }
+
+    /// Close the engine, running `compaction` after the final flush
+    pub async fn close_with(&mut self, compaction: CompactOnClose) -> EngineResult<()> {
+        info!("Closing Engine");
+
+        // Force flush any remaining data
+        self.force_flush().await?;
+        self.wait_for_compactions()?;
+
+        match compaction {
+            CompactOnClose::Never => {}
+            CompactOnClose::Level0 => {
+                let picker = CompactionPicker::new(self.config.compaction);
+                if let Some(task) = picker.pick_level0(&self.versions.current()) {
+                    self.run_compaction(&task)?;
+                }
+            }
+            CompactOnClose::Full => {
+                self.compact_all().await?;
+            }
+        }
+
+        if let Some(syncer) = &self.wal_syncer {
+            syncer.sync_now()?;
+        }
+        self.save_access_profile()?;
+
+        info!("Engine closed successfully");
+        Ok(())
+    }
+}
+
+/// File name an SSTable is recorded under in the access profile
+pub(crate) fn sstable_file_name(sstable: &SSTable) -> Option<&str> {
+    sstable.path().file_name().and_then(|name| name.to_str())
+}
+
+/// Newest entry for a key in `version`, including tombstones
+///
+/// Merge operands are folded into older versions until one that isn't an
+/// operand is found.
+pub(crate) fn find_entry_in(
+    version: &Version,
+    blobs: &BlobStore,
+    key: &[u8],
+    unreadable: impl Fn(&Arc<SSTable>, SSTableError) -> EngineError,
+) -> EngineResult<Option<Entry>> {
+    // First, check the MemTables (most recent data), newest first
+    let mut found = None;
+    for memtable in version.memtables() {
+        if found.as_ref().is_some_and(|entry: &Entry| !entry.merge) {
+            return Ok(found);
+        }
+        if let Some(entry) = memtable.get_entry(key)? {
+            found = Some(match found {
+                Some(newer) => merge::fold(entry, newer, |base| read_base(blobs, base))?,
+                None => entry,
+            });
+        }
+    }
+
+    // Then check SSTables level by level, newest first
+    for sstable in version.sstables_for_key(key) {
+        if found.as_ref().is_some_and(|entry| !entry.merge) {
+            return Ok(found);
+        }
+        let entry = sstable.get_entry(key).map_err(|e| unreadable(sstable, e))?;
+        if let Some(entry) = entry {
+            found = Some(match found {
+                Some(newer) => merge::fold(entry, newer, |base| read_base(blobs, base))?,
+                None => entry,
+            });
+        }
+    }
+
+    Ok(found.map(merge::resolve).transpose()?)
+}
+
+/// At most `len` bytes of `value` from `offset`, clamped to its length
+fn slice_value(value: &Bytes, offset: u64, len: u64) -> Bytes {
+    let start = offset.min(value.len() as u64) as usize;
+    let len = len.min((value.len() - start) as u64) as usize;
+    value.slice(start..start + len)
+}
+
+/// Check whether `key` falls between two borrowed bounds
+pub(crate) fn key_in_range(start: Bound<&[u8]>, end: Bound<&[u8]>, key: &[u8]) -> bool {
+    RangeBounds::<[u8]>::contains(&(start, end), key)
+}
+
+/// Check whether an SSTable's key range can intersect the given bounds
+pub(crate) fn sstable_overlaps(sstable: &SSTable, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    let (Some(first), Some(last)) = (sstable.first_key(), sstable.last_key()) else {
+        return false;
+    };
+    let after_start = match start {
+        Bound::Included(key) => last >= key,
+        Bound::Excluded(key) => last > key,
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(key) => first <= key,
+        Bound::Excluded(key) => first < key,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+/// Streams a large value to disk for [`Engine::put_writer`]
+pub struct PutWriter<'a> {
+    engine: &'a mut Engine,
+    key: Vec<u8>,
+    blob: BlobWriter,
+}
+
+impl PutWriter<'_> {
+    /// Sync the streamed value and make the key visible, returning the
+    /// sequence number of the write
+    pub async fn finish(self) -> EngineResult<u64> {
+        let pointer = self.blob.finish()?;
+        self.engine.put_indirect(&self.key, pointer, None).await
+    }
+}
+
+impl Write for PutWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.blob.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.blob.flush()
+    }
+}
+
+/// A record of a write batch before it is numbered
+struct PendingWrite {
+    key: Vec<u8>,
+    value: Option<Bytes>,
+    /// Whether a large value may move to the value log, as for user puts
+    separable: bool,
+    indirect: bool,
+}
+
+impl PendingWrite {
+    fn value(key: Vec<u8>, value: Option<Bytes>) -> Self {
+        Self {
+            key,
+            value,
+            separable: true,
+            indirect: false,
+        }
+    }
+
+    /// Expiry records and stale index deletes, always stored inline
+    fn internal(key: Vec<u8>, value: Option<Bytes>) -> Self {
+        Self {
+            separable: false,
+            ..Self::value(key, value)
+        }
+    }
+
+    fn is_separated(&self, threshold: Option<usize>) -> bool {
+        self.separable
+            && threshold
+                .is_some_and(|threshold| self.value.as_ref().is_some_and(|v| v.len() >= threshold))
+    }
+
+    /// MemTable bytes the record takes once logged, as [`Entry::size_bytes`]
+    fn size_bytes(&self, threshold: Option<usize>) -> usize {
+        let value_len = if self.is_separated(threshold) {
+            BlobPointer::ENCODED_LEN
+        } else {
+            self.value.as_ref().map_or(0, |v| v.len())
+        };
+        self.key.len() + value_len + 16
+    }
+}
+
+/// A value and the version metadata stored with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionedValue {
+    pub value: Bytes,
+    /// Write time in milliseconds since the Unix epoch, or the caller-supplied timestamp
+    pub timestamp: u64,
+    /// Engine-wide sequence number of the write
+    pub sequence_number: u64,
+}
+
+/// One page of a paginated scan
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanPage {
+    /// Live key-value pairs on this page, in key order
+    pub entries: Vec<(Vec<u8>, Bytes)>,
+    /// Token for the next page, or None once the range is exhausted
+    pub next_token: Option<String>,
+}
+
+/// Continuation state carried between pages of a scan
+///
+/// Encoded as hex: version (1) + last key. Version 1 tokens also carried the
+/// sequence number of the first page, which no read ever used; they are still
+/// accepted, and the sequence is ignored.
+#[derive(Debug, Clone, PartialEq)]
+struct ScanToken {
+    /// Last key returned on the previous page
+    last_key: Vec<u8>,
+}
+
+impl ScanToken {
+    const VERSION: u8 = 2;
+    /// Version whose tokens carry an 8-byte sequence number before the key
+    const SEQUENCE_VERSION: u8 = 1;
+
+    fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(1 + self.last_key.len());
+        bytes.push(Self::VERSION);
+        bytes.extend_from_slice(&self.last_key);
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn decode(token: &str) -> EngineResult<Self> {
+        let invalid = |reason: &str| EngineError::InvalidToken(reason.to_string());
+        if !token.is_ascii() || !token.len().is_multiple_of(2) {
+            return Err(invalid("not a hex string"));
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid("not a hex string"))?;
+
+        let key_offset = match bytes.first() {
+            Some(&Self::VERSION) => 1,
+            Some(&Self::SEQUENCE_VERSION) => 9,
+            Some(_) => return Err(invalid("unsupported token version")),
+            None => return Err(invalid("token too short")),
+        };
+        if bytes.len() < key_offset {
+            return Err(invalid("token too short"));
+        }
+        Ok(Self {
+            last_key: bytes[key_offset..].to_vec(),
+        })
+    }
+}
+
+/// Outcome of a retention pass
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct RetentionStats {
+    /// SSTables deleted because every key in them was expired
+    pub sstables_dropped: usize,
+    /// Expired keys that had to be deleted individually
+    pub keys_deleted: usize,
+}
+
+/// What [`Engine::purge_range`] removed
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct PurgeStats {
+    /// Live keys tombstoned
+    pub keys_deleted: usize,
+    /// SSTables compacted to drop the purged data
+    pub sstables_rewritten: usize,
+    /// Older WAL files deleted after the flush
+    pub wal_files_removed: usize,
+    /// Value log collection, when the database has a value log
+    pub value_log: Option<ValueLogGcStats>,
+    /// Whether every file that held the purged data has been shredded, so the
+    /// keys can't be recovered from the data directory. Requires
+    /// `secure_delete`; false while a snapshot or iterator still holds a
+    /// rewritten table or a pinned value log file kept dead values.
+    pub unrecoverable: bool,
+}
+
+/// Outcome of a maintenance pass
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct MaintenanceStats {
+    /// Result of the retention sweep, when one ran
+    pub retention: Option<RetentionStats>,
+    /// Compactions run
+    pub compactions: usize,
+    /// Legacy SSTables whose metadata was rebuilt
+    pub metadata_rebuilds: usize,
+    /// Result of the scrub, when one ran
+    pub scrub: Option<ScrubStats>,
+    /// Stats snapshot written at the end of the pass
+    pub stats_snapshot: Option<PathBuf>,
+}
+
+/// What [`Engine::scrub`] checked
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct ScrubStats {
+    pub sstables_checked: usize,
+    pub bytes_checked: u64,
+    /// Tables that failed verification, at the paths they had when checked
+    pub corrupt_sstables: Vec<PathBuf>,
+}
+
+/// Outcome of a compaction
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct CompactionStats {
+    /// SSTables merged
+    pub input_sstables: usize,
+    /// SSTables written
+    pub output_sstables: usize,
+    /// Entries in the output tables
+    pub entries_written: usize,
+    /// Overwritten versions and tombstones left out of the output
+    pub entries_dropped: usize,
+    /// Bytes of value log values whose pointers were left out of the output,
+    /// now garbage for [`Engine::collect_value_log_garbage`]
+    pub blob_bytes_dropped: u64,
+}
+
+/// Outcome of a value log garbage collection pass
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct ValueLogGcStats {
+    /// Blob files deleted, including rewritten ones
+    pub files_deleted: usize,
+    /// Files whose live values were moved before deletion
+    pub files_rewritten: usize,
+    /// Bytes of dead values reclaimed
+    pub bytes_reclaimed: u64,
+    /// Files holding dead values that were kept because merge operands still
+    /// apply to one of their live values, or a live iterator may read them
+    pub files_pinned: usize,
+}
+
+/// Database statistics
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct EngineStats {
+    pub memtable_size: usize,
+    pub sstable_count: usize,
+    pub data_dir: PathBuf,
+    /// I/O backend selected for WAL appends and SSTable reads
+    pub io_backend: IoBackend,
+    /// Most accessed keys, hottest first, when hot-key tracking is on
+    pub hot_keys: Vec<HotKey>,
+    /// SSTables quarantined at startup or by scrubbing, at their new paths
+    pub quarantined_sstables: Vec<PathBuf>,
+    /// Files held open by the engine and the process
+    pub open_files: OpenFiles,
+    /// Background compactions not yet installed
+    pub compactions_running: usize,
+    /// Full MemTables waiting for a background flush
+    pub immutable_memtables: usize,
+    /// Block cache usage and hits; all zero when the cache is disabled
+    pub block_cache: BlockCacheStats,
+}
+
+/// Share of the open file limit at which the engine logs a warning
+pub const OPEN_FILES_WARN_PERCENT: u64 = 80;
+
+/// Open file handles, from [`Engine::open_files`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct OpenFiles {
+    /// Active write-ahead log
+    pub wal: usize,
+    /// SSTables in the current version
+    pub sstables: usize,
+    /// Active value log file
+    pub value_log: usize,
+    /// Descriptors open in the whole process, where the OS lists them
+    pub process: Option<usize>,
+    /// Soft limit on open descriptors, where the OS reports one
+    pub limit: Option<u64>,
+}
+
+impl OpenFiles {
+    /// Files held open by the engine
+    pub fn total(&self) -> usize {
+        self.wal + self.sstables + self.value_log
+    }
+
+    /// Whether open files reached [`OPEN_FILES_WARN_PERCENT`] of the limit
+    pub fn near_limit(&self) -> bool {
+        let open = self.process.unwrap_or(self.total()) as u64;
+        self.limit
+            .is_some_and(|limit| open * 100 >= limit * OPEN_FILES_WARN_PERCENT)
+    }
+}
+
+/// What [`Engine::export_archive`] wrote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArchiveStats {
+    pub sstables: usize,
+    pub blob_files: usize,
+    /// Size of the archive file
+    pub archive_bytes: u64,
+}
+
+/// What [`Engine::import_archive`] and [`Engine::merge_archive`] did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportStats {
+    /// Files unpacked and their total size
+    pub files: usize,
+    pub bytes: u64,
+    /// Keys merged into an existing database
+    pub keys_ingested: usize,
+}
+
+/// What [`Engine::checkpoint`] wrote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckpointStats {
+    pub sstables: usize,
+    pub wal_files: usize,
+    pub blob_files: usize,
+    /// SSTables and value log files hard-linked instead of copied
+    pub linked_files: usize,
+    /// Bytes copied, not counting hard-linked files
+    pub bytes_copied: u64,
+}
+
+/// What [`Engine::warm_up`] loaded
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmUpStats {
+    /// SSTables whose bloom filter and index are resident
+    pub sstables: usize,
+    pub bloom_filter_bytes: usize,
+    pub index_entries: usize,
+    /// Hot SSTables read into the page cache
+    pub hot_tables: usize,
+    pub bytes_prefetched: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveReader;
+    use crate::batch::BatchLimits;
+    use crate::sstable::SSTableBuilder;
+    use crate::wal::{RecordType, WalDumpItem};
+    use tempfile::tempdir;
+
+    async fn create_test_engine() -> (Engine, tempfile::TempDir) {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        (engine, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_basic_operations() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        // Test put and get
+        engine.put(b"key1", b"value1").await.unwrap();
+        let value = engine.get(b"key1").await.unwrap();
+        assert_eq!(value, Some(Bytes::from_static(b"value1")));
+
+        // Test delete
+        engine.delete(b"key1").await.unwrap();
+        let value = engine.get(b"key1").await.unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[tokio::test]
+    async fn test_persistence() {
+        let temp_dir = tempdir().unwrap();
+        let engine_path = temp_dir.path();
+
+        // Create engine and add data
+        {
+            let mut engine = Engine::new(engine_path).await.unwrap();
+            engine
+                .put(b"persistent_key", b"persistent_value")
+                .await
+                .unwrap();
+            engine.put(b"another_key", b"another_value").await.unwrap();
+            engine.close().await.unwrap();
+        }
+
+        // Reopen engine and verify data persists
+        let engine = Engine::new(engine_path).await.unwrap();
+        let value1 = engine.get(b"persistent_key").await.unwrap();
+        let value2 = engine.get(b"another_key").await.unwrap();
+
+        assert_eq!(value1, Some(Bytes::from_static(b"persistent_value")));
+        assert_eq!(value2, Some(Bytes::from_static(b"another_value")));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_point_reads() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+        for i in 0..200u32 {
+            engine
+                .put(format!("key{:03}", i).as_bytes(), &i.to_le_bytes())
+                .await
+                .unwrap();
+            if i % 50 == 49 {
+                engine.force_flush().await.unwrap();
+            }
+        }
+
+        // Point lookups only need `&self`, so readers share the engine
+        let engine = Arc::new(engine);
+        let readers: Vec<_> = (0..8u32)
+            .map(|reader| {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    for i in (0..200u32).map(|i| (i + reader * 37) % 200) {
+                        let value = engine.get(format!("key{:03}", i).as_bytes()).await;
+                        assert_eq!(
+                            value.unwrap(),
+                            Some(Bytes::copy_from_slice(&i.to_le_bytes()))
+                        );
+                    }
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lazy_open_sstables() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for i in 0..3 {
+            engine
+                .put(format!("key{}", i).as_bytes(), b"value")
+                .await
+                .unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        engine.close().await.unwrap();
+
+        let engine = Engine::with_config(EngineConfig {
+            lazy_open_sstables: true,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.sstable_count(), 3);
+        assert!(version.sstables().all(|t| !t.is_loaded()));
+
+        // Warming up loads what lazy opening deferred
+        let warm = Engine::with_config(EngineConfig {
+            lazy_open_sstables: true,
+            ..config
+        })
+        .await
+        .unwrap();
+        assert!(warm.warm_up().unwrap().bloom_filter_bytes > 0);
+        assert!(warm.versions.current().sstables().all(|t| t.is_loaded()));
+        drop(warm);
+
+        // Compaction loads the indexes it reads from
+        let mut engine = engine;
+        let stats = engine.compact_all().await.unwrap();
+        assert_eq!(stats.entries_written, 3);
+        for i in 0..3 {
+            assert!(
+                engine
+                    .get(format!("key{}", i).as_bytes())
+                    .await
+                    .unwrap()
+                    .is_some()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lazy_open_applies_corrupt_file_policy_on_read() {
+        // Two versions of a key, the newer in a table lazily opened with a
+        // flipped byte that only its checksum catches
+        let open_corrupted = async |dir: &Path, policy: CorruptFilePolicy| {
+            let config = EngineConfig {
+                data_dir: dir.to_path_buf(),
+                lazy_open_sstables: true,
+                corrupt_file_policy: policy,
+                env_overrides: false,
+                ..Default::default()
+            };
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            engine.put(b"k", b"value-one-old").await.unwrap();
+            engine.force_flush().await.unwrap();
+            engine.put(b"k", b"value-two-new").await.unwrap();
+            engine.force_flush().await.unwrap();
+            let newest = engine.versions.current().levels[0][0].path().to_path_buf();
+            engine.close().await.unwrap();
+            drop(engine);
+
+            let mut bytes = std::fs::read(&newest).unwrap();
+            let at = bytes
+                .windows(13)
+                .position(|w| w == b"value-two-new")
+                .unwrap();
+            bytes[at] ^= 0xff;
+            std::fs::write(&newest, bytes).unwrap();
+            (Engine::with_config(config).await.unwrap(), newest)
+        };
+
+        // Strict fails every read of the key the table shadows
+        let temp_dir = tempdir().unwrap();
+        let (engine, newest) = open_corrupted(temp_dir.path(), CorruptFilePolicy::Strict).await;
+        let mut events = engine.subscribe();
+        for _ in 0..2 {
+            assert!(matches!(
+                engine.get(b"k").await,
+                Err(EngineError::SSTable(SSTableError::CorruptedFile(_)))
+            ));
+            assert!(matches!(
+                engine.get_range_of_value(b"k", 0, 5).await,
+                Err(EngineError::SSTable(SSTableError::CorruptedFile(_)))
+            ));
+        }
+        assert_eq!(engine.stats().sstable_count, 2);
+        // The bus hears about it once, however many reads fail
+        assert!(matches!(
+            events.try_recv(),
+            Ok(EngineEvent::CorruptionDetected { path, quarantined_to: None, .. }) if path == newest
+        ));
+        assert!(events.try_recv().is_err());
+        drop(engine);
+        assert!(newest.exists());
+
+        // Skip fails the read that found it; later reads go on without it
+        let temp_dir = tempdir().unwrap();
+        let (engine, newest) = open_corrupted(temp_dir.path(), CorruptFilePolicy::Skip).await;
+        assert!(engine.get(b"k").await.is_err());
+        assert_eq!(
+            engine.get(b"k").await.unwrap(),
+            Some(Bytes::from_static(b"value-one-old"))
+        );
+        assert_eq!(engine.stats().sstable_count, 1);
+        drop(engine);
+        assert!(newest.exists());
+
+        // Quarantine also moves the file aside, and the manifest forgets it
+        let temp_dir = tempdir().unwrap();
+        let (engine, newest) = open_corrupted(temp_dir.path(), CorruptFilePolicy::Quarantine).await;
+        let mut events = engine.subscribe();
+        assert!(engine.get_range_of_value(b"k", 0, 5).await.is_err());
+        assert_eq!(
+            engine.get_range_of_value(b"k", 0, 5).await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+        let moved = temp_dir
+            .path()
+            .join(CORRUPT_DIR)
+            .join(newest.file_name().unwrap());
+        assert_eq!(engine.stats().quarantined_sstables, vec![moved.clone()]);
+        assert!(moved.exists() && !newest.exists());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(EngineEvent::CorruptionDetected { quarantined_to: Some(target), .. }) if target == moved
+        ));
+        drop(engine);
+        let engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            corrupt_file_policy: CorruptFilePolicy::Strict,
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            engine.get(b"k").await.unwrap(),
+            Some(Bytes::from_static(b"value-one-old"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reads_skip_tables_outside_key_range() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for prefix in ["a", "m", "x"] {
+            for i in 0..10 {
+                let key = format!("{}{:02}", prefix, i);
+                engine.put(key.as_bytes(), b"value").await.unwrap();
+            }
+            engine.force_flush().await.unwrap();
+        }
+        engine.close().await.unwrap();
+
+        // Lazily opened tables load their index and bloom filter only when probed
+        let engine = Engine::with_config(EngineConfig {
+            lazy_open_sstables: true,
+            ..config
+        })
+        .await
+        .unwrap();
+        let loaded = |first: &[u8]| {
+            engine
+                .versions
+                .current()
+                .sstables()
+                .find(|t| t.first_key() == Some(first))
+                .unwrap()
+                .is_loaded()
+        };
+
+        assert!(engine.get(b"m05").await.unwrap().is_some());
+        assert!(engine.get(b"b").await.unwrap().is_none());
+        assert!(engine.get(b"z").await.unwrap().is_none());
+        assert!(loaded(b"m00"));
+        assert!(!loaded(b"a00") && !loaded(b"x00"));
+
+        let keys = engine.scan(b"w".as_slice()..).await.unwrap();
+        assert_eq!(keys.len(), 10);
+        assert!(loaded(b"x00"));
+        assert!(!loaded(b"a00"));
+    }
+
+    #[tokio::test]
+    async fn test_prefix_bloom_skips_tables() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            prefix_extractor: Some(PrefixExtractor::Delimiter(b'/')),
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        // Both tables span "c/", only the first holds it
+        for keys in [["a/1", "c/1", "e/1"], ["a/2", "b/2", "e/2"]] {
+            for key in keys {
+                engine.put(key.as_bytes(), b"value").await.unwrap();
+            }
+            engine.force_flush().await.unwrap();
+        }
+
+        let limit = keys::prefix_end(b"c/").unwrap();
+        let (start, end) = (
+            Bound::Included(&b"c/"[..]),
+            Bound::Excluded(limit.as_slice()),
+        );
+        let version = engine.versions.current();
+        let read: Vec<_> = version
+            .sstables()
+            .filter(|t| engine.scan_reads(t, start, end))
+            .map(|t| t.first_key().unwrap().to_vec())
+            .collect();
+        assert_eq!(read, [b"a/1".to_vec()]);
+        assert_eq!(engine.scan_range(start, end).unwrap().len(), 1);
+
+        // Ranges spanning several prefixes read every overlapping table
+        let wide = (Bound::Included(&b"b/"[..]), end);
+        assert_eq!(engine.scan_range(wide.0, wide.1).unwrap().len(), 2);
+        assert!(
+            version
+                .sstables()
+                .all(|t| engine.scan_reads(t, wide.0, wide.1))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_size_config() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            block_size: 256,
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        for i in 0..200u32 {
+            let key = format!("key{:04}", i);
+            engine.put(key.as_bytes(), &[i as u8; 16]).await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+
+        let version = engine.versions.current();
+        let sstable = version.sstables().next().unwrap();
+        let blocks = sstable.block_handles().unwrap();
+        assert!(blocks.len() > 10);
+        assert!(
+            blocks
+                .iter()
+                .all(|b| b.size as usize >= 256 || b.offset + b.size as u64 == sstable.data_size())
+        );
+        for i in (0..200u32).step_by(17) {
+            let key = format!("key{:04}", i);
+            assert_eq!(
+                engine.get(key.as_bytes()).await.unwrap(),
+                Some(Bytes::from(vec![i as u8; 16]))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_cache() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            block_size: 256,
+            block_cache_size: 1024,
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for i in 0..100u32 {
+            let key = format!("key{:03}", i);
+            engine.put(key.as_bytes(), &[i as u8; 32]).await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+
+        // The first get reads its block from disk, the second from the cache
+        assert!(engine.get(b"key042").await.unwrap().is_some());
+        let stats = engine.stats().block_cache;
+        assert_eq!((stats.hits, stats.misses, stats.blocks), (0, 1, 1));
+        assert!(engine.get(b"key043").await.unwrap().is_some());
+        assert_eq!(engine.stats().block_cache.hits, 1);
+
+        // Reading every block keeps the cache within its budget
+        for i in 0..100u32 {
+            let key = format!("key{:03}", i);
+            assert!(engine.get(key.as_bytes()).await.unwrap().is_some());
+        }
+        let stats = engine.stats().block_cache;
+        assert!(stats.used_bytes <= 1024 && stats.used_bytes > 0);
+        assert_eq!(stats.hits + stats.misses, 102);
+        engine.close().await.unwrap();
+
+        let engine = Engine::with_config(EngineConfig {
+            block_cache_size: 0,
+            ..config
+        })
+        .await
+        .unwrap();
+        assert!(engine.get(b"key042").await.unwrap().is_some());
+        assert_eq!(engine.stats().block_cache, BlockCacheStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_scrub() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for key in [b"apple", b"grape", b"melon"] {
+            engine.put(key, b"fresh").await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        let stats = engine.scrub(u64::MAX).unwrap();
+        assert_eq!(stats.sstables_checked, 3);
+        assert!(stats.corrupt_sstables.is_empty());
+
+        // Small budgets resume where the last pass stopped
+        let mut checked = HashSet::new();
+        for _ in 0..3 {
+            let stats = engine.scrub(1).unwrap();
+            assert_eq!(stats.sstables_checked, 1);
+            checked.insert(engine.scrub_cursor.clone().unwrap());
+        }
+        assert_eq!(checked.len(), 3);
+
+        // Flip a byte of one key in its data section
+        let version = engine.versions.current();
+        let victim = version
+            .sstables()
+            .find(|t| t.get(b"grape").unwrap().is_some())
+            .unwrap()
+            .path()
+            .to_path_buf();
+        drop(version);
+        let mut contents = std::fs::read(&victim).unwrap();
+        let at = contents.windows(5).position(|w| w == b"grape").unwrap();
+        contents[at] = b'G';
+        std::fs::write(&victim, contents).unwrap();
+
+        // Skip only reports it
+        let stats = engine.scrub(u64::MAX).unwrap();
+        assert_eq!(stats.corrupt_sstables, vec![victim.clone()]);
+        assert_eq!(engine.stats().sstable_count, 3);
+
+        engine.config.corrupt_file_policy = CorruptFilePolicy::Quarantine;
+        let stats = engine.scrub(u64::MAX).unwrap();
+        assert_eq!(stats.corrupt_sstables, vec![victim.clone()]);
+        let moved = temp_dir
+            .path()
+            .join(CORRUPT_DIR)
+            .join(victim.file_name().unwrap());
+        assert_eq!(engine.stats().quarantined_sstables, vec![moved.clone()]);
+        assert!(moved.exists() && !victim.exists());
+        assert_eq!(engine.stats().sstable_count, 2);
+        assert!(engine.get(b"apple").await.unwrap().is_some());
+        assert!(engine.scrub(u64::MAX).unwrap().corrupt_sstables.is_empty());
+        drop(engine);
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_engine_events() {
+        let temp_dir = tempdir().unwrap();
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 1024,
+            corrupt_file_policy: CorruptFilePolicy::Quarantine,
+            events: Some(bus),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        let garbage = engine
+            .versions
+            .current()
+            .sstables()
+            .next()
+            .unwrap()
+            .path()
+            .to_path_buf();
+        drop(engine);
+        std::fs::write(&garbage, b"not an sstable").unwrap();
+        while events.try_recv().is_ok() {}
+
+        // Subscribing through the config sees what startup found
+        let mut engine = Engine::with_config(config).await.unwrap();
+        match events.try_recv().unwrap() {
+            EngineEvent::CorruptionDetected {
+                path,
+                quarantined_to,
+                ..
+            } => {
+                assert_eq!(path, garbage);
+                assert!(
+                    quarantined_to
+                        .unwrap()
+                        .starts_with(temp_dir.path().join(CORRUPT_DIR))
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // A full MemTable stalls the write that fills it until it is flushed
+        let mut later = engine.subscribe();
+        for i in 0..20 {
+            engine
+                .put(format!("key{:02}", i).as_bytes(), &[b'v'; 100])
+                .await
+                .unwrap();
+        }
+        let mut seen = Vec::new();
+        while let Ok(event) = later.try_recv() {
+            seen.push(event);
+        }
+        assert!(matches!(
+            seen[..3],
+            [
+                EngineEvent::StallStarted {
+                    reason: StallReason::Flush
+                },
+                EngineEvent::FlushFinished {
+                    reason: FlushReason::Full,
+                    sstables: 1,
+                    ..
+                },
+                EngineEvent::StallEnded {
+                    reason: StallReason::Flush,
+                    ..
+                },
+            ]
+        ));
+
+        let stats = engine.compact_all().await.unwrap();
+        let Ok(EngineEvent::CompactionFinished {
+            reason,
+            stats: published,
+            ..
+        }) = later.try_recv()
+        else {
+            panic!("expected a compaction event");
+        };
+        assert_eq!(reason, CompactionReason::Manual);
+        assert_eq!(published, stats);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_history() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        assert_eq!(
+            engine
+                .get_property("rustedgedb.compaction-history")
+                .as_deref(),
+            Some("")
+        );
+        engine.put(b"a", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"b", b"2").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.compact_all().await.unwrap();
+        drop(engine);
+
+        // The history is read back from disk on open
+        let engine = Engine::with_config(config).await.unwrap();
+        let history = engine
+            .get_property("rustedgedb.compaction-history")
+            .unwrap();
+        let records: Vec<JobRecord> = history
+            .lines()
+            .map(|l| JobRecord::parse(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].kind, JobKind::Flush(FlushReason::Manual));
+        assert_eq!(records[0].outputs.len(), 1);
+        let flushed: Vec<String> = records[..2]
+            .iter()
+            .flat_map(|r| r.outputs.clone())
+            .collect();
+        let JobKind::Compaction { reason, .. } = records[2].kind else {
+            panic!("expected a compaction record");
+        };
+        assert_eq!(reason, CompactionReason::Manual);
+        let mut inputs = records[2].inputs.clone();
+        inputs.sort();
+        let mut expected = flushed;
+        expected.sort();
+        assert_eq!(inputs, expected);
+        assert!(!records[2].outputs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_legacy_sstables() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            maintenance: Some(MaintenanceOptions {
+                max_compactions: 0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"key", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+        let oldest = engine.versions.current().levels[0][1].path().to_path_buf();
+        drop(engine);
+
+        // Point the older table's bloom filter offset at its data section
+        let mut bytes = std::fs::read(&oldest).unwrap();
+        let data_offset = bytes[32..40].to_vec();
+        bytes[24..32].copy_from_slice(&data_offset);
+        std::fs::write(&oldest, bytes).unwrap();
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let stats = engine.run_maintenance().await.unwrap();
+        assert_eq!(stats.metadata_rebuilds, 1);
+        #[cfg(feature = "stats")]
+        {
+            let snapshot = stats.stats_snapshot.as_ref().unwrap();
+            assert!(snapshot.starts_with(temp_dir.path().join(STATS_DIR)));
+            let json: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(snapshot).unwrap()).unwrap();
+            assert_eq!(json["stats"]["sstable_count"], 2);
+            assert_eq!(json["maintenance"]["metadata_rebuilds"], 1);
+        }
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 2);
+        assert_ne!(version.levels[0][1].path(), oldest);
+        assert!(version.levels[0][1].bloom_filter_size() > 0);
+        drop(version);
+        assert_eq!(engine.get(b"key").await.unwrap().unwrap().as_ref(), b"new");
+        assert_eq!(engine.rebuild_legacy_sstables(usize::MAX).unwrap(), 0);
+        drop(engine);
+
+        assert!(!oldest.exists());
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 2);
+        assert_eq!(engine.get(b"key").await.unwrap().unwrap().as_ref(), b"new");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_writes_keep_their_sequence_numbers() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            startup_checks: StartupCheckPolicy::Fail,
+            ..Default::default()
+        };
+
+        // The tombstone, the newest flushed write, is compacted away
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"1").await.unwrap();
+        engine.delete(b"key").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.compact_all().await.unwrap();
+        assert_eq!(engine.versions.current().max_sequence(), None);
+        engine.put(b"other", b"3").await.unwrap();
+        drop(engine);
+
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.get(b"other").await.unwrap(), Some(Bytes::from("3")));
+        assert_eq!(engine.get(b"key").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_compact_on_open() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            compaction: CompactionOptions {
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for i in 0..6 {
+            engine
+                .put(format!("key{}", i).as_bytes(), b"value")
+                .await
+                .unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        drop(engine);
+
+        let engine = Engine::with_config(EngineConfig {
+            compact_on_open: CompactOnOpen::Capped(1),
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 0);
+        assert_eq!(version.levels[1].len(), 1);
+        drop(engine);
+
+        let mut overrides = config.clone();
+        overrides
+            .apply_overrides([("RUSTEDGEDB_COMPACT_ON_OPEN", "full")])
+            .unwrap();
+        assert_eq!(overrides.compact_on_open, CompactOnOpen::Full);
+        let engine = Engine::with_config(overrides).await.unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.levels[6].len(), 1);
+        assert_eq!(version.sstable_count(), 1);
+        for i in 0..6 {
+            let key = format!("key{}", i);
+            assert!(engine.get(key.as_bytes()).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compact_on_close() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            compact_on_close: CompactOnClose::Level0,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"a", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"b", b"2").await.unwrap();
+
+        // The final flush is merged with the rest of level 0
+        engine.close().await.unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 0);
+        assert_eq!(version.levels[1].len(), 1);
+        drop(engine);
+
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"c", b"3").await.unwrap();
+        engine.close_with(CompactOnClose::Full).await.unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.sstable_count(), 1);
+        assert_eq!(version.levels[2].len(), 1);
+        assert_eq!(version.levels[2][0].entry_count(), 3);
+
+        assert!("sometimes".parse::<CompactOnClose>().is_err());
+        assert_eq!(
+            "L0".parse::<CompactOnClose>().unwrap(),
+            CompactOnClose::Level0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_put_writer_streams_value() {
+        let temp_dir = tempdir().unwrap();
+        let engine_path = temp_dir.path();
+        let value: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+
+        {
+            let mut engine = Engine::new(engine_path).await.unwrap();
+            let mut writer = engine.put_writer(b"firmware").await.unwrap();
+            for chunk in value.chunks(4096) {
+                writer.write_all(chunk).unwrap();
+            }
+            writer.finish().await.unwrap();
+            assert_eq!(
+                engine.get(b"firmware").await.unwrap(),
+                Some(Bytes::from(value.clone()))
+            );
+
+            // An abandoned writer leaves no trace
+            let mut writer = engine.put_writer(b"partial").await.unwrap();
+            writer.write_all(b"half a value").unwrap();
+            drop(writer);
+            assert_eq!(engine.get(b"partial").await.unwrap(), None);
+        }
+
+        // Recovered from the WAL pointer
+        {
+            let mut engine = Engine::new(engine_path).await.unwrap();
+            assert_eq!(
+                engine.get(b"firmware").await.unwrap(),
+                Some(Bytes::from(value.clone()))
+            );
+            engine.close().await.unwrap();
+        }
+
+        // Served from the flushed SSTable pointer
+        let engine = Engine::new(engine_path).await.unwrap();
+        assert_eq!(
+            engine.get(b"firmware").await.unwrap(),
+            Some(Bytes::from(value))
+        );
+        assert_eq!(
+            std::fs::read_dir(engine_path.join("blobs"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_value_log_separation() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(64),
+            ..Default::default()
+        };
+        let large = vec![b'v'; 1024];
+
+        {
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            engine.put(b"small", b"inline").await.unwrap();
+            engine.put(b"large", &large).await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
+
+        // The SSTable only holds a pointer to the large value
+        let engine = Engine::with_config(config).await.unwrap();
+        let sstable_bytes: u64 = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "sst"))
+            .map(|e| e.metadata().unwrap().len())
+            .sum();
+        assert!(sstable_bytes < large.len() as u64);
+
+        assert_eq!(
+            engine.get(b"small").await.unwrap(),
+            Some(Bytes::from_static(b"inline"))
+        );
+        assert_eq!(
+            engine.get(b"large").await.unwrap(),
+            Some(Bytes::from(large))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_value_log_gc() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(16),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        let value = |i: u8| vec![i; 100];
+
+        // One log file with four values, three of which are then overwritten inline
+        for i in 0..4 {
+            engine.put(&[b'k', i], &value(i)).await.unwrap();
+        }
+        engine
+            .put_with_timestamp(b"kept", &value(9), 1234)
+            .await
+            .unwrap();
+        engine.force_flush().await.unwrap();
+        for i in 0..3 {
+            engine.put(&[b'k', i], b"small").await.unwrap();
+        }
+        let before = engine.get_with_metadata(b"kept").await.unwrap().unwrap();
+
+        let stats = engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(stats.files_deleted, 1);
+        assert_eq!(stats.files_rewritten, 1);
+        assert_eq!(stats.bytes_reclaimed, 300);
+
+        // Re-pointing keeps the write's timestamp
+        let after = engine.get_with_metadata(b"kept").await.unwrap().unwrap();
+        assert_eq!(after.value, before.value);
+        assert_eq!(after.timestamp, 1234);
+        assert!(after.sequence_number > before.sequence_number);
+
+        // The surviving value moved to a new log file
+        assert_eq!(
+            engine.get(b"k\x03").await.unwrap(),
+            Some(Bytes::from(value(3)))
+        );
+        assert_eq!(
+            engine.get(b"k\x00").await.unwrap(),
+            Some(Bytes::from_static(b"small"))
+        );
+
+        // Once the last pointer is gone the whole file is dropped
+        engine.delete(b"k\x03").await.unwrap();
+        engine.delete(b"kept").await.unwrap();
+        let stats = engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(stats.files_deleted, 1);
+        assert_eq!(stats.files_rewritten, 0);
+        assert_eq!(stats.bytes_reclaimed, 200);
+        assert!(engine.blobs.file_ids().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_value_log_gc_after_compaction() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            value_log_threshold: Some(16),
+            value_log_auto_gc_bytes: Some(100),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"key", &[1; 100]).await.unwrap();
+        engine.force_flush().await.unwrap();
+        let old_file = engine.blobs.active_file_id().unwrap();
+        engine.put(b"key", &[2; 20]).await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        // Dropping the old pointer makes the collection due
+        let stats = engine.compact_all().await.unwrap();
+        assert_eq!(stats.blob_bytes_dropped, 100);
+        assert!(!engine.blobs.file_ids().unwrap().contains(&old_file));
+        assert_eq!(engine.value_log_garbage, 0);
+        assert_eq!(
+            engine.get(b"key").await.unwrap(),
+            Some(Bytes::from(vec![2; 20]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_value_log_gc_spares_files_iterators_read() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            value_log_threshold: Some(16),
+            value_log_auto_gc_bytes: None,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"key", &[1; 100]).await.unwrap();
+        engine.force_flush().await.unwrap();
+        let mut iter = engine.iter::<&[u8], _>(..);
+        engine.delete(b"key").await.unwrap();
+        engine.compact_all().await.unwrap();
+
+        // The iterator still points at the old value
+        let stats = engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(stats.files_deleted, 0);
+        assert_eq!(stats.files_pinned, 1);
+        let (key, value) = iter.next().unwrap().unwrap();
+        assert_eq!(
+            (key.as_slice(), value),
+            (&b"key"[..], Bytes::from(vec![1; 100]))
+        );
+        drop(iter);
+
+        let stats = engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(stats.files_deleted, 1);
+        assert!(engine.blobs.file_ids().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_merges_memtable_and_sstables() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        engine.put(b"a", b"1").await.unwrap();
+        engine.put(b"b", b"old").await.unwrap();
+        engine.put(b"c", b"3").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"b", b"2").await.unwrap();
+        engine.delete(b"c").await.unwrap();
+        engine.put(b"d", b"4").await.unwrap();
+
+        let keys = |results: Vec<(Vec<u8>, Bytes)>| {
+            results
+                .into_iter()
+                .map(|(k, v)| (String::from_utf8(k).unwrap(), v))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(engine.scan::<&[u8], _>(..).await.unwrap()),
+            vec![
+                ("a".to_string(), Bytes::from_static(b"1")),
+                ("b".to_string(), Bytes::from_static(b"2")),
+                ("d".to_string(), Bytes::from_static(b"4")),
+            ]
+        );
+        assert_eq!(
+            keys(engine.scan("a".."c").await.unwrap()),
+            vec![
+                ("a".to_string(), Bytes::from_static(b"1")),
+                ("b".to_string(), Bytes::from_static(b"2")),
+            ]
+        );
+        assert_eq!(
+            keys(
+                engine
+                    .scan::<&str, _>((Bound::Excluded("a"), Bound::Included("b")))
+                    .await
+                    .unwrap()
+            ),
+            vec![("b".to_string(), Bytes::from_static(b"2"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_page() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        for i in 0..10 {
+            let key = format!("key{:02}", i);
+            engine.put(key.as_bytes(), b"value").await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+        engine.delete(b"key03").await.unwrap();
+
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        let mut pages = 0;
+        loop {
+            let page = engine
+                .scan_page(b"key01".as_slice().., 3, token.as_deref())
+                .await
+                .unwrap();
+            keys.extend(page.entries.into_iter().map(|(k, _)| k));
+            pages += 1;
+
+            // Keys written between pages show up only if they sort after the cursor
+            if pages == 1 {
+                engine.put(b"key00x", b"value").await.unwrap();
+                engine.put(b"key09x", b"value").await.unwrap();
+            }
+            match page.next_token {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+
+        let expected: Vec<Vec<u8>> = ["01", "02", "04", "05", "06", "07", "08", "09", "09x"]
+            .iter()
+            .map(|s| format!("key{}", s).into_bytes())
+            .collect();
+        assert_eq!(keys, expected);
+        assert_eq!(pages, 3);
+
+        assert!(matches!(
+            engine.scan_page::<&[u8], _>(.., 3, Some("zz")).await,
+            Err(EngineError::InvalidToken(_))
+        ));
+
+        // Version 1 tokens carried a sequence number, which is skipped
+        let key: String = b"key07".iter().map(|b| format!("{:02x}", b)).collect();
+        let legacy = format!("01{}{}", "00".repeat(8), key);
+        let page = engine
+            .scan_page(b"key01".as_slice().., 3, Some(&legacy))
+            .await
+            .unwrap();
+        let keys: Vec<_> = page.entries.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, [&b"key08"[..], b"key09", b"key09x"]);
+        assert!(engine.scan_page::<&[u8], _>(.., 0, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_prefix() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        for i in 0..7 {
+            engine
+                .put(format!("dev/{}", i).as_bytes(), b"value")
+                .await
+                .unwrap();
+        }
+        for key in ["de", "dev", "dev0", "dew/1", "zz"] {
+            engine.put(key.as_bytes(), b"other").await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+        engine.put(b"dev/7", b"value").await.unwrap();
+
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        let mut pages = 0;
+        loop {
+            let page = engine
+                .scan_prefix(b"dev/", 3, token.as_deref())
+                .await
+                .unwrap();
+            assert!(page.entries.iter().all(|(_, v)| v.as_ref() == b"value"));
+            keys.extend(page.entries.into_iter().map(|(k, _)| k));
+            pages += 1;
+            match page.next_token {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        let expected: Vec<Vec<u8>> = (0..8).map(|i| format!("dev/{}", i).into_bytes()).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(pages, 3);
+
+        // Prefixes without an end and the empty prefix cover the rest of the keyspace
+        engine.put(&[0xFF, 0xFF, 1], b"max").await.unwrap();
+        let page = engine.scan_prefix(&[0xFF, 0xFF], 10, None).await.unwrap();
+        assert_eq!(page.entries.len(), 1);
+        let page = engine.scan_prefix(b"", 100, None).await.unwrap();
+        assert_eq!(page.entries.len(), 14);
+        assert!(page.next_token.is_none());
+        assert!(engine.scan_prefix(b"dev/", 0, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_secondary_index() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        // Values are "<city>:<name>"; index by city
+        let city = |v: &[u8]| v.split(|b| *b == b':').next().map(|c| c.to_vec());
+        engine.put(b"user:0", b"berlin:zoe").await.unwrap();
+        engine.register_index("city", city).unwrap();
+        assert!(engine.register_index("city", city).is_err());
+
+        engine.put(b"user:1", b"oslo:ann").await.unwrap();
+        engine.put(b"user:2", b"lima:bob").await.unwrap();
+        engine.put(b"user:3", b"oslo:cid").await.unwrap();
+
+        let primary_keys = |results: Vec<(Vec<u8>, Bytes)>| {
+            results.into_iter().map(|(k, _)| k).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            primary_keys(engine.scan_index("city", "oslo"..="oslo").await.unwrap()),
+            vec![b"user:1".to_vec(), b"user:3".to_vec()]
+        );
+
+        // Updates move the entry and deletes remove it
+        engine.put(b"user:1", b"lima:ann").await.unwrap();
+        engine.delete(b"user:3").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert!(
+            engine
+                .scan_index("city", "oslo"..="oslo")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            primary_keys(engine.scan_index("city", "a".."m").await.unwrap()),
+            vec![b"user:1".to_vec(), b"user:2".to_vec()]
+        );
+
+        // Data written before registration is picked up by a rebuild
+        assert_eq!(
+            engine
+                .scan_index::<&str, _>("city", ..)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        engine.rebuild_index("city").await.unwrap();
+        assert_eq!(
+            primary_keys(engine.scan_index::<&str, _>("city", ..).await.unwrap()),
+            vec![b"user:0".to_vec(), b"user:1".to_vec(), b"user:2".to_vec()]
+        );
+
+        // Index entries never show up in primary scans
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 3);
+        assert!(matches!(
+            engine.scan_index::<&str, _>("missing", ..).await,
+            Err(EngineError::IndexNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_secondary_index_ignores_stale_entries() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine
+            .register_index("value", |v: &[u8]| Some(v.to_vec()))
+            .unwrap();
+        engine.put(b"key", b"new").await.unwrap();
+
+        // Simulate a crash that left an index entry for an old value behind
+        let stale = engine.indexes[0].entry_key(b"old", b"key");
+        engine.write_value(&stale, b"key", None).await.unwrap();
+
+        assert!(
+            engine
+                .scan_index("value", "old"..="old")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            engine.scan_index("value", "new"..="new").await.unwrap(),
+            vec![(b"key".to_vec(), Bytes::from_static(b"new"))]
+        );
+
+        // A rebuild deletes it and keeps the live entry
+        engine.rebuild_index("value").await.unwrap();
+        let version = engine.versions.current();
+        let stored = version
+            .memtables()
+            .find_map(|memtable| memtable.get_entry(&stale).unwrap())
+            .unwrap();
+        assert!(stored.value.is_none());
+        assert_eq!(
+            engine.scan_index::<&str, _>("value", ..).await.unwrap(),
+            vec![(b"key".to_vec(), Bytes::from_static(b"new"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index_batches_fit_the_memtable() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 4096,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        for i in 0..300 {
+            let key = format!("user:{:03}", i);
+            engine.put(key.as_bytes(), b"oslo").await.unwrap();
+        }
+        engine
+            .register_index("city", |v: &[u8]| Some(v.to_vec()))
+            .unwrap();
+
+        engine.rebuild_index("city").await.unwrap();
+        assert!(engine.stats().sstable_count > 1);
+        let hits = engine.scan_index("city", "oslo"..="oslo").await.unwrap();
+        assert_eq!(hits.len(), 300);
+    }
+
+    #[tokio::test]
+    async fn test_write_batch_limits() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            batch_limits: BatchLimits {
+                max_bytes: Some(64),
+                max_entries: Some(3),
+                split: false,
+            },
+            ..Default::default()
+        };
+        let mut batch = WriteBatch::new();
+        for i in 0..5u8 {
+            batch.put(&[b'k', i], &[i; 20]);
+        }
+
+        // Rejected whole, before any sequence number is used
+        {
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            let before = engine.sequence_number();
+            assert!(matches!(
+                engine.write_batch(batch.clone()).await,
+                Err(EngineError::BatchLimitExceeded {
+                    entries: 5,
+                    bytes: 110
+                })
+            ));
+            assert_eq!(engine.sequence_number(), before);
+            assert_eq!(engine.get(b"k\0").await.unwrap(), None);
+            engine.close().await.unwrap();
+        }
+
+        // Split into atomic sub-batches of at most 64 bytes and 3 operations
+        config.batch_limits.split = true;
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let before = engine.sequence_number();
+        let sequence = engine.write_batch(batch.clone()).await.unwrap();
+        assert_eq!(sequence - before, 5);
+        let begins = crate::wal::dump(engine.wal.path())
+            .unwrap()
+            .items
+            .iter()
+            .filter(|item| {
+                matches!(item, WalDumpItem::Record { record, .. }
+                    if record.record_type == RecordType::BatchBegin)
+            })
+            .count();
+        assert_eq!(begins, 3);
+        for i in 0..5u8 {
+            assert_eq!(
+                engine.get(&[b'k', i]).await.unwrap().as_deref(),
+                Some(&[i; 20][..])
+            );
+        }
+
+        // An operation over the byte cap alone can't be split off
+        let mut oversized = WriteBatch::new();
+        oversized.put(b"a", b"small").put(b"big", &[0; 64]);
+        assert!(matches!(
+            engine.write_batch(oversized).await,
+            Err(EngineError::BatchLimitExceeded { entries: 1, .. })
+        ));
+        assert_eq!(engine.get(b"a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_indexed_writes_log_one_batch() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        let city = |v: &[u8]| v.split(|b| *b == b':').next().map(|c| c.to_vec());
+        engine.register_index("city", city).unwrap();
+        engine.put(b"user:1", b"oslo:ann").await.unwrap();
+
+        // The new index entry, the value and the stale entry's delete
+        let before = engine.sequence_number();
+        let sequence = engine.put(b"user:1", b"lima:ann").await.unwrap();
+        assert_eq!(sequence - before, 3);
+        engine.delete(b"user:1").await.unwrap();
+
+        let types: Vec<RecordType> = crate::wal::dump(engine.wal.path())
+            .unwrap()
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                WalDumpItem::Record { record, .. } => Some(record.record_type),
+                WalDumpItem::Corrupt { .. } => None,
+            })
+            .collect();
+        let batch = |records: &[RecordType]| {
+            [&[RecordType::BatchBegin], records, &[RecordType::BatchEnd]].concat()
+        };
+        let expected = [
+            batch(&[RecordType::Put, RecordType::Put]),
+            batch(&[RecordType::Put, RecordType::Put, RecordType::Delete]),
+            batch(&[RecordType::Delete, RecordType::Delete]),
+        ]
+        .concat();
+        assert_eq!(types, expected);
+        assert!(
+            engine
+                .scan_index::<&str, _>("city", ..)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_batch() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 64 * 1024,
+            ..Default::default()
+        };
+        let city = |v: &[u8]| v.split(|b| *b == b':').next().map(|c| c.to_vec());
+
+        {
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            engine.register_index("city", city).unwrap();
+            engine.put(b"user:1", b"oslo:ann").await.unwrap();
+            engine.put(b"user:2", b"lima:bob").await.unwrap();
+            engine
+                .expire_at(b"user:2", expiry::now_secs() + 3600)
+                .await
+                .unwrap();
+
+            // Later ops see earlier ones, so user:3 only ends up indexed under rome
+            let mut batch = WriteBatch::new();
+            batch
+                .put(b"user:1", b"lima:ann")
+                .put(b"user:3", b"oslo:cid")
+                .put(b"user:3", b"rome:cid")
+                .delete(b"user:2");
+            let before = engine.sequence_number();
+            let sequence = engine.write_batch(batch).await.unwrap();
+            assert_eq!(sequence, engine.sequence_number());
+            assert_eq!(sequence - before, 11);
+            assert_eq!(
+                engine.write_batch(WriteBatch::new()).await.unwrap(),
+                sequence
+            );
+
+            // A batch that can't be applied whole writes nothing
+            let mut invalid = WriteBatch::new();
+            invalid.put(b"user:4", b"oslo:dan").delete(b"");
+            assert!(engine.write_batch(invalid).await.is_err());
+            let mut huge = WriteBatch::new();
+            huge.put(b"user:4", b"oslo:dan")
+                .put(b"blob", &vec![0; config.memtable_size]);
+            assert!(matches!(
+                engine.write_batch(huge).await,
+                Err(EngineError::BatchTooLarge(_))
+            ));
+            assert_eq!(engine.get(b"user:4").await.unwrap(), None);
+            assert_eq!(engine.sequence_number(), sequence);
+        }
+
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.register_index("city", city).unwrap();
+        assert_eq!(
+            engine.get(b"user:1").await.unwrap().as_deref(),
+            Some(&b"lima:ann"[..])
+        );
+        assert_eq!(
+            engine.get(b"user:3").await.unwrap().as_deref(),
+            Some(&b"rome:cid"[..])
+        );
+        assert_eq!(engine.get(b"user:2").await.unwrap(), None);
+        assert_eq!(engine.expiries.get(b"user:2"), None);
+
+        let primary_keys = |results: Vec<(Vec<u8>, Bytes)>| {
+            results.into_iter().map(|(k, _)| k).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            primary_keys(engine.scan_index::<&str, _>("city", ..).await.unwrap()),
+            vec![b"user:1".to_vec(), b"user:3".to_vec()]
+        );
+        for (city, key) in [("oslo", "user:1"), ("oslo", "user:3"), ("lima", "user:2")] {
+            let entry_key = engine.indexes[0].entry_key(city.as_bytes(), key.as_bytes());
+            assert!(
+                engine
+                    .find_entry(&entry_key)
+                    .unwrap()
+                    .unwrap()
+                    .value
+                    .is_none()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_time_series_mode() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            time_series: Some(TimeSeriesOptions {
+                partition_duration_ms: 1000,
+            }),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        let key = |ts: u64| keys::KeyEncoder::new().u64(ts).str("temp").finish();
+
+        assert!(engine.put(b"short", b"x").await.is_err());
+
+        for ts in (0..5000).step_by(250) {
+            engine.put(&key(ts), b"21.5").await.unwrap();
+        }
+        engine.force_flush().await.unwrap();
+
+        // One SSTable per second, with disjoint key ranges
+        {
+            let version = engine.versions.current();
+            assert_eq!(version.sstable_count(), 5);
+            let mut ranges: Vec<_> = version
+                .sstables()
+                .map(|t| {
+                    (
+                        t.first_key().unwrap().to_vec(),
+                        t.last_key().unwrap().to_vec(),
+                    )
+                })
+                .collect();
+            ranges.sort();
+            assert!(ranges.windows(2).all(|w| w[0].1 < w[1].0));
+        }
+
+        let window = engine.scan_time_window(1000, 2000).await.unwrap();
+        let timestamps: Vec<u64> = window
+            .iter()
+            .map(|(k, _)| keys::decode_u64(&k[..8]).unwrap())
+            .collect();
+        assert_eq!(timestamps, vec![1000, 1250, 1500, 1750]);
+
+        // Whole partitions are dropped without writing tombstones
+        let stats = engine.drop_before(2000).await.unwrap();
+        assert_eq!(stats.sstables_dropped, 2);
+        assert_eq!(stats.keys_deleted, 0);
+        assert!(engine.scan_time_window(0, 2000).await.unwrap().is_empty());
+
+        // Late arrivals still in the MemTable are deleted individually
+        engine.put(&key(2100), b"late").await.unwrap();
+        let stats = engine.drop_before(3000).await.unwrap();
+        assert_eq!(stats.sstables_dropped, 1);
+        assert_eq!(stats.keys_deleted, 1);
+        assert_eq!(engine.scan_time_window(0, 3000).await.unwrap().len(), 0);
+        assert_eq!(engine.scan_time_window(0, 5000).await.unwrap().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_drops_expired_data() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            time_series: Some(TimeSeriesOptions::default()),
+            maintenance: Some(MaintenanceOptions {
+                retention: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let key = |ts: u64| keys::KeyEncoder::new().u64(ts).str("temp").finish();
+
+        engine
+            .put(&key(now_ms - 3 * 3600 * 1000), b"old")
+            .await
+            .unwrap();
+        engine.put(&key(now_ms), b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        let stats = engine.run_maintenance().await.unwrap();
+        assert_eq!(stats.retention.unwrap().sstables_dropped, 1);
+        assert_eq!(stats.compactions, 0);
+        let remaining = engine.scan::<&[u8], _>(..).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].1, Bytes::from("new"));
+    }
+
+    #[tokio::test]
+    async fn test_open_files() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+        assert_eq!(engine.open_files().total(), 1);
+
+        for i in 0..2u8 {
+            engine.put(&[b'k', i], b"value").await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        let open_files = engine.stats().open_files;
+        assert_eq!(open_files.sstables, 2);
+        assert_eq!(open_files.total(), 3);
+        if let (Some(process), Some(limit)) = (open_files.process, open_files.limit) {
+            assert!(process >= open_files.total());
+            assert!(limit > 0);
+        }
+
+        let near = OpenFiles {
+            process: None,
+            limit: Some(3),
+            ..open_files
+        };
+        assert!(near.near_limit());
+        assert!(
+            !OpenFiles {
+                limit: None,
+                ..near
+            }
+            .near_limit()
+        );
+        assert!(
+            !OpenFiles {
+                limit: Some(100),
+                ..near
+            }
+            .near_limit()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hot_key_stats() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        assert!(engine.stats().hot_keys.is_empty());
+
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            hot_keys: Some(HotKeyOptions {
+                top_n: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        engine = Engine::with_config(config).await.unwrap();
+        engine
+            .register_index("by_value", |value| Some(value.to_vec()))
+            .unwrap();
+
+        engine.put(b"a", b"1").await.unwrap();
+        engine.put(b"b", b"2").await.unwrap();
+        engine.put(b"c", b"3").await.unwrap();
+        for _ in 0..5 {
+            engine.get(b"b").await.unwrap();
+        }
+        engine.get_with_metadata(b"a").await.unwrap();
+        engine.delete(b"a").await.unwrap();
+
+        // Index maintenance and internal keys don't count as accesses
+        let hot: Vec<(Vec<u8>, u64)> = engine
+            .stats()
+            .hot_keys
+            .into_iter()
+            .map(|hot| (hot.key, hot.count))
+            .collect();
+        assert_eq!(hot, vec![(b"b".to_vec(), 6), (b"a".to_vec(), 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_batched_wal_sync() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            wal_sync_policy: SyncPolicy::Interval(Duration::from_millis(5)),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        let mut tickets = Vec::new();
+        for i in 0..10 {
+            let key = format!("key{}", i);
+            engine.put(key.as_bytes(), b"value").await.unwrap();
+            tickets.push(engine.durability_ticket().unwrap());
+        }
+        engine.delete(b"key0").await.unwrap();
+        tickets.push(engine.durability_ticket().unwrap());
+
+        for ticket in tickets {
+            tokio::time::timeout(Duration::from_secs(5), ticket.wait())
+                .await
+                .expect("ticket should resolve on the next sync")
+                .unwrap();
+        }
+
+        // Without a sync interval the ticket is complete immediately
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine.put(b"key", b"value").await.unwrap();
+        engine.durability_ticket().unwrap().wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_durable() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            wal_sync_policy: SyncPolicy::Interval(Duration::from_secs(3600)),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"before", b"value").await.unwrap();
+        drop(engine);
+
+        // Recovered writes are durable as soon as the engine opens
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.wait_durable(1).await.unwrap();
+        // Let the sync task take its immediate first tick
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        engine.put(b"key", b"value").await.unwrap();
+        let sequence = engine.sequence_number();
+        let waiting =
+            tokio::time::timeout(Duration::from_millis(20), engine.wait_durable(sequence));
+        assert!(waiting.await.is_err(), "the hourly sync hasn't run yet");
+        engine.sync().unwrap();
+        engine.wait_durable(sequence).await.unwrap();
+
+        assert!(matches!(
+            engine.wait_durable(sequence + 1).await,
+            Err(EngineError::SequenceNotWritten(_))
+        ));
+
+        // Without a sync interval the WAL is fsynced on the spot
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine.put(b"key", b"value").await.unwrap();
+        engine.wait_durable(engine.sequence_number()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_iter_raw() {
+        use crate::iterator::EntrySource;
+
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine.put(b"a", b"old").await.unwrap();
+        engine.put(b"b", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"a", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.delete(b"a").await.unwrap();
+        engine.put(b"c", b"value").await.unwrap();
+
+        let raw: Vec<_> = engine
+            .iter_raw(b"a".as_slice()..b"c".as_slice())
+            .collect::<EngineResult<_>>()
+            .unwrap();
+        let versions: Vec<_> = raw
+            .iter()
+            .map(|r| {
+                (
+                    r.entry.key.as_slice(),
+                    r.entry.sequence_number,
+                    r.entry.value.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                (&b"a"[..], 4, None),
+                (&b"a"[..], 3, Some(&b"new"[..])),
+                (&b"a"[..], 1, Some(&b"old"[..])),
+                (&b"b"[..], 2, Some(&b"value"[..])),
+            ]
+        );
+        assert_eq!(raw[0].source, EntrySource::MemTable);
+        assert!(matches!(
+            &raw[1].source,
+            EntrySource::SSTable { level: 0, .. }
+        ));
+        assert_ne!(raw[1].source, raw[2].source);
+
+        // Excluded start bounds skip every version of the key
+        let after_a: Vec<_> = engine
+            .iter_raw::<&[u8], _>((Bound::Excluded(b"a".as_slice()), Bound::Unbounded))
+            .map(|r| r.unwrap().entry.key)
+            .collect();
+        assert_eq!(after_a, vec![b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_writes_return_sequence_numbers() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        assert_eq!(engine.put(b"a", b"1").await.unwrap(), 1);
+        assert_eq!(engine.incr(b"n", 5).await.unwrap(), 2);
+        assert_eq!(engine.delete(b"a").await.unwrap(), 3);
+        assert_eq!(engine.delete_if(b"n", b"nope").await.unwrap(), None);
+        assert_eq!(engine.expire_at(b"n", u64::MAX).await.unwrap(), Some(4));
+        // Overwriting clears the expiry first
+        assert_eq!(engine.put(b"n", b"2").await.unwrap(), 6);
+        assert_eq!(engine.sequence_number(), 6);
+
+        // Index maintenance is covered by the returned sequence
+        engine
+            .register_index("by_value", |value| Some(value.to_vec()))
+            .unwrap();
+        engine.put(b"k", b"x").await.unwrap();
+        let sequence = engine.put(b"k", b"y").await.unwrap();
+        assert_eq!(sequence, engine.sequence_number());
+        engine.wait_durable(sequence).await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+    }
+
+    #[tokio::test]
+    async fn test_last_durable_sequence() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine.put(b"a", b"value").await.unwrap();
+        engine.put(b"b", b"value").await.unwrap();
+        assert_eq!(engine.sequence_number(), 2);
+        assert_eq!(engine.last_durable_sequence(), 0);
+
+        engine.sync().unwrap();
+        assert_eq!(engine.last_durable_sequence(), 2);
+
+        // A flush makes writes durable without a WAL fsync
+        engine.put(b"c", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), 3);
+
+        // The background sync task moves the watermark on its own
+        engine.set_option("wal_sync_interval_ms", "5").unwrap();
+        engine.put(b"d", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), 3);
+        engine.durability_ticket().unwrap().wait().await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), 4);
+
+        engine.put(b"e", b"value").await.unwrap();
+        engine.set_option("wal_sync_interval_ms", "none").unwrap();
+        assert_eq!(engine.last_durable_sequence(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_wal_sync_policies() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            wal_sync_policy: SyncPolicy::Always,
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        // Every append is fsynced before the write returns
+        let sequence = engine.put(b"a", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+
+        // A group pays for one fsync at its end
+        engine.begin_group_commit();
+        engine.put(b"b", b"value").await.unwrap();
+        let sequence = engine.put(b"c", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence - 2);
+        engine.commit_group().unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+
+        engine.set_option("wal_sync_policy", "every:3").unwrap();
+        assert_eq!(
+            engine.get_property("wal_sync_policy").as_deref(),
+            Some("every:3")
+        );
+        engine.put(b"d", b"value").await.unwrap();
+        engine.put(b"e", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+        let sequence = engine.put(b"f", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+
+        // Switching policy syncs what the old one left behind
+        let sequence = engine.put(b"g", b"value").await.unwrap();
+        engine.set_option("wal_sync_policy", "os").unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+        engine.put(b"h", b"value").await.unwrap();
+        assert_eq!(engine.last_durable_sequence(), sequence);
+        assert!(engine.set_option("wal_sync_policy", "every:0").is_err());
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let mut config = EngineConfig::default();
+        config
+            .apply_overrides([
+                ("RUSTEDGEDB_DATA_DIR", "/var/lib/edge"),
+                ("RUSTEDGEDB_MEMTABLE_SIZE", "1048576"),
+                ("RUSTEDGEDB_COMPRESSION", "Zstd"),
+                ("RUSTEDGEDB_USE_DIRECT_IO", "true"),
+                ("RUSTEDGEDB_VALUE_LOG_THRESHOLD", "4096"),
+                ("RUSTEDGEDB_VALUE_LOG_AUTO_GC_BYTES", "none"),
+                ("RUSTEDGEDB_WAL_SYNC_INTERVAL_MS", "5"),
+                ("RUSTEDGEDB_TIME_SERIES_PARTITION_MS", "none"),
+                ("PATH", "/usr/bin"),
+            ])
+            .unwrap();
+
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/edge"));
+        assert_eq!(config.memtable_size, 1024 * 1024);
+        assert_eq!(config.compression, CompressionType::Zstd);
+        assert!(config.use_direct_io);
+        assert_eq!(config.value_log_threshold, Some(4096));
+        assert_eq!(config.value_log_auto_gc_bytes, None);
+        assert_eq!(
+            config.wal_sync_policy,
+            SyncPolicy::Interval(Duration::from_millis(5))
+        );
+        assert!(config.time_series.is_none());
+
+        assert!(
+            config
+                .apply_overrides([("RUSTEDGEDB_MEMTABLE_SIZE", "big")])
+                .is_err()
+        );
+        assert!(
+            config
+                .apply_overrides([("RUSTEDGEDB_COMPRESSION", "snappy")])
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_runtime_options() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        assert_eq!(engine.get_property("compression").as_deref(), Some("none"));
+        assert_eq!(
+            engine.get_property("wal_sync_interval_ms").as_deref(),
+            Some("none")
+        );
+
+        engine.set_option("compression", "lz4").unwrap();
+        engine.set_option("wal_sync_interval_ms", "5").unwrap();
+        assert_eq!(engine.get_property("compression").as_deref(), Some("lz4"));
+        assert_eq!(
+            engine.get_property("wal_sync_interval_ms").as_deref(),
+            Some("5")
+        );
+
+        // The new sync task acknowledges writes
+        engine.put(b"key", b"value").await.unwrap();
+        let ticket = engine.durability_ticket().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), ticket.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        engine.set_option("wal_sync_interval_ms", "none").unwrap();
+        engine.durability_ticket().unwrap().wait().await.unwrap();
+
+        // Invalid values and settings fixed at open time are rejected
+        assert!(engine.set_option("compression", "snappy").is_err());
+        assert!(engine.set_option("data_dir", "/tmp").is_err());
+        assert_eq!(engine.get_property("compression").as_deref(), Some("lz4"));
+
+        assert_eq!(engine.get_property("sstable_count").as_deref(), Some("0"));
+        assert_eq!(engine.get_property("no_such_property"), None);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_block_cache_and_rate_limit() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            block_cache_size: 0,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"key", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        // Turning the cache on attaches it to tables already open
+        engine.set_option("block_cache_size", "4096").unwrap();
+        assert_eq!(
+            engine.get_property("block_cache_size").as_deref(),
+            Some("4096")
+        );
+        engine.get(b"key").await.unwrap();
+        engine.get(b"key").await.unwrap();
+        let stats = engine.stats().block_cache;
+        assert_eq!((stats.capacity, stats.hits, stats.blocks), (4096, 1, 1));
+
+        engine.set_option("block_cache_size", "0").unwrap();
+        let stats = engine.stats().block_cache;
+        assert_eq!((stats.capacity, stats.used_bytes), (0, 0));
+
+        // A rate limit starts background compaction, then retunes it
+        assert_eq!(
+            engine.get_property("compaction_rate_limit").as_deref(),
+            Some("none")
+        );
+        engine
+            .set_option("compaction_rate_limit", "1048576")
+            .unwrap();
+        assert!(engine.compactor.is_some());
+        engine.set_option("compaction_rate_limit", "2048").unwrap();
+        assert_eq!(
+            engine.get_property("compaction_rate_limit").as_deref(),
+            Some("2048")
+        );
+        engine.set_option("compaction_rate_limit", "none").unwrap();
+        assert_eq!(
+            engine.get_property("compaction_rate_limit").as_deref(),
+            Some("none")
+        );
+        assert!(engine.set_option("compaction_rate_limit", "fast").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_prefetches_hot_tables() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            warm_up_hot_tables: 1,
+            ..Default::default()
+        };
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"cold", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"hot", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        for _ in 0..3 {
+            engine.get(b"hot").await.unwrap();
+        }
+        engine.get(b"cold").await.unwrap();
+        engine.close().await.unwrap();
+        drop(engine);
+
+        // Only the most-read table is prefetched after a restart
+        let engine = Engine::with_config(config).await.unwrap();
+        let stats = engine.warm_up().unwrap();
+        assert_eq!(stats.sstables, 2);
+        assert_eq!(stats.index_entries, 2);
+        assert!(stats.bloom_filter_bytes > 0);
+        assert_eq!(stats.hot_tables, 1);
+        assert!(stats.bytes_prefetched > 0);
+
+        // A damaged profile is ignored
+        std::fs::write(temp_dir.path().join(ACCESS_PROFILE_FILE), "garbage").unwrap();
+        drop(engine);
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        assert_eq!(engine.warm_up().unwrap().hot_tables, 0);
+    }
+
+    #[tokio::test]
+    async fn test_correctness_with_deletions() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        // Add data
+        engine.put(b"user:1", b"John").await.unwrap();
+        engine.put(b"user:2", b"Jane").await.unwrap();
+        engine.put(b"config:theme", b"dark").await.unwrap();
+
+        // Verify initial state
+        assert_eq!(
+            engine.get(b"user:1").await.unwrap(),
+            Some(Bytes::from_static(b"John"))
+        );
+        assert_eq!(
+            engine.get(b"user:2").await.unwrap(),
+            Some(Bytes::from_static(b"Jane"))
+        );
+        assert_eq!(
+            engine.get(b"config:theme").await.unwrap(),
+            Some(Bytes::from_static(b"dark"))
+        );
+
+        // Delete a key
+        engine.delete(b"user:1").await.unwrap();
+        assert_eq!(engine.get(b"user:1").await.unwrap(), None);
+
+        // Update a key
+        engine.put(b"user:2", b"Jane Smith").await.unwrap();
+        assert_eq!(
+            engine.get(b"user:2").await.unwrap(),
+            Some(Bytes::from_static(b"Jane Smith"))
+        );
+
+        // Verify other keys unchanged
+        assert_eq!(
+            engine.get(b"config:theme").await.unwrap(),
+            Some(Bytes::from_static(b"dark"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_key_handling() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        // Test empty key in put
+        let result = engine.put(b"", b"value").await;
+        assert!(result.is_err());
+
+        // Test empty key in get
+        let result = engine.get(b"").await;
+        assert!(result.is_err());
+
+        // Test empty key in delete
+        let result = engine.delete(b"").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sequence_number_continuity() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+
+        // Add multiple operations
+        engine.put(b"key1", b"value1").await.unwrap();
+        engine.put(b"key2", b"value2").await.unwrap();
+        engine.delete(b"key1").await.unwrap();
+        engine.put(b"key3", b"value3").await.unwrap();
+
+        // Verify all operations are accessible
+        assert_eq!(engine.get(b"key1").await.unwrap(), None); // Deleted
+        assert_eq!(
+            engine.get(b"key2").await.unwrap(),
+            Some(Bytes::from_static(b"value2"))
+        );
+        assert_eq!(
+            engine.get(b"key3").await.unwrap(),
+            Some(Bytes::from_static(b"value3"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_put_with_timestamp() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+        engine
+            .put_with_timestamp(b"event", b"payload", 1_000)
+            .await
+            .unwrap();
+        engine.put(b"local", b"value").await.unwrap();
+        assert_eq!(
+            engine.get(b"event").await.unwrap().as_deref(),
+            Some(&b"payload"[..])
+        );
+        drop(engine);
+
+        // The original time survives WAL replay and flushing
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+        let entry = engine.memtable.get_entry(b"event").unwrap().unwrap();
+        assert_eq!(entry.timestamp, 1_000);
+        engine.force_flush().await.unwrap();
+        let timestamps: Vec<(Vec<u8>, u64)> = engine.versions.current().levels[0][0]
+            .iter()
+            .map(|e| e.map(|e| (e.key, e.timestamp)).unwrap())
+            .collect();
+        assert_eq!(timestamps[0], (b"event".to_vec(), 1_000));
+        assert!(timestamps[1].1 > 1_000);
+
+        // An older timestamp still overwrites: order follows sequence numbers
+        engine
+            .put_with_timestamp(b"local", b"older", 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.get(b"local").await.unwrap().as_deref(),
+            Some(&b"older"[..])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_iterator_is_snapshot_consistent() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 512,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        for i in 0..40 {
+            let key = format!("key{:02}", i);
+            engine.put(key.as_bytes(), b"old").await.unwrap();
+        }
+        engine.delete(b"key05").await.unwrap();
+        assert!(engine.versions.current().sstable_count() > 0);
+
+        let expected = engine.scan(&b"key01"[..]..&b"key30"[..]).await.unwrap();
+        let mut iter = engine.iter(&b"key01"[..]..&b"key30"[..]);
+        let first = iter.next().unwrap().unwrap();
+
+        // Overwrite, delete and flush while the iterator is open
+        for i in 0..40 {
+            let key = format!("key{:02}", i);
+            engine.put(key.as_bytes(), b"new").await.unwrap();
+        }
+        engine.delete(b"key10").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        let mut seen = vec![first];
+        seen.extend(iter.map(|item| item.unwrap()));
+        assert_eq!(seen, expected);
+        assert_eq!(seen.len(), 28);
+        assert!(seen.iter().all(|(_, v)| v.as_ref() == b"old"));
+
+        // A fresh iterator sees the new state
+        let now: Vec<_> = engine.iter::<&[u8], _>(..).map(|i| i.unwrap()).collect();
+        assert_eq!(now.len(), 39);
+        assert!(now.iter().all(|(_, v)| v.as_ref() == b"new"));
+    }
+
+    #[tokio::test]
+    async fn test_get_with_metadata() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine
+            .put_with_timestamp(b"key1", b"value1", 1_000)
+            .await
+            .unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"key2", b"value2").await.unwrap();
+
+        let flushed = engine.get_with_metadata(b"key1").await.unwrap().unwrap();
+        assert_eq!(
+            flushed,
+            VersionedValue {
+                value: Bytes::from_static(b"value1"),
+                timestamp: 1_000,
+                sequence_number: 1,
+            }
+        );
+        let buffered = engine.get_with_metadata(b"key2").await.unwrap().unwrap();
+        assert_eq!(buffered.sequence_number, 2);
+
+        engine.delete(b"key1").await.unwrap();
+        assert_eq!(engine.get_with_metadata(b"key1").await.unwrap(), None);
+        assert_eq!(engine.get_with_metadata(b"missing").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_expire_at() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let past = expiry::now_secs() - 60;
+        let future = expiry::now_secs() + 3600;
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"a", b"1").await.unwrap();
+        engine.put(b"b", b"2").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert!(engine.expire_at(b"missing", past).await.unwrap().is_none());
+        assert!(engine.expire_at(b"a", past).await.unwrap().is_some());
+        assert!(engine.expire_at(b"b", future).await.unwrap().is_some());
+
+        // Expired keys read as missing on every path
+        assert_eq!(engine.get(b"a").await.unwrap(), None);
+        assert_eq!(engine.get_with_metadata(b"a").await.unwrap(), None);
+        assert_eq!(engine.get(b"b").await.unwrap().unwrap().as_ref(), b"2");
+        let expected = vec![(b"b".to_vec(), Bytes::from_static(b"2"))];
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap(), expected);
+        let iterated: Vec<_> = engine.iter::<&[u8], _>(..).map(Result::unwrap).collect();
+        assert_eq!(iterated, expected);
+        let page = engine.scan_page::<&[u8], _>(.., 10, None).await.unwrap();
+        assert_eq!(page.entries, expected);
+        assert!(engine.expire_at(b"a", future).await.unwrap().is_none());
+        drop(engine);
+
+        // Deadlines survive a restart, and a new value clears them
+        let mut engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.get(b"a").await.unwrap(), None);
+        assert_eq!(engine.expiries.get(b"b"), Some(future));
+        engine.put(b"a", b"3").await.unwrap();
+        assert_eq!(engine.get(b"a").await.unwrap().unwrap().as_ref(), b"3");
+        assert_eq!(engine.expiries.get(b"a"), None);
+
+        // Compaction drops expired values, then their records
+        assert!(engine.expire_at(b"a", past).await.unwrap().is_some());
+        engine.force_flush().await.unwrap();
+        engine.compact_all().await.unwrap();
+        assert_eq!(engine.expiries.get(b"a"), None);
+        assert_eq!(engine.expiries.len(), 1);
+        let version = engine.versions.current();
+        for sstable in version.sstables() {
+            for entry in sstable.iter() {
+                assert_ne!(entry.unwrap().key, b"a");
+            }
+        }
+        assert_eq!(engine.get(b"a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_put_with_ttl() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine
+            .put_with_ttl(b"fresh", b"1", Duration::from_secs(3600))
+            .await
+            .unwrap();
+        engine
+            .put_with_ttl(b"stale", b"2", Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(engine.get(b"fresh").await.unwrap().unwrap().as_ref(), b"1");
+        assert_eq!(engine.get(b"stale").await.unwrap(), None);
+        assert!(
+            engine
+                .put_with_ttl(&expiry::expiry_key(b"fresh"), b"x", Duration::ZERO)
+                .await
+                .is_err()
+        );
+
+        // Later writes in a batch replace the deadline of earlier ones
+        let mut batch = WriteBatch::new();
+        batch
+            .put_with_ttl(b"later", b"3", Duration::ZERO)
+            .put(b"later", b"4")
+            .put(b"renewed", b"5")
+            .put_with_ttl(b"renewed", b"6", Duration::from_secs(60));
+        engine.write_batch(batch).await.unwrap();
+        assert_eq!(engine.get(b"later").await.unwrap().unwrap().as_ref(), b"4");
+        assert_eq!(engine.expiries.get(b"later"), None);
+        assert!(engine.expiries.get(b"renewed").is_some());
+        drop(engine);
+
+        // Deadlines are recovered with the values, and compaction drops expired ones
+        let mut engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.get(b"stale").await.unwrap(), None);
+        assert!(engine.expiries.get(b"fresh").is_some());
+        engine.force_flush().await.unwrap();
+        engine.compact_all().await.unwrap();
+        let version = engine.versions.current();
+        for sstable in version.sstables() {
+            for entry in sstable.iter() {
+                assert_ne!(entry.unwrap().key, b"stale");
+            }
+        }
+        assert_eq!(engine.expiries.get(b"stale"), None);
+        assert_eq!(engine.get(b"fresh").await.unwrap().unwrap().as_ref(), b"1");
+
+        // A plain put clears the TTL
+        engine.put(b"fresh", b"7").await.unwrap();
+        assert_eq!(engine.expiries.get(b"fresh"), None);
+    }
+
+    #[tokio::test]
+    async fn test_truncate() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let sstable_files = |dir: &Path| {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .flatten()
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "sst"))
+                .count()
+        };
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"flushed", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"buffered", b"2").await.unwrap();
+        let iter = engine.iter::<&[u8], _>(..);
+
+        engine.truncate().await.unwrap();
+        assert_eq!(engine.get(b"flushed").await.unwrap(), None);
+        assert!(engine.scan::<&[u8], _>(..).await.unwrap().is_empty());
+        assert_eq!(engine.stats().sstable_count, 0);
+        assert_eq!(sstable_files(temp_dir.path()), 0);
+        assert_eq!(list_wal_files(temp_dir.path()).len(), 1);
+        // Open iterators still see the data they started with
+        assert_eq!(iter.count(), 2);
+
+        engine.put(b"fresh", b"3").await.unwrap();
+        assert_eq!(engine.sequence_number(), 3);
+        drop(engine);
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let expected = vec![(b"fresh".to_vec(), Bytes::from_static(b"3"))];
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap(), expected);
+        assert_eq!(engine.sequence_number(), 3);
+
+        // A crash right after the new manifest leaves old files behind for open to remove
+        engine.put(b"old", b"4").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"unflushed", b"5").await.unwrap();
+        let epoch = Epoch {
+            number: engine.epoch.number + 1,
+            sequence_floor: engine.sequence_number(),
+        };
+        let empty = Version::new(Arc::new(MemTable::new(1024)), config.max_levels);
+        Manifest::from_version(&empty)
+            .with_epoch(epoch)
+            .write(temp_dir.path())
+            .unwrap();
+        drop(engine);
+
+        let engine = Engine::with_config(config).await.unwrap();
+        assert!(engine.scan::<&[u8], _>(..).await.unwrap().is_empty());
+        assert_eq!(engine.sequence_number(), epoch.sequence_floor);
+        assert_eq!(sstable_files(temp_dir.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn test_export_archive() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("db"),
+            value_log_threshold: Some(1024),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"flushed", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"large", &[9u8; 4096]).await.unwrap();
+        engine.put(b"buffered", b"2").await.unwrap();
+
+        let archive_path = temp_dir.path().join("db.rarc");
+        let stats = engine.export_archive(&archive_path).await.unwrap();
+        assert_eq!(stats.sstables, 2);
+        assert_eq!(stats.blob_files, 1);
+        assert_eq!(
+            stats.archive_bytes,
+            std::fs::metadata(&archive_path).unwrap().len()
+        );
+        engine.put(b"after", b"3").await.unwrap();
+
+        // Unpacked, the archive opens as a database holding the snapshot
+        let restored = temp_dir.path().join("restored");
+        ArchiveReader::open(&archive_path)
+            .unwrap()
+            .for_each_entry(|name, contents| {
+                let path = restored.join(name);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                io::copy(contents, &mut std::fs::File::create(path)?)?;
+                Ok(())
+            })
+            .unwrap();
+        let restored = Engine::new(&restored).await.unwrap();
+        let keys: Vec<Vec<u8>> = restored
+            .scan::<&[u8], _>(..)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![b"buffered".to_vec(), b"flushed".to_vec(), b"large".to_vec()]
+        );
+        assert_eq!(restored.get(b"large").await.unwrap().unwrap().len(), 4096);
+        assert_eq!(restored.sequence_number(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("db"),
+            value_log_threshold: Some(1024),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"flushed", b"1").await.unwrap();
+        engine.put(b"large", &[9u8; 4096]).await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"buffered", b"2").await.unwrap();
+        engine
+            .expire_at(b"flushed", expiry::now_secs() + 3600)
+            .await
+            .unwrap();
+
+        let checkpoint = temp_dir.path().join("checkpoint");
+        let sequence = engine.sequence_number();
+        let stats = engine.checkpoint(&checkpoint).unwrap();
+        assert_eq!((stats.sstables, stats.blob_files), (1, 1));
+        assert!(stats.wal_files >= 1);
+        // The table is linked; the active value log and the WAL are copied
+        assert_eq!(stats.linked_files, 1);
+        assert!(stats.bytes_copied >= 4096);
+        assert!(engine.checkpoint(&checkpoint).is_err());
+
+        // Later writes, flushes and compactions don't reach the checkpoint
+        engine.put(b"after", b"3").await.unwrap();
+        engine.delete(b"buffered").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.compact_all().await.unwrap();
+
+        let copy = Engine::with_config(EngineConfig {
+            data_dir: checkpoint.clone(),
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let keys: Vec<Vec<u8>> = copy
+            .scan::<&[u8], _>(..)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![b"buffered".to_vec(), b"flushed".to_vec(), b"large".to_vec()]
+        );
+        assert_eq!(copy.get(b"large").await.unwrap().unwrap().len(), 4096);
+        assert!(copy.expiries.get(b"flushed").is_some());
+        assert_eq!(copy.sequence_number(), sequence);
+        assert_eq!(engine.get(b"after").await.unwrap().unwrap().as_ref(), b"3");
+
+        // Secure delete copies tables, so shredding the originals leaves the copy intact
+        let secure = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().join("secure"),
+            secure_delete: true,
+            ..config
+        })
+        .await
+        .unwrap();
+        let stats = secure
+            .checkpoint(temp_dir.path().join("secure-copy"))
+            .unwrap();
+        assert_eq!(stats.linked_files, 0);
+    }
+
+    #[tokio::test]
+    async fn test_import_archive() {
+        let temp_dir = tempdir().unwrap();
+        let archive_path = temp_dir.path().join("db.rarc");
+        let mut source = Engine::new(temp_dir.path().join("source")).await.unwrap();
+        source.put(b"a", b"archived").await.unwrap();
+        source.put(b"b", b"archived").await.unwrap();
+        source.put(b"c", b"archived").await.unwrap();
+        assert!(source.expire_at(b"c", u64::MAX).await.unwrap().is_some());
+        source.export_archive(&archive_path).await.unwrap();
+        drop(source);
 
-        EngineStats {
-            memtable_size,
-            sstable_count,
-            data_dir: self.config.data_dir.clone(),
-        }
-    }
+        // Unpacked into a fresh directory, ready to open
+        let restored = temp_dir.path().join("restored");
+        let stats = Engine::import_archive(&archive_path, &restored).unwrap();
+        assert!(stats.files >= 3);
+        let engine = Engine::new(&restored).await.unwrap();
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 3);
+        drop(engine);
+        assert!(Engine::import_archive(&archive_path, &restored).is_err());
 
-    /// Force flush of MemTable (useful for testing and shutdown)
-    pub async fn force_flush(&mut self) -> EngineResult<()> {
-        if !self.memtable.is_empty() {
-            self.flush_memtable().await?;
-        }
-        Ok(())
+        // A damaged archive leaves nothing behind
+        let damaged_path = temp_dir.path().join("damaged.rarc");
+        let mut bytes = std::fs::read(&archive_path).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xFF;
+        std::fs::write(&damaged_path, bytes).unwrap();
+        let target = temp_dir.path().join("damaged");
+        assert!(matches!(
+            Engine::import_archive(&damaged_path, &target),
+            Err(EngineError::Archive(_))
+        ));
+        assert!(!target.exists());
+        assert!(!temp_dir.path().join("damaged.importing").exists());
+
+        // Merged keys replace existing ones, keep their expiry, and survive a restart
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("existing"),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"a", b"existing").await.unwrap();
+        engine.put(b"z", b"existing").await.unwrap();
+        let stats = engine.merge_archive(&archive_path).await.unwrap();
+        assert_eq!(stats.keys_ingested, 3);
+        assert_eq!(engine.expiries.get(b"c"), Some(u64::MAX));
+        drop(engine);
+
+        let engine = Engine::with_config(config).await.unwrap();
+        let value = |v: &'static [u8]| Bytes::from_static(v);
+        assert_eq!(
+            engine.scan::<&[u8], _>(..).await.unwrap(),
+            vec![
+                (b"a".to_vec(), value(b"archived")),
+                (b"b".to_vec(), value(b"archived")),
+                (b"c".to_vec(), value(b"archived")),
+                (b"z".to_vec(), value(b"existing")),
+            ]
+        );
+        assert_eq!(engine.expiries.get(b"c"), Some(u64::MAX));
+        assert!(!temp_dir.path().join("existing").join("import").exists());
     }
 
-    /// Close the engine and flush any remaining data
-    pub async fn close(&mut self) -> EngineResult<()> {
-        info!("Closing Engine");
+    #[tokio::test]
+    async fn test_ingest_sstable() {
+        let temp_dir = tempdir().unwrap();
+        let table_path = temp_dir.path().join("seed.sst");
+        let mut builder = SSTableBuilder::new(&table_path, SSTableWriteOptions::default());
+        builder.put(b"a", b"seeded").unwrap();
+        builder.delete(b"b").unwrap();
+        builder.put(b"c", b"seeded").unwrap();
+        assert!(builder.put(b"c", b"again").is_err());
+        assert_eq!(builder.len(), 3);
+        builder.finish().unwrap();
 
-        // Force flush any remaining data
-        self.force_flush().await?;
+        // Ingested entries replace existing keys and delete tombstoned ones
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("db"),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"a", b"existing").await.unwrap();
+        engine.put(b"b", b"existing").await.unwrap();
+        engine.put(b"z", b"existing").await.unwrap();
+        assert!(engine.expire_at(b"a", u64::MAX).await.unwrap().is_some());
+        let before = engine.sequence_number();
+        assert_eq!(engine.ingest_sstable(&table_path).await.unwrap(), 3);
+        assert!(table_path.exists());
+        // The built entries share one sequence number, shifted past every
+        // write, and clearing the expiry takes another
+        assert_eq!(engine.sequence_number(), before + 1 + 1);
+        assert_eq!(engine.expiries.get(b"a"), None);
+        let level0 = engine.versions.current().levels[0].clone();
+        assert!(level0.iter().any(|sstable| sstable.sequence_range()
+            == Some((before + 1, before + 1))
+            && sstable.entry_count() == 3));
+        assert!(!std::fs::read_dir(&config.data_dir).unwrap().any(|e| {
+            e.unwrap()
+                .path()
+                .to_string_lossy()
+                .ends_with(TEMP_SSTABLE_EXTENSION)
+        }));
+        drop(engine);
 
-        info!("Engine closed successfully");
-        Ok(())
+        let engine = Engine::with_config(config).await.unwrap();
+        let value = |v: &'static [u8]| Bytes::from_static(v);
+        assert_eq!(
+            engine.scan::<&[u8], _>(..).await.unwrap(),
+            vec![
+                (b"a".to_vec(), value(b"seeded")),
+                (b"c".to_vec(), value(b"seeded")),
+                (b"z".to_vec(), value(b"existing")),
+            ]
+        );
+        drop(engine);
+
+        // Values meant for the value log are rewritten with fresh sequence numbers
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().join("blobbed"),
+            value_log_threshold: Some(4),
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let before = engine.sequence_number();
+        assert_eq!(engine.ingest_sstable(&table_path).await.unwrap(), 3);
+        assert_eq!(engine.sequence_number(), before + 3);
+        assert_eq!(engine.get(b"c").await.unwrap(), Some(value(b"seeded")));
+        drop(engine);
+
+        // Tables that point into another database's value log are refused
+        let blob_table = temp_dir.path().join("blobs.sst");
+        let entries = vec![Entry::indirect(
+            b"k".to_vec(),
+            Bytes::from_static(b"pointer"),
+            0,
+            1,
+        )];
+        SSTable::from_entries_with_options(&blob_table, &entries, &Default::default()).unwrap();
+        let mut engine = Engine::new(temp_dir.path().join("other")).await.unwrap();
+        assert!(matches!(
+            engine.ingest_sstable(&blob_table).await,
+            Err(EngineError::InvalidConfig(_))
+        ));
+        assert!(engine.get(b"k").await.unwrap().is_none());
     }
-}
 
-/// Database statistics
-#[derive(Debug, Clone)]
-pub struct EngineStats {
-    pub memtable_size: usize,
-    pub sstable_count: usize,
-    pub data_dir: PathBuf,
-}
+    #[tokio::test]
+    async fn test_export_and_import() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("source"),
+            value_log_threshold: Some(1024),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut source = Engine::with_config(config).await.unwrap();
+        source.put_with_timestamp(b"a", b"one", 1000).await.unwrap();
+        source.put(b"blob", &[b'x'; 2048]).await.unwrap();
+        source.force_flush().await.unwrap();
+        source.put(b"binary", &[0xFF, 0x00]).await.unwrap();
+        source.put(b"gone", b"deleted").await.unwrap();
+        source.delete(b"gone").await.unwrap();
+        source
+            .put_with_timestamp(b"z", b"two,\"quoted\"\n", 2000)
+            .await
+            .unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
+        for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+            let mut exported = Vec::new();
+            assert_eq!(source.export(&mut exported, format).unwrap(), 4);
 
-    async fn create_test_engine() -> (Engine, tempfile::TempDir) {
+            let mut target = Engine::new(temp_dir.path().join(format.to_string()))
+                .await
+                .unwrap();
+            target.put(b"a", b"replaced").await.unwrap();
+            assert_eq!(target.import(&exported[..], format).await.unwrap(), 4);
+            assert_eq!(
+                target.scan::<&[u8], _>(..).await.unwrap(),
+                source.scan::<&[u8], _>(..).await.unwrap()
+            );
+            let a = target.get_with_metadata(b"a").await.unwrap().unwrap();
+            assert_eq!(a.timestamp, 1000);
+        }
+
+        // Records before a malformed line are kept
+        let mut target = Engine::new(temp_dir.path().join("partial")).await.unwrap();
+        let input = "{\"key\":\"k\",\"value\":\"v\"}\n{\"key\":\"k2\"}\n";
+        assert!(matches!(
+            target
+                .import(input.as_bytes(), ExportFormat::JsonLines)
+                .await,
+            Err(EngineError::Export(ExportError::Parse { line: 2, .. }))
+        ));
+        assert_eq!(target.get(b"k").await.unwrap().unwrap().as_ref(), b"v");
+    }
+
+    #[tokio::test]
+    async fn test_temp_dir() {
         let temp_dir = tempdir().unwrap();
-        let engine = Engine::new(temp_dir.path()).await.unwrap();
-        (engine, temp_dir)
+        let scratch = temp_dir.path().join("scratch");
+        let mut config = EngineConfig {
+            data_dir: temp_dir.path().join("data"),
+            env_overrides: false,
+            ..Default::default()
+        };
+        config
+            .apply_overrides([("RUSTEDGEDB_TEMP_DIR", scratch.to_str().unwrap())])
+            .unwrap();
+        assert_eq!(config.temp_dir.as_deref(), Some(scratch.as_path()));
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for i in 0..3u8 {
+            engine.put(&[b'k', i], b"value").await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        engine.compact_all().await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 1);
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 3);
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+        drop(engine);
+
+        // A table left behind by a crashed compaction is removed on open
+        let leftover = scratch.join("sstable_1.sst.tmp");
+        std::fs::write(&leftover, b"partial").unwrap();
+        let engine = Engine::with_config(config).await.unwrap();
+        assert!(!leftover.exists());
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 3);
     }
 
     #[tokio::test]
-    async fn test_basic_operations() {
-        let (mut engine, _temp_dir) = create_test_engine().await;
+    async fn test_level_dirs() {
+        let temp_dir = tempdir().unwrap();
+        let cold = temp_dir.path().join("cold");
+        let mut config = EngineConfig {
+            data_dir: temp_dir.path().join("data"),
+            max_levels: 3,
+            env_overrides: false,
+            ..Default::default()
+        };
+        config
+            .apply_overrides([("RUSTEDGEDB_LEVEL_DIRS", format!("2={}", cold.display()))])
+            .unwrap();
+        assert_eq!(config.level_dirs.get(&2), Some(&cold));
+        assert!(
+            config
+                .clone()
+                .apply_overrides([("RUSTEDGEDB_LEVEL_DIRS", "cold")])
+                .is_err()
+        );
 
-        // Test put and get
-        engine.put(b"key1", b"value1").await.unwrap();
-        let value = engine.get(b"key1").await.unwrap();
-        assert_eq!(value, Some(b"value1".to_vec()));
+        let sst_files = |dir: &Path| {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .filter(|e| {
+                    e.as_ref()
+                        .unwrap()
+                        .path()
+                        .extension()
+                        .is_some_and(|x| x == "sst")
+                })
+                .count()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for i in 0..3u8 {
+            engine.put(&[b'k', i], b"value").await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
+        assert_eq!(sst_files(&config.data_dir), 3);
+        assert_eq!(sst_files(&cold), 0);
 
-        // Test delete
-        engine.delete(b"key1").await.unwrap();
-        let value = engine.get(b"key1").await.unwrap();
-        assert_eq!(value, None);
+        // Compacting into the bottom level moves the data to the cold directory
+        engine.compact_all().await.unwrap();
+        engine.put(b"hot", b"value").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(sst_files(&config.data_dir), 1);
+        assert_eq!(sst_files(&cold), 1);
+        drop(engine);
+
+        let engine = Engine::with_config(config.clone()).await.unwrap();
+        assert_eq!(engine.versions.current().levels[2].len(), 1);
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 4);
+        let follower = crate::follower::Follower::with_config(&config).unwrap();
+        assert_eq!(follower.scan::<&[u8], _>(..).unwrap().len(), 4);
     }
 
     #[tokio::test]
-    async fn test_persistence() {
+    async fn test_purge_range_commits_in_batches() {
         let temp_dir = tempdir().unwrap();
-        let engine_path = temp_dir.path();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 4096,
+            env_overrides: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        engine
+            .register_index("city", |v: &[u8]| Some(v.to_vec()))
+            .unwrap();
+        for i in 0..300 {
+            let key = format!("user:{:03}", i);
+            engine.put(key.as_bytes(), b"oslo").await.unwrap();
+        }
+        engine
+            .put_with_ttl(b"user:100", b"oslo", Duration::from_secs(3600))
+            .await
+            .unwrap();
 
-        // Create engine and add data
-        {
-            let mut engine = Engine::new(engine_path).await.unwrap();
+        let stats = engine
+            .purge_range(b"user:".as_slice()..b"user;".as_slice())
+            .await
+            .unwrap();
+        assert_eq!(stats.keys_deleted, 300);
+        assert!(engine.scan::<&[u8], _>(..).await.unwrap().is_empty());
+        assert!(
             engine
-                .put(b"persistent_key", b"persistent_value")
+                .scan_index::<&str, _>("city", ..)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(engine.expiries.is_empty());
+        // Index entries and the expiry record were deleted, not just hidden
+        assert_eq!(engine.iter::<&[u8], _>(..).with_internal_keys().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_purge_range() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(64),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for (i, key) in [b"a", b"b", b"c", b"d", b"e"].into_iter().enumerate() {
+            engine
+                .put(key, format!("secret-{}", key[0] as char).as_bytes())
                 .await
                 .unwrap();
-            engine.put(b"another_key", b"another_value").await.unwrap();
-            engine.close().await.unwrap();
+            if i % 2 == 0 {
+                engine.force_flush().await.unwrap();
+            }
         }
+        engine.put(b"c", &[b'x'; 100]).await.unwrap();
+        engine.compact().await.unwrap();
 
-        // Reopen engine and verify data persists
-        let engine = Engine::new(engine_path).await.unwrap();
-        let value1 = engine.get(b"persistent_key").await.unwrap();
-        let value2 = engine.get(b"another_key").await.unwrap();
+        let stats = engine
+            .purge_range(b"b".as_slice()..=b"d".as_slice())
+            .await
+            .unwrap();
+        assert_eq!(stats.keys_deleted, 3);
+        assert!(stats.sstables_rewritten > 0);
+        assert!(stats.wal_files_removed > 0);
+        assert!(stats.value_log.is_some());
+        let keys: Vec<Vec<u8>> = engine
+            .scan::<&[u8], _>(..)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"e".to_vec()]);
+
+        // Nothing purged is left in any file
+        fn contains(dir: &Path, needle: &[u8]) -> bool {
+            std::fs::read_dir(dir).unwrap().any(|entry| {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    return contains(&path, needle);
+                }
+                std::fs::read(&path)
+                    .unwrap()
+                    .windows(needle.len())
+                    .any(|w| w == needle)
+            })
+        }
+        for purged in [&b"secret-b"[..], b"secret-c", b"secret-d", &[b'x'; 100]] {
+            assert!(!contains(temp_dir.path(), purged));
+        }
+        assert!(contains(temp_dir.path(), b"secret-a"));
+        drop(engine);
 
-        assert_eq!(value1, Some(b"persistent_value".to_vec()));
-        assert_eq!(value2, Some(b"another_value".to_vec()));
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 2);
     }
 
     #[tokio::test]
-    async fn test_crash_recovery() {
+    async fn test_secure_purge() {
         let temp_dir = tempdir().unwrap();
-        let engine_path = temp_dir.path();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            secure_delete: true,
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for key in [b"a", b"b", b"c"] {
+            engine.put(key, b"secret").await.unwrap();
+            engine.force_flush().await.unwrap();
+        }
 
-        // Create engine and add data
-        let mut engine = Engine::new(engine_path).await.unwrap();
-        engine
-            .put(b"recovery_key", b"recovery_value")
+        // An open iterator keeps the old tables around
+        let iter = engine.iter::<&[u8], _>(..);
+        let stats = engine
+            .purge_range(b"a".as_slice()..b"b".as_slice())
             .await
             .unwrap();
-        engine.put(b"test_key", b"test_value").await.unwrap();
+        assert_eq!(stats.keys_deleted, 1);
+        assert!(!stats.unrecoverable);
+        drop(iter);
+
+        let stats = engine.purge_range(b"b".as_slice()..).await.unwrap();
+        assert_eq!(stats.keys_deleted, 2);
+        assert!(stats.unrecoverable);
 
-        // Simulate crash by dropping engine without proper close
+        // Without secure delete nothing is promised
         drop(engine);
+        let mut engine = Engine::with_config(EngineConfig {
+            secure_delete: false,
+            ..config
+        })
+        .await
+        .unwrap();
+        engine.put(b"d", b"secret").await.unwrap();
+        let stats = engine.purge_range::<&[u8], _>(..).await.unwrap();
+        assert!(!stats.unrecoverable);
+    }
 
-        // Reopen engine and verify recovery
-        let engine = Engine::new(engine_path).await.unwrap();
-        let value1 = engine.get(b"recovery_key").await.unwrap();
-        let value2 = engine.get(b"test_key").await.unwrap();
+    #[tokio::test]
+    async fn test_delete_if() {
+        let (mut engine, _temp_dir) = create_test_engine().await;
+        engine.put(b"lease", b"owner-1").await.unwrap();
+
+        assert!(
+            engine
+                .delete_if(b"lease", b"owner-2")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            engine
+                .delete_if(b"missing", b"owner-1")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            engine.get(b"lease").await.unwrap().unwrap().as_ref(),
+            b"owner-1"
+        );
 
-        assert_eq!(value1, Some(b"recovery_value".to_vec()));
-        assert_eq!(value2, Some(b"test_value".to_vec()));
+        assert!(
+            engine
+                .delete_if(b"lease", b"owner-1")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(engine.get(b"lease").await.unwrap(), None);
+        assert!(
+            engine
+                .delete_if(b"lease", b"owner-1")
+                .await
+                .unwrap()
+                .is_none()
+        );
     }
 
     #[tokio::test]
-    async fn test_memtable_flush() {
+    async fn test_incr() {
         let temp_dir = tempdir().unwrap();
-        let engine_path = temp_dir.path();
+        {
+            let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+            engine.incr(b"hits", 5).await.unwrap();
+            engine.incr(b"hits", 2).await.unwrap();
+            assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(7));
 
-        // Create engine with small MemTable size
-        let config = EngineConfig {
-            data_dir: engine_path.to_path_buf(),
-            memtable_size: 100, // Very small to trigger flush
-            compression: CompressionType::None,
-            max_levels: 7,
-        };
+            // Operands on top of a flushed base, then on top of other operands
+            engine.force_flush().await.unwrap();
+            engine.incr(b"hits", -10).await.unwrap();
+            assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(-3));
+            engine.force_flush().await.unwrap();
+            engine.incr(b"hits", 1).await.unwrap();
+            engine.force_flush().await.unwrap();
+            assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(-2));
+            let scanned = engine.scan::<&[u8], _>(..).await.unwrap();
+            assert_eq!(scanned[0].1.as_ref(), (-2i64).to_le_bytes());
+            let iterated: Vec<_> = engine.iter::<&[u8], _>(..).map(Result::unwrap).collect();
+            assert_eq!(iterated, scanned);
 
-        let mut engine = Engine::with_config(config).await.unwrap();
+            engine.compact_all().await.unwrap();
+            assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(-2));
 
-        // Add data that exceeds MemTable size
-        engine.put(b"key1", b"value1").await.unwrap();
-        engine.put(b"key2", b"value2").await.unwrap();
-        engine.put(b"key3", b"value3").await.unwrap();
+            // A delete resets the count; non-integers count from zero
+            engine.incr(b"hits", 4).await.unwrap();
+            engine.delete(b"hits").await.unwrap();
+            engine.incr(b"hits", 1).await.unwrap();
+            engine.put(b"text", b"not a number").await.unwrap();
+            assert!(matches!(
+                engine.get_i64(b"text").await,
+                Err(EngineError::Merge(MergeError::NotAnInteger(12)))
+            ));
+            engine.incr(b"text", 3).await.unwrap();
+        }
 
-        // Force flush
-        engine.force_flush().await.unwrap();
+        // Operands are replayed from the WAL
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(1));
+        assert_eq!(engine.get_i64(b"text").await.unwrap(), Some(3));
+        assert_eq!(engine.get_i64(b"missing").await.unwrap(), None);
+    }
 
-        // Verify data is still accessible
-        let value1 = engine.get(b"key1").await.unwrap();
-        let value2 = engine.get(b"key2").await.unwrap();
-        let value3 = engine.get(b"key3").await.unwrap();
+    #[tokio::test]
+    async fn test_incr_over_value_log() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(8),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        engine.put(b"counter", &40i64.to_le_bytes()).await.unwrap();
+        engine.incr(b"counter", 1).await.unwrap();
+        engine.force_flush().await.unwrap();
 
-        assert_eq!(value1, Some(b"value1".to_vec()));
-        assert_eq!(value2, Some(b"value2".to_vec()));
-        assert_eq!(value3, Some(b"value3".to_vec()));
+        engine.put(b"counter", &100i64.to_le_bytes()).await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.incr(b"counter", 1).await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.get_i64(b"counter").await.unwrap(), Some(101));
 
-        // Check stats
-        let stats = engine.stats();
-        assert_eq!(stats.sstable_count, 1);
+        // The base under an operand keeps its blob through GC and compaction
+        engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(engine.get_i64(b"counter").await.unwrap(), Some(101));
+        engine.compact_all().await.unwrap();
+        engine.collect_value_log_garbage().await.unwrap();
+        assert_eq!(engine.get_i64(b"counter").await.unwrap(), Some(101));
     }
 
     #[tokio::test]
-    async fn test_correctness_with_deletions() {
-        let (mut engine, _temp_dir) = create_test_engine().await;
+    async fn test_append() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(16),
+            ..Default::default()
+        };
+        {
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            engine.append(b"events", b"boot;").await.unwrap();
+            engine.force_flush().await.unwrap();
+            engine.append(b"events", b"reading;").await.unwrap();
+            engine.append(b"events", b"alarm;").await.unwrap();
+            assert_eq!(
+                engine.get(b"events").await.unwrap().unwrap().as_ref(),
+                b"boot;reading;alarm;"
+            );
 
-        // Add data
-        engine.put(b"user:1", b"John").await.unwrap();
-        engine.put(b"user:2", b"Jane").await.unwrap();
-        engine.put(b"config:theme", b"dark").await.unwrap();
+            // A base in the value log, with operands over it after a flush
+            engine.put(b"log", &[b'x'; 32]).await.unwrap();
+            engine.append(b"log", b"y").await.unwrap();
+            engine.force_flush().await.unwrap();
+            engine.append(b"log", b"z").await.unwrap();
+            engine.compact_all().await.unwrap();
+            engine.append(b"events", b"halt;").await.unwrap();
+        }
 
-        // Verify initial state
-        assert_eq!(engine.get(b"user:1").await.unwrap(), Some(b"John".to_vec()));
-        assert_eq!(engine.get(b"user:2").await.unwrap(), Some(b"Jane".to_vec()));
+        let engine = Engine::with_config(config).await.unwrap();
         assert_eq!(
-            engine.get(b"config:theme").await.unwrap(),
-            Some(b"dark".to_vec())
+            engine.get(b"events").await.unwrap().unwrap().as_ref(),
+            b"boot;reading;alarm;halt;"
         );
+        let log = engine.get(b"log").await.unwrap().unwrap();
+        assert_eq!(log.as_ref(), [&[b'x'; 32][..], b"yz"].concat());
+    }
 
-        // Delete a key
-        engine.delete(b"user:1").await.unwrap();
-        assert_eq!(engine.get(b"user:1").await.unwrap(), None);
+    #[tokio::test]
+    async fn test_get_range_of_value() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            value_log_threshold: Some(1024),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config).await.unwrap();
+        let blob: Vec<u8> = (0..8192u32).map(|i| (i % 253) as u8).collect();
+        engine.put(b"blob", &blob).await.unwrap();
+        engine.put(b"inline", b"0123456789").await.unwrap();
+        engine.append(b"log", b"abc").await.unwrap();
 
-        // Update a key
-        engine.put(b"user:2", b"Jane Smith").await.unwrap();
+        // Served from the MemTable, then from SSTables and the value log
+        for flush in [false, true] {
+            if flush {
+                engine.force_flush().await.unwrap();
+                engine.append(b"log", b"def").await.unwrap();
+                engine.force_flush().await.unwrap();
+            }
+            let range = engine.get_range_of_value(b"blob", 4000, 100).await.unwrap();
+            assert_eq!(range.unwrap().as_ref(), &blob[4000..4100]);
+            let range = engine.get_range_of_value(b"inline", 8, 5).await.unwrap();
+            assert_eq!(range.unwrap().as_ref(), b"89");
+            let range = engine.get_range_of_value(b"inline", 20, 5).await.unwrap();
+            assert_eq!(range.unwrap().as_ref(), b"");
+            let range = engine.get_range_of_value(b"log", 1, 3).await.unwrap();
+            let expected: &[u8] = if flush { b"bcd" } else { b"bc" };
+            assert_eq!(range.unwrap().as_ref(), expected);
+        }
+
+        engine.delete(b"inline").await.unwrap();
         assert_eq!(
-            engine.get(b"user:2").await.unwrap(),
-            Some(b"Jane Smith".to_vec())
+            engine.get_range_of_value(b"inline", 0, 1).await.unwrap(),
+            None
         );
-
-        // Verify other keys unchanged
         assert_eq!(
-            engine.get(b"config:theme").await.unwrap(),
-            Some(b"dark".to_vec())
+            engine.get_range_of_value(b"missing", 0, 1).await.unwrap(),
+            None
         );
     }
 
     #[tokio::test]
-    async fn test_empty_key_handling() {
-        let (mut engine, _temp_dir) = create_test_engine().await;
-
-        // Test empty key in put
-        let result = engine.put(b"", b"value").await;
-        assert!(result.is_err());
-
-        // Test empty key in get
-        let result = engine.get(b"").await;
-        assert!(result.is_err());
-
-        // Test empty key in delete
-        let result = engine.delete(b"").await;
-        assert!(result.is_err());
-    }
-
-    #[tokio::test]
-    async fn test_sequence_number_continuity() {
-        let (mut engine, _temp_dir) = create_test_engine().await;
-
-        // Add multiple operations
+    async fn test_sequence_numbers_span_flushes_and_restarts() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
         engine.put(b"key1", b"value1").await.unwrap();
         engine.put(b"key2", b"value2").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.sequence_number(), 2);
+
+        // Flushing no longer restarts the count
         engine.delete(b"key1").await.unwrap();
-        engine.put(b"key3", b"value3").await.unwrap();
+        assert_eq!(engine.sequence_number(), 3);
+        let flushed: Vec<u64> = engine.versions.current().levels[0][0]
+            .iter()
+            .map(|e| e.unwrap().sequence_number)
+            .collect();
+        assert_eq!(flushed, vec![1, 2]);
+        drop(engine);
 
-        // Verify all operations are accessible
-        assert_eq!(engine.get(b"key1").await.unwrap(), None); // Deleted
-        assert_eq!(engine.get(b"key2").await.unwrap(), Some(b"value2".to_vec()));
-        assert_eq!(engine.get(b"key3").await.unwrap(), Some(b"value3".to_vec()));
+        // Recovery keeps logged sequence numbers and continues after them
+        let mut engine = Engine::new(temp_dir.path()).await.unwrap();
+        assert_eq!(engine.sequence_number(), 3);
+        let tombstone = engine.memtable.get_entry(b"key1").unwrap().unwrap();
+        assert_eq!(tombstone.sequence_number, 3);
+        engine.put(b"key3", b"value3").await.unwrap();
+        assert_eq!(engine.sequence_number(), 4);
+        assert_eq!(
+            engine
+                .memtable
+                .get_entry(b"key3")
+                .unwrap()
+                .unwrap()
+                .sequence_number,
+            4
+        );
     }
 }
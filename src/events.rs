@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::trace;
+
+use crate::compaction::CompactionReason;
+use crate::engine::CompactionStats;
+use crate::history::FlushReason;
+
+/// Number of events a subscriber can fall behind before it starts missing them
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Something the engine did that applications may want to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// The MemTable was written out as new level 0 SSTables
+    FlushFinished {
+        reason: FlushReason,
+        sstables: usize,
+        entries: usize,
+        duration: Duration,
+    },
+    /// A compaction replaced its input tables with new ones
+    CompactionFinished {
+        reason: CompactionReason,
+        output_level: usize,
+        stats: CompactionStats,
+        duration: Duration,
+    },
+    /// A write is blocked until background work catches up
+    StallStarted { reason: StallReason },
+    /// The blocked write can proceed
+    StallEnded {
+        reason: StallReason,
+        duration: Duration,
+    },
+    /// An SSTable failed to open, verify or serve a point read
+    CorruptionDetected {
+        path: PathBuf,
+        error: String,
+        /// Where the table was moved by [`crate::engine::CorruptFilePolicy::Quarantine`]
+        quarantined_to: Option<PathBuf>,
+    },
+}
+
+/// Why writes stalled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallReason {
+    /// The MemTable or WAL hit a flush trigger and is flushed before the write returns
+    Flush,
+    /// `max_immutable_memtables` MemTables are waiting to flush, so the write
+    /// waits for the oldest
+    ImmutableMemTables,
+}
+
+/// Broadcast channel of [`EngineEvent`]s
+///
+/// Publishing never blocks the engine: events are dropped while nobody is
+/// subscribed, and a subscriber more than [`EVENT_BUS_CAPACITY`] events behind
+/// receives `RecvError::Lagged` and skips ahead.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<EngineEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<EngineEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Send an event to the current subscribers
+    pub fn publish(&self, event: EngineEvent) {
+        trace!("Publishing {:?}", event);
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.sender.receiver_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+    #[tokio::test]
+    async fn test_event_bus() {
+        let bus = EventBus::new();
+        // Nobody is listening yet, so this one is dropped
+        bus.publish(EngineEvent::StallStarted {
+            reason: StallReason::Flush,
+        });
+
+        let mut first = bus.clone().subscribe();
+        let mut second = bus.subscribe();
+        let event = EngineEvent::StallEnded {
+            reason: StallReason::Flush,
+            duration: Duration::from_millis(5),
+        };
+        bus.publish(event.clone());
+        assert_eq!(first.recv().await.unwrap(), event);
+        assert_eq!(second.recv().await.unwrap(), event);
+        assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+
+        // A slow subscriber skips what it missed
+        for _ in 0..EVENT_BUS_CAPACITY + 1 {
+            bus.publish(event.clone());
+        }
+        assert_eq!(first.recv().await, Err(RecvError::Lagged(1)));
+        assert_eq!(first.recv().await.unwrap(), event);
+    }
+}
@@ -0,0 +1,163 @@
+use imbl::OrdMap;
+use std::time::Duration;
+
+use crate::index::INTERNAL_KEY_PREFIX;
+use crate::keys::{self, KeyDecoder, KeyEncoder};
+
+/// Internal key holding the expiry of `key`
+pub fn expiry_key(key: &[u8]) -> Vec<u8> {
+    let mut expiry_key = expiry_prefix();
+    expiry_key.extend(KeyEncoder::new().bytes(key).finish());
+    expiry_key
+}
+
+/// Prefix shared by every expiry record
+pub fn expiry_prefix() -> Vec<u8> {
+    let mut prefix = INTERNAL_KEY_PREFIX.to_vec();
+    prefix.extend(KeyEncoder::new().str("exp").finish());
+    prefix
+}
+
+/// Recover the user key from an expiry record key
+pub fn decode_expiry_key(expiry_key: &[u8]) -> Option<Vec<u8>> {
+    let encoded = expiry_key.strip_prefix(expiry_prefix().as_slice())?;
+    let mut decoder = KeyDecoder::new(encoded);
+    let key = decoder.bytes().ok()?;
+    decoder.is_empty().then_some(key)
+}
+
+/// Encode an expiry deadline as a record value
+pub fn encode_deadline(unix_ts: u64) -> [u8; 8] {
+    keys::encode_u64(unix_ts)
+}
+
+/// Decode a record value written by [`encode_deadline`]
+pub fn decode_deadline(value: &[u8]) -> Option<u64> {
+    keys::decode_u64(value).ok()
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Deadline `ttl` from now, in whole seconds rounded up
+pub fn deadline_after(ttl: Duration) -> u64 {
+    let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+    now_secs().saturating_add(secs)
+}
+
+/// Expiry deadlines of user keys, as Unix timestamps in seconds
+///
+/// Held in a persistent ordered map, so cloning is O(1): iterators and
+/// compactions keep the deadlines as of their creation, and a change made
+/// while they do copies only the tree nodes on the path to its key rather
+/// than every deadline.
+#[derive(Clone, Debug, Default)]
+pub struct Expiries {
+    deadlines: OrdMap<Vec<u8>, u64>,
+}
+
+impl Expiries {
+    /// Deadline set for a key, if any
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.deadlines.get(key).copied()
+    }
+
+    /// Whether a key's deadline is at or before `now`
+    pub fn is_expired(&self, key: &[u8], now: u64) -> bool {
+        self.get(key).is_some_and(|deadline| deadline <= now)
+    }
+
+    /// Keys whose deadline is at or before `now`
+    pub fn expired(&self, now: u64) -> Vec<Vec<u8>> {
+        self.deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Every key and its deadline, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], u64)> {
+        self.deadlines
+            .iter()
+            .map(|(key, deadline)| (key.as_slice(), *deadline))
+    }
+
+    /// Number of keys with a deadline
+    pub fn len(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    /// Whether no key has a deadline
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+
+    /// Set or replace a key's deadline
+    pub fn set(&mut self, key: &[u8], unix_ts: u64) {
+        self.deadlines.insert(key.to_vec(), unix_ts);
+    }
+
+    /// Clear a key's deadline, returning whether it had one
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        if !self.deadlines.contains_key(key) {
+            return false;
+        }
+        self.deadlines.remove(key).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index;
+
+    #[test]
+    fn test_expiry_keys_round_trip() {
+        let key = expiry_key(b"user\x00key");
+        assert!(index::is_internal_key(&key));
+        assert!(key.starts_with(&expiry_prefix()));
+        assert_eq!(decode_expiry_key(&key), Some(b"user\x00key".to_vec()));
+        assert_eq!(decode_expiry_key(b"user"), None);
+
+        assert_eq!(
+            decode_deadline(&encode_deadline(1_700_000_000)),
+            Some(1_700_000_000)
+        );
+        assert_eq!(decode_deadline(b"short"), None);
+    }
+
+    #[test]
+    fn test_expiries_snapshot() {
+        let mut expiries = Expiries::default();
+        expiries.set(b"a", 100);
+        expiries.set(b"b", 200);
+        let snapshot = expiries.clone();
+
+        assert!(expiries.remove(b"a"));
+        assert!(!expiries.remove(b"a"));
+        assert_eq!(expiries.get(b"a"), None);
+        assert_eq!(snapshot.get(b"a"), Some(100));
+
+        assert!(snapshot.is_expired(b"a", 100));
+        assert!(!snapshot.is_expired(b"b", 150));
+        assert_eq!(snapshot.expired(150), vec![b"a".to_vec()]);
+        assert_eq!(expiries.len(), 1);
+
+        // Snapshots share structure, so each may keep changing on its own
+        for i in 0..10_000u32 {
+            expiries.set(&i.to_be_bytes(), u64::from(i));
+        }
+        let mut later = expiries.clone();
+        later.set(&7u32.to_be_bytes(), 1);
+        assert!(later.remove(&8u32.to_be_bytes()));
+        assert_eq!(expiries.get(&7u32.to_be_bytes()), Some(7));
+        assert_eq!(expiries.get(&8u32.to_be_bytes()), Some(8));
+        assert_eq!((expiries.len(), later.len()), (10_001, 10_000));
+    }
+}
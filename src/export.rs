@@ -0,0 +1,634 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Errors that can occur while writing or reading exported records
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("Unknown export format: {0}")]
+    UnknownFormat(String),
+}
+
+/// Result type for export and import
+pub type ExportResult<T> = Result<T, ExportError>;
+
+/// Text formats that [`Engine::export`] writes and [`Engine::import`] reads
+///
+/// [`Engine::export`]: crate::engine::Engine::export
+/// [`Engine::import`]: crate::engine::Engine::import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line: `{"key":..,"value":..,"timestamp":..}`
+    JsonLines,
+    /// A `key,value,timestamp,encoding` header, then one row per record
+    Csv,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "ndjson" | "json" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            other => Err(ExportError::UnknownFormat(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::JsonLines => "jsonl",
+            Self::Csv => "csv",
+        })
+    }
+}
+
+/// Value of the `encoding` field when the key and value are written in hex
+///
+/// Records are written as text when both are valid UTF-8, and both in hex
+/// otherwise, so binary data round-trips without guessing.
+pub const HEX_ENCODING: &str = "hex";
+
+const CSV_HEADER: &str = "key,value,timestamp,encoding";
+
+/// One exported key-value pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    /// Write time in milliseconds since the Unix epoch; imports without one
+    /// are stamped when written
+    pub timestamp: Option<u64>,
+}
+
+/// Writes records in an [`ExportFormat`]
+pub struct RecordWriter<W: Write> {
+    writer: W,
+    format: ExportFormat,
+    started: bool,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: ExportFormat) -> Self {
+        Self {
+            writer,
+            format,
+            started: false,
+        }
+    }
+
+    /// Write one record, after the CSV header if this is the first
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        if !self.started && self.format == ExportFormat::Csv {
+            writeln!(self.writer, "{}", CSV_HEADER)?;
+        }
+        self.started = true;
+
+        let (key, value, encoding) = match (
+            std::str::from_utf8(&record.key),
+            std::str::from_utf8(&record.value),
+        ) {
+            (Ok(key), Ok(value)) => (key.to_string(), value.to_string(), None),
+            _ => (
+                encode_hex(&record.key),
+                encode_hex(&record.value),
+                Some(HEX_ENCODING),
+            ),
+        };
+        let timestamp = record.timestamp.map(|ts| ts.to_string());
+        match self.format {
+            ExportFormat::JsonLines => {
+                let mut line = format!(
+                    "{{\"key\":{},\"value\":{}",
+                    json_string(&key),
+                    json_string(&value)
+                );
+                if let Some(timestamp) = timestamp {
+                    line.push_str(&format!(",\"timestamp\":{}", timestamp));
+                }
+                if let Some(encoding) = encoding {
+                    line.push_str(&format!(",\"encoding\":{}", json_string(encoding)));
+                }
+                writeln!(self.writer, "{}}}", line)
+            }
+            ExportFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{}",
+                csv_field(&key),
+                csv_field(&value),
+                timestamp.unwrap_or_default(),
+                encoding.unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Write the CSV header if no record was written, and flush
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.started && self.format == ExportFormat::Csv {
+            writeln!(self.writer, "{}", CSV_HEADER)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads records in an [`ExportFormat`], one per line or CSV row
+///
+/// JSON objects need `key` and `value` strings and may carry a `timestamp`
+/// number and an `encoding`; other scalar fields are ignored. CSV input
+/// starts with a header naming at least the `key` and `value` columns, in
+/// any order. Blank lines are skipped.
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    format: ExportFormat,
+    /// Positions of the key, value, timestamp and encoding CSV columns
+    columns: Option<CsvColumns>,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CsvColumns {
+    key: usize,
+    value: usize,
+    timestamp: Option<usize>,
+    encoding: Option<usize>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, format: ExportFormat) -> Self {
+        Self {
+            reader,
+            format,
+            columns: None,
+            line: 0,
+        }
+    }
+
+    fn parse_error(&self, message: impl Into<String>) -> ExportError {
+        ExportError::Parse {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    /// Read the next non-blank line, without its line ending
+    fn read_line(&mut self) -> ExportResult<Option<String>> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            let trimmed = line.trim_end_matches(['\n', '\r']);
+            if !trimmed.trim().is_empty() {
+                return Ok(Some(trimmed.to_string()));
+            }
+        }
+    }
+
+    /// Read one CSV row, continuing onto further lines inside quoted fields
+    fn read_csv_row(&mut self) -> ExportResult<Option<Vec<String>>> {
+        let Some(mut text) = self.read_line()? else {
+            return Ok(None);
+        };
+        loop {
+            if let Some(fields) = split_csv_row(&text) {
+                return Ok(Some(fields));
+            }
+            let mut next = String::new();
+            if self.reader.read_line(&mut next)? == 0 {
+                return Err(self.parse_error("unterminated quoted field"));
+            }
+            self.line += 1;
+            text.push('\n');
+            text.push_str(next.trim_end_matches(['\n', '\r']));
+        }
+    }
+
+    fn next_json(&mut self) -> ExportResult<Option<Record>> {
+        let Some(line) = self.read_line()? else {
+            return Ok(None);
+        };
+        let fields = parse_json_object(&line).map_err(|message| self.parse_error(message))?;
+        let mut key = None;
+        let mut value = None;
+        let mut timestamp = None;
+        let mut encoding = None;
+        for (name, field) in fields {
+            match (name.as_str(), field) {
+                ("key", JsonScalar::String(s)) => key = Some(s),
+                ("value", JsonScalar::String(s)) => value = Some(s),
+                ("timestamp", JsonScalar::Number(n)) => {
+                    timestamp = Some(
+                        n.parse()
+                            .map_err(|_| self.parse_error(format!("invalid timestamp {}", n)))?,
+                    )
+                }
+                ("encoding", JsonScalar::String(s)) => encoding = Some(s),
+                ("timestamp", JsonScalar::Null) | ("encoding", JsonScalar::Null) => {}
+                ("key" | "value" | "timestamp" | "encoding", _) => {
+                    return Err(self.parse_error(format!("field {:?} has the wrong type", name)));
+                }
+                _ => {}
+            }
+        }
+        let key = key.ok_or_else(|| self.parse_error("missing \"key\""))?;
+        let value = value.ok_or_else(|| self.parse_error("missing \"value\""))?;
+        self.decode(key, value, timestamp, encoding.as_deref())
+            .map(Some)
+    }
+
+    fn next_csv(&mut self) -> ExportResult<Option<Record>> {
+        let columns = match self.columns {
+            Some(columns) => columns,
+            None => {
+                let Some(header) = self.read_csv_row()? else {
+                    return Ok(None);
+                };
+                let find = |name: &str| header.iter().position(|column| column.trim() == name);
+                let columns = CsvColumns {
+                    key: find("key").ok_or_else(|| self.parse_error("header has no key column"))?,
+                    value: find("value")
+                        .ok_or_else(|| self.parse_error("header has no value column"))?,
+                    timestamp: find("timestamp"),
+                    encoding: find("encoding"),
+                };
+                self.columns = Some(columns);
+                columns
+            }
+        };
+        let Some(mut row) = self.read_csv_row()? else {
+            return Ok(None);
+        };
+        let mut take = |column: Option<usize>| {
+            column
+                .and_then(|i| row.get_mut(i))
+                .map(std::mem::take)
+                .filter(|field| !field.is_empty())
+        };
+        let key = take(Some(columns.key));
+        let value = take(Some(columns.value)).unwrap_or_default();
+        let timestamp = take(columns.timestamp);
+        let encoding = take(columns.encoding);
+        let key = key.ok_or_else(|| self.parse_error("missing key"))?;
+        let timestamp = timestamp
+            .map(|ts| {
+                ts.trim()
+                    .parse()
+                    .map_err(|_| self.parse_error(format!("invalid timestamp {}", ts)))
+            })
+            .transpose()?;
+        self.decode(key, value, timestamp, encoding.as_deref())
+            .map(Some)
+    }
+
+    /// Build a record, decoding hex fields
+    fn decode(
+        &self,
+        key: String,
+        value: String,
+        timestamp: Option<u64>,
+        encoding: Option<&str>,
+    ) -> ExportResult<Record> {
+        let (key, value) = match encoding {
+            None | Some("") | Some("utf8") | Some("utf-8") => {
+                (key.into_bytes(), value.into_bytes())
+            }
+            Some(HEX_ENCODING) => {
+                let hex = |s: &str| {
+                    decode_hex(s).ok_or_else(|| self.parse_error(format!("invalid hex {:?}", s)))
+                };
+                (hex(&key)?, hex(&value)?)
+            }
+            Some(other) => return Err(self.parse_error(format!("unknown encoding {:?}", other))),
+        };
+        if key.is_empty() {
+            return Err(self.parse_error("empty key"));
+        }
+        Ok(Record {
+            key,
+            value,
+            timestamp,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = ExportResult<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            ExportFormat::JsonLines => self.next_json(),
+            ExportFormat::Csv => self.next_csv(),
+        }
+        .transpose()
+    }
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split a CSV row into fields, or None if it ends inside a quoted field
+fn split_csv_row(row: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A JSON value that fits on one record's field
+enum JsonScalar {
+    String(String),
+    /// The number as written
+    Number(String),
+    Bool,
+    Null,
+}
+
+/// Parse a flat JSON object whose fields are strings, numbers, booleans or null
+fn parse_json_object(text: &str) -> Result<Vec<(String, JsonScalar)>, String> {
+    let mut parser = JsonParser {
+        chars: text.chars().peekable(),
+    };
+    parser.expect('{')?;
+    let mut fields = Vec::new();
+    if parser.peek() == Some('}') {
+        parser.chars.next();
+    } else {
+        loop {
+            parser.expect('"')?;
+            let name = parser.string()?;
+            parser.expect(':')?;
+            let value = parser.scalar()?;
+            fields.push((name, value));
+            match parser.next() {
+                Some(',') => {}
+                Some('}') => break,
+                _ => return Err("expected ',' or '}'".to_string()),
+            }
+        }
+    }
+    if parser.peek().is_some() {
+        return Err("trailing characters after object".to_string());
+    }
+    Ok(fields)
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    /// Next character that isn't whitespace, without consuming it
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.peek()?;
+        self.chars.next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected {:?}, found {:?}", expected, c)),
+            None => Err(format!("expected {:?}, found end of line", expected)),
+        }
+    }
+
+    fn scalar(&mut self) -> Result<JsonScalar, String> {
+        match self.peek() {
+            Some('"') => {
+                self.chars.next();
+                self.string().map(JsonScalar::String)
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    number.push(c);
+                }
+                Ok(JsonScalar::Number(number))
+            }
+            Some('t' | 'f' | 'n') => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "true" | "false" => Ok(JsonScalar::Bool),
+                    "null" => Ok(JsonScalar::Null),
+                    _ => Err(format!("unexpected {:?}", word)),
+                }
+            }
+            Some('{' | '[') => Err("nested objects and arrays are not supported".to_string()),
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("expected a value, found end of line".to_string()),
+        }
+    }
+
+    /// Read a string whose opening quote was consumed
+    fn string(&mut self) -> Result<String, String> {
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => {
+                        let unit = self.hex4()?;
+                        let code = if (0xD800..0xDC00).contains(&unit) {
+                            // High surrogate: the low half follows as another escape
+                            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                                return Err("unpaired surrogate".to_string());
+                            }
+                            let low = self.hex4()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err("unpaired surrogate".to_string());
+                            }
+                            0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)
+                        } else {
+                            unit
+                        };
+                        out.push(char::from_u32(code).ok_or("invalid \\u escape")?);
+                    }
+                    _ => return Err("invalid escape".to_string()),
+                },
+                Some(c) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.chars.next()).collect();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|_| digits.len() == 4)
+            .ok_or_else(|| "invalid \\u escape".to_string())
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: ExportFormat, records: &[Record]) -> Vec<Record> {
+        let mut writer = RecordWriter::new(Vec::new(), format);
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        RecordReader::new(&bytes[..], format)
+            .collect::<ExportResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let records = vec![
+            Record {
+                key: b"plain".to_vec(),
+                value: b"value".to_vec(),
+                timestamp: Some(1_700_000_000_000),
+            },
+            Record {
+                key: "quote\"comma,line\nbreak".into(),
+                value: "tab\tslash\\ \u{1}\u{1F600}".into(),
+                timestamp: None,
+            },
+            Record {
+                key: vec![0xFF, 0x00],
+                value: b"text".to_vec(),
+                timestamp: Some(1),
+            },
+            Record {
+                key: b"empty".to_vec(),
+                value: Vec::new(),
+                timestamp: Some(2),
+            },
+        ];
+        for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+            assert_eq!(round_trip(format, &records), records, "{}", format);
+        }
+        assert!(round_trip(ExportFormat::Csv, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_read_external_records() {
+        let json = "\n{ \"value\": \"\\u00e9\\ud83d\\ude00\", \"ttl\": null, \"key\": \"k\", \"n\": 1.5e3 }\n";
+        let records: Vec<_> = RecordReader::new(json.as_bytes(), ExportFormat::JsonLines)
+            .collect::<ExportResult<_>>()
+            .unwrap();
+        assert_eq!(records[0].key, b"k");
+        assert_eq!(records[0].value, "é😀".as_bytes());
+        assert_eq!(records[0].timestamp, None);
+
+        // Columns are found by name and trailing CRs are dropped
+        let csv = "value,key\r\nv1,k1\r\n\"a \"\"b\"\"\",k2\r\n";
+        let records: Vec<_> = RecordReader::new(csv.as_bytes(), ExportFormat::Csv)
+            .collect::<ExportResult<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].key, b"k2");
+        assert_eq!(records[1].value, b"a \"b\"");
+
+        let errors = [
+            (ExportFormat::JsonLines, "{\"key\":\"k\"}\n"),
+            (ExportFormat::JsonLines, "{\"key\":\"k\",\"value\":{}}\n"),
+            (
+                ExportFormat::JsonLines,
+                "{\"key\":\"k\",\"value\":\"v\"} x\n",
+            ),
+            (
+                ExportFormat::JsonLines,
+                "{\"key\":\"zz\",\"value\":\"\",\"encoding\":\"hex\"}\n",
+            ),
+            (ExportFormat::Csv, "key,value\n,v\n"),
+            (ExportFormat::Csv, "key,value\n\"k,v\n"),
+            (ExportFormat::Csv, "name,value\nk,v\n"),
+        ];
+        for (format, input) in errors {
+            let result: ExportResult<Vec<_>> =
+                RecordReader::new(input.as_bytes(), format).collect();
+            assert!(
+                matches!(result, Err(ExportError::Parse { .. })),
+                "{:?} accepted",
+                input
+            );
+        }
+    }
+}
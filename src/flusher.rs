@@ -0,0 +1,824 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::engine::{
+    Engine, EngineResult, TEMP_SSTABLE_EXTENSION, TimeSeriesOptions, list_wal_files,
+    remove_data_file,
+};
+use crate::events::{EngineEvent, StallReason};
+use crate::history::{FlushReason, JobKind};
+use crate::io_backend::sync_dir;
+use crate::memtable::{Entry, MemTable};
+use crate::sstable::{SSTable, SSTableWriteOptions};
+
+/// A frozen MemTable with everything needed to write it out without the engine
+///
+/// Tables are written under temporary names in the scratch directory; the
+/// engine moves them into level 0 when it installs the result.
+pub(crate) struct FlushJob {
+    pub memtable: Arc<MemTable>,
+    pub reason: FlushReason,
+    /// Splits the output into one table per time partition
+    pub time_series: Option<TimeSeriesOptions>,
+    pub write_options: SSTableWriteOptions,
+    pub scratch_dir: PathBuf,
+    pub secure_delete: bool,
+}
+
+/// Written tables of a [`FlushJob`], ready to install
+pub(crate) struct FinishedFlush {
+    pub memtable: Arc<MemTable>,
+    pub reason: FlushReason,
+    /// Output tables, still at their temporary paths
+    pub outputs: Vec<SSTable>,
+    pub entries: usize,
+    pub duration: Duration,
+}
+
+impl FinishedFlush {
+    /// Delete the outputs of a flush that won't be installed
+    pub fn discard(self, secure: bool) {
+        discard_outputs(&self.outputs, secure);
+    }
+}
+
+impl FlushJob {
+    /// Write the MemTable's entries to new tables
+    ///
+    /// Entries are sorted, so each time partition is a contiguous run. Tables
+    /// already written are deleted on failure.
+    pub fn run(self) -> EngineResult<FinishedFlush> {
+        let started = Instant::now();
+        let entries = self.memtable.entries();
+        debug!("MemTable has {} entries before flush", entries.len());
+
+        let mut runs: Vec<&[Entry]> = Vec::new();
+        match &self.time_series {
+            Some(time_series) => {
+                let mut rest = entries.as_slice();
+                while let Some(first) = rest.first() {
+                    let partition = time_series.partition_of(&first.key);
+                    let len =
+                        rest.partition_point(|e| time_series.partition_of(&e.key) == partition);
+                    runs.push(&rest[..len]);
+                    rest = &rest[len..];
+                }
+            }
+            None => runs.push(&entries),
+        }
+
+        let mut outputs = Vec::with_capacity(runs.len());
+        for run in runs {
+            match self.write_output(run) {
+                Ok(sstable) => outputs.push(sstable),
+                Err(e) => {
+                    discard_outputs(&outputs, self.secure_delete);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(FinishedFlush {
+            memtable: self.memtable,
+            reason: self.reason,
+            outputs,
+            entries: entries.len(),
+            duration: started.elapsed(),
+        })
+    }
+
+    /// Write sorted entries to a new table under a temporary name
+    fn write_output(&self, entries: &[Entry]) -> EngineResult<SSTable> {
+        static NEXT_OUTPUT: AtomicU64 = AtomicU64::new(0);
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let name = format!(
+            "flush_{}_{}.{}",
+            millis,
+            NEXT_OUTPUT.fetch_add(1, Ordering::Relaxed),
+            TEMP_SSTABLE_EXTENSION
+        );
+        let path = self.scratch_dir.join(name);
+        Ok(SSTable::from_entries_with_options(
+            path,
+            entries,
+            &self.write_options,
+        )?)
+    }
+}
+
+fn discard_outputs(outputs: &[SSTable], secure: bool) {
+    for sstable in outputs {
+        if let Err(e) = remove_data_file(sstable.path(), secure) {
+            warn!("Failed to remove flush output {:?}: {}", sstable.path(), e);
+        }
+    }
+}
+
+/// Writes frozen MemTables out on a background thread, oldest first
+///
+/// Jobs run one at a time in the order they were queued, so level 0 receives
+/// flushed tables in the order their writes happened.
+pub(crate) struct BackgroundFlusher {
+    /// Dropped with the flusher, so the worker exits once the queue is empty
+    jobs: Sender<FlushJob>,
+    /// Locked only so the engine stays `Sync`; the engine alone receives
+    results: Mutex<Receiver<EngineResult<FinishedFlush>>>,
+    /// Jobs queued or running
+    pending: usize,
+}
+
+impl BackgroundFlusher {
+    pub fn start() -> std::io::Result<Self> {
+        let (jobs, queue) = mpsc::channel::<FlushJob>();
+        let (sender, results) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("rustedgedb-flush".to_string())
+            .spawn(move || {
+                for job in queue {
+                    let result = job.run();
+                    if let Err(e) = &result {
+                        error!("Background flush failed: {}", e);
+                    }
+                    // The engine may have been dropped; its next open replays the WAL
+                    let _ = sender.send(result);
+                }
+            })?;
+        Ok(Self {
+            jobs,
+            results: Mutex::new(results),
+            pending: 0,
+        })
+    }
+
+    /// Queue `job` behind the flushes already started
+    pub fn spawn(&mut self, job: FlushJob) {
+        debug!("Queued background flush of {} entries", job.memtable.len());
+        if self.jobs.send(job).is_ok() {
+            self.pending += 1;
+        }
+    }
+
+    /// Results of the flushes that have finished, oldest first, without waiting
+    pub fn finished(&mut self) -> Vec<EngineResult<FinishedFlush>> {
+        let results: Vec<_> = self.results.get_mut().unwrap().try_iter().collect();
+        self.pending -= results.len();
+        results
+    }
+
+    /// Wait for the oldest pending flush, if any
+    pub fn wait_one(&mut self) -> Option<EngineResult<FinishedFlush>> {
+        if self.pending == 0 {
+            return None;
+        }
+        let result = self.results.get_mut().unwrap().recv().ok()?;
+        self.pending -= 1;
+        Some(result)
+    }
+
+    /// Wait for every pending flush and return their results, oldest first
+    pub fn wait(&mut self) -> Vec<EngineResult<FinishedFlush>> {
+        let mut finished = Vec::new();
+        while let Some(result) = self.wait_one() {
+            finished.push(result);
+        }
+        finished
+    }
+}
+
+/// Flushing MemTables into level 0 and retiring the WAL files they cover
+impl Engine {
+    /// Delete every WAL file but the active one
+    ///
+    /// Only call this right after a flush, when the older logs hold nothing
+    /// that isn't in a table. Returns the number of files deleted.
+    pub(crate) fn remove_flushed_wals(&mut self) -> EngineResult<usize> {
+        let mut removed = 0;
+        for wal_path in list_wal_files(&self.config.data_dir) {
+            if wal_path != self.wal.path() {
+                remove_data_file(&wal_path, self.config.secure_delete)?;
+                removed += 1;
+            }
+        }
+        sync_dir(&self.config.data_dir)?;
+        self.retired_wals.clear();
+        Ok(removed)
+    }
+
+    /// Delete the retired WAL files whose writes are all at or below
+    /// `flushed_sequence`
+    ///
+    /// Callers pass the sequence of the oldest MemTable just flushed, so every
+    /// write up to it is in a table. Files already gone are skipped.
+    fn remove_obsolete_wals(&mut self, flushed_sequence: u64) -> EngineResult<usize> {
+        let obsolete = self
+            .retired_wals
+            .iter()
+            .take_while(|(_, last)| *last <= flushed_sequence)
+            .count();
+        if obsolete == 0 {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for (path, _) in self.retired_wals.drain(..obsolete) {
+            match remove_data_file(&path, self.config.secure_delete) {
+                Ok(()) => {
+                    debug!("Removed obsolete WAL {:?}", path);
+                    removed += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        sync_dir(&self.config.data_dir)?;
+        Ok(removed)
+    }
+
+    /// Flush the current MemTable to an SSTable
+    ///
+    /// Immutable MemTables waiting on a background flush hold older writes,
+    /// so they are flushed first. In time-series mode each MemTable is split
+    /// into one SSTable per time partition, so tables never overlap and old
+    /// ones can be dropped whole.
+    pub(crate) async fn flush_memtable(&mut self, reason: FlushReason) -> EngineResult<()> {
+        info!("Flushing MemTable to SSTable");
+        // Whatever a failed background flush left behind is flushed below
+        if let Err(e) = self.install_background_flushes(true) {
+            warn!("Background flush failed, flushing inline: {}", e);
+        }
+        if !self.memtable.is_empty() {
+            self.freeze_memtable()?;
+        }
+        while let Some(oldest) = self.versions.current().immutables.last().cloned() {
+            let finished = self.flush_job(oldest, reason, false).run()?;
+            self.install_flush(finished)?;
+        }
+        // Every write so far is now in a synced table
+        self.mark_durable(self.sequence_number());
+        info!("MemTable flushed successfully");
+
+        self.compact_after_flush()?;
+        Ok(())
+    }
+
+    /// Freeze the active MemTable as the newest immutable one, with a fresh
+    /// MemTable and WAL taking writes in its place
+    fn freeze_memtable(&mut self) -> EngineResult<Arc<MemTable>> {
+        let memtable = Arc::new(MemTable::new(self.config.memtable_size));
+        let version = self
+            .versions
+            .current()
+            .with_frozen_memtable(memtable.clone());
+        self.install_version(version)?;
+        let frozen = std::mem::replace(&mut self.memtable, memtable);
+        self.rotate_wal()?;
+        Ok(frozen)
+    }
+
+    /// Everything needed to write `memtable` out without the engine
+    fn flush_job(
+        &self,
+        memtable: Arc<MemTable>,
+        reason: FlushReason,
+        background: bool,
+    ) -> FlushJob {
+        let scratch_dir = match &self.config.temp_dir {
+            Some(temp_dir) if background => temp_dir.clone(),
+            _ => self.level_dir(0).to_path_buf(),
+        };
+        FlushJob {
+            memtable,
+            reason,
+            time_series: self.config.time_series.clone(),
+            write_options: SSTableWriteOptions {
+                compression: self.config.compression,
+                compression_options: self.config.compression_options,
+                use_direct_io: self.config.use_direct_io,
+                block_size: self.config.block_size,
+                prefix_extractor: self.config.prefix_extractor,
+                ..Default::default()
+            },
+            scratch_dir,
+            secure_delete: self.config.secure_delete,
+        }
+    }
+
+    /// Move a flush's tables into level 0 in place of its immutable MemTable
+    ///
+    /// Readers see the flushed writes in exactly one of the two at any time.
+    fn install_flush(&mut self, finished: FinishedFlush) -> EngineResult<()> {
+        let FinishedFlush {
+            memtable,
+            reason,
+            outputs,
+            entries,
+            duration,
+        } = finished;
+        let level_dir = self.level_dir(0).to_path_buf();
+        let mut flushed = Vec::with_capacity(outputs.len());
+        for mut sstable in outputs {
+            let path = self.timestamped_path(&level_dir, "sstable", "sst");
+            sstable.persist_to(&path)?;
+            debug!(
+                "SSTable created at {:?} with {} entries",
+                path,
+                sstable.entry_count()
+            );
+            flushed.push(Arc::new(sstable));
+        }
+        let version = self
+            .versions
+            .current()
+            .with_flushed_memtable(&memtable, &flushed);
+        self.install_version(version)?;
+        self.mark_durable(memtable.sequence_number());
+        self.remove_obsolete_wals(memtable.sequence_number())?;
+
+        self.record_job(JobKind::Flush(reason), &[], &flushed, duration);
+        self.events.publish(EngineEvent::FlushFinished {
+            reason,
+            sstables: flushed.len(),
+            entries,
+            duration,
+        });
+        Ok(())
+    }
+
+    /// Freeze the full MemTable and queue its flush, so writes go on in a
+    /// fresh one
+    ///
+    /// Writes only stall once `max_immutable_memtables` are waiting, until the
+    /// oldest of them is flushed.
+    pub(crate) fn flush_in_background(&mut self) -> EngineResult<()> {
+        self.install_background_flushes(false)?;
+        if self.versions.current().immutables.len() >= self.config.max_immutable_memtables {
+            let reason = StallReason::ImmutableMemTables;
+            self.events.publish(EngineEvent::StallStarted { reason });
+            let started = Instant::now();
+            let drained = self.drain_oldest_immutable();
+            self.events.publish(EngineEvent::StallEnded {
+                reason,
+                duration: started.elapsed(),
+            });
+            drained?;
+        }
+        let frozen = self.freeze_memtable()?;
+        let job = self.flush_job(frozen, FlushReason::Full, true);
+        if let Some(flusher) = &mut self.flusher {
+            flusher.spawn(job);
+        }
+        Ok(())
+    }
+
+    /// Get the oldest immutable MemTable into level 0, waiting for its
+    /// background flush or, when none is pending, flushing it inline
+    fn drain_oldest_immutable(&mut self) -> EngineResult<()> {
+        match self.flusher.as_mut().and_then(BackgroundFlusher::wait_one) {
+            Some(result) => self.install_flush_results(vec![result]),
+            None => {
+                let Some(oldest) = self.versions.current().immutables.last().cloned() else {
+                    return Ok(());
+                };
+                let finished = self.flush_job(oldest, FlushReason::Full, false).run()?;
+                self.install_flush(finished)?;
+                self.compact_after_flush()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Install background flushes that have finished, first waiting for every
+    /// pending one when `wait` is set
+    ///
+    /// Returns the number installed.
+    pub(crate) fn install_background_flushes(&mut self, wait: bool) -> EngineResult<usize> {
+        let Some(flusher) = &mut self.flusher else {
+            return Ok(0);
+        };
+        let results = if wait {
+            flusher.wait()
+        } else {
+            flusher.finished()
+        };
+        let installed = results.iter().filter(|result| result.is_ok()).count();
+        self.install_flush_results(results)?;
+        Ok(installed)
+    }
+
+    /// Install finished background flushes in order, then run the
+    /// compactions they call for
+    ///
+    /// A failed flush leaves its MemTable immutable, to be flushed inline
+    /// before any newer one is installed; the first failure is returned.
+    fn install_flush_results(
+        &mut self,
+        results: Vec<EngineResult<FinishedFlush>>,
+    ) -> EngineResult<()> {
+        let mut failure = None;
+        let mut installed = 0;
+        for result in results {
+            let finished = match result {
+                Ok(finished) => finished,
+                Err(e) => {
+                    failure.get_or_insert(e);
+                    continue;
+                }
+            };
+            // Level 0 must receive MemTables oldest first
+            while let Some(older) = self
+                .versions
+                .current()
+                .immutables
+                .last()
+                .filter(|oldest| !Arc::ptr_eq(oldest, &finished.memtable))
+                .cloned()
+            {
+                let retried = self
+                    .flush_job(older, FlushReason::Full, false)
+                    .run()
+                    .and_then(|retried| self.install_flush(retried));
+                if let Err(e) = retried {
+                    finished.discard(self.config.secure_delete);
+                    return Err(e);
+                }
+            }
+            self.install_flush(finished)?;
+            installed += 1;
+        }
+        if installed > 0 {
+            self.compact_after_flush()?;
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait for background flushes of immutable MemTables and install them
+    ///
+    /// Returns the number installed; without `max_immutable_memtables` there
+    /// is nothing to wait for.
+    pub fn wait_for_flushes(&mut self) -> EngineResult<usize> {
+        self.install_background_flushes(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compaction::CompactionOptions;
+    use crate::engine::EngineConfig;
+    use crate::sstable::CompressionType;
+    use bytes::Bytes;
+    use tempfile::tempdir;
+
+    /// A flush of one MemTable holding `keys`, written into `scratch_dir`
+    fn flush_job(scratch_dir: &std::path::Path, keys: &[&[u8]]) -> FlushJob {
+        let memtable = Arc::new(MemTable::new(1024 * 1024));
+        for key in keys {
+            memtable.put(key, b"value").unwrap();
+        }
+        FlushJob {
+            memtable,
+            reason: FlushReason::Full,
+            time_series: None,
+            write_options: SSTableWriteOptions::default(),
+            scratch_dir: scratch_dir.to_path_buf(),
+            secure_delete: false,
+        }
+    }
+
+    fn scratch_files(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn test_background_flusher_finishes_jobs_in_queue_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut flusher = BackgroundFlusher::start().unwrap();
+        let jobs = [
+            flush_job(temp_dir.path(), &[b"c", b"d"]),
+            flush_job(temp_dir.path(), &[b"a"]),
+            flush_job(temp_dir.path(), &[b"b", b"e", b"f"]),
+        ];
+        let memtables: Vec<_> = jobs.iter().map(|job| job.memtable.clone()).collect();
+        for job in jobs {
+            flusher.spawn(job);
+        }
+
+        let finished: Vec<_> = flusher.wait().into_iter().map(Result::unwrap).collect();
+        assert_eq!(finished.len(), 3);
+        for (finished, memtable) in finished.iter().zip(&memtables) {
+            assert!(Arc::ptr_eq(&finished.memtable, memtable));
+            assert_eq!(finished.entries, memtable.len());
+            assert_eq!(finished.outputs.len(), 1);
+        }
+        assert_eq!(finished[1].outputs[0].first_key(), Some(b"a".as_slice()));
+
+        // Nothing is pending once every result has been handed back
+        assert!(flusher.wait_one().is_none());
+        assert!(flusher.finished().is_empty());
+        assert_eq!(scratch_files(temp_dir.path()), 3);
+    }
+
+    #[test]
+    fn test_background_flusher_keeps_going_after_a_failed_job() {
+        let temp_dir = tempdir().unwrap();
+        let mut flusher = BackgroundFlusher::start().unwrap();
+        flusher.spawn(flush_job(&temp_dir.path().join("missing"), &[b"a"]));
+        flusher.spawn(flush_job(temp_dir.path(), &[b"b"]));
+
+        // The failure is handed back in its place in the queue, and the
+        // frozen MemTable it carried is left for the engine to flush again
+        assert!(flusher.wait_one().unwrap().is_err());
+        let finished = flusher.wait_one().unwrap().unwrap();
+        assert_eq!(finished.outputs[0].first_key(), Some(b"b".as_slice()));
+        assert!(flusher.wait_one().is_none());
+
+        finished.discard(false);
+        assert_eq!(scratch_files(temp_dir.path()), 0);
+    }
+
+    #[test]
+    fn test_flush_job_splits_time_partitions() {
+        let temp_dir = tempdir().unwrap();
+        let key = |ms: u64| crate::keys::encode_u64(ms).to_vec();
+        let (early, late, next) = (key(100), key(900), key(1500));
+        let mut job = flush_job(temp_dir.path(), &[&early, &late, &next]);
+        job.time_series = Some(TimeSeriesOptions {
+            partition_duration_ms: 1000,
+        });
+
+        let finished = job.run().unwrap();
+        assert_eq!(finished.entries, 3);
+        assert_eq!(finished.outputs.len(), 2);
+        assert_eq!(finished.outputs[0].entry_count(), 2);
+        assert_eq!(finished.outputs[1].first_key(), Some(next.as_slice()));
+
+        finished.discard(false);
+        assert_eq!(scratch_files(temp_dir.path()), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_background_flush_is_retried_inline() {
+        let temp_dir = tempdir().unwrap();
+        let scratch = temp_dir.path().join("scratch");
+        let config = EngineConfig {
+            data_dir: temp_dir.path().join("db"),
+            temp_dir: Some(scratch.clone()),
+            memtable_size: 1024,
+            max_immutable_memtables: 2,
+            compaction: CompactionOptions {
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        std::fs::remove_dir(&scratch).unwrap();
+
+        let key = |i: u32| format!("key{:04}", i).into_bytes();
+        let mut written = 0;
+        while engine.stats().immutable_memtables == 0 {
+            engine.put(&key(written), b"value").await.unwrap();
+            written += 1;
+        }
+
+        // The MemTable whose flush failed stays immutable and readable
+        assert!(engine.wait_for_flushes().is_err());
+        assert_eq!(engine.stats().immutable_memtables, 1);
+        assert_eq!(engine.stats().sstable_count, 0);
+        assert_eq!(
+            engine.get(&key(0)).await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+
+        // The next flush writes it into level 0 before the active MemTable
+        engine.force_flush().await.unwrap();
+        assert_eq!(engine.stats().immutable_memtables, 0);
+        assert_eq!(engine.stats().sstable_count, 2);
+
+        std::fs::create_dir(&scratch).unwrap();
+        engine.close().await.unwrap();
+        drop(engine);
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.iter::<&[u8], _>(..).count(), written as usize);
+    }
+
+    #[tokio::test]
+    async fn test_memtable_flush() {
+        let temp_dir = tempdir().unwrap();
+        let engine_path = temp_dir.path();
+
+        // Create engine with small MemTable size
+        let config = EngineConfig {
+            data_dir: engine_path.to_path_buf(),
+            memtable_size: 100, // Very small to trigger flush
+            compression: CompressionType::None,
+            max_levels: 7,
+            ..Default::default()
+        };
+
+        let mut engine = Engine::with_config(config).await.unwrap();
+
+        // Add data that exceeds MemTable size
+        engine.put(b"key1", b"value1").await.unwrap();
+        engine.put(b"key2", b"value2").await.unwrap();
+        engine.put(b"key3", b"value3").await.unwrap();
+
+        // Force flush
+        engine.force_flush().await.unwrap();
+
+        // Verify data is still accessible
+        let value1 = engine.get(b"key1").await.unwrap();
+        let value2 = engine.get(b"key2").await.unwrap();
+        let value3 = engine.get(b"key3").await.unwrap();
+
+        assert_eq!(value1, Some(Bytes::from_static(b"value1")));
+        assert_eq!(value2, Some(Bytes::from_static(b"value2")));
+        assert_eq!(value3, Some(Bytes::from_static(b"value3")));
+
+        // Check stats
+        let stats = engine.stats();
+        assert_eq!(stats.sstable_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_wal_bytes_triggers_flush() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_wal_bytes: Some(1024),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Each record is well under the MemTable size, so only the WAL limit can fire
+        for i in 0..20 {
+            engine
+                .put(format!("key{:02}", i).as_bytes(), &[b'v'; 100])
+                .await
+                .unwrap();
+        }
+        assert!(engine.stats().sstable_count >= 1);
+        assert!(engine.wal.logged_bytes() <= 1024);
+        assert!(engine.get(b"key00").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_memtable_max_entries_triggers_flush() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::with_config(EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_max_entries: Some(10),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        for i in 0..25 {
+            engine
+                .put(format!("k{:02}", i).as_bytes(), b"v")
+                .await
+                .unwrap();
+        }
+        assert_eq!(engine.stats().sstable_count, 2);
+        assert_eq!(engine.memtable.len(), 5);
+
+        // Overwrites don't add entries
+        for _ in 0..20 {
+            engine.put(b"k00", b"again").await.unwrap();
+        }
+        assert_eq!(engine.stats().sstable_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_background_flush_of_immutable_memtables() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 1024,
+            max_immutable_memtables: 2,
+            compaction: CompactionOptions {
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let key = |i: u32| format!("key{:04}", i).into_bytes();
+        let value = [7u8; 64];
+
+        // The full MemTable is frozen and stays readable until its flush is installed
+        let mut written = 0;
+        while engine.stats().immutable_memtables == 0 {
+            engine.put(&key(written), &value).await.unwrap();
+            written += 1;
+        }
+        assert_eq!(engine.stats().sstable_count, 0);
+        assert_eq!(
+            engine.get(&key(0)).await.unwrap(),
+            Some(Bytes::copy_from_slice(&value))
+        );
+
+        for i in written..200 {
+            engine.put(&key(i), &value).await.unwrap();
+        }
+        engine.delete(&key(0)).await.unwrap();
+        assert!(engine.stats().immutable_memtables <= 2);
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 199);
+
+        engine.wait_for_flushes().unwrap();
+        assert_eq!(engine.stats().immutable_memtables, 0);
+        assert!(engine.stats().sstable_count > 1);
+        assert_eq!(engine.get(&key(0)).await.unwrap(), None);
+        assert_eq!(engine.iter::<&[u8], _>(..).count(), 199);
+
+        engine.close().await.unwrap();
+        drop(engine);
+        let engine = Engine::with_config(config).await.unwrap();
+        for i in 1..200 {
+            assert_eq!(
+                engine.get(&key(i)).await.unwrap(),
+                Some(Bytes::copy_from_slice(&value))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flushed_wals_are_removed() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 1024,
+            max_immutable_memtables: 2,
+            env_overrides: false,
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        assert_eq!(list_wal_files(temp_dir.path()), vec![engine.wal.path()]);
+
+        // A frozen MemTable's log stays until its background flush is installed
+        let mut written = 0u32;
+        while engine.stats().immutable_memtables == 0 {
+            engine
+                .put(&written.to_be_bytes(), &[2u8; 64])
+                .await
+                .unwrap();
+            written += 1;
+        }
+        assert!(!engine.retired_wals.is_empty());
+        assert!(engine.retired_wals.iter().all(|(path, _)| path.exists()));
+        engine.wait_for_flushes().unwrap();
+        assert_eq!(list_wal_files(temp_dir.path()), vec![engine.wal.path()]);
+
+        // Writes after a reopen go to a new log that replays after the old ones
+        engine.put(b"key", b"3").await.unwrap();
+        drop(engine);
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"4").await.unwrap();
+        drop(engine);
+        let mut engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(engine.get(b"key").await.unwrap(), Some(Bytes::from("4")));
+        engine.force_flush().await.unwrap();
+        assert_eq!(list_wal_files(temp_dir.path()), vec![engine.wal.path()]);
+    }
+
+    #[tokio::test]
+    async fn test_direct_io_flush() {
+        let temp_dir = tempdir().unwrap();
+        let engine_path = temp_dir.path();
+
+        let config = EngineConfig {
+            data_dir: engine_path.to_path_buf(),
+            use_direct_io: true,
+            ..Default::default()
+        };
+
+        {
+            let mut engine = Engine::with_config(config.clone()).await.unwrap();
+            engine.put(b"direct", b"value").await.unwrap();
+            engine.force_flush().await.unwrap();
+            assert_eq!(
+                engine.get(b"direct").await.unwrap(),
+                Some(Bytes::from_static(b"value"))
+            );
+        }
+
+        // Reopen and read the directly written table back from disk
+        let engine = Engine::with_config(config).await.unwrap();
+        assert_eq!(
+            engine.get(b"direct").await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use crate::blob::BlobStore;
+use crate::engine::{EngineConfig, EngineError, EngineResult, find_entry_in, list_wal_files};
+use crate::expiry::{self, Expiries};
+use crate::iterator::{EngineIterator, MergingIterator, Source, read_base};
+use crate::layout::{self, CURRENT_LAYOUT_VERSION, LayoutError};
+use crate::manifest::{MANIFEST_FILE, Manifest};
+use crate::memtable::MemTable;
+use crate::merge;
+use crate::sstable::{SSTable, SSTableError};
+use crate::version::Version;
+use crate::wal::WAL;
+
+/// Attempts at loading a consistent set of tables while the leader replaces them
+const MAX_RELOAD_ATTEMPTS: usize = 3;
+
+/// Read-only view of a database that another process has open for writing
+///
+/// Loads the SSTables listed in the manifest and replays the WAL on top of
+/// them, then [`Follower::refresh`] picks up the leader's new writes by
+/// reading the WAL from where it left off. Nothing in the data directory is
+/// ever created, changed or removed, so local analytics can read a live
+/// database without going through the IPC server.
+pub struct Follower {
+    data_dir: PathBuf,
+    /// Other directories the leader keeps SSTables in, see [`EngineConfig::level_dirs`]
+    level_dirs: Vec<PathBuf>,
+    /// Manifest the tables were loaded from, to notice flushes and compactions
+    manifest: String,
+    version: Version,
+    blobs: Arc<BlobStore>,
+    expiries: Expiries,
+    /// Newest write held by the loaded SSTables; older WAL records are skipped
+    flushed_sequence: u64,
+    sequence_number: u64,
+    /// How far each WAL file has been read
+    wal_offsets: HashMap<PathBuf, u64>,
+}
+
+impl Follower {
+    /// Attach to the database in `data_dir`
+    ///
+    /// The directory must already be at the current layout version; open it
+    /// with an [`crate::engine::Engine`] first to upgrade it.
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> EngineResult<Self> {
+        Self::with_config(&EngineConfig {
+            data_dir: data_dir.as_ref().to_path_buf(),
+            ..Default::default()
+        })
+    }
+
+    /// Attach to the database the leader opened with `config`
+    ///
+    /// Only the data directory and the leader's `level_dirs` are used.
+    pub fn with_config(config: &EngineConfig) -> EngineResult<Self> {
+        let data_dir = config.data_dir.clone();
+        match layout::read_version(&data_dir)? {
+            Some(found) if found > CURRENT_LAYOUT_VERSION => {
+                return Err(LayoutError::Unsupported {
+                    found,
+                    supported: CURRENT_LAYOUT_VERSION,
+                }
+                .into());
+            }
+            Some(found) if found < CURRENT_LAYOUT_VERSION => {
+                return Err(EngineError::InvalidConfig(format!(
+                    "Layout v{} must be upgraded by a writer before a follower can attach",
+                    found
+                )));
+            }
+            _ => {}
+        }
+
+        let memtable = Arc::new(MemTable::new(usize::MAX));
+        let mut follower = Self {
+            blobs: Arc::new(BlobStore::open_read_only(data_dir.join("blobs"))),
+            data_dir,
+            level_dirs: config.level_dirs.values().cloned().collect(),
+            manifest: String::new(),
+            version: Version::new(memtable, EngineConfig::default().max_levels),
+            expiries: Expiries::default(),
+            flushed_sequence: 0,
+            sequence_number: 0,
+            wal_offsets: HashMap::new(),
+        };
+        follower.reload()?;
+        info!(
+            "Follower attached to {:?} at sequence {}",
+            follower.data_dir, follower.sequence_number
+        );
+        Ok(follower)
+    }
+
+    /// Catch up with the leader
+    ///
+    /// Reloads the tables if the manifest changed since the last call, which
+    /// a flush, compaction or truncate does, and otherwise only reads WAL
+    /// records appended since then. Returns the number of records applied.
+    pub fn refresh(&mut self) -> EngineResult<usize> {
+        if read_manifest(&self.data_dir)? != self.manifest {
+            return self.reload();
+        }
+        self.tail_wal()
+    }
+
+    /// Sequence number of the newest write this follower has seen
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// Get a value by key, as of the last refresh
+    pub fn get(&self, key: &[u8]) -> EngineResult<Option<Bytes>> {
+        if self.expiries.is_expired(key, expiry::now_secs()) {
+            return Ok(None);
+        }
+        match find_entry_in(&self.version, &self.blobs, key, |_, e| e.into())? {
+            Some(entry) => read_base(&self.blobs, entry),
+            None => Ok(None),
+        }
+    }
+
+    /// Lazily iterate over live key-value pairs in `range`, as of the last refresh
+    pub fn iter<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> EngineIterator {
+        let start = range.start_bound().map(|k| k.as_ref());
+        let end = range.end_bound().map(|k| k.as_ref());
+        let memtable = self
+            .version
+            .memtable
+            .range::<&[u8], _>((start, end))
+            .collect();
+        EngineIterator::new(
+            vec![memtable],
+            self.version.sstables().cloned().collect(),
+            start,
+            end,
+            self.blobs.clone(),
+            self.expiries.clone(),
+            expiry::now_secs(),
+        )
+    }
+
+    /// Scan live key-value pairs in `range`, in key order
+    pub fn scan<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> EngineResult<Vec<(Vec<u8>, Bytes)>> {
+        self.iter(range).collect()
+    }
+
+    /// Load the tables in the manifest, then replay every WAL file over them
+    fn reload(&mut self) -> EngineResult<usize> {
+        let mut attempt = 0;
+        let (manifest, levels) = loop {
+            let text = read_manifest(&self.data_dir)?;
+            match self.open_tables(&Manifest::load(&self.data_dir)?) {
+                Ok(levels) if read_manifest(&self.data_dir)? == text => break (text, levels),
+                // A compaction replaced tables while they were opened
+                Ok(_) => {}
+                Err(EngineError::SSTable(SSTableError::Io(e)))
+                    if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            attempt += 1;
+            if attempt == MAX_RELOAD_ATTEMPTS {
+                return Err(EngineError::RecoveryFailed(
+                    "Tables kept changing while the follower loaded them".to_string(),
+                ));
+            }
+        };
+
+        self.manifest = manifest;
+        self.version = Version {
+            memtable: Arc::new(MemTable::new(usize::MAX)),
+            immutables: Vec::new(),
+            levels,
+        };
+        self.flushed_sequence = self
+            .version
+            .sstables()
+            .filter_map(|t| t.sequence_range())
+            .map(|(_, max)| max)
+            .max()
+            .unwrap_or(0);
+        self.sequence_number = self.flushed_sequence;
+        self.wal_offsets.clear();
+        self.expiries = self.load_expiries()?;
+        debug!(
+            "Follower loaded {} SSTables up to sequence {}",
+            self.version.sstable_count(),
+            self.flushed_sequence
+        );
+        self.tail_wal()
+    }
+
+    /// Open the tables listed in `manifest`, ordered the way the leader reads them
+    fn open_tables(&self, manifest: &Manifest) -> EngineResult<Vec<Vec<Arc<SSTable>>>> {
+        let mut levels: Vec<Vec<Arc<SSTable>>> =
+            vec![Vec::new(); EngineConfig::default().max_levels];
+        for name in manifest.names() {
+            let Some(entry) = manifest.get(name) else {
+                continue;
+            };
+            let sstable = SSTable::open(self.locate_table(name))?;
+            match entry.sequence_range {
+                Some(range) => sstable.set_sequence_range(range),
+                None => {
+                    sstable.load_sequence_range()?;
+                }
+            }
+            if levels.len() <= entry.level {
+                levels.resize_with(entry.level + 1, Vec::new);
+            }
+            levels[entry.level].push(Arc::new(sstable));
+        }
+        levels[0]
+            .sort_by_key(|t| std::cmp::Reverse(t.sequence_range().map(|(min, max)| (max, min))));
+        for level in &mut levels[1..] {
+            level.sort_by(|a, b| a.first_key().cmp(&b.first_key()));
+        }
+        Ok(levels)
+    }
+
+    /// Path of an SSTable, searching the leader's level directories
+    fn locate_table(&self, name: &str) -> PathBuf {
+        self.level_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| self.data_dir.join(name))
+    }
+
+    /// Apply WAL records written since the last read
+    fn tail_wal(&mut self) -> EngineResult<usize> {
+        let mut applied = 0;
+        let wal_files = list_wal_files(&self.data_dir);
+        self.wal_offsets.retain(|path, _| wal_files.contains(path));
+        for path in wal_files {
+            let offset = self.wal_offsets.get(&path).copied().unwrap_or(0);
+            let (records, end) = match WAL::tail(&path, offset) {
+                Ok(read) => read,
+                // Removed by the leader since it was listed
+                Err(crate::wal::WALError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.wal_offsets.insert(path, end);
+
+            for record in records {
+                // Older records are already in the loaded tables
+                if record.sequence_number <= self.flushed_sequence {
+                    continue;
+                }
+                self.sequence_number = self.sequence_number.max(record.sequence_number);
+                let entry = record.to_entry();
+                if let Some(key) = expiry::decode_expiry_key(&entry.key) {
+                    match entry.value.as_deref().and_then(expiry::decode_deadline) {
+                        Some(deadline) => self.expiries.set(&key, deadline),
+                        None => {
+                            self.expiries.remove(&key);
+                        }
+                    }
+                }
+                self.version.memtable.apply(entry)?;
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Read the expiry records held by the loaded tables
+    fn load_expiries(&self) -> EngineResult<Expiries> {
+        let prefix = expiry::expiry_prefix();
+        let sources: Vec<Source> = self
+            .version
+            .sstables()
+            .map(|sstable| -> Source {
+                let mut iter = sstable.iter_shared();
+                iter.seek(&prefix);
+                Box::new(iter.map(|entry| entry.map_err(Into::into)))
+            })
+            .collect();
+
+        let mut expiries = Expiries::default();
+        for entry in MergingIterator::new(sources, self.blobs.clone()) {
+            let entry = merge::resolve(entry?)?;
+            if !entry.key.starts_with(&prefix) {
+                break;
+            }
+            let deadline = entry.value.as_deref().and_then(expiry::decode_deadline);
+            if let (Some(key), Some(deadline)) = (expiry::decode_expiry_key(&entry.key), deadline) {
+                expiries.set(&key, deadline);
+            }
+        }
+        Ok(expiries)
+    }
+}
+
+/// Current manifest contents, empty if there is none yet
+fn read_manifest(data_dir: &Path) -> EngineResult<String> {
+    match std::fs::read_to_string(data_dir.join(MANIFEST_FILE)) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_follower_tails_leader() {
+        let temp_dir = tempdir().unwrap();
+        let mut leader = Engine::new(temp_dir.path()).await.unwrap();
+        leader.put(b"flushed", b"v1").await.unwrap();
+        leader.force_flush().await.unwrap();
+        leader.put(b"logged", b"v1").await.unwrap();
+        leader.incr(b"count", 2).await.unwrap();
+
+        let mut follower = Follower::open(temp_dir.path()).unwrap();
+        assert_eq!(follower.sequence_number(), leader.sequence_number());
+        assert_eq!(follower.get(b"flushed").unwrap().unwrap().as_ref(), b"v1");
+        assert_eq!(follower.get(b"logged").unwrap().unwrap().as_ref(), b"v1");
+
+        // New WAL records are picked up without reloading tables
+        leader.put(b"logged", b"v2").await.unwrap();
+        leader.delete(b"flushed").await.unwrap();
+        leader.incr(b"count", 3).await.unwrap();
+        assert_eq!(follower.refresh().unwrap(), 3);
+        assert_eq!(follower.get(b"logged").unwrap().unwrap().as_ref(), b"v2");
+        assert_eq!(follower.get(b"flushed").unwrap(), None);
+        assert_eq!(follower.refresh().unwrap(), 0);
+
+        // A flush and compaction change the manifest and reload everything
+        leader.force_flush().await.unwrap();
+        leader.compact_all().await.unwrap();
+        leader.put(b"after", b"v1").await.unwrap();
+        leader.expire_at(b"logged", 1).await.unwrap();
+        follower.refresh().unwrap();
+        assert_eq!(follower.sequence_number(), leader.sequence_number());
+        let count = follower.get(b"count").unwrap().unwrap();
+        assert_eq!(merge::decode_i64(&count), Ok(5));
+        assert_eq!(follower.get(b"logged").unwrap(), None);
+        let keys: Vec<Vec<u8>> = follower
+            .scan::<&[u8], _>(..)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"after".to_vec(), b"count".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_follower_never_writes() {
+        let temp_dir = tempdir().unwrap();
+        let mut leader = Engine::new(temp_dir.path()).await.unwrap();
+        leader.put(b"key", b"value").await.unwrap();
+        leader.force_flush().await.unwrap();
+
+        let listing = |dir: &Path| {
+            let mut names: Vec<_> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (entry.file_name(), entry.metadata().unwrap().len())
+                })
+                .collect();
+            names.sort();
+            names
+        };
+        let before = listing(temp_dir.path());
+        let mut follower = Follower::open(temp_dir.path()).unwrap();
+        follower.refresh().unwrap();
+        assert_eq!(follower.get(b"key").unwrap().unwrap().as_ref(), b"value");
+        assert_eq!(listing(temp_dir.path()), before);
+    }
+}
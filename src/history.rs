@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::compaction::CompactionReason;
+
+/// File in the data directory holding recent flush and compaction records
+pub const HISTORY_FILE: &str = "HISTORY";
+
+/// Number of records kept; the file is trimmed to this many as it grows
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Why the MemTable was flushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushReason {
+    /// A MemTable size, entry count or WAL size limit was reached on the write path
+    Full,
+    /// WAL replay left a full MemTable at open
+    Recovery,
+    /// Requested through `force_flush` or `close`, or by an operation that
+    /// works on tables only, such as an export
+    Manual,
+}
+
+/// What a [`JobRecord`] describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Flush(FlushReason),
+    Compaction {
+        reason: CompactionReason,
+        level: usize,
+        output_level: usize,
+    },
+}
+
+/// One finished flush or compaction
+///
+/// Displays as the line stored in [`HISTORY_FILE`]:
+/// `<finished ms> flush <reason> <µs>us in=<files> out=<files>`, with
+/// `compaction <reason> L<level>->L<output level>` for compactions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobRecord {
+    /// Unix time in milliseconds when the job finished
+    pub finished_at_ms: u64,
+    pub kind: JobKind,
+    /// File names of the SSTables replaced
+    pub inputs: Vec<String>,
+    /// File names of the SSTables written
+    pub outputs: Vec<String>,
+    pub duration: Duration,
+}
+
+impl fmt::Display for JobRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.finished_at_ms)?;
+        match self.kind {
+            JobKind::Flush(reason) => write!(f, "flush {}", flush_reason_name(reason))?,
+            JobKind::Compaction {
+                reason,
+                level,
+                output_level,
+            } => write!(
+                f,
+                "compaction {} L{}->L{}",
+                compaction_reason_name(reason),
+                level,
+                output_level
+            )?,
+        }
+        write!(
+            f,
+            " {}us in={} out={}",
+            self.duration.as_micros(),
+            self.inputs.join(","),
+            self.outputs.join(",")
+        )
+    }
+}
+
+impl JobRecord {
+    /// Parse a line written by the `Display` impl
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split(' ');
+        let finished_at_ms = fields.next()?.parse().ok()?;
+        let kind = match fields.next()? {
+            "flush" => JobKind::Flush(parse_flush_reason(fields.next()?)?),
+            "compaction" => {
+                let reason = parse_compaction_reason(fields.next()?)?;
+                let (level, output_level) = fields.next()?.split_once("->")?;
+                JobKind::Compaction {
+                    reason,
+                    level: level.strip_prefix('L')?.parse().ok()?,
+                    output_level: output_level.strip_prefix('L')?.parse().ok()?,
+                }
+            }
+            _ => return None,
+        };
+        let duration = Duration::from_micros(fields.next()?.strip_suffix("us")?.parse().ok()?);
+        let files = |field: Option<&str>, prefix: &str| -> Option<Vec<String>> {
+            let list = field?.strip_prefix(prefix)?;
+            Some(
+                list.split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            )
+        };
+        let inputs = files(fields.next(), "in=")?;
+        let outputs = files(fields.next(), "out=")?;
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            finished_at_ms,
+            kind,
+            inputs,
+            outputs,
+            duration,
+        })
+    }
+}
+
+/// The last [`HISTORY_LIMIT`] jobs, persisted in [`HISTORY_FILE`]
+///
+/// Records are appended one line at a time and the file is rewritten with
+/// only the newest ones once it holds twice the limit. The history is a
+/// diagnostic aid, so unreadable lines are skipped instead of failing open.
+#[derive(Debug)]
+pub struct JobHistory {
+    path: PathBuf,
+    records: VecDeque<JobRecord>,
+    /// Lines in the file, including records already trimmed from memory
+    file_lines: usize,
+}
+
+impl JobHistory {
+    /// Load the history kept in `data_dir`, if any
+    pub fn open(data_dir: &Path) -> Self {
+        let path = data_dir.join(HISTORY_FILE);
+        let mut records = VecDeque::new();
+        let mut file_lines = 0;
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            for line in contents.lines() {
+                file_lines += 1;
+                match JobRecord::parse(line) {
+                    Some(record) => records.push_back(record),
+                    None => warn!("Ignoring malformed history line {:?}", line),
+                }
+            }
+        }
+        while records.len() > HISTORY_LIMIT {
+            records.pop_front();
+        }
+        Self {
+            path,
+            records,
+            file_lines,
+        }
+    }
+
+    /// Recorded jobs, oldest first
+    pub fn records(&self) -> impl Iterator<Item = &JobRecord> {
+        self.records.iter()
+    }
+
+    /// Add a finished job and persist it
+    pub fn record(&mut self, record: JobRecord) -> io::Result<()> {
+        let line = format!("{}\n", record);
+        self.records.push_back(record);
+        if self.records.len() > HISTORY_LIMIT {
+            self.records.pop_front();
+        }
+
+        if self.file_lines >= 2 * HISTORY_LIMIT {
+            let contents: String = self.records.iter().map(|r| format!("{}\n", r)).collect();
+            let tmp_path = self.path.with_extension("tmp");
+            std::fs::write(&tmp_path, contents)?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            self.file_lines = self.records.len();
+        } else {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            file.write_all(line.as_bytes())?;
+            self.file_lines += 1;
+        }
+        Ok(())
+    }
+}
+
+fn flush_reason_name(reason: FlushReason) -> &'static str {
+    match reason {
+        FlushReason::Full => "full",
+        FlushReason::Recovery => "recovery",
+        FlushReason::Manual => "manual",
+    }
+}
+
+fn parse_flush_reason(name: &str) -> Option<FlushReason> {
+    [
+        FlushReason::Full,
+        FlushReason::Recovery,
+        FlushReason::Manual,
+    ]
+    .into_iter()
+    .find(|&reason| flush_reason_name(reason) == name)
+}
+
+fn compaction_reason_name(reason: CompactionReason) -> &'static str {
+    match reason {
+        CompactionReason::Level0FileCount => "level0-file-count",
+        CompactionReason::LevelSize => "level-size",
+        CompactionReason::OversizedFile => "oversized-file",
+        CompactionReason::Tombstones => "tombstones",
+        CompactionReason::SizeTier => "size-tier",
+        CompactionReason::Manual => "manual",
+    }
+}
+
+fn parse_compaction_reason(name: &str) -> Option<CompactionReason> {
+    [
+        CompactionReason::Level0FileCount,
+        CompactionReason::LevelSize,
+        CompactionReason::OversizedFile,
+        CompactionReason::Tombstones,
+        CompactionReason::SizeTier,
+        CompactionReason::Manual,
+    ]
+    .into_iter()
+    .find(|&reason| compaction_reason_name(reason) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn compaction(finished_at_ms: u64) -> JobRecord {
+        JobRecord {
+            finished_at_ms,
+            kind: JobKind::Compaction {
+                reason: CompactionReason::Level0FileCount,
+                level: 0,
+                output_level: 1,
+            },
+            inputs: vec!["a.sst".to_string(), "b.sst".to_string()],
+            outputs: vec!["c.sst".to_string()],
+            duration: Duration::from_micros(1500),
+        }
+    }
+
+    #[test]
+    fn test_job_record_roundtrip() {
+        let record = compaction(1_700_000_000_000);
+        let line = record.to_string();
+        assert_eq!(
+            line,
+            "1700000000000 compaction level0-file-count L0->L1 1500us in=a.sst,b.sst out=c.sst"
+        );
+        assert_eq!(JobRecord::parse(&line), Some(record));
+
+        let flush = JobRecord {
+            finished_at_ms: 5,
+            kind: JobKind::Flush(FlushReason::Full),
+            inputs: Vec::new(),
+            outputs: vec!["d.sst".to_string()],
+            duration: Duration::ZERO,
+        };
+        assert_eq!(JobRecord::parse(&flush.to_string()), Some(flush));
+        assert_eq!(JobRecord::parse("5 flush sideways 0us in= out="), None);
+    }
+
+    #[test]
+    fn test_history_is_trimmed_and_reloaded() {
+        let temp_dir = tempdir().unwrap();
+        let mut history = JobHistory::open(temp_dir.path());
+        assert_eq!(history.records().count(), 0);
+
+        for i in 0..2 * HISTORY_LIMIT as u64 + 5 {
+            history.record(compaction(i)).unwrap();
+        }
+        assert_eq!(history.records().count(), HISTORY_LIMIT);
+        let contents = std::fs::read_to_string(temp_dir.path().join(HISTORY_FILE)).unwrap();
+        assert!(contents.lines().count() < 2 * HISTORY_LIMIT);
+
+        let reopened = JobHistory::open(temp_dir.path());
+        let kept: Vec<u64> = reopened.records().map(|r| r.finished_at_ms).collect();
+        let expected: Vec<u64> = (HISTORY_LIMIT as u64 + 5..2 * HISTORY_LIMIT as u64 + 5).collect();
+        assert_eq!(kept, expected);
+    }
+}
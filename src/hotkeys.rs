@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Options for hot-key tracking
+#[derive(Debug, Clone)]
+pub struct HotKeyOptions {
+    /// Number of hot keys reported in [`crate::engine::EngineStats`]
+    pub top_n: usize,
+    /// Track key prefixes of this many bytes instead of whole keys
+    pub prefix_len: Option<usize>,
+    /// Counters per sketch row; wider sketches overestimate less
+    pub sketch_width: usize,
+    /// Sketch rows, each with its own hash
+    pub sketch_depth: usize,
+}
+
+impl Default for HotKeyOptions {
+    fn default() -> Self {
+        Self {
+            top_n: 10,
+            prefix_len: None,
+            sketch_width: 2048,
+            sketch_depth: 4,
+        }
+    }
+}
+
+/// A key or prefix with its estimated number of accesses
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub struct HotKey {
+    pub key: Vec<u8>,
+    pub count: u64,
+}
+
+/// Count-min sketch of access frequencies
+///
+/// Estimates never undercount; collisions can only inflate them.
+#[derive(Debug, Clone)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> Self {
+        let (width, depth) = (width.max(1), depth.max(1));
+        Self {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+        }
+    }
+
+    /// Count one access to `key` and return its new estimate
+    pub fn increment(&mut self, key: &[u8]) -> u64 {
+        let mut estimate = u64::MAX;
+        for row in 0..self.depth {
+            let slot = row * self.width + self.column(key, row);
+            self.counters[slot] = self.counters[slot].saturating_add(1);
+            estimate = estimate.min(self.counters[slot]);
+        }
+        estimate
+    }
+
+    /// Estimated number of accesses to `key`
+    pub fn estimate(&self, key: &[u8]) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[row * self.width + self.column(key, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Counter column for `key` in `row`: FNV-1a seeded per row
+    fn column(&self, key: &[u8], row: usize) -> usize {
+        let mut hash: u64 = 0xcbf29ce484222325 ^ (row as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        for &byte in key {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        // Mix the high bits down so nearby keys spread across columns
+        hash ^= hash >> 33;
+        (hash % self.width as u64) as usize
+    }
+}
+
+/// Tracks approximate access frequency and the most accessed keys
+#[derive(Debug)]
+pub struct HotKeyTracker {
+    options: HotKeyOptions,
+    state: Mutex<TrackerState>,
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    sketch: CountMinSketch,
+    /// Current top keys and their estimates
+    top: HashMap<Vec<u8>, u64>,
+}
+
+impl HotKeyTracker {
+    pub fn new(options: HotKeyOptions) -> Self {
+        let sketch = CountMinSketch::new(options.sketch_width, options.sketch_depth);
+        Self {
+            options,
+            state: Mutex::new(TrackerState {
+                sketch,
+                top: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Count one access to `key`
+    pub fn record(&self, key: &[u8]) {
+        let key = match self.options.prefix_len {
+            Some(len) => &key[..len.min(key.len())],
+            None => key,
+        };
+        let mut state = self.state.lock().unwrap();
+        let estimate = state.sketch.increment(key);
+
+        if let Some(count) = state.top.get_mut(key) {
+            *count = estimate;
+            return;
+        }
+        if state.top.len() < self.options.top_n {
+            state.top.insert(key.to_vec(), estimate);
+            return;
+        }
+        // Replace the coldest tracked key if this one has overtaken it
+        let coldest = state
+            .top
+            .iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(key, count)| (key.clone(), *count));
+        if let Some((coldest, count)) = coldest
+            && estimate > count
+        {
+            state.top.remove(&coldest);
+            state.top.insert(key.to_vec(), estimate);
+        }
+    }
+
+    /// Estimated number of accesses to `key` (or its prefix)
+    pub fn estimate(&self, key: &[u8]) -> u64 {
+        let key = match self.options.prefix_len {
+            Some(len) => &key[..len.min(key.len())],
+            None => key,
+        };
+        self.state.lock().unwrap().sketch.estimate(key)
+    }
+
+    /// Tracked hot keys, most accessed first
+    pub fn top(&self) -> Vec<HotKey> {
+        let state = self.state.lock().unwrap();
+        let mut top: Vec<HotKey> = state
+            .top
+            .iter()
+            .map(|(key, count)| HotKey {
+                key: key.clone(),
+                count: *count,
+            })
+            .collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_key_tracker() {
+        let tracker = HotKeyTracker::new(HotKeyOptions {
+            top_n: 2,
+            ..Default::default()
+        });
+        for i in 0..100u32 {
+            tracker.record(format!("cold{}", i).as_bytes());
+            if i % 2 == 0 {
+                tracker.record(b"hot");
+            }
+            if i % 4 == 0 {
+                tracker.record(b"warm");
+            }
+        }
+
+        let top = tracker.top();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].key, b"hot");
+        assert!(top[0].count >= 50);
+        assert_eq!(top[1].key, b"warm");
+        assert!(tracker.estimate(b"cold7") >= 1);
+
+        // Prefixes aggregate every key that shares them
+        let tracker = HotKeyTracker::new(HotKeyOptions {
+            prefix_len: Some(5),
+            ..Default::default()
+        });
+        for i in 0..10 {
+            tracker.record(format!("user:{}", i).as_bytes());
+        }
+        tracker.record(b"item:1");
+        assert_eq!(
+            tracker.top(),
+            vec![
+                HotKey {
+                    key: b"user:".to_vec(),
+                    count: 10
+                },
+                HotKey {
+                    key: b"item:".to_vec(),
+                    count: 1
+                },
+            ]
+        );
+    }
+}
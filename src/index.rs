@@ -0,0 +1,156 @@
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use crate::keys::KeyEncoder;
+
+/// Prefix reserved for keys the engine maintains itself, such as index entries
+pub const INTERNAL_KEY_PREFIX: &[u8] = b"\xFF\xFFrustedgedb\x00";
+
+/// Extracts the index key from a primary value, or None to leave the value unindexed
+pub type IndexExtractor = Arc<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Check whether a key lives in the engine's reserved keyspace
+pub fn is_internal_key(key: &[u8]) -> bool {
+    key.starts_with(INTERNAL_KEY_PREFIX)
+}
+
+/// A secondary index over primary values
+///
+/// Each indexed value is stored as an internal entry keyed by
+/// `(index name, index key, primary key)`, with the primary key as its value,
+/// so a range of index keys is a single contiguous range of entries.
+#[derive(Clone)]
+pub struct SecondaryIndex {
+    name: String,
+    extractor: IndexExtractor,
+    /// Key prefix shared by every entry of this index
+    prefix: Vec<u8>,
+}
+
+impl SecondaryIndex {
+    /// Create an index definition
+    pub fn new(name: &str, extractor: IndexExtractor) -> Self {
+        let mut prefix = INTERNAL_KEY_PREFIX.to_vec();
+        prefix.extend(KeyEncoder::new().str("idx").str(name).finish());
+        Self {
+            name: name.to_string(),
+            extractor,
+            prefix,
+        }
+    }
+
+    /// Index name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Extract the index key for a primary value
+    pub fn extract(&self, value: &[u8]) -> Option<Vec<u8>> {
+        (self.extractor)(value)
+    }
+
+    /// Internal key of the entry linking `index_key` to `primary_key`
+    pub fn entry_key(&self, index_key: &[u8], primary_key: &[u8]) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend(
+            KeyEncoder::new()
+                .bytes(index_key)
+                .bytes(primary_key)
+                .finish(),
+        );
+        key
+    }
+
+    /// Translate a range of index keys into a range of internal entry keys
+    ///
+    /// Encoded components end in `0x00 0x01`, so bumping that last byte to
+    /// `0x02` gives the first key past every entry for a given index key.
+    pub fn entry_range<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let component = |index_key: &K, past_end: bool| {
+            let mut key = self.prefix.clone();
+            key.extend(KeyEncoder::new().bytes(index_key.as_ref()).finish());
+            if past_end {
+                *key.last_mut().unwrap() += 1;
+            }
+            key
+        };
+
+        let start = match range.start_bound() {
+            Bound::Included(k) => Bound::Included(component(k, false)),
+            Bound::Excluded(k) => Bound::Included(component(k, true)),
+            Bound::Unbounded => Bound::Included(self.prefix.clone()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(k) => Bound::Excluded(component(k, true)),
+            Bound::Excluded(k) => Bound::Excluded(component(k, false)),
+            Bound::Unbounded => {
+                // The prefix itself ends in 0x00 0x01 from the encoded name
+                let mut end = self.prefix.clone();
+                *end.last_mut().unwrap() += 1;
+                Bound::Excluded(end)
+            }
+        };
+        (start, end)
+    }
+}
+
+impl fmt::Debug for SecondaryIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecondaryIndex")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_byte_index() -> SecondaryIndex {
+        SecondaryIndex::new("first", Arc::new(|v: &[u8]| v.first().map(|b| vec![*b])))
+    }
+
+    fn contains(range: &(Bound<Vec<u8>>, Bound<Vec<u8>>), key: &[u8]) -> bool {
+        let bounds = (
+            range.0.as_ref().map(|k| k.as_slice()),
+            range.1.as_ref().map(|k| k.as_slice()),
+        );
+        RangeBounds::<[u8]>::contains(&bounds, key)
+    }
+
+    #[test]
+    fn test_entry_keys_are_internal() {
+        let index = first_byte_index();
+        assert_eq!(index.extract(b"abc"), Some(b"a".to_vec()));
+        assert_eq!(index.extract(b""), None);
+        assert!(is_internal_key(&index.entry_key(b"a", b"pk")));
+        assert!(!is_internal_key(b"user:1"));
+    }
+
+    #[test]
+    fn test_entry_range_bounds() {
+        let index = first_byte_index();
+        let other = SecondaryIndex::new("firstx", index.extractor.clone());
+        let entry = |ik: &[u8]| index.entry_key(ik, b"\xFF\xFFpk");
+
+        let range = index.entry_range(b"b".as_slice()..=b"c".as_slice());
+        assert!(!contains(&range, &entry(b"a")));
+        assert!(contains(&range, &entry(b"b")));
+        assert!(contains(&range, &entry(b"c")));
+        assert!(!contains(&range, &entry(b"c\0")));
+        assert!(!contains(&range, &entry(b"d")));
+
+        let range = index.entry_range(b"b".as_slice()..b"c".as_slice());
+        assert!(contains(&range, &entry(b"b\xFF")));
+        assert!(!contains(&range, &entry(b"c")));
+
+        let range = index.entry_range::<&[u8], _>(..);
+        assert!(contains(&range, &entry(b"")));
+        assert!(contains(&range, &entry(b"\xFF\xFF")));
+        assert!(!contains(&range, &other.entry_key(b"a", b"pk")));
+    }
+}
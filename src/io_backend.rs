@@ -0,0 +1,503 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Alignment required for O_DIRECT buffers, offsets, and lengths
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// Size of the aligned staging buffer used for direct writes
+const DIRECT_IO_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// I/O backend used for WAL appends and SSTable reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "stats", derive(serde::Serialize))]
+pub enum IoBackend {
+    /// Portable positioned reads and appends through `std::fs`
+    Portable,
+    /// Linux io_uring submission queue (requires the `io-uring` feature)
+    IoUring,
+}
+
+/// Process-wide backend, probed once on first use
+static BACKEND: OnceLock<IoBackend> = OnceLock::new();
+
+impl IoBackend {
+    /// Get the process-wide backend, probing for io_uring support on first use
+    pub fn current() -> Self {
+        *BACKEND.get_or_init(Self::detect)
+    }
+
+    /// Pick io_uring when it was compiled in and the kernel supports it
+    fn detect() -> Self {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if sys::uring::probe() {
+            info!("Using io_uring I/O backend");
+            return Self::IoUring;
+        }
+
+        info!("Using portable I/O backend");
+        Self::Portable
+    }
+
+    /// Read exactly `buf.len()` bytes from `file` starting at `offset`
+    ///
+    /// Positioned reads never move the file cursor, so callers don't need to seek.
+    pub fn read_exact_at(self, file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Self::IoUring => sys::uring::read_exact_at(file, buf, offset),
+            _ => portable::read_exact_at(file, buf, offset),
+        }
+    }
+
+    /// Append all of `buf` to a file that was opened in append mode
+    pub fn append(self, file: &File, buf: &[u8]) -> io::Result<()> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Self::IoUring => sys::uring::append(file, buf),
+            _ => portable::append(file, buf),
+        }
+    }
+}
+
+/// Write `data` to a new file at `path`, bypassing the OS page cache where supported
+///
+/// Data is staged through an aligned buffer and the final block is zero-padded, then
+/// the file is truncated back to `data.len()`. Platforms or filesystems without
+/// O_DIRECT fall back to a regular buffered write, including those that accept the
+/// flag at open but reject the aligned writes with EINVAL (e.g. tmpfs, overlayfs).
+pub fn write_file_direct<P: AsRef<Path>>(path: P, data: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let error = match open_direct(path) {
+        Ok(file) => match write_direct(file, data) {
+            // Accepted O_DIRECT at open but rejects the aligned writes
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => e,
+            written => return written,
+        },
+        Err(e) => e,
+    };
+    warn!(
+        "Direct I/O unavailable for {:?} ({}), using buffered write",
+        path, error
+    );
+    write_file_buffered(path, data)
+}
+
+/// Write `data` through an O_DIRECT handle in aligned, zero-padded chunks
+fn write_direct(mut file: File, data: &[u8]) -> io::Result<()> {
+    // Carve an aligned window out of a slightly larger allocation
+    let mut staging = vec![0u8; DIRECT_IO_CHUNK_SIZE + DIRECT_IO_ALIGNMENT];
+    let start = staging.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+    let aligned = &mut staging[start..start + DIRECT_IO_CHUNK_SIZE];
+
+    for chunk in data.chunks(DIRECT_IO_CHUNK_SIZE) {
+        let padded_len = chunk.len().next_multiple_of(DIRECT_IO_ALIGNMENT);
+        aligned[..chunk.len()].copy_from_slice(chunk);
+        aligned[chunk.len()..padded_len].fill(0);
+        file.write_all(&aligned[..padded_len])?;
+    }
+
+    // Drop the padding from the last block
+    file.set_len(data.len() as u64)?;
+    file.sync_data()
+}
+
+/// Write `data` to a new file at `path` through the page cache
+fn write_file_buffered(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)?;
+    file.write_all(data)?;
+    file.sync_data()
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "O_DIRECT is only supported on Linux",
+    ))
+}
+
+/// Fsync a directory so renames and newly created entries in it survive a crash
+///
+/// Directories can't be opened for syncing on Windows, where this is a no-op.
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Move a file, copying it when `to` is on another filesystem
+///
+/// A copy is written next to `to` with a `.tmp` suffix, synced and renamed
+/// into place before the source is removed, so `to` never holds a partial
+/// file. Returns whether the file had to be copied.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<bool> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut partial = to.as_os_str().to_os_string();
+            partial.push(".tmp");
+            let partial = std::path::PathBuf::from(partial);
+            std::fs::copy(from, &partial)?;
+            File::open(&partial)?.sync_all()?;
+            std::fs::rename(&partial, to)?;
+            std::fs::remove_file(from)?;
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Overwrite a file with zeros, fsync it, then delete it and check it is gone
+///
+/// This removes the contents from the file system's view of the data
+/// directory; copy-on-write file systems and flash translation layers may
+/// still keep older copies of the blocks on the device.
+pub fn shred_file(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let zeros = vec![0u8; DIRECT_IO_CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk = (len - written).min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        written += chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::remove_file(path)?;
+    if path.exists() {
+        return Err(io::Error::other(format!(
+            "{:?} still exists after being shredded",
+            path
+        )));
+    }
+    Ok(())
+}
+
+/// Hint that `len` bytes at `offset` will be read soon so the kernel can prefetch them
+///
+/// This is advisory only: it is a no-op on platforms without `posix_fadvise`, and
+/// failures are ignored because the subsequent read still works without the hint.
+pub fn advise_willneed(file: &File, offset: u64, len: u64) {
+    #[cfg(target_os = "linux")]
+    sys::fadvise_willneed(file, offset, len);
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, offset, len);
+}
+
+/// Soft limit on open file descriptors for this process, where the OS reports one
+pub fn open_file_limit() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    return sys::open_file_soft_limit();
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// File descriptors currently open in this process, where the OS lists them
+pub fn process_open_files() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        // Less the descriptor used to list the directory itself
+        let entries = std::fs::read_dir("/proc/self/fd").ok()?;
+        Some(entries.count().saturating_sub(1))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Portable implementation on top of the standard library
+mod portable {
+    use std::fs::File;
+    use std::io::{self, Write};
+
+    #[cfg(unix)]
+    pub(super) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        file.read_exact_at(buf, offset)
+    }
+
+    #[cfg(windows)]
+    pub(super) fn read_exact_at(
+        file: &File,
+        mut buf: &mut [u8],
+        mut offset: u64,
+    ) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match file.seek_read(buf, offset) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn append(mut file: &File, buf: &[u8]) -> io::Result<()> {
+        file.write_all(buf)
+    }
+}
+
+/// Calls into the OS that need `unsafe`, kept together so each one carries its
+/// safety argument and the rest of the crate can deny unsafe code
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+mod sys {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    pub(super) fn fadvise_willneed(file: &File, offset: u64, len: u64) {
+        // SAFETY: posix_fadvise only reads its integer arguments, and the descriptor
+        // stays open because `file` is borrowed for the duration of the call. The
+        // result is ignored since the hint is advisory.
+        unsafe {
+            libc::posix_fadvise(
+                file.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_WILLNEED,
+            );
+        }
+    }
+
+    pub(super) fn open_file_soft_limit() -> Option<u64> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: getrlimit writes one rlimit through the pointer, which refers to a
+        // valid, exclusively borrowed local for the duration of the call.
+        let status = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+        (status == 0 && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur)
+    }
+
+    /// io_uring implementation with one ring per thread
+    #[cfg(feature = "io-uring")]
+    pub(super) mod uring {
+        use io_uring::{IoUring, Probe, opcode, squeue, types};
+        use std::cell::RefCell;
+        use std::fs::File;
+        use std::io;
+        use std::os::unix::io::AsRawFd;
+
+        /// Requests are submitted one at a time, so a shallow queue is enough
+        const QUEUE_DEPTH: u32 = 8;
+
+        thread_local! {
+            static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+        }
+
+        /// Check that a ring can be created and supports the opcodes we use
+        pub(in crate::io_backend) fn probe() -> bool {
+            let Ok(ring) = IoUring::new(QUEUE_DEPTH) else {
+                return false;
+            };
+            let mut probe = Probe::new();
+            if ring.submitter().register_probe(&mut probe).is_err() {
+                return false;
+            }
+            probe.is_supported(opcode::Read::CODE) && probe.is_supported(opcode::Write::CODE)
+        }
+
+        /// Submit a single entry and wait for its completion
+        ///
+        /// # Safety
+        ///
+        /// Any buffer referenced by `entry` must stay valid until this returns.
+        unsafe fn submit(entry: squeue::Entry) -> io::Result<usize> {
+            RING.with(|cell| {
+                let mut slot = cell.borrow_mut();
+                let ring = match slot.as_mut() {
+                    Some(ring) => ring,
+                    None => slot.insert(IoUring::new(QUEUE_DEPTH)?),
+                };
+
+                // SAFETY: the caller guarantees the buffer outlives the submission, and we
+                // block on its completion below before returning.
+                unsafe {
+                    ring.submission()
+                        .push(&entry)
+                        .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+                }
+                ring.submit_and_wait(1)?;
+
+                let cqe = ring
+                    .completion()
+                    .next()
+                    .ok_or_else(|| io::Error::other("io_uring completion missing"))?;
+                if cqe.result() < 0 {
+                    Err(io::Error::from_raw_os_error(-cqe.result()))
+                } else {
+                    Ok(cqe.result() as usize)
+                }
+            })
+        }
+
+        pub(in crate::io_backend) fn read_exact_at(
+            file: &File,
+            buf: &mut [u8],
+            offset: u64,
+        ) -> io::Result<()> {
+            let fd = types::Fd(file.as_raw_fd());
+            let mut done = 0;
+            while done < buf.len() {
+                let remaining = &mut buf[done..];
+                let entry = opcode::Read::new(fd, remaining.as_mut_ptr(), remaining.len() as u32)
+                    .offset(offset + done as u64)
+                    .build();
+                // SAFETY: `remaining` borrows `buf`, which outlives the synchronous submit.
+                match unsafe { submit(entry) } {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                    Ok(n) => done += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+
+        pub(in crate::io_backend) fn append(file: &File, buf: &[u8]) -> io::Result<()> {
+            let fd = types::Fd(file.as_raw_fd());
+            let mut done = 0;
+            while done < buf.len() {
+                let remaining = &buf[done..];
+                // Offset -1 writes at the file position; O_APPEND makes that the end of file
+                let entry = opcode::Write::new(fd, remaining.as_ptr(), remaining.len() as u32)
+                    .offset(u64::MAX)
+                    .build();
+                // SAFETY: `remaining` borrows `buf`, which outlives the synchronous submit.
+                match unsafe { submit(entry) } {
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                    Ok(n) => done += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_open_file_counts() {
+        let before = process_open_files().unwrap();
+        let temp_dir = tempdir().unwrap();
+        let _file = File::create(temp_dir.path().join("fd.bin")).unwrap();
+        assert!(process_open_files().unwrap() > 0);
+        assert!(before > 0);
+        if let Some(limit) = open_file_limit() {
+            assert!(limit > 0);
+        }
+    }
+
+    #[test]
+    fn test_move_file() {
+        let temp_dir = tempdir().unwrap();
+        let from = temp_dir.path().join("from.bin");
+        let to = temp_dir.path().join("to.bin");
+        std::fs::write(&from, b"contents").unwrap();
+        assert!(!move_file(&from, &to).unwrap());
+        assert!(!from.exists());
+        assert_eq!(std::fs::read(&to).unwrap(), b"contents");
+    }
+
+    #[test]
+    fn test_shred_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("secret.bin");
+        std::fs::write(&path, vec![0xAB; DIRECT_IO_CHUNK_SIZE + 10]).unwrap();
+        // Keep the inode alive to check what was left in it
+        let survivor = File::open(&path).unwrap();
+        shred_file(&path).unwrap();
+        assert!(!path.exists());
+
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut &survivor, &mut contents).unwrap();
+        assert_eq!(contents.len(), DIRECT_IO_CHUNK_SIZE + 10);
+        assert!(contents.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_backend_detection_is_stable() {
+        assert_eq!(IoBackend::current(), IoBackend::current());
+    }
+
+    #[test]
+    fn test_append_and_read_exact_at() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("io.bin");
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(&path)
+            .unwrap();
+
+        for backend in [IoBackend::Portable, IoBackend::current()] {
+            file.set_len(0).unwrap();
+            backend.append(&file, b"hello ").unwrap();
+            backend.append(&file, b"world").unwrap();
+
+            let mut buf = [0u8; 5];
+            backend.read_exact_at(&file, &mut buf, 6).unwrap();
+            assert_eq!(&buf, b"world");
+
+            // Reading past the end of file is an error, not a short read
+            let mut buf = [0u8; 8];
+            let err = backend.read_exact_at(&file, &mut buf, 6).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn test_write_file_direct_unaligned_length() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("direct.bin");
+
+        // Spans several staging chunks and ends mid-block
+        let data: Vec<u8> = (0..DIRECT_IO_CHUNK_SIZE * 2 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        write_file_direct(&path, &data).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        // Rewriting a shorter file truncates the old contents
+        write_file_direct(&path, b"short").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"short");
+    }
+}
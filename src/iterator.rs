@@ -0,0 +1,518 @@
+use bytes::Bytes;
+use std::cmp::Reverse;
+use std::iter::Peekable;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::blob::{BlobPin, BlobPointer, BlobStore};
+use crate::engine::{EngineResult, VersionedValue};
+use crate::expiry::Expiries;
+use crate::index;
+use crate::memtable::Entry;
+use crate::merge;
+use crate::sstable::SSTable;
+
+/// Entries from one source of a merged iterator, in key order
+pub(crate) type Source = Box<dyn Iterator<Item = EngineResult<Entry>> + Send>;
+
+/// K-way merge of sources ordered newest first
+///
+/// Yields the newest entry for each key in key order, tombstones and internal
+/// keys included. A merge operand is folded into the older versions behind it
+/// and only stays an operand if every version of its key was one.
+pub(crate) struct MergingIterator {
+    sources: Vec<Peekable<Source>>,
+    /// Reads blob values that operands are folded into
+    blobs: Arc<BlobStore>,
+}
+
+impl MergingIterator {
+    pub(crate) fn new(sources: Vec<Source>, blobs: Arc<BlobStore>) -> Self {
+        Self {
+            sources: sources.into_iter().map(Iterator::peekable).collect(),
+            blobs,
+        }
+    }
+}
+
+impl Iterator for MergingIterator {
+    type Item = EngineResult<Entry>;
+
+    /// Take the newest entry for the smallest key across all sources
+    fn next(&mut self) -> Option<Self::Item> {
+        // Surface read errors as soon as a source hits one
+        for source in &mut self.sources {
+            if let Some(Err(_)) = source.peek() {
+                return source.next();
+            }
+        }
+
+        let mut smallest: Option<(usize, &[u8])> = None;
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            if let Some(Ok(entry)) = source.peek()
+                && smallest.is_none_or(|(_, key)| entry.key.as_slice() < key)
+            {
+                smallest = Some((i, entry.key.as_slice()));
+            }
+        }
+        let (newest, key) = smallest?;
+        let key = key.to_vec();
+
+        // Older versions of the same key are skipped, or folded into an operand
+        let mut entry = self.sources[newest].next()?;
+        for source in &mut self.sources[newest + 1..] {
+            let Some(older) = source.next_if(|entry| matches!(entry, Ok(e) if e.key == key)) else {
+                continue;
+            };
+            entry = match (entry, older) {
+                (Ok(newer), Ok(older)) if newer.merge => {
+                    merge::fold(older, newer, |base| read_base(&self.blobs, base))
+                }
+                (entry, _) => entry,
+            };
+        }
+        Some(entry)
+    }
+}
+
+/// Load an entry's value, following blob pointers
+pub(crate) fn read_base(blobs: &BlobStore, entry: Entry) -> EngineResult<Option<Bytes>> {
+    match entry.value {
+        Some(pointer) if entry.indirect => {
+            let pointer = BlobPointer::decode(&pointer)?;
+            Ok(Some(blobs.read(&pointer)?))
+        }
+        value => Ok(value),
+    }
+}
+
+/// Iterator over live key-value pairs as of the moment it was created
+///
+/// Holds a copy of the MemTables' entries in range and a reference to every
+/// SSTable that was live, so flushes and compactions that replace files while
+/// it is in use don't change what it yields. Sources are merged lazily; when
+/// a key appears in several, the newest source wins.
+pub struct EngineIterator {
+    /// Sources ordered newest first: the MemTables, then SSTables
+    merged: MergingIterator,
+    end: Bound<Vec<u8>>,
+    blobs: Arc<BlobStore>,
+    /// Keeps value log garbage collection off the files the sources point into
+    blob_pin: Option<BlobPin>,
+    /// Expiry deadlines and the time they are checked against
+    expiries: Expiries,
+    now: u64,
+    /// Yield keys in the engine's reserved internal keyspace too
+    internal_keys: bool,
+}
+
+impl EngineIterator {
+    pub(crate) fn new(
+        memtables: Vec<Vec<Entry>>,
+        sstables: Vec<Arc<SSTable>>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        blobs: Arc<BlobStore>,
+        expiries: Expiries,
+        now: u64,
+    ) -> Self {
+        let mut sources: Vec<Source> = Vec::with_capacity(memtables.len() + sstables.len());
+        for memtable in memtables {
+            sources.push(Box::new(memtable.into_iter().map(Ok)));
+        }
+
+        for sstable in sstables {
+            let mut iter = sstable.iter_shared();
+            if let Bound::Included(key) | Bound::Excluded(key) = start {
+                iter.seek(key);
+            }
+            let excluded = match start {
+                Bound::Excluded(key) => Some(key.to_vec()),
+                _ => None,
+            };
+            sources.push(Box::new(
+                iter.map(|entry| entry.map_err(Into::into))
+                    .filter(move |entry| match (entry, &excluded) {
+                        (Ok(entry), Some(key)) => entry.key != *key,
+                        _ => true,
+                    }),
+            ));
+        }
+
+        Self {
+            merged: MergingIterator::new(sources, blobs.clone()),
+            end: end.map(|key| key.to_vec()),
+            blob_pin: Some(blobs.pin()),
+            blobs,
+            expiries,
+            now,
+            internal_keys: false,
+        }
+    }
+
+    /// Also yield internal keys, such as secondary index entries
+    pub(crate) fn with_internal_keys(mut self) -> Self {
+        self.internal_keys = true;
+        self
+    }
+}
+
+impl EngineIterator {
+    /// Advance like `next`, also returning the timestamp and sequence number
+    /// the value was written with
+    pub fn next_versioned(&mut self) -> Option<EngineResult<(Vec<u8>, VersionedValue)>> {
+        loop {
+            let entry = match self.merged.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            // An operand left over has nothing older to apply to
+            let entry = match merge::resolve(entry) {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if !before_end(&self.end, &entry.key) {
+                // Exhausted: drop the sources so pinned tables are released early
+                self.merged.sources.clear();
+                self.blob_pin = None;
+                return None;
+            }
+            if (!self.internal_keys && index::is_internal_key(&entry.key))
+                || self.expiries.is_expired(&entry.key, self.now)
+            {
+                continue;
+            }
+
+            let Some(value) = entry.value else {
+                continue; // Tombstone
+            };
+            let value = if entry.indirect {
+                match BlobPointer::decode(&value).and_then(|pointer| self.blobs.read(&pointer)) {
+                    Ok(value) => value,
+                    Err(e) => return Some(Err(e.into())),
+                }
+            } else {
+                value
+            };
+            let versioned = VersionedValue {
+                value,
+                timestamp: entry.timestamp,
+                sequence_number: entry.sequence_number,
+            };
+            return Some(Ok((entry.key, versioned)));
+        }
+    }
+}
+
+impl Iterator for EngineIterator {
+    type Item = EngineResult<(Vec<u8>, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_versioned()
+            .map(|pair| pair.map(|(key, versioned)| (key, versioned.value)))
+    }
+}
+
+/// Where a [`RawEntry`] was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntrySource {
+    MemTable,
+    SSTable { path: PathBuf, level: usize },
+}
+
+/// One physical version of a key as it is stored
+#[derive(Debug, Clone)]
+pub struct RawEntry {
+    /// The entry with its sequence number and timestamp; tombstones have no value
+    pub entry: Entry,
+    pub source: EntrySource,
+}
+
+/// Iterator over every stored version of the keys in a range
+///
+/// Nothing is merged or hidden: tombstones, merge operands, shadowed and
+/// expired versions and internal keys all come through, in key order and
+/// newest first within a key. Values in the value log are left as pointers.
+pub struct RawIterator {
+    sources: Vec<(EntrySource, Peekable<Source>)>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl RawIterator {
+    pub(crate) fn new(
+        memtables: Vec<Vec<Entry>>,
+        sstables: Vec<(Arc<SSTable>, usize)>,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Self {
+        let mut sources: Vec<(EntrySource, Peekable<Source>)> = Vec::new();
+        for memtable in memtables {
+            let memtable: Source = Box::new(memtable.into_iter().map(Ok));
+            sources.push((EntrySource::MemTable, memtable.peekable()));
+        }
+        for (sstable, level) in sstables {
+            let path = sstable.path().to_path_buf();
+            let mut iter = sstable.iter_shared();
+            if let Bound::Included(key) | Bound::Excluded(key) = start {
+                iter.seek(key);
+            }
+            let source: Source = Box::new(iter.map(|entry| entry.map_err(Into::into)));
+            sources.push((EntrySource::SSTable { path, level }, source.peekable()));
+        }
+        Self {
+            sources,
+            start: start.map(<[u8]>::to_vec),
+            end: end.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl Iterator for RawIterator {
+    type Item = EngineResult<RawEntry>;
+
+    /// Take the newest version of the smallest key across all sources
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Surface read errors as soon as a source hits one
+            for (_, source) in &mut self.sources {
+                if let Some(Err(_)) = source.peek()
+                    && let Some(Err(e)) = source.next()
+                {
+                    return Some(Err(e));
+                }
+            }
+
+            let mut newest: Option<(usize, &Entry)> = None;
+            for (i, (_, source)) in self.sources.iter_mut().enumerate() {
+                if let Some(Ok(entry)) = source.peek()
+                    && newest.is_none_or(|(_, best)| {
+                        (&entry.key, Reverse(entry.sequence_number))
+                            < (&best.key, Reverse(best.sequence_number))
+                    })
+                {
+                    newest = Some((i, entry));
+                }
+            }
+            let (i, _) = newest?;
+            let (source, iter) = &mut self.sources[i];
+            let entry = match iter.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            if !before_end(&self.end, &entry.key) {
+                // Exhausted: drop the sources so pinned tables are released early
+                self.sources.clear();
+                return None;
+            }
+            if matches!(&self.start, Bound::Excluded(start) if *start == entry.key) {
+                continue;
+            }
+            return Some(Ok(RawEntry {
+                entry,
+                source: source.clone(),
+            }));
+        }
+    }
+}
+
+/// Check a key against an owned upper bound
+fn before_end(end: &Bound<Vec<u8>>, key: &[u8]) -> bool {
+    match end {
+        Bound::Included(end) => key <= end.as_slice(),
+        Bound::Excluded(end) => key < end.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineError;
+    use crate::index::INTERNAL_KEY_PREFIX;
+    use crate::merge::MergeOp;
+    use crate::sstable::SSTableWriteOptions;
+    use tempfile::tempdir;
+
+    fn put(key: &[u8], value: &[u8], sequence: u64) -> Entry {
+        Entry::new(
+            key.to_vec(),
+            Some(Bytes::copy_from_slice(value)),
+            0,
+            sequence,
+        )
+    }
+
+    fn tombstone(key: &[u8], sequence: u64) -> Entry {
+        Entry::new(key.to_vec(), None, 0, sequence)
+    }
+
+    fn source(entries: Vec<EngineResult<Entry>>) -> Source {
+        Box::new(entries.into_iter())
+    }
+
+    fn blobs(dir: &std::path::Path) -> Arc<BlobStore> {
+        Arc::new(BlobStore::open(dir.join("blobs")).unwrap())
+    }
+
+    #[test]
+    fn test_merging_iterator_yields_newest_version_of_each_key() {
+        let temp_dir = tempdir().unwrap();
+        let newer = source(vec![Ok(put(b"a", b"new", 5)), Ok(tombstone(b"c", 6))]);
+        let older = source(vec![
+            Ok(put(b"a", b"old", 1)),
+            Ok(put(b"b", b"old", 2)),
+            Ok(put(b"c", b"old", 3)),
+        ]);
+
+        let merged: Vec<Entry> = MergingIterator::new(vec![newer, older], blobs(temp_dir.path()))
+            .map(Result::unwrap)
+            .collect();
+        let versions: Vec<_> = merged
+            .iter()
+            .map(|e| (e.key.as_slice(), e.sequence_number, e.value.is_some()))
+            .collect();
+        // Tombstones come through for the caller to act on
+        assert_eq!(
+            versions,
+            vec![
+                (b"a".as_slice(), 5, true),
+                (b"b".as_slice(), 2, true),
+                (b"c".as_slice(), 6, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merging_iterator_folds_operands_into_older_versions() {
+        let temp_dir = tempdir().unwrap();
+        let append =
+            |bytes: &'static [u8]| merge::encode(&[MergeOp::Append(Bytes::from_static(bytes))]);
+        let newer = source(vec![
+            Ok(Entry::merge_operand(b"a".to_vec(), append(b"!"), 0, 4)),
+            Ok(Entry::merge_operand(b"b".to_vec(), append(b"?"), 0, 5)),
+        ]);
+        let older = source(vec![Ok(put(b"a", b"hi", 1))]);
+
+        let merged: Vec<Entry> = MergingIterator::new(vec![newer, older], blobs(temp_dir.path()))
+            .map(Result::unwrap)
+            .collect();
+        assert!(!merged[0].merge);
+        assert_eq!(merged[0].value, Some(Bytes::from_static(b"hi!")));
+        assert_eq!(merged[0].sequence_number, 4);
+        // Nothing older to apply to, so it stays an operand
+        assert!(merged[1].merge);
+    }
+
+    #[test]
+    fn test_merging_iterator_surfaces_source_errors() {
+        let temp_dir = tempdir().unwrap();
+        let failing = source(vec![
+            Ok(put(b"b", b"value", 2)),
+            Err(EngineError::RecoveryFailed("bad block".to_string())),
+        ]);
+        let healthy = source(vec![Ok(put(b"a", b"value", 1)), Ok(put(b"c", b"value", 3))]);
+
+        let mut merged = MergingIterator::new(vec![failing, healthy], blobs(temp_dir.path()));
+        assert_eq!(merged.next().unwrap().unwrap().key, b"a");
+        assert_eq!(merged.next().unwrap().unwrap().key, b"b");
+        assert!(merged.next().unwrap().is_err());
+        assert_eq!(merged.next().unwrap().unwrap().key, b"c");
+        assert!(merged.next().is_none());
+    }
+
+    #[test]
+    fn test_engine_iterator_hides_dead_and_internal_keys() {
+        let temp_dir = tempdir().unwrap();
+        let mut internal = INTERNAL_KEY_PREFIX.to_vec();
+        internal.extend_from_slice(b"entry");
+        // MemTable entries arrive already cut to the range; tables are seeked
+        let memtables = vec![
+            vec![
+                tombstone(b"b", 6),
+                put(b"c", b"expired", 7),
+                put(b"d", b"live", 8),
+                put(&internal, b"d", 9),
+            ],
+            vec![put(b"e", b"value", 2)],
+        ];
+        let sstable = Arc::new(
+            SSTable::from_entries_with_options(
+                temp_dir.path().join("table.sst"),
+                &[put(b"a", b"value", 1), put(b"b", b"deleted", 1)],
+                &SSTableWriteOptions::default(),
+            )
+            .unwrap(),
+        );
+        let mut expiries = Expiries::default();
+        expiries.set(b"c", 10);
+        let iter = |start: Bound<&[u8]>, end: Bound<&[u8]>| {
+            EngineIterator::new(
+                memtables.clone(),
+                vec![sstable.clone()],
+                start,
+                end,
+                blobs(temp_dir.path()),
+                expiries.clone(),
+                20,
+            )
+        };
+        let keys = |iter: EngineIterator| iter.map(|pair| pair.unwrap().0).collect::<Vec<_>>();
+
+        let bounded = iter(Bound::Excluded(b"a"), Bound::Excluded(b"e"));
+        assert_eq!(keys(bounded), vec![b"d".to_vec()]);
+        let everything = iter(Bound::Unbounded, Bound::Unbounded);
+        assert_eq!(
+            keys(everything),
+            vec![b"a".to_vec(), b"d".to_vec(), b"e".to_vec()]
+        );
+
+        let mut with_internal = iter(Bound::Unbounded, Bound::Unbounded).with_internal_keys();
+        let (key, versioned) = with_internal.next_versioned().unwrap().unwrap();
+        assert_eq!(key, b"a");
+        assert_eq!(versioned.sequence_number, 1);
+        assert_eq!(
+            keys(with_internal),
+            vec![b"d".to_vec(), b"e".to_vec(), internal]
+        );
+    }
+
+    #[test]
+    fn test_raw_iterator_yields_every_version() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("table.sst");
+        let sstable = Arc::new(
+            SSTable::from_entries_with_options(
+                path.clone(),
+                &[put(b"a", b"oldest", 1), put(b"b", b"old", 2)],
+                &SSTableWriteOptions::default(),
+            )
+            .unwrap(),
+        );
+        let memtables = vec![
+            vec![tombstone(b"b", 5)],
+            vec![put(b"a", b"newer", 3), put(b"c", b"value", 4)],
+        ];
+
+        let raw: Vec<_> = RawIterator::new(
+            memtables,
+            vec![(sstable, 2)],
+            Bound::Unbounded,
+            Bound::Excluded(b"c"),
+        )
+        .map(Result::unwrap)
+        .map(|raw| (raw.entry.key, raw.entry.sequence_number, raw.source))
+        .collect();
+        let table = EntrySource::SSTable { path, level: 2 };
+        assert_eq!(
+            raw,
+            vec![
+                (b"a".to_vec(), 3, EntrySource::MemTable),
+                (b"a".to_vec(), 1, table.clone()),
+                (b"b".to_vec(), 5, EntrySource::MemTable),
+                (b"b".to_vec(), 2, table),
+            ]
+        );
+    }
+}
@@ -0,0 +1,751 @@
+use std::ops::Bound;
+use thiserror::Error;
+
+/// Errors that can occur while decoding keys
+#[derive(Error, Debug, PartialEq)]
+pub enum KeyError {
+    #[error("Unexpected end of key: needed {needed} more bytes")]
+    UnexpectedEnd { needed: usize },
+    #[error("Invalid key encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("Unsupported key type: {0}")]
+    Unsupported(String),
+}
+
+/// Result type for key decoding
+pub type KeyResult<T> = Result<T, KeyError>;
+
+/// Escape byte for 0x00 inside variable-length components
+const ESCAPE: u8 = 0x00;
+/// Follows `ESCAPE` for an embedded 0x00 byte
+const ESCAPED_ZERO: u8 = 0xFF;
+/// Follows `ESCAPE` to end a variable-length component
+const TERMINATOR: u8 = 0x01;
+
+/// Smallest key greater than every key starting with `prefix`
+///
+/// None when no such key exists, i.e. the prefix is empty or all `0xFF`.
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&b| b != 0xFF)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+/// How the prefix of a key is taken for prefix bloom filters
+///
+/// Keys without a prefix, shorter than the fixed length or missing the
+/// delimiter, are left out of the filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixExtractor {
+    /// The first `n` bytes of the key
+    FixedLength(u32),
+    /// The key up to and including the first occurrence of a byte
+    Delimiter(u8),
+}
+
+impl PrefixExtractor {
+    /// Prefix of `key`, if it has one
+    pub fn extract<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            Self::FixedLength(len) => key.get(..len as usize),
+            Self::Delimiter(delimiter) => {
+                let end = key.iter().position(|&b| b == delimiter)?;
+                Some(&key[..=end])
+            }
+        }
+    }
+
+    /// The one prefix shared by every key in a range, if there is one
+    ///
+    /// The range must start at or after a key with a prefix and end no later
+    /// than the first key past that prefix.
+    pub fn range_prefix<'a>(&self, start: Bound<&'a [u8]>, end: Bound<&[u8]>) -> Option<&'a [u8]> {
+        let (Bound::Included(start) | Bound::Excluded(start)) = start else {
+            return None;
+        };
+        let prefix = self.extract(start)?;
+        let within = match (end, prefix_end(prefix)) {
+            (Bound::Unbounded, _) | (_, None) => false,
+            (Bound::Included(end), Some(limit)) => end < limit.as_slice(),
+            (Bound::Excluded(end), Some(limit)) => end <= limit.as_slice(),
+        };
+        within.then_some(prefix)
+    }
+}
+
+impl std::str::FromStr for PrefixExtractor {
+    type Err = KeyError;
+
+    /// Parse `fixed:<len>` or `delimiter:<byte>`
+    fn from_str(s: &str) -> KeyResult<Self> {
+        let invalid = || KeyError::InvalidEncoding(format!("Invalid prefix extractor: {}", s));
+        match s.split_once(':') {
+            Some(("fixed", len)) => len.parse().map(Self::FixedLength).map_err(|_| invalid()),
+            Some(("delimiter", delimiter)) => match delimiter.as_bytes() {
+                &[delimiter] => Ok(Self::Delimiter(delimiter)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Encode an unsigned integer so byte order matches numeric order
+pub fn encode_u64(value: u64) -> [u8; 8] {
+    value.to_be_bytes()
+}
+
+/// Decode a key component written by [`encode_u64`]
+pub fn decode_u64(bytes: &[u8]) -> KeyResult<u64> {
+    Ok(u64::from_be_bytes(fixed(bytes)?))
+}
+
+/// Encode a signed integer so byte order matches numeric order
+///
+/// Flipping the sign bit moves negative values below positive ones.
+pub fn encode_i64(value: i64) -> [u8; 8] {
+    ((value as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+/// Decode a key component written by [`encode_i64`]
+pub fn decode_i64(bytes: &[u8]) -> KeyResult<i64> {
+    Ok((u64::from_be_bytes(fixed(bytes)?) ^ (1 << 63)) as i64)
+}
+
+/// Encode a float so byte order matches `f64::total_cmp`
+///
+/// Positive values get their sign bit set; negative values have every bit
+/// inverted so larger magnitudes sort first.
+pub fn encode_f64(value: f64) -> [u8; 8] {
+    let bits = value.to_bits();
+    let ordered = if bits & (1 << 63) != 0 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    ordered.to_be_bytes()
+}
+
+/// Decode a key component written by [`encode_f64`]
+pub fn decode_f64(bytes: &[u8]) -> KeyResult<f64> {
+    let ordered = u64::from_be_bytes(fixed(bytes)?);
+    let bits = if ordered & (1 << 63) != 0 {
+        ordered & !(1 << 63)
+    } else {
+        !ordered
+    };
+    Ok(f64::from_bits(bits))
+}
+
+/// Take exactly eight bytes for a fixed-width component
+fn fixed(bytes: &[u8]) -> KeyResult<[u8; 8]> {
+    bytes
+        .try_into()
+        .map_err(|_| KeyError::InvalidEncoding(format!("Expected 8 bytes, got {}", bytes.len())))
+}
+
+/// Builds composite keys from ordered components
+///
+/// Fixed-width components are written as-is; byte strings are escaped and
+/// terminated so that a shorter string always sorts before its extensions,
+/// whatever follows it. Tuples therefore compare component by component.
+#[derive(Debug, Clone, Default)]
+pub struct KeyEncoder {
+    buf: Vec<u8>,
+}
+
+impl KeyEncoder {
+    /// Create an empty encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an unsigned integer component
+    pub fn u64(mut self, value: u64) -> Self {
+        self.buf.extend_from_slice(&encode_u64(value));
+        self
+    }
+
+    /// Append a signed integer component
+    pub fn i64(mut self, value: i64) -> Self {
+        self.buf.extend_from_slice(&encode_i64(value));
+        self
+    }
+
+    /// Append a float component
+    pub fn f64(mut self, value: f64) -> Self {
+        self.buf.extend_from_slice(&encode_f64(value));
+        self
+    }
+
+    /// Append a byte string component
+    pub fn bytes(mut self, value: &[u8]) -> Self {
+        for &byte in value {
+            self.buf.push(byte);
+            if byte == ESCAPE {
+                self.buf.push(ESCAPED_ZERO);
+            }
+        }
+        self.buf.extend_from_slice(&[ESCAPE, TERMINATOR]);
+        self
+    }
+
+    /// Append a string component
+    pub fn str(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    /// Finish and return the encoded key
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads components back out of a key built by [`KeyEncoder`]
+///
+/// Components must be read in the order and with the types they were written.
+#[derive(Debug, Clone)]
+pub struct KeyDecoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> KeyDecoder<'a> {
+    /// Start decoding an encoded key
+    pub fn new(key: &'a [u8]) -> Self {
+        Self { buf: key }
+    }
+
+    /// Read an unsigned integer component
+    pub fn u64(&mut self) -> KeyResult<u64> {
+        decode_u64(self.take(8)?)
+    }
+
+    /// Read a signed integer component
+    pub fn i64(&mut self) -> KeyResult<i64> {
+        decode_i64(self.take(8)?)
+    }
+
+    /// Read a float component
+    pub fn f64(&mut self) -> KeyResult<f64> {
+        decode_f64(self.take(8)?)
+    }
+
+    /// Read a byte string component
+    pub fn bytes(&mut self) -> KeyResult<Vec<u8>> {
+        let mut value = Vec::new();
+        let mut i = 0;
+        loop {
+            let Some(&byte) = self.buf.get(i) else {
+                return Err(KeyError::UnexpectedEnd { needed: 2 });
+            };
+            if byte != ESCAPE {
+                value.push(byte);
+                i += 1;
+                continue;
+            }
+
+            match self.buf.get(i + 1) {
+                Some(&ESCAPED_ZERO) => {
+                    value.push(ESCAPE);
+                    i += 2;
+                }
+                Some(&TERMINATOR) => {
+                    self.buf = &self.buf[i + 2..];
+                    return Ok(value);
+                }
+                Some(other) => {
+                    return Err(KeyError::InvalidEncoding(format!(
+                        "Invalid escape sequence 0x00 0x{:02x}",
+                        other
+                    )));
+                }
+                None => return Err(KeyError::UnexpectedEnd { needed: 1 }),
+            }
+        }
+    }
+
+    /// Read a string component
+    pub fn str(&mut self) -> KeyResult<String> {
+        String::from_utf8(self.bytes()?)
+            .map_err(|e| KeyError::InvalidEncoding(format!("Invalid UTF-8: {}", e)))
+    }
+
+    /// Check whether every component has been read
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Bytes not yet decoded
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    fn take(&mut self, len: usize) -> KeyResult<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(KeyError::UnexpectedEnd {
+                needed: len - self.buf.len(),
+            });
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+}
+
+/// Encode any serializable value as an order-preserving key
+///
+/// Integers are widened to 64 bits and floats to `f64`, so the result matches
+/// what [`KeyEncoder`] produces for the same components: `("a", 5u32)` encodes
+/// like `KeyEncoder::new().str("a").u64(5)`. Options and sequences carry a
+/// marker byte per element so shorter ones sort first; maps are not supported.
+#[cfg(feature = "typed")]
+pub fn to_key<T: serde::Serialize + ?Sized>(value: &T) -> KeyResult<Vec<u8>> {
+    let mut serializer = KeySerializer {
+        encoder: KeyEncoder::new(),
+    };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.encoder.finish())
+}
+
+#[cfg(feature = "typed")]
+impl serde::ser::Error for KeyError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        KeyError::Unsupported(msg.to_string())
+    }
+}
+
+/// Marker written before an element of a sequence or a present option
+#[cfg(feature = "typed")]
+const ELEMENT_MARKER: u8 = 0x01;
+/// Marker ending a sequence or standing in for an absent option
+#[cfg(feature = "typed")]
+const END_MARKER: u8 = 0x00;
+
+#[cfg(feature = "typed")]
+struct KeySerializer {
+    encoder: KeyEncoder,
+}
+
+#[cfg(feature = "typed")]
+impl KeySerializer {
+    fn with(&mut self, f: impl FnOnce(KeyEncoder) -> KeyEncoder) {
+        self.encoder = f(std::mem::take(&mut self.encoder));
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.encoder.buf.push(byte);
+    }
+}
+
+#[cfg(feature = "typed")]
+impl<'a> serde::Serializer for &'a mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+    type SerializeSeq = KeySeqSerializer<'a>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = serde::ser::Impossible<(), KeyError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> KeyResult<()> {
+        self.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> KeyResult<()> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> KeyResult<()> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> KeyResult<()> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> KeyResult<()> {
+        self.with(|e| e.i64(v));
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> KeyResult<()> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> KeyResult<()> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> KeyResult<()> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> KeyResult<()> {
+        self.with(|e| e.u64(v));
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> KeyResult<()> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> KeyResult<()> {
+        self.with(|e| e.f64(v));
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> KeyResult<()> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> KeyResult<()> {
+        self.with(|e| e.str(v));
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> KeyResult<()> {
+        self.with(|e| e.bytes(v));
+        Ok(())
+    }
+
+    fn serialize_none(self) -> KeyResult<()> {
+        self.push(END_MARKER);
+        Ok(())
+    }
+
+    fn serialize_some<T: serde::Serialize + ?Sized>(self, value: &T) -> KeyResult<()> {
+        self.push(ELEMENT_MARKER);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> KeyResult<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> KeyResult<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> KeyResult<()> {
+        self.serialize_u64(variant_index.into())
+    }
+
+    fn serialize_newtype_struct<T: serde::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: serde::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        self.with(|e| e.u64(variant_index.into()));
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> KeyResult<KeySeqSerializer<'a>> {
+        Ok(KeySeqSerializer { inner: self })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> KeyResult<Self> {
+        self.with(|e| e.u64(variant_index.into()));
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> KeyResult<Self::SerializeMap> {
+        Err(KeyError::Unsupported(
+            "maps have no stable key order".to_string(),
+        ))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> KeyResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> KeyResult<Self> {
+        self.with(|e| e.u64(variant_index.into()));
+        Ok(self)
+    }
+}
+
+/// Writes fixed-arity compounds (tuples, structs, variants) component by component
+#[cfg(feature = "typed")]
+macro_rules! impl_compound {
+    ($($trait:ident :: $method:ident),*) => {$(
+        impl serde::ser::$trait for &mut KeySerializer {
+            type Ok = ();
+            type Error = KeyError;
+
+            fn $method<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> KeyResult<()> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> KeyResult<()> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "typed")]
+impl_compound!(
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+#[cfg(feature = "typed")]
+impl serde::ser::SerializeStruct for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_field<T: serde::Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "typed")]
+impl serde::ser::SerializeStructVariant for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_field<T: serde::Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> KeyResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        Ok(())
+    }
+}
+
+/// Writes variable-length sequences with a marker per element and an end marker
+#[cfg(feature = "typed")]
+struct KeySeqSerializer<'a> {
+    inner: &'a mut KeySerializer,
+}
+
+#[cfg(feature = "typed")]
+impl serde::ser::SerializeSeq for KeySeqSerializer<'_> {
+    type Ok = ();
+    type Error = KeyError;
+
+    fn serialize_element<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> KeyResult<()> {
+        self.inner.push(ELEMENT_MARKER);
+        value.serialize(&mut *self.inner)
+    }
+
+    fn end(self) -> KeyResult<()> {
+        self.inner.push(END_MARKER);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assert that encoding preserves the order of already sorted values
+    fn assert_order_preserved<T, E: Fn(&T) -> Vec<u8>>(sorted: &[T], encode: E) {
+        for pair in sorted.windows(2) {
+            assert!(encode(&pair[0]) < encode(&pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_end(&[0x01, 0xFF, 0xFF]), Some(vec![0x02]));
+        assert_eq!(prefix_end(&[0xFF, 0xFF]), None);
+        assert_eq!(prefix_end(b""), None);
+    }
+
+    #[test]
+    fn test_prefix_extractor() {
+        let fixed: PrefixExtractor = "fixed:3".parse().unwrap();
+        assert_eq!(fixed, PrefixExtractor::FixedLength(3));
+        assert_eq!(fixed.extract(b"dev42"), Some(&b"dev"[..]));
+        assert_eq!(fixed.extract(b"de"), None);
+
+        let delimited: PrefixExtractor = "delimiter:/".parse().unwrap();
+        assert_eq!(delimited, PrefixExtractor::Delimiter(b'/'));
+        assert_eq!(delimited.extract(b"user/1/name"), Some(&b"user/"[..]));
+        assert_eq!(delimited.extract(b"user"), None);
+        for invalid in ["fixed:x", "delimiter:ab", "prefix:3", "fixed"] {
+            assert!(invalid.parse::<PrefixExtractor>().is_err());
+        }
+
+        // Only ranges inside one prefix have one
+        let range = |start, end| delimited.range_prefix(start, end);
+        let end = prefix_end(b"user/").unwrap();
+        assert_eq!(
+            range(Bound::Included(b"user/"), Bound::Excluded(&end)),
+            Some(&b"user/"[..])
+        );
+        assert_eq!(
+            range(Bound::Excluded(b"user/7"), Bound::Included(b"user/9")),
+            Some(&b"user/"[..])
+        );
+        assert_eq!(
+            range(Bound::Included(b"user/"), Bound::Included(&end)),
+            None
+        );
+        assert_eq!(range(Bound::Included(b"user/"), Bound::Unbounded), None);
+        assert_eq!(
+            range(Bound::Included(b"user"), Bound::Excluded(b"users")),
+            None
+        );
+        assert_eq!(range(Bound::Unbounded, Bound::Excluded(&end)), None);
+    }
+
+    #[test]
+    fn test_integer_order_and_roundtrip() {
+        let unsigned = [0, 1, 255, 256, u64::MAX / 2, u64::MAX];
+        assert_order_preserved(&unsigned, |v| encode_u64(*v).to_vec());
+        for v in unsigned {
+            assert_eq!(decode_u64(&encode_u64(v)).unwrap(), v);
+        }
+
+        let signed = [i64::MIN, -256, -1, 0, 1, 256, i64::MAX];
+        assert_order_preserved(&signed, |v| encode_i64(*v).to_vec());
+        for v in signed {
+            assert_eq!(decode_i64(&encode_i64(v)).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn test_float_order_and_roundtrip() {
+        let floats = [
+            f64::NEG_INFINITY,
+            -1e300,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            1e300,
+            f64::INFINITY,
+        ];
+        assert_order_preserved(&floats, |v| encode_f64(*v).to_vec());
+        for v in floats {
+            assert_eq!(decode_f64(&encode_f64(v)).unwrap().to_bits(), v.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_composite_key_order() {
+        let key = |s: &[u8], n: u64| KeyEncoder::new().bytes(s).u64(n).finish();
+
+        // A prefix sorts first even when the following component starts high
+        assert!(key(b"a", u64::MAX) < key(b"a\0", 0));
+        assert!(key(b"a", u64::MAX) < key(b"ab", 0));
+        assert!(key(b"a\0", u64::MAX) < key(b"a\x01", 0));
+        assert!(key(b"user", 1) < key(b"user", 2));
+    }
+
+    #[test]
+    fn test_composite_key_roundtrip() {
+        let key = KeyEncoder::new()
+            .str("sensor\0name")
+            .i64(-42)
+            .f64(21.5)
+            .bytes(b"")
+            .u64(7)
+            .finish();
+
+        let mut decoder = KeyDecoder::new(&key);
+        assert_eq!(decoder.str().unwrap(), "sensor\0name");
+        assert_eq!(decoder.i64().unwrap(), -42);
+        assert_eq!(decoder.f64().unwrap(), 21.5);
+        assert_eq!(decoder.bytes().unwrap(), b"");
+        assert_eq!(decoder.u64().unwrap(), 7);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(decode_u64(&[1, 2, 3]).is_err());
+        assert_eq!(
+            KeyDecoder::new(&[1, 2]).u64(),
+            Err(KeyError::UnexpectedEnd { needed: 6 })
+        );
+        assert!(KeyDecoder::new(b"abc").bytes().is_err());
+        assert!(KeyDecoder::new(&[b'a', 0x00, 0x02]).bytes().is_err());
+    }
+
+    #[cfg(feature = "typed")]
+    #[test]
+    fn test_to_key_matches_encoder() {
+        #[derive(serde::Serialize)]
+        struct Reading<'a> {
+            sensor: &'a str,
+            at: u32,
+        }
+
+        let expected = KeyEncoder::new().str("temp").u64(5).finish();
+        assert_eq!(to_key(&("temp", 5u32)).unwrap(), expected);
+        assert_eq!(
+            to_key(&Reading {
+                sensor: "temp",
+                at: 5
+            })
+            .unwrap(),
+            expected
+        );
+
+        // Shorter sequences and absent options sort first
+        assert!(to_key(&vec![1u8]).unwrap() < to_key(&vec![1u8, 0]).unwrap());
+        assert!(to_key(&None::<u64>).unwrap() < to_key(&Some(0u64)).unwrap());
+        assert!(to_key(&(-1i32, "z")).unwrap() < to_key(&(0i32, "a")).unwrap());
+
+        let map: std::collections::BTreeMap<u8, u8> = Default::default();
+        assert!(matches!(to_key(&map), Err(KeyError::Unsupported(_))));
+    }
+}
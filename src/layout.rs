@@ -0,0 +1,249 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+use crate::io_backend::sync_dir;
+use crate::sstable::{SSTABLE_FORMAT_VERSION, SSTable, SSTableError};
+
+/// Errors that can occur while checking or upgrading the on-disk layout
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("SSTable error: {0}")]
+    SSTable(#[from] SSTableError),
+    #[error("Invalid layout marker: {0}")]
+    InvalidMarker(String),
+    #[error("Layout version {found} is newer than supported version {supported}")]
+    Unsupported { found: u32, supported: u32 },
+}
+
+/// Result type for layout operations
+pub type LayoutResult<T> = Result<T, LayoutError>;
+
+/// Name of the layout marker file in the data directory
+pub const LAYOUT_FILE: &str = "LAYOUT";
+
+/// Layout version written by this build
+///
+/// - v1: any data directory created before the marker existed
+/// - v2: marker present
+/// - v3: SSTables use format v2
+/// - v4: new WAL files use format v2; older WAL files stay readable as they are
+/// - v5: SSTables and WAL files may hold merge operands
+pub const CURRENT_LAYOUT_VERSION: u32 = 5;
+
+const MARKER_PREFIX: &str = "rustedgedb-layout ";
+
+/// What an upgrade changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpgradeReport {
+    /// Layout version found before the upgrade
+    pub from_version: u32,
+    /// Layout version after the upgrade
+    pub to_version: u32,
+    /// SSTables rewritten in the current format
+    pub sstables_rewritten: usize,
+}
+
+/// Read the layout version of a data directory
+///
+/// Returns None for a directory that holds no database files yet.
+pub fn read_version(data_dir: &Path) -> LayoutResult<Option<u32>> {
+    let marker = data_dir.join(LAYOUT_FILE);
+    match std::fs::read_to_string(&marker) {
+        Ok(contents) => {
+            let version = contents
+                .trim()
+                .strip_prefix(MARKER_PREFIX)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| LayoutError::InvalidMarker(contents.trim().to_string()))?;
+            Ok(Some(version))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if has_database_files(data_dir)? {
+                Ok(Some(1))
+            } else {
+                Ok(None)
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Bring a data directory up to [`CURRENT_LAYOUT_VERSION`]
+///
+/// Older files are rewritten in place, each through a temporary file that is
+/// renamed over the original, and the marker is only advanced once every step
+/// has succeeded, so an interrupted upgrade is simply run again on next open.
+pub fn upgrade(data_dir: &Path) -> LayoutResult<UpgradeReport> {
+    let from_version = read_version(data_dir)?.unwrap_or(CURRENT_LAYOUT_VERSION);
+    if from_version > CURRENT_LAYOUT_VERSION {
+        return Err(LayoutError::Unsupported {
+            found: from_version,
+            supported: CURRENT_LAYOUT_VERSION,
+        });
+    }
+
+    let mut report = UpgradeReport {
+        from_version,
+        to_version: CURRENT_LAYOUT_VERSION,
+        sstables_rewritten: 0,
+    };
+    if from_version < CURRENT_LAYOUT_VERSION {
+        info!(
+            "Upgrading data directory {:?} from layout v{} to v{}",
+            data_dir, from_version, CURRENT_LAYOUT_VERSION
+        );
+        report.sstables_rewritten = rewrite_old_sstables(data_dir)?;
+    }
+
+    write_marker(data_dir)?;
+    Ok(report)
+}
+
+/// Rewrite SSTables whose header predates [`SSTABLE_FORMAT_VERSION`]
+fn rewrite_old_sstables(data_dir: &Path) -> LayoutResult<usize> {
+    let mut rewritten = 0;
+    for path in sstable_paths(data_dir)? {
+        let sstable = SSTable::open(&path)?;
+        if sstable.format_version() >= SSTABLE_FORMAT_VERSION {
+            continue;
+        }
+
+        // Entries of tables before v3 are re-encoded with varint headers
+        let tmp_path = path.with_extension("sst.upgrade");
+        drop(sstable.rebuild_metadata(&tmp_path)?);
+        drop(sstable);
+        std::fs::rename(&tmp_path, &path)?;
+        info!(
+            "Rewrote {:?} in SSTable format v{}",
+            path, SSTABLE_FORMAT_VERSION
+        );
+        rewritten += 1;
+    }
+
+    if rewritten > 0 {
+        sync_dir(data_dir)?;
+    }
+    Ok(rewritten)
+}
+
+/// Atomically write the marker for the current layout version
+fn write_marker(data_dir: &Path) -> LayoutResult<()> {
+    let tmp_path = data_dir.join(format!("{}.tmp", LAYOUT_FILE));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(&tmp_path)?;
+    writeln!(file, "{}{}", MARKER_PREFIX, CURRENT_LAYOUT_VERSION)?;
+    file.sync_all()?;
+
+    fail::fail_point!("layout::marker", |_| Err(LayoutError::Io(
+        io::Error::other("injected failure at layout::marker")
+    )));
+    std::fs::rename(&tmp_path, data_dir.join(LAYOUT_FILE))?;
+    sync_dir(data_dir)?;
+    Ok(())
+}
+
+fn sstable_paths(data_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(data_dir)?.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "sst") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn has_database_files(data_dir: &Path) -> io::Result<bool> {
+    if !data_dir.exists() {
+        return Ok(false);
+    }
+    for entry in std::fs::read_dir(data_dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.ends_with(".sst") || name.ends_with(".log") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memtable::MemTable;
+    use crate::sstable::SSTableWriteOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_fresh_directory_gets_current_marker() {
+        let temp_dir = tempdir().unwrap();
+        assert_eq!(read_version(temp_dir.path()).unwrap(), None);
+
+        let report = upgrade(temp_dir.path()).unwrap();
+        assert_eq!(report.from_version, CURRENT_LAYOUT_VERSION);
+        assert_eq!(
+            read_version(temp_dir.path()).unwrap(),
+            Some(CURRENT_LAYOUT_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_upgrade_rewrites_old_sstables() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("sstable_1.sst");
+        let memtable = MemTable::new(1024);
+        memtable.put(b"key1", b"value1").unwrap();
+        memtable.put(b"key2", b"value2").unwrap();
+        // Write the table in the version before the current one
+        let options = SSTableWriteOptions {
+            format_version: SSTABLE_FORMAT_VERSION - 1,
+            ..Default::default()
+        };
+        SSTable::from_memtable_with_options(&path, &memtable, &options).unwrap();
+
+        // Unmarked directories with database files are layout v1
+        assert_eq!(read_version(temp_dir.path()).unwrap(), Some(1));
+        let report = upgrade(temp_dir.path()).unwrap();
+        assert_eq!(report.from_version, 1);
+        assert_eq!(report.sstables_rewritten, 1);
+
+        let sstable = SSTable::open(&path).unwrap();
+        assert_eq!(sstable.format_version(), SSTABLE_FORMAT_VERSION);
+        assert_eq!(
+            sstable.get(b"key2").unwrap().as_deref(),
+            Some(&b"value2"[..])
+        );
+
+        // Upgrading again is a no-op
+        assert_eq!(upgrade(temp_dir.path()).unwrap().sstables_rewritten, 0);
+    }
+
+    #[test]
+    fn test_newer_layout_is_rejected() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join(LAYOUT_FILE),
+            format!("{}{}\n", MARKER_PREFIX, CURRENT_LAYOUT_VERSION + 1),
+        )
+        .unwrap();
+        assert!(matches!(
+            upgrade(temp_dir.path()),
+            Err(LayoutError::Unsupported { .. })
+        ));
+
+        std::fs::write(temp_dir.path().join(LAYOUT_FILE), "garbage").unwrap();
+        assert!(matches!(
+            read_version(temp_dir.path()),
+            Err(LayoutError::InvalidMarker(_))
+        ));
+    }
+}
@@ -2,8 +2,42 @@
 //!
 //! This library provides the core database engine functionality.
 
+#![deny(unsafe_code)]
+
+pub mod archive;
+pub mod batch;
+pub mod bench;
+pub mod blob;
+pub mod cache;
 pub mod compaction;
+pub mod compactor;
+pub mod db;
 pub mod engine;
+pub mod events;
+pub mod expiry;
+pub mod export;
+pub mod flusher;
+pub mod follower;
+pub mod history;
+pub mod hotkeys;
+pub mod index;
+pub mod io_backend;
+pub mod iterator;
+pub mod keys;
+pub mod layout;
+pub mod manager;
+pub mod manifest;
 pub mod memtable;
+pub mod merge;
+pub mod recovery;
+#[cfg(unix)]
+pub mod server;
+pub mod shell;
 pub mod sstable;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "typed")]
+pub mod typed;
+pub mod version;
 pub mod wal;
+pub mod write_queue;
@@ -2,18 +2,294 @@
 //!
 //! This is the main entry point for the RustEdgeDB application.
 
-pub mod memtable;
-pub mod sstable;
-pub mod wal;
+#![deny(unsafe_code)]
 
-fn main() {
-    println!("RustEdgeDB - Edge-First Database Engine");
-    println!("Version: 0.1.0");
-    println!("Rust Edition: 2024");
+use rustedgedb::bench::{self, BenchConfig, Workload};
+use rustedgedb::engine::Engine;
+#[cfg(unix)]
+use rustedgedb::server::Server;
+use rustedgedb::shell::{self, Command, SHELL_HELP, Shell};
+use rustedgedb::{sstable, wal};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-    // TODO: Initialize database engine
-    // TODO: Set up configuration
-    // TODO: Start database service
+const BENCH_USAGE: &str = "\
+Usage: rustedgedb bench <load|read-heavy|write-heavy|scan|all> [options]
 
-    println!("Database engine initialized successfully!");
+Options:
+  --data-dir <dir>       Target data directory (default ./bench-data)
+  --records <n>          Records loaded before the run (default 100000)
+  --operations <n>       Operations timed per workload (default 100000)
+  --value-size <bytes>   Value size (default 100)
+  --scan-length <n>      Keys per scan (default 50)
+  --memtable-size <n>    MemTable size in bytes
+  --seed <n>             Random seed (default 42)";
+
+const SERVE_USAGE: &str = "\
+Usage: rustedgedb serve --data-dir <dir> [--socket <path>]
+
+Serves the database to local processes over a unix socket until interrupted.
+
+Options:
+  --data-dir <dir>       Data directory to open
+  --socket <path>        Socket path (default <dir>/rustedgedb.sock)";
+
+const OPEN_USAGE: &str = "\
+Usage: rustedgedb open <dir> [<command> [<args>...]]
+
+Runs one command against the database in <dir>, or reads commands from
+standard input, one per line, when none is given.
+
+";
+
+const SST_USAGE: &str = "\
+Usage: rustedgedb sst inspect <file.sst> [--dump]
+
+Prints an SSTable's header, footer, bloom filter and index, and checks its
+checksum and entries; exits with an error if any of them is damaged.
+
+Options:
+  --dump                 Also print every entry";
+
+const WAL_USAGE: &str = "\
+Usage: rustedgedb wal <inspect|repair> <file>
+
+inspect lists every record of a WAL file with its sequence number and flags
+corrupt regions; exits with an error if the file is damaged.
+repair truncates a damaged WAL after its last valid record so the database
+can open, saving the removed bytes to <file>.truncated. Only repair a WAL
+that no process has open.";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("bench") => match run_bench(&args[1..]).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}", e, BENCH_USAGE);
+                ExitCode::FAILURE
+            }
+        },
+        Some("open") => match run_open(&args[1..]).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}{}", e, OPEN_USAGE, SHELL_HELP);
+                ExitCode::FAILURE
+            }
+        },
+        Some("sst") => match run_sst(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}", e, SST_USAGE);
+                ExitCode::FAILURE
+            }
+        },
+        Some("wal") => match run_wal(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}", e, WAL_USAGE);
+                ExitCode::FAILURE
+            }
+        },
+        #[cfg(unix)]
+        Some("serve") => match run_serve(&args[1..]).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}", e, SERVE_USAGE);
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            println!("RustEdgeDB - Edge-First Database Engine");
+            println!("Version: 0.1.0");
+            println!("Rust Edition: 2024");
+            println!();
+            println!("{}{}", OPEN_USAGE, SHELL_HELP);
+            println!();
+            println!("{}", SST_USAGE);
+            println!();
+            println!("{}", WAL_USAGE);
+            println!();
+            println!("{}", BENCH_USAGE);
+            println!();
+            println!("{}", SERVE_USAGE);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Parse `bench` arguments and print one report line per workload
+async fn run_bench(args: &[String]) -> Result<(), String> {
+    let (workload, options) = args
+        .split_first()
+        .ok_or_else(|| "missing workload".to_string())?;
+    let workloads = if workload == "all" {
+        Workload::ALL.to_vec()
+    } else {
+        vec![workload.parse::<Workload>().map_err(|e| e.to_string())?]
+    };
+
+    let mut config = BenchConfig::default();
+    config.engine_config.data_dir = PathBuf::from("./bench-data");
+    let mut options = options.iter();
+    while let Some(flag) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid value for {}: {}", flag, value))
+        };
+        match flag.as_str() {
+            "--data-dir" => config.engine_config.data_dir = PathBuf::from(value),
+            "--records" => config.record_count = number()?,
+            "--operations" => config.operation_count = number()?,
+            "--value-size" => config.value_size = number()?,
+            "--scan-length" => config.scan_length = number()?,
+            "--memtable-size" => config.engine_config.memtable_size = number()?,
+            "--seed" => config.seed = number()? as u64,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    println!(
+        "Benchmarking {:?}: {} records, {} operations, {}-byte values",
+        config.engine_config.data_dir,
+        config.record_count,
+        config.operation_count,
+        config.value_size
+    );
+    for workload in workloads {
+        config.workload = workload;
+        let report = bench::run(&config).await.map_err(|e| e.to_string())?;
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+/// Run the command in `open` arguments, or a shell on stdin if there is none
+async fn run_open(args: &[String]) -> Result<(), String> {
+    let (data_dir, command) = args
+        .split_first()
+        .ok_or_else(|| "missing data directory".to_string())?;
+    // Parse before opening, so a typo doesn't create a database
+    let command = if command.is_empty() {
+        None
+    } else {
+        let words: Vec<Vec<u8>> = command
+            .iter()
+            .map(|word| shell::unescape(word))
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        Some(Command::parse(&words).map_err(|e| e.to_string())?)
+    };
+
+    let engine = Engine::new(data_dir).await.map_err(|e| e.to_string())?;
+    let mut shell = Shell::new(engine, std::io::stdout());
+    let result = match command {
+        Some(command) => shell.execute(command).await.map(|_| ()),
+        None => {
+            let interactive = std::io::IsTerminal::is_terminal(&std::io::stdin());
+            let prompt = interactive.then_some("rustedgedb> ");
+            let stdin = std::io::stdin().lock();
+            shell.run(stdin, prompt).await.map_err(Into::into)
+        }
+    };
+    let closed = shell.close().await;
+    result.and(closed).map_err(|e| e.to_string())
+}
+
+/// Parse `sst` arguments and print the inspection report
+fn run_sst(args: &[String]) -> Result<(), String> {
+    let (command, options) = args
+        .split_first()
+        .ok_or_else(|| "missing sst command".to_string())?;
+    if command != "inspect" {
+        return Err(format!("unknown sst command {}", command));
+    }
+    let mut path = None;
+    let mut dump = false;
+    for option in options {
+        match option.as_str() {
+            "--dump" => dump = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            file if path.is_none() => path = Some(PathBuf::from(file)),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    let path = path.ok_or_else(|| "missing SSTable file".to_string())?;
+
+    let report = sstable::inspect(&path, dump).map_err(|e| e.to_string())?;
+    print!("{}", report);
+    if !report.is_intact() {
+        return Err(format!("{} is damaged", path.display()));
+    }
+    Ok(())
+}
+
+/// Parse `wal` arguments and print the dump or repair summary
+fn run_wal(args: &[String]) -> Result<(), String> {
+    let [command, file] = args else {
+        return Err("expected a wal command and a WAL file".to_string());
+    };
+    let path = PathBuf::from(file);
+    match command.as_str() {
+        "inspect" => {
+            let dump = wal::dump(&path).map_err(|e| e.to_string())?;
+            print!("{}", dump);
+            if !dump.is_intact() {
+                return Err(format!("{} is damaged", path.display()));
+            }
+        }
+        "repair" => {
+            let repair = wal::repair(&path).map_err(|e| e.to_string())?;
+            match &repair.saved_to {
+                Some(saved_to) => println!(
+                    "Truncated {} from {} to {} bytes, dropping {} records; removed bytes saved to {}",
+                    path.display(),
+                    repair.original_len,
+                    repair.repaired_len,
+                    repair.records_dropped,
+                    saved_to.display()
+                ),
+                None => println!("{} is intact, nothing to repair", path.display()),
+            }
+        }
+        _ => return Err(format!("unknown wal command {}", command)),
+    }
+    Ok(())
+}
+
+/// Parse `serve` arguments and serve the engine until Ctrl-C
+#[cfg(unix)]
+async fn run_serve(args: &[String]) -> Result<(), String> {
+    let mut data_dir = None;
+    let mut socket = None;
+    let mut options = args.iter();
+    while let Some(flag) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--data-dir" => data_dir = Some(PathBuf::from(value)),
+            "--socket" => socket = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    let data_dir = data_dir.ok_or_else(|| "missing --data-dir".to_string())?;
+    let socket = socket.unwrap_or_else(|| data_dir.join("rustedgedb.sock"));
+
+    let engine = Engine::new(&data_dir).await.map_err(|e| e.to_string())?;
+    let server = Server::bind(engine, &socket).map_err(|e| e.to_string())?;
+    println!("Serving {:?} on {:?}", data_dir, socket);
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    server
+        .run_until(shutdown)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
 }
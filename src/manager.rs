@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::engine::{Engine, EngineConfig, EngineError, EngineResult};
+
+/// Shared handle to an engine opened by a [`DbManager`]
+pub type SharedEngine = Arc<Mutex<Engine>>;
+
+/// Smallest MemTable a database is given, however many are open
+const MIN_MEMTABLE_SIZE: usize = 1024 * 1024;
+
+/// Configuration for a [`DbManager`]
+#[derive(Debug, Clone)]
+pub struct DbManagerConfig {
+    /// Directory holding one subdirectory per database
+    pub root_dir: PathBuf,
+    /// Total MemTable memory shared by all open databases
+    pub memory_budget: usize,
+    /// Template for each database's config; `data_dir` and `memtable_size` are overridden
+    pub engine_config: EngineConfig,
+}
+
+impl Default for DbManagerConfig {
+    fn default() -> Self {
+        Self {
+            root_dir: PathBuf::from("./data"),
+            memory_budget: 256 * 1024 * 1024, // 256MB
+            engine_config: EngineConfig::default(),
+        }
+    }
+}
+
+/// Opens and caches named databases under one root directory
+///
+/// Every open database gets an equal share of the memory budget, rebalanced
+/// as databases are opened and closed. Background work such as WAL sync
+/// tasks runs on the one tokio runtime the manager is used from.
+pub struct DbManager {
+    config: DbManagerConfig,
+    open: Mutex<HashMap<String, SharedEngine>>,
+}
+
+impl DbManager {
+    /// Create a manager, creating the root directory if needed
+    pub fn new(mut config: DbManagerConfig) -> EngineResult<Self> {
+        // Environment overrides apply once to the template rather than to each
+        // database, where RUSTEDGEDB_DATA_DIR would point them all at one directory
+        if config.engine_config.env_overrides {
+            config.engine_config.apply_env_overrides()?;
+            config.engine_config.env_overrides = false;
+        }
+
+        std::fs::create_dir_all(&config.root_dir)?;
+        info!("Database manager opened at {:?}", config.root_dir);
+        Ok(Self {
+            config,
+            open: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Open a database, creating it if it does not exist
+    ///
+    /// Opening an already open database returns the cached handle.
+    pub async fn open(&self, name: &str) -> EngineResult<SharedEngine> {
+        validate_name(name)?;
+
+        let mut open = self.open.lock().await;
+        if let Some(engine) = open.get(name) {
+            return Ok(engine.clone());
+        }
+
+        let mut config = self.config.engine_config.clone();
+        config.data_dir = self.db_path(name);
+        config.memtable_size = self.memtable_share(open.len() + 1);
+        let engine = Arc::new(Mutex::new(Engine::with_config(config).await?));
+
+        open.insert(name.to_string(), engine.clone());
+        self.rebalance(&open).await?;
+        info!("Opened database {:?} ({} open)", name, open.len());
+        Ok(engine)
+    }
+
+    /// Flush and close a database; returns false if it was not open
+    pub async fn close(&self, name: &str) -> EngineResult<bool> {
+        let mut open = self.open.lock().await;
+        let Some(engine) = open.remove(name) else {
+            return Ok(false);
+        };
+
+        engine.lock().await.close().await?;
+        self.rebalance(&open).await?;
+        info!("Closed database {:?} ({} open)", name, open.len());
+        Ok(true)
+    }
+
+    /// Close a database and delete all of its files
+    pub async fn drop_database(&self, name: &str) -> EngineResult<()> {
+        validate_name(name)?;
+        self.close(name).await?;
+
+        let path = self.db_path(name);
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        info!("Dropped database {:?}", name);
+        Ok(())
+    }
+
+    /// Names of all databases under the root directory, open or not
+    pub fn list(&self) -> EngineResult<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.config.root_dir)?.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir())
+                && let Some(name) = entry.file_name().to_str()
+                && validate_name(name).is_ok()
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Names of the currently open databases
+    pub async fn open_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.open.lock().await.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Flush and close every open database
+    pub async fn close_all(&self) -> EngineResult<()> {
+        let mut open = self.open.lock().await;
+        for (_, engine) in open.drain() {
+            engine.lock().await.close().await?;
+        }
+        Ok(())
+    }
+
+    /// Directory of a named database
+    pub fn db_path(&self, name: &str) -> PathBuf {
+        self.config.root_dir.join(name)
+    }
+
+    /// Root directory of the manager
+    pub fn root_dir(&self) -> &Path {
+        &self.config.root_dir
+    }
+
+    fn memtable_share(&self, open_count: usize) -> usize {
+        (self.config.memory_budget / open_count.max(1)).max(MIN_MEMTABLE_SIZE)
+    }
+
+    /// Give every open database an equal slice of the memory budget
+    async fn rebalance(&self, open: &HashMap<String, SharedEngine>) -> EngineResult<()> {
+        let share = self.memtable_share(open.len()).to_string();
+        for engine in open.values() {
+            engine.lock().await.set_option("memtable_size", &share)?;
+        }
+        Ok(())
+    }
+}
+
+/// Database names become directory names, so keep them to a safe character set
+fn validate_name(name: &str) -> EngineResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 255
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(EngineError::InvalidConfig(format!(
+            "Invalid database name {:?}",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn create_test_manager(root: &Path) -> DbManager {
+        DbManager::new(DbManagerConfig {
+            root_dir: root.to_path_buf(),
+            memory_budget: 8 * 1024 * 1024,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_databases_are_isolated() {
+        let temp_dir = tempdir().unwrap();
+        let manager = create_test_manager(temp_dir.path());
+
+        let sensor_a = manager.open("sensor-a").await.unwrap();
+        let sensor_b = manager.open("sensor-b").await.unwrap();
+        sensor_a.lock().await.put(b"reading", b"1").await.unwrap();
+        sensor_b.lock().await.put(b"reading", b"2").await.unwrap();
+
+        // Reopening returns the cached engine
+        let again = manager.open("sensor-a").await.unwrap();
+        assert!(Arc::ptr_eq(&sensor_a, &again));
+        assert_eq!(
+            again.lock().await.get(b"reading").await.unwrap().as_deref(),
+            Some(&b"1"[..])
+        );
+
+        // The budget is split across open databases
+        assert_eq!(
+            sensor_a
+                .lock()
+                .await
+                .get_property("memtable_size")
+                .as_deref(),
+            Some("4194304")
+        );
+        assert!(manager.close("sensor-b").await.unwrap());
+        assert_eq!(
+            sensor_a
+                .lock()
+                .await
+                .get_property("memtable_size")
+                .as_deref(),
+            Some("8388608")
+        );
+
+        assert_eq!(manager.open_names().await, vec!["sensor-a"]);
+        assert_eq!(manager.list().unwrap(), vec!["sensor-a", "sensor-b"]);
+
+        // Closed databases keep their data
+        let sensor_b = manager.open("sensor-b").await.unwrap();
+        assert_eq!(
+            sensor_b
+                .lock()
+                .await
+                .get(b"reading")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(&b"2"[..])
+        );
+
+        manager.drop_database("sensor-b").await.unwrap();
+        assert_eq!(manager.list().unwrap(), vec!["sensor-a"]);
+        manager.close_all().await.unwrap();
+        assert!(manager.open_names().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_names() {
+        let temp_dir = tempdir().unwrap();
+        let manager = create_test_manager(temp_dir.path());
+
+        for name in ["", "..", "../escape", "a/b", ".hidden"] {
+            assert!(manager.open(name).await.is_err(), "{:?}", name);
+        }
+    }
+}
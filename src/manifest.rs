@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::io_backend::sync_dir;
+use crate::version::Version;
+
+/// Errors that can occur while reading or writing the manifest
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Corrupt manifest: {0}")]
+    Corrupt(String),
+}
+
+/// Result type for manifest operations
+pub type ManifestResult<T> = Result<T, ManifestError>;
+
+/// Name of the manifest file in the data directory
+pub const MANIFEST_FILE: &str = "MANIFEST";
+
+/// First manifest version stored as an edit log that lists every table
+pub const EDIT_LOG_VERSION: u32 = 4;
+
+const HEADER_V1: &str = "rustedgedb-manifest 1";
+const HEADER_V2: &str = "rustedgedb-manifest 2";
+const HEADER_V3: &str = "rustedgedb-manifest 3";
+const HEADER: &str = "rustedgedb-manifest 4";
+
+/// Edits appended to a log before it is rewritten as a single snapshot
+const MAX_LOG_EDITS: usize = 256;
+
+/// Generation of a data directory, bumped each time it is truncated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Epoch {
+    pub number: u64,
+    /// Last sequence number written before the epoch began; WAL files and
+    /// unlisted SSTables holding nothing newer belong to an earlier epoch
+    pub sequence_floor: u64,
+}
+
+/// What the manifest records about one SSTable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub level: usize,
+    /// Smallest and largest sequence numbers in the table
+    pub sequence_range: Option<(u64, u64)>,
+    /// Smallest and largest keys in the table
+    pub key_range: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// One change to the set of live SSTables, logged atomically
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VersionEdit {
+    /// New epoch, after a truncate
+    pub epoch: Option<Epoch>,
+    /// Tables added, or moved to another level
+    pub added: Vec<(String, ManifestEntry)>,
+    /// Tables no longer live
+    pub removed: Vec<String>,
+}
+
+impl VersionEdit {
+    /// Check whether the edit changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.epoch.is_none() && self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Lines of the edit, ending with its `commit <crc32>` line
+    fn encode(&self) -> String {
+        let mut lines = String::new();
+        if let Some(epoch) = self.epoch {
+            lines.push_str(&format!(
+                "epoch {} {}\n",
+                epoch.number, epoch.sequence_floor
+            ));
+        }
+        for name in &self.removed {
+            lines.push_str(&format!("remove {}\n", name));
+        }
+        for (name, entry) in &self.added {
+            let (min, max) = match entry.sequence_range {
+                Some((min, max)) => (min.to_string(), max.to_string()),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let (first, last) = match &entry.key_range {
+                Some((first, last)) => (encode_hex(first), encode_hex(last)),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            lines.push_str(&format!(
+                "add {} {} {} {} {} {}\n",
+                entry.level, min, max, first, last, name
+            ));
+        }
+        let checksum = crc32fast::hash(lines.as_bytes());
+        lines.push_str(&format!("commit {:08x}\n", checksum));
+        lines
+    }
+}
+
+/// Level, sequence range and key range of every live SSTable, persisted
+/// across restarts
+///
+/// Version 4 stores a header line, then a log of [`VersionEdit`]s: `epoch
+/// <number> <sequence floor>`, `remove <file name>` and `add <level> <min seq>
+/// <max seq> <first key> <last key> <file name>` lines (keys in hex, `-` when
+/// unknown), each edit closed by a `commit <crc32>` line over its other lines.
+/// An edit torn by a crash is dropped on load. Version 3 is a snapshot with
+/// an epoch line and `<level> <min seq> <max seq> <file name>` lines, version 2
+/// has no epoch line and version 1 lines are `<level> <file name>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// Format the manifest was loaded from, 0 when there was none
+    format_version: u32,
+    epoch: Epoch,
+    tables: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Record the level and sequence range of each SSTable in `version`
+    pub fn from_version(version: &Version) -> Self {
+        let mut tables = BTreeMap::new();
+        for (level, sstables) in version.levels.iter().enumerate() {
+            for sstable in sstables {
+                if let Some(name) = sstable.path().file_name().and_then(|n| n.to_str()) {
+                    let key_range = sstable
+                        .first_key()
+                        .zip(sstable.last_key())
+                        .map(|(first, last)| (first.to_vec(), last.to_vec()));
+                    let entry = ManifestEntry {
+                        level,
+                        sequence_range: sstable.sequence_range(),
+                        key_range,
+                    };
+                    tables.insert(name.to_string(), entry);
+                }
+            }
+        }
+        Self {
+            format_version: EDIT_LOG_VERSION,
+            epoch: Epoch::default(),
+            tables,
+        }
+    }
+
+    /// Replace the epoch recorded with the tables
+    pub fn with_epoch(mut self, epoch: Epoch) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Read the manifest of a data directory
+    ///
+    /// A directory without one yields an empty manifest.
+    pub fn load(data_dir: &Path) -> ManifestResult<Self> {
+        let contents = match std::fs::read_to_string(data_dir.join(MANIFEST_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = contents.lines();
+        let header = lines.next();
+        if header == Some(HEADER) {
+            return Self::replay(lines);
+        }
+        let v1 = header == Some(HEADER_V1);
+        let (format_version, epoch) = match header {
+            Some(HEADER_V3) => {
+                let line = lines.next().unwrap_or_default();
+                let epoch = parse_epoch_line(line)
+                    .ok_or_else(|| ManifestError::Corrupt(format!("invalid epoch {:?}", line)))?;
+                (3, epoch)
+            }
+            Some(HEADER_V2) => (2, Epoch::default()),
+            Some(HEADER_V1) => (1, Epoch::default()),
+            _ => return Err(ManifestError::Corrupt("missing header".to_string())),
+        };
+        let mut tables = BTreeMap::new();
+        for line in lines {
+            let (name, entry) = if v1 {
+                parse_v1_line(line)
+            } else {
+                parse_v3_line(line)
+            }
+            .ok_or_else(|| ManifestError::Corrupt(format!("invalid line {:?}", line)))?;
+            tables.insert(name.to_string(), entry);
+        }
+        Ok(Self {
+            format_version,
+            epoch,
+            tables,
+        })
+    }
+
+    /// Apply the edits of a version 4 log in order
+    ///
+    /// A last edit without a valid commit line was torn by a crash and is
+    /// dropped; a bad edit followed by others means the log is corrupt.
+    fn replay<'a>(lines: impl Iterator<Item = &'a str>) -> ManifestResult<Self> {
+        let mut manifest = Self {
+            format_version: EDIT_LOG_VERSION,
+            ..Self::default()
+        };
+        let mut edit = VersionEdit::default();
+        let mut pending = String::new();
+        let mut torn = None;
+        for line in lines {
+            if let Some(line) = torn {
+                return Err(ManifestError::Corrupt(format!(
+                    "edit ending at {:?} is not committed",
+                    line
+                )));
+            }
+            if let Some(checksum) = line.strip_prefix("commit ") {
+                if u32::from_str_radix(checksum, 16).ok()
+                    == Some(crc32fast::hash(pending.as_bytes()))
+                {
+                    manifest.apply(std::mem::take(&mut edit));
+                    pending.clear();
+                } else {
+                    torn = Some(line);
+                }
+                continue;
+            }
+            match parse_edit_line(line, &mut edit) {
+                Some(()) => {
+                    pending.push_str(line);
+                    pending.push('\n');
+                }
+                None => torn = Some(line),
+            }
+        }
+        if torn.is_some() || !pending.is_empty() {
+            warn!("Dropping an incomplete edit at the end of the manifest");
+        }
+        Ok(manifest)
+    }
+
+    /// Apply an edit to the recorded tables
+    pub fn apply(&mut self, edit: VersionEdit) {
+        if let Some(epoch) = edit.epoch {
+            self.epoch = epoch;
+        }
+        for name in &edit.removed {
+            self.tables.remove(name);
+        }
+        self.tables.extend(edit.added);
+    }
+
+    /// Edit turning this manifest into `next`
+    pub fn diff(&self, next: &Manifest) -> VersionEdit {
+        VersionEdit {
+            epoch: (next.epoch != self.epoch).then_some(next.epoch),
+            added: next
+                .tables
+                .iter()
+                .filter(|(name, entry)| self.tables.get(*name) != Some(entry))
+                .map(|(name, entry)| (name.clone(), entry.clone()))
+                .collect(),
+            removed: self
+                .tables
+                .keys()
+                .filter(|name| !next.tables.contains_key(*name))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Atomically replace the manifest in `data_dir` with this one
+    pub fn write(&self, data_dir: &Path) -> ManifestResult<()> {
+        let tmp_path = data_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&tmp_path)?;
+        file.write_all(self.encode().as_bytes())?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp_path, data_dir.join(MANIFEST_FILE))?;
+        sync_dir(data_dir)?;
+        Ok(())
+    }
+
+    /// Contents of a manifest file holding this one as a single edit
+    pub fn encode(&self) -> String {
+        let snapshot = VersionEdit {
+            epoch: Some(self.epoch),
+            added: self
+                .tables
+                .iter()
+                .map(|(name, entry)| (name.clone(), entry.clone()))
+                .collect(),
+            removed: Vec::new(),
+        };
+        format!("{}\n{}", HEADER, snapshot.encode())
+    }
+
+    /// Format version the manifest was loaded from, 0 when there was none
+    ///
+    /// Only from [`EDIT_LOG_VERSION`] on is every live table listed; older
+    /// versions may miss tables flushed just before a crash.
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Epoch the directory is in
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    /// What is recorded for an SSTable file name
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.tables.get(name)
+    }
+
+    /// Level recorded for an SSTable file name
+    pub fn level_of(&self, name: &str) -> Option<usize> {
+        self.get(name).map(|entry| entry.level)
+    }
+
+    /// File names of every SSTable recorded
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(String::as_str)
+    }
+
+    /// Number of SSTables recorded
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Check whether no SSTables are recorded
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+}
+
+/// Manifest of a data directory, appended to as the set of tables changes
+///
+/// Each change is logged as one [`VersionEdit`] and fsynced before it is
+/// installed. After [`MAX_LOG_EDITS`] edits the log is rewritten as a single
+/// snapshot so it doesn't grow without bound.
+pub struct ManifestLog {
+    data_dir: PathBuf,
+    file: File,
+    manifest: Manifest,
+    /// Edits appended since the last snapshot
+    edits: usize,
+}
+
+impl ManifestLog {
+    /// Start a new log in `data_dir` holding `manifest` as its only edit
+    pub fn create(data_dir: &Path, manifest: Manifest) -> ManifestResult<Self> {
+        manifest.write(data_dir)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(data_dir.join(MANIFEST_FILE))?;
+        Ok(Self {
+            data_dir: data_dir.to_path_buf(),
+            file,
+            manifest,
+            edits: 0,
+        })
+    }
+
+    /// Log the edit from the current manifest to `next`
+    pub fn log(&mut self, next: Manifest) -> ManifestResult<()> {
+        let edit = self.manifest.diff(&next);
+        if edit.is_empty() {
+            return Ok(());
+        }
+        if self.edits >= MAX_LOG_EDITS {
+            debug!("Rewriting the manifest after {} edits", self.edits);
+            *self = Self::create(&self.data_dir, next)?;
+            return Ok(());
+        }
+        let appended = self
+            .file
+            .write_all(edit.encode().as_bytes())
+            .and_then(|()| self.file.sync_data());
+        if let Err(e) = appended {
+            // Part of the edit may be on disk, so the next one starts a new log
+            self.edits = MAX_LOG_EDITS;
+            return Err(e.into());
+        }
+        self.manifest.apply(edit);
+        self.edits += 1;
+        Ok(())
+    }
+
+    /// Tables and epoch as of the last logged edit
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+}
+
+/// Parse one `epoch`, `remove` or `add` line of a version 4 edit into `edit`
+fn parse_edit_line(line: &str, edit: &mut VersionEdit) -> Option<()> {
+    let (kind, rest) = line.split_once(' ')?;
+    match kind {
+        "epoch" => edit.epoch = Some(parse_epoch_line(line)?),
+        "remove" => edit.removed.push(rest.to_string()),
+        "add" => {
+            let mut parts = rest.splitn(6, ' ');
+            let level = parts.next()?.parse().ok()?;
+            let sequence_range = match (parts.next()?, parts.next()?) {
+                ("-", "-") => None,
+                (min, max) => Some((min.parse().ok()?, max.parse().ok()?)),
+            };
+            let key_range = match (parts.next()?, parts.next()?) {
+                ("-", "-") => None,
+                (first, last) => Some((decode_hex(first)?, decode_hex(last)?)),
+            };
+            let name = parts.next()?;
+            let entry = ManifestEntry {
+                level,
+                sequence_range,
+                key_range,
+            };
+            edit.added.push((name.to_string(), entry));
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Parse a version 3 `<level> <min seq> <max seq> <file name>` line
+fn parse_v3_line(line: &str) -> Option<(&str, ManifestEntry)> {
+    let mut parts = line.splitn(4, ' ');
+    let level = parts.next()?.parse().ok()?;
+    let sequence_range = match (parts.next()?, parts.next()?) {
+        ("-", "-") => None,
+        (min, max) => Some((min.parse().ok()?, max.parse().ok()?)),
+    };
+    let name = parts.next()?;
+    Some((
+        name,
+        ManifestEntry {
+            level,
+            sequence_range,
+            key_range: None,
+        },
+    ))
+}
+
+/// Parse an `epoch <number> <sequence floor>` line
+fn parse_epoch_line(line: &str) -> Option<Epoch> {
+    let mut parts = line.strip_prefix("epoch ")?.split(' ');
+    let epoch = Epoch {
+        number: parts.next()?.parse().ok()?,
+        sequence_floor: parts.next()?.parse().ok()?,
+    };
+    parts.next().is_none().then_some(epoch)
+}
+
+/// Parse a version 1 `<level> <file name>` line
+fn parse_v1_line(line: &str) -> Option<(&str, ManifestEntry)> {
+    let (level, name) = line.split_once(' ')?;
+    let entry = ManifestEntry {
+        level: level.parse().ok()?,
+        sequence_range: None,
+        key_range: None,
+    };
+    Some((name, entry))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        assert!(Manifest::load(temp_dir.path()).unwrap().is_empty());
+
+        let mut manifest = Manifest {
+            format_version: EDIT_LOG_VERSION,
+            ..Default::default()
+        };
+        let entry = |level, sequence_range| ManifestEntry {
+            level,
+            sequence_range,
+            key_range: None,
+        };
+        manifest.tables.insert(
+            "sstable_1.sst".to_string(),
+            ManifestEntry {
+                key_range: Some((b"a".to_vec(), b"z\xff".to_vec())),
+                ..entry(0, Some((5, 9)))
+            },
+        );
+        manifest
+            .tables
+            .insert("sstable 2.sst".to_string(), entry(3, None));
+        let manifest = manifest.with_epoch(Epoch {
+            number: 2,
+            sequence_floor: 40,
+        });
+        manifest.write(temp_dir.path()).unwrap();
+
+        let loaded = Manifest::load(temp_dir.path()).unwrap();
+        assert_eq!(loaded, manifest);
+        assert_eq!(loaded.level_of("sstable 2.sst"), Some(3));
+        assert_eq!(
+            loaded.get("sstable_1.sst").unwrap().key_range,
+            Some((b"a".to_vec(), b"z\xff".to_vec()))
+        );
+        assert_eq!(loaded.level_of("missing.sst"), None);
+
+        // Version 1 manifests have levels only
+        std::fs::write(
+            temp_dir.path().join(MANIFEST_FILE),
+            "rustedgedb-manifest 1\n2 sstable 3.sst\n",
+        )
+        .unwrap();
+        let loaded = Manifest::load(temp_dir.path()).unwrap();
+        assert_eq!(loaded.get("sstable 3.sst"), Some(&entry(2, None)));
+        assert_eq!(loaded.format_version(), 1);
+
+        // Version 2 manifests start in the first epoch
+        std::fs::write(
+            temp_dir.path().join(MANIFEST_FILE),
+            "rustedgedb-manifest 2\n1 4 7 sstable_4.sst\n",
+        )
+        .unwrap();
+        let loaded = Manifest::load(temp_dir.path()).unwrap();
+        assert_eq!(loaded.epoch(), Epoch::default());
+        assert_eq!(loaded.get("sstable_4.sst"), Some(&entry(1, Some((4, 7)))));
+
+        // Version 3 manifests carry an epoch but no key ranges
+        std::fs::write(
+            temp_dir.path().join(MANIFEST_FILE),
+            "rustedgedb-manifest 3\nepoch 1 12\n0 13 15 sstable_5.sst\n",
+        )
+        .unwrap();
+        let loaded = Manifest::load(temp_dir.path()).unwrap();
+        assert_eq!(loaded.epoch().sequence_floor, 12);
+        assert_eq!(loaded.get("sstable_5.sst"), Some(&entry(0, Some((13, 15)))));
+
+        std::fs::write(temp_dir.path().join(MANIFEST_FILE), "garbage\n").unwrap();
+        assert!(matches!(
+            Manifest::load(temp_dir.path()),
+            Err(ManifestError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest_edit_log() {
+        let temp_dir = tempdir().unwrap();
+        let entry = |level| ManifestEntry {
+            level,
+            sequence_range: Some((1, 2)),
+            key_range: Some((b"a".to_vec(), b"b".to_vec())),
+        };
+        let with_tables = |tables: &[(&str, usize)]| Manifest {
+            format_version: EDIT_LOG_VERSION,
+            epoch: Epoch::default(),
+            tables: tables
+                .iter()
+                .map(|(name, level)| (name.to_string(), entry(*level)))
+                .collect(),
+        };
+
+        let mut log = ManifestLog::create(temp_dir.path(), with_tables(&[("a.sst", 0)])).unwrap();
+        log.log(with_tables(&[("a.sst", 0), ("b.sst", 0)])).unwrap();
+        // A compaction moves one table down and drops the other
+        let compacted = with_tables(&[("c.sst", 1)]);
+        log.log(compacted.clone()).unwrap();
+        assert_eq!(log.manifest(), &compacted);
+        assert_eq!(Manifest::load(temp_dir.path()).unwrap(), compacted);
+        let contents = std::fs::read_to_string(temp_dir.path().join(MANIFEST_FILE)).unwrap();
+        assert_eq!(contents.matches("commit ").count(), 3);
+
+        // An edit torn by a crash is dropped
+        let path = temp_dir.path().join(MANIFEST_FILE);
+        let torn = format!("{}add 0 3 4 61 62 d.sst\ncommit 0000", contents);
+        std::fs::write(&path, &torn).unwrap();
+        assert_eq!(Manifest::load(temp_dir.path()).unwrap(), compacted);
+
+        // A damaged edit with others after it is corruption
+        let damaged = contents.replacen("add 0 1 2", "add 0 1 3", 1);
+        std::fs::write(&path, damaged).unwrap();
+        assert!(matches!(
+            Manifest::load(temp_dir.path()),
+            Err(ManifestError::Corrupt(_))
+        ));
+
+        // The log is rewritten as one snapshot once it has enough edits
+        let mut log = ManifestLog::create(temp_dir.path(), Manifest::default()).unwrap();
+        for i in 0..=MAX_LOG_EDITS {
+            log.log(with_tables(&[(&format!("t{}.sst", i), 0)]))
+                .unwrap();
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.matches("commit ").count(), 1);
+        assert_eq!(
+            Manifest::load(temp_dir.path()).unwrap(),
+            with_tables(&[(&format!("t{}.sst", MAX_LOG_EDITS), 0)])
+        );
+    }
+}
@@ -1,7 +1,14 @@
-use std::sync::{Arc, RwLock};
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use thiserror::Error;
 use tracing::{debug, trace};
 
+use crate::keys;
+use crate::merge::{self, MergeError};
+
 /// Errors that can occur during MemTable operations
 #[derive(Error, Debug)]
 pub enum MemTableError {
@@ -11,6 +18,8 @@ pub enum MemTableError {
     InvalidKey(String),
     #[error("Invalid value: {0}")]
     InvalidValue(String),
+    #[error("Merge error: {0}")]
+    Merge(#[from] MergeError),
 }
 
 /// Result type for MemTable operations
@@ -20,19 +29,46 @@ pub type MemTableResult<T> = Result<T, MemTableError>;
 #[derive(Debug, Clone, PartialEq)]
 pub struct Entry {
     pub key: Vec<u8>,
-    pub value: Option<Vec<u8>>, // None for deletions (tombstones)
+    pub value: Option<Bytes>, // None for deletions (tombstones)
     pub timestamp: u64,
     pub sequence_number: u64,
+    /// The value is an encoded blob pointer rather than the value itself
+    pub indirect: bool,
+    /// The value is a merge operand to apply to the previous version
+    pub merge: bool,
 }
 
 impl Entry {
     /// Create a new entry
-    pub fn new(key: Vec<u8>, value: Option<Vec<u8>>, timestamp: u64, sequence_number: u64) -> Self {
+    pub fn new(key: Vec<u8>, value: Option<Bytes>, timestamp: u64, sequence_number: u64) -> Self {
         Self {
             key,
             value,
             timestamp,
             sequence_number,
+            indirect: false,
+            merge: false,
+        }
+    }
+
+    /// Create an entry whose value is an encoded pointer to a value stored elsewhere
+    pub fn indirect(key: Vec<u8>, pointer: Bytes, timestamp: u64, sequence_number: u64) -> Self {
+        Self {
+            indirect: true,
+            ..Self::new(key, Some(pointer), timestamp, sequence_number)
+        }
+    }
+
+    /// Create an entry holding a merge operand for the previous version of its key
+    pub fn merge_operand(
+        key: Vec<u8>,
+        operand: Bytes,
+        timestamp: u64,
+        sequence_number: u64,
+    ) -> Self {
+        Self {
+            merge: true,
+            ..Self::new(key, Some(operand), timestamp, sequence_number)
         }
     }
 
@@ -47,11 +83,17 @@ impl Entry {
     }
 }
 
-/// Thread-safe MemTable implementation using a sorted vector for simplicity and performance
+/// Thread-safe MemTable backed by a lock-free skiplist
+///
+/// Inserts take O(log n) without shifting existing entries, and readers never
+/// wait on writers. Writers are serialized among themselves, so replacing an
+/// entry, folding a merge operand and the size accounting happen as one step.
 pub struct MemTable {
-    data: Arc<RwLock<Vec<Entry>>>,
-    size_bytes: Arc<RwLock<usize>>,
-    max_size_bytes: usize,
+    data: SkipMap<Vec<u8>, Entry>,
+    /// Held by writers for the whole read-modify-write of an insert
+    write_lock: Mutex<()>,
+    size_bytes: AtomicUsize,
+    max_size_bytes: AtomicUsize,
     sequence_number: Arc<RwLock<u64>>,
 }
 
@@ -59,9 +101,10 @@ impl MemTable {
     /// Create a new MemTable with the specified maximum size
     pub fn new(max_size_bytes: usize) -> Self {
         Self {
-            data: Arc::new(RwLock::new(Vec::new())),
-            size_bytes: Arc::new(RwLock::new(0)),
-            max_size_bytes,
+            data: SkipMap::new(),
+            write_lock: Mutex::new(()),
+            size_bytes: AtomicUsize::new(0),
+            max_size_bytes: AtomicUsize::new(max_size_bytes),
             sequence_number: Arc::new(RwLock::new(0)),
         }
     }
@@ -77,67 +120,59 @@ impl MemTable {
             return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
         }
 
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-
-        let sequence_number = {
-            let mut seq = self.sequence_number.write().unwrap();
-            *seq += 1;
-            *seq
-        };
-
+        let (timestamp, sequence_number) = self.next_version();
         let entry = Entry::new(
             key.to_vec(),
-            Some(value.to_vec()),
+            Some(Bytes::copy_from_slice(value)),
             timestamp,
             sequence_number,
         );
+        self.insert(entry)
+    }
 
-        // Check if adding this entry would exceed the size limit
-        let current_size = *self.size_bytes.read().unwrap();
-        let entry_size = entry.size_bytes();
-        if current_size + entry_size > self.max_size_bytes {
-            return Err(MemTableError::TableFull);
-        }
-
-        // Update the data
-        let mut data = self.data.write().unwrap();
-        let old_entry = self.insert_or_update(&mut data, entry.clone());
-
-        // Update size tracking - recalculate total size
-        if let Some(old_entry) = old_entry {
-            // If updating, we need to account for the size difference
-            let old_size = old_entry.size_bytes();
-            let size_diff = entry_size.saturating_sub(old_size);
-            *self.size_bytes.write().unwrap() += size_diff;
-        } else {
-            // If inserting new, add the full entry size
-            *self.size_bytes.write().unwrap() += entry_size;
+    /// Put a key whose value lives outside the MemTable, referenced by an encoded pointer
+    pub fn put_indirect(&self, key: &[u8], pointer: &[u8]) -> MemTableResult<()> {
+        if key.is_empty() {
+            return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
         }
 
-        debug!(
-            "Put key={:?}, value_len={}, sequence={}, size_bytes={}",
-            String::from_utf8_lossy(key),
-            value.len(),
+        let (timestamp, sequence_number) = self.next_version();
+        let entry = Entry::indirect(
+            key.to_vec(),
+            Bytes::copy_from_slice(pointer),
+            timestamp,
             sequence_number,
-            *self.size_bytes.read().unwrap()
         );
+        self.insert(entry)
+    }
 
+    /// Insert an entry whose timestamp and sequence number were assigned by the caller
+    ///
+    /// The MemTable's own counter is advanced past the entry's sequence number.
+    pub fn apply(&self, entry: Entry) -> MemTableResult<()> {
+        if entry.key.is_empty() {
+            return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
+        }
+
+        let sequence_number = entry.sequence_number;
+        self.insert(entry)?;
+        let mut seq = self.sequence_number.write().unwrap();
+        *seq = (*seq).max(sequence_number);
         Ok(())
     }
 
     /// Get a value from the MemTable
-    pub fn get(&self, key: &[u8]) -> MemTableResult<Option<Vec<u8>>> {
+    ///
+    /// The returned `Bytes` shares the stored allocation rather than copying it.
+    pub fn get(&self, key: &[u8]) -> MemTableResult<Option<Bytes>> {
         if key.is_empty() {
             return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
         }
 
-        let data = self.data.read().unwrap();
         let result = self
-            .find_entry(&data, key)
-            .and_then(|entry| entry.value.clone());
+            .data
+            .get(key)
+            .and_then(|entry| entry.value().value.clone());
 
         trace!(
             "Get key={:?}, found={}",
@@ -148,12 +183,28 @@ impl MemTable {
         Ok(result)
     }
 
+    /// Get the full entry for a key, including tombstones and indirect values
+    pub fn get_entry(&self, key: &[u8]) -> MemTableResult<Option<Entry>> {
+        if key.is_empty() {
+            return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
+        }
+
+        Ok(self.data.get(key).map(|entry| entry.value().clone()))
+    }
+
     /// Delete a key from the MemTable (creates a tombstone)
     pub fn delete(&self, key: &[u8]) -> MemTableResult<()> {
         if key.is_empty() {
             return Err(MemTableError::InvalidKey("Key cannot be empty".to_string()));
         }
 
+        let (timestamp, sequence_number) = self.next_version();
+        let entry = Entry::new(key.to_vec(), None, timestamp, sequence_number);
+        self.insert(entry)
+    }
+
+    /// Take the timestamp and sequence number for a new entry
+    fn next_version(&self) -> (u64, u64) {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -165,35 +216,58 @@ impl MemTable {
             *seq
         };
 
-        let entry = Entry::new(key.to_vec(), None, timestamp, sequence_number);
+        (timestamp, sequence_number)
+    }
+
+    /// Insert an entry, replacing any existing entry for the same key
+    ///
+    /// A merge operand is folded into the entry it replaces instead.
+    fn insert(&self, entry: Entry) -> MemTableResult<()> {
+        let _writer = self.write_lock.lock().unwrap();
+        let older = self.data.get(&entry.key).map(|e| e.value().clone());
+        let old_size = older.as_ref().map(Entry::size_bytes);
+        let entry = match older {
+            Some(older) if entry.merge => merge::fold(older, entry, |base| {
+                if base.indirect {
+                    return Err(MemTableError::InvalidValue(
+                        "Cannot merge into a value stored out of line".to_string(),
+                    ));
+                }
+                Ok(base.value)
+            })?,
+            _ => entry,
+        };
 
         // Check if adding this entry would exceed the size limit
-        let current_size = *self.size_bytes.read().unwrap();
+        let current_size = self.size_bytes();
         let entry_size = entry.size_bytes();
-        if current_size + entry_size > self.max_size_bytes {
+        if current_size + entry_size > self.max_size() {
             return Err(MemTableError::TableFull);
         }
 
+        let key = entry.key.clone();
+        let value_len = entry.value.as_ref().map(|v| v.len());
+        let sequence_number = entry.sequence_number;
+
         // Update the data
-        let mut data = self.data.write().unwrap();
-        let old_entry = self.insert_or_update(&mut data, entry.clone());
+        self.data.insert(key.clone(), entry);
 
         // Update size tracking - recalculate total size
-        if let Some(old_entry) = old_entry {
+        if let Some(old_size) = old_size {
             // If updating, we need to account for the size difference
-            let old_size = old_entry.size_bytes();
             let size_diff = entry_size.saturating_sub(old_size);
-            *self.size_bytes.write().unwrap() += size_diff;
+            self.size_bytes.fetch_add(size_diff, Ordering::Relaxed);
         } else {
             // If inserting new, add the full entry size
-            *self.size_bytes.write().unwrap() += entry_size;
+            self.size_bytes.fetch_add(entry_size, Ordering::Relaxed);
         }
 
         debug!(
-            "Delete key={:?}, sequence={}, size_bytes={}",
-            String::from_utf8_lossy(key),
+            "Insert key={:?}, value_len={:?}, sequence={}, size_bytes={}",
+            String::from_utf8_lossy(&key),
+            value_len,
             sequence_number,
-            *self.size_bytes.read().unwrap()
+            self.size_bytes()
         );
 
         Ok(())
@@ -201,17 +275,17 @@ impl MemTable {
 
     /// Get the current size of the MemTable in bytes
     pub fn size_bytes(&self) -> usize {
-        *self.size_bytes.read().unwrap()
+        self.size_bytes.load(Ordering::Relaxed)
     }
 
     /// Check if the MemTable is empty
     pub fn is_empty(&self) -> bool {
-        self.data.read().unwrap().is_empty()
+        self.data.is_empty()
     }
 
     /// Get the number of entries in the MemTable
     pub fn len(&self) -> usize {
-        self.data.read().unwrap().len()
+        self.data.len()
     }
 
     /// Get the current sequence number
@@ -221,44 +295,63 @@ impl MemTable {
 
     /// Check if the MemTable is full
     pub fn is_full(&self) -> bool {
-        self.size_bytes() >= self.max_size_bytes
+        self.size_bytes() >= self.max_size()
+    }
+
+    /// Check whether an entry with the given key and value lengths fits under the size limit
+    pub fn has_room_for(&self, key_len: usize, value_len: usize) -> bool {
+        self.size_bytes() + key_len + value_len + 16 <= self.max_size()
+    }
+
+    /// Change the size limit; entries already stored are kept even if they exceed it
+    pub fn set_max_size(&self, max_size_bytes: usize) {
+        self.max_size_bytes.store(max_size_bytes, Ordering::Relaxed);
+    }
+
+    /// Current size limit in bytes
+    pub fn max_size(&self) -> usize {
+        self.max_size_bytes.load(Ordering::Relaxed)
     }
 
     /// Get all entries as a vector (for flushing to SSTable)
     pub fn entries(&self) -> Vec<Entry> {
-        self.data.read().unwrap().clone()
+        self.data
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
     }
 
-    /// Clear the MemTable and reset sequence number
-    pub fn clear(&self) {
-        let mut data = self.data.write().unwrap();
-        data.clear();
-        *self.size_bytes.write().unwrap() = 0;
-        *self.sequence_number.write().unwrap() = 0;
-        debug!("MemTable cleared");
+    /// Iterate in key order over the entries whose keys fall in `range`
+    ///
+    /// Entries are cloned one at a time as the iterator advances, so writes
+    /// made meanwhile may or may not be seen.
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = Entry> + '_ {
+        let bounds = (
+            range.start_bound().map(|k| k.as_ref().to_vec()),
+            range.end_bound().map(|k| k.as_ref().to_vec()),
+        );
+        self.data.range(bounds).map(|entry| entry.value().clone())
     }
 
-    /// Insert or update an entry in the sorted vector
-    fn insert_or_update(&self, data: &mut Vec<Entry>, entry: Entry) -> Option<Entry> {
-        match data.binary_search_by(|e| e.key.as_slice().cmp(entry.key.as_slice())) {
-            Ok(index) => {
-                // Key exists, update it
-                let old_entry = std::mem::replace(&mut data[index], entry);
-                Some(old_entry)
-            }
-            Err(index) => {
-                // Key doesn't exist, insert it
-                data.insert(index, entry);
-                None
-            }
-        }
+    /// Iterate in key order over the entries whose keys start with `prefix`
+    pub fn prefix(&self, prefix: &[u8]) -> impl Iterator<Item = Entry> + '_ {
+        let end = match keys::prefix_end(prefix) {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        self.range((Bound::Included(prefix.to_vec()), end))
     }
 
-    /// Find an entry by key using binary search
-    fn find_entry<'a>(&self, data: &'a [Entry], key: &[u8]) -> Option<&'a Entry> {
-        data.binary_search_by(|e| e.key.as_slice().cmp(key))
-            .ok()
-            .map(|index| &data[index])
+    /// Clear the MemTable and reset sequence number
+    pub fn clear(&self) {
+        let _writer = self.write_lock.lock().unwrap();
+        self.data.clear();
+        self.size_bytes.store(0, Ordering::Relaxed);
+        *self.sequence_number.write().unwrap() = 0;
+        debug!("MemTable cleared");
     }
 }
 
@@ -287,23 +380,84 @@ mod tests {
 
         // Test basic put and get
         memtable.put(b"key1", b"value1").unwrap();
-        assert_eq!(memtable.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"value1"))
+        );
         assert_eq!(memtable.len(), 1);
         assert!(!memtable.is_empty());
 
         // Test overwriting
         memtable.put(b"key1", b"new_value").unwrap();
-        assert_eq!(memtable.get(b"key1").unwrap(), Some(b"new_value".to_vec()));
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"new_value"))
+        );
         assert_eq!(memtable.len(), 1); // Still only one key
     }
 
+    #[test]
+    fn test_memtable_get_shares_value() {
+        let memtable = MemTable::new(1024);
+        memtable.put(b"key1", b"value1").unwrap();
+
+        // Repeated reads hand out the same allocation instead of copying it
+        let first = memtable.get(b"key1").unwrap().unwrap();
+        let second = memtable.get(b"key1").unwrap().unwrap();
+        assert_eq!(first.as_ptr(), second.as_ptr());
+    }
+
+    #[test]
+    fn test_memtable_put_indirect() {
+        let memtable = MemTable::new(1024);
+        memtable.put_indirect(b"key1", b"pointer").unwrap();
+
+        let entry = memtable.get_entry(b"key1").unwrap().unwrap();
+        assert!(entry.indirect);
+        assert_eq!(entry.value, Some(Bytes::from_static(b"pointer")));
+
+        // A regular put replaces the pointer
+        memtable.put(b"key1", b"value1").unwrap();
+        let entry = memtable.get_entry(b"key1").unwrap().unwrap();
+        assert!(!entry.indirect);
+    }
+
+    #[test]
+    fn test_memtable_out_of_order_inserts() {
+        let memtable = MemTable::new(1024);
+        for key in [&b"b"[..], b"d", b"a", b"c", b"e", b"c"] {
+            memtable.put(key, b"v").unwrap();
+        }
+
+        let keys: Vec<Vec<u8>> = memtable.entries().into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec![b"a", b"b", b"c", b"d", b"e"]);
+    }
+
+    #[test]
+    fn test_memtable_room_and_resize() {
+        let memtable = MemTable::new(64);
+        assert!(memtable.has_room_for(8, 40));
+        assert!(!memtable.has_room_for(8, 41));
+
+        memtable.put(b"key", &[0u8; 40]).unwrap();
+        assert!(!memtable.has_room_for(1, 8));
+        assert!(memtable.put(b"other", b"value").is_err());
+
+        memtable.set_max_size(1024);
+        assert!(memtable.has_room_for(1, 8));
+        memtable.put(b"other", b"value").unwrap();
+    }
+
     #[test]
     fn test_memtable_delete() {
         let memtable = MemTable::new(1024);
 
         // Put a value
         memtable.put(b"key1", b"value1").unwrap();
-        assert_eq!(memtable.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"value1"))
+        );
 
         // Delete it
         memtable.delete(b"key1").unwrap();
@@ -328,7 +482,10 @@ mod tests {
         assert!(matches!(result.unwrap_err(), MemTableError::TableFull));
 
         // Should still be able to get the first entry
-        assert_eq!(memtable.get(b"key1").unwrap(), Some(b"value1".to_vec()));
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"value1"))
+        );
         assert_eq!(memtable.len(), 1);
     }
 
@@ -438,7 +595,7 @@ mod tests {
                 let key = format!("key_{}_{}", i, j);
                 let expected_value = format!("value_{}_{}", i, j);
                 let actual_value = memtable.get(key.as_bytes()).unwrap();
-                assert_eq!(actual_value, Some(expected_value.into_bytes()));
+                assert_eq!(actual_value, Some(Bytes::from(expected_value)));
             }
         }
     }
@@ -460,4 +617,65 @@ mod tests {
         assert_eq!(entries[1].key, b"banana");
         assert_eq!(entries[2].key, b"zebra");
     }
+
+    #[test]
+    fn test_memtable_reads_during_writes() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let memtable = Arc::new(MemTable::new(16 * 1024 * 1024));
+        memtable.put(b"stable", b"value").unwrap();
+
+        // Descending keys land at the front, which used to shift every entry
+        let writer = {
+            let memtable = Arc::clone(&memtable);
+            thread::spawn(move || {
+                for i in (0..20_000u32).rev() {
+                    memtable.put(&i.to_be_bytes(), b"v").unwrap();
+                }
+            })
+        };
+        while !writer.is_finished() {
+            assert_eq!(
+                memtable.get(b"stable").unwrap(),
+                Some(Bytes::from_static(b"value"))
+            );
+        }
+        writer.join().unwrap();
+
+        let entries = memtable.entries();
+        assert_eq!(entries.len(), 20_001);
+        assert!(entries.windows(2).all(|pair| pair[0].key < pair[1].key));
+    }
+
+    #[test]
+    fn test_memtable_range_and_prefix() {
+        let memtable = MemTable::new_default();
+        for key in [&b"a"[..], b"ab", b"abc", b"b", b"\xff", b"\xff\x01"] {
+            memtable.put(key, b"v").unwrap();
+        }
+        memtable.delete(b"ac").unwrap();
+
+        let keys =
+            |entries: Vec<Entry>| -> Vec<Vec<u8>> { entries.into_iter().map(|e| e.key).collect() };
+        assert_eq!(
+            keys(memtable.range(&b"ab"[..]..&b"b"[..]).collect()),
+            vec![b"ab".to_vec(), b"abc".to_vec(), b"ac".to_vec()]
+        );
+        assert_eq!(
+            keys(memtable.range(&b"b"[..]..=&b"b"[..]).collect()),
+            vec![b"b".to_vec()]
+        );
+        assert_eq!(memtable.range::<&[u8], _>(..).count(), 7);
+
+        // Tombstones are yielded like any other entry
+        let prefixed: Vec<Entry> = memtable.prefix(b"a").collect();
+        assert_eq!(prefixed.len(), 4);
+        assert!(prefixed[3].is_deletion());
+        assert_eq!(
+            keys(memtable.prefix(b"\xff").collect()),
+            vec![b"\xff".to_vec(), b"\xff\x01".to_vec()]
+        );
+        assert_eq!(memtable.prefix(b"c").count(), 0);
+    }
 }
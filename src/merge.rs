@@ -0,0 +1,248 @@
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::memtable::Entry;
+
+/// Errors that can occur while resolving merge operands
+#[derive(Error, Debug, PartialEq)]
+pub enum MergeError {
+    #[error("Corrupt merge operand: {0}")]
+    Corrupt(String),
+    #[error("Value of {0} bytes is not an 8-byte integer")]
+    NotAnInteger(usize),
+}
+
+/// Result type for merge operations
+pub type MergeResult<T> = Result<T, MergeError>;
+
+const ADD_TAG: u8 = 1;
+const APPEND_TAG: u8 = 2;
+
+/// One operation recorded by a merge operand
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeOp {
+    /// Add to an 8-byte little-endian integer
+    Add(i64),
+    /// Concatenate bytes to the end of the value
+    Append(Bytes),
+}
+
+/// Encode operations as the value of a merge operand
+pub fn encode(ops: &[MergeOp]) -> Bytes {
+    let mut buf = Vec::new();
+    for op in ops {
+        match op {
+            MergeOp::Add(delta) => {
+                buf.push(ADD_TAG);
+                buf.extend_from_slice(&delta.to_le_bytes());
+            }
+            MergeOp::Append(bytes) => {
+                buf.push(APPEND_TAG);
+                buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+            }
+        }
+    }
+    Bytes::from(buf)
+}
+
+/// Decode the operations of a merge operand, oldest first
+pub fn decode(operand: &[u8]) -> MergeResult<Vec<MergeOp>> {
+    let mut ops = Vec::new();
+    let mut rest = operand;
+    while let Some((&tag, body)) = rest.split_first() {
+        match tag {
+            ADD_TAG => {
+                let (delta, tail) = body
+                    .split_first_chunk::<8>()
+                    .ok_or_else(|| MergeError::Corrupt("truncated add".to_string()))?;
+                ops.push(MergeOp::Add(i64::from_le_bytes(*delta)));
+                rest = tail;
+            }
+            APPEND_TAG => {
+                let (len, tail) = body
+                    .split_first_chunk::<4>()
+                    .ok_or_else(|| MergeError::Corrupt("truncated append".to_string()))?;
+                let len = u32::from_le_bytes(*len) as usize;
+                if tail.len() < len {
+                    return Err(MergeError::Corrupt("truncated append".to_string()));
+                }
+                let (bytes, tail) = tail.split_at(len);
+                ops.push(MergeOp::Append(Bytes::copy_from_slice(bytes)));
+                rest = tail;
+            }
+            tag => return Err(MergeError::Corrupt(format!("unknown tag {}", tag))),
+        }
+    }
+    Ok(ops)
+}
+
+/// Decode an integer value as written by [`MergeOp::Add`]
+pub fn decode_i64(value: &[u8]) -> MergeResult<i64> {
+    value
+        .try_into()
+        .map(i64::from_le_bytes)
+        .map_err(|_| MergeError::NotAnInteger(value.len()))
+}
+
+/// Apply an operand to the value it was written over
+///
+/// A missing base starts from nothing: an empty value for appends, zero for
+/// adds. An add over a value that isn't an 8-byte integer also starts again
+/// from zero.
+pub fn full_merge(base: Option<&[u8]>, operand: &[u8]) -> MergeResult<Bytes> {
+    let mut value = base.map(<[u8]>::to_vec);
+    for op in decode(operand)? {
+        match op {
+            MergeOp::Add(delta) => {
+                let current = value.as_deref().and_then(|v| decode_i64(v).ok());
+                let sum = current.unwrap_or(0).wrapping_add(delta);
+                value = Some(sum.to_le_bytes().to_vec());
+            }
+            MergeOp::Append(bytes) => {
+                value.get_or_insert_default().extend_from_slice(&bytes);
+            }
+        }
+    }
+    Ok(Bytes::from(value.unwrap_or_default()))
+}
+
+/// Combine two operands for the same key into one, `older` applied first
+///
+/// Operations are associative, so this can happen wherever the two meet;
+/// adjacent adds collapse into a single one, as do adjacent appends.
+pub fn combine(older: &[u8], newer: &[u8]) -> MergeResult<Bytes> {
+    let mut ops = decode(older)?;
+    for op in decode(newer)? {
+        match (ops.last_mut(), op) {
+            (Some(MergeOp::Add(total)), MergeOp::Add(delta)) => {
+                *total = total.wrapping_add(delta);
+            }
+            (Some(MergeOp::Append(head)), MergeOp::Append(bytes)) => {
+                let mut joined = Vec::with_capacity(head.len() + bytes.len());
+                joined.extend_from_slice(head);
+                joined.extend_from_slice(&bytes);
+                *head = Bytes::from(joined);
+            }
+            (_, op) => ops.push(op),
+        }
+    }
+    Ok(encode(&ops))
+}
+
+/// Fold a merge operand into the next older version of its key
+///
+/// `read_base` loads an older value, following blob pointers. The result
+/// keeps the newer entry's timestamp and sequence number, and stays an
+/// operand only if the older version was one too.
+pub fn fold<E: From<MergeError>>(
+    older: Entry,
+    newer: Entry,
+    read_base: impl FnOnce(Entry) -> Result<Option<Bytes>, E>,
+) -> Result<Entry, E> {
+    let operand = newer.value.clone().unwrap_or_default();
+    let merge = older.merge;
+    let value = if merge {
+        let older_operand = older.value.unwrap_or_default();
+        combine(&older_operand, &operand)?
+    } else {
+        let base = read_base(older)?;
+        full_merge(base.as_deref(), &operand)?
+    };
+    Ok(Entry {
+        value: Some(value),
+        merge,
+        indirect: false,
+        ..newer
+    })
+}
+
+/// Resolve an operand that has no older version left to fold into
+pub fn resolve(entry: Entry) -> MergeResult<Entry> {
+    if !entry.merge {
+        return Ok(entry);
+    }
+    let operand = entry.value.clone().unwrap_or_default();
+    Ok(Entry {
+        value: Some(full_merge(None, &operand)?),
+        merge: false,
+        ..entry
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_operands() {
+        let operand = encode(&[MergeOp::Add(5), MergeOp::Add(-2)]);
+        assert_eq!(
+            decode(&operand).unwrap(),
+            vec![MergeOp::Add(5), MergeOp::Add(-2)]
+        );
+        assert!(decode(&[ADD_TAG, 1, 2]).is_err());
+        assert!(decode(&[9]).is_err());
+
+        // Missing and non-integer bases count from zero
+        let merged = full_merge(None, &operand).unwrap();
+        assert_eq!(decode_i64(&merged), Ok(3));
+        let merged = full_merge(Some(&10i64.to_le_bytes()), &operand).unwrap();
+        assert_eq!(decode_i64(&merged), Ok(13));
+        let merged = full_merge(Some(b"text"), &operand).unwrap();
+        assert_eq!(decode_i64(&merged), Ok(3));
+        assert_eq!(decode_i64(b"text"), Err(MergeError::NotAnInteger(4)));
+
+        let combined = combine(&encode(&[MergeOp::Add(1)]), &encode(&[MergeOp::Add(2)])).unwrap();
+        assert_eq!(decode(&combined).unwrap(), vec![MergeOp::Add(3)]);
+    }
+
+    #[test]
+    fn test_append_operands() {
+        let append = |bytes: &'static [u8]| MergeOp::Append(Bytes::from_static(bytes));
+        let operand = encode(&[append(b"b"), append(b"")]);
+        assert_eq!(decode(&operand).unwrap(), vec![append(b"b"), append(b"")]);
+        assert!(decode(&[APPEND_TAG, 5, 0, 0, 0, b'x']).is_err());
+
+        assert_eq!(full_merge(None, &operand).unwrap().as_ref(), b"b");
+        assert_eq!(full_merge(Some(b"a"), &operand).unwrap().as_ref(), b"ab");
+
+        let combined = combine(&encode(&[append(b"a")]), &encode(&[append(b"b")])).unwrap();
+        assert_eq!(decode(&combined).unwrap(), vec![append(b"ab")]);
+
+        // Mixed operations keep their order
+        let combined = combine(&encode(&[MergeOp::Add(1)]), &operand).unwrap();
+        let merged = full_merge(None, &combined).unwrap();
+        assert_eq!(merged.as_ref(), [&1i64.to_le_bytes()[..], b"b"].concat());
+    }
+
+    #[test]
+    fn test_fold_entries() {
+        let operand = |delta: i64, seq| {
+            Entry::merge_operand(b"k".to_vec(), encode(&[MergeOp::Add(delta)]), 0, seq)
+        };
+        let read = |entry: Entry| -> MergeResult<Option<Bytes>> { Ok(entry.value) };
+
+        let folded = fold(operand(1, 1), operand(2, 2), read).unwrap();
+        assert!(folded.merge);
+        assert_eq!(folded.sequence_number, 2);
+
+        let base = Entry::new(
+            b"k".to_vec(),
+            Some(Bytes::copy_from_slice(&7i64.to_le_bytes())),
+            0,
+            0,
+        );
+        let folded = fold(base, folded, read).unwrap();
+        assert!(!folded.merge);
+        assert_eq!(decode_i64(folded.value.as_ref().unwrap()), Ok(10));
+
+        let tombstone = Entry::new(b"k".to_vec(), None, 0, 0);
+        let folded = fold(tombstone, operand(4, 3), read).unwrap();
+        assert_eq!(decode_i64(folded.value.as_ref().unwrap()), Ok(4));
+
+        let resolved = resolve(operand(6, 4)).unwrap();
+        assert!(!resolved.merge);
+        assert_eq!(decode_i64(resolved.value.as_ref().unwrap()), Ok(6));
+    }
+}
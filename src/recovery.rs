@@ -0,0 +1,1031 @@
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::engine::{
+    CorruptFilePolicy, Engine, EngineError, EngineResult, StartupCheckPolicy,
+    TEMP_SSTABLE_EXTENSION, list_wal_files, remove_data_file, sstable_file_name, sstable_overlaps,
+};
+use crate::manifest::{EDIT_LOG_VERSION, Manifest};
+use crate::sstable::{OBSOLETE_SSTABLE_EXTENSION, SSTable, SSTableError};
+use crate::wal::{RecoveryProgress, WAL, WALError};
+
+/// Startup check failures logged individually before the rest are counted
+const MAX_LOGGED_VIOLATIONS: usize = 10;
+
+/// Replaying the WAL, loading SSTables and checking what was recovered
+impl Engine {
+    /// Recover from existing WAL files, returning the final replay progress
+    pub(crate) fn recover_from_wal(&mut self) -> EngineResult<RecoveryProgress> {
+        info!("Attempting WAL recovery...");
+
+        let wal_files = list_wal_files(&self.config.data_dir);
+
+        // Recover from each WAL file in order; the logs can hold more than one
+        // MemTable's worth, so the limit is lifted until replay is done
+        self.memtable.set_max_size(usize::MAX);
+        let mut last_sequence = self.wal.sequence_number();
+        let flushed_sequence = self.sequence_number();
+        let mut progress = RecoveryProgress::default();
+        for wal_path in &wal_files {
+            progress.bytes_total += std::fs::metadata(wal_path)?.len();
+        }
+        let callback = self.config.recovery_progress.clone();
+        let mut on_progress = |progress: &RecoveryProgress| {
+            if let Some(callback) = &callback {
+                (callback.0)(progress);
+            }
+        };
+        if self.config.recovery_threads > 1 && wal_files.len() > 1 && segments_ordered(&wal_files)?
+        {
+            last_sequence = last_sequence.max(self.recover_segments_parallel(
+                &wal_files,
+                flushed_sequence,
+                &mut progress,
+                &mut on_progress,
+            )?);
+        } else {
+            for wal_path in &wal_files {
+                info!("Recovering from WAL: {:?}", wal_path);
+                let wal = WAL::new(wal_path)?;
+                wal.recover_with_progress(
+                    &self.memtable,
+                    flushed_sequence,
+                    &mut progress,
+                    &mut on_progress,
+                )?;
+                last_sequence = last_sequence.max(wal.sequence_number());
+            }
+        }
+        self.memtable.set_max_size(self.config.memtable_size);
+
+        // Continue the sequence after the newest write, logged or flushed, which
+        // may have been dropped by a truncate
+        {
+            let mut sequence_number = self.sequence_number.write().unwrap();
+            *sequence_number = (*sequence_number)
+                .max(last_sequence)
+                .max(self.epoch.sequence_floor);
+        }
+
+        // The replayed logs are obsolete once the MemTable holding them is flushed
+        let active = self.wal.path().to_path_buf();
+        self.retired_wals = wal_files
+            .iter()
+            .filter(|path| **path != active)
+            .map(|path| (path.clone(), last_sequence))
+            .collect();
+
+        info!("WAL recovery completed from {} files", wal_files.len());
+        Ok(progress)
+    }
+
+    /// Decode WAL segments on worker threads, `recovery_threads` at a time, and
+    /// apply them in file order
+    ///
+    /// Only decoding runs in parallel, so the MemTable ends up exactly as a
+    /// one-at-a-time replay would leave it. Returns the highest logged sequence.
+    fn recover_segments_parallel(
+        &self,
+        wal_files: &[PathBuf],
+        flushed_sequence: u64,
+        progress: &mut RecoveryProgress,
+        on_progress: &mut dyn FnMut(&RecoveryProgress),
+    ) -> EngineResult<u64> {
+        info!(
+            "Decoding {} WAL segments on up to {} threads",
+            wal_files.len(),
+            self.config.recovery_threads
+        );
+        let mut last_sequence = 0;
+        for batch in wal_files.chunks(self.config.recovery_threads) {
+            let decoded = std::thread::scope(|scope| {
+                let workers: Vec<_> = batch
+                    .iter()
+                    .map(|path| {
+                        scope.spawn(move || {
+                            let wal = WAL::new(path)?;
+                            let mut segment = RecoveryProgress::default();
+                            let records = wal.read_records(&mut segment)?;
+                            Ok::<_, WALError>((records, segment, wal.sequence_number()))
+                        })
+                    })
+                    .collect();
+                // A worker that panicked fails recovery like a decode error
+                workers
+                    .into_iter()
+                    .zip(batch)
+                    .map(|(worker, path)| {
+                        worker.join().map_err(|_| {
+                            EngineError::RecoveryFailed(format!(
+                                "Decoding WAL segment {:?} panicked",
+                                path
+                            ))
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            for result in decoded {
+                let (records, segment, sequence) = result??;
+                for record in records {
+                    if record.is_flushed_merge(flushed_sequence) {
+                        continue;
+                    }
+                    if self
+                        .memtable
+                        .get_entry(&record.key)?
+                        .is_some_and(|newer| newer.sequence_number > record.sequence_number)
+                    {
+                        progress.records_out_of_order += 1;
+                    }
+                    self.memtable.apply(record.to_entry())?;
+                }
+                progress.bytes_processed += segment.bytes_processed;
+                progress.records_applied += segment.records_applied;
+                progress.records_corrupted += segment.records_corrupted;
+                progress.records_skipped += segment.records_skipped;
+                progress.batches_dropped += segment.batches_dropped;
+                on_progress(progress);
+                last_sequence = last_sequence.max(sequence);
+            }
+        }
+        Ok(last_sequence)
+    }
+
+    /// Load existing SSTables from the data directory
+    pub(crate) fn load_existing_sstables(&mut self) -> EngineResult<()> {
+        info!("Loading existing SSTables from {:?}", self.config.data_dir);
+
+        let mut sstable_files = Vec::new();
+
+        // A compaction, flush or import crashed before moving its table into place
+        if let Some(temp_dir) = self.config.temp_dir.as_ref() {
+            for entry in std::fs::read_dir(temp_dir)?.flatten() {
+                let path = entry.path();
+                if path
+                    .to_str()
+                    .is_some_and(|p| p.ends_with(TEMP_SSTABLE_EXTENSION))
+                {
+                    warn!("Removing incomplete SSTable {:?}", path);
+                    remove_data_file(&path, self.config.secure_delete)?;
+                }
+            }
+        }
+
+        // Scan every SSTable directory for table files
+        let dirs: Vec<PathBuf> = self
+            .sstable_dirs()
+            .into_iter()
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "sst") {
+                    sstable_files.push(path);
+                } else if path
+                    .to_str()
+                    .is_some_and(|p| p.ends_with(TEMP_SSTABLE_EXTENSION))
+                {
+                    // A flush crashed before its rename; the WAL still holds the data
+                    warn!("Removing incomplete SSTable {:?}", path);
+                    remove_data_file(&path, self.config.secure_delete)?;
+                } else if path
+                    .to_str()
+                    .is_some_and(|p| p.ends_with(OBSOLETE_SSTABLE_EXTENSION))
+                {
+                    // Retired before the last shutdown but still held by a reader then
+                    info!("Removing obsolete SSTable {:?}", path);
+                    remove_data_file(&path, self.config.secure_delete)?;
+                }
+            }
+        }
+
+        // An edit log manifest lists every live table, so only those are
+        // loaded; older manifests may miss tables flushed just before a crash,
+        // so then every table file is loaded and unlisted ones count as flushes
+        let manifest = Manifest::load(&self.config.data_dir)?;
+        if manifest.format_version() >= EDIT_LOG_VERSION {
+            sstable_files = self.select_listed_sstables(&manifest, sstable_files)?;
+        }
+        let deepest = self.config.max_levels - 1;
+        let mut version = self.versions.current().as_ref().clone();
+        let mut sstable_count = 0;
+        for sstable_path in sstable_files {
+            let opened = if self.config.lazy_open_sstables {
+                SSTable::open_lazy(&sstable_path)
+            } else {
+                SSTable::open(&sstable_path)
+            };
+            match opened {
+                Ok(sstable) => {
+                    let entry = sstable_file_name(&sstable).and_then(|name| manifest.get(name));
+                    if let Some((first, last)) = entry.and_then(|entry| entry.key_range.as_ref())
+                        && (sstable.first_key() != Some(first.as_slice())
+                            || sstable.last_key() != Some(last.as_slice()))
+                    {
+                        let error = SSTableError::CorruptedFile(
+                            "key range differs from the manifest".to_string(),
+                        );
+                        drop(sstable);
+                        self.handle_corrupt_sstable(&sstable_path, &error)?;
+                        continue;
+                    }
+                    match entry.and_then(|entry| entry.sequence_range) {
+                        Some(range) => sstable.set_sequence_range(range),
+                        None => {
+                            // Not recorded yet; read the table once to find it
+                            if let Err(e) = sstable.load_sequence_range() {
+                                self.handle_corrupt_sstable(&sstable_path, &e)?;
+                                continue;
+                            }
+                        }
+                    }
+                    // A truncate retired it but crashed before it was renamed
+                    if entry.is_none()
+                        && sstable
+                            .sequence_range()
+                            .is_some_and(|(_, max)| max <= self.epoch.sequence_floor)
+                    {
+                        info!(
+                            "Removing SSTable {:?} from before the last truncate",
+                            sstable_path
+                        );
+                        drop(sstable);
+                        remove_data_file(&sstable_path, self.config.secure_delete)?;
+                        continue;
+                    }
+                    let recorded = entry.map_or(0, |entry| entry.level);
+                    if recorded > deepest {
+                        warn!(
+                            "SSTable {:?} is at level {} beyond max_levels; moving it to level {}",
+                            sstable_path, recorded, deepest
+                        );
+                    }
+                    let level = recorded.min(deepest);
+                    info!("Loaded SSTable {:?} at level {}", sstable_path, level);
+                    version.levels[level].push(Arc::new(sstable));
+                    sstable_count += 1;
+                }
+                Err(e) => self.handle_corrupt_sstable(&sstable_path, &e)?,
+            }
+        }
+        // Level 0 tables may overlap, so reads probe them newest first by the
+        // writes they hold; deeper levels are ordered by key
+        version.levels[0]
+            .sort_by_key(|t| std::cmp::Reverse(t.sequence_range().map(|(min, max)| (max, min))));
+        for level in &mut version.levels[1..] {
+            level.sort_by(|a, b| a.first_key().cmp(&b.first_key()));
+        }
+
+        // Ingested tables hold writes the WAL never saw
+        let newest_in_tables = version.max_sequence().unwrap_or(0);
+        {
+            let mut sequence_number = self.sequence_number.write().unwrap();
+            *sequence_number = (*sequence_number).max(newest_in_tables);
+        }
+
+        self.install_version(version)?;
+        info!("Loaded {} existing SSTables", sstable_count);
+        Ok(())
+    }
+
+    /// Run the [`StartupCheckPolicy`] checks on the recovered MemTable and the
+    /// loaded SSTables
+    pub(crate) fn check_startup_invariants(&self, progress: &RecoveryProgress) -> EngineResult<()> {
+        if self.config.startup_checks == StartupCheckPolicy::Off {
+            return Ok(());
+        }
+        let violations = self.invariant_violations(progress);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        for violation in violations.iter().take(MAX_LOGGED_VIOLATIONS) {
+            warn!("Startup check failed: {}", violation);
+        }
+        if violations.len() > MAX_LOGGED_VIOLATIONS {
+            warn!(
+                "{} more startup check failures not shown",
+                violations.len() - MAX_LOGGED_VIOLATIONS
+            );
+        }
+        match self.config.startup_checks {
+            StartupCheckPolicy::Fail => Err(EngineError::RecoveryFailed(format!(
+                "{} startup check failures, first: {}",
+                violations.len(),
+                violations[0]
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Describe every way the recovered data would serve a stale version
+    ///
+    /// Unreadable tables and entries are skipped here; [`Engine::verify`]
+    /// reports them, and point reads that hit them fail with the table's
+    /// error. So are tables that `lazy_open_sstables` hasn't loaded yet, to
+    /// keep startup fast.
+    fn invariant_violations(&self, progress: &RecoveryProgress) -> Vec<String> {
+        let mut violations = Vec::new();
+        if progress.records_out_of_order > 0 {
+            violations.push(format!(
+                "{} WAL records were replayed after a newer version of their key",
+                progress.records_out_of_order
+            ));
+        }
+
+        // Each rotation starts the next WAL segment right after the last write,
+        // and only writes that reached a table are left out of the logs, so
+        // the segments' sequence ranges may neither overlap nor leave a gap
+        // above the tables
+        let version = self.versions.current();
+        let flushed = version
+            .max_sequence()
+            .unwrap_or(0)
+            .max(self.epoch.sequence_floor);
+        match wal_sequence_ranges(&list_wal_files(&self.config.data_dir)) {
+            Ok(segments) => {
+                let mut previous: Option<&(PathBuf, u64, u64)> = None;
+                for segment in &segments {
+                    let (path, first, last) = segment;
+                    if *last <= flushed {
+                        // Flushed already; kept by a crash before it was deleted
+                        previous = Some(segment);
+                        continue;
+                    }
+                    if *first <= flushed {
+                        violations.push(format!(
+                            "WAL segment {:?} holds sequences {}..={}, but SSTables already hold up to sequence {}",
+                            path, first, last, flushed
+                        ));
+                    }
+                    let expected = match previous {
+                        Some((_, _, previous_last)) => previous_last.max(&flushed) + 1,
+                        None => flushed + 1,
+                    };
+                    match previous {
+                        Some((previous_path, _, previous_last)) if first <= previous_last => {
+                            violations.push(format!(
+                                "WAL segment {:?} starts at sequence {}, overlapping {:?}, which ends at sequence {}",
+                                path, first, previous_path, previous_last
+                            ));
+                        }
+                        _ if *first > expected => {
+                            violations.push(format!(
+                                "Sequences {}..={} are in neither an SSTable nor a WAL segment; {:?} starts at sequence {}",
+                                expected,
+                                first - 1,
+                                path,
+                                first
+                            ));
+                        }
+                        _ => {}
+                    }
+                    previous = Some(segment);
+                }
+            }
+            Err(e) => warn!("Skipping WAL sequence checks: {}", e),
+        }
+
+        // The MemTable is read first, so it must hold the newest version of its
+        // keys, and a sequence number names one write only
+        for entry in self.memtable.entries() {
+            for sstable in version.sstables_for_key(&entry.key) {
+                if !sstable.is_loaded() {
+                    continue;
+                }
+                let Ok(Some(stored)) = sstable.peek_entry(&entry.key) else {
+                    continue;
+                };
+                if stored.sequence_number > entry.sequence_number {
+                    violations.push(format!(
+                        "SSTable {:?} holds sequence {} of key {:?}, newer than sequence {} recovered from the WAL",
+                        sstable.path(),
+                        stored.sequence_number,
+                        String::from_utf8_lossy(&entry.key),
+                        entry.sequence_number
+                    ));
+                } else if stored.sequence_number == entry.sequence_number
+                    && !entry.merge
+                    && (stored.value != entry.value || stored.indirect != entry.indirect)
+                {
+                    violations.push(format!(
+                        "SSTable {:?} and the WAL hold different writes of key {:?} at sequence {}",
+                        sstable.path(),
+                        String::from_utf8_lossy(&entry.key),
+                        entry.sequence_number
+                    ));
+                }
+            }
+        }
+
+        // Tables are read in this order, so one may only hold newer versions
+        // of the keys it shares with a later one
+        let tables: Vec<&Arc<SSTable>> = version
+            .levels
+            .iter()
+            .flatten()
+            .filter(|t| t.is_loaded())
+            .collect();
+        for (i, earlier) in tables.iter().enumerate() {
+            for later in &tables[i + 1..] {
+                let (Some((earlier_min, _)), Some((_, later_max))) =
+                    (earlier.sequence_range(), later.sequence_range())
+                else {
+                    continue;
+                };
+                let (Some(first), Some(last)) = (earlier.first_key(), earlier.last_key()) else {
+                    continue;
+                };
+                if earlier_min >= later_max
+                    || !sstable_overlaps(later, Bound::Included(first), Bound::Included(last))
+                {
+                    continue;
+                }
+                for entry in earlier.iter().map_while(Result::ok) {
+                    if let Ok(Some(stored)) = later.peek_entry(&entry.key)
+                        && stored.sequence_number > entry.sequence_number
+                    {
+                        violations.push(format!(
+                            "SSTable {:?} holds sequence {} of key {:?} but is read before {:?}, which holds sequence {}",
+                            earlier.path(),
+                            entry.sequence_number,
+                            String::from_utf8_lossy(&entry.key),
+                            later.path(),
+                            stored.sequence_number
+                        ));
+                    }
+                }
+            }
+        }
+        violations
+    }
+
+    /// Keep the table files `manifest` lists, in its order
+    ///
+    /// Unlisted files were never recorded, e.g. written by a flush or
+    /// compaction that crashed first, and are left alone unless a truncate
+    /// retired them. A listed file that is missing fails a strict open.
+    fn select_listed_sstables(
+        &mut self,
+        manifest: &Manifest,
+        mut found: Vec<PathBuf>,
+    ) -> EngineResult<Vec<PathBuf>> {
+        let mut listed = Vec::with_capacity(manifest.len());
+        for name in manifest.names() {
+            let position = found
+                .iter()
+                .position(|path| path.file_name().is_some_and(|n| n == name));
+            match position {
+                Some(index) => listed.push(found.swap_remove(index)),
+                None if self.config.corrupt_file_policy == CorruptFilePolicy::Strict => {
+                    return Err(EngineError::RecoveryFailed(format!(
+                        "SSTable {} in the manifest is missing",
+                        name
+                    )));
+                }
+                None => warn!("SSTable {} in the manifest is missing", name),
+            }
+        }
+        for path in found {
+            let retired = SSTable::open_lazy(&path).is_ok_and(|sstable| {
+                sstable.load_sequence_range().is_ok_and(|range| {
+                    range.is_some_and(|(_, max)| max <= self.epoch.sequence_floor)
+                })
+            });
+            if retired {
+                info!("Removing SSTable {:?} from before the last truncate", path);
+                remove_data_file(&path, self.config.secure_delete)?;
+            } else {
+                warn!(
+                    "Ignoring SSTable {:?}, which the manifest doesn't list",
+                    path
+                );
+            }
+        }
+        Ok(listed)
+    }
+}
+
+/// Check that WAL segments, in replay order, start at increasing sequence
+/// numbers, so no segment's writes interleave with the next one's
+fn segments_ordered(wal_files: &[PathBuf]) -> EngineResult<bool> {
+    let mut previous = None;
+    for path in wal_files {
+        let Some(first) = WAL::first_sequence_number(path)? else {
+            continue;
+        };
+        if previous.is_some_and(|previous| first <= previous) {
+            warn!(
+                "WAL segment {:?} overlaps the one before it; replaying segments one at a time",
+                path
+            );
+            return Ok(false);
+        }
+        previous = Some(first);
+    }
+    Ok(true)
+}
+
+/// First and last sequence number of each non-empty WAL segment, in replay order
+fn wal_sequence_ranges(wal_files: &[PathBuf]) -> EngineResult<Vec<(PathBuf, u64, u64)>> {
+    let mut ranges = Vec::new();
+    for path in wal_files {
+        let Some(first) = WAL::first_sequence_number(path)? else {
+            continue;
+        };
+        let last = WAL::new(path)?.sequence_number();
+        ranges.push((path.clone(), first, last));
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CORRUPT_DIR, EngineConfig, RecoveryProgressCallback};
+    use bytes::Bytes;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_crash_recovery() {
+        let temp_dir = tempdir().unwrap();
+        let engine_path = temp_dir.path();
+
+        // Create engine and add data
+        let mut engine = Engine::new(engine_path).await.unwrap();
+        engine
+            .put(b"recovery_key", b"recovery_value")
+            .await
+            .unwrap();
+        engine.put(b"test_key", b"test_value").await.unwrap();
+
+        // Simulate crash by dropping engine without proper close
+        drop(engine);
+
+        // Reopen engine and verify recovery
+        let engine = Engine::new(engine_path).await.unwrap();
+        let value1 = engine.get(b"recovery_key").await.unwrap();
+        let value2 = engine.get(b"test_key").await.unwrap();
+
+        assert_eq!(value1, Some(Bytes::from_static(b"recovery_value")));
+        assert_eq!(value2, Some(Bytes::from_static(b"test_value")));
+    }
+
+    #[tokio::test]
+    async fn test_recovery_progress_callback() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let value = vec![b'v'; 4096];
+        for i in 0..400 {
+            engine
+                .put(format!("key{:04}", i).as_bytes(), &value)
+                .await
+                .unwrap();
+        }
+        drop(engine);
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let engine = Engine::with_config(EngineConfig {
+            recovery_progress: Some(RecoveryProgressCallback::new(move |progress| {
+                sink.lock().unwrap().push(progress.clone())
+            })),
+            ..config
+        })
+        .await
+        .unwrap();
+        assert!(engine.get(b"key0399").await.unwrap().is_some());
+
+        // Large logs report along the way, not only once they're done
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() > 1);
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].bytes_processed <= w[1].bytes_processed)
+        );
+        let last = reports.last().unwrap();
+        assert_eq!(last.bytes_processed, last.bytes_total);
+        assert_eq!(last.records_applied, 400);
+        assert_eq!(last.records_corrupted, 0);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_recovery_matches_sequential() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        for round in 0..5 {
+            for i in 0..20 {
+                let value = format!("round{}", round);
+                engine
+                    .put(
+                        format!("key{}", i % (5 + round)).as_bytes(),
+                        value.as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            engine.delete(b"key0").await.unwrap();
+            // Each flush starts a new WAL segment
+            engine.force_flush().await.unwrap();
+        }
+        engine.put(b"key1", b"latest").await.unwrap();
+        drop(engine);
+
+        let mut recovered = Vec::new();
+        for recovery_threads in [1, 4] {
+            let engine = Engine::with_config(EngineConfig {
+                recovery_threads,
+                ..config.clone()
+            })
+            .await
+            .unwrap();
+            recovered.push((
+                engine.scan::<&[u8], _>(..).await.unwrap(),
+                engine.sequence_number(),
+                engine.memtable.len(),
+            ));
+        }
+        assert_eq!(recovered[0], recovered[1]);
+        assert_eq!(recovered[1].1, 106);
+        assert_eq!(
+            engine_value(&recovered[1].0, b"key1"),
+            Some(b"latest".as_slice())
+        );
+        assert_eq!(engine_value(&recovered[1].0, b"key0"), None);
+    }
+
+    fn engine_value<'a>(entries: &'a [(Vec<u8>, Bytes)], key: &[u8]) -> Option<&'a [u8]> {
+        entries
+            .iter()
+            .find(|(k, _)| k.as_slice() == key)
+            .map(|(_, v)| v.as_ref())
+    }
+
+    #[tokio::test]
+    async fn test_sstable_levels_persist_across_restarts() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_levels: 3,
+            ..Default::default()
+        };
+
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"a", b"old").await.unwrap();
+        engine.put(b"b", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"a", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+
+        // Flushes land in level 0; move the older table down by hand
+        let version = engine.versions.current();
+        assert_eq!(version.levels.len(), 3);
+        assert_eq!(version.levels[0].len(), 2);
+        let older = version.levels[0][1].clone();
+        let moved = version
+            .retain_sstables(|t| !Arc::ptr_eq(t, &older))
+            .with_new_sstable(2, older);
+        engine.install_version(moved).unwrap();
+        engine.close().await.unwrap();
+        drop(engine);
+
+        let engine = Engine::with_config(config.clone()).await.unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.levels[0].len(), 1);
+        assert_eq!(version.levels[2].len(), 1);
+        assert_eq!(engine.get(b"a").await.unwrap(), Some(Bytes::from("new")));
+        assert_eq!(engine.get(b"b").await.unwrap(), Some(Bytes::from("old")));
+        drop(engine);
+
+        // Levels beyond a lowered max_levels collapse into the deepest one
+        let engine = Engine::with_config(EngineConfig {
+            max_levels: 2,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let version = engine.versions.current();
+        assert_eq!(version.levels.len(), 2);
+        assert_eq!(version.levels[1].len(), 1);
+        drop(engine);
+
+        let result = Engine::with_config(EngineConfig {
+            max_levels: 0,
+            ..config
+        })
+        .await;
+        assert!(matches!(result, Err(EngineError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_file_policy() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        let flush_table = async |engine: &mut Engine, key: &[u8]| {
+            engine.put(key, b"value").await.unwrap();
+            engine.force_flush().await.unwrap();
+            let version = engine.versions.current();
+            let newest = version.sstables_for_key(key).next().unwrap();
+            newest.path().to_path_buf()
+        };
+        flush_table(&mut engine, b"key").await;
+        let garbage = flush_table(&mut engine, b"other").await;
+        engine.close().await.unwrap();
+        drop(engine);
+        std::fs::write(&garbage, b"not an sstable").unwrap();
+
+        let mut strict = config.clone();
+        strict
+            .apply_overrides([("RUSTEDGEDB_CORRUPT_FILE_POLICY", "strict")])
+            .unwrap();
+        assert!(matches!(
+            Engine::with_config(strict.clone()).await,
+            Err(EngineError::RecoveryFailed(_))
+        ));
+
+        let engine = Engine::with_config(EngineConfig {
+            corrupt_file_policy: CorruptFilePolicy::Quarantine,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        let moved = temp_dir
+            .path()
+            .join(CORRUPT_DIR)
+            .join(garbage.file_name().unwrap());
+        assert_eq!(engine.stats().quarantined_sstables, vec![moved.clone()]);
+        assert!(moved.exists() && !garbage.exists());
+        drop(engine);
+
+        // Once quarantined, strict opens cleanly
+        let mut engine = Engine::with_config(strict.clone()).await.unwrap();
+        assert_eq!(
+            engine.get(b"key").await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+
+        // Skip leaves the file where it is, and the manifest forgets it
+        let garbage = flush_table(&mut engine, b"third").await;
+        engine.close().await.unwrap();
+        drop(engine);
+        std::fs::write(&garbage, b"not an sstable").unwrap();
+        let engine = Engine::with_config(config.clone()).await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 1);
+        assert!(engine.stats().quarantined_sstables.is_empty());
+        drop(engine);
+        assert!(garbage.exists());
+        let engine = Engine::with_config(strict).await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 1);
+        drop(engine);
+
+        // A table the manifest lists must exist
+        let listed = Manifest::load(temp_dir.path()).unwrap();
+        let name = listed.names().next().unwrap().to_string();
+        std::fs::remove_file(temp_dir.path().join(&name)).unwrap();
+        assert!(matches!(
+            Engine::with_config(EngineConfig {
+                corrupt_file_policy: CorruptFilePolicy::Strict,
+                ..config
+            })
+            .await,
+            Err(EngineError::RecoveryFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_startup_checks() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            env_overrides: false,
+            ..Default::default()
+        };
+        let failing = EngineConfig {
+            startup_checks: StartupCheckPolicy::Fail,
+            ..config.clone()
+        };
+        let mut engine = Engine::with_config(config.clone()).await.unwrap();
+        engine.put(b"key", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"key", b"2").await.unwrap();
+        engine.force_flush().await.unwrap();
+        drop(engine);
+
+        // Level 0 holds the older table and level 1 the newer one
+        let mut engine = Engine::with_config(failing.clone()).await.unwrap();
+        let mut version = engine.versions.current().as_ref().clone();
+        version.levels[0].sort_by_key(|t| t.sequence_range());
+        let newer = version.levels[0].pop().unwrap();
+        version.levels[1].push(newer);
+        engine.install_version(version).unwrap();
+        drop(engine);
+        assert!(matches!(
+            Engine::with_config(failing.clone()).await,
+            Err(EngineError::RecoveryFailed(_))
+        ));
+
+        // Warnings don't stop the engine from opening, and skipping the checks
+        // opens it too
+        drop(Engine::with_config(config.clone()).await.unwrap());
+        let mut skipping = config.clone();
+        skipping
+            .apply_overrides([("RUSTEDGEDB_STARTUP_CHECKS", "off")])
+            .unwrap();
+        drop(Engine::with_config(skipping).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_startup_checks_catch_wal_sequence_gaps_and_overlaps() {
+        let open = |dir: &Path| {
+            Engine::with_config(EngineConfig {
+                data_dir: dir.to_path_buf(),
+                env_overrides: false,
+                startup_checks: StartupCheckPolicy::Fail,
+                ..Default::default()
+            })
+        };
+        let write_wal = |path: PathBuf, records: &[(&[u8], &[u8], u64)]| {
+            let mut wal = WAL::new(path).unwrap();
+            for (key, value, sequence) in records {
+                wal.write_record(&crate::wal::WALRecord::new(
+                    key.to_vec(),
+                    Some(value.to_vec()),
+                    0,
+                    *sequence,
+                ))
+                .unwrap();
+            }
+        };
+
+        // A log that was appended to after a rotation replays after it,
+        // whatever its name
+        let temp_dir = tempdir().unwrap();
+        write_wal(temp_dir.path().join("wal.log"), &[(b"key", b"3", 3)]);
+        write_wal(
+            temp_dir.path().join("wal_1.log"),
+            &[(b"key", b"1", 1), (b"key", b"2", 2)],
+        );
+        let engine = open(temp_dir.path()).await.unwrap();
+        assert_eq!(engine.get(b"key").await.unwrap(), Some(Bytes::from("3")));
+        drop(engine);
+
+        // Segments sharing sequence numbers
+        let temp_dir = tempdir().unwrap();
+        write_wal(
+            temp_dir.path().join("wal.log"),
+            &[(b"a", b"1", 1), (b"key", b"4", 4)],
+        );
+        write_wal(temp_dir.path().join("wal_1.log"), &[(b"key", b"2", 2)]);
+        let Err(EngineError::RecoveryFailed(message)) = open(temp_dir.path()).await else {
+            panic!("expected the startup checks to fail");
+        };
+        assert!(message.starts_with("2 startup check failures"));
+        assert!(message.contains("replayed after a newer version"));
+
+        // A missing sequence between segments, with no table holding it
+        let temp_dir = tempdir().unwrap();
+        write_wal(temp_dir.path().join("wal.log"), &[(b"key", b"1", 1)]);
+        write_wal(temp_dir.path().join("wal_1.log"), &[(b"key", b"3", 3)]);
+        let Err(EngineError::RecoveryFailed(message)) = open(temp_dir.path()).await else {
+            panic!("expected the startup checks to fail");
+        };
+        assert!(message.contains("Sequences 2..=2 are in neither"));
+
+        // The same gap above the tables' newest write
+        let temp_dir = tempdir().unwrap();
+        let mut engine = open(temp_dir.path()).await.unwrap();
+        engine.put(b"key", b"1").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.close().await.unwrap();
+        write_wal(temp_dir.path().join("wal_9.log"), &[(b"key", b"3", 3)]);
+        let Err(EngineError::RecoveryFailed(message)) = open(temp_dir.path()).await else {
+            panic!("expected the startup checks to fail");
+        };
+        assert!(message.contains("Sequences 2..=2 are in neither"));
+    }
+
+    #[tokio::test]
+    async fn test_level0_order_follows_sequence_ranges() {
+        let temp_dir = tempdir().unwrap();
+        let data_dir = temp_dir.path().to_path_buf();
+        let mut engine = Engine::new(&data_dir).await.unwrap();
+        engine.put(b"key", b"old").await.unwrap();
+        engine.force_flush().await.unwrap();
+        engine.put(b"key", b"new").await.unwrap();
+        engine.force_flush().await.unwrap();
+        let names: Vec<String> = engine
+            .versions
+            .current()
+            .sstables()
+            .map(|t| sstable_file_name(t).unwrap().to_string())
+            .collect();
+        engine.close().await.unwrap();
+
+        // Give the newer table a name that sorts first and record it in a
+        // version 3 manifest, which holds sequence ranges but no key ranges
+        let manifest_path = data_dir.join(crate::manifest::MANIFEST_FILE);
+        let manifest = Manifest::load(&data_dir).unwrap();
+        let mut v3 = "rustedgedb-manifest 3\nepoch 0 0\n".to_string();
+        for (name, renamed) in names.iter().zip(["a.sst", "b.sst"]) {
+            std::fs::rename(data_dir.join(name), data_dir.join(renamed)).unwrap();
+            let entry = manifest.get(name).unwrap();
+            let (min, max) = entry.sequence_range.unwrap();
+            v3.push_str(&format!("{} {} {} {}\n", entry.level, min, max, renamed));
+        }
+        std::fs::write(&manifest_path, v3).unwrap();
+
+        let newest_first = |engine: &Engine| {
+            let version = engine.versions.current();
+            let newest = version.sstables_for_key(b"key").next().unwrap();
+            newest.get(b"key").unwrap().unwrap()
+        };
+        let engine = Engine::new(&data_dir).await.unwrap();
+        assert_eq!(newest_first(&engine), Bytes::from_static(b"new"));
+        drop(engine);
+
+        // Without a manifest the ranges are read from the tables and recorded again
+        std::fs::remove_file(&manifest_path).unwrap();
+        let engine = Engine::new(&data_dir).await.unwrap();
+        assert_eq!(newest_first(&engine), Bytes::from_static(b"new"));
+        let manifest = Manifest::load(&data_dir).unwrap();
+        assert_eq!(manifest.get("a.sst").unwrap().sequence_range, Some((2, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_manifest_lists_the_loaded_tables() {
+        let temp_dir = tempdir().unwrap();
+        let data_dir = temp_dir.path().to_path_buf();
+        let mut engine = Engine::new(&data_dir).await.unwrap();
+        engine.put(b"apple", b"1").await.unwrap();
+        engine.put(b"cherry", b"2").await.unwrap();
+        engine.force_flush().await.unwrap();
+        let path = engine
+            .versions
+            .current()
+            .sstables()
+            .next()
+            .unwrap()
+            .path()
+            .to_path_buf();
+        engine.close().await.unwrap();
+        drop(engine);
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let manifest = Manifest::load(&data_dir).unwrap();
+        assert_eq!(manifest.format_version(), EDIT_LOG_VERSION);
+        assert_eq!(
+            manifest.get(name).unwrap().key_range,
+            Some((b"apple".to_vec(), b"cherry".to_vec()))
+        );
+
+        // A table file the manifest doesn't list is never loaded
+        std::fs::copy(&path, data_dir.join("sstable_unlisted.sst")).unwrap();
+        let engine = Engine::new(&data_dir).await.unwrap();
+        assert_eq!(engine.stats().sstable_count, 1);
+        drop(engine);
+        assert!(data_dir.join("sstable_unlisted.sst").exists());
+
+        // A listed table whose keys differ from the manifest is corrupt
+        let mut other = Engine::new(temp_dir.path().join("other")).await.unwrap();
+        other.put(b"banana", b"3").await.unwrap();
+        other.force_flush().await.unwrap();
+        let replacement = other
+            .versions
+            .current()
+            .sstables()
+            .next()
+            .unwrap()
+            .path()
+            .to_path_buf();
+        std::fs::copy(replacement, &path).unwrap();
+        let strict = EngineConfig {
+            data_dir,
+            corrupt_file_policy: CorruptFilePolicy::Strict,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Engine::with_config(strict).await,
+            Err(EngineError::RecoveryFailed(_))
+        ));
+    }
+}
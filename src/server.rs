@@ -0,0 +1,650 @@
+use bytes::Bytes;
+use std::future::Future;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::db::MaintenanceSchedule;
+use crate::engine::{Engine, EngineError, EngineResult};
+
+/// Errors that can occur while serving or talking to an engine over a socket
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Engine error: {0}")]
+    Engine(#[from] EngineError),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Socket {0:?} is already being served")]
+    AddrInUse(PathBuf),
+    #[error("Server error: {0}")]
+    Remote(String),
+    #[error("Engine is still shared by {0} other handles")]
+    EngineInUse(usize),
+}
+
+/// Result type for server and client operations
+pub type ServerResult<T> = Result<T, ServerError>;
+
+/// Largest frame either side accepts
+pub const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// Pause after a failed `accept`, so running out of file descriptors doesn't
+/// spin the accept loop
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+const OP_GET: u8 = 1;
+const OP_PUT: u8 = 2;
+const OP_DELETE: u8 = 3;
+const OP_SCAN: u8 = 4;
+const OP_INCR: u8 = 5;
+
+const STATUS_OK: u8 = 0;
+const STATUS_VALUE: u8 = 1;
+const STATUS_NOT_FOUND: u8 = 2;
+const STATUS_PAIRS: u8 = 3;
+const STATUS_ERROR: u8 = 4;
+
+/// A request sent to the server
+///
+/// Every frame is a u32 little-endian length followed by that many bytes. A
+/// request frame is an opcode byte followed by its fields; byte strings are
+/// a u32 length and the bytes, integers are little-endian.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// `1 | key`
+    Get { key: Vec<u8> },
+    /// `2 | key | value`
+    Put { key: Vec<u8>, value: Vec<u8> },
+    /// `3 | key`
+    Delete { key: Vec<u8> },
+    /// `4 | start | has_end: u8 | [end] | limit: u32`, end exclusive and
+    /// a limit of 0 meaning no limit
+    Scan {
+        start: Vec<u8>,
+        end: Option<Vec<u8>>,
+        limit: u32,
+    },
+    /// `5 | key | delta: i64`
+    Incr { key: Vec<u8>, delta: i64 },
+}
+
+/// A response to one request, led by a status byte
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// `0`: the request was applied
+    Ok,
+    /// `1 | value`
+    Value(Bytes),
+    /// `2`: the key has no value
+    NotFound,
+    /// `3 | count: u32 | (key | value)*`
+    Pairs(Vec<(Vec<u8>, Bytes)>),
+    /// `4 | message`
+    Error(String),
+}
+
+impl Request {
+    /// Encode as the body of a frame
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Get { key } => {
+                buf.push(OP_GET);
+                put_bytes(&mut buf, key);
+            }
+            Self::Put { key, value } => {
+                buf.push(OP_PUT);
+                put_bytes(&mut buf, key);
+                put_bytes(&mut buf, value);
+            }
+            Self::Delete { key } => {
+                buf.push(OP_DELETE);
+                put_bytes(&mut buf, key);
+            }
+            Self::Scan { start, end, limit } => {
+                buf.push(OP_SCAN);
+                put_bytes(&mut buf, start);
+                buf.push(end.is_some() as u8);
+                if let Some(end) = end {
+                    put_bytes(&mut buf, end);
+                }
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            Self::Incr { key, delta } => {
+                buf.push(OP_INCR);
+                put_bytes(&mut buf, key);
+                buf.extend_from_slice(&delta.to_le_bytes());
+            }
+        }
+        buf
+    }
+
+    /// Decode the body of a frame
+    pub fn decode(frame: &[u8]) -> ServerResult<Self> {
+        let mut fields = Fields(frame);
+        let request = match fields.u8()? {
+            OP_GET => Self::Get {
+                key: fields.bytes()?.to_vec(),
+            },
+            OP_PUT => Self::Put {
+                key: fields.bytes()?.to_vec(),
+                value: fields.bytes()?.to_vec(),
+            },
+            OP_DELETE => Self::Delete {
+                key: fields.bytes()?.to_vec(),
+            },
+            OP_SCAN => Self::Scan {
+                start: fields.bytes()?.to_vec(),
+                end: match fields.u8()? {
+                    0 => None,
+                    _ => Some(fields.bytes()?.to_vec()),
+                },
+                limit: u32::from_le_bytes(fields.array()?),
+            },
+            OP_INCR => Self::Incr {
+                key: fields.bytes()?.to_vec(),
+                delta: i64::from_le_bytes(fields.array()?),
+            },
+            op => return Err(ServerError::Protocol(format!("unknown opcode {}", op))),
+        };
+        fields.finish()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    /// Encode as the body of a frame
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Ok => buf.push(STATUS_OK),
+            Self::Value(value) => {
+                buf.push(STATUS_VALUE);
+                put_bytes(&mut buf, value);
+            }
+            Self::NotFound => buf.push(STATUS_NOT_FOUND),
+            Self::Pairs(pairs) => {
+                buf.push(STATUS_PAIRS);
+                buf.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+                for (key, value) in pairs {
+                    put_bytes(&mut buf, key);
+                    put_bytes(&mut buf, value);
+                }
+            }
+            Self::Error(message) => {
+                buf.push(STATUS_ERROR);
+                put_bytes(&mut buf, message.as_bytes());
+            }
+        }
+        buf
+    }
+
+    /// Decode the body of a frame
+    pub fn decode(frame: &[u8]) -> ServerResult<Self> {
+        let mut fields = Fields(frame);
+        let response = match fields.u8()? {
+            STATUS_OK => Self::Ok,
+            STATUS_VALUE => Self::Value(Bytes::copy_from_slice(fields.bytes()?)),
+            STATUS_NOT_FOUND => Self::NotFound,
+            STATUS_PAIRS => {
+                let count = u32::from_le_bytes(fields.array()?);
+                let mut pairs = Vec::new();
+                for _ in 0..count {
+                    let key = fields.bytes()?.to_vec();
+                    pairs.push((key, Bytes::copy_from_slice(fields.bytes()?)));
+                }
+                Self::Pairs(pairs)
+            }
+            STATUS_ERROR => Self::Error(String::from_utf8_lossy(fields.bytes()?).into_owned()),
+            status => return Err(ServerError::Protocol(format!("unknown status {}", status))),
+        };
+        fields.finish()?;
+        Ok(response)
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+/// Cursor over the fields of a frame
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> ServerResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(ServerError::Protocol("truncated frame".to_string()));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> ServerResult<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> ServerResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bytes(&mut self) -> ServerResult<&'a [u8]> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        self.take(len)
+    }
+
+    fn finish(self) -> ServerResult<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ServerError::Protocol(format!(
+                "{} trailing bytes",
+                self.0.len()
+            )))
+        }
+    }
+}
+
+/// Read one frame, or None if the peer closed the connection between frames
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> ServerResult<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(ServerError::Protocol(format!(
+            "frame of {} bytes exceeds the {} byte limit",
+            len, MAX_FRAME_BYTES
+        )));
+    }
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> ServerResult<()> {
+    writer
+        .write_all(&(frame.len() as u32).to_le_bytes())
+        .await?;
+    writer.write_all(frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Serves one engine to local processes over a unix domain socket
+///
+/// Lets several processes on a device share a database without each of them
+/// opening the data directory. Requests from all connections are applied to
+/// the one engine: reads run concurrently, writes one at a time. While it
+/// runs, the engine's `maintenance` passes are scheduled as for a
+/// [`crate::db::Db`].
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    engine: Arc<RwLock<Engine>>,
+}
+
+impl Server {
+    /// Listen on `path`, replacing a socket file left behind by a server that exited
+    ///
+    /// Fails with [`ServerError::AddrInUse`] if another server still answers there.
+    pub fn bind<P: AsRef<Path>>(engine: Engine, path: P) -> ServerResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(ServerError::AddrInUse(path));
+            }
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        info!("Serving engine on {:?}", path);
+        Ok(Self {
+            listener,
+            path,
+            engine: Arc::new(RwLock::new(engine)),
+        })
+    }
+
+    /// Socket path the server listens on
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accept connections until `shutdown` resolves, then hand the engine back
+    ///
+    /// Open connections are closed and the socket file is removed. A failed
+    /// `accept`, e.g. when the process is out of file descriptors, is logged
+    /// and retried after a short pause rather than ending the server, so the
+    /// engine is always returned for the caller to close.
+    pub async fn run_until<F: Future<Output = ()>>(self, shutdown: F) -> ServerResult<Engine> {
+        let schedule = MaintenanceSchedule::spawn(&self.engine).map(Arc::new);
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                accepted = self.listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            warn!("Failed to accept a connection on {:?}: {}", self.path, e);
+                            tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                            continue;
+                        }
+                    };
+                    let engine = self.engine.clone();
+                    let schedule = schedule.clone();
+                    connections.spawn(async move {
+                        if let Err(e) = serve_connection(stream, engine, schedule).await {
+                            warn!("Closing client connection: {}", e);
+                        }
+                    });
+                }
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            }
+        }
+
+        connections.shutdown().await;
+        if let Some(schedule) = schedule {
+            schedule.shutdown().await;
+        }
+        drop(self.listener);
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove socket {:?}: {}", self.path, e);
+        }
+        info!("Stopped serving on {:?}", self.path);
+        // Every connection and the maintenance task holding a handle has stopped
+        let engine = Arc::try_unwrap(self.engine)
+            .map_err(|engine| ServerError::EngineInUse(Arc::strong_count(&engine) - 1))?;
+        Ok(engine.into_inner())
+    }
+}
+
+/// Answer requests on one connection until the client hangs up
+async fn serve_connection(
+    stream: UnixStream,
+    engine: Arc<RwLock<Engine>>,
+    schedule: Option<Arc<MaintenanceSchedule>>,
+) -> ServerResult<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    while let Some(frame) = read_frame(&mut reader).await? {
+        let response = match Request::decode(&frame) {
+            Ok(request) => {
+                let writes = matches!(
+                    request,
+                    Request::Put { .. } | Request::Delete { .. } | Request::Incr { .. }
+                );
+                if let (true, Some(schedule)) = (writes, &schedule) {
+                    schedule.note_write();
+                }
+                handle(&engine, request).await
+            }
+            Err(e) => Response::Error(e.to_string()),
+        };
+        write_frame(&mut writer, &response.encode()).await?;
+    }
+    debug!("Client disconnected");
+    Ok(())
+}
+
+async fn handle(engine: &RwLock<Engine>, request: Request) -> Response {
+    let result = match request {
+        Request::Get { key } => engine
+            .read()
+            .await
+            .get(&key)
+            .await
+            .map(|value| match value {
+                Some(value) => Response::Value(value),
+                None => Response::NotFound,
+            }),
+        Request::Put { key, value } => engine
+            .write()
+            .await
+            .put(&key, &value)
+            .await
+            .map(|_| Response::Ok),
+        Request::Delete { key } => engine
+            .write()
+            .await
+            .delete(&key)
+            .await
+            .map(|_| Response::Ok),
+        Request::Scan { start, end, limit } => {
+            let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+            let limit = if limit == 0 {
+                usize::MAX
+            } else {
+                limit as usize
+            };
+            let pairs: EngineResult<_> = engine
+                .read()
+                .await
+                .iter((Bound::Included(start), end))
+                .take(limit)
+                .collect();
+            pairs.map(Response::Pairs)
+        }
+        Request::Incr { key, delta } => engine
+            .write()
+            .await
+            .incr(&key, delta)
+            .await
+            .map(|_| Response::Ok),
+    };
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+/// Connection to a [`Server`] from another process
+pub struct Client {
+    stream: BufReader<UnixStream>,
+}
+
+impl Client {
+    /// Connect to a server listening on `path`
+    pub async fn connect<P: AsRef<Path>>(path: P) -> ServerResult<Self> {
+        let stream = UnixStream::connect(path).await?;
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    /// Send one request and wait for its response
+    pub async fn call(&mut self, request: &Request) -> ServerResult<Response> {
+        write_frame(self.stream.get_mut(), &request.encode()).await?;
+        let frame = read_frame(&mut self.stream)
+            .await?
+            .ok_or_else(|| ServerError::Protocol("server closed the connection".to_string()))?;
+        match Response::decode(&frame)? {
+            Response::Error(message) => Err(ServerError::Remote(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// See [`Engine::get`]
+    pub async fn get(&mut self, key: &[u8]) -> ServerResult<Option<Bytes>> {
+        match self.call(&Request::Get { key: key.to_vec() }).await? {
+            Response::Value(value) => Ok(Some(value)),
+            Response::NotFound => Ok(None),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// See [`Engine::put`]
+    pub async fn put(&mut self, key: &[u8], value: &[u8]) -> ServerResult<()> {
+        let request = Request::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        };
+        self.expect_ok(&request).await
+    }
+
+    /// See [`Engine::delete`]
+    pub async fn delete(&mut self, key: &[u8]) -> ServerResult<()> {
+        self.expect_ok(&Request::Delete { key: key.to_vec() }).await
+    }
+
+    /// Up to `limit` pairs from `start` up to `end` (exclusive), 0 meaning no limit
+    pub async fn scan(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        limit: u32,
+    ) -> ServerResult<Vec<(Vec<u8>, Bytes)>> {
+        let request = Request::Scan {
+            start: start.to_vec(),
+            end: end.map(<[u8]>::to_vec),
+            limit,
+        };
+        match self.call(&request).await? {
+            Response::Pairs(pairs) => Ok(pairs),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// See [`Engine::incr`]
+    pub async fn incr(&mut self, key: &[u8], delta: i64) -> ServerResult<()> {
+        let request = Request::Incr {
+            key: key.to_vec(),
+            delta,
+        };
+        self.expect_ok(&request).await
+    }
+
+    async fn expect_ok(&mut self, request: &Request) -> ServerResult<()> {
+        match self.call(request).await? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> ServerError {
+    ServerError::Protocol(format!("unexpected response {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_frames_round_trip() {
+        let requests = [
+            Request::Get { key: b"k".to_vec() },
+            Request::Put {
+                key: b"k".to_vec(),
+                value: b"v".to_vec(),
+            },
+            Request::Scan {
+                start: b"a".to_vec(),
+                end: None,
+                limit: 10,
+            },
+            Request::Incr {
+                key: b"n".to_vec(),
+                delta: -3,
+            },
+        ];
+        for request in requests {
+            assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+        }
+
+        let response = Response::Pairs(vec![(b"k".to_vec(), Bytes::from_static(b"v"))]);
+        assert_eq!(Response::decode(&response.encode()).unwrap(), response);
+        assert!(Request::decode(&[OP_GET, 9, 0, 0, 0]).is_err());
+        assert!(Request::decode(&[0xFF]).is_err());
+        assert!(Response::decode(&[STATUS_OK, 0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_clients_share_engine() {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path().join("db")).await.unwrap();
+        let socket = temp_dir.path().join("db.sock");
+        let server = Server::bind(engine, &socket).unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn(server.run_until(async {
+            let _ = stopped.await;
+        }));
+
+        // A second server on the same socket is refused
+        let other = Engine::new(temp_dir.path().join("other")).await.unwrap();
+        assert!(matches!(
+            Server::bind(other, &socket),
+            Err(ServerError::AddrInUse(_))
+        ));
+
+        let mut tasks = Vec::new();
+        for client in 0..4 {
+            let socket = socket.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut client_conn = Client::connect(&socket).await.unwrap();
+                for i in 0..10 {
+                    let key = format!("c{}:{}", client, i);
+                    client_conn.put(key.as_bytes(), b"value").await.unwrap();
+                }
+                client_conn.incr(b"hits", 1).await.unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut client = Client::connect(&socket).await.unwrap();
+        assert_eq!(
+            client.get(b"c2:5").await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+        assert_eq!(client.get(b"missing").await.unwrap(), None);
+        client.delete(b"c2:5").await.unwrap();
+        let pairs = client.scan(b"c2:", Some(b"c2;"), 0).await.unwrap();
+        assert_eq!(pairs.len(), 9);
+        assert_eq!(client.scan(b"c", None, 5).await.unwrap().len(), 5);
+
+        // Engine errors come back as errors without closing the connection
+        assert!(matches!(
+            client.put(b"", b"value").await,
+            Err(ServerError::Remote(_))
+        ));
+        assert!(client.get(b"c1:1").await.unwrap().is_some());
+
+        stop.send(()).unwrap();
+        let engine = running.await.unwrap().unwrap();
+        assert!(!socket.exists());
+        assert_eq!(engine.get_i64(b"hits").await.unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_engine_is_returned_when_socket_is_gone() {
+        let temp_dir = tempdir().unwrap();
+        let mut engine = Engine::new(temp_dir.path().join("db")).await.unwrap();
+        engine.put(b"key", b"value").await.unwrap();
+        let socket = temp_dir.path().join("db.sock");
+        let server = Server::bind(engine, &socket).unwrap();
+
+        // Someone removed the socket file while serving
+        std::fs::remove_file(&socket).unwrap();
+        let engine = server.run_until(async {}).await.unwrap();
+        assert_eq!(
+            engine.get(b"key").await.unwrap(),
+            Some(Bytes::from_static(b"value"))
+        );
+    }
+}
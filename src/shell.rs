@@ -0,0 +1,442 @@
+use std::io::{self, BufRead, Write};
+use std::ops::Bound;
+use thiserror::Error;
+
+use crate::engine::{Engine, EngineError};
+
+/// Errors from parsing or running a shell command
+#[derive(Error, Debug)]
+pub enum ShellError {
+    #[error("{0}")]
+    Usage(String),
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Result type for shell commands
+pub type ShellResult<T> = Result<T, ShellError>;
+
+/// Commands understood by [`Shell`], one per line in the REPL
+pub const SHELL_HELP: &str = "\
+Commands:
+  get <key>                       Print the value of a key
+  put <key> <value>               Set a key
+  delete <key>                    Delete a key
+  scan [<start> [<end>]] [--limit <n>]
+                                  Print keys in [start, end) with their values
+  stats                           Print engine statistics
+  flush                           Flush the MemTable to an SSTable
+  compact                         Compact every SSTable into the last level
+  help                            Show this help
+  quit                            Leave the shell
+
+Keys and values may be quoted, and take \\n, \\t, \\\\ and \\xNN escapes;
+output escapes bytes that aren't printable ASCII the same way.";
+
+/// One parsed shell command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Get {
+        key: Vec<u8>,
+    },
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+    Scan {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        limit: Option<usize>,
+    },
+    Stats,
+    Flush,
+    Compact,
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a command from its words, as split by [`split_line`]
+    pub fn parse(words: &[Vec<u8>]) -> ShellResult<Self> {
+        let Some((name, args)) = words.split_first() else {
+            return Err(ShellError::Usage("empty command".to_string()));
+        };
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        let arity = |expected: usize, usage: &str| {
+            if args.len() == expected {
+                Ok(())
+            } else {
+                Err(ShellError::Usage(format!("usage: {}", usage)))
+            }
+        };
+        let command = match name.as_str() {
+            "get" => {
+                arity(1, "get <key>")?;
+                Command::Get {
+                    key: args[0].clone(),
+                }
+            }
+            "put" | "set" => {
+                arity(2, "put <key> <value>")?;
+                Command::Put {
+                    key: args[0].clone(),
+                    value: args[1].clone(),
+                }
+            }
+            "delete" | "del" => {
+                arity(1, "delete <key>")?;
+                Command::Delete {
+                    key: args[0].clone(),
+                }
+            }
+            "scan" => Self::parse_scan(args)?,
+            "stats" => {
+                arity(0, "stats")?;
+                Command::Stats
+            }
+            "flush" => {
+                arity(0, "flush")?;
+                Command::Flush
+            }
+            "compact" => {
+                arity(0, "compact")?;
+                Command::Compact
+            }
+            "help" | "?" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            other => {
+                return Err(ShellError::Usage(format!(
+                    "unknown command {:?}; try help",
+                    other
+                )));
+            }
+        };
+        Ok(command)
+    }
+
+    fn parse_scan(args: &[Vec<u8>]) -> ShellResult<Self> {
+        let usage = || ShellError::Usage("usage: scan [<start> [<end>]] [--limit <n>]".to_string());
+        let mut bounds = Vec::new();
+        let mut limit = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == b"--limit" {
+                let n = args.next().ok_or_else(usage)?;
+                limit = Some(
+                    std::str::from_utf8(n)
+                        .ok()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or_else(usage)?,
+                );
+            } else {
+                bounds.push(arg.clone());
+            }
+        }
+        if bounds.len() > 2 {
+            return Err(usage());
+        }
+        let mut bounds = bounds.into_iter();
+        Ok(Command::Scan {
+            start: bounds.next(),
+            end: bounds.next(),
+            limit,
+        })
+    }
+}
+
+/// Split a command line into words
+///
+/// Words are separated by whitespace; single or double quotes keep
+/// whitespace in a word, and backslash escapes (`\n`, `\t`, `\\`, `\"`,
+/// `\'`, `\xNN`) produce arbitrary bytes.
+pub fn split_line(line: &str) -> ShellResult<Vec<Vec<u8>>> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word
+                .get_or_insert_with(Vec::new)
+                .push(unescape_next(&mut chars)?),
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(c);
+                word.get_or_insert_with(Vec::new);
+            }
+            c if c.is_whitespace() && quote.is_none() => words.extend(word.take()),
+            c => push_char(word.get_or_insert_with(Vec::new), c),
+        }
+    }
+    if quote.is_some() {
+        return Err(ShellError::Usage("unterminated quote".to_string()));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Decode the backslash escapes in one word that is already split, such as
+/// a command-line argument; quotes and whitespace are kept as they are
+pub fn unescape(word: &str) -> ShellResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(word.len());
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => bytes.push(unescape_next(&mut chars)?),
+            c => push_char(&mut bytes, c),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decode the escape after a backslash
+fn unescape_next(chars: &mut std::str::Chars<'_>) -> ShellResult<u8> {
+    match chars.next() {
+        Some('n') => Ok(b'\n'),
+        Some('r') => Ok(b'\r'),
+        Some('t') => Ok(b'\t'),
+        Some('0') => Ok(0),
+        Some('x') => {
+            let hex: String = chars.take(2).collect();
+            u8::from_str_radix(&hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 2)
+                .ok_or_else(|| ShellError::Usage(format!("invalid escape \\x{}", hex)))
+        }
+        Some(c @ ('\\' | '"' | '\'' | ' ')) => Ok(c as u8),
+        Some(c) => Err(ShellError::Usage(format!("invalid escape \\{}", c))),
+        None => Err(ShellError::Usage("trailing backslash".to_string())),
+    }
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buf = [0u8; 4];
+    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Runs shell commands against an open engine, writing their output to `out`
+pub struct Shell<W: Write> {
+    engine: Engine,
+    out: W,
+}
+
+impl<W: Write> Shell<W> {
+    pub fn new(engine: Engine, out: W) -> Self {
+        Self { engine, out }
+    }
+
+    /// The engine commands run against
+    pub fn engine(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Run one command; returns false once the shell should exit
+    pub async fn execute(&mut self, command: Command) -> ShellResult<bool> {
+        match command {
+            Command::Get { key } => match self.engine.get(&key).await? {
+                Some(value) => writeln!(self.out, "{}", value.escape_ascii())?,
+                None => writeln!(self.out, "(not found)")?,
+            },
+            Command::Put { key, value } => {
+                let sequence = self.engine.put(&key, &value).await?;
+                writeln!(self.out, "OK (sequence {})", sequence)?;
+            }
+            Command::Delete { key } => {
+                let sequence = self.engine.delete(&key).await?;
+                writeln!(self.out, "OK (sequence {})", sequence)?;
+            }
+            Command::Scan { start, end, limit } => {
+                let start = start.as_deref().map_or(Bound::Unbounded, Bound::Included);
+                let end = end.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+                let iter = self.engine.iter::<&[u8], _>((start, end));
+                let mut count = 0;
+                for pair in iter.take(limit.unwrap_or(usize::MAX)) {
+                    let (key, value) = pair?;
+                    writeln!(self.out, "{}\t{}", key.escape_ascii(), value.escape_ascii())?;
+                    count += 1;
+                }
+                writeln!(self.out, "({} keys)", count)?;
+            }
+            Command::Stats => self.write_stats()?,
+            Command::Flush => {
+                self.engine.force_flush().await?;
+                writeln!(self.out, "OK")?;
+            }
+            Command::Compact => {
+                let stats = self.engine.compact_all().await?;
+                writeln!(
+                    self.out,
+                    "Compacted {} SSTables into {} ({} entries written, {} dropped)",
+                    stats.input_sstables,
+                    stats.output_sstables,
+                    stats.entries_written,
+                    stats.entries_dropped
+                )?;
+            }
+            Command::Help => writeln!(self.out, "{}", SHELL_HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn write_stats(&mut self) -> io::Result<()> {
+        let stats = self.engine.stats();
+        let out = &mut self.out;
+        writeln!(out, "data_dir: {}", stats.data_dir.display())?;
+        writeln!(out, "sequence_number: {}", self.engine.sequence_number())?;
+        writeln!(
+            out,
+            "last_durable_sequence: {}",
+            self.engine.last_durable_sequence()
+        )?;
+        writeln!(out, "memtable_bytes: {}", stats.memtable_size)?;
+        writeln!(out, "immutable_memtables: {}", stats.immutable_memtables)?;
+        writeln!(out, "sstable_count: {}", stats.sstable_count)?;
+        writeln!(out, "compactions_running: {}", stats.compactions_running)?;
+        writeln!(out, "open_files: {}", stats.open_files.total())?;
+        writeln!(
+            out,
+            "block_cache: {} of {} bytes, {:.1}% hits",
+            stats.block_cache.used_bytes,
+            stats.block_cache.capacity,
+            stats.block_cache.hit_rate() * 100.0
+        )?;
+        writeln!(out, "io_backend: {:?}", stats.io_backend)?;
+        Ok(())
+    }
+
+    /// Read commands line by line from `input` until it ends or `quit`,
+    /// printing `prompt` before each; errors are printed and the loop goes on
+    pub async fn run<R: BufRead>(&mut self, mut input: R, prompt: Option<&str>) -> io::Result<()> {
+        loop {
+            if let Some(prompt) = prompt {
+                write!(self.out, "{}", prompt)?;
+                self.out.flush()?;
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let result = match split_line(&line) {
+                Ok(words) if words.is_empty() => continue,
+                Ok(words) => match Command::parse(&words) {
+                    Ok(command) => self.execute(command).await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(e) => writeln!(self.out, "error: {}", e)?,
+            }
+        }
+    }
+
+    /// Close the engine, flushing what it holds
+    pub async fn close(mut self) -> ShellResult<()> {
+        self.engine.close().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn words(line: &str) -> Vec<Vec<u8>> {
+        split_line(line).unwrap()
+    }
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(
+            words("  put k  v "),
+            vec![b"put".to_vec(), b"k".to_vec(), b"v".to_vec()]
+        );
+        assert_eq!(
+            words(r#"put "a key" 'it''s' \xff\x00\n"#),
+            vec![
+                b"put".to_vec(),
+                b"a key".to_vec(),
+                b"its".to_vec(),
+                vec![0xFF, 0x00, b'\n']
+            ]
+        );
+        assert_eq!(words(r#"get """#), vec![b"get".to_vec(), Vec::new()]);
+        assert!(split_line("get \"open").is_err());
+        assert!(split_line("get \\xZ1").is_err());
+        assert!(split_line("get \\q").is_err());
+        assert_eq!(unescape("two \"words\"\\t").unwrap(), b"two \"words\"\t");
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            Command::parse(&words("SCAN a --limit 5")).unwrap(),
+            Command::Scan {
+                start: Some(b"a".to_vec()),
+                end: None,
+                limit: Some(5),
+            }
+        );
+        assert_eq!(
+            Command::parse(&words("del k")).unwrap(),
+            Command::Delete { key: b"k".to_vec() }
+        );
+        for bad in [
+            "get",
+            "put k",
+            "scan a b c",
+            "scan --limit x",
+            "stats now",
+            "frobnicate",
+        ] {
+            assert!(
+                matches!(Command::parse(&words(bad)), Err(ShellError::Usage(_))),
+                "{:?} parsed",
+                bad
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shell_session() {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        let mut shell = Shell::new(engine, Vec::new());
+        let input = "\
+put a 1
+put b \"two words\"
+put c \\xff
+bogus
+
+delete a
+get a
+scan
+scan b c
+flush
+stats
+quit
+put d never
+";
+        shell.run(input.as_bytes(), None).await.unwrap();
+        let output = String::from_utf8(std::mem::take(&mut shell.out)).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "OK (sequence 1)");
+        assert!(lines[3].starts_with("error: unknown command"));
+        assert_eq!(lines[5], "(not found)");
+        assert_eq!(&lines[6..9], ["b\ttwo words", "c\t\\xff", "(2 keys)"]);
+        assert_eq!(&lines[9..11], ["b\ttwo words", "(1 keys)"]);
+        assert_eq!(lines[11], "OK");
+        assert!(output.contains("sstable_count: 1\n"));
+        assert!(shell.engine().get(b"d").await.unwrap().is_none());
+        shell.close().await.unwrap();
+    }
+}
@@ -1,10 +1,19 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{debug, info, trace, warn};
 
-use crate::memtable::MemTable;
+use crate::cache::BlockCache;
+use crate::io_backend::{self, IoBackend};
+use crate::keys::PrefixExtractor;
+use crate::memtable::{Entry, MemTable};
 
 /// Errors that can occur during SSTable operations
 #[derive(Error, Debug)]
@@ -19,30 +28,190 @@ pub enum SSTableError {
     KeyNotFound(String),
     #[error("Invalid index: {0}")]
     InvalidIndex(String),
+    #[error("Compression error: {0}")]
+    Compression(String),
+    #[error("Unsupported SSTable format version {0}")]
+    UnsupportedVersion(u32),
 }
 
 /// Result type for SSTable operations
 pub type SSTableResult<T> = Result<T, SSTableError>;
 
 /// Compression type for SSTable data
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum CompressionType {
+    #[default]
     None,
     LZ4,
     Zstd,
 }
 
-impl Default for CompressionType {
+impl CompressionType {
+    /// Parse the compression type stored in an SSTable header
+    pub fn from_u8(value: u8) -> SSTableResult<Self> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::LZ4),
+            2 => Ok(Self::Zstd),
+            other => Err(SSTableError::InvalidFormat(format!(
+                "Unknown compression type {}",
+                other
+            ))),
+        }
+    }
+
+    /// Compress `data`
+    pub fn compress(self, data: &[u8]) -> SSTableResult<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::LZ4 => Ok(lz4_flex::compress_prepend_size(data)),
+            Self::Zstd => {
+                zstd::bulk::compress(data, 0).map_err(|e| SSTableError::Compression(e.to_string()))
+            }
+        }
+    }
+
+    /// Decompress data produced by [`CompressionType::compress`]
+    pub fn decompress(self, data: &[u8]) -> SSTableResult<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::LZ4 => {
+                // Don't let a corrupt size prefix drive a huge allocation; LZ4
+                // cannot expand input by more than 255x
+                let claimed = data
+                    .get(..4)
+                    .map_or(0, |prefix| u32::from_le_bytes(prefix.try_into().unwrap()));
+                if claimed as usize > data.len().saturating_mul(255) {
+                    return Err(SSTableError::Compression(format!(
+                        "Decompressed size {} is implausible for {} input bytes",
+                        claimed,
+                        data.len()
+                    )));
+                }
+                lz4_flex::decompress_size_prepended(data)
+                    .map_err(|e| SSTableError::Compression(e.to_string()))
+            }
+            Self::Zstd => {
+                zstd::stream::decode_all(data).map_err(|e| SSTableError::Compression(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Options controlling which values get compressed
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionOptions {
+    /// Values shorter than this are stored uncompressed to skip codec overhead
+    pub min_value_size: usize,
+}
+
+impl Default for CompressionOptions {
     fn default() -> Self {
-        Self::None
+        Self { min_value_size: 64 }
     }
 }
 
-/// Metadata for compression
+/// SSTable format version written by this build
+///
+/// - v1: original format; entry value lengths are plain byte counts
+/// - v2: the top bits of an entry's value length flag indirect, compressed and merge values
+/// - v3: entry headers are varints, with timestamps and sequence numbers stored
+///   as deltas from per-table bases in the file header
+/// - v4: keys in the data section omit the prefix they share with the previous
+///   key, restarting every [`KEY_RESTART_INTERVAL`] entries
+/// - v5: the bloom filter section starts with the filter's byte length, bit
+///   count and hash count
+/// - v6: the data section is a run of blocks, each ending in the offsets of
+///   its restart points, and the index holds one handle per block instead of
+///   one entry per key
+pub const SSTABLE_FORMAT_VERSION: u32 = 6;
+
+/// First format version whose data section is split into blocks
+pub const BLOCK_FORMAT_VERSION: u32 = 6;
+
+/// Default target size of a data block in bytes
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Oldest SSTable format version this build can read
+pub const MIN_SSTABLE_FORMAT_VERSION: u32 = 1;
+
+/// Size of the fixed per-entry header of v1 and v2 tables: key_len (4) + value_len (4) + timestamp (8) + seq (8)
+pub const ENTRY_HEADER_SIZE: usize = 24;
+
+/// Largest entry header of a v4 table: shared prefix (5) + key suffix (5) +
+/// value_len and flags (5) + timestamp delta (10) + seq delta (10), all varints
+pub const MAX_ENTRY_HEADER_SIZE: usize = 35;
+
+/// Entries between restart points of a v4 table, or of a block from v6 on,
+/// where a key is stored whole
+pub const KEY_RESTART_INTERVAL: usize = 16;
+
+/// High bit of an entry's value length, set when the value is a blob pointer
+const INDIRECT_VALUE_FLAG: u32 = 1 << 31;
+
+/// Bit of an entry's value length set when the value is stored compressed
+const COMPRESSED_VALUE_FLAG: u32 = 1 << 30;
+
+/// Bit of an entry's value length set when the value is a merge operand
+const MERGE_OPERAND_FLAG: u32 = 1 << 29;
+
+/// Every flag bit of an entry's value length
+const VALUE_FLAGS: u32 = INDIRECT_VALUE_FLAG | COMPRESSED_VALUE_FLAG | MERGE_OPERAND_FLAG;
+
+/// Position of the lowest flag bit; v3 headers store the flags below the length instead
+const VALUE_FLAGS_SHIFT: u32 = 29;
+
+/// Default read-ahead window for sequential scans
+pub const DEFAULT_READAHEAD_BYTES: usize = 256 * 1024;
+
+/// Number of back-to-back sequential reads before read-ahead kicks in
+const SEQUENTIAL_READS_BEFORE_READAHEAD: usize = 2;
+
+/// Options controlling how SSTable files are written
 #[derive(Debug, Clone)]
+pub struct SSTableWriteOptions {
+    /// Compression type for the data section
+    pub compression: CompressionType,
+    /// Which values are worth compressing
+    pub compression_options: CompressionOptions,
+    /// Write with O_DIRECT so large flushes don't evict the OS page cache
+    pub use_direct_io: bool,
+    /// Format version to write; 2 keeps fixed-size entry headers that older
+    /// builds can read
+    pub format_version: u32,
+    /// Size at which a data block is closed; a block ends with the first
+    /// entry that takes it to this size, so one large value gets a block of
+    /// its own (v6)
+    pub block_size: usize,
+    /// Also add key prefixes to the bloom filter, so scans within one prefix
+    /// can skip the table (v5 on)
+    pub prefix_extractor: Option<PrefixExtractor>,
+}
+
+impl Default for SSTableWriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: CompressionType::default(),
+            compression_options: CompressionOptions::default(),
+            use_direct_io: false,
+            format_version: SSTABLE_FORMAT_VERSION,
+            block_size: DEFAULT_BLOCK_SIZE,
+            prefix_extractor: None,
+        }
+    }
+}
+
+/// How much a table's compressed values shrank
+///
+/// Sizes only count the values stored compressed; values below the size
+/// threshold, or that didn't shrink, are left out of both.
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompressionMetadata {
     pub compression_type: CompressionType,
+    /// Bytes of the compressed values before compression (0 for tables
+    /// written before it was recorded)
     pub original_size: usize,
+    /// Bytes the compressed values take in the data section
     pub compressed_size: usize,
 }
 
@@ -57,7 +226,7 @@ impl Default for CompressionMetadata {
 }
 
 /// Index entry for fast key lookup
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IndexEntry {
     pub key: Vec<u8>,
     pub offset: u64,
@@ -65,10 +234,553 @@ pub struct IndexEntry {
     pub value_size: u32,
 }
 
+impl IndexEntry {
+    /// Length of the stored (possibly compressed) value in bytes
+    pub fn value_len(&self) -> usize {
+        (self.value_size & !VALUE_FLAGS) as usize
+    }
+
+    /// Whether the stored value is compressed with the table's compression type
+    pub fn is_compressed(&self) -> bool {
+        self.value_size & COMPRESSED_VALUE_FLAG != 0
+    }
+
+    /// Whether the stored value is a blob pointer
+    pub fn is_indirect(&self) -> bool {
+        self.value_size & INDIRECT_VALUE_FLAG != 0
+    }
+
+    /// Whether the stored value is a merge operand
+    pub fn is_merge(&self) -> bool {
+        self.value_size & MERGE_OPERAND_FLAG != 0
+    }
+}
+
+/// Location of one data block of a v6 table, as listed in its index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHandle {
+    /// Key of the block's first entry, which is stored whole
+    pub first_key: Vec<u8>,
+    /// Offset of the block relative to the data section
+    pub offset: u64,
+    /// Stored length of the block, restart offsets included
+    pub size: u32,
+    pub entry_count: u32,
+    pub tombstone_count: u32,
+}
+
+/// Size of each restart offset, and of the restart count that ends a block
+const RESTART_OFFSET_SIZE: usize = 4;
+
+/// Size of the fixed fields of a block handle in the index: key_len (4) +
+/// offset (8) + size (4) + entry_count (4) + tombstone_count (4)
+const BLOCK_HANDLE_FIXED_SIZE: u64 = 24;
+
+/// Size of the fields after the block handles of a v6 index: compressed value
+/// bytes (8), then the last key's length (4) after the key itself
+const BLOCK_INDEX_TRAILER_SIZE: u64 = 12;
+
+/// Data block being filled while a v6 table is written
+#[derive(Default)]
+struct BlockBuilder {
+    buf: Vec<u8>,
+    /// Offsets of the restart points within `buf`
+    restarts: Vec<u32>,
+    first_key: Vec<u8>,
+    entry_count: u32,
+    tombstone_count: u32,
+}
+
+impl BlockBuilder {
+    /// Append an entry whose header has already been encoded
+    fn add(&mut self, key: &[u8], entry_header: &[u8], shared_prefix: usize, value: &[u8]) {
+        if (self.entry_count as usize).is_multiple_of(KEY_RESTART_INTERVAL) {
+            self.restarts.push(self.buf.len() as u32);
+        }
+        if self.entry_count == 0 {
+            self.first_key = key.to_vec();
+        }
+        self.buf.extend_from_slice(entry_header);
+        self.buf.extend_from_slice(&key[shared_prefix..]);
+        self.buf.extend_from_slice(value);
+        self.entry_count += 1;
+        if value.is_empty() {
+            self.tombstone_count += 1;
+        }
+    }
+
+    /// Close the block with its restart offsets and write it at `offset` in
+    /// the data section
+    fn finish<W: Write>(&mut self, writer: &mut W, offset: u64) -> io::Result<BlockHandle> {
+        let mut block = std::mem::take(self);
+        for restart in &block.restarts {
+            block.buf.extend_from_slice(&restart.to_le_bytes());
+        }
+        block
+            .buf
+            .extend_from_slice(&(block.restarts.len() as u32).to_le_bytes());
+        writer.write_all(&block.buf)?;
+        Ok(BlockHandle {
+            first_key: block.first_key,
+            offset,
+            size: block.buf.len() as u32,
+            entry_count: block.entry_count,
+            tombstone_count: block.tombstone_count,
+        })
+    }
+}
+
+/// Bytes of one data block: read whole, or fetched piece by piece with
+/// positioned reads so a lookup touches only the headers and keys it needs
+enum BlockSource<'a> {
+    Buffer(Bytes),
+    File {
+        sstable: &'a SSTable,
+        /// File offset of the block
+        start: u64,
+        size: usize,
+    },
+}
+
+impl BlockSource<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Buffer(bytes) => bytes.len(),
+            Self::File { size, .. } => *size,
+        }
+    }
+
+    /// `len` bytes at `offset` within the block
+    fn read(&self, offset: usize, len: usize) -> SSTableResult<Bytes> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len()) {
+            return Err(SSTableError::InvalidFormat(format!(
+                "Read of {} bytes at {} overruns a {}-byte block",
+                len,
+                offset,
+                self.len()
+            )));
+        }
+        match self {
+            Self::Buffer(bytes) => Ok(bytes.slice(offset..offset + len)),
+            Self::File { sstable, start, .. } => {
+                let mut buf = vec![0u8; len];
+                IoBackend::current().read_exact_at(
+                    &sstable.file,
+                    &mut buf,
+                    start + offset as u64,
+                )?;
+                Ok(Bytes::from(buf))
+            }
+        }
+    }
+
+    /// Restart offsets stored at the end of the block, and where its entries end
+    fn restarts(&self, handle: &BlockHandle) -> SSTableResult<(Vec<u32>, usize)> {
+        let invalid = |what: &str| {
+            SSTableError::InvalidFormat(format!("Block at {} has {}", handle.offset, what))
+        };
+        let count_at = self
+            .len()
+            .checked_sub(RESTART_OFFSET_SIZE)
+            .ok_or_else(|| invalid("no restart count"))?;
+        let count = u32::from_le_bytes(
+            self.read(count_at, RESTART_OFFSET_SIZE)?[..]
+                .try_into()
+                .unwrap(),
+        );
+        if count as usize != (handle.entry_count as usize).div_ceil(KEY_RESTART_INTERVAL) {
+            return Err(invalid("a restart count that disagrees with the index"));
+        }
+        let entries_len = (count as usize)
+            .checked_mul(RESTART_OFFSET_SIZE)
+            .and_then(|len| count_at.checked_sub(len))
+            .ok_or_else(|| invalid("more restarts than bytes"))?;
+        let restarts: Vec<u32> = self
+            .read(entries_len, count as usize * RESTART_OFFSET_SIZE)?
+            .as_chunks::<RESTART_OFFSET_SIZE>()
+            .0
+            .iter()
+            .map(|offset| u32::from_le_bytes(*offset))
+            .collect();
+        if restarts.first() != Some(&0)
+            || restarts.windows(2).any(|pair| pair[0] >= pair[1])
+            || restarts
+                .last()
+                .is_some_and(|&last| last as usize >= entries_len)
+        {
+            return Err(invalid("restart offsets out of order"));
+        }
+        Ok((restarts, entries_len))
+    }
+}
+
+/// An entry decoded from a block, with its value left in place
+#[derive(Debug)]
+struct BlockEntry {
+    key: Vec<u8>,
+    header: EntryHeader,
+    /// Offsets within the block of the entry header, the stored key suffix
+    /// and the value, and where the entry ends
+    start: usize,
+    key_offset: usize,
+    value_offset: usize,
+    end: usize,
+}
+
+impl BlockEntry {
+    /// Decode the entry at `offset`, whose key shares a prefix with `previous_key`
+    fn read(
+        source: &BlockSource<'_>,
+        table_header: &SSTableHeader,
+        offset: usize,
+        entries_len: usize,
+        previous_key: &[u8],
+    ) -> SSTableResult<Self> {
+        let invalid = || SSTableError::InvalidFormat(format!("Invalid block entry at {}", offset));
+        let available = entries_len.checked_sub(offset).ok_or_else(invalid)?;
+        let head = source.read(offset, MAX_ENTRY_HEADER_SIZE.min(available))?;
+        let (header, header_len) = EntryHeader::decode_prefix(table_header, &head)?;
+        let shared_prefix = header.shared_prefix as usize;
+        if shared_prefix > previous_key.len() {
+            return Err(invalid());
+        }
+        let key_offset = offset + header_len;
+        let suffix_len = header.key_size as usize - shared_prefix;
+        let value_offset = key_offset + suffix_len;
+        let end = value_offset + (header.value_size & !VALUE_FLAGS) as usize;
+        if end > entries_len {
+            return Err(invalid());
+        }
+        let mut key = Vec::with_capacity(header.key_size as usize);
+        key.extend_from_slice(&previous_key[..shared_prefix]);
+        key.extend_from_slice(&source.read(key_offset, suffix_len)?);
+        Ok(Self {
+            key,
+            header,
+            start: offset,
+            key_offset,
+            value_offset,
+            end,
+        })
+    }
+
+    /// Every entry of a block, checked against its handle and restart offsets
+    fn read_all(
+        source: &BlockSource<'_>,
+        table_header: &SSTableHeader,
+        handle: &BlockHandle,
+    ) -> SSTableResult<Vec<Self>> {
+        let (restarts, entries_len) = source.restarts(handle)?;
+        let mut entries: Vec<Self> = Vec::with_capacity(handle.entry_count as usize);
+        let mut offset = 0;
+        while offset < entries_len {
+            let slot = entries.len();
+            let restart = slot.is_multiple_of(KEY_RESTART_INTERVAL);
+            if restart && restarts.get(slot / KEY_RESTART_INTERVAL) != Some(&(offset as u32)) {
+                return Err(SSTableError::InvalidFormat(format!(
+                    "Block at {} has a misplaced restart point",
+                    handle.offset
+                )));
+            }
+            let previous_key = match entries.last() {
+                Some(previous) if !restart => previous.key.as_slice(),
+                _ => &[],
+            };
+            let entry = Self::read(source, table_header, offset, entries_len, previous_key)?;
+            offset = entry.end;
+            entries.push(entry);
+        }
+        let tombstones = entries
+            .iter()
+            .filter(|entry| entry.value_len() == 0)
+            .count();
+        if entries.len() != handle.entry_count as usize
+            || tombstones != handle.tombstone_count as usize
+            || entries.first().map(|entry| &entry.key) != Some(&handle.first_key)
+        {
+            return Err(SSTableError::InvalidIndex(format!(
+                "Block at {} disagrees with its index entry",
+                handle.offset
+            )));
+        }
+        Ok(entries)
+    }
+
+    /// Find `key` in a block, scanning from the last restart point at or before it
+    fn find(
+        source: &BlockSource<'_>,
+        table_header: &SSTableHeader,
+        handle: &BlockHandle,
+        key: &[u8],
+    ) -> SSTableResult<Option<Self>> {
+        let (restarts, entries_len) = source.restarts(handle)?;
+        let (mut low, mut high) = (0, restarts.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let restart = Self::read(
+                source,
+                table_header,
+                restarts[mid] as usize,
+                entries_len,
+                &[],
+            )?;
+            if restart.key.as_slice() <= key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let Some(restart) = low.checked_sub(1) else {
+            return Ok(None);
+        };
+
+        let mut offset = restarts[restart] as usize;
+        let end = restarts.get(low).map_or(entries_len, |&next| next as usize);
+        let mut previous_key = Vec::new();
+        while offset < end {
+            let entry = Self::read(source, table_header, offset, entries_len, &previous_key)?;
+            match entry.key.as_slice().cmp(key) {
+                std::cmp::Ordering::Less => {
+                    offset = entry.end;
+                    previous_key = entry.key;
+                }
+                std::cmp::Ordering::Equal => return Ok(Some(entry)),
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        Ok(None)
+    }
+
+    fn value_len(&self) -> usize {
+        (self.header.value_size & !VALUE_FLAGS) as usize
+    }
+
+    /// The entry as an index entry, with its key offset relative to the data
+    /// section the block at `block_offset` lies in
+    fn index_entry(&self, block_offset: u64) -> IndexEntry {
+        IndexEntry {
+            key: self.key.clone(),
+            offset: block_offset + self.key_offset as u64,
+            key_size: self.header.key_size,
+            value_size: self.header.value_size,
+        }
+    }
+}
+
+/// Length of the prefix the key at `position` shares with the previous key,
+/// which v4 tables don't store
+///
+/// The index holds whole keys, so readers work this out from it rather than
+/// from the data section.
+fn shared_prefix_len(version: u32, position: usize, previous_key: &[u8], key: &[u8]) -> usize {
+    if version < 4 || position.is_multiple_of(KEY_RESTART_INTERVAL) {
+        return 0;
+    }
+    previous_key
+        .iter()
+        .zip(key)
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// [`shared_prefix_len`] of the index entry at `position`
+fn index_shared_prefix_len(version: u32, entries: &[IndexEntry], position: usize) -> usize {
+    let previous_key = &entries[position.saturating_sub(1)].key;
+    shared_prefix_len(version, position, previous_key, &entries[position].key)
+}
+
+/// Offset just past the value of the entry at `position`, relative to the data section
+fn entry_end(version: u32, entries: &[IndexEntry], position: usize) -> u64 {
+    let entry = &entries[position];
+    let shared_prefix = index_shared_prefix_len(version, entries, position);
+    let key_len = (entry.key_size as usize).saturating_sub(shared_prefix);
+    entry
+        .offset
+        .saturating_add(key_len as u64)
+        .saturating_add(entry.value_len() as u64)
+}
+
+/// Length of the header of the entry at `position` in a table of `version`
+///
+/// Headers from v3 on vary in size and the index only records where keys
+/// start, so a header fills the gap between the previous entry's end and its key.
+fn entry_header_len(version: u32, entries: &[IndexEntry], position: usize) -> SSTableResult<usize> {
+    if version < 3 {
+        return Ok(ENTRY_HEADER_SIZE);
+    }
+    let entry = &entries[position];
+    let previous_end = position
+        .checked_sub(1)
+        .map_or(0, |p| entry_end(version, entries, p));
+    let len = entry.offset.saturating_sub(previous_end);
+    // One byte per varint at least
+    let min_len = if version < 4 { 4 } else { 5 };
+    if !(min_len..=MAX_ENTRY_HEADER_SIZE as u64).contains(&len) {
+        return Err(SSTableError::InvalidIndex(format!(
+            "Entry for key {:?} has a {}-byte header",
+            String::from_utf8_lossy(&entry.key),
+            len
+        )));
+    }
+    Ok(len as usize)
+}
+
+/// Where the stored bytes of an entry lie in the file
+#[derive(Debug, Clone, Copy)]
+struct EntrySpan {
+    /// File offset of the entry header
+    start: u64,
+    header_len: usize,
+    /// Leading key bytes shared with the previous key and not stored
+    shared_prefix: usize,
+    /// Stored key bytes, after the shared prefix
+    key_len: usize,
+    value_len: usize,
+}
+
+impl EntrySpan {
+    /// Total stored length of the entry
+    fn len(&self) -> usize {
+        self.header_len + self.key_len + self.value_len
+    }
+}
+
+/// Header fields stored in front of each entry's key in the data section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EntryHeader {
+    /// Leading key bytes shared with the previous key (v4)
+    shared_prefix: u32,
+    /// Length of the whole key, shared prefix included
+    key_size: u32,
+    /// Value length with the flag bits, as in [`IndexEntry::value_size`]
+    value_size: u32,
+    timestamp: u64,
+    sequence_number: u64,
+}
+
+impl EntryHeader {
+    /// Append the header in the encoding of `header`'s format version
+    fn encode(&self, header: &SSTableHeader, buf: &mut Vec<u8>) {
+        if header.version < 3 {
+            buf.extend_from_slice(&self.key_size.to_le_bytes());
+            buf.extend_from_slice(&self.value_size.to_le_bytes());
+            buf.extend_from_slice(&self.timestamp.to_le_bytes());
+            buf.extend_from_slice(&self.sequence_number.to_le_bytes());
+            return;
+        }
+        // Flags go in the low bits so small values still take one byte
+        let value_len = (self.value_size & !VALUE_FLAGS) as u64;
+        let flags = (self.value_size >> VALUE_FLAGS_SHIFT) as u64;
+        if header.version >= 4 {
+            put_varint(buf, self.shared_prefix as u64);
+            put_varint(buf, (self.key_size - self.shared_prefix) as u64);
+        } else {
+            put_varint(buf, self.key_size as u64);
+        }
+        put_varint(buf, (value_len << 3) | flags);
+        put_varint(buf, self.timestamp - header.base_timestamp);
+        put_varint(buf, self.sequence_number - header.base_sequence);
+    }
+
+    /// Decode a header that spans all of `bytes`
+    fn decode(header: &SSTableHeader, bytes: &[u8]) -> SSTableResult<Self> {
+        match Self::decode_prefix(header, bytes)? {
+            (entry_header, len) if len == bytes.len() => Ok(entry_header),
+            _ => Err(SSTableError::InvalidFormat(
+                "Invalid entry header".to_string(),
+            )),
+        }
+    }
+
+    /// Decode the header at the front of `bytes`, returning it and its length
+    fn decode_prefix(header: &SSTableHeader, bytes: &[u8]) -> SSTableResult<(Self, usize)> {
+        let invalid = || SSTableError::InvalidFormat("Invalid entry header".to_string());
+        if header.version < 3 {
+            if bytes.len() < ENTRY_HEADER_SIZE {
+                return Err(invalid());
+            }
+            let entry_header = Self {
+                shared_prefix: 0,
+                key_size: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+                value_size: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+                timestamp: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+                sequence_number: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            };
+            return Ok((entry_header, ENTRY_HEADER_SIZE));
+        }
+
+        let mut rest = bytes;
+        let mut next = || {
+            let (value, len) = get_varint(rest).ok_or_else(invalid)?;
+            rest = &rest[len..];
+            Ok::<_, SSTableError>(value)
+        };
+        let shared_prefix = if header.version >= 4 { next()? } else { 0 };
+        let key_size = shared_prefix
+            .checked_add(next()?)
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(invalid)?;
+        let shared_prefix = shared_prefix as u32;
+        let value = next()?;
+        let value_len = u32::try_from(value >> 3)
+            .ok()
+            .filter(|len| len & VALUE_FLAGS == 0)
+            .ok_or_else(invalid)?;
+        let value_size = value_len | (((value & 7) as u32) << VALUE_FLAGS_SHIFT);
+        let timestamp = header.base_timestamp.checked_add(next()?);
+        let sequence_number = header.base_sequence.checked_add(next()?);
+        let len = bytes.len() - rest.len();
+        match (timestamp, sequence_number) {
+            (Some(timestamp), Some(sequence_number)) => Ok((
+                Self {
+                    shared_prefix,
+                    key_size,
+                    value_size,
+                    timestamp,
+                    sequence_number,
+                },
+                len,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Append `value` as a LEB128 varint
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Decode a LEB128 varint from the front of `bytes`, returning it and its length
+fn get_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(10) {
+        let bits = (byte & 0x7f) as u64;
+        if i == 9 && bits > 1 {
+            return None;
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
 /// SSTable index for binary search
+///
+/// Tables before v6 list every entry; block-based tables list their blocks
+/// and the last key instead, so the index grows with the number of blocks.
 #[derive(Debug, Clone)]
 pub struct SSTableIndex {
     pub entries: Vec<IndexEntry>,
+    pub blocks: Vec<BlockHandle>,
+    /// Largest key of a block-based table
+    pub last_key: Vec<u8>,
     pub bloom_filter_bits: Vec<u8>,
     pub compression_metadata: CompressionMetadata,
 }
@@ -78,11 +790,20 @@ impl SSTableIndex {
     pub fn new() -> Self {
         Self {
             entries: Vec::new(),
+            blocks: Vec::new(),
+            last_key: Vec::new(),
             bloom_filter_bits: Vec::new(),
             compression_metadata: CompressionMetadata::default(),
         }
     }
 
+    /// Position of the block that would hold `key`
+    pub fn find_block(&self, key: &[u8]) -> Option<usize> {
+        self.blocks
+            .partition_point(|block| block.first_key.as_slice() <= key)
+            .checked_sub(1)
+    }
+
     /// Add an index entry
     pub fn add_entry(&mut self, key: Vec<u8>, offset: u64, key_size: u32, value_size: u32) {
         self.entries.push(IndexEntry {
@@ -95,10 +816,15 @@ impl SSTableIndex {
 
     /// Find a key using binary search
     pub fn find_key(&self, target_key: &[u8]) -> Option<&IndexEntry> {
+        self.find_position(target_key)
+            .map(|position| &self.entries[position])
+    }
+
+    /// Position of a key in the index, found by binary search
+    pub fn find_position(&self, target_key: &[u8]) -> Option<usize> {
         self.entries
             .binary_search_by(|entry| entry.key.as_slice().cmp(target_key))
             .ok()
-            .map(|index| &self.entries[index])
     }
 
     /// Get the number of index entries
@@ -119,6 +845,10 @@ impl Default for SSTableIndex {
 }
 
 /// SSTable file header (64 bytes)
+///
+/// On disk the compression type comes right after the data offset, followed
+/// by the v3 delta bases, which v1 and v2 tables leave zero, and the size of
+/// the compressed values before compression.
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct SSTableHeader {
@@ -128,8 +858,11 @@ pub struct SSTableHeader {
     pub index_offset: u64,        // Offset to index section
     pub bloom_filter_offset: u64, // Offset to bloom filter
     pub data_offset: u64,         // Offset to data section
+    pub base_timestamp: u64,      // Smallest entry timestamp (v3)
+    pub base_sequence: u64,       // Smallest entry sequence number (v3)
+    pub uncompressed_bytes: u64,  // Compressed values' size before compression
     pub compression_type: u8,     // Compression algorithm
-    pub reserved: [u8; 31],       // Reserved for future use
+    pub reserved: [u8; 7],        // Reserved for future use
 }
 
 impl SSTableHeader {
@@ -142,16 +875,43 @@ impl SSTableHeader {
     ) -> Self {
         Self {
             magic: *b"RUSTEDGE",
-            version: 1,
+            version: SSTABLE_FORMAT_VERSION,
             entry_count,
             index_offset,
             bloom_filter_offset,
             data_offset,
+            base_timestamp: 0,
+            base_sequence: 0,
+            uncompressed_bytes: 0,
             compression_type: CompressionType::None as u8,
-            reserved: [0; 31],
+            reserved: [0; 7],
+        }
+    }
+
+    /// Extractor whose prefixes are in the bloom filter, if any
+    ///
+    /// Kept in the reserved bytes as a kind byte followed by the fixed length
+    /// or delimiter as a u32; tables without prefixes leave them zero.
+    pub fn prefix_extractor(&self) -> Option<PrefixExtractor> {
+        let param = u32::from_le_bytes(self.reserved[1..5].try_into().unwrap());
+        match self.reserved[0] {
+            PREFIX_FIXED_LENGTH => Some(PrefixExtractor::FixedLength(param)),
+            PREFIX_DELIMITER => u8::try_from(param).ok().map(PrefixExtractor::Delimiter),
+            _ => None,
         }
     }
 
+    /// Record the extractor whose prefixes are in the bloom filter
+    pub fn set_prefix_extractor(&mut self, extractor: Option<PrefixExtractor>) {
+        let (kind, param) = match extractor {
+            None => (0, 0),
+            Some(PrefixExtractor::FixedLength(len)) => (PREFIX_FIXED_LENGTH, len),
+            Some(PrefixExtractor::Delimiter(delimiter)) => (PREFIX_DELIMITER, delimiter as u32),
+        };
+        self.reserved[0] = kind;
+        self.reserved[1..5].copy_from_slice(&param.to_le_bytes());
+    }
+
     /// Write header to writer
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_all(&self.magic)?;
@@ -161,19 +921,29 @@ impl SSTableHeader {
         writer.write_all(&self.bloom_filter_offset.to_le_bytes())?;
         writer.write_all(&self.data_offset.to_le_bytes())?;
         writer.write_all(&[self.compression_type])?;
+        writer.write_all(&self.base_timestamp.to_le_bytes())?;
+        writer.write_all(&self.base_sequence.to_le_bytes())?;
+        writer.write_all(&self.uncompressed_bytes.to_le_bytes())?;
         writer.write_all(&self.reserved)?;
         Ok(())
     }
 
-    /// Read header from reader
-    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+    /// Decode a header from the start of `bytes`
+    pub fn decode(bytes: &[u8]) -> SSTableResult<Self> {
+        Self::read(&mut &bytes[..])
+    }
+
+    /// Read header from reader, dispatching on the format version
+    ///
+    /// Versions newer than [`SSTABLE_FORMAT_VERSION`] fail with
+    /// [`SSTableError::UnsupportedVersion`] rather than being misread.
+    pub fn read<R: Read>(reader: &mut R) -> SSTableResult<Self> {
         let mut magic = [0u8; 8];
         reader.read_exact(&mut magic)?;
 
         if magic != *b"RUSTEDGE" {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid SSTable magic number",
+            return Err(SSTableError::InvalidFormat(
+                "Invalid SSTable magic number".to_string(),
             ));
         }
 
@@ -181,6 +951,18 @@ impl SSTableHeader {
         reader.read_exact(&mut version_bytes)?;
         let version = u32::from_le_bytes(version_bytes);
 
+        match version {
+            // Later versions only changed how entries are encoded and filled
+            // in reserved bytes, so all share the header layout
+            MIN_SSTABLE_FORMAT_VERSION..=SSTABLE_FORMAT_VERSION => {
+                Ok(Self::read_fields(reader, magic, version)?)
+            }
+            other => Err(SSTableError::UnsupportedVersion(other)),
+        }
+    }
+
+    /// Decode the header fields that follow the magic and version
+    fn read_fields<R: Read>(reader: &mut R, magic: [u8; 8], version: u32) -> io::Result<Self> {
         let mut entry_count_bytes = [0u8; 4];
         reader.read_exact(&mut entry_count_bytes)?;
         let entry_count = u32::from_le_bytes(entry_count_bytes);
@@ -201,7 +983,16 @@ impl SSTableHeader {
         reader.read_exact(&mut compression_type_bytes)?;
         let compression_type = compression_type_bytes[0];
 
-        let mut reserved = [0u8; 31];
+        let mut base_bytes = [0u8; 8];
+        reader.read_exact(&mut base_bytes)?;
+        let base_timestamp = u64::from_le_bytes(base_bytes);
+        reader.read_exact(&mut base_bytes)?;
+        let base_sequence = u64::from_le_bytes(base_bytes);
+        let mut uncompressed_bytes = [0u8; 8];
+        reader.read_exact(&mut uncompressed_bytes)?;
+        let uncompressed_bytes = u64::from_le_bytes(uncompressed_bytes);
+
+        let mut reserved = [0u8; 7];
         reader.read_exact(&mut reserved)?;
 
         Ok(Self {
@@ -211,38 +1002,73 @@ impl SSTableHeader {
             index_offset,
             bloom_filter_offset,
             data_offset,
+            base_timestamp,
+            base_sequence,
+            uncompressed_bytes,
             compression_type,
             reserved,
         })
     }
 }
 
+/// Header kinds of [`PrefixExtractor`]
+const PREFIX_FIXED_LENGTH: u8 = 1;
+const PREFIX_DELIMITER: u8 = 2;
+
+/// Number of distinct prefixes of sorted `keys`; keys sharing a prefix are adjacent
+fn distinct_prefixes<'a>(
+    keys: impl Iterator<Item = &'a [u8]>,
+    extractor: &PrefixExtractor,
+) -> usize {
+    let mut count = 0;
+    let mut last = None;
+    for prefix in keys.filter_map(|key| extractor.extract(key)) {
+        if last != Some(prefix) {
+            count += 1;
+            last = Some(prefix);
+        }
+    }
+    count
+}
+
+/// Footer flag set when `checksum` holds the CRC32 of the data and index
+/// sections; tables written before it have a zero checksum and no flags
+pub const FOOTER_CHECKSUM_FLAG: u8 = 0x01;
+
 /// SSTable footer (32 bytes)
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct SSTableFooter {
-    pub checksum: u32,      // CRC32 checksum of data
+    pub checksum: u32,      // CRC32 of the data and index sections
     pub data_size: u64,     // Size of data section
     pub index_size: u64,    // Size of index section
-    pub reserved: [u8; 12], // Reserved for future use
+    pub flags: u8,          // FOOTER_CHECKSUM_FLAG
+    pub reserved: [u8; 11], // Reserved for future use
 }
 
 impl SSTableFooter {
-    /// Create a new footer
+    /// Create a footer recording the checksum of the data and index sections
     pub fn new(checksum: u32, data_size: u64, index_size: u64) -> Self {
         Self {
             checksum,
             data_size,
             index_size,
-            reserved: [0; 12],
+            flags: FOOTER_CHECKSUM_FLAG,
+            reserved: [0; 11],
         }
     }
 
+    /// Whether the footer records a checksum to verify
+    pub fn has_checksum(&self) -> bool {
+        self.flags & FOOTER_CHECKSUM_FLAG != 0
+    }
+
     /// Write footer to writer
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_all(&self.checksum.to_le_bytes())?;
         writer.write_all(&self.data_size.to_le_bytes())?;
         writer.write_all(&self.index_size.to_le_bytes())?;
+        writer.write_all(&[self.flags])?;
         writer.write_all(&self.reserved)?;
         Ok(())
     }
@@ -261,18 +1087,218 @@ impl SSTableFooter {
         reader.read_exact(&mut index_size_bytes)?;
         let index_size = u64::from_le_bytes(index_size_bytes);
 
-        let mut reserved = [0u8; 12];
+        let mut flags = [0u8; 1];
+        reader.read_exact(&mut flags)?;
+
+        let mut reserved = [0u8; 11];
         reader.read_exact(&mut reserved)?;
 
         Ok(Self {
             checksum,
             data_size,
             index_size,
+            flags: flags[0],
             reserved,
         })
     }
 }
 
+fn check_format_version(options: &SSTableWriteOptions) -> SSTableResult<()> {
+    if !(2..=SSTABLE_FORMAT_VERSION).contains(&options.format_version) {
+        return Err(SSTableError::InvalidFormat(format!(
+            "Cannot write format version {}",
+            options.format_version
+        )));
+    }
+    Ok(())
+}
+
+/// Encodes entries, added in key order, into a data section and builds its index
+///
+/// Only the open block is held in memory; blocks, or entries before v6, are
+/// written out as they fill.
+struct DataSectionWriter {
+    /// Header of the table being written, whose bases entries are encoded against
+    header: SSTableHeader,
+    index: SSTableIndex,
+    block: BlockBuilder,
+    block_size: usize,
+    compression_options: CompressionOptions,
+    entry_header: Vec<u8>,
+    previous_key: Vec<u8>,
+    entry_count: usize,
+}
+
+impl DataSectionWriter {
+    fn new(header: &SSTableHeader, options: &SSTableWriteOptions) -> Self {
+        let mut index = SSTableIndex::new();
+        index.compression_metadata.compression_type = options.compression;
+        Self {
+            header: header.clone(),
+            index,
+            block: BlockBuilder::default(),
+            block_size: options.block_size,
+            compression_options: options.compression_options,
+            entry_header: Vec::with_capacity(MAX_ENTRY_HEADER_SIZE),
+            previous_key: Vec::new(),
+            entry_count: 0,
+        }
+    }
+
+    fn is_block_based(&self) -> bool {
+        self.header.version >= BLOCK_FORMAT_VERSION
+    }
+
+    /// Append `entry` to the data section starting at `data_offset` in `writer`
+    fn add<W: Write + Seek>(
+        &mut self,
+        writer: &mut W,
+        data_offset: u64,
+        entry: &Entry,
+    ) -> SSTableResult<()> {
+        let compression = self.index.compression_metadata.compression_type;
+
+        // Compress values big enough to benefit, keeping the raw bytes if it doesn't help
+        let mut value = entry.value.clone();
+        let mut compressed = false;
+        if let Some(raw) = &entry.value
+            && compression != CompressionType::None
+            && !entry.indirect
+            && raw.len() >= self.compression_options.min_value_size
+        {
+            let packed = compression.compress(raw)?;
+            if packed.len() < raw.len() {
+                self.index.compression_metadata.original_size += raw.len();
+                self.index.compression_metadata.compressed_size += packed.len();
+                value = Some(Bytes::from(packed));
+                compressed = true;
+            }
+        }
+
+        // Write entry header: key_len, value_len with flags, timestamp and seq
+        let key_len = entry.key.len() as u32;
+        let mut value_len = value.as_ref().map_or(0, |v| v.len()) as u32;
+        if entry.indirect {
+            value_len |= INDIRECT_VALUE_FLAG;
+        }
+        if compressed {
+            value_len |= COMPRESSED_VALUE_FLAG;
+        }
+        if entry.merge {
+            value_len |= MERGE_OPERAND_FLAG;
+        }
+
+        // Blocks restart their shared prefixes, so the first entry of each is whole
+        let slot = if self.is_block_based() {
+            self.block.entry_count as usize
+        } else {
+            self.entry_count
+        };
+        let shared_prefix =
+            shared_prefix_len(self.header.version, slot, &self.previous_key, &entry.key);
+        self.entry_header.clear();
+        EntryHeader {
+            shared_prefix: shared_prefix as u32,
+            key_size: key_len,
+            value_size: value_len,
+            timestamp: entry.timestamp,
+            sequence_number: entry.sequence_number,
+        }
+        .encode(&self.header, &mut self.entry_header);
+        self.previous_key.clear();
+        self.previous_key.extend_from_slice(&entry.key);
+        self.entry_count += 1;
+
+        if self.is_block_based() {
+            let value = value.as_deref().unwrap_or_default();
+            self.block
+                .add(&entry.key, &self.entry_header, shared_prefix, value);
+            if self.block.buf.len() >= self.block_size {
+                self.finish_block(writer, data_offset)?;
+            }
+            return Ok(());
+        }
+        writer.write_all(&self.entry_header)?;
+
+        // Calculate key data offset (after the header) - this should be relative to data_offset
+        let key_data_offset = writer.stream_position()? - data_offset;
+
+        // Write key and value data
+        writer.write_all(&entry.key[shared_prefix..])?;
+        if let Some(value) = &value {
+            writer.write_all(value)?;
+        }
+
+        // Add to index with key_data_offset (points to where key data starts, relative to data section)
+        self.index
+            .add_entry(entry.key.clone(), key_data_offset, key_len, value_len);
+        Ok(())
+    }
+
+    fn finish_block<W: Write + Seek>(
+        &mut self,
+        writer: &mut W,
+        data_offset: u64,
+    ) -> io::Result<()> {
+        let offset = writer.stream_position()? - data_offset;
+        self.index.blocks.push(self.block.finish(writer, offset)?);
+        Ok(())
+    }
+
+    /// Write the open block, if any, and return the index
+    fn finish<W: Write + Seek>(
+        mut self,
+        writer: &mut W,
+        data_offset: u64,
+    ) -> SSTableResult<SSTableIndex> {
+        if self.is_block_based() {
+            if self.block.entry_count > 0 {
+                self.finish_block(writer, data_offset)?;
+            }
+            self.index.last_key = self.previous_key;
+        }
+        Ok(self.index)
+    }
+}
+
+/// Writer that keeps a CRC32 of every byte written through it
+struct ChecksumWriter<'a, W> {
+    inner: &'a mut W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<'a, W> ChecksumWriter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    fn checksum(self) -> u32 {
+        self.hasher.finalize()
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for ChecksumWriter<'_, W> {
+    /// Only for querying the position; bytes skipped over aren't hashed
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
 /// Simple bloom filter implementation
 #[derive(Debug, Clone)]
 pub struct BloomFilter {
@@ -284,6 +1310,7 @@ pub struct BloomFilter {
 impl BloomFilter {
     /// Create a new bloom filter
     pub fn new(size: usize, hash_count: usize) -> Self {
+        let size = size.max(1); // Bit indexes are taken modulo the size
         let byte_size = size.div_ceil(8); // Round up to nearest byte
         Self {
             bits: vec![0; byte_size],
@@ -294,8 +1321,13 @@ impl BloomFilter {
 
     /// Add a key to the bloom filter
     pub fn add(&mut self, key: &[u8]) {
+        self.add_hash(Self::key_hash(key));
+    }
+
+    /// Add a key by its [`key_hash`](Self::key_hash)
+    pub fn add_hash(&mut self, key_hash: usize) {
         for i in 0..self.hash_count {
-            let hash = self.hash(key, i);
+            let hash = key_hash.wrapping_add(i);
             let bit_index = hash % self.size;
             let byte_index = bit_index / 8;
             let bit_offset = bit_index % 8;
@@ -305,8 +1337,9 @@ impl BloomFilter {
 
     /// Check if a key might be in the bloom filter
     pub fn might_contain(&self, key: &[u8]) -> bool {
+        let key_hash = Self::key_hash(key);
         for i in 0..self.hash_count {
-            let hash = self.hash(key, i);
+            let hash = key_hash.wrapping_add(i);
             let bit_index = hash % self.size;
             let byte_index = bit_index / 8;
             let bit_offset = bit_index % 8;
@@ -317,14 +1350,15 @@ impl BloomFilter {
         true
     }
 
-    /// Simple hash function (Fowler-Noll-Vo hash)
-    fn hash(&self, key: &[u8], seed: usize) -> usize {
+    /// Simple hash function (Fowler-Noll-Vo hash); the filter's hash
+    /// functions add their index to it
+    pub fn key_hash(key: &[u8]) -> usize {
         let mut hash: usize = 0x811c9dc5;
         for &byte in key {
             hash ^= byte as usize;
             hash = hash.wrapping_mul(0x01000193);
         }
-        hash.wrapping_add(seed)
+        hash
     }
 
     /// Get the bloom filter bits
@@ -333,29 +1367,147 @@ impl BloomFilter {
     }
 
     /// Set the bloom filter bits
-    pub fn set_bits(&mut self, bits: Vec<u8>) {
+    ///
+    /// Extra trailing bytes are ignored; missing ones are treated as unset.
+    pub fn set_bits(&mut self, mut bits: Vec<u8>) {
+        bits.resize(bits.len().max(self.size.div_ceil(8)), 0);
         self.bits = bits;
     }
-}
 
-/// SSTable implementation for immutable file storage
-#[derive(Debug)]
-pub struct SSTable {
-    file: File,
-    path: std::path::PathBuf,
-    header: SSTableHeader,
+    /// Number of bits keys are hashed into
+    pub fn bit_count(&self) -> usize {
+        self.size
+    }
+
+    /// Number of bits set per key
+    pub fn hash_count(&self) -> usize {
+        self.hash_count
+    }
+
+    /// Size of the filter as written by [`BloomFilter::encode`]
+    pub fn encoded_len(&self) -> usize {
+        BLOOM_PREFIX_SIZE + self.bits.len()
+    }
+
+    /// Encode the filter as a v5 bloom filter section: byte length (u32), bit
+    /// count (u64) and hash count (u32), then the bits
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        buf.extend_from_slice(&(self.bits.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(self.size as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.hash_count as u32).to_le_bytes());
+        buf.extend_from_slice(&self.bits);
+        buf
+    }
+
+    /// Decode a filter written by [`BloomFilter::encode`], which must fill `bytes`
+    pub fn decode(bytes: &[u8]) -> SSTableResult<Self> {
+        let invalid =
+            |reason: String| SSTableError::InvalidFormat(format!("Bloom filter {}", reason));
+        if bytes.len() < BLOOM_PREFIX_SIZE {
+            return Err(invalid(format!("section of {} bytes", bytes.len())));
+        }
+        let byte_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let bit_count = u64::from_le_bytes(bytes[4..12].try_into().unwrap());
+        let hash_count = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let bits = &bytes[BLOOM_PREFIX_SIZE..];
+        if byte_len != bits.len() || bit_count == 0 || bit_count.div_ceil(8) != byte_len as u64 {
+            return Err(invalid(format!(
+                "of {} bits does not fit {} bytes",
+                bit_count,
+                bits.len()
+            )));
+        }
+        if !(1..=MAX_BLOOM_HASH_COUNT).contains(&hash_count) {
+            return Err(invalid(format!("hash count {}", hash_count)));
+        }
+        Ok(Self {
+            bits: bits.to_vec(),
+            size: bit_count as usize,
+            hash_count,
+        })
+    }
+}
+
+/// SSTable implementation for immutable file storage
+#[derive(Debug)]
+pub struct SSTable {
+    file: File,
+    path: std::path::PathBuf,
+    header: SSTableHeader,
+    /// Smallest and largest keys, known without loading the index
+    key_range: Option<(Vec<u8>, Vec<u8>)>,
+    /// Loaded on open, or on first use for tables opened with [`SSTable::open_lazy`]
+    sections: OnceLock<TableSections>,
+    /// Smallest and largest sequence numbers, known when the table was written
+    /// here or recorded in the manifest
+    sequence_range: OnceLock<(u64, u64)>,
+    /// Point reads served by this table, for the engine's access profile
+    reads: AtomicU64,
+    /// Set once the table is no longer part of the database; the file is
+    /// deleted when the last reference is dropped
+    obsolete_path: OnceLock<PathBuf>,
+    /// Overwrite the file before deleting it, see [`SSTable::shred_when_deleted`]
+    shred: AtomicBool,
+    /// Cache shared with the engine's other tables and this table's id in it
+    block_cache: OnceLock<(Arc<BlockCache>, u64)>,
+}
+
+/// Index and bloom filter of an SSTable
+#[derive(Debug)]
+struct TableSections {
     index: SSTableIndex,
-    bloom_filter: BloomFilter,
+    /// None for legacy tables written without a full-size filter
+    bloom_filter: Option<BloomFilter>,
+}
+
+impl TableSections {
+    /// Check the bloom filter, if the table has one
+    fn might_contain(&self, key: &[u8]) -> bool {
+        self.bloom_filter
+            .as_ref()
+            .is_none_or(|bloom_filter| bloom_filter.might_contain(key))
+    }
 }
 
+/// Size of the fixed fields at the end of each index entry: offset (8) + key_size (4) + value_size (4)
+const INDEX_ENTRY_TAIL_SIZE: u64 = 16;
+
+/// Size of the footer written after the index
+const FOOTER_SIZE: u64 = 32;
+
+/// Size of the byte length, bit count and hash count before v5 bloom filter bits
+pub const BLOOM_PREFIX_SIZE: usize = 16;
+
+/// Most hash functions a stored bloom filter may use
+const MAX_BLOOM_HASH_COUNT: usize = 32;
+
+/// Bytes of the data section read at a time while checking the checksum
+const CHECKSUM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Extension given to SSTables that are waiting for their last reader to finish
+pub const OBSOLETE_SSTABLE_EXTENSION: &str = "sst.obsolete";
+
 impl SSTable {
     /// Create a new SSTable by flushing a MemTable
     pub fn from_memtable<P: AsRef<Path>>(
         path: P,
         memtable: &MemTable,
-        _compression: CompressionType,
+        compression: CompressionType,
+    ) -> SSTableResult<Self> {
+        let options = SSTableWriteOptions {
+            compression,
+            ..Default::default()
+        };
+        Self::from_memtable_with_options(path, memtable, &options)
+    }
+
+    /// Create a new SSTable by flushing a MemTable with explicit write options
+    pub fn from_memtable_with_options<P: AsRef<Path>>(
+        path: P,
+        memtable: &MemTable,
+        options: &SSTableWriteOptions,
     ) -> SSTableResult<Self> {
-        let path = path.as_ref().to_path_buf();
         let entries = memtable.entries();
 
         if entries.is_empty() {
@@ -364,213 +1516,1265 @@ impl SSTable {
             ));
         }
 
-        info!(
-            "Creating SSTable from MemTable with {} entries",
-            entries.len()
-        );
+        Self::from_entries_with_options(path, &entries, options)
+    }
+
+    /// Create a new SSTable from entries already sorted by key
+    pub fn from_entries_with_options<P: AsRef<Path>>(
+        path: P,
+        entries: &[Entry],
+        options: &SSTableWriteOptions,
+    ) -> SSTableResult<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if entries.is_empty() {
+            return Err(SSTableError::InvalidFormat(
+                "Cannot create SSTable without entries".to_string(),
+            ));
+        }
+
+        info!("Creating SSTable with {} entries", entries.len());
+
+        let (header, index, bloom_filter) = if options.use_direct_io {
+            // O_DIRECT needs aligned, sequential writes, so build the table in memory first
+            let mut buffer = Cursor::new(Vec::new());
+            let parts = Self::write_table(&mut buffer, entries, options)?;
+            io_backend::write_file_direct(&path, buffer.get_ref())?;
+            parts
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .open(&path)
+                .map_err(SSTableError::Io)?;
+
+            let mut writer = BufWriter::new(file);
+            let parts = Self::write_table(&mut writer, entries, options)?;
+            writer.flush()?;
+            parts
+        };
 
-        // Create the file
+        // Reopen file for reading
         let file = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
             .read(true)
             .open(&path)
             .map_err(SSTableError::Io)?;
 
-        let mut writer = BufWriter::new(file);
-        let mut index = SSTableIndex::new();
-        let mut bloom_filter = BloomFilter::new(entries.len() * 10, 3); // 10x size, 3 hash functions
+        info!("SSTable created successfully at {:?}", path);
 
+        let key_range = Some((
+            entries[0].key.clone(),
+            entries[entries.len() - 1].key.clone(),
+        ));
+        let sequence_range = OnceLock::new();
+        let sequences = entries.iter().map(|entry| entry.sequence_number);
+        if let (Some(min), Some(max)) = (sequences.clone().min(), sequences.max()) {
+            let _ = sequence_range.set((min, max));
+        }
+        Ok(Self {
+            file,
+            path,
+            header,
+            key_range,
+            sections: OnceLock::from(TableSections {
+                index,
+                bloom_filter: Some(bloom_filter),
+            }),
+            sequence_range,
+            reads: AtomicU64::new(0),
+            obsolete_path: OnceLock::new(),
+            shred: AtomicBool::new(false),
+            block_cache: OnceLock::new(),
+        })
+    }
+
+    /// Write header, bloom filter, data, index, and footer sections for sorted entries
+    pub(crate) fn write_table<W: Write + Seek>(
+        writer: &mut W,
+        entries: &[Entry],
+        options: &SSTableWriteOptions,
+    ) -> SSTableResult<(SSTableHeader, SSTableIndex, BloomFilter)> {
+        check_format_version(options)?;
+        // Prefixes need a self-describing filter, sized for them too
+        let prefix_extractor = options
+            .prefix_extractor
+            .filter(|_| options.format_version >= 5);
+        let prefix_count = prefix_extractor.map_or(0, |extractor| {
+            distinct_prefixes(entries.iter().map(|e| e.key.as_slice()), &extractor)
+        });
+        let mut bloom_filter = BloomFilter::new((entries.len() + prefix_count) * 10, 3); // 10x size, 3 hash functions
+        for entry in entries {
+            bloom_filter.add(&entry.key);
+            if let Some(prefix) = prefix_extractor.and_then(|e| e.extract(&entry.key)) {
+                bloom_filter.add(prefix);
+            }
+        }
+
+        // Entry timestamps and sequence numbers are stored relative to the smallest ones
+        let mut header = SSTableHeader::new(entries.len() as u32, 0, 0, 0);
+        header.version = options.format_version;
+        header.compression_type = options.compression as u8;
+        header.set_prefix_extractor(prefix_extractor);
+        if header.version >= 3 {
+            header.base_timestamp = entries.iter().map(|e| e.timestamp).min().unwrap_or(0);
+            header.base_sequence = entries.iter().map(|e| e.sequence_number).min().unwrap_or(0);
+        }
+
+        let mut data = DataSectionWriter::new(&header, options);
+        let (header, index) =
+            Self::write_sections(writer, header, &bloom_filter, |hashed, data_offset| {
+                for entry in entries {
+                    data.add(hashed, data_offset, entry)?;
+                }
+                data.finish(hashed, data_offset)
+            })?;
+        Ok((header, index, bloom_filter))
+    }
+
+    /// Write a table around a data section produced by `write_data`
+    ///
+    /// `write_data` gets the writer, positioned at the data section, and the
+    /// section's offset; it writes the data and returns its index. Everything
+    /// it writes is hashed with the index for the footer checksum.
+    fn write_sections<W: Write + Seek>(
+        writer: &mut W,
+        mut header: SSTableHeader,
+        bloom_filter: &BloomFilter,
+        write_data: impl FnOnce(&mut ChecksumWriter<'_, W>, u64) -> SSTableResult<SSTableIndex>,
+    ) -> SSTableResult<(SSTableHeader, SSTableIndex)> {
         // Write header placeholder (we'll update it later)
         let header_size = std::mem::size_of::<SSTableHeader>();
         let header_placeholder = vec![0u8; header_size];
         writer.write_all(&header_placeholder)?;
 
-        // Write bloom filter placeholder - use actual bloom filter size
+        // Bloom filter, prefixed with its parameters from v5 on
         let bloom_filter_offset = writer.stream_position()?;
-        let bloom_filter_size = bloom_filter.bits().len();
-        let bloom_filter_placeholder = vec![0u8; bloom_filter_size];
-        writer.write_all(&bloom_filter_placeholder)?;
+        if header.version >= 5 {
+            writer.write_all(&bloom_filter.encode())?;
+        } else {
+            writer.write_all(bloom_filter.bits())?;
+        }
 
-        // Write data section
+        // Write data section, hashing it and the index for the footer checksum
         let data_offset = writer.stream_position()?;
-
-        for entry in &entries {
-            // Add to bloom filter
-            bloom_filter.add(&entry.key);
-
-            // Calculate entry start position (after header)
-            let _entry_start = writer.stream_position()?;
-
-            // Write entry header: key_len (4) + value_len (4) + timestamp (8) + seq (8)
-            let key_len = entry.key.len() as u32;
-            let value_len = entry.value.as_ref().map_or(0, |v| v.len()) as u32;
-
-            writer.write_all(&key_len.to_le_bytes())?;
-            writer.write_all(&value_len.to_le_bytes())?;
-            writer.write_all(&entry.timestamp.to_le_bytes())?;
-            writer.write_all(&entry.sequence_number.to_le_bytes())?;
-
-            // Calculate key data offset (after the header) - this should be relative to data_offset
-            let key_data_offset = writer.stream_position()? - data_offset;
-
-            // Write key and value data
-            writer.write_all(&entry.key)?;
-            if let Some(value) = &entry.value {
-                writer.write_all(value)?;
-            }
-
-            // Add to index with key_data_offset (points to where key data starts, relative to data section)
-            index.add_entry(entry.key.clone(), key_data_offset, key_len, value_len);
-        }
+        let mut hashed = ChecksumWriter::new(writer);
+        let index = write_data(&mut hashed, data_offset)?;
 
         // Calculate total data size
-        let data_size = writer.stream_position()? - data_offset;
+        let data_size = hashed.stream_position()? - data_offset;
 
         // Write index section
-        let index_offset = writer.stream_position()?;
-        let index_size = Self::write_index(&mut writer, &index)?;
+        let index_offset = hashed.stream_position()?;
+        let index_size = Self::write_index(&mut hashed, &index, header.version)?;
 
         // Write footer
-        let footer = SSTableFooter::new(0, data_size, index_size as u64); // TODO: Add checksum
-        footer.write(&mut writer)?;
-
-        // Update bloom filter
-        let _bloom_filter_size = bloom_filter.bits().len();
-        writer.seek(SeekFrom::Start(bloom_filter_offset))?;
-        writer.write_all(bloom_filter.bits())?;
+        let footer = SSTableFooter::new(hashed.checksum(), data_size, index_size as u64);
+        footer.write(writer)?;
 
         // Write header with final offsets
-        let header = SSTableHeader::new(
-            entries.len() as u32,
-            index_offset,
-            bloom_filter_offset,
-            data_offset,
-        );
+        header.uncompressed_bytes = index.compression_metadata.original_size as u64;
+        header.index_offset = index_offset;
+        header.bloom_filter_offset = bloom_filter_offset;
+        header.data_offset = data_offset;
         writer.seek(SeekFrom::Start(0))?;
-        header.write(&mut writer)?;
+        header.write(writer)?;
 
-        // Flush and close writer
-        writer.flush()?;
-        drop(writer);
+        Ok((header, index))
+    }
 
-        // Reopen file for reading
-        let file = OpenOptions::new()
+    /// Open an existing SSTable for reading, loading its index and bloom filter
+    pub fn open<P: AsRef<Path>>(path: P) -> SSTableResult<Self> {
+        let sstable = Self::open_lazy(path)?;
+        sstable.sections()?;
+        info!("SSTable opened successfully from {:?}", sstable.path);
+        Ok(sstable)
+    }
+
+    /// Open an existing SSTable reading only its header and key range
+    ///
+    /// The index and bloom filter are loaded, and checked, the first time a
+    /// read needs them, so opening many tables is nearly instant.
+    pub fn open_lazy<P: AsRef<Path>>(path: P) -> SSTableResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
             .read(true)
             .open(&path)
             .map_err(SSTableError::Io)?;
 
-        info!("SSTable created successfully at {:?}", path);
+        // Read header
+        let header = SSTableHeader::read(&mut file).map_err(|e| match e {
+            SSTableError::UnsupportedVersion(_) => e,
+            e => SSTableError::InvalidFormat(format!("Failed to read header: {}", e)),
+        })?;
+
+        // Check the section offsets against the file before sizing any buffer from them
+        let file_len = file.metadata()?.len();
+        let header_len = std::mem::size_of::<SSTableHeader>() as u64;
+        if header.bloom_filter_offset < header_len
+            || header.bloom_filter_offset > header.data_offset
+            || header.data_offset > header.index_offset
+            || header.index_offset > file_len
+        {
+            return Err(SSTableError::InvalidFormat(format!(
+                "Section offsets out of bounds: bloom={}, data={}, index={}, file_len={}",
+                header.bloom_filter_offset, header.data_offset, header.index_offset, file_len
+            )));
+        }
+
+        let key_range = if header.entry_count == 0 {
+            None
+        } else {
+            Some(Self::read_key_range(&file, &header, file_len)?)
+        };
 
         Ok(Self {
             file,
             path,
             header,
-            index,
-            bloom_filter,
+            key_range,
+            sections: OnceLock::new(),
+            sequence_range: OnceLock::new(),
+            reads: AtomicU64::new(0),
+            obsolete_path: OnceLock::new(),
+            shred: AtomicBool::new(false),
+            block_cache: OnceLock::new(),
         })
     }
 
-    /// Open an existing SSTable for reading
-    pub fn open<P: AsRef<Path>>(path: P) -> SSTableResult<Self> {
-        let path = path.as_ref().to_path_buf();
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(&path)
-            .map_err(SSTableError::Io)?;
+    /// Read the first key from the start of the data section and the last key
+    /// from the end of the index, without reading anything in between
+    ///
+    /// The first entry of the data section is always stored whole; the index
+    /// ends with the last key in v6 and with the last key's entry before it.
+    fn read_key_range(
+        file: &File,
+        header: &SSTableHeader,
+        file_len: u64,
+    ) -> SSTableResult<(Vec<u8>, Vec<u8>)> {
+        let io = IoBackend::current();
+        let invalid = |what: &str| SSTableError::InvalidFormat(format!("Invalid {}", what));
+
+        // v3 headers vary in length, so read as much as the largest could take
+        let data_size = header.index_offset - header.data_offset;
+        let header_bytes = if header.version < 3 {
+            ENTRY_HEADER_SIZE
+        } else {
+            MAX_ENTRY_HEADER_SIZE.min(data_size as usize)
+        };
+        if header_bytes as u64 > data_size {
+            return Err(invalid("data section"));
+        }
+        let mut entry_header = vec![0u8; header_bytes];
+        io.read_exact_at(file, &mut entry_header, header.data_offset)?;
+        let (entry_header, header_len) = EntryHeader::decode_prefix(header, &entry_header)
+            .map_err(|_| invalid("first entry header"))?;
+        // The first entry is a restart point, so its key is stored whole
+        if entry_header.shared_prefix != 0 {
+            return Err(invalid("first entry header"));
+        }
+        let key_len = entry_header.key_size as u64;
+        let key_start = header.data_offset + header_len as u64;
+        if key_start + key_len > header.index_offset {
+            return Err(invalid("first key"));
+        }
+        let mut first = vec![0u8; key_len as usize];
+        io.read_exact_at(file, &mut first, key_start)?;
 
-        // Read header
-        let header = SSTableHeader::read(&mut file)
-            .map_err(|e| SSTableError::InvalidFormat(format!("Failed to read header: {}", e)))?;
+        // The footer records where the index ends; its last entry ends with fixed fields
+        if file_len < header.index_offset + FOOTER_SIZE {
+            return Err(invalid("footer"));
+        }
+        let mut footer = [0u8; FOOTER_SIZE as usize];
+        io.read_exact_at(file, &mut footer, file_len - FOOTER_SIZE)?;
+        let footer = SSTableFooter::read(&mut footer.as_slice())?;
+        let index_end = header.index_offset.checked_add(footer.index_size);
+        let min_index_size = if header.version >= BLOCK_FORMAT_VERSION {
+            4 + BLOCK_HANDLE_FIXED_SIZE + BLOCK_INDEX_TRAILER_SIZE
+        } else {
+            4 + 4 + INDEX_ENTRY_TAIL_SIZE
+        };
+        if index_end != Some(file_len - FOOTER_SIZE) || footer.index_size < min_index_size {
+            return Err(invalid("footer"));
+        }
+        let index_end = file_len - FOOTER_SIZE;
+
+        if header.version >= BLOCK_FORMAT_VERSION {
+            let mut key_size = [0u8; 4];
+            io.read_exact_at(file, &mut key_size, index_end - 4)?;
+            let key_size = u32::from_le_bytes(key_size) as u64;
+            if key_size + min_index_size > footer.index_size {
+                return Err(invalid("last key"));
+            }
+            let mut last = vec![0u8; key_size as usize];
+            io.read_exact_at(file, &mut last, index_end - 4 - key_size)?;
+            return Ok((first, last));
+        }
 
-        // Read bloom filter
-        file.seek(SeekFrom::Start(header.bloom_filter_offset))?;
-        let bloom_filter_size = header
-            .index_offset
-            .saturating_sub(header.bloom_filter_offset);
+        let mut tail = [0u8; INDEX_ENTRY_TAIL_SIZE as usize];
+        io.read_exact_at(file, &mut tail, index_end - INDEX_ENTRY_TAIL_SIZE)?;
+        let key_size = u32::from_le_bytes(tail[8..12].try_into().unwrap()) as u64;
+        if key_size + 4 + INDEX_ENTRY_TAIL_SIZE > footer.index_size - 4 {
+            return Err(invalid("last key"));
+        }
+        let key_start = index_end - INDEX_ENTRY_TAIL_SIZE - key_size;
+        let mut last = vec![0u8; 4 + key_size as usize];
+        io.read_exact_at(file, &mut last, key_start - 4)?;
+        if u32::from_le_bytes(last[..4].try_into().unwrap()) as u64 != key_size {
+            return Err(invalid("last key"));
+        }
+        last.drain(..4);
+
+        Ok((first, last))
+    }
 
-        let mut bloom_filter_bits = vec![0u8; bloom_filter_size as usize];
-        if bloom_filter_size > 0 {
-            file.read_exact(&mut bloom_filter_bits)?;
+    /// Index and bloom filter, loading them on first use
+    fn sections(&self) -> SSTableResult<&TableSections> {
+        if let Some(sections) = self.sections.get() {
+            return Ok(sections);
         }
+        let sections = self.load_sections()?;
+        Ok(self.sections.get_or_init(|| sections))
+    }
 
-        let mut bloom_filter = BloomFilter::new(header.entry_count as usize * 10, 3);
-        bloom_filter.set_bits(bloom_filter_bits);
+    /// Read and validate the bloom filter and index
+    fn load_sections(&self) -> SSTableResult<TableSections> {
+        let header = &self.header;
+        let io = IoBackend::current();
+        let file_len = self.file.metadata()?.len();
+
+        // Read bloom filter; v5 tables record its parameters, older ones are
+        // assumed to use the writer's defaults. Legacy tables may have none, or
+        // one too small to trust, and every lookup then goes to the index
+        let bloom_filter_size = header.data_offset - header.bloom_filter_offset;
+        let bloom_bits_needed = (header.entry_count as u64 * 10).div_ceil(8);
+        let bloom_filter = if header.version >= 5 && bloom_filter_size > 0 {
+            let mut section = vec![0u8; bloom_filter_size as usize];
+            io.read_exact_at(&self.file, &mut section, header.bloom_filter_offset)?;
+            Some(BloomFilter::decode(&section)?)
+        } else if header.version >= 5 || bloom_filter_size < bloom_bits_needed {
+            debug!(
+                "SSTable {:?} has a {}-byte bloom filter for {} entries; ignoring it",
+                self.path, bloom_filter_size, header.entry_count
+            );
+            None
+        } else {
+            let mut bloom_filter_bits = vec![0u8; bloom_filter_size as usize];
+            if bloom_filter_size > 0 {
+                io.read_exact_at(
+                    &self.file,
+                    &mut bloom_filter_bits,
+                    header.bloom_filter_offset,
+                )?;
+            }
+            let mut bloom_filter = BloomFilter::new(header.entry_count as usize * 10, 3);
+            bloom_filter.set_bits(bloom_filter_bits);
+            Some(bloom_filter)
+        };
 
         // Read index
-        file.seek(SeekFrom::Start(header.index_offset))?;
-        let index = Self::read_index(&mut file, header.entry_count as usize)?;
+        let mut index_bytes = vec![0u8; (file_len - header.index_offset) as usize];
+        io.read_exact_at(&self.file, &mut index_bytes, header.index_offset)?;
+        self.verify_checksum(&index_bytes)?;
+        let index_len = index_bytes.len() - FOOTER_SIZE as usize;
+        let mut index = Self::read_index(
+            &mut &index_bytes[..index_len],
+            header.entry_count as usize,
+            header.version,
+        )?;
+        if header.version >= BLOCK_FORMAT_VERSION {
+            self.check_block_index(&mut index)?;
+            return Ok(TableSections {
+                index,
+                bloom_filter,
+            });
+        }
+        index.compression_metadata = CompressionMetadata {
+            compression_type: CompressionType::from_u8(header.compression_type)?,
+            original_size: header.uncompressed_bytes as usize,
+            compressed_size: index
+                .entries
+                .iter()
+                .filter(|entry| entry.is_compressed())
+                .map(IndexEntry::value_len)
+                .sum(),
+        };
+
+        // Every entry must lie inside the data section
+        for (position, entry) in index.entries.iter().enumerate() {
+            let header_len = entry_header_len(header.version, &index.entries, position)?;
+            let end =
+                header
+                    .data_offset
+                    .checked_add(entry_end(header.version, &index.entries, position));
+            if entry.offset < header_len as u64
+                || entry.key_size as usize != entry.key.len()
+                || end.is_none_or(|end| end > header.index_offset)
+            {
+                return Err(SSTableError::InvalidIndex(format!(
+                    "Entry for key {:?} lies outside the data section",
+                    String::from_utf8_lossy(&entry.key)
+                )));
+            }
+        }
 
-        info!("SSTable opened successfully from {:?}", path);
+        // The key range read on open must agree with the index
+        let index_range = index.entries.first().zip(index.entries.last());
+        let agrees = match (&self.key_range, index_range) {
+            (Some((first, last)), Some((a, b))) => *first == a.key && *last == b.key,
+            (None, None) => true,
+            _ => false,
+        };
+        if !agrees {
+            return Err(SSTableError::InvalidIndex(
+                "Key range does not match the index".to_string(),
+            ));
+        }
 
-        Ok(Self {
-            file,
-            path,
-            header,
+        Ok(TableSections {
             index,
             bloom_filter,
         })
     }
 
+    /// Check that the blocks of a v6 index tile the data section in key order
+    /// and agree with the header and the key range read on open
+    fn check_block_index(&self, index: &mut SSTableIndex) -> SSTableResult<()> {
+        let invalid = |reason: String| SSTableError::InvalidIndex(reason);
+        let mut expected_offset = 0u64;
+        let mut entry_count = 0u64;
+        let mut previous: Option<&[u8]> = None;
+        for block in &index.blocks {
+            if block.offset != expected_offset
+                || block.entry_count == 0
+                || block.tombstone_count > block.entry_count
+                || previous.is_some_and(|previous| previous >= block.first_key.as_slice())
+            {
+                return Err(invalid(format!(
+                    "Block at {} is out of place",
+                    block.offset
+                )));
+            }
+            expected_offset += block.size as u64;
+            entry_count += block.entry_count as u64;
+            previous = Some(&block.first_key);
+        }
+        if expected_offset != self.data_size() || entry_count != self.header.entry_count as u64 {
+            return Err(invalid(format!(
+                "Blocks cover {} bytes and {} entries of {} and {}",
+                expected_offset,
+                entry_count,
+                self.data_size(),
+                self.header.entry_count
+            )));
+        }
+
+        let index_range = index
+            .blocks
+            .first()
+            .map(|block| (block.first_key.clone(), index.last_key.clone()));
+        if self.key_range != index_range {
+            return Err(invalid("Key range does not match the index".to_string()));
+        }
+        index.compression_metadata.compression_type =
+            CompressionType::from_u8(self.header.compression_type)?;
+        index.compression_metadata.original_size = self.header.uncompressed_bytes as usize;
+        Ok(())
+    }
+
+    /// Check the footer checksum against the data section and `index_bytes`,
+    /// the rest of the file after it
+    ///
+    /// Tables written before checksums were recorded pass unchecked.
+    fn verify_checksum(&self, index_bytes: &[u8]) -> SSTableResult<()> {
+        let Some(footer_bytes) = index_bytes.len().checked_sub(FOOTER_SIZE as usize) else {
+            return Err(SSTableError::InvalidFormat("Missing footer".to_string()));
+        };
+        let (index_bytes, footer) = index_bytes.split_at(footer_bytes);
+        let footer = SSTableFooter::read(&mut &footer[..])?;
+        if !footer.has_checksum() {
+            return Ok(());
+        }
+
+        let io = IoBackend::current();
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0u8; CHECKSUM_CHUNK_SIZE.min(self.data_size() as usize)];
+        let mut offset = self.header.data_offset;
+        while offset < self.header.index_offset {
+            let len = buf.len().min((self.header.index_offset - offset) as usize);
+            io.read_exact_at(&self.file, &mut buf[..len], offset)?;
+            hasher.update(&buf[..len]);
+            offset += len as u64;
+        }
+        hasher.update(index_bytes);
+
+        let computed = hasher.finalize();
+        if computed != footer.checksum {
+            return Err(SSTableError::CorruptedFile(format!(
+                "Checksum mismatch in {:?}: footer has {:#010x}, contents hash to {:#010x}",
+                self.path, footer.checksum, computed
+            )));
+        }
+        Ok(())
+    }
+
+    /// Load the index and bloom filter now rather than on first read
+    pub fn load(&self) -> SSTableResult<()> {
+        self.sections().map(|_| ())
+    }
+
+    /// Re-read the whole table from disk and check that it is intact
+    ///
+    /// The index and bloom filter are read again rather than taken from
+    /// memory, keys must be strictly ascending and in the bloom filter, and
+    /// every entry is decoded against its index entry, which also decompresses
+    /// compressed values. Returns the number of bytes checked.
+    pub fn verify(&self) -> SSTableResult<u64> {
+        self.verify_with(|_| Ok(()))
+    }
+
+    /// [`SSTable::verify`], also passing every decoded entry, in key order,
+    /// to `check`, which can end the pass with an error of its own
+    pub fn verify_with<E: From<SSTableError>>(
+        &self,
+        mut check: impl FnMut(&Entry) -> Result<(), E>,
+    ) -> Result<u64, E> {
+        let sections = self.load_sections()?;
+        if let Some(loaded) = self.sections.get()
+            && (loaded.index.entries != sections.index.entries
+                || loaded.index.blocks != sections.index.blocks)
+        {
+            return Err(SSTableError::InvalidIndex(
+                "Index on disk differs from the one loaded".to_string(),
+            )
+            .into());
+        }
+        if self.is_block_based() {
+            return self.verify_blocks(&sections, check);
+        }
+
+        let mut previous: Option<&[u8]> = None;
+        for (position, index_entry) in sections.index.entries.iter().enumerate() {
+            let key = index_entry.key.as_slice();
+            if previous.is_some_and(|previous| previous >= key) {
+                return Err(SSTableError::InvalidIndex(format!(
+                    "Key {:?} is out of order",
+                    String::from_utf8_lossy(key)
+                ))
+                .into());
+            }
+            if !sections.might_contain(key) {
+                return Err(SSTableError::InvalidFormat(format!(
+                    "Bloom filter is missing key {:?}",
+                    String::from_utf8_lossy(key)
+                ))
+                .into());
+            }
+            previous = Some(key);
+
+            let span = self.entry_span(&sections.index.entries, position)?;
+            let mut buf = vec![0u8; span.len()];
+            IoBackend::current()
+                .read_exact_at(&self.file, &mut buf, span.start)
+                .map_err(SSTableError::Io)?;
+            let entry_header = EntryHeader::decode(&self.header, &buf[..span.header_len])?;
+            if entry_header.key_size != index_entry.key_size
+                || entry_header.value_size != index_entry.value_size
+                || entry_header.shared_prefix as usize != span.shared_prefix
+            {
+                return Err(SSTableError::InvalidIndex(format!(
+                    "Entry header for key {:?} disagrees with the index",
+                    String::from_utf8_lossy(key)
+                ))
+                .into());
+            }
+            check(&self.decode_entry(Bytes::from(buf), &span, index_entry)?)?;
+        }
+        Ok(self.file.metadata().map_err(SSTableError::Io)?.len())
+    }
+
+    /// [`SSTable::verify_with`] for a block-based table, decoding every block
+    fn verify_blocks<E: From<SSTableError>>(
+        &self,
+        sections: &TableSections,
+        mut check: impl FnMut(&Entry) -> Result<(), E>,
+    ) -> Result<u64, E> {
+        let mut previous: Option<Vec<u8>> = None;
+        for handle in &sections.index.blocks {
+            let bytes = self.read_block(handle)?;
+            let source = BlockSource::Buffer(bytes.clone());
+            for found in BlockEntry::read_all(&source, &self.header, handle)? {
+                let key = found.key.as_slice();
+                if previous.as_deref().is_some_and(|previous| previous >= key) {
+                    return Err(SSTableError::InvalidIndex(format!(
+                        "Key {:?} is out of order",
+                        String::from_utf8_lossy(key)
+                    ))
+                    .into());
+                }
+                if !sections.might_contain(key) {
+                    return Err(SSTableError::InvalidFormat(format!(
+                        "Bloom filter is missing key {:?}",
+                        String::from_utf8_lossy(key)
+                    ))
+                    .into());
+                }
+                let value = bytes.slice(found.value_offset..found.end);
+                let entry = self.build_entry(found.key, &found.header, value)?;
+                check(&entry)?;
+                previous = Some(entry.key);
+            }
+        }
+        if !sections.index.blocks.is_empty() && previous.as_ref() != Some(&sections.index.last_key)
+        {
+            return Err(
+                SSTableError::InvalidIndex("Last key differs from the index".to_string()).into(),
+            );
+        }
+        Ok(self.file.metadata().map_err(SSTableError::Io)?.len())
+    }
+
+    /// Whether the table predates the current format or has no usable bloom filter
+    ///
+    /// Such tables are still readable, but [`SSTable::rebuild_metadata`] lets
+    /// them use the current read path without a full recompaction. A filter
+    /// that is present but lacks keys is corruption, caught by [`SSTable::verify`].
+    pub fn needs_metadata_rebuild(&self) -> SSTableResult<bool> {
+        Ok(self.header.version < SSTABLE_FORMAT_VERSION || self.sections()?.bloom_filter.is_none())
+    }
+
+    /// Write a copy of this table at `path` with a fresh header, bloom filter
+    /// and index in the current format
+    ///
+    /// The data section is copied byte for byte; index offsets are relative to
+    /// it, so entries are neither decoded nor rewritten. Tables in an older
+    /// format encode entries differently and are re-encoded instead.
+    pub fn rebuild_metadata<P: AsRef<Path>>(&self, path: P) -> SSTableResult<SSTable> {
+        let path = path.as_ref().to_path_buf();
+        if self.header.version < SSTABLE_FORMAT_VERSION {
+            return self.reencode(path);
+        }
+        let sections = self.sections()?;
+        let entries = self.stored_entries()?;
+        let prefix_extractor = self.header.prefix_extractor();
+        let prefix_count = prefix_extractor.map_or(0, |extractor| {
+            distinct_prefixes(entries.iter().map(|(e, _)| e.key.as_slice()), &extractor)
+        });
+        let mut bloom_filter = BloomFilter::new((entries.len() + prefix_count) * 10, 3);
+        for (entry, _) in &entries {
+            bloom_filter.add(&entry.key);
+            if let Some(prefix) = prefix_extractor.and_then(|e| e.extract(&entry.key)) {
+                bloom_filter.add(prefix);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)?;
+        let mut writer = BufWriter::new(file);
+        let header_size = std::mem::size_of::<SSTableHeader>() as u64;
+        writer.write_all(&vec![0u8; header_size as usize])?;
+        writer.write_all(&bloom_filter.encode())?;
+
+        let data_offset = writer.stream_position()?;
+        let mut hashed = ChecksumWriter::new(&mut writer);
+        let mut source = &self.file;
+        source.seek(SeekFrom::Start(self.header.data_offset))?;
+        let copied = io::copy(&mut source.take(self.data_size()), &mut hashed)?;
+        if copied != self.data_size() {
+            return Err(SSTableError::InvalidFormat(format!(
+                "Data section ended after {} of {} bytes",
+                copied,
+                self.data_size()
+            )));
+        }
+
+        let index_offset = hashed.stream_position()?;
+        let index_size = Self::write_index(&mut hashed, &sections.index, self.header.version)?;
+        let checksum = hashed.checksum();
+        SSTableFooter::new(checksum, copied, index_size as u64).write(&mut writer)?;
+
+        let mut header =
+            SSTableHeader::new(entries.len() as u32, index_offset, header_size, data_offset);
+        header.compression_type = self.header.compression_type;
+        header.base_timestamp = self.header.base_timestamp;
+        header.base_sequence = self.header.base_sequence;
+        header.uncompressed_bytes = self.header.uncompressed_bytes;
+        header.set_prefix_extractor(prefix_extractor);
+        writer.seek(SeekFrom::Start(0))?;
+        header.write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        let rebuilt = SSTable::open(&path)?;
+        if let Some(range) = self.sequence_range() {
+            rebuilt.set_sequence_range(range);
+        }
+        info!(
+            "Rebuilt metadata of SSTable {:?} into {:?}",
+            self.path, path
+        );
+        Ok(rebuilt)
+    }
+
+    /// Write every entry of this table at `path` in the current format
+    fn reencode(&self, path: PathBuf) -> SSTableResult<SSTable> {
+        let entries = self.iter().collect::<SSTableResult<Vec<_>>>()?;
+        let options = SSTableWriteOptions {
+            compression: CompressionType::from_u8(self.header.compression_type)?,
+            prefix_extractor: self.header.prefix_extractor(),
+            ..Default::default()
+        };
+        let rebuilt = Self::from_entries_with_options(&path, &entries, &options)?;
+        rebuilt.file.sync_all()?;
+        info!("Re-encoded SSTable {:?} into {:?}", self.path, path);
+        Ok(rebuilt)
+    }
+
+    /// Whether the index and bloom filter are in memory
+    pub fn is_loaded(&self) -> bool {
+        self.sections.get().is_some()
+    }
+
     /// Get a value by key using binary search
-    pub fn get(&mut self, key: &[u8]) -> SSTableResult<Option<Vec<u8>>> {
+    ///
+    /// The value is a slice of the buffer filled by the read, so it is not copied again.
+    /// Indirect entries return their encoded blob pointer; use [`SSTable::get_entry`]
+    /// to tell them apart.
+    pub fn get(&self, key: &[u8]) -> SSTableResult<Option<Bytes>> {
+        Ok(self.get_entry(key)?.and_then(|entry| entry.value))
+    }
+
+    /// Whether the table may hold keys whose prefix, taken by `extractor`, is `prefix`
+    ///
+    /// Only tables whose bloom filter holds that extractor's prefixes can rule
+    /// a prefix out; any other table may hold it.
+    pub fn might_contain_prefix(
+        &self,
+        extractor: &PrefixExtractor,
+        prefix: &[u8],
+    ) -> SSTableResult<bool> {
+        if self.header.prefix_extractor() != Some(*extractor) {
+            return Ok(true);
+        }
+        Ok(self.sections()?.might_contain(prefix))
+    }
+
+    /// Get the full entry for a key, including tombstones and indirect values
+    pub fn get_entry(&self, key: &[u8]) -> SSTableResult<Option<Entry>> {
+        let entry = self.peek_entry(key)?;
+        if entry.is_some() {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(entry)
+    }
+
+    /// Like [`SSTable::get_entry`], without counting a read for the access profile
+    pub fn peek_entry(&self, key: &[u8]) -> SSTableResult<Option<Entry>> {
+        let sections = self.sections()?;
+
         // Check bloom filter first
-        if !self.bloom_filter.might_contain(key) {
-            println!("DEBUG: Bloom filter rejected key {:?}", String::from_utf8_lossy(key));
+        if !sections.might_contain(key) {
+            trace!(
+                "Bloom filter rejected key {:?}",
+                String::from_utf8_lossy(key)
+            );
             return Ok(None);
         }
-        println!("DEBUG: Bloom filter passed for key {:?}", String::from_utf8_lossy(key));
+
+        if self.is_block_based() {
+            // Read the one block that could hold the key and search it in memory
+            let Some(block) = sections.index.find_block(key) else {
+                return Ok(None);
+            };
+            let handle = &sections.index.blocks[block];
+            let bytes = self.read_cached_block(handle)?;
+            let source = BlockSource::Buffer(bytes.clone());
+            let Some(found) = BlockEntry::find(&source, &self.header, handle, key)? else {
+                return Ok(None);
+            };
+            let value = bytes.slice(found.value_offset..found.end);
+            return self.build_entry(found.key, &found.header, value).map(Some);
+        }
 
         // Find key in index
-        let index_entry = self.index.find_key(key);
-        if index_entry.is_none() {
-            println!("DEBUG: Key {:?} not found in index", String::from_utf8_lossy(key));
+        let Some(position) = sections.index.find_position(key) else {
+            return Ok(None);
+        };
+        let index_entry = &sections.index.entries[position];
+
+        // Read the entry with a single positioned read
+        let span = self.entry_span(&sections.index.entries, position)?;
+        let buf = self.read_cached(span.start, span.len())?;
+
+        let entry = self.decode_entry(buf, &span, index_entry)?;
+        Ok(Some(entry))
+    }
+
+    /// Get the entry for a key, reading at most `len` bytes of its value from `offset`
+    ///
+    /// Only the requested range of a plain value is read from the data section,
+    /// clamped to the value's length. Compressed values are read whole and then
+    /// sliced; tombstones, indirect values and merge operands come back whole,
+    /// since their stored bytes aren't the value itself.
+    pub fn get_entry_range(
+        &self,
+        key: &[u8],
+        offset: usize,
+        len: usize,
+    ) -> SSTableResult<Option<Entry>> {
+        let sections = self.sections()?;
+        if !sections.might_contain(key) {
             return Ok(None);
         }
-        let index_entry = index_entry.unwrap();
-        println!("DEBUG: Key {:?} found in index at offset {}", String::from_utf8_lossy(key), index_entry.offset);
-
-        // Use the key_size and value_size from the index entry directly
-        let key_size = index_entry.key_size as usize;
-        let value_size = index_entry.value_size as usize;
-        
-        println!("DEBUG: Using key_size: {}, value_size: {} from index", key_size, value_size);
-
-        // Read key (verify it matches)
-        let mut stored_key = vec![0u8; key_size];
-        // The offset in the index is relative to the data section start
-        let absolute_offset = self.header.data_offset + index_entry.offset;
-        self.file.seek(SeekFrom::Start(absolute_offset))?;
-        self.file.read_exact(&mut stored_key)?;
-        println!("DEBUG: Read stored_key: {:?}", String::from_utf8_lossy(&stored_key));
-
-        if stored_key != key {
-            println!("DEBUG: Key mismatch! Expected {:?}, got {:?}", 
-                    String::from_utf8_lossy(key), String::from_utf8_lossy(&stored_key));
-            return Err(SSTableError::InvalidIndex(format!(
-                "Key mismatch: expected {:?}, got {:?}",
-                String::from_utf8_lossy(key),
-                String::from_utf8_lossy(&stored_key)
-            )));
+        if self.is_block_based() {
+            return self.get_block_entry_range(sections, key, offset, len);
+        }
+        let Some(position) = sections.index.find_position(key) else {
+            return Ok(None);
+        };
+        let index_entry = &sections.index.entries[position];
+
+        let value_len = index_entry.value_len();
+        if value_len == 0 || index_entry.is_indirect() || index_entry.is_merge() {
+            return self.get_entry(key);
+        }
+        if index_entry.is_compressed() {
+            return Ok(self.get_entry(key)?.map(|mut entry| {
+                entry.value = entry.value.map(|value| {
+                    let offset = offset.min(value.len());
+                    value.slice(offset..offset + len.min(value.len() - offset))
+                });
+                entry
+            }));
         }
-        println!("DEBUG: Key verification passed");
 
-        // Read value
-        if value_size > 0 {
-            let mut value = vec![0u8; value_size];
-            self.file.read_exact(&mut value)?;
-            println!("DEBUG: Read value: {:?}", String::from_utf8_lossy(&value));
-            Ok(Some(value))
+        // Read the header and key, then just the requested part of the value
+        let span = self.entry_span(&sections.index.entries, position)?;
+        let start = span.start;
+        let head_len = span.header_len + span.key_len;
+        let offset = offset.min(value_len);
+        let len = len.min(value_len - offset);
+        let mut buf = vec![0u8; head_len + len];
+        IoBackend::current().read_exact_at(&self.file, &mut buf[..head_len], start)?;
+        IoBackend::current().read_exact_at(
+            &self.file,
+            &mut buf[head_len..],
+            start + (head_len + offset) as u64,
+        )?;
+
+        let entry = self.decode_entry(Bytes::from(buf), &span, index_entry)?;
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(entry))
+    }
+
+    /// [`SSTable::get_entry_range`] for a block-based table
+    ///
+    /// Unless the block is cached, it is searched with small positioned reads
+    /// of its restart offsets and of the headers and keys on the way to `key`,
+    /// so a slice of a large value is read without the rest of it.
+    fn get_block_entry_range(
+        &self,
+        sections: &TableSections,
+        key: &[u8],
+        offset: usize,
+        len: usize,
+    ) -> SSTableResult<Option<Entry>> {
+        let Some(block) = sections.index.find_block(key) else {
+            return Ok(None);
+        };
+        let handle = &sections.index.blocks[block];
+        let start = self.header.data_offset + handle.offset;
+        let cached = self
+            .block_cache
+            .get()
+            .and_then(|(cache, id)| cache.get(*id, start));
+        let source = match cached {
+            Some(bytes) => BlockSource::Buffer(bytes),
+            None => BlockSource::File {
+                sstable: self,
+                start,
+                size: handle.size as usize,
+            },
+        };
+        let Some(found) = BlockEntry::find(&source, &self.header, handle, key)? else {
+            return Ok(None);
+        };
+
+        // Only plain values can be read in part
+        let value_len = found.value_len();
+        let whole = found.header.value_size & VALUE_FLAGS != 0;
+        let (read_from, read_len) = if whole {
+            (0, value_len)
         } else {
-            println!("DEBUG: Value size is 0 (tombstone)");
-            Ok(None) // Tombstone
+            let offset = offset.min(value_len);
+            (offset, len.min(value_len - offset))
+        };
+        let value = source.read(found.value_offset + read_from, read_len)?;
+        let mut entry = self.build_entry(found.key, &found.header, value)?;
+        if found.header.value_size & COMPRESSED_VALUE_FLAG != 0 {
+            entry.value = entry.value.map(|value| {
+                let offset = offset.min(value.len());
+                value.slice(offset..offset + len.min(value.len() - offset))
+            });
         }
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(entry))
     }
 
-    /// Get the file path
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Whether the data section is split into blocks (v6 on)
+    fn is_block_based(&self) -> bool {
+        self.header.version >= BLOCK_FORMAT_VERSION
+    }
+
+    /// Read a whole data block through the block cache, if the table has one
+    fn read_cached_block(&self, handle: &BlockHandle) -> SSTableResult<Bytes> {
+        self.read_cached(
+            self.header.data_offset + handle.offset,
+            handle.size as usize,
+        )
+    }
+
+    /// Read `len` bytes at `offset` through the block cache, if the table has one
+    ///
+    /// Legacy tables cache each entry they read this way in place of a block.
+    fn read_cached(&self, offset: u64, len: usize) -> SSTableResult<Bytes> {
+        let read = || {
+            let mut buf = vec![0u8; len];
+            IoBackend::current().read_exact_at(&self.file, &mut buf, offset)?;
+            Ok(Bytes::from(buf))
+        };
+        match self.block_cache.get() {
+            Some((cache, id)) => cache.get_or_read(*id, offset, read),
+            None => read(),
+        }
+    }
+
+    /// Read a whole data block
+    fn read_block(&self, handle: &BlockHandle) -> SSTableResult<Bytes> {
+        let mut buf = vec![0u8; handle.size as usize];
+        IoBackend::current().read_exact_at(
+            &self.file,
+            &mut buf,
+            self.header.data_offset + handle.offset,
+        )?;
+        Ok(Bytes::from(buf))
+    }
+
+    /// Where the entry at `position` is stored; index offsets point at the
+    /// stored key, just past the header
+    fn entry_span(&self, entries: &[IndexEntry], position: usize) -> SSTableResult<EntrySpan> {
+        let version = self.header.version;
+        let index_entry = &entries[position];
+        let header_len = entry_header_len(version, entries, position)?;
+        let start = (self.header.data_offset + index_entry.offset)
+            .checked_sub(header_len as u64)
+            .ok_or_else(|| {
+                SSTableError::InvalidIndex(format!(
+                    "Entry offset {} precedes its header",
+                    index_entry.offset
+                ))
+            })?;
+        let shared_prefix = index_shared_prefix_len(version, entries, position);
+        Ok(EntrySpan {
+            start,
+            header_len,
+            shared_prefix,
+            key_len: index_entry.key.len() - shared_prefix,
+            value_len: index_entry.value_len(),
+        })
+    }
+
+    /// Index entry of every entry in key order, with the bytes it takes in
+    /// the data section
+    ///
+    /// Tables before v6 answer from the index alone; block-based tables read
+    /// each block and decode its entry headers, but no values.
+    pub fn stored_entries(&self) -> SSTableResult<Vec<(IndexEntry, usize)>> {
+        let sections = self.sections()?;
+        if !self.is_block_based() {
+            let entries = &sections.index.entries;
+            return (0..entries.len())
+                .map(|position| {
+                    let span = self.entry_span(entries, position)?;
+                    Ok((entries[position].clone(), span.len()))
+                })
+                .collect();
+        }
+
+        let mut stored = Vec::with_capacity(self.entry_count() as usize);
+        for handle in &sections.index.blocks {
+            let source = BlockSource::Buffer(self.read_block(handle)?);
+            for found in BlockEntry::read_all(&source, &self.header, handle)? {
+                stored.push((found.index_entry(handle.offset), found.end - found.start));
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Decode an entry (header, key, and value) read for an index entry
+    ///
+    /// Uncompressed values are slices of `bytes`, so they are not copied.
+    fn decode_entry(
+        &self,
+        bytes: Bytes,
+        span: &EntrySpan,
+        index_entry: &IndexEntry,
+    ) -> SSTableResult<Entry> {
+        let header_len = span.header_len;
+        let key = &bytes[header_len..header_len + span.key_len];
+
+        // Verify the stored key matches
+        if key != &index_entry.key[span.shared_prefix..] {
+            return Err(SSTableError::InvalidIndex(format!(
+                "Key mismatch: expected {:?}, got {:?}",
+                String::from_utf8_lossy(&index_entry.key),
+                String::from_utf8_lossy(key)
+            )));
+        }
+
+        let mut entry_header = EntryHeader::decode(&self.header, &bytes[..header_len])?;
+        entry_header.value_size = index_entry.value_size;
+        let value = bytes.slice(header_len + span.key_len..);
+        self.build_entry(index_entry.key.clone(), &entry_header, value)
+    }
+
+    /// Make an entry from its decoded header and stored value, decompressing it if needed
+    fn build_entry(
+        &self,
+        key: Vec<u8>,
+        entry_header: &EntryHeader,
+        mut value: Bytes,
+    ) -> SSTableResult<Entry> {
+        let EntryHeader {
+            value_size,
+            timestamp,
+            sequence_number,
+            ..
+        } = *entry_header;
+
+        if value_size & !VALUE_FLAGS == 0 {
+            // Tombstone
+            return Ok(Entry::new(key, None, timestamp, sequence_number));
+        }
+
+        if value_size & COMPRESSED_VALUE_FLAG != 0 {
+            let compression = CompressionType::from_u8(self.header.compression_type)?;
+            value = Bytes::from(compression.decompress(&value)?);
+        }
+        if value_size & INDIRECT_VALUE_FLAG != 0 {
+            Ok(Entry::indirect(key, value, timestamp, sequence_number))
+        } else if value_size & MERGE_OPERAND_FLAG != 0 {
+            Ok(Entry::merge_operand(key, value, timestamp, sequence_number))
+        } else {
+            Ok(Entry::new(key, Some(value), timestamp, sequence_number))
+        }
+    }
+
+    /// Iterate over all entries (including tombstones) in key order
+    ///
+    /// Uses [`DEFAULT_READAHEAD_BYTES`] of read-ahead once sequential access is detected.
+    pub fn iter(&self) -> SSTableIterator<'_> {
+        SSTableIter::new(self)
+    }
+
+    /// Iterate over a shared table, keeping it alive for as long as the iterator
+    pub fn iter_shared(self: &Arc<Self>) -> SSTableIter<Arc<SSTable>> {
+        SSTableIter::new(self.clone())
+    }
+
+    /// Get the file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Durably move a freshly written table to its final path
+    ///
+    /// Tables are written under a temporary name and renamed into place, so a
+    /// crash never leaves a partial file under a name that gets loaded.
+    pub fn persist_to<P: AsRef<Path>>(&mut self, path: P) -> SSTableResult<()> {
+        let path = path.as_ref().to_path_buf();
+        self.file.sync_all()?;
+
+        fail::fail_point!("sstable::rename", |_| Err(SSTableError::Io(
+            io::Error::other("injected failure at sstable::rename")
+        )));
+        // A table written in a scratch directory on another filesystem is
+        // copied, so reopen it where it now lives
+        if io_backend::move_file(&self.path, &path)? {
+            self.file = File::open(&path)?;
+        }
+        if let Some(dir) = path.parent() {
+            io_backend::sync_dir(dir)?;
+        }
+
+        self.path = path;
+        Ok(())
+    }
+
+    /// Read the whole data section so the OS page cache holds it
+    ///
+    /// Returns the number of bytes read.
+    pub fn prefetch(&self) -> SSTableResult<u64> {
+        let start = self.header.data_offset;
+        let end = self.header.index_offset;
+        io_backend::advise_willneed(&self.file, start, end.saturating_sub(start));
+
+        let mut buf = vec![0u8; DEFAULT_READAHEAD_BYTES];
+        let mut offset = start;
+        while offset < end {
+            let len = (end - offset).min(DEFAULT_READAHEAD_BYTES as u64) as usize;
+            IoBackend::current().read_exact_at(&self.file, &mut buf[..len], offset)?;
+            offset += len as u64;
+        }
+        Ok(end.saturating_sub(start))
+    }
+
+    /// Number of point reads this table has served
+    pub fn read_count(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Seed the read counter, e.g. from a persisted access profile
+    pub fn set_read_count(&self, reads: u64) {
+        self.reads.store(reads, Ordering::Relaxed);
+    }
+
+    /// Size of the in-memory bloom filter in bytes (0 until it is loaded)
+    pub fn bloom_filter_size(&self) -> usize {
+        self.sections
+            .get()
+            .and_then(|sections| sections.bloom_filter.as_ref())
+            .map_or(0, |bloom_filter| bloom_filter.bits().len())
+    }
+
+    /// Retire the table once it has been removed from the database
+    ///
+    /// The file is renamed right away so it is never loaded again, but only
+    /// deleted when the last reference to the table is dropped, so iterators
+    /// and snapshots that still hold it keep working.
+    pub fn mark_obsolete(&self) -> SSTableResult<()> {
+        if self.obsolete_path.get().is_some() {
+            return Ok(());
+        }
+        let obsolete_path = self.path.with_extension(OBSOLETE_SSTABLE_EXTENSION);
+        std::fs::rename(&self.path, &obsolete_path)?;
+        if let Some(dir) = obsolete_path.parent() {
+            io_backend::sync_dir(dir)?;
+        }
+        let _ = self.obsolete_path.set(obsolete_path);
+        Ok(())
+    }
+
+    /// Serve point reads of this table through `cache`
+    ///
+    /// Gets look blocks up in the cache and cache the blocks they read; scans
+    /// and checks read the file directly. A table keeps the first cache it is
+    /// given.
+    pub fn set_block_cache(&self, cache: &Arc<BlockCache>) {
+        self.block_cache
+            .get_or_init(|| (cache.clone(), cache.new_table_id()));
+    }
+
+    /// Overwrite the file with zeros when it is finally deleted
+    pub fn shred_when_deleted(&self) {
+        self.shred.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`SSTable::mark_obsolete`] has been called
+    pub fn is_obsolete(&self) -> bool {
+        self.obsolete_path.get().is_some()
+    }
+
+    /// Smallest key in the table
+    pub fn first_key(&self) -> Option<&[u8]> {
+        self.key_range.as_ref().map(|(first, _)| first.as_slice())
+    }
+
+    /// Largest key in the table
+    pub fn last_key(&self) -> Option<&[u8]> {
+        self.key_range.as_ref().map(|(_, last)| last.as_slice())
+    }
+
+    /// Smallest and largest sequence numbers in the table, if known
+    pub fn sequence_range(&self) -> Option<(u64, u64)> {
+        self.sequence_range.get().copied()
+    }
+
+    /// Record the sequence range, e.g. from the manifest
+    pub fn set_sequence_range(&self, range: (u64, u64)) {
+        let _ = self.sequence_range.set(range);
+    }
+
+    /// Sequence range, reading every entry to find it when it isn't known yet
+    pub fn load_sequence_range(&self) -> SSTableResult<Option<(u64, u64)>> {
+        if let Some(range) = self.sequence_range() {
+            return Ok(Some(range));
+        }
+        let mut range: Option<(u64, u64)> = None;
+        for entry in self.iter() {
+            let seq = entry?.sequence_number;
+            range = Some(range.map_or((seq, seq), |(min, max)| (min.min(seq), max.max(seq))));
+        }
+        if let Some(range) = range {
+            self.set_sequence_range(range);
+        }
+        Ok(range)
+    }
+
+    /// Shift every sequence number in the table by the same amount so the
+    /// smallest becomes `first`, returning the table reopened
+    ///
+    /// From v3 on entries store sequence numbers relative to the header's
+    /// base, so only the header is rewritten, and synced. The sequence range
+    /// is read from the entries if it isn't known yet.
+    pub fn rebase_sequences(self, first: u64) -> SSTableResult<SSTable> {
+        if self.header.version < 3 {
+            return Err(SSTableError::InvalidFormat(format!(
+                "Format version {} stores absolute sequence numbers",
+                self.header.version
+            )));
+        }
+        let Some((min, max)) = self.load_sequence_range()? else {
+            return Ok(self);
+        };
+        let base = first
+            .checked_sub(min - self.header.base_sequence)
+            .ok_or_else(|| {
+                SSTableError::InvalidFormat(format!(
+                    "Sequence numbers from {} can't be shifted to start at {}",
+                    min, first
+                ))
+            })?;
+        let mut header = self.header.clone();
+        header.base_sequence = base;
+        let path = self.path.clone();
+        drop(self);
+
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        header.write(&mut file)?;
+        file.sync_all()?;
+        let sstable = SSTable::open_lazy(&path)?;
+        sstable.set_sequence_range((first, first + (max - min)));
+        Ok(sstable)
+    }
+
+    /// Format version recorded in the header
+    pub fn format_version(&self) -> u32 {
+        self.header.version
+    }
+
+    /// Compression type recorded in the header
+    pub fn compression_type(&self) -> SSTableResult<CompressionType> {
+        CompressionType::from_u8(self.header.compression_type)
+    }
+
+    /// Original and stored sizes of the values this table keeps compressed
+    pub fn compression_metadata(&self) -> SSTableResult<CompressionMetadata> {
+        Ok(self.sections()?.index.compression_metadata.clone())
     }
 
     /// Get the number of entries
@@ -583,73 +2787,886 @@ impl SSTable {
         self.entry_count() == 0
     }
 
-    /// Write index to writer
-    fn write_index<W: Write + Seek>(writer: &mut W, index: &SSTableIndex) -> io::Result<usize> {
-        let start_pos = writer.stream_position()?;
+    /// Index entries in key order; empty for block-based tables, whose index
+    /// lists blocks (see [`SSTable::block_handles`] and [`SSTable::stored_entries`])
+    pub fn index_entries(&self) -> SSTableResult<&[IndexEntry]> {
+        Ok(&self.sections()?.index.entries)
+    }
+
+    /// Data blocks in key order; empty for tables before v6
+    pub fn block_handles(&self) -> SSTableResult<&[BlockHandle]> {
+        Ok(&self.sections()?.index.blocks)
+    }
+
+    /// Size of the data section in bytes
+    pub fn data_size(&self) -> u64 {
+        self.header.index_offset - self.header.data_offset
+    }
+
+    /// Number of tombstones, counted from the index without reading data
+    ///
+    /// None while the index of a lazily opened table hasn't been loaded.
+    pub fn tombstone_count(&self) -> Option<usize> {
+        let sections = self.sections.get()?;
+        let blocks = &sections.index.blocks;
+        Some(
+            sections
+                .index
+                .entries
+                .iter()
+                .filter(|entry| entry.value_len() == 0)
+                .count()
+                + blocks
+                    .iter()
+                    .map(|block| block.tombstone_count as usize)
+                    .sum::<usize>(),
+        )
+    }
+
+    /// Write index to writer in the layout of format `version`
+    fn write_index<W: Write + Seek>(
+        writer: &mut W,
+        index: &SSTableIndex,
+        version: u32,
+    ) -> io::Result<usize> {
+        let start_pos = writer.stream_position()?;
+
+        if version >= BLOCK_FORMAT_VERSION {
+            // block_count (4), then per block key_len (4) + first key + offset (8)
+            // + size (4) + entry_count (4) + tombstone_count (4), then the
+            // compressed value bytes (8) and the last key followed by its length
+            writer.write_all(&(index.blocks.len() as u32).to_le_bytes())?;
+            for block in &index.blocks {
+                writer.write_all(&(block.first_key.len() as u32).to_le_bytes())?;
+                writer.write_all(&block.first_key)?;
+                writer.write_all(&block.offset.to_le_bytes())?;
+                writer.write_all(&block.size.to_le_bytes())?;
+                writer.write_all(&block.entry_count.to_le_bytes())?;
+                writer.write_all(&block.tombstone_count.to_le_bytes())?;
+            }
+            let compressed_size = index.compression_metadata.compressed_size as u64;
+            writer.write_all(&compressed_size.to_le_bytes())?;
+            writer.write_all(&index.last_key)?;
+            writer.write_all(&(index.last_key.len() as u32).to_le_bytes())?;
+            let end_pos = writer.stream_position()?;
+            return Ok((end_pos - start_pos) as usize);
+        }
+
+        // Write index header: entry_count (4 bytes)
+        let entry_count = index.len() as u32;
+        writer.write_all(&entry_count.to_le_bytes())?;
+
+        // Write each index entry
+        for entry in &index.entries {
+            // key_len (4) + key + offset (8) + key_size (4) + value_size (4)
+            let key_len = entry.key.len() as u32;
+            writer.write_all(&key_len.to_le_bytes())?;
+            writer.write_all(&entry.key)?;
+            writer.write_all(&entry.offset.to_le_bytes())?;
+            writer.write_all(&entry.key_size.to_le_bytes())?;
+            writer.write_all(&entry.value_size.to_le_bytes())?;
+        }
+
+        let end_pos = writer.stream_position()?;
+        Ok((end_pos - start_pos) as usize)
+    }
+
+    /// Read index from reader, which holds the whole index section
+    fn read_index(
+        reader: &mut &[u8],
+        entry_count: usize,
+        version: u32,
+    ) -> io::Result<SSTableIndex> {
+        if version >= BLOCK_FORMAT_VERSION {
+            return Self::read_block_index(reader);
+        }
+        let mut index = SSTableIndex::new();
+
+        // Read index header
+        let mut entry_count_bytes = [0u8; 4];
+        reader.read_exact(&mut entry_count_bytes)?;
+        let actual_entry_count = u32::from_le_bytes(entry_count_bytes) as usize;
+
+        if actual_entry_count != entry_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Index entry count mismatch: expected {}, got {}",
+                    entry_count, actual_entry_count
+                ),
+            ));
+        }
+
+        // Read each index entry
+        for _ in 0..actual_entry_count {
+            let mut key_len_bytes = [0u8; 4];
+            reader.read_exact(&mut key_len_bytes)?;
+            let key_len = u32::from_le_bytes(key_len_bytes) as usize;
+            if key_len > reader.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Index key length {} exceeds the index section", key_len),
+                ));
+            }
+
+            let mut key = vec![0u8; key_len];
+            reader.read_exact(&mut key)?;
+
+            let mut offset_bytes = [0u8; 8];
+            reader.read_exact(&mut offset_bytes)?;
+            let offset = u64::from_le_bytes(offset_bytes);
+
+            let mut key_size_bytes = [0u8; 4];
+            reader.read_exact(&mut key_size_bytes)?;
+            let key_size = u32::from_le_bytes(key_size_bytes);
+
+            let mut value_size_bytes = [0u8; 4];
+            reader.read_exact(&mut value_size_bytes)?;
+            let value_size = u32::from_le_bytes(value_size_bytes);
+
+            // v1 predates value flags, so set flag bits can only mean corruption
+            if version < 2 && value_size & VALUE_FLAGS != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid v1 value size {:#x}", value_size),
+                ));
+            }
+
+            index.add_entry(key, offset, key_size, value_size);
+        }
+
+        Ok(index)
+    }
+
+    /// Read the block handles and trailer of a v6 index
+    fn read_block_index(reader: &mut &[u8]) -> io::Result<SSTableIndex> {
+        let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+        let mut index = SSTableIndex::new();
+        let mut u32_bytes = [0u8; 4];
+        let mut u64_bytes = [0u8; 8];
+
+        reader.read_exact(&mut u32_bytes)?;
+        let block_count = u32::from_le_bytes(u32_bytes);
+        for _ in 0..block_count {
+            reader.read_exact(&mut u32_bytes)?;
+            let key_len = u32::from_le_bytes(u32_bytes) as usize;
+            if key_len > reader.len() {
+                return Err(invalid(format!(
+                    "Block key length {} exceeds the index section",
+                    key_len
+                )));
+            }
+            let mut first_key = vec![0u8; key_len];
+            reader.read_exact(&mut first_key)?;
+            reader.read_exact(&mut u64_bytes)?;
+            let offset = u64::from_le_bytes(u64_bytes);
+            let mut fields = [0u32; 3];
+            for field in &mut fields {
+                reader.read_exact(&mut u32_bytes)?;
+                *field = u32::from_le_bytes(u32_bytes);
+            }
+            let [size, entry_count, tombstone_count] = fields;
+            index.blocks.push(BlockHandle {
+                first_key,
+                offset,
+                size,
+                entry_count,
+                tombstone_count,
+            });
+        }
+
+        reader.read_exact(&mut u64_bytes)?;
+        index.compression_metadata.compressed_size = u64::from_le_bytes(u64_bytes) as usize;
+        // What is left is the last key and its length
+        let key_len = reader
+            .len()
+            .checked_sub(4)
+            .map(|len| (len, u32::from_le_bytes(reader[len..].try_into().unwrap())));
+        match key_len {
+            Some((len, key_len)) if len == key_len as usize => {
+                index.last_key = reader[..len].to_vec();
+                Ok(index)
+            }
+            _ => Err(invalid("Index ends with a malformed last key".to_string())),
+        }
+    }
+}
+
+impl Drop for SSTable {
+    fn drop(&mut self) {
+        if let Some((cache, id)) = self.block_cache.get() {
+            cache.remove_table(*id);
+        }
+        if let Some(path) = self.obsolete_path.get() {
+            let removed = if self.shred.load(Ordering::Relaxed) {
+                io_backend::shred_file(path)
+            } else {
+                std::fs::remove_file(path)
+            };
+            match removed {
+                Ok(()) => info!("Deleted obsolete SSTable {:?}", path),
+                Err(e) => warn!("Failed to delete obsolete SSTable {:?}: {}", path, e),
+            }
+        }
+    }
+}
+
+/// Writes a standalone SSTable from keys added in strictly ascending order,
+/// for bulk loads with [`Engine::ingest_sstable`](crate::engine::Engine::ingest_sstable)
+///
+/// Blocks are encoded and streamed to a scratch file next to the table as
+/// keys are added; only the open block and each key's bloom filter hash stay
+/// in memory. [`finish`](Self::finish) writes the table under a temporary
+/// name around that data, fsyncs it and renames it into place. Entries carry
+/// sequence number 0; ingestion assigns real ones. `use_direct_io` is ignored.
+pub struct SSTableBuilder {
+    path: PathBuf,
+    options: SSTableWriteOptions,
+    header: SSTableHeader,
+    data: DataSectionWriter,
+    /// Scratch file holding the data section, created with the first key
+    spill: Option<BufWriter<File>>,
+    spill_path: PathBuf,
+    /// Bloom filter hashes of every key and distinct prefix
+    key_hashes: Vec<usize>,
+    last_prefix: Option<Vec<u8>>,
+    timestamp: u64,
+}
+
+impl SSTableBuilder {
+    /// Start a table that `finish` writes to `path`
+    pub fn new<P: AsRef<Path>>(path: P, options: SSTableWriteOptions) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut header = SSTableHeader::new(0, 0, 0, 0);
+        header.version = options.format_version;
+        header.compression_type = options.compression as u8;
+        header.set_prefix_extractor(options.prefix_extractor.filter(|_| header.version >= 5));
+        if header.version >= 3 {
+            header.base_timestamp = timestamp;
+        }
+        let data = DataSectionWriter::new(&header, &options);
+        Self {
+            spill_path: scratch_path(&path, ".data.tmp"),
+            path,
+            options,
+            header,
+            data,
+            spill: None,
+            key_hashes: Vec::new(),
+            last_prefix: None,
+            timestamp,
+        }
+    }
+
+    /// Add `key` with `value`; `key` must sort after every key added so far
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> SSTableResult<()> {
+        self.push(key, Some(Bytes::copy_from_slice(value)))
+    }
+
+    /// Add a tombstone for `key`, deleting it from the database the table is
+    /// ingested into
+    pub fn delete(&mut self, key: &[u8]) -> SSTableResult<()> {
+        self.push(key, None)
+    }
+
+    fn push(&mut self, key: &[u8], value: Option<Bytes>) -> SSTableResult<()> {
+        let last = &self.data.previous_key;
+        if !self.is_empty() && last.as_slice() >= key {
+            return Err(SSTableError::InvalidFormat(format!(
+                "Key {:?} added after {:?}; keys must be strictly ascending",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(last)
+            )));
+        }
+        let spill = match self.spill.take() {
+            Some(spill) => spill,
+            None => {
+                check_format_version(&self.options)?;
+                let file = OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .read(true)
+                    .write(true)
+                    .open(&self.spill_path)?;
+                BufWriter::new(file)
+            }
+        };
+        let spill = self.spill.insert(spill);
+        let entry = Entry::new(key.to_vec(), value, self.timestamp, 0);
+        self.data.add(spill, 0, &entry)?;
+
+        self.key_hashes.push(BloomFilter::key_hash(key));
+        let prefix = self.header.prefix_extractor().and_then(|e| e.extract(key));
+        if let Some(prefix) = prefix
+            && self.last_prefix.as_deref() != Some(prefix)
+        {
+            self.key_hashes.push(BloomFilter::key_hash(prefix));
+            self.last_prefix = Some(prefix.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Number of entries added
+    pub fn len(&self) -> usize {
+        self.data.entry_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.entry_count == 0
+    }
+
+    /// Write the table, durably, and open it
+    pub fn finish(mut self) -> SSTableResult<SSTable> {
+        let Some(mut spill) = self.spill.take() else {
+            return Err(SSTableError::InvalidFormat(
+                "Cannot create SSTable without entries".to_string(),
+            ));
+        };
+        let mut header = self.header.clone();
+        header.entry_count = self.len() as u32;
+        let data = std::mem::replace(
+            &mut self.data,
+            DataSectionWriter::new(&self.header, &self.options),
+        );
+        let index = data.finish(&mut spill, 0)?;
+        let mut spill = spill.into_inner().map_err(|e| e.into_error())?;
+        spill.seek(SeekFrom::Start(0))?;
+
+        let mut bloom_filter = BloomFilter::new(self.key_hashes.len() * 10, 3); // 10x size, 3 hash functions
+        for &hash in &self.key_hashes {
+            bloom_filter.add_hash(hash);
+        }
+
+        let temp_path = scratch_path(&self.path, ".tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        SSTable::write_sections(&mut writer, header, &bloom_filter, |hashed, _| {
+            io::copy(&mut spill, hashed)?;
+            Ok(index)
+        })?;
+        writer.flush()?;
+        drop(writer);
+
+        let mut sstable = SSTable::open(&temp_path)?;
+        sstable.persist_to(&self.path)?;
+        info!(
+            "Built SSTable with {} entries at {:?}",
+            sstable.header.entry_count, self.path
+        );
+        Ok(sstable)
+    }
+}
+
+impl Drop for SSTableBuilder {
+    fn drop(&mut self) {
+        // Close the scratch file first, then remove it whether or not `finish` ran
+        self.spill = None;
+        if self.spill_path.exists() {
+            let _ = std::fs::remove_file(&self.spill_path);
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its file name
+fn scratch_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Everything [`inspect`] could read from an SSTable file
+///
+/// Sections are read independently, so a damaged table still reports the
+/// parts that decode; each failure is kept as text next to the section.
+#[derive(Debug, Clone)]
+pub struct SSTableInspection {
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub header: SSTableHeader,
+    pub footer: Result<SSTableFooter, String>,
+    /// Whether the data and index sections hash to the footer checksum; None
+    /// for tables written before checksums were recorded
+    pub checksum_valid: Option<bool>,
+    /// Index entries of tables before v6, or the block handles of v6 tables
+    pub index: Result<SSTableIndex, String>,
+    pub bloom: Result<Option<BloomStats>, String>,
+    /// Bytes checked by [`SSTable::verify`], or why it failed
+    pub verify: Result<u64, String>,
+    /// Every entry in key order, when asked for; a read error ends the list
+    pub entries: Option<Result<Vec<Entry>, String>>,
+}
+
+/// Size and fill of a stored bloom filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomStats {
+    pub bit_count: usize,
+    pub hash_count: usize,
+    pub bits_set: usize,
+}
+
+impl BloomStats {
+    /// False positive rate implied by the share of bits set
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        if self.bit_count == 0 {
+            return 1.0;
+        }
+        (self.bits_set as f64 / self.bit_count as f64).powi(self.hash_count as i32)
+    }
+}
+
+impl SSTableInspection {
+    /// Whether every section decoded and the checksum matched
+    pub fn is_intact(&self) -> bool {
+        self.footer.is_ok()
+            && self.checksum_valid != Some(false)
+            && self.index.is_ok()
+            && self.bloom.is_ok()
+            && self.verify.is_ok()
+            && self.entries.as_ref().is_none_or(Result::is_ok)
+    }
+}
+
+/// Read the header, footer, index and bloom filter of the SSTable at `path`,
+/// check its checksum and entries, and with `dump_entries` decode every entry
+///
+/// Fails only if the file can't be read or has no SSTable header; damage
+/// anywhere after the header is recorded in the report instead.
+pub fn inspect<P: AsRef<Path>>(path: P, dump_entries: bool) -> SSTableResult<SSTableInspection> {
+    let path = path.as_ref();
+    let contents = std::fs::read(path)?;
+    let header = SSTableHeader::decode(&contents)?;
+    let file_size = contents.len() as u64;
+
+    let footer = contents
+        .len()
+        .checked_sub(FOOTER_SIZE as usize)
+        .ok_or_else(|| "file too short for a footer".to_string())
+        .and_then(|start| SSTableFooter::read(&mut &contents[start..]).map_err(|e| e.to_string()));
+    let header_len = std::mem::size_of::<SSTableHeader>() as u64;
+    let sections_in_bounds = header.bloom_filter_offset >= header_len
+        && header.bloom_filter_offset <= header.data_offset
+        && header.data_offset <= header.index_offset
+        && header
+            .index_offset
+            .checked_add(FOOTER_SIZE)
+            .is_some_and(|end| end <= file_size);
+    let (bloom_bytes, data_bytes, index_bytes) = if sections_in_bounds {
+        let index_end = (file_size - FOOTER_SIZE) as usize;
+        (
+            &contents[header.bloom_filter_offset as usize..header.data_offset as usize],
+            &contents[header.data_offset as usize..header.index_offset as usize],
+            &contents[header.index_offset as usize..index_end],
+        )
+    } else {
+        (&[][..], &[][..], &[][..])
+    };
+    let out_of_bounds = || {
+        format!(
+            "section offsets out of bounds: bloom={}, data={}, index={}, file_len={}",
+            header.bloom_filter_offset, header.data_offset, header.index_offset, file_size
+        )
+    };
+
+    let checksum_valid = match &footer {
+        Ok(footer) if footer.has_checksum() => {
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(data_bytes);
+            hasher.update(index_bytes);
+            Some(sections_in_bounds && hasher.finalize() == footer.checksum)
+        }
+        _ => None,
+    };
+    let index = if sections_in_bounds {
+        SSTable::read_index(
+            &mut &index_bytes[..],
+            header.entry_count as usize,
+            header.version,
+        )
+        .map_err(|e| e.to_string())
+    } else {
+        Err(out_of_bounds())
+    };
+    let bloom = if !sections_in_bounds {
+        Err(out_of_bounds())
+    } else if bloom_bytes.is_empty() {
+        Ok(None)
+    } else if header.version >= 5 {
+        BloomFilter::decode(bloom_bytes)
+            .map(|filter| Some(bloom_stats(&filter)))
+            .map_err(|e| e.to_string())
+    } else {
+        // Older tables don't record the parameters; the writer always used 3 hashes
+        let mut filter = BloomFilter::new(bloom_bytes.len() * 8, 3);
+        filter.set_bits(bloom_bytes.to_vec());
+        Ok(Some(bloom_stats(&filter)))
+    };
+
+    let sstable = SSTable::open_lazy(path);
+    let verify = match &sstable {
+        Ok(sstable) => sstable.verify().map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let entries = dump_entries.then(|| match &sstable {
+        Ok(sstable) => sstable
+            .iter()
+            .collect::<SSTableResult<Vec<_>>>()
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    });
+
+    Ok(SSTableInspection {
+        path: path.to_path_buf(),
+        file_size,
+        header,
+        footer,
+        checksum_valid,
+        index,
+        bloom,
+        verify,
+        entries,
+    })
+}
+
+fn bloom_stats(filter: &BloomFilter) -> BloomStats {
+    BloomStats {
+        bit_count: filter.bit_count(),
+        hash_count: filter.hash_count(),
+        bits_set: filter.bits().iter().map(|b| b.count_ones() as usize).sum(),
+    }
+}
+
+/// Longest value printed whole in an inspection; longer ones are cut short
+const INSPECT_VALUE_PREVIEW: usize = 64;
+
+impl fmt::Display for SSTableInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        writeln!(
+            f,
+            "File: {} ({} bytes)",
+            self.path.display(),
+            self.file_size
+        )?;
+        writeln!(f, "Header:")?;
+        writeln!(f, "  version: {}", header.version)?;
+        writeln!(f, "  entry_count: {}", header.entry_count)?;
+        writeln!(f, "  bloom_filter_offset: {}", header.bloom_filter_offset)?;
+        writeln!(f, "  data_offset: {}", header.data_offset)?;
+        writeln!(f, "  index_offset: {}", header.index_offset)?;
+        match CompressionType::from_u8(header.compression_type) {
+            Ok(compression) => writeln!(f, "  compression: {:?}", compression)?,
+            Err(_) => writeln!(f, "  compression: unknown ({})", header.compression_type)?,
+        }
+        writeln!(f, "  uncompressed_bytes: {}", header.uncompressed_bytes)?;
+        writeln!(f, "  base_timestamp: {}", header.base_timestamp)?;
+        writeln!(f, "  base_sequence: {}", header.base_sequence)?;
+        if let Some(extractor) = header.prefix_extractor() {
+            writeln!(f, "  prefix_extractor: {:?}", extractor)?;
+        }
+
+        writeln!(f, "Footer:")?;
+        match &self.footer {
+            Ok(footer) => {
+                writeln!(f, "  data_size: {}", footer.data_size)?;
+                writeln!(f, "  index_size: {}", footer.index_size)?;
+                let status = match self.checksum_valid {
+                    Some(true) => "valid",
+                    Some(false) => "MISMATCH",
+                    None => "not recorded",
+                };
+                writeln!(f, "  checksum: {:#010x} ({})", footer.checksum, status)?;
+            }
+            Err(e) => writeln!(f, "  unreadable: {}", e)?,
+        }
+
+        writeln!(f, "Bloom filter:")?;
+        match &self.bloom {
+            Ok(Some(bloom)) => {
+                writeln!(
+                    f,
+                    "  {} bits, {} hashes, {} set ({:.1}%), ~{:.3}% false positives",
+                    bloom.bit_count,
+                    bloom.hash_count,
+                    bloom.bits_set,
+                    bloom.bits_set as f64 * 100.0 / bloom.bit_count.max(1) as f64,
+                    bloom.estimated_false_positive_rate() * 100.0
+                )?;
+            }
+            Ok(None) => writeln!(f, "  none")?,
+            Err(e) => writeln!(f, "  unreadable: {}", e)?,
+        }
+
+        match &self.index {
+            Ok(index) if !index.blocks.is_empty() => {
+                writeln!(f, "Index: {} blocks", index.blocks.len())?;
+                for block in &index.blocks {
+                    writeln!(
+                        f,
+                        "  {} @{} size={} entries={} tombstones={}",
+                        block.first_key.escape_ascii(),
+                        block.offset,
+                        block.size,
+                        block.entry_count,
+                        block.tombstone_count
+                    )?;
+                }
+                writeln!(f, "  last key: {}", index.last_key.escape_ascii())?;
+            }
+            Ok(index) => {
+                writeln!(f, "Index: {} entries", index.entries.len())?;
+                for entry in &index.entries {
+                    write!(
+                        f,
+                        "  {} @{} key={} value={}",
+                        entry.key.escape_ascii(),
+                        entry.offset,
+                        entry.key_size,
+                        entry.value_len()
+                    )?;
+                    for (set, flag) in [
+                        (entry.is_compressed(), "compressed"),
+                        (entry.is_indirect(), "blob"),
+                        (entry.is_merge(), "merge"),
+                    ] {
+                        if set {
+                            write!(f, " {}", flag)?;
+                        }
+                    }
+                    writeln!(f)?;
+                }
+            }
+            Err(e) => writeln!(f, "Index: unreadable: {}", e)?,
+        }
+
+        match &self.verify {
+            Ok(bytes) => writeln!(f, "Verify: OK ({} bytes checked)", bytes)?,
+            Err(e) => writeln!(f, "Verify: FAILED: {}", e)?,
+        }
+
+        match &self.entries {
+            Some(Ok(entries)) => {
+                writeln!(f, "Entries: {}", entries.len())?;
+                for entry in entries {
+                    write!(
+                        f,
+                        "  {} seq={} ts={}",
+                        entry.key.escape_ascii(),
+                        entry.sequence_number,
+                        entry.timestamp
+                    )?;
+                    match &entry.value {
+                        None => write!(f, " tombstone")?,
+                        Some(value) => {
+                            let kind = match (entry.indirect, entry.merge) {
+                                (true, _) => " blob pointer",
+                                (_, true) => " merge operand",
+                                _ => "",
+                            };
+                            let preview = &value[..value.len().min(INSPECT_VALUE_PREVIEW)];
+                            write!(f, "{} => {}", kind, preview.escape_ascii())?;
+                            if value.len() > preview.len() {
+                                write!(f, "... ({} bytes)", value.len())?;
+                            }
+                        }
+                    }
+                    writeln!(f)?;
+                }
+            }
+            Some(Err(e)) => writeln!(f, "Entries: unreadable: {}", e)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the entries of an SSTable in key order
+///
+/// Reads are positioned, so several iterators can share one SSTable. When
+/// consecutive reads are contiguous on disk, the iterator switches from one small
+/// read per entry to reading whole read-ahead windows and asks the kernel to
+/// prefetch the following window.
+pub struct SSTableIter<S> {
+    sstable: S,
+    /// Position of the next entry in the index, or of the next block of a
+    /// block-based table
+    position: usize,
+    /// Entries of the current block not yet returned
+    block_entries: VecDeque<Entry>,
+    /// Key passed to `seek`, skipped up to in the block read next
+    seek_key: Option<Vec<u8>>,
+    /// Read-ahead window size in bytes (0 disables read-ahead)
+    readahead_bytes: usize,
+    /// Bytes read ahead, starting at file offset `buffer_offset`
+    buffer: Bytes,
+    buffer_offset: u64,
+    /// End offset of the previous read, used to detect sequential access
+    last_read_end: Option<u64>,
+    sequential_reads: usize,
+    /// Number of read-ahead windows filled so far
+    readahead_fills: usize,
+    /// Loading the index failed and the error has been returned
+    failed: bool,
+}
 
-        // Write index header: entry_count (4 bytes)
-        let entry_count = index.len() as u32;
-        writer.write_all(&entry_count.to_le_bytes())?;
+/// Iterator borrowing the SSTable it reads
+pub type SSTableIterator<'a> = SSTableIter<&'a SSTable>;
 
-        // Write each index entry
-        for entry in &index.entries {
-            // key_len (4) + key + offset (8) + key_size (4) + value_size (4)
-            let key_len = entry.key.len() as u32;
-            writer.write_all(&key_len.to_le_bytes())?;
-            writer.write_all(&entry.key)?;
-            writer.write_all(&entry.offset.to_le_bytes())?;
-            writer.write_all(&entry.key_size.to_le_bytes())?;
-            writer.write_all(&entry.value_size.to_le_bytes())?;
+impl<S: Deref<Target = SSTable>> SSTableIter<S> {
+    fn new(sstable: S) -> Self {
+        Self {
+            sstable,
+            position: 0,
+            block_entries: VecDeque::new(),
+            seek_key: None,
+            readahead_bytes: DEFAULT_READAHEAD_BYTES,
+            buffer: Bytes::new(),
+            buffer_offset: 0,
+            last_read_end: None,
+            sequential_reads: 0,
+            readahead_fills: 0,
+            failed: false,
         }
+    }
 
-        let end_pos = writer.stream_position()?;
-        Ok((end_pos - start_pos) as usize)
+    /// Set the read-ahead window size in bytes (0 disables read-ahead)
+    pub fn with_readahead(mut self, readahead_bytes: usize) -> Self {
+        self.readahead_bytes = readahead_bytes;
+        self
     }
 
-    /// Read index from reader
-    fn read_index<R: Read>(reader: &mut R, entry_count: usize) -> io::Result<SSTableIndex> {
-        let mut index = SSTableIndex::new();
+    /// Position the iterator at the first entry with a key >= `key`
+    ///
+    /// If the index can't be loaded, the error is returned by the next call to `next`.
+    pub fn seek(&mut self, key: &[u8]) {
+        let Ok(sections) = self.sstable.sections() else {
+            return;
+        };
+        if self.sstable.is_block_based() {
+            self.position = sections.index.find_block(key).unwrap_or(0);
+            self.block_entries.clear();
+            self.seek_key = Some(key.to_vec());
+        } else {
+            self.position = sections
+                .index
+                .entries
+                .partition_point(|entry| entry.key.as_slice() < key);
+        }
+    }
 
-        // Read index header
-        let mut entry_count_bytes = [0u8; 4];
-        reader.read_exact(&mut entry_count_bytes)?;
-        let actual_entry_count = u32::from_le_bytes(entry_count_bytes) as usize;
+    /// Read and decode the block at `position`, dropping entries before a sought key
+    fn read_block(&mut self, position: usize) -> SSTableResult<VecDeque<Entry>> {
+        let handle = self.sstable.sections()?.index.blocks[position].clone();
+        let start = self.sstable.header.data_offset + handle.offset;
+        let bytes = self.read_at(start, handle.size as usize)?;
+        let source = BlockSource::Buffer(bytes.clone());
+        let seek_key = self.seek_key.take();
+        let mut entries = VecDeque::with_capacity(handle.entry_count as usize);
+        for found in BlockEntry::read_all(&source, &self.sstable.header, &handle)? {
+            if seek_key
+                .as_ref()
+                .is_some_and(|seek_key| found.key < *seek_key)
+            {
+                continue;
+            }
+            let value = bytes.slice(found.value_offset..found.end);
+            entries.push_back(self.sstable.build_entry(found.key, &found.header, value)?);
+        }
+        Ok(entries)
+    }
 
-        if actual_entry_count != entry_count {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Index entry count mismatch: expected {}, got {}",
-                    entry_count, actual_entry_count
-                ),
-            ));
+    /// Read a full entry (header, key, and value) for the index entry at `position`
+    fn read_entry(&mut self, position: usize) -> SSTableResult<Entry> {
+        let entries = &self.sstable.sections()?.index.entries;
+        let span = self.sstable.entry_span(entries, position)?;
+        let bytes = self.read_at(span.start, span.len())?;
+        let index_entry = &self.sstable.sections()?.index.entries[position];
+        self.sstable.decode_entry(bytes, &span, index_entry)
+    }
+
+    /// Read `len` bytes at `offset`, serving from the read-ahead window when possible
+    ///
+    /// Reads served from the window are slices of it rather than copies.
+    fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Bytes> {
+        let end = offset + len as u64;
+        let buffer_end = self.buffer_offset + self.buffer.len() as u64;
+        if offset >= self.buffer_offset && end <= buffer_end {
+            let start = (offset - self.buffer_offset) as usize;
+            self.last_read_end = Some(end);
+            return Ok(self.buffer.slice(start..start + len));
         }
 
-        // Read each index entry
-        for _ in 0..actual_entry_count {
-            let mut key_len_bytes = [0u8; 4];
-            reader.read_exact(&mut key_len_bytes)?;
-            let key_len = u32::from_le_bytes(key_len_bytes) as usize;
+        if self.last_read_end == Some(offset) {
+            self.sequential_reads += 1;
+        } else {
+            self.sequential_reads = 0;
+        }
+        self.last_read_end = Some(end);
 
-            let mut key = vec![0u8; key_len];
-            reader.read_exact(&mut key)?;
+        let file = &self.sstable.file;
+        if self.readahead_bytes == 0 || self.sequential_reads < SEQUENTIAL_READS_BEFORE_READAHEAD {
+            let mut bytes = vec![0u8; len];
+            IoBackend::current().read_exact_at(file, &mut bytes, offset)?;
+            return Ok(Bytes::from(bytes));
+        }
 
-            let mut offset_bytes = [0u8; 8];
-            reader.read_exact(&mut offset_bytes)?;
-            let offset = u64::from_le_bytes(offset_bytes);
+        // Sequential scan detected: read a whole window, never past the data section
+        let data_end = self.sstable.header.index_offset.max(end);
+        let window = (self.readahead_bytes.max(len) as u64).min(data_end - offset) as usize;
+        let mut buffer = vec![0u8; window];
+        IoBackend::current().read_exact_at(file, &mut buffer, offset)?;
+        self.buffer = Bytes::from(buffer);
+        self.buffer_offset = offset;
+        self.readahead_fills += 1;
+
+        // Let the kernel start on the next window while this one is consumed
+        let next = offset + window as u64;
+        if next < data_end {
+            io_backend::advise_willneed(file, next, self.readahead_bytes as u64);
+        }
 
-            let mut key_size_bytes = [0u8; 4];
-            reader.read_exact(&mut key_size_bytes)?;
-            let key_size = u32::from_le_bytes(key_size_bytes);
+        Ok(self.buffer.slice(..len))
+    }
+}
 
-            let mut value_size_bytes = [0u8; 4];
-            reader.read_exact(&mut value_size_bytes)?;
-            let value_size = u32::from_le_bytes(value_size_bytes);
+impl<S: Deref<Target = SSTable>> Iterator for SSTableIter<S> {
+    type Item = SSTableResult<Entry>;
 
-            index.add_entry(key, offset, key_size, value_size);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
         }
-
-        Ok(index)
+        let (entry_count, block_count) = match self.sstable.sections() {
+            Ok(sections) => (sections.index.entries.len(), sections.index.blocks.len()),
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        if self.sstable.is_block_based() {
+            loop {
+                if let Some(entry) = self.block_entries.pop_front() {
+                    return Some(Ok(entry));
+                }
+                let position = self.position;
+                if position >= block_count {
+                    return None;
+                }
+                self.position += 1;
+                match self.read_block(position) {
+                    Ok(entries) => self.block_entries = entries,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }
+        let position = self.position;
+        if position >= entry_count {
+            return None;
+        }
+        self.position += 1;
+        Some(self.read_entry(position))
     }
 }
 
@@ -677,7 +3694,7 @@ mod tests {
 
         // Create SSTable from MemTable
         let memtable = create_test_memtable();
-        let mut sstable =
+        let sstable =
             SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
 
         // Verify basic properties
@@ -688,11 +3705,11 @@ mod tests {
         // Test reading values
         assert_eq!(
             sstable.get(b"apple").unwrap(),
-            Some(b"apple_value".to_vec())
+            Some(Bytes::from_static(b"apple_value"))
         );
         assert_eq!(
             sstable.get(b"cherry").unwrap(),
-            Some(b"cherry_value".to_vec())
+            Some(Bytes::from_static(b"cherry_value"))
         );
         assert_eq!(sstable.get(b"banana").unwrap(), None); // Tombstone
 
@@ -700,6 +3717,55 @@ mod tests {
         assert_eq!(sstable.get(b"nonexistent").unwrap(), None);
     }
 
+    #[test]
+    fn test_sstable_iterator() {
+        let temp_dir = tempdir().unwrap();
+        let sstable_path = temp_dir.path().join("iter.sst");
+
+        let memtable = create_test_memtable();
+        let sstable =
+            SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
+
+        // Iteration yields every entry, tombstones included, in key order
+        let entries: Vec<Entry> = sstable.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, memtable.entries());
+
+        // Seeking positions at the first key >= target
+        let mut iter = sstable.iter();
+        iter.seek(b"b");
+        let keys: Vec<Vec<u8>> = iter.map(|e| e.unwrap().key).collect();
+        assert_eq!(keys, vec![b"banana".to_vec(), b"cherry".to_vec()]);
+    }
+
+    #[test]
+    fn test_sstable_iterator_readahead() {
+        let temp_dir = tempdir().unwrap();
+        let sstable_path = temp_dir.path().join("readahead.sst");
+
+        let memtable = MemTable::new(1024 * 1024);
+        for i in 0..2000 {
+            let key = format!("key_{:05}", i);
+            let value = format!("value_{}", i);
+            memtable.put(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        let sstable =
+            SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
+        let expected = memtable.entries();
+
+        // A long scan switches to window reads and still returns identical data
+        let mut iter = sstable.iter().with_readahead(4096);
+        let entries: Vec<Entry> = iter.by_ref().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, expected);
+        assert!(iter.readahead_fills > 0);
+        assert!(iter.readahead_fills < expected.len() / 10);
+
+        // Disabling read-ahead falls back to one read per entry
+        let mut iter = sstable.iter().with_readahead(0);
+        let entries: Vec<Entry> = iter.by_ref().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, expected);
+        assert_eq!(iter.readahead_fills, 0);
+    }
+
     #[test]
     fn test_sstable_bloom_filter() {
         let temp_dir = tempdir().unwrap();
@@ -710,15 +3776,16 @@ mod tests {
             SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
 
         // Bloom filter should contain our keys
-        assert!(sstable.bloom_filter.might_contain(b"apple"));
-        assert!(sstable.bloom_filter.might_contain(b"cherry"));
+        assert!(sstable.sections().unwrap().might_contain(b"apple"));
+        assert!(sstable.sections().unwrap().might_contain(b"cherry"));
 
         // Bloom filter might have false positives, but should be reasonable
         // This is a probabilistic test
         let false_positives = (0..100)
             .filter(|_| {
                 sstable
-                    .bloom_filter
+                    .sections()
+                    .unwrap()
                     .might_contain(format!("random_key_{}", rand::random::<u32>()).as_bytes())
             })
             .count();
@@ -736,22 +3803,26 @@ mod tests {
         let sstable =
             SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
 
-        // Verify index properties
-        assert_eq!(sstable.index.len(), 3);
-        assert!(!sstable.index.is_empty());
-
-        // Verify index entries are sorted (MemTable maintains insertion order)
-        let keys: Vec<&[u8]> = sstable
-            .index
-            .entries
-            .iter()
-            .map(|e| e.key.as_slice())
+        // Small tables fit in one block, listed by its first key
+        let index = &sstable.sections().unwrap().index;
+        assert!(index.is_empty());
+        assert_eq!(index.blocks.len(), 1);
+        assert_eq!(index.blocks[0].first_key, b"apple");
+        assert_eq!(index.blocks[0].entry_count, 3);
+        assert_eq!(index.blocks[0].tombstone_count, 1);
+        assert_eq!(index.last_key, b"cherry");
+
+        // Stored entries come back in key order: apple, banana (tombstone), cherry
+        let keys: Vec<Vec<u8>> = sstable
+            .stored_entries()
+            .unwrap()
+            .into_iter()
+            .map(|(entry, _)| entry.key)
             .collect();
-        // The order should be: apple, banana (tombstone), cherry
-        assert_eq!(keys.len(), 3);
-        assert!(keys.iter().any(|k| k == b"apple"));
-        assert!(keys.iter().any(|k| k == b"banana"));
-        assert!(keys.iter().any(|k| k == b"cherry"));
+        assert_eq!(
+            keys,
+            vec![b"apple".to_vec(), b"banana".to_vec(), b"cherry".to_vec()]
+        );
     }
 
     #[test]
@@ -801,11 +3872,300 @@ mod tests {
             CompressionType::None as u8
         );
 
-        // Note: LZ4 and Zstd compression would require additional dependencies
-        // For now, we just test that the enum works correctly
         assert_eq!(CompressionType::None as u8, 0);
         assert_eq!(CompressionType::LZ4 as u8, 1);
         assert_eq!(CompressionType::Zstd as u8, 2);
+        assert_eq!(CompressionType::from_u8(1).unwrap(), CompressionType::LZ4);
+        assert!(CompressionType::from_u8(9).is_err());
+    }
+
+    #[test]
+    fn test_sstable_get_entry_range() {
+        let temp_dir = tempdir().unwrap();
+        let memtable = MemTable::new(1024 * 1024);
+        let large: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        memtable.put(b"large", &large).unwrap();
+        memtable.put(b"small", b"tiny").unwrap();
+        memtable.delete(b"gone").unwrap();
+
+        for compression in [CompressionType::None, CompressionType::Zstd] {
+            let path = temp_dir.path().join(format!("range_{:?}.sst", compression));
+            let options = SSTableWriteOptions {
+                compression,
+                ..Default::default()
+            };
+            let sstable = SSTable::from_memtable_with_options(&path, &memtable, &options).unwrap();
+
+            let entry = sstable
+                .get_entry_range(b"large", 1000, 16)
+                .unwrap()
+                .unwrap();
+            assert_eq!(entry.value.unwrap().as_ref(), &large[1000..1016]);
+
+            // Ranges are clamped to the value
+            let entry = sstable.get_entry_range(b"small", 2, 100).unwrap().unwrap();
+            assert_eq!(entry.value.unwrap().as_ref(), b"ny");
+            let entry = sstable.get_entry_range(b"small", 10, 1).unwrap().unwrap();
+            assert_eq!(entry.value, Some(Bytes::new()));
+
+            let entry = sstable.get_entry_range(b"gone", 0, 1).unwrap().unwrap();
+            assert!(entry.is_deletion());
+            assert!(sstable.get_entry_range(b"missing", 0, 1).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_sstable_verify() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("verify.sst");
+        let memtable = MemTable::new(1024 * 1024);
+        memtable.put(b"apple", b"red").unwrap();
+        memtable.put(b"banana", b"yellow").unwrap();
+        memtable.delete(b"cherry").unwrap();
+        let sstable = SSTable::from_memtable(&path, &memtable, CompressionType::LZ4).unwrap();
+        assert_eq!(
+            sstable.verify().unwrap(),
+            std::fs::metadata(&path).unwrap().len()
+        );
+
+        // Decay in the data section is caught even though reads of other keys still work
+        let contents = std::fs::read(&path).unwrap();
+        let at = contents.windows(6).position(|w| w == b"banana").unwrap();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(at as u64 + 1)).unwrap();
+        file.write_all(b"X").unwrap();
+        drop(file);
+        assert_eq!(sstable.get(b"apple").unwrap().unwrap().as_ref(), b"red");
+        assert!(matches!(
+            sstable.verify(),
+            Err(SSTableError::CorruptedFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_sstable_checksums() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("checksum.sst");
+        let memtable = create_test_memtable();
+        SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        let footer_at = contents.len() - FOOTER_SIZE as usize;
+        let footer = SSTableFooter::read(&mut &contents[footer_at..]).unwrap();
+        assert!(footer.has_checksum());
+        let header = SSTableHeader::decode(&contents).unwrap();
+        assert_eq!(
+            footer.checksum,
+            crc32fast::hash(&contents[header.data_offset as usize..footer_at])
+        );
+
+        // Rebuilt tables get a checksum of their own
+        let rebuilt_path = temp_dir.path().join("rebuilt.sst");
+        SSTable::open(&path)
+            .unwrap()
+            .rebuild_metadata(rebuilt_path.clone())
+            .unwrap();
+        assert!(SSTable::open(&rebuilt_path).is_ok());
+
+        // A flipped bit in a value fails the open, even though the entry still decodes
+        let mut corrupt = contents.clone();
+        let at = corrupt
+            .windows(12)
+            .position(|w| w == b"cherry_value")
+            .unwrap();
+        corrupt[at + 7] ^= 0x01;
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(matches!(
+            SSTable::open(&path),
+            Err(SSTableError::CorruptedFile(_))
+        ));
+        let lazy = SSTable::open_lazy(&path).unwrap();
+        assert!(matches!(
+            lazy.get(b"cherry"),
+            Err(SSTableError::CorruptedFile(_))
+        ));
+
+        // Tables written before checksums have no flag and open unchecked
+        corrupt[footer_at..footer_at + 4].fill(0);
+        corrupt[footer_at + 20] = 0;
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(SSTable::open(&path).is_ok());
+    }
+
+    #[test]
+    fn test_sstable_rebuild_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("legacy.sst");
+        let memtable = create_test_memtable();
+        SSTable::from_memtable(&path, &memtable, CompressionType::Zstd).unwrap();
+        assert!(
+            !SSTable::open(&path)
+                .unwrap()
+                .needs_metadata_rebuild()
+                .unwrap()
+        );
+
+        // A v1 table without a bloom filter section
+        let options = SSTableWriteOptions {
+            compression: CompressionType::Zstd,
+            format_version: 2,
+            ..Default::default()
+        };
+        SSTable::from_memtable_with_options(&path, &memtable, &options).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let header = SSTableHeader::decode(&bytes).unwrap();
+        bytes[8..12].copy_from_slice(&1u32.to_le_bytes());
+        bytes[24..32].copy_from_slice(&header.data_offset.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let legacy = SSTable::open(&path).unwrap();
+        legacy.set_sequence_range((1, 4));
+        assert!(legacy.needs_metadata_rebuild().unwrap());
+        assert_eq!(legacy.bloom_filter_size(), 0);
+        assert_eq!(
+            legacy.get(b"apple").unwrap(),
+            memtable.get(b"apple").unwrap()
+        );
+
+        let rebuilt = legacy
+            .rebuild_metadata(temp_dir.path().join("rebuilt.sst"))
+            .unwrap();
+        assert_eq!(rebuilt.format_version(), SSTABLE_FORMAT_VERSION);
+        assert_eq!(rebuilt.sequence_range(), Some((1, 4)));
+        assert!(!rebuilt.needs_metadata_rebuild().unwrap());
+        assert!(rebuilt.bloom_filter_size() > 0);
+        assert!(rebuilt.verify().is_ok());
+        let entries: Vec<Entry> = rebuilt.iter().map(Result::unwrap).collect();
+        let expected: Vec<Entry> = memtable.entries();
+        assert_eq!(entries, expected);
+        assert_eq!(
+            rebuilt.get(b"apple").unwrap(),
+            memtable.get(b"apple").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sstable_compression_threshold() {
+        let temp_dir = tempdir().unwrap();
+        let memtable = MemTable::new(1024 * 1024);
+        let large = vec![b'x'; 4096];
+        memtable.put(b"large", &large).unwrap();
+        memtable.put(b"small", b"tiny").unwrap();
+        memtable.delete(b"gone").unwrap();
+
+        for compression in [CompressionType::LZ4, CompressionType::Zstd] {
+            let path = temp_dir.path().join(format!("{:?}.sst", compression));
+            let options = SSTableWriteOptions {
+                compression,
+                compression_options: CompressionOptions { min_value_size: 64 },
+                ..Default::default()
+            };
+            SSTable::from_memtable_with_options(&path, &memtable, &options).unwrap();
+            assert!(std::fs::metadata(&path).unwrap().len() < large.len() as u64);
+
+            // Only the value above the threshold is flagged as compressed
+            let sstable = SSTable::open(&path).unwrap();
+            assert_eq!(sstable.header.compression_type, compression as u8);
+            let stored = sstable.stored_entries().unwrap();
+            let find = |key: &[u8]| {
+                stored
+                    .iter()
+                    .map(|(entry, _)| entry)
+                    .find(|entry| entry.key == key)
+                    .unwrap()
+            };
+            assert!(find(b"large").is_compressed());
+            assert!(!find(b"small").is_compressed());
+
+            assert_eq!(
+                sstable.get(b"large").unwrap(),
+                Some(Bytes::from(large.clone()))
+            );
+            assert_eq!(
+                sstable.get(b"small").unwrap(),
+                Some(Bytes::from_static(b"tiny"))
+            );
+            assert_eq!(sstable.get(b"gone").unwrap(), None);
+
+            // The header keeps the original size of the compressed values
+            let metadata = sstable.compression_metadata().unwrap();
+            assert_eq!(metadata.compression_type, compression);
+            assert_eq!(metadata.original_size, large.len());
+            assert_eq!(metadata.compressed_size, find(b"large").value_len());
+
+            let scanned: Vec<_> = sstable.iter().map(|e| e.unwrap().value).collect();
+            assert_eq!(
+                scanned,
+                vec![
+                    None,
+                    Some(Bytes::from(large.clone())),
+                    Some(Bytes::from_static(b"tiny"))
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_sstable_lazy_open() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("lazy.sst");
+        SSTable::from_memtable(&path, &create_test_memtable(), CompressionType::None).unwrap();
+
+        let sstable = SSTable::open_lazy(&path).unwrap();
+        assert!(!sstable.is_loaded());
+        assert_eq!(sstable.first_key(), Some(b"apple".as_slice()));
+        assert_eq!(sstable.last_key(), Some(b"cherry".as_slice()));
+        assert_eq!(sstable.bloom_filter_size(), 0);
+        assert_eq!(sstable.tombstone_count(), None);
+
+        assert_eq!(
+            sstable.get(b"apple").unwrap(),
+            Some(Bytes::from_static(b"apple_value"))
+        );
+        assert!(sstable.is_loaded());
+        assert_eq!(sstable.tombstone_count(), Some(1));
+
+        // A damaged index is only noticed once a read needs it
+        let mut bytes = std::fs::read(&path).unwrap();
+        let index_offset = SSTableHeader::decode(&bytes).unwrap().index_offset as usize;
+        bytes[index_offset..index_offset + 4].copy_from_slice(&99u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let sstable = SSTable::open_lazy(&path).unwrap();
+        assert!(sstable.get(b"apple").is_err());
+        let mut iter = sstable.iter();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert!(SSTable::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_sstable_concurrent_gets() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("shared.sst");
+        let memtable = MemTable::new(usize::MAX);
+        for i in 0..500u32 {
+            memtable
+                .put(format!("key{:04}", i).as_bytes(), &i.to_le_bytes())
+                .unwrap();
+        }
+        SSTable::from_memtable(&path, &memtable, CompressionType::LZ4).unwrap();
+
+        // Reads use positioned I/O, so threads share one handle, even while
+        // the first of them loads the lazily opened sections
+        let sstable = SSTable::open_lazy(&path).unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..8u32 {
+                let sstable = &sstable;
+                scope.spawn(move || {
+                    for i in (0..500u32).map(|i| (i + thread * 61) % 500) {
+                        let key = format!("key{:04}", i);
+                        assert_eq!(
+                            sstable.get(key.as_bytes()).unwrap(),
+                            Some(Bytes::copy_from_slice(&i.to_le_bytes()))
+                        );
+                    }
+                });
+            }
+        });
     }
 
     #[test]
@@ -822,6 +4182,365 @@ mod tests {
         assert_eq!(footer.index_size, 500);
     }
 
+    #[test]
+    fn test_sstable_format_versions() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("versioned.sst");
+        let memtable = MemTable::new(1024);
+        memtable.put(b"key1", b"value1").unwrap();
+        let sstable = SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+        assert_eq!(sstable.format_version(), SSTABLE_FORMAT_VERSION);
+
+        let stamp = |version: u32| {
+            let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+            file.seek(SeekFrom::Start(8)).unwrap();
+            file.write_all(&version.to_le_bytes()).unwrap();
+        };
+
+        // v1 and v2 share fixed-size entry headers
+        let options = SSTableWriteOptions {
+            format_version: 2,
+            ..Default::default()
+        };
+        SSTable::from_memtable_with_options(&path, &memtable, &options).unwrap();
+        for version in MIN_SSTABLE_FORMAT_VERSION..=2 {
+            stamp(version);
+            let sstable = SSTable::open(&path).unwrap();
+            assert_eq!(sstable.format_version(), version);
+            assert_eq!(
+                sstable.get(b"key1").unwrap(),
+                Some(Bytes::from_static(b"value1"))
+            );
+        }
+
+        for version in [0, SSTABLE_FORMAT_VERSION + 1] {
+            stamp(version);
+            assert!(matches!(
+                SSTable::open(&path),
+                Err(SSTableError::UnsupportedVersion(v)) if v == version
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sstable_varint_entry_headers() {
+        let temp_dir = tempdir().unwrap();
+        let large = Bytes::from(vec![b'x'; 300]);
+        let entries = vec![
+            Entry::new(
+                b"a".to_vec(),
+                Some(Bytes::from_static(b"1")),
+                1_700_000_000_000,
+                1_000,
+            ),
+            Entry::merge_operand(
+                b"b".to_vec(),
+                Bytes::from_static(b"+2"),
+                1_700_000_000_005,
+                1_001,
+            ),
+            Entry::new(b"c".to_vec(), None, 1_700_000_000_009, 1_002),
+            Entry::indirect(
+                b"d".to_vec(),
+                Bytes::from_static(b"ptr"),
+                1_700_000_000_010,
+                5_000,
+            ),
+            Entry::new(b"e".to_vec(), Some(large.clone()), u64::MAX, u64::MAX),
+        ];
+
+        let write = |name: &str, format_version: u32| {
+            let options = SSTableWriteOptions {
+                format_version,
+                ..Default::default()
+            };
+            let path = temp_dir.path().join(name);
+            SSTable::from_entries_with_options(&path, &entries, &options)
+        };
+        let v2 = write("v2.sst", 2).unwrap();
+        let v3 = write("v3.sst", 3).unwrap();
+        assert!(write("unsupported.sst", SSTABLE_FORMAT_VERSION + 1).is_err());
+
+        // Entries near the bases shrink from a 24-byte header to a few bytes
+        assert_eq!(v3.stored_entries().unwrap()[0].1, 4 + 1 + 1);
+        assert!(v3.data_size() + 4 * 18 <= v2.data_size());
+
+        let v3 = SSTable::open(v3.path()).unwrap();
+        assert_eq!(v3.format_version(), 3);
+        assert_eq!(
+            v3.verify().unwrap(),
+            std::fs::metadata(v3.path()).unwrap().len()
+        );
+        let read: Vec<Entry> = v3.iter().map(Result::unwrap).collect();
+        assert_eq!(read, entries);
+        assert_eq!(v3.get_entry(b"b").unwrap().unwrap(), entries[1]);
+        assert_eq!(v3.load_sequence_range().unwrap(), Some((1_000, u64::MAX)));
+        let range = v3.get_entry_range(b"e", 290, 100).unwrap().unwrap();
+        assert_eq!(range.value, Some(large.slice(290..)));
+        assert_eq!(range.timestamp, u64::MAX);
+    }
+
+    #[test]
+    fn test_sstable_shared_key_prefixes() {
+        let temp_dir = tempdir().unwrap();
+        let entries: Vec<Entry> = (0..40)
+            .map(|i| {
+                let key = format!("sensors/greenhouse-7/temperature/{}", 1000 + i).into_bytes();
+                Entry::new(key, Some(Bytes::from_static(b"21.5")), 1_000 + i, i)
+            })
+            .collect();
+        let write = |name: &str, format_version: u32| {
+            let options = SSTableWriteOptions {
+                format_version,
+                ..Default::default()
+            };
+            let path = temp_dir.path().join(name);
+            SSTable::from_entries_with_options(&path, &entries, &options).unwrap()
+        };
+        let v3 = write("v3.sst", 3);
+        let v4 = write("v4.sst", 4);
+        assert!(v4.data_size() * 2 < v3.data_size());
+
+        // Restart points store the whole key
+        let key_len = entries[0].key.len();
+        let stored = v4.stored_entries().unwrap();
+        assert_eq!(stored[0].1, 5 + key_len + 4);
+        assert_eq!(stored[1].1, 5 + 1 + 4);
+        assert_eq!(stored[KEY_RESTART_INTERVAL].1, 5 + key_len + 4);
+
+        let v4 = SSTable::open(v4.path()).unwrap();
+        assert!(v4.verify().is_ok());
+        let read: Vec<Entry> = v4.iter().map(Result::unwrap).collect();
+        assert_eq!(read, entries);
+        for entry in &entries {
+            assert_eq!(v4.get_entry(&entry.key).unwrap().as_ref(), Some(entry));
+        }
+        let mut iter = v4.iter();
+        iter.seek(&entries[17].key);
+        assert_eq!(iter.next().unwrap().unwrap(), entries[17]);
+
+        // Older tables are re-encoded with shared prefixes
+        let rebuilt = v3
+            .rebuild_metadata(temp_dir.path().join("rebuilt.sst"))
+            .unwrap();
+        assert_eq!(rebuilt.format_version(), SSTABLE_FORMAT_VERSION);
+        let current = write("current.sst", SSTABLE_FORMAT_VERSION);
+        assert_eq!(rebuilt.data_size(), current.data_size());
+    }
+
+    #[test]
+    fn test_sstable_prefix_bloom() {
+        let temp_dir = tempdir().unwrap();
+        let entries: Vec<Entry> = ["a/1", "a/2", "c/1", "c/2", "c/3", "e/1", "plain"]
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                Entry::new(
+                    key.as_bytes().to_vec(),
+                    Some(Bytes::from_static(b"v")),
+                    1,
+                    i as u64,
+                )
+            })
+            .collect();
+        let extractor = PrefixExtractor::Delimiter(b'/');
+        let options = SSTableWriteOptions {
+            prefix_extractor: Some(extractor),
+            ..Default::default()
+        };
+        let path = temp_dir.path().join("prefixed.sst");
+        let sstable = SSTable::from_entries_with_options(&path, &entries, &options).unwrap();
+        assert_eq!(sstable.header.prefix_extractor(), Some(extractor));
+
+        let check = |sstable: &SSTable| {
+            for prefix in ["a/", "c/", "e/"] {
+                assert!(
+                    sstable
+                        .might_contain_prefix(&extractor, prefix.as_bytes())
+                        .unwrap()
+                );
+            }
+            assert!(!sstable.might_contain_prefix(&extractor, b"b/").unwrap());
+            assert!(!sstable.might_contain_prefix(&extractor, b"d/").unwrap());
+            // Prefixes taken another way can't be ruled out
+            let fixed = PrefixExtractor::FixedLength(2);
+            assert!(sstable.might_contain_prefix(&fixed, b"b/").unwrap());
+            assert_eq!(sstable.get(b"c/2").unwrap(), Some(Bytes::from_static(b"v")));
+        };
+        check(&SSTable::open(&path).unwrap());
+        check(
+            &sstable
+                .rebuild_metadata(temp_dir.path().join("rebuilt.sst"))
+                .unwrap(),
+        );
+
+        // Tables written without an extractor may hold any prefix
+        let plain = SSTable::from_entries_with_options(
+            temp_dir.path().join("plain.sst"),
+            &entries,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(plain.header.prefix_extractor(), None);
+        assert!(plain.might_contain_prefix(&extractor, b"b/").unwrap());
+    }
+
+    #[test]
+    fn test_sstable_blocks() {
+        let temp_dir = tempdir().unwrap();
+        let large = Bytes::from(vec![b'v'; 3000]);
+        let entries: Vec<Entry> = (0..300u64)
+            .map(|i| {
+                let key = format!("sensors/greenhouse-7/{:05}", i * 2).into_bytes();
+                let value = match i {
+                    100 => Some(large.clone()),
+                    _ if i % 7 == 0 => None,
+                    _ => Some(Bytes::from(format!("reading {}", i))),
+                };
+                Entry::new(key, value, 1_000 + i, i)
+            })
+            .collect();
+        let options = SSTableWriteOptions {
+            block_size: 512,
+            ..Default::default()
+        };
+        let path = temp_dir.path().join("blocks.sst");
+        SSTable::from_entries_with_options(&path, &entries, &options).unwrap();
+
+        // The index lists blocks by first key, not every entry
+        let sstable = SSTable::open_lazy(&path).unwrap();
+        assert_eq!(sstable.first_key(), Some(entries[0].key.as_slice()));
+        assert_eq!(sstable.last_key(), Some(entries[299].key.as_slice()));
+        let blocks = sstable.block_handles().unwrap().to_vec();
+        assert!(blocks.len() > 10);
+        assert!(sstable.index_entries().unwrap().is_empty());
+        assert_eq!(
+            blocks.iter().map(|b| b.entry_count).sum::<u32>(),
+            entries.len() as u32
+        );
+        assert_eq!(sstable.tombstone_count(), Some(43));
+        let index_size = std::fs::metadata(&path).unwrap().len() - sstable.header.index_offset;
+        let per_entry_index_size: usize = entries.iter().map(|e| 20 + e.key.len()).sum();
+        assert!(index_size * 5 < per_entry_index_size as u64);
+        for block in &blocks {
+            // A block closes with the entry that takes it past the block size
+            assert!(block.size as usize <= 512 + large.len() + 64);
+            assert!(entries.iter().any(|e| e.key == block.first_key));
+        }
+
+        // Every key is found, including restart points and block boundaries,
+        // and keys between or around the stored ones are not
+        for entry in &entries {
+            assert_eq!(sstable.get_entry(&entry.key).unwrap().as_ref(), Some(entry));
+            let mut between = entry.key.clone();
+            between.push(b'!');
+            assert_eq!(sstable.get_entry(&between).unwrap(), None);
+        }
+        assert_eq!(sstable.get_entry(b"sensors/").unwrap(), None);
+        assert_eq!(sstable.get_entry(b"zzz").unwrap(), None);
+
+        let read: Vec<Entry> = sstable.iter().map(Result::unwrap).collect();
+        assert_eq!(read, entries);
+        for target in [0, 1, 16, 17, 99, 100, 101, 299] {
+            let mut iter = sstable.iter();
+            iter.seek(&entries[target].key);
+            assert_eq!(iter.next().unwrap().unwrap(), entries[target]);
+            let mut iter = sstable.iter().with_readahead(0);
+            let mut key = entries[target].key.clone();
+            key.push(0);
+            iter.seek(&key);
+            let next = iter.next().map(Result::unwrap);
+            assert_eq!(next.as_ref(), entries.get(target + 1));
+        }
+
+        // Partial reads of a value fetch only the requested bytes of it
+        let range = sstable
+            .get_entry_range(&entries[100].key, 1000, 10)
+            .unwrap()
+            .unwrap();
+        assert_eq!(range.value, Some(large.slice(1000..1010)));
+        let range = sstable.get_entry_range(&entries[7].key, 0, 10).unwrap();
+        assert!(range.unwrap().is_deletion());
+
+        assert_eq!(
+            sstable.verify().unwrap(),
+            std::fs::metadata(&path).unwrap().len()
+        );
+        let stored = sstable.stored_entries().unwrap();
+        assert_eq!(stored.len(), entries.len());
+        assert_eq!(
+            stored.iter().map(|(_, len)| *len as u64).sum::<u64>()
+                + blocks
+                    .iter()
+                    .map(|b| 4 * (b.entry_count as u64).div_ceil(16) + 4)
+                    .sum::<u64>(),
+            sstable.data_size()
+        );
+
+        // A restart offset that points into the middle of an entry is caught
+        let mut bytes = std::fs::read(&path).unwrap();
+        let block = &blocks[1];
+        let restarts_end =
+            (sstable.header.data_offset + block.offset + block.size as u64) as usize - 4;
+        let second_restart = restarts_end - 4 * (block.entry_count as usize).div_ceil(16) + 4;
+        bytes[second_restart] ^= 0x01;
+        // Re-stamp the checksum so the block itself has to catch it
+        let footer_at = bytes.len() - FOOTER_SIZE as usize;
+        let checksum = crc32fast::hash(&bytes[sstable.header.data_offset as usize..footer_at]);
+        bytes[footer_at..footer_at + 4].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let sstable = SSTable::open(&path).unwrap();
+        assert!(sstable.verify().is_err());
+        assert!(sstable.iter().any(|entry| entry.is_err()));
+    }
+
+    #[test]
+    fn test_sstable_rejects_malformed_files() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("valid.sst");
+        let memtable = MemTable::new(1024);
+        memtable.put(b"key1", b"value1").unwrap();
+        memtable.put(b"key2", b"value2").unwrap();
+        SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+        let valid = std::fs::read(&path).unwrap();
+
+        assert!(SSTableHeader::decode(&valid).is_ok());
+        assert!(SSTableHeader::decode(&valid[..20]).is_err());
+
+        // Truncated files either fail to open or still read back without panicking
+        let broken = temp_dir.path().join("broken.sst");
+        for len in (0..valid.len()).step_by(7) {
+            std::fs::write(&broken, &valid[..len]).unwrap();
+            if let Ok(sstable) = SSTable::open(&broken) {
+                let _ = sstable.iter().collect::<Vec<_>>();
+                let _ = sstable.get(b"key2");
+            }
+        }
+
+        // Oversized offsets and counts are rejected before anything is allocated from them
+        for (offset, field) in [(16, u64::MAX), (24, 1 << 40), (24, 8), (16, 1 << 40)] {
+            let mut bytes = valid.clone();
+            bytes[offset..offset + 8].copy_from_slice(&field.to_le_bytes());
+            std::fs::write(&broken, &bytes).unwrap();
+            assert!(
+                SSTable::open(&broken).is_err(),
+                "offset {} = {}",
+                offset,
+                field
+            );
+        }
+        let mut bytes = valid.clone();
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes()); // entry_count
+        std::fs::write(&broken, &bytes).unwrap();
+        assert!(SSTable::open(&broken).is_err());
+
+        assert!(
+            CompressionType::LZ4
+                .decompress(&[0xFF, 0xFF, 0xFF, 0x7F, 0])
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_bloom_filter() {
         let mut bloom = BloomFilter::new(100, 3);
@@ -837,4 +4556,211 @@ mod tests {
         // Should not contain random keys (with high probability)
         assert!(!bloom.might_contain(b"random_key"));
     }
+
+    #[test]
+    fn test_bloom_filter_encoding() {
+        let mut bloom = BloomFilter::new(1001, 7);
+        bloom.add(b"key1");
+        let encoded = bloom.encode();
+        assert_eq!(encoded.len(), bloom.encoded_len());
+        let decoded = BloomFilter::decode(&encoded).unwrap();
+        assert_eq!((decoded.bit_count(), decoded.hash_count()), (1001, 7));
+        assert!(decoded.might_contain(b"key1"));
+
+        // Parameters that disagree with the bits are rejected
+        assert!(BloomFilter::decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut no_hashes = encoded.clone();
+        no_hashes[12..16].fill(0);
+        assert!(BloomFilter::decode(&no_hashes).is_err());
+        let mut too_many_bits = encoded;
+        too_many_bits[4..12].copy_from_slice(&2000u64.to_le_bytes());
+        assert!(BloomFilter::decode(&too_many_bits).is_err());
+    }
+
+    #[test]
+    fn test_sstable_bloom_filter_parameters() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("bloom.sst");
+        let memtable = MemTable::new(1024 * 1024);
+        for i in 0..500 {
+            memtable
+                .put(format!("key{:04}", i).as_bytes(), b"v")
+                .unwrap();
+        }
+        SSTable::from_memtable(&path, &memtable, CompressionType::None).unwrap();
+
+        // The section records the filter's parameters ahead of its bits
+        let contents = std::fs::read(&path).unwrap();
+        let header = SSTableHeader::decode(&contents).unwrap();
+        let section = &contents[header.bloom_filter_offset as usize..header.data_offset as usize];
+        let stored = BloomFilter::decode(section).unwrap();
+        assert_eq!((stored.bit_count(), stored.hash_count()), (5000, 3));
+
+        // A filter built with other parameters is read back with them
+        let mut custom = BloomFilter::new(4999, 5);
+        for i in 0..500 {
+            custom.add(format!("key{:04}", i).as_bytes());
+        }
+        let mut patched = contents.clone();
+        patched[header.bloom_filter_offset as usize..header.data_offset as usize]
+            .copy_from_slice(&custom.encode());
+        std::fs::write(&path, &patched).unwrap();
+        let sstable = SSTable::open(&path).unwrap();
+        let sections = sstable.sections().unwrap();
+        let loaded = sections.bloom_filter.as_ref().unwrap();
+        assert_eq!((loaded.bit_count(), loaded.hash_count()), (4999, 5));
+        for i in 0..500 {
+            assert!(sections.might_contain(format!("key{:04}", i).as_bytes()));
+        }
+        assert!(sstable.verify().is_ok());
+
+        // A damaged prefix is corruption rather than a silently useless filter
+        let bloom_offset = header.bloom_filter_offset as usize;
+        patched[bloom_offset + 12..bloom_offset + 16].copy_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&path, &patched).unwrap();
+        assert!(matches!(
+            SSTable::open(&path),
+            Err(SSTableError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_sstable_builder() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("built.sst");
+        let options = SSTableWriteOptions {
+            compression: CompressionType::Zstd,
+            ..Default::default()
+        };
+        let mut builder = SSTableBuilder::new(&path, options);
+        for i in 0..100 {
+            builder
+                .put(format!("key{:04}", i).as_bytes(), &[b'v'; 200])
+                .unwrap();
+        }
+        builder.delete(b"key9999").unwrap();
+        assert!(matches!(
+            builder.put(b"key0050", b"v"),
+            Err(SSTableError::InvalidFormat(_))
+        ));
+        assert_eq!(builder.len(), 101);
+        builder.finish().unwrap();
+
+        // Written under a temporary name and renamed into place
+        let names: Vec<_> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("built.sst")]);
+
+        let sstable = SSTable::open(&path).unwrap();
+        assert!(sstable.verify().is_ok());
+        assert_eq!(sstable.entry_count(), 101);
+        assert_eq!(sstable.compression_type().unwrap(), CompressionType::Zstd);
+        assert_eq!(sstable.load_sequence_range().unwrap(), Some((0, 0)));
+        assert_eq!(sstable.get(b"key0042").unwrap().unwrap().len(), 200);
+        assert!(
+            sstable
+                .get_entry(b"key9999")
+                .unwrap()
+                .unwrap()
+                .value
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_sstable_builder_matches_buffered_writes() {
+        let temp_dir = tempdir().unwrap();
+        for format_version in [4, SSTABLE_FORMAT_VERSION] {
+            let options = SSTableWriteOptions {
+                compression: CompressionType::LZ4,
+                format_version,
+                block_size: 256,
+                prefix_extractor: Some(PrefixExtractor::Delimiter(b'/')),
+                ..Default::default()
+            };
+            let built_path = temp_dir.path().join(format!("built{}.sst", format_version));
+            let mut builder = SSTableBuilder::new(&built_path, options.clone());
+            let mut entries = Vec::new();
+            for i in 0..300 {
+                let key = format!("user{}/item{:04}", i / 50, i);
+                let value = format!("value{}", i).repeat(i % 7 + 1);
+                builder.put(key.as_bytes(), value.as_bytes()).unwrap();
+                entries.push(Entry::new(
+                    key.into_bytes(),
+                    Some(Bytes::from(value)),
+                    builder.timestamp,
+                    0,
+                ));
+            }
+            builder.finish().unwrap();
+
+            // Streaming the data section writes the same bytes as building from a slice
+            let buffered_path = temp_dir
+                .path()
+                .join(format!("buffered{}.sst", format_version));
+            SSTable::from_entries_with_options(&buffered_path, &entries, &options).unwrap();
+            assert_eq!(
+                std::fs::read(&built_path).unwrap(),
+                std::fs::read(&buffered_path).unwrap()
+            );
+        }
+
+        // An abandoned builder removes its scratch file
+        let mut builder =
+            SSTableBuilder::new(temp_dir.path().join("abandoned.sst"), Default::default());
+        builder.put(b"key", b"value").unwrap();
+        drop(builder);
+        assert!(!temp_dir.path().join("abandoned.sst.data.tmp").exists());
+    }
+
+    #[test]
+    fn test_inspect() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("inspect.sst");
+        let mut builder = SSTableBuilder::new(&path, SSTableWriteOptions::default());
+        builder.put(b"alpha", &[b'a'; 100]).unwrap();
+        builder.delete(b"beta").unwrap();
+        builder.put(b"gamma", b"\x00g").unwrap();
+        builder.finish().unwrap();
+
+        let report = inspect(&path, true).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.header.entry_count, 3);
+        assert_eq!(report.checksum_valid, Some(true));
+        assert_eq!(report.index.as_ref().unwrap().blocks.len(), 1);
+        assert_eq!(
+            report.bloom.as_ref().unwrap().as_ref().unwrap().hash_count,
+            3
+        );
+        assert_eq!(report.entries.as_ref().unwrap().as_ref().unwrap().len(), 3);
+        let text = report.to_string();
+        assert!(text.contains("(valid)"));
+        assert!(text.contains("Verify: OK"));
+        assert!(text.contains("beta seq=0"));
+        assert!(text.contains("tombstone"));
+        assert!(text.contains("gamma seq=0 ts="));
+        assert!(text.contains("=> \\x00g"));
+        assert!(text.contains("... (100 bytes)"));
+
+        // Damage in the data section is reported next to what still reads
+        let mut contents = std::fs::read(&path).unwrap();
+        let data_offset = report.header.data_offset as usize;
+        contents[data_offset + 10] ^= 0xFF;
+        std::fs::write(&path, &contents).unwrap();
+        let report = inspect(&path, true).unwrap();
+        assert!(!report.is_intact());
+        assert_eq!(report.checksum_valid, Some(false));
+        assert!(report.index.is_ok());
+        assert!(report.verify.is_err());
+        assert!(report.entries.as_ref().unwrap().is_err());
+        let text = report.to_string();
+        assert!(text.contains("(MISMATCH)"));
+        assert!(text.contains("Verify: FAILED"));
+
+        // Without a header there is nothing to report
+        std::fs::write(&path, b"not a table").unwrap();
+        assert!(inspect(&path, false).is_err());
+    }
 }
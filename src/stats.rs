@@ -0,0 +1,121 @@
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::engine::{EngineStats, MaintenanceStats};
+
+/// Directory in the data directory holding periodic stats snapshots
+pub const STATS_DIR: &str = "stats";
+
+/// One stats snapshot, as written to [`STATS_DIR`] by maintenance passes
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot<'a> {
+    /// Unix time in milliseconds when the snapshot was taken
+    pub taken_at_ms: u64,
+    /// Last sequence number assigned
+    pub sequence_number: u64,
+    pub stats: &'a EngineStats,
+    /// Outcome of the maintenance pass that took the snapshot
+    pub maintenance: Option<&'a MaintenanceStats>,
+}
+
+/// Write `snapshot` as `stats-<ms>.json` in `dir`, then delete the oldest
+/// snapshots so at most `keep` remain
+///
+/// The file is written under a temporary name and renamed, so a crash never
+/// leaves a truncated snapshot behind.
+pub fn write_snapshot(
+    dir: &Path,
+    snapshot: &StatsSnapshot<'_>,
+    keep: usize,
+) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    // Names must sort after every existing snapshot, even if the clock went back
+    let newest = list_snapshots(dir)?.last().and_then(|path| {
+        let stem = path.file_stem()?.to_str()?;
+        stem.strip_prefix("stats-")?.parse::<u64>().ok()
+    });
+    let timestamp = newest.map_or(snapshot.taken_at_ms, |newest| {
+        snapshot.taken_at_ms.max(newest + 1)
+    });
+    let path = dir.join(format!("stats-{:013}.json", timestamp));
+
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(snapshot)?)?;
+    std::fs::rename(&tmp_path, &path)?;
+
+    let snapshots = list_snapshots(dir)?;
+    for old in &snapshots[..snapshots.len().saturating_sub(keep.max(1))] {
+        std::fs::remove_file(old)?;
+    }
+    Ok(path)
+}
+
+/// Snapshot files in `dir`, oldest first
+pub fn list_snapshots(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with("stats-") && name.ends_with(".json") {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::OpenFiles;
+    use crate::io_backend::IoBackend;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_snapshots_rotate() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().join(STATS_DIR);
+        let stats = EngineStats {
+            memtable_size: 128,
+            sstable_count: 3,
+            data_dir: temp_dir.path().to_path_buf(),
+            io_backend: IoBackend::Portable,
+            hot_keys: Vec::new(),
+            quarantined_sstables: Vec::new(),
+            open_files: OpenFiles {
+                wal: 1,
+                sstables: 3,
+                value_log: 0,
+                process: None,
+                limit: Some(1024),
+            },
+            compactions_running: 0,
+            immutable_memtables: 0,
+            block_cache: Default::default(),
+        };
+
+        let mut written = Vec::new();
+        for i in 0..5 {
+            let snapshot = StatsSnapshot {
+                taken_at_ms: 1_000,
+                sequence_number: i,
+                stats: &stats,
+                maintenance: None,
+            };
+            written.push(write_snapshot(&dir, &snapshot, 3).unwrap());
+        }
+        assert_eq!(list_snapshots(&dir).unwrap(), written[2..]);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&written[4]).unwrap()).unwrap();
+        assert_eq!(json["sequence_number"], 4);
+        assert_eq!(json["stats"]["sstable_count"], 3);
+        assert_eq!(json["stats"]["open_files"]["limit"], 1024);
+        assert!(json["maintenance"].is_null());
+    }
+}
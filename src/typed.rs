@@ -0,0 +1,185 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use thiserror::Error;
+
+use crate::engine::{Engine, EngineError};
+use crate::keys::{self, KeyError};
+
+/// Errors that can occur during typed operations
+#[derive(Error, Debug)]
+pub enum TypedError {
+    #[error("Engine error: {0}")]
+    Engine(#[from] EngineError),
+    #[error("Key encoding error: {0}")]
+    Key(#[from] KeyError),
+    #[error("Value codec error: {0}")]
+    Codec(String),
+}
+
+/// Result type for typed operations
+pub type TypedResult<T> = Result<T, TypedError>;
+
+/// Serialization format used for values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueCodec {
+    /// Compact binary encoding (bincode)
+    #[default]
+    Bincode,
+    /// Self-describing binary encoding (CBOR)
+    Cbor,
+    /// Human-readable encoding (JSON)
+    Json,
+}
+
+impl ValueCodec {
+    /// Serialize a value
+    pub fn encode<V: Serialize + ?Sized>(self, value: &V) -> TypedResult<Vec<u8>> {
+        match self {
+            Self::Bincode => {
+                bincode::serialize(value).map_err(|e| TypedError::Codec(e.to_string()))
+            }
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf)
+                    .map_err(|e| TypedError::Codec(e.to_string()))?;
+                Ok(buf)
+            }
+            Self::Json => serde_json::to_vec(value).map_err(|e| TypedError::Codec(e.to_string())),
+        }
+    }
+
+    /// Deserialize a value
+    pub fn decode<V: DeserializeOwned>(self, bytes: &[u8]) -> TypedResult<V> {
+        match self {
+            Self::Bincode => {
+                bincode::deserialize(bytes).map_err(|e| TypedError::Codec(e.to_string()))
+            }
+            Self::Cbor => {
+                ciborium::from_reader(bytes).map_err(|e| TypedError::Codec(e.to_string()))
+            }
+            Self::Json => {
+                serde_json::from_slice(bytes).map_err(|e| TypedError::Codec(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Typed key-value store layered over [`Engine`]
+///
+/// Keys are encoded with [`keys::to_key`] so they keep their natural order on
+/// disk; values go through the configured [`ValueCodec`]. The raw byte API stays
+/// available through [`TypedDb::engine_mut`].
+pub struct TypedDb<K, V> {
+    engine: Engine,
+    codec: ValueCodec,
+    _types: PhantomData<fn(K, V)>,
+}
+
+impl<K, V> TypedDb<K, V>
+where
+    K: Serialize,
+    V: Serialize + DeserializeOwned,
+{
+    /// Wrap an engine, encoding values with `codec`
+    pub fn new(engine: Engine, codec: ValueCodec) -> Self {
+        Self {
+            engine,
+            codec,
+            _types: PhantomData,
+        }
+    }
+
+    /// Put a typed key-value pair, returning its sequence number
+    pub async fn put(&mut self, key: &K, value: &V) -> TypedResult<u64> {
+        let key = keys::to_key(key)?;
+        let value = self.codec.encode(value)?;
+        Ok(self.engine.put(&key, &value).await?)
+    }
+
+    /// Get the value for a typed key
+    pub async fn get(&self, key: &K) -> TypedResult<Option<V>> {
+        let key = keys::to_key(key)?;
+        match self.engine.get(&key).await? {
+            Some(bytes) => Ok(Some(self.codec.decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a typed key, returning the sequence number of the tombstone
+    pub async fn delete(&mut self, key: &K) -> TypedResult<u64> {
+        let key = keys::to_key(key)?;
+        Ok(self.engine.delete(&key).await?)
+    }
+
+    /// Get the value codec
+    pub fn codec(&self) -> ValueCodec {
+        self.codec
+    }
+
+    /// Borrow the underlying engine
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Mutably borrow the underlying engine for raw byte operations
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Unwrap the underlying engine
+    pub fn into_inner(self) -> Engine {
+        self.engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tempfile::tempdir;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        celsius: f64,
+        label: String,
+        tags: Vec<String>,
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            celsius: 21.5,
+            label: "kitchen".to_string(),
+            tags: vec!["indoor".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_value_codecs_roundtrip() {
+        for codec in [ValueCodec::Bincode, ValueCodec::Cbor, ValueCodec::Json] {
+            let bytes = codec.encode(&reading()).unwrap();
+            assert_eq!(codec.decode::<Reading>(&bytes).unwrap(), reading());
+        }
+
+        assert!(ValueCodec::Json.decode::<Reading>(b"not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_typed_put_get_delete() {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        let mut db: TypedDb<(String, u64), Reading> = TypedDb::new(engine, ValueCodec::Cbor);
+
+        let key = ("sensor-1".to_string(), 42);
+        db.put(&key, &reading()).await.unwrap();
+        assert_eq!(db.get(&key).await.unwrap(), Some(reading()));
+        assert_eq!(db.get(&("sensor-1".to_string(), 43)).await.unwrap(), None);
+
+        // The raw API sees the order-preserving key encoding
+        let raw_key = keys::KeyEncoder::new().str("sensor-1").u64(42).finish();
+        assert!(db.engine().get(&raw_key).await.unwrap().is_some());
+
+        db.delete(&key).await.unwrap();
+        assert_eq!(db.get(&key).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,317 @@
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+
+use crate::memtable::MemTable;
+use crate::sstable::SSTable;
+
+/// Immutable view of the MemTable and SSTables that make up the database
+///
+/// Flushes and other changes to the set of tables build a new version and
+/// install it in one step, so a reader that loads the current version sees a
+/// consistent set of tables for as long as it holds it.
+#[derive(Clone)]
+pub struct Version {
+    /// MemTable receiving writes when this version was installed
+    pub memtable: Arc<MemTable>,
+    /// Full MemTables waiting to be flushed, newest first
+    pub immutables: Vec<Arc<MemTable>>,
+    /// SSTables per level; level 0 holds flushed tables, newest first, and
+    /// deeper levels hold non-overlapping tables sorted by first key
+    pub levels: Vec<Vec<Arc<SSTable>>>,
+}
+
+impl Version {
+    /// Version with `level_count` empty levels
+    pub fn new(memtable: Arc<MemTable>, level_count: usize) -> Self {
+        Self {
+            memtable,
+            immutables: Vec::new(),
+            levels: vec![Vec::new(); level_count.max(1)],
+        }
+    }
+
+    /// Active and immutable MemTables in lookup order, newest first
+    pub fn memtables(&self) -> impl Iterator<Item = &Arc<MemTable>> {
+        std::iter::once(&self.memtable).chain(&self.immutables)
+    }
+
+    /// All SSTables in lookup order: level by level, newest first within a level
+    pub fn sstables(&self) -> impl Iterator<Item = &Arc<SSTable>> {
+        self.levels.iter().flatten()
+    }
+
+    /// Total number of SSTables across levels
+    pub fn sstable_count(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    /// Newest sequence number any SSTable is known to hold
+    ///
+    /// Tables whose range hasn't been read yet are left out.
+    pub fn max_sequence(&self) -> Option<u64> {
+        self.sstables()
+            .filter_map(|t| t.sequence_range())
+            .map(|(_, max)| max)
+            .max()
+    }
+
+    /// SSTables that may hold `key`, in lookup order
+    ///
+    /// Every overlapping level 0 table is returned newest first; deeper levels
+    /// contribute at most one table each, found by binary search on key ranges.
+    pub fn sstables_for_key<'a>(&'a self, key: &'a [u8]) -> impl Iterator<Item = &'a Arc<SSTable>> {
+        let covers = move |sstable: &&Arc<SSTable>| {
+            sstable.first_key().is_some_and(|first| first <= key)
+                && sstable.last_key().is_some_and(|last| last >= key)
+        };
+        let (level0, deeper) = self.levels.split_first().expect("at least one level");
+        let level0 = level0.iter().filter(covers);
+        let deeper = deeper.iter().filter_map(move |level| {
+            let i = level.partition_point(|t| t.last_key().is_some_and(|last| last < key));
+            level.get(i).filter(covers)
+        });
+        level0.chain(deeper)
+    }
+
+    /// Copy of this version with a different MemTable
+    pub fn with_memtable(&self, memtable: Arc<MemTable>) -> Self {
+        Self {
+            memtable,
+            immutables: self.immutables.clone(),
+            levels: self.levels.clone(),
+        }
+    }
+
+    /// Copy of this version with the active MemTable frozen as the newest
+    /// immutable one and `memtable` taking writes in its place
+    pub fn with_frozen_memtable(&self, memtable: Arc<MemTable>) -> Self {
+        let mut version = self.with_memtable(memtable);
+        version.immutables.insert(0, self.memtable.clone());
+        version
+    }
+
+    /// Copy of this version with the flushed immutable MemTable `memtable`
+    /// replaced by `sstables` in level 0
+    pub fn with_flushed_memtable(
+        &self,
+        memtable: &Arc<MemTable>,
+        sstables: &[Arc<SSTable>],
+    ) -> Self {
+        let mut version = self.clone();
+        version.immutables.retain(|m| !Arc::ptr_eq(m, memtable));
+        for sstable in sstables {
+            version = version.with_new_sstable(0, sstable.clone());
+        }
+        version
+    }
+
+    /// Copy of this version with `sstable` added to `level`
+    ///
+    /// Level 0 takes it as its newest table; deeper levels keep their tables
+    /// ordered by first key.
+    pub fn with_new_sstable(&self, level: usize, sstable: Arc<SSTable>) -> Self {
+        let mut version = self.clone();
+        if version.levels.len() <= level {
+            version.levels.resize_with(level + 1, Vec::new);
+        }
+        let tables = &mut version.levels[level];
+        let position = if level == 0 {
+            0
+        } else {
+            tables.partition_point(|t| t.first_key() < sstable.first_key())
+        };
+        tables.insert(position, sstable);
+        version
+    }
+
+    /// Copy of this version with compaction `inputs` replaced by `outputs` in `level`
+    ///
+    /// Level 0 takes the outputs where the newest input sat, so tables newer
+    /// and older than the inputs stay on either side; deeper levels keep their
+    /// tables ordered by first key.
+    pub fn with_compacted_sstables(
+        &self,
+        inputs: &[Arc<SSTable>],
+        level: usize,
+        outputs: &[Arc<SSTable>],
+    ) -> Self {
+        let is_input = |t: &Arc<SSTable>| inputs.iter().any(|i| Arc::ptr_eq(i, t));
+        let mut version = self.retain_sstables(|t| !is_input(t));
+        if level == 0 {
+            let position = self.levels[0].iter().position(is_input).unwrap_or(0);
+            version.levels[0].splice(position..position, outputs.iter().cloned());
+            return version;
+        }
+        for sstable in outputs {
+            version = version.with_new_sstable(level, sstable.clone());
+        }
+        version
+    }
+
+    /// Copy of this version with `old` swapped for `new` in the same position
+    pub fn with_replaced_sstable(&self, old: &Arc<SSTable>, new: Arc<SSTable>) -> Self {
+        let mut version = self.clone();
+        if let Some(slot) = version
+            .levels
+            .iter_mut()
+            .flatten()
+            .find(|t| Arc::ptr_eq(t, old))
+        {
+            *slot = new;
+        }
+        version
+    }
+
+    /// Copy of this version keeping only the SSTables for which `keep` returns true
+    pub fn retain_sstables<F>(&self, mut keep: F) -> Self
+    where
+        F: FnMut(&Arc<SSTable>) -> bool,
+    {
+        let mut version = self.clone();
+        for level in &mut version.levels {
+            level.retain(&mut keep);
+        }
+        version
+    }
+}
+
+/// Holder of the current [`Version`]
+///
+/// Readers take the current version with a single atomic load; writers
+/// replace it wholesale.
+pub struct VersionSet {
+    current: ArcSwap<Version>,
+}
+
+impl VersionSet {
+    /// Start with `version` as the current version
+    pub fn new(version: Version) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(version),
+        }
+    }
+
+    /// The current version
+    pub fn current(&self) -> Arc<Version> {
+        self.current.load_full()
+    }
+
+    /// Make `version` the current version
+    pub fn install(&self, version: Version) {
+        self.current.store(Arc::new(version));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sstable::CompressionType;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_installed_versions_do_not_affect_readers() {
+        let temp_dir = tempdir().unwrap();
+        let memtable = Arc::new(MemTable::new(1024));
+        memtable.put(b"key", b"value").unwrap();
+        let sstable = Arc::new(
+            SSTable::from_memtable(
+                temp_dir.path().join("sstable_1.sst"),
+                &memtable,
+                CompressionType::None,
+            )
+            .unwrap(),
+        );
+
+        let versions = VersionSet::new(Version::new(memtable.clone(), 1));
+        let before = versions.current();
+
+        let next = Arc::new(MemTable::new(1024));
+        versions.install(
+            before
+                .with_new_sstable(0, sstable)
+                .with_memtable(next.clone()),
+        );
+
+        // The old version still has its own MemTable and no SSTables
+        assert_eq!(before.sstable_count(), 0);
+        assert!(Arc::ptr_eq(&before.memtable, &memtable));
+
+        let after = versions.current();
+        assert_eq!(after.sstable_count(), 1);
+        assert!(Arc::ptr_eq(&after.memtable, &next));
+        assert_eq!(after.retain_sstables(|_| false).sstable_count(), 0);
+    }
+
+    #[test]
+    fn test_frozen_memtables_are_read_until_flushed() {
+        let temp_dir = tempdir().unwrap();
+        let first = Arc::new(MemTable::new(1024));
+        first.put(b"key", b"first").unwrap();
+        let second = Arc::new(MemTable::new(1024));
+        second.put(b"key", b"second").unwrap();
+        let active = Arc::new(MemTable::new(1024));
+
+        let version = Version::new(first.clone(), 1)
+            .with_frozen_memtable(second.clone())
+            .with_frozen_memtable(active.clone());
+        let memtables: Vec<_> = version.memtables().collect();
+        assert_eq!(memtables.len(), 3);
+        assert!(Arc::ptr_eq(memtables[0], &active));
+        assert!(Arc::ptr_eq(memtables[1], &second));
+        assert!(Arc::ptr_eq(memtables[2], &first));
+
+        let sstable = Arc::new(
+            SSTable::from_memtable(
+                temp_dir.path().join("sstable_1.sst"),
+                &first,
+                CompressionType::None,
+            )
+            .unwrap(),
+        );
+        let flushed = version.with_flushed_memtable(&first, &[sstable]);
+        assert_eq!(flushed.immutables.len(), 1);
+        assert!(Arc::ptr_eq(&flushed.immutables[0], &second));
+        assert_eq!(flushed.sstable_count(), 1);
+    }
+
+    #[test]
+    fn test_sstables_for_key_probes_levels_in_order() {
+        let temp_dir = tempdir().unwrap();
+        let table = |name: &str, keys: &[&[u8]]| {
+            let memtable = MemTable::new(1024);
+            for key in keys {
+                memtable.put(key, name.as_bytes()).unwrap();
+            }
+            Arc::new(
+                SSTable::from_memtable(
+                    temp_dir.path().join(format!("{}.sst", name)),
+                    &memtable,
+                    CompressionType::None,
+                )
+                .unwrap(),
+            )
+        };
+
+        let version = Version::new(Arc::new(MemTable::new(1024)), 3)
+            .with_new_sstable(1, table("l1_c", &[b"m", b"p"]))
+            .with_new_sstable(1, table("l1_a", &[b"a", b"c"]))
+            .with_new_sstable(1, table("l1_b", &[b"d", b"k"]))
+            .with_new_sstable(2, table("l2", &[b"a", b"z"]))
+            .with_new_sstable(0, table("l0_old", &[b"b", b"e"]))
+            .with_new_sstable(0, table("l0_new", &[b"e", b"f"]));
+
+        let names = |key: &[u8]| -> Vec<String> {
+            version
+                .sstables_for_key(key)
+                .map(|t| t.path().file_stem().unwrap().to_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(names(b"e"), ["l0_new", "l0_old", "l1_b", "l2"]);
+        assert_eq!(names(b"b"), ["l0_old", "l1_a", "l2"]);
+        assert_eq!(names(b"l"), ["l2"]);
+        assert_eq!(names(b"p"), ["l1_c", "l2"]);
+
+        let level1: Vec<_> = version.levels[1].iter().map(|t| t.first_key()).collect();
+        assert_eq!(level1, [Some(&b"a"[..]), Some(&b"d"[..]), Some(&b"m"[..])]);
+    }
+}
@@ -1,9 +1,15 @@
+use bytes::Bytes;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, trace, warn};
 
+use crate::io_backend::IoBackend;
 use crate::memtable::{Entry, MemTable, MemTableError};
 
 /// Errors that can occur during WAL operations
@@ -19,28 +25,109 @@ pub enum WALError {
     MemTable(#[from] MemTableError),
     #[error("WAL file not found: {0}")]
     FileNotFound(String),
+    #[error("WAL sync task stopped before the write was durable")]
+    SyncStopped,
+    #[error("Unsupported WAL format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid sync policy: {0}")]
+    InvalidSyncPolicy(String),
 }
 
 /// Result type for WAL operations
 pub type WALResult<T> = Result<T, WALError>;
 
+/// High bit of the encoded value length, set when the value is a blob pointer
+const INDIRECT_VALUE_FLAG: u32 = 1 << 31;
+
+/// WAL format version written by this build
+///
+/// - v1: no file header; a record is key_len, value_len, timestamp and seq, then key and value
+/// - v2: files start with a header; records add a type/flags byte after seq
+pub const WAL_FORMAT_VERSION: u32 = 2;
+
+/// Magic bytes opening a WAL file with a header
+const WAL_MAGIC: &[u8; 4] = b"RWAL";
+
+/// Size of the file header: magic (4) + format version (4)
+pub const WAL_HEADER_SIZE: usize = 8;
+
+/// Size of the fields shared by every format: key_len (4) + value_len (4) + timestamp (8) + seq (8)
+const FIXED_HEADER_SIZE: usize = 24;
+
+/// Size of a v2 record header: the fixed fields plus the type/flags byte
+const RECORD_HEADER_SIZE: usize = FIXED_HEADER_SIZE + 1;
+
+/// Low bits of the type/flags byte hold the record type; the high bits are reserved flags
+const RECORD_TYPE_MASK: u8 = 0x0F;
+
+/// Kind of operation a WAL record logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    Put,
+    Delete,
+    RangeDelete,
+    Merge,
+    BatchBegin,
+    BatchEnd,
+    /// A type this build doesn't know, written by a newer one
+    Unknown(u8),
+}
+
+impl RecordType {
+    /// Decode the type from a type/flags byte, ignoring flags
+    pub fn from_byte(byte: u8) -> Self {
+        match byte & RECORD_TYPE_MASK {
+            1 => Self::Put,
+            2 => Self::Delete,
+            3 => Self::RangeDelete,
+            4 => Self::Merge,
+            5 => Self::BatchBegin,
+            6 => Self::BatchEnd,
+            other => Self::Unknown(other),
+        }
+    }
+
+    /// Type/flags byte for this type, with no flags set
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Put => 1,
+            Self::Delete => 2,
+            Self::RangeDelete => 3,
+            Self::Merge => 4,
+            Self::BatchBegin => 5,
+            Self::BatchEnd => 6,
+            Self::Unknown(other) => other & RECORD_TYPE_MASK,
+        }
+    }
+}
+
 /// Represents a single WAL record
 #[derive(Debug, Clone, PartialEq)]
 pub struct WALRecord {
+    pub record_type: RecordType,
     pub key: Vec<u8>,
     pub value: Option<Vec<u8>>, // None for deletions (tombstones)
     pub timestamp: u64,
     pub sequence_number: u64,
+    /// The value is an encoded blob pointer rather than the value itself
+    pub indirect: bool,
 }
 
 impl WALRecord {
-    /// Create a new WAL record
+    /// Create a new put record, or a delete record when `value` is None
     pub fn new(key: Vec<u8>, value: Option<Vec<u8>>, timestamp: u64, sequence_number: u64) -> Self {
+        let record_type = if value.is_some() {
+            RecordType::Put
+        } else {
+            RecordType::Delete
+        };
         Self {
+            record_type,
             key,
             value,
             timestamp,
             sequence_number,
+            indirect: false,
         }
     }
 
@@ -49,22 +136,130 @@ impl WALRecord {
         self.value.is_none()
     }
 
+    /// Encode the record in the current format: header (key_len, value_len,
+    /// timestamp, seq, type/flags) followed by key and value
+    ///
+    /// Indirect records set the high bit of `value_len`.
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_version(WAL_FORMAT_VERSION)
+    }
+
+    /// Encode the record in the given WAL format version
+    fn encode_version(&self, version: u32) -> Vec<u8> {
+        let key_len = self.key.len() as u32;
+        let value_len = self.value.as_ref().map_or(0, |v| v.len()) as u32;
+        let encoded_value_len = if self.indirect {
+            value_len | INDIRECT_VALUE_FLAG
+        } else {
+            value_len
+        };
+
+        let mut buf = Vec::with_capacity(RECORD_HEADER_SIZE + self.key.len() + value_len as usize);
+        buf.extend_from_slice(&key_len.to_le_bytes());
+        buf.extend_from_slice(&encoded_value_len.to_le_bytes());
+        buf.extend_from_slice(&self.timestamp.to_le_bytes());
+        buf.extend_from_slice(&self.sequence_number.to_le_bytes());
+        if version >= 2 {
+            buf.push(self.record_type.to_byte());
+        }
+        buf.extend_from_slice(&self.key);
+        if let Some(value) = &self.value {
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+
+    /// Build a record that logs a MemTable entry
+    pub fn from_entry(entry: &Entry) -> Self {
+        let mut record = Self::new(
+            entry.key.clone(),
+            entry.value.as_ref().map(|v| v.to_vec()),
+            entry.timestamp,
+            entry.sequence_number,
+        );
+        record.indirect = entry.indirect;
+        if entry.merge {
+            record.record_type = RecordType::Merge;
+        }
+        record
+    }
+
     /// Convert to a MemTable Entry
     pub fn to_entry(&self) -> Entry {
-        Entry::new(
+        let mut entry = Entry::new(
             self.key.clone(),
-            self.value.clone(),
+            self.value.clone().map(Bytes::from),
             self.timestamp,
             self.sequence_number,
-        )
+        );
+        entry.indirect = self.indirect;
+        entry.merge = self.record_type == RecordType::Merge;
+        entry
+    }
+
+    /// Whether this is a merge record that a flush at `flushed_sequence` covered
+    pub fn is_flushed_merge(&self, flushed_sequence: u64) -> bool {
+        self.record_type == RecordType::Merge && self.sequence_number <= flushed_sequence
+    }
+}
+
+/// Report recovery progress at least once per this many bytes read
+const RECOVERY_PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Progress of a WAL replay, accumulated across every file being replayed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryProgress {
+    /// Bytes read so far
+    pub bytes_processed: u64,
+    /// Total bytes to read
+    pub bytes_total: u64,
+    /// Records applied to the MemTable
+    pub records_applied: u64,
+    /// Corrupted records skipped
+    pub records_corrupted: u64,
+    /// Records of types this build doesn't apply
+    pub records_skipped: u64,
+    /// Records replayed after a newer version of their key, which then
+    /// replaced it in the MemTable
+    pub records_out_of_order: u64,
+    /// Write batches left out because they were incomplete or damaged
+    pub batches_dropped: u64,
+}
+
+/// Records of a batch read since its begin marker
+struct PendingBatch {
+    expected: Option<u32>,
+    records: Vec<WALRecord>,
+}
+
+impl PendingBatch {
+    fn begin(marker: &WALRecord) -> Self {
+        let expected = marker
+            .value
+            .as_deref()
+            .and_then(|count| count.try_into().ok())
+            .map(u32::from_le_bytes);
+        Self {
+            expected,
+            records: Vec::new(),
+        }
+    }
+
+    /// The batch's records, or None if some are missing
+    fn finish(self) -> Option<Vec<WALRecord>> {
+        (self.expected == Some(self.records.len() as u32)).then_some(self.records)
     }
 }
 
 /// Write-Ahead Log implementation for durability
 pub struct WAL {
-    file: BufWriter<File>,
+    file: File,
     path: std::path::PathBuf,
     sequence_number: u64,
+    /// Format of this file; records are appended in the format the file started with
+    format_version: u32,
+    /// Current file length, tracked so writers can check it without a syscall
+    logged_bytes: u64,
 }
 
 impl WAL {
@@ -79,10 +274,21 @@ impl WAL {
             .open(&path)
             .map_err(WALError::Io)?;
 
+        // New files get a header; files without one predate it and stay in v1
+        let format_version = if file.metadata()?.len() == 0 {
+            IoBackend::current().append(&file, &Self::file_header())?;
+            WAL_FORMAT_VERSION
+        } else {
+            Self::read_format_version(&file)?
+        };
+        let logged_bytes = file.metadata()?.len();
+
         let mut wal = Self {
-            file: BufWriter::new(file),
+            file,
             path,
             sequence_number: 0,
+            format_version,
+            logged_bytes,
         };
 
         // Recover sequence number from existing file
@@ -97,32 +303,30 @@ impl WAL {
 
     /// Write a record to the WAL
     pub fn write_record(&mut self, record: &WALRecord) -> WALResult<()> {
-        // Ensure sequence number is correct
-        if record.sequence_number != self.sequence_number + 1 {
+        // Sequence numbers are assigned by the caller and must only move forward
+        if record.sequence_number <= self.sequence_number {
             return Err(WALError::InvalidRecord(format!(
-                "Expected sequence number {}, got {}",
-                self.sequence_number + 1,
-                record.sequence_number
+                "Sequence number {} does not follow {}",
+                record.sequence_number, self.sequence_number
             )));
         }
 
-        // Write record header: key_len (4 bytes) + value_len (4 bytes) + timestamp (8 bytes) + seq (8 bytes)
-        let key_len = record.key.len() as u32;
-        let value_len = record.value.as_ref().map_or(0, |v| v.len()) as u32;
-
-        self.file.write_all(&key_len.to_le_bytes())?;
-        self.file.write_all(&value_len.to_le_bytes())?;
-        self.file.write_all(&record.timestamp.to_le_bytes())?;
-        self.file.write_all(&record.sequence_number.to_le_bytes())?;
-
-        // Write key and value data
-        self.file.write_all(&record.key)?;
-        if let Some(value) = &record.value {
-            self.file.write_all(value)?;
+        // Version 1 has no type byte, so it can only log puts and deletes
+        if self.format_version < 2
+            && !matches!(record.record_type, RecordType::Put | RecordType::Delete)
+        {
+            return Err(WALError::InvalidRecord(format!(
+                "{:?} records need WAL format v2",
+                record.record_type
+            )));
         }
 
-        // Flush to ensure durability
-        self.file.flush()?;
+        fail::fail_point!("wal::append", |_| Err(injected_failure("wal::append")));
+
+        // Append the whole record in a single write so it reaches the OS unbuffered
+        let encoded = record.encode_version(self.format_version);
+        IoBackend::current().append(&self.file, &encoded)?;
+        self.logged_bytes += encoded.len() as u64;
 
         // Update sequence number
         self.sequence_number = record.sequence_number;
@@ -137,6 +341,72 @@ impl WAL {
         Ok(())
     }
 
+    /// Append `records` as a batch that recovery applies all or nothing
+    ///
+    /// The records are framed by [`RecordType::BatchBegin`] and
+    /// [`RecordType::BatchEnd`] markers holding the record count and are
+    /// written with a single append. Replay drops a batch whose end marker
+    /// never made it to disk.
+    pub fn write_batch(&mut self, records: &[WALRecord]) -> WALResult<()> {
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            return Ok(());
+        };
+        if self.format_version < 2 {
+            return Err(WALError::InvalidRecord(
+                "Batches need WAL format v2".to_string(),
+            ));
+        }
+        let mut previous = self.sequence_number;
+        for record in records {
+            if record.sequence_number <= previous {
+                return Err(WALError::InvalidRecord(format!(
+                    "Sequence number {} does not follow {}",
+                    record.sequence_number, previous
+                )));
+            }
+            if !matches!(
+                record.record_type,
+                RecordType::Put | RecordType::Delete | RecordType::Merge
+            ) {
+                return Err(WALError::InvalidRecord(format!(
+                    "{:?} records cannot be batched",
+                    record.record_type
+                )));
+            }
+            previous = record.sequence_number;
+        }
+
+        fail::fail_point!("wal::append", |_| Err(injected_failure("wal::append")));
+
+        let count = (records.len() as u32).to_le_bytes().to_vec();
+        let marker = |record_type, sequence_number| {
+            let mut marker = WALRecord::new(
+                Vec::new(),
+                Some(count.clone()),
+                first.timestamp,
+                sequence_number,
+            );
+            marker.record_type = record_type;
+            marker.encode_version(self.format_version)
+        };
+        let mut encoded = marker(RecordType::BatchBegin, first.sequence_number);
+        for record in records {
+            encoded.extend(record.encode_version(self.format_version));
+        }
+        encoded.extend(marker(RecordType::BatchEnd, last.sequence_number));
+        IoBackend::current().append(&self.file, &encoded)?;
+        self.logged_bytes += encoded.len() as u64;
+        self.sequence_number = last.sequence_number;
+
+        trace!(
+            "WAL batch write: {} records, seq={}..={}",
+            records.len(),
+            first.sequence_number,
+            last.sequence_number
+        );
+        Ok(())
+    }
+
     /// Write a put operation to the WAL
     pub fn put(&mut self, key: &[u8], value: &[u8], timestamp: u64) -> WALResult<()> {
         let record = WALRecord::new(
@@ -148,6 +418,18 @@ impl WAL {
         self.write_record(&record)
     }
 
+    /// Write a put whose value is an encoded blob pointer
+    pub fn put_indirect(&mut self, key: &[u8], pointer: &[u8], timestamp: u64) -> WALResult<()> {
+        let mut record = WALRecord::new(
+            key.to_vec(),
+            Some(pointer.to_vec()),
+            timestamp,
+            self.sequence_number + 1,
+        );
+        record.indirect = true;
+        self.write_record(&record)
+    }
+
     /// Write a delete operation to the WAL
     pub fn delete(&mut self, key: &[u8], timestamp: u64) -> WALResult<()> {
         let record = WALRecord::new(
@@ -159,44 +441,256 @@ impl WAL {
         self.write_record(&record)
     }
 
+    /// Path of the WAL file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// Get the current sequence number
     pub fn sequence_number(&self) -> u64 {
         self.sequence_number
     }
 
-    /// Recover all records from the WAL into a MemTable
-    pub fn recover(&self, memtable: &MemTable) -> WALResult<()> {
-        info!("Starting WAL recovery for {:?}", self.path);
+    /// Length of the file in bytes, including records appended through this handle
+    pub fn logged_bytes(&self) -> u64 {
+        self.logged_bytes
+    }
+
+    /// Format version of this file
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Header written at the start of every new WAL file
+    fn file_header() -> [u8; WAL_HEADER_SIZE] {
+        let mut header = [0u8; WAL_HEADER_SIZE];
+        header[..4].copy_from_slice(WAL_MAGIC);
+        header[4..].copy_from_slice(&WAL_FORMAT_VERSION.to_le_bytes());
+        header
+    }
 
+    /// Detect the format of a non-empty WAL file from its first bytes
+    ///
+    /// The magic can't be mistaken for a v1 record: read as a key length it
+    /// is far above the largest key v1 accepts.
+    fn read_format_version(file: &File) -> WALResult<u32> {
+        let mut header = [0u8; WAL_HEADER_SIZE];
+        let mut reader = file;
+        reader.seek(SeekFrom::Start(0))?;
+        let read = reader.read(&mut header)?;
+        if read < WAL_HEADER_SIZE || &header[..4] != WAL_MAGIC {
+            return Ok(1);
+        }
+        let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if version < 2 || version > WAL_FORMAT_VERSION {
+            return Err(WALError::UnsupportedVersion(version));
+        }
+        Ok(version)
+    }
+
+    /// Open a buffered reader positioned at the first record
+    fn open_reader(&self) -> WALResult<BufReader<File>> {
         let file = OpenOptions::new()
             .read(true)
             .open(&self.path)
             .map_err(WALError::Io)?;
+        let mut reader = BufReader::new(file);
+        if self.format_version >= 2 {
+            reader.seek(SeekFrom::Start(WAL_HEADER_SIZE as u64))?;
+        }
+        Ok(reader)
+    }
+
+    /// Recover all records from the WAL into a MemTable
+    pub fn recover(&self, memtable: &MemTable) -> WALResult<()> {
+        let mut progress = RecoveryProgress {
+            bytes_total: self.file_size()?,
+            ..Default::default()
+        };
+        self.recover_with_progress(memtable, 0, &mut progress, &mut |_| {})
+    }
+
+    /// Recover all records into a MemTable, adding to `progress` and passing it
+    /// to `on_progress` about every megabyte and once the file is done
+    ///
+    /// `progress.bytes_processed` counts on from its value on entry, so one
+    /// progress value can span several files. Merge records at or below
+    /// `flushed_sequence` are already in SSTables and are skipped: unlike puts
+    /// and deletes, applying one twice changes the result.
+    pub fn recover_with_progress(
+        &self,
+        memtable: &MemTable,
+        flushed_sequence: u64,
+        progress: &mut RecoveryProgress,
+        on_progress: &mut dyn FnMut(&RecoveryProgress),
+    ) -> WALResult<()> {
+        let mut out_of_order = 0;
+        self.replay(progress, on_progress, |record| {
+            if record.is_flushed_merge(flushed_sequence) {
+                return Ok(());
+            }
+            if memtable
+                .get_entry(&record.key)?
+                .is_some_and(|newer| newer.sequence_number > record.sequence_number)
+            {
+                out_of_order += 1;
+            }
+            // Replay with the logged version so sequence numbers survive restarts
+            memtable
+                .apply(record.to_entry())
+                .map_err(WALError::MemTable)
+        })?;
+        progress.records_out_of_order += out_of_order;
+        Ok(())
+    }
+
+    /// Decode every record that recovery would apply, without applying them
+    ///
+    /// Lets segments be decoded on worker threads and applied in order afterwards.
+    pub fn read_records(&self, progress: &mut RecoveryProgress) -> WALResult<Vec<WALRecord>> {
+        let mut records = Vec::new();
+        self.replay(progress, &mut |_| {}, |record| {
+            records.push(record);
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    /// Read the complete records after byte `offset` of a WAL file that
+    /// another process may still be appending to
+    ///
+    /// Opens the file read-only and returns the records this build applies,
+    /// with the offset just past the last complete one to pass next time. A
+    /// record still being written, or a damaged one, ends the read.
+    pub fn tail<P: AsRef<Path>>(path: P, offset: u64) -> WALResult<(Vec<WALRecord>, u64)> {
+        let file = File::open(path.as_ref())?;
+        if file.metadata()?.len() < WAL_HEADER_SIZE as u64 {
+            // Not even the header has been written yet
+            return Ok((Vec::new(), offset));
+        }
+        let version = Self::read_format_version(&file)?;
+        let start = if version >= 2 {
+            offset.max(WAL_HEADER_SIZE as u64)
+        } else {
+            offset
+        };
 
         let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(start))?;
+        let mut records = Vec::new();
+        let mut end = start;
+        // A batch is only returned once its end marker has been read
+        let mut batch: Option<PendingBatch> = None;
+        while let Ok(Some(record)) = Self::read_record(&mut reader, version) {
+            match record.record_type {
+                RecordType::BatchBegin => batch = Some(PendingBatch::begin(&record)),
+                RecordType::BatchEnd => {
+                    if let Some(complete) = batch.take().and_then(PendingBatch::finish) {
+                        records.extend(complete);
+                    }
+                }
+                RecordType::Put | RecordType::Delete | RecordType::Merge => match &mut batch {
+                    Some(batch) => batch.records.push(record),
+                    None => records.push(record),
+                },
+                _ => {}
+            }
+            if batch.is_none() {
+                end = reader.stream_position()?;
+            }
+        }
+        Ok((records, end))
+    }
+
+    /// Sequence number of the first readable record in a WAL file, reading only that record
+    pub fn first_sequence_number<P: AsRef<Path>>(path: P) -> WALResult<Option<u64>> {
+        let file = File::open(path.as_ref())?;
+        if file.metadata()?.len() == 0 {
+            return Ok(None);
+        }
+        let version = Self::read_format_version(&file)?;
+        let mut reader = BufReader::new(file);
+        let offset = if version >= 2 { WAL_HEADER_SIZE } else { 0 };
+        reader.seek(SeekFrom::Start(offset as u64))?;
+        Ok(Self::read_record(&mut reader, version)
+            .ok()
+            .flatten()
+            .map(|record| record.sequence_number))
+    }
+
+    /// Read every record, passing the ones this build applies to `apply`
+    fn replay(
+        &self,
+        progress: &mut RecoveryProgress,
+        on_progress: &mut dyn FnMut(&RecoveryProgress),
+        mut apply: impl FnMut(WALRecord) -> WALResult<()>,
+    ) -> WALResult<()> {
+        info!("Starting WAL recovery for {:?}", self.path);
+
+        let mut reader = self.open_reader()?;
+        let start = progress.bytes_processed;
+        let mut last_reported = start;
         let mut recovered_count = 0;
         let mut corrupted_count = 0;
+        let mut skipped_count = 0;
+        let mut batch: Option<PendingBatch> = None;
 
         loop {
-            match self.read_record(&mut reader) {
-                Ok(Some(record)) => {
-                    // Apply record to MemTable
-                    if record.is_deletion() {
-                        memtable.delete(&record.key).map_err(WALError::MemTable)?;
-                    } else {
-                        let value = record.value.as_ref().unwrap();
-                        memtable
-                            .put(&record.key, value)
-                            .map_err(WALError::MemTable)?;
+            match Self::read_record(&mut reader, self.format_version) {
+                Ok(Some(record)) if record.record_type == RecordType::BatchBegin => {
+                    if batch.replace(PendingBatch::begin(&record)).is_some() {
+                        warn!("Dropping batch interrupted by another batch");
+                        progress.batches_dropped += 1;
                     }
-                    recovered_count += 1;
-
+                }
+                Ok(Some(record)) if record.record_type == RecordType::BatchEnd => {
+                    match batch.take().and_then(PendingBatch::finish) {
+                        Some(records) => {
+                            for record in records {
+                                apply(record)?;
+                                recovered_count += 1;
+                                progress.records_applied += 1;
+                            }
+                        }
+                        None => {
+                            warn!("Dropping batch ending at seq {}", record.sequence_number);
+                            progress.batches_dropped += 1;
+                        }
+                    }
+                }
+                Ok(Some(record))
+                    if batch.is_some()
+                        && matches!(
+                            record.record_type,
+                            RecordType::Put | RecordType::Delete | RecordType::Merge
+                        ) =>
+                {
+                    batch.as_mut().unwrap().records.push(record);
+                }
+                Ok(Some(record))
+                    if !matches!(
+                        record.record_type,
+                        RecordType::Put | RecordType::Delete | RecordType::Merge
+                    ) =>
+                {
+                    // Types this build can't apply are skipped rather than treated as corruption
+                    debug!(
+                        "Skipping {:?} record at seq {}",
+                        record.record_type, record.sequence_number
+                    );
+                    skipped_count += 1;
+                    progress.records_skipped += 1;
+                }
+                Ok(Some(record)) => {
                     trace!(
                         "Recovered record: key={:?}, seq={}, is_deletion={}",
                         String::from_utf8_lossy(&record.key),
                         record.sequence_number,
                         record.is_deletion()
                     );
+                    apply(record)?;
+                    recovered_count += 1;
+                    progress.records_applied += 1;
                 }
                 Ok(None) => {
                     // End of file
@@ -205,28 +699,84 @@ impl WAL {
                 Err(e) => {
                     warn!("Corrupted record during recovery: {}", e);
                     corrupted_count += 1;
+                    progress.records_corrupted += 1;
+                    if batch.take().is_some() {
+                        progress.batches_dropped += 1;
+                    }
 
                     // Try to find the next valid record by seeking forward
-                    if let Err(seek_err) = self.seek_to_next_record(&mut reader) {
+                    if let Err(seek_err) =
+                        Self::seek_to_next_record(&mut reader, self.format_version)
+                    {
                         error!("Failed to seek to next record: {}", seek_err);
                         break;
                     }
                 }
             }
+
+            progress.bytes_processed = start + reader.stream_position()?;
+            if progress.bytes_processed - last_reported >= RECOVERY_PROGRESS_INTERVAL {
+                on_progress(progress);
+                last_reported = progress.bytes_processed;
+            }
         }
 
+        // A batch still open at the end was cut short by a crash
+        if batch.is_some() {
+            warn!("Dropping incomplete batch at the end of {:?}", self.path);
+            progress.batches_dropped += 1;
+        }
+
+        progress.bytes_processed = start + self.file_size()?;
+        on_progress(progress);
+
         info!(
-            "WAL recovery completed: {} records recovered, {} corrupted",
-            recovered_count, corrupted_count
+            "WAL recovery completed: {} records recovered, {} corrupted, {} skipped",
+            recovered_count, corrupted_count, skipped_count
         );
 
         Ok(())
     }
 
-    /// Read a single record from the reader
-    fn read_record<R: Read + Seek>(&self, reader: &mut R) -> WALResult<Option<WALRecord>> {
-        // Read header (24 bytes total)
-        let mut header = [0u8; 24];
+    /// Decode one record in the current format from the start of `bytes`
+    ///
+    /// Returns the record and the number of bytes it occupied, or None for an
+    /// empty slice. Lengths are checked against the slice before anything is
+    /// allocated, so arbitrary input cannot cause large allocations.
+    pub fn read_record_from_slice(bytes: &[u8]) -> WALResult<Option<(WALRecord, usize)>> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        if bytes.len() < RECORD_HEADER_SIZE {
+            return Err(WALError::InvalidRecord(format!(
+                "Truncated record header: {} bytes",
+                bytes.len()
+            )));
+        }
+
+        let key_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        let value_len =
+            (u32::from_le_bytes(bytes[4..8].try_into().unwrap()) & !INDIRECT_VALUE_FLAG) as usize;
+        let record_len = RECORD_HEADER_SIZE + key_len + value_len;
+        if record_len > bytes.len() {
+            return Err(WALError::InvalidRecord(format!(
+                "Record of {} bytes overruns the {} available",
+                record_len,
+                bytes.len()
+            )));
+        }
+
+        let mut reader = io::Cursor::new(&bytes[..record_len]);
+        match Self::read_record(&mut reader, WAL_FORMAT_VERSION)? {
+            Some(record) => Ok(Some((record, record_len))),
+            None => Ok(None),
+        }
+    }
+
+    /// Read a single record in the given format from the reader
+    fn read_record<R: Read + Seek>(reader: &mut R, version: u32) -> WALResult<Option<WALRecord>> {
+        // Read the fixed part of the header (24 bytes)
+        let mut header = [0u8; FIXED_HEADER_SIZE];
         match reader.read_exact(&mut header) {
             Ok(_) => {}
             Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
@@ -237,7 +787,9 @@ impl WAL {
 
         // Parse header
         let key_len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
-        let value_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let raw_value_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let indirect = raw_value_len & INDIRECT_VALUE_FLAG != 0;
+        let value_len = (raw_value_len & !INDIRECT_VALUE_FLAG) as usize;
         let timestamp = u64::from_le_bytes([
             header[8], header[9], header[10], header[11], header[12], header[13], header[14],
             header[15],
@@ -247,6 +799,15 @@ impl WAL {
             header[23],
         ]);
 
+        // v1 records have no type byte; an empty value marks a delete
+        let record_type = if version >= 2 {
+            let mut type_byte = [0u8; 1];
+            reader.read_exact(&mut type_byte)?;
+            Some(RecordType::from_byte(type_byte[0]))
+        } else {
+            None
+        };
+
         // Validate record sizes
         if key_len > 1024 * 1024 || value_len > 100 * 1024 * 1024 {
             // 1MB key, 100MB value limit
@@ -261,7 +822,7 @@ impl WAL {
         reader.read_exact(&mut key)?;
 
         // Read value (if any)
-        let value = if value_len > 0 {
+        let value = if value_len > 0 || record_type.is_some_and(|t| t != RecordType::Delete) {
             let mut value_data = vec![0u8; value_len];
             reader.read_exact(&mut value_data)?;
             Some(value_data)
@@ -269,14 +830,20 @@ impl WAL {
             None
         };
 
-        Ok(Some(WALRecord::new(key, value, timestamp, sequence_number)))
+        let mut record = WALRecord::new(key, value, timestamp, sequence_number);
+        if let Some(record_type) = record_type {
+            record.record_type = record_type;
+        }
+        record.indirect = indirect;
+        Ok(Some(record))
     }
 
     /// Try to seek to the next valid record after corruption
-    fn seek_to_next_record<R: Read + Seek>(&self, reader: &mut R) -> WALResult<()> {
+    fn seek_to_next_record<R: Read + Seek>(reader: &mut R, version: u32) -> WALResult<()> {
         // Try to find the next record by looking for a valid header pattern
         let mut buffer = [0u8; 1024];
-        let mut offset: i64 = 0;
+        let start = reader.stream_position()?;
+        let mut offset = 0u64;
 
         loop {
             match reader.read(&mut buffer) {
@@ -296,14 +863,22 @@ impl WAL {
                             buffer[i + 7],
                         ]) as usize;
 
+                        // v2 headers must also carry a known record type
+                        let known_type = version < 2
+                            || buffer.get(i + FIXED_HEADER_SIZE).is_some_and(|&byte| {
+                                !matches!(RecordType::from_byte(byte), RecordType::Unknown(_))
+                            });
+
                         // Check if these look like reasonable lengths
-                        if key_len <= 1024 * 1024 && value_len <= 100 * 1024 * 1024 {
+                        if key_len <= 1024 * 1024 && value_len <= 100 * 1024 * 1024 && known_type {
                             // Seek to this potential record start
-                            reader.seek(SeekFrom::Current(offset + i as i64))?;
+                            reader.seek(SeekFrom::Start(start + offset + i as u64))?;
                             return Ok(());
                         }
                     }
-                    offset += n as i64;
+                    // Rescan the tail, which may hold the start of a header
+                    offset += n.saturating_sub(23).max(1) as u64;
+                    reader.seek(SeekFrom::Start(start + offset))?;
                 }
                 Ok(_) => break, // End of file
                 Err(e) => return Err(WALError::Io(e)),
@@ -318,16 +893,11 @@ impl WAL {
 
     /// Recover the sequence number from the existing WAL file
     fn recover_sequence_number(&mut self) -> WALResult<()> {
-        let file = OpenOptions::new()
-            .read(true)
-            .open(&self.path)
-            .map_err(WALError::Io)?;
-
-        let mut reader = BufReader::new(file);
+        let mut reader = self.open_reader()?;
         let mut max_seq = 0u64;
 
         loop {
-            match self.read_record(&mut reader) {
+            match Self::read_record(&mut reader, self.format_version) {
                 Ok(Some(record)) => {
                     max_seq = max_seq.max(record.sequence_number);
                 }
@@ -346,17 +916,12 @@ impl WAL {
 
     /// Truncate the WAL file (call after successful flush to SSTable)
     pub fn truncate(&mut self) -> WALResult<()> {
-        // Flush any pending writes
-        self.file.flush()?;
-
-        // Close the current writer and truncate the file
-        let file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&self.path)
-            .map_err(WALError::Io)?;
-
-        self.file = BufWriter::new(file);
+        // Records are appended unbuffered, so the file can be truncated in place;
+        // it starts over in the current format
+        self.file.set_len(0)?;
+        IoBackend::current().append(&self.file, &Self::file_header())?;
+        self.format_version = WAL_FORMAT_VERSION;
+        self.logged_bytes = WAL_HEADER_SIZE as u64;
         self.sequence_number = 0;
 
         info!("WAL truncated at {:?}", self.path);
@@ -368,19 +933,486 @@ impl WAL {
         let metadata = std::fs::metadata(&self.path).map_err(WALError::Io)?;
         Ok(metadata.len())
     }
+
+    /// Fsync everything appended so far
+    pub fn sync(&self) -> WALResult<()> {
+        fail::fail_point!("wal::fsync", |_| Err(injected_failure("wal::fsync")));
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// One record or damaged stretch of a WAL file, as listed by [`dump`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalDumpItem {
+    Record {
+        /// Byte offset of the record in the file
+        offset: u64,
+        len: u64,
+        record: WALRecord,
+    },
+    /// Bytes that don't decode, up to the next plausible record header
+    Corrupt {
+        offset: u64,
+        len: u64,
+        error: String,
+    },
+}
+
+impl WalDumpItem {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::Record { offset, .. } | Self::Corrupt { offset, .. } => *offset,
+        }
+    }
+}
+
+/// Every record and damaged region of a WAL file, from [`dump`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalDump {
+    pub format_version: u32,
+    pub file_size: u64,
+    /// Records and corrupt regions in file order
+    pub items: Vec<WalDumpItem>,
+    /// Length up to which the file can be kept: the end of the last record
+    /// before the first damage that isn't inside an unfinished batch
+    pub valid_len: u64,
+}
+
+impl WalDump {
+    /// Whether the file decodes to its end with no damage or open batch
+    pub fn is_intact(&self) -> bool {
+        self.valid_len == self.file_size
+    }
+
+    pub fn corrupt_regions(&self) -> impl Iterator<Item = &WalDumpItem> {
+        self.items
+            .iter()
+            .filter(|item| matches!(item, WalDumpItem::Corrupt { .. }))
+    }
+}
+
+impl std::fmt::Display for WalDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Format version: {}, {} bytes",
+            self.format_version, self.file_size
+        )?;
+        for item in &self.items {
+            match item {
+                WalDumpItem::Record {
+                    offset,
+                    len,
+                    record,
+                } => {
+                    write!(
+                        f,
+                        "@{} len={} seq={} ts={} {:?} {}",
+                        offset,
+                        len,
+                        record.sequence_number,
+                        record.timestamp,
+                        record.record_type,
+                        record.key.escape_ascii()
+                    )?;
+                    match &record.value {
+                        Some(_) if record.indirect => write!(f, " -> blob")?,
+                        Some(value) => write!(f, " = {}", value.escape_ascii())?,
+                        None => {}
+                    }
+                    writeln!(f)?;
+                }
+                WalDumpItem::Corrupt { offset, len, error } => {
+                    writeln!(f, "@{} len={} CORRUPT: {}", offset, len, error)?;
+                }
+            }
+        }
+        if self.is_intact() {
+            writeln!(f, "Intact: {} records", self.items.len())
+        } else {
+            writeln!(
+                f,
+                "Damaged: {} corrupt regions, valid up to byte {}",
+                self.corrupt_regions().count(),
+                self.valid_len
+            )
+        }
+    }
 }
 
-impl Drop for WAL {
+/// Decode every record of the WAL file at `path`/// Decode every record of the WAL file at `path` without opening it for
+/// writing, marking the regions that don't decode
+///
+/// After damage, decoding resumes at the next plausible record header, as
+/// recovery does, so records past a corrupt region are listed too.
+pub fn dump<P: AsRef<Path>>(path: P) -> WALResult<WalDump> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let format_version = WAL::read_format_version(&file)?;
+    let contents = std::fs::read(path)?;
+    let file_size = contents.len() as u64;
+    let start = if format_version >= 2 {
+        (WAL_HEADER_SIZE as u64).min(file_size)
+    } else {
+        0
+    };
+
+    let mut reader = io::Cursor::new(&contents[..]);
+    reader.set_position(start);
+    let mut items: Vec<WalDumpItem> = Vec::new();
+    // End of the last record that can be kept, and whether damage came since
+    let mut valid_len = start;
+    let mut damaged = false;
+    let mut batch_start = None;
+    loop {
+        let offset = reader.position();
+        let error = match WAL::read_record(&mut reader, format_version) {
+            Ok(Some(record)) => {
+                let len = reader.position() - offset;
+                match record.record_type {
+                    RecordType::BatchBegin => batch_start = Some(offset),
+                    RecordType::BatchEnd => batch_start = None,
+                    _ => {}
+                }
+                if !damaged && batch_start.is_none() {
+                    valid_len = offset + len;
+                }
+                items.push(WalDumpItem::Record {
+                    offset,
+                    len,
+                    record,
+                });
+                continue;
+            }
+            // A header cut short reads as the end of the file
+            Ok(None) if offset == file_size => break,
+            Ok(None) => "truncated record header".to_string(),
+            Err(e) => e.to_string(),
+        };
+
+        damaged = true;
+        reader.set_position(offset + 1);
+        let end = match WAL::seek_to_next_record(&mut reader, format_version) {
+            Ok(()) => reader.position(),
+            Err(_) => file_size,
+        };
+        reader.set_position(end);
+        match items.last_mut() {
+            Some(WalDumpItem::Corrupt {
+                offset: previous,
+                len,
+                ..
+            }) if *previous + *len == offset => *len = end - *previous,
+            _ => items.push(WalDumpItem::Corrupt {
+                offset,
+                len: end - offset,
+                error,
+            }),
+        }
+        if end >= file_size {
+            break;
+        }
+    }
+
+    Ok(WalDump {
+        format_version,
+        file_size,
+        items,
+        valid_len,
+    })
+}
+
+/// What [`repair`] did to a WAL file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalRepair {
+    /// Length of the file before and after the repair
+    pub original_len: u64,
+    pub repaired_len: u64,
+    /// Records after the cut that decoded but were removed with it
+    pub records_dropped: usize,
+    /// Where the removed bytes were saved, if any were removed
+    pub saved_to: Option<PathBuf>,
+}
+
+/// Truncate the WAL file at `path` after its last record that recovery can
+/// trust, so a damaged log doesn't block startup
+///
+/// The file is cut at [`WalDump::valid_len`]: before the first corrupt
+/// region, or before a batch that never finished, since records appended
+/// after an open batch would be read as part of it. The removed bytes are
+/// saved next to the file with a `.truncated` suffix first. Don't repair a
+/// WAL that an engine has open.
+pub fn repair<P: AsRef<Path>>(path: P) -> WALResult<WalRepair> {
+    let path = path.as_ref();
+    let dump = dump(path)?;
+    let mut repair = WalRepair {
+        original_len: dump.file_size,
+        repaired_len: dump.valid_len,
+        ..Default::default()
+    };
+    if dump.is_intact() {
+        return Ok(repair);
+    }
+    repair.records_dropped = dump
+        .items
+        .iter()
+        .filter(
+            |item| matches!(item, WalDumpItem::Record { offset, .. } if *offset >= dump.valid_len),
+        )
+        .count();
+
+    let contents = std::fs::read(path)?;
+    let mut saved_name = path.file_name().unwrap_or_default().to_os_string();
+    saved_name.push(".truncated");
+    let saved_to = path.with_file_name(saved_name);
+    std::fs::write(&saved_to, &contents[dump.valid_len as usize..])?;
+    File::open(&saved_to)?.sync_all()?;
+
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(dump.valid_len)?;
+    file.sync_all()?;
+    repair.saved_to = Some(saved_to);
+
+    warn!(
+        "Truncated WAL {:?} from {} to {} bytes, dropping {} records",
+        path, repair.original_len, repair.repaired_len, repair.records_dropped
+    );
+    Ok(repair)
+}
+
+/// Error returned by a failpoint configured with the `return` action
+#[cfg(feature = "failpoints")]
+fn injected_failure(name: &str) -> WALError {
+    WALError::Io(io::Error::other(format!("injected failure at {}", name)))
+}
+
+/// When appends to the WAL are fsynced
+///
+/// Parsed from and displayed as `always`, `every:<appends>`, `interval:<ms>`
+/// or `os`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Fsync every append before the write returns; writes applied together
+    /// by a write queue share one fsync
+    Always,
+    /// Fsync once this many appends have gone unsynced
+    EveryN(u64),
+    /// Fsync in the background on this interval, acknowledging waiting
+    /// writers in groups; see [`WalSyncer`]
+    Interval(Duration),
+    /// Leave appends in the OS page cache until it writes them back
+    #[default]
+    OsBuffered,
+}
+
+impl std::str::FromStr for SyncPolicy {
+    type Err = WALError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || WALError::InvalidSyncPolicy(s.to_string());
+        let lower = s.trim().to_ascii_lowercase();
+        match lower.split_once(':') {
+            None if lower == "always" => Ok(Self::Always),
+            None if lower == "os" || lower == "os-buffered" => Ok(Self::OsBuffered),
+            Some(("every", n)) => match n.trim().parse() {
+                Ok(n) if n > 0 => Ok(Self::EveryN(n)),
+                _ => Err(invalid()),
+            },
+            Some(("interval", ms)) => ms
+                .trim()
+                .parse()
+                .map(|ms| Self::Interval(Duration::from_millis(ms)))
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for SyncPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::EveryN(n) => write!(f, "every:{}", n),
+            Self::Interval(interval) => write!(f, "interval:{}", interval.as_millis()),
+            Self::OsBuffered => write!(f, "os"),
+        }
+    }
+}
+
+/// Background task that fsyncs the WAL on a fixed interval
+///
+/// Writers call [`WalSyncer::note_write`] with the sequence number of each
+/// append and wait on a [`SyncTicket`] when they need durability; a single
+/// fsync per interval then acknowledges every write that arrived since the
+/// previous one.
+pub struct WalSyncer {
+    shared: Arc<SyncShared>,
+    task: JoinHandle<()>,
+}
+
+struct SyncShared {
+    state: Mutex<SyncState>,
+    /// Highest sequence number known to be durable
+    synced: watch::Sender<u64>,
+}
+
+/// WAL file currently being appended to and the highest sequence number
+/// appended so far, across every WAL rotation
+struct SyncState {
+    file: File,
+    written: u64,
+}
+
+impl SyncShared {
+    /// Fsync the current file if it has unsynced appends
+    fn sync_pending(&self) -> WALResult<()> {
+        let (file, target) = {
+            let state = self.state.lock().unwrap();
+            if state.written <= *self.synced.borrow() {
+                return Ok(());
+            }
+            (state.file.try_clone()?, state.written)
+        };
+
+        fail::fail_point!("wal::fsync", |_| Err(injected_failure("wal::fsync")));
+        file.sync_data()?;
+        self.publish(target);
+        Ok(())
+    }
+
+    fn publish(&self, target: u64) {
+        self.synced.send_if_modified(|synced| {
+            let advanced = target > *synced;
+            if advanced {
+                *synced = target;
+            }
+            advanced
+        });
+    }
+}
+
+impl WalSyncer {
+    /// Start syncing `wal` every `interval`
+    ///
+    /// Writes up to `sequence` are fsynced first, so they count as durable.
+    /// Must be called from within a tokio runtime.
+    pub fn start(wal: &WAL, interval: Duration, sequence: u64) -> WALResult<Self> {
+        wal.sync()?;
+        let (synced, _) = watch::channel(sequence);
+        let shared = Arc::new(SyncShared {
+            state: Mutex::new(SyncState {
+                file: wal.file.try_clone()?,
+                written: sequence,
+            }),
+            synced,
+        });
+
+        let task_shared = shared.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let shared = task_shared.clone();
+                match tokio::task::spawn_blocking(move || shared.sync_pending()).await {
+                    Ok(Ok(())) => {}
+                    // Waiters stay pending and the next tick retries
+                    Ok(Err(e)) => error!("Background WAL sync failed: {}", e),
+                    Err(e) => error!("Background WAL sync task panicked: {}", e),
+                }
+            }
+        });
+
+        debug!("WAL sync task started with interval {:?}", interval);
+        Ok(Self { shared, task })
+    }
+
+    /// Record an append to the WAL with the given sequence number
+    pub fn note_write(&self, sequence: u64) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.written = state.written.max(sequence);
+    }
+
+    /// Ticket that resolves once every append noted so far is durable
+    pub fn ticket(&self) -> SyncTicket {
+        self.ticket_for(self.shared.state.lock().unwrap().written)
+    }
+
+    /// Ticket that resolves once appends up to `sequence` are durable
+    pub fn ticket_for(&self, sequence: u64) -> SyncTicket {
+        SyncTicket {
+            target: sequence,
+            synced: Some(self.shared.synced.subscribe()),
+        }
+    }
+
+    /// Highest sequence number known to be durable
+    pub fn durable_sequence(&self) -> u64 {
+        *self.shared.synced.borrow()
+    }
+
+    /// Acknowledge writes up to `sequence` made durable some other way, such
+    /// as by being flushed to a synced SSTable
+    pub fn mark_durable(&self, sequence: u64) {
+        self.shared.publish(sequence);
+    }
+
+    /// Fsync pending appends immediately instead of waiting for the next tick
+    pub fn sync_now(&self) -> WALResult<()> {
+        self.shared.sync_pending()
+    }
+
+    /// Follow a newly rotated WAL, syncing the outgoing file first
+    pub fn switch_file(&self, wal: &WAL) -> WALResult<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.file.sync_data()?;
+        self.shared.publish(state.written);
+        state.file = wal.file.try_clone()?;
+        Ok(())
+    }
+}
+
+impl Drop for WalSyncer {
     fn drop(&mut self) {
-        if let Err(e) = self.file.flush() {
-            error!("Failed to flush WAL on drop: {}", e);
+        self.task.abort();
+    }
+}
+
+/// Acknowledgment for WAL appends that resolves once they have been fsynced
+#[derive(Debug)]
+pub struct SyncTicket {
+    target: u64,
+    /// None when the appends were already durable when the ticket was issued
+    synced: Option<watch::Receiver<u64>>,
+}
+
+impl SyncTicket {
+    /// Ticket for appends that are already durable
+    pub fn ready() -> Self {
+        Self {
+            target: 0,
+            synced: None,
         }
     }
+
+    /// Wait until the covered appends are durable
+    pub async fn wait(self) -> WALResult<()> {
+        let Some(mut synced) = self.synced else {
+            return Ok(());
+        };
+        synced
+            .wait_for(|synced| *synced >= self.target)
+            .await
+            .map_err(|_| WALError::SyncStopped)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
     use tempfile::tempdir;
 
     fn create_test_wal() -> (WAL, tempfile::TempDir) {
@@ -390,11 +1422,34 @@ mod tests {
         (wal, temp_dir)
     }
 
+    #[test]
+    fn test_sync_policy_parsing() {
+        for policy in [
+            SyncPolicy::Always,
+            SyncPolicy::EveryN(8),
+            SyncPolicy::Interval(Duration::from_millis(5)),
+            SyncPolicy::OsBuffered,
+        ] {
+            assert_eq!(policy.to_string().parse::<SyncPolicy>().unwrap(), policy);
+        }
+        assert_eq!(
+            "OS-Buffered".parse::<SyncPolicy>().unwrap(),
+            SyncPolicy::OsBuffered
+        );
+        for invalid in ["sometimes", "every:0", "every:x", "interval:"] {
+            assert!(matches!(
+                invalid.parse::<SyncPolicy>(),
+                Err(WALError::InvalidSyncPolicy(_))
+            ));
+        }
+    }
+
     #[test]
     fn test_wal_creation() {
         let (wal, _temp_dir) = create_test_wal();
         assert_eq!(wal.sequence_number(), 0);
-        assert!(wal.file_size().unwrap() == 0);
+        assert_eq!(wal.file_size().unwrap(), WAL_HEADER_SIZE as u64);
+        assert_eq!(wal.format_version(), WAL_FORMAT_VERSION);
     }
 
     #[test]
@@ -455,7 +1510,10 @@ mod tests {
 
         // Verify recovery
         assert_eq!(memtable.get(b"key1").unwrap(), None); // Deleted
-        assert_eq!(memtable.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+        assert_eq!(
+            memtable.get(b"key2").unwrap(),
+            Some(Bytes::from_static(b"value2"))
+        );
         assert_eq!(memtable.len(), 2); // Both records exist (one as tombstone)
     }
 
@@ -479,6 +1537,34 @@ mod tests {
         assert_eq!(wal.sequence_number(), 3);
     }
 
+    #[test]
+    fn test_wal_caller_assigned_sequence_numbers() {
+        let (mut wal, _temp_dir) = create_test_wal();
+
+        // Gaps are allowed, going backwards is not
+        wal.write_record(&WALRecord::new(
+            b"key1".to_vec(),
+            Some(b"v1".to_vec()),
+            1,
+            10,
+        ))
+        .unwrap();
+        wal.write_record(&WALRecord::new(b"key2".to_vec(), None, 2, 15))
+            .unwrap();
+        assert!(
+            wal.write_record(&WALRecord::new(b"key3".to_vec(), None, 3, 15))
+                .is_err()
+        );
+        assert_eq!(wal.sequence_number(), 15);
+
+        // Replay keeps the logged versions
+        let memtable = MemTable::new(1024);
+        wal.recover(&memtable).unwrap();
+        let entry = memtable.get_entry(b"key1").unwrap().unwrap();
+        assert_eq!((entry.timestamp, entry.sequence_number), (1, 10));
+        assert_eq!(memtable.sequence_number(), 15);
+    }
+
     #[test]
     fn test_wal_truncation() {
         let (mut wal, _temp_dir) = create_test_wal();
@@ -494,12 +1580,14 @@ mod tests {
 
         let size_before = wal.file_size().unwrap();
         assert!(size_before > 0);
+        assert_eq!(wal.logged_bytes(), size_before);
 
         // Truncate
         wal.truncate().unwrap();
 
+        // Only the file header remains
         let size_after = wal.file_size().unwrap();
-        assert_eq!(size_after, 0);
+        assert_eq!(size_after, WAL_HEADER_SIZE as u64);
         assert_eq!(wal.sequence_number(), 0);
     }
 
@@ -534,8 +1622,336 @@ mod tests {
         wal.recover(&memtable).unwrap();
 
         // Should have recovered the valid records
-        assert_eq!(memtable.get(b"key1").unwrap(), Some(b"value1".to_vec()));
-        assert_eq!(memtable.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"value1"))
+        );
+        assert_eq!(
+            memtable.get(b"key2").unwrap(),
+            Some(Bytes::from_static(b"value2"))
+        );
+    }
+
+    #[test]
+    fn test_wal_indirect_record_recovery() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("indirect.wal");
+
+        {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            wal.put(b"plain", b"value", 1).unwrap();
+            wal.put_indirect(b"blob", b"pointer", 2).unwrap();
+        }
+
+        let wal = WAL::new(&wal_path).unwrap();
+        assert_eq!(wal.sequence_number(), 2);
+
+        let memtable = MemTable::new(1024 * 1024);
+        wal.recover(&memtable).unwrap();
+
+        let plain = memtable.get_entry(b"plain").unwrap().unwrap();
+        assert!(!plain.indirect);
+        let blob = memtable.get_entry(b"blob").unwrap().unwrap();
+        assert!(blob.indirect);
+        assert_eq!(blob.value, Some(Bytes::from_static(b"pointer")));
+    }
+
+    #[test]
+    fn test_wal_read_record_from_slice() {
+        let mut record = WALRecord::new(b"key".to_vec(), Some(b"value".to_vec()), 1000, 7);
+        record.indirect = true;
+        let mut bytes = record.encode();
+        let record_len = bytes.len();
+        bytes.extend(WALRecord::new(b"next".to_vec(), None, 1001, 8).encode());
+
+        let (decoded, consumed) = WAL::read_record_from_slice(&bytes).unwrap().unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(consumed, record_len);
+        let (next, _) = WAL::read_record_from_slice(&bytes[consumed..])
+            .unwrap()
+            .unwrap();
+        assert!(next.is_deletion());
+
+        assert!(WAL::read_record_from_slice(&[]).unwrap().is_none());
+        assert!(WAL::read_record_from_slice(&bytes[..10]).is_err());
+        assert!(WAL::read_record_from_slice(&bytes[..record_len - 1]).is_err());
+
+        // A huge claimed length fails before allocating
+        let mut huge = bytes.clone();
+        huge[4..8].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+        assert!(WAL::read_record_from_slice(&huge).is_err());
+    }
+
+    #[test]
+    fn test_wal_reads_legacy_files() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("legacy.wal");
+
+        // A v1 file has no header and no type byte
+        let legacy = [
+            WALRecord::new(b"key1".to_vec(), Some(b"value1".to_vec()), 1, 1),
+            WALRecord::new(b"key2".to_vec(), None, 2, 2),
+        ];
+        let bytes: Vec<u8> = legacy.iter().flat_map(|r| r.encode_version(1)).collect();
+        std::fs::write(&wal_path, bytes).unwrap();
+
+        {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            assert_eq!(wal.format_version(), 1);
+            assert_eq!(wal.sequence_number(), 2);
+            // Appends keep the format the file started with
+            wal.put(b"key3", b"value3", 3).unwrap();
+        }
+
+        let wal = WAL::new(&wal_path).unwrap();
+        assert_eq!(wal.format_version(), 1);
+        let memtable = MemTable::new(1024 * 1024);
+        wal.recover(&memtable).unwrap();
+        assert_eq!(
+            memtable.get(b"key1").unwrap(),
+            Some(Bytes::from_static(b"value1"))
+        );
+        assert!(
+            memtable
+                .get_entry(b"key2")
+                .unwrap()
+                .unwrap()
+                .value
+                .is_none()
+        );
+        assert_eq!(
+            memtable.get(b"key3").unwrap(),
+            Some(Bytes::from_static(b"value3"))
+        );
+    }
+
+    #[test]
+    fn test_wal_skips_unapplied_record_types() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("types.wal");
+
+        {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            wal.put(b"key1", b"value1", 1).unwrap();
+            let mut marker = WALRecord::new(b"range".to_vec(), Some(b"end".to_vec()), 2, 2);
+            marker.record_type = RecordType::RangeDelete;
+            let mut future = WALRecord::new(b"future".to_vec(), Some(b"x".to_vec()), 3, 3);
+            future.record_type = RecordType::Unknown(0x0F);
+            let mut file = OpenOptions::new().append(true).open(&wal_path).unwrap();
+            file.write_all(&marker.encode()).unwrap();
+            file.write_all(&future.encode()).unwrap();
+            wal.sequence_number = 3;
+            wal.put(b"key2", b"value2", 4).unwrap();
+        }
+
+        let wal = WAL::new(&wal_path).unwrap();
+        assert_eq!(wal.sequence_number(), 4);
+        let memtable = MemTable::new(1024 * 1024);
+        wal.recover(&memtable).unwrap();
+        assert_eq!(memtable.len(), 2);
+        assert!(memtable.get(b"range").unwrap().is_none());
+        assert!(memtable.get(b"future").unwrap().is_none());
+
+        // Flag bits don't change the decoded type
+        assert_eq!(RecordType::from_byte(0x80 | 2), RecordType::Delete);
+
+        // A header from a newer build is rejected rather than misread
+        let newer = temp_dir.path().join("newer.wal");
+        let mut header = WAL::file_header();
+        header[4..].copy_from_slice(&(WAL_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&newer, header).unwrap();
+        assert!(matches!(
+            WAL::new(&newer),
+            Err(WALError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_wal_batches_apply_all_or_nothing() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("batch.wal");
+
+        let complete_len = {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            wal.put(b"before", b"value", 1).unwrap();
+            let batch = [
+                WALRecord::new(b"object".to_vec(), Some(b"v1".to_vec()), 2, 2),
+                WALRecord::new(b"index".to_vec(), Some(b"object".to_vec()), 2, 3),
+                WALRecord::new(b"stale".to_vec(), None, 2, 4),
+            ];
+            wal.write_batch(&batch).unwrap();
+            assert_eq!(wal.sequence_number(), 4);
+
+            // Sequence numbers must still move forward
+            assert!(wal.write_batch(&batch).is_err());
+            let complete_len = wal.file_size().unwrap();
+
+            let torn = [
+                WALRecord::new(b"object".to_vec(), Some(b"v2".to_vec()), 3, 5),
+                WALRecord::new(b"index".to_vec(), Some(b"other".to_vec()), 3, 6),
+            ];
+            wal.write_batch(&torn).unwrap();
+            complete_len
+        };
+
+        let memtable = MemTable::new(1024 * 1024);
+        WAL::new(&wal_path).unwrap().recover(&memtable).unwrap();
+        assert_eq!(
+            memtable.get(b"object").unwrap().as_deref(),
+            Some(&b"v2"[..])
+        );
+
+        // Cut the second batch short: none of it is applied
+        let file = OpenOptions::new().write(true).open(&wal_path).unwrap();
+        file.set_len(complete_len + 40).unwrap();
+        let (records, end) = WAL::tail(&wal_path, 0).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(end, complete_len);
+
+        let wal = WAL::new(&wal_path).unwrap();
+        let memtable = MemTable::new(1024 * 1024);
+        let mut progress = RecoveryProgress::default();
+        wal.recover_with_progress(&memtable, 0, &mut progress, &mut |_| {})
+            .unwrap();
+        assert_eq!(progress.records_applied, 4);
+        assert_eq!(progress.batches_dropped, 1);
+        assert_eq!(
+            memtable.get(b"object").unwrap().as_deref(),
+            Some(&b"v1"[..])
+        );
+        assert_eq!(
+            memtable.get(b"index").unwrap().as_deref(),
+            Some(&b"object"[..])
+        );
+        assert!(
+            memtable
+                .get_entry(b"stale")
+                .unwrap()
+                .unwrap()
+                .value
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_wal_dump_and_repair() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("wal.log");
+
+        let (first_end, second_end) = {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            wal.put(b"key1", b"value1", 1).unwrap();
+            let first_end = wal.file_size().unwrap();
+            wal.put(b"key2", b"value2", 2).unwrap();
+            let second_end = wal.file_size().unwrap();
+            wal.put(b"key3", b"value3", 3).unwrap();
+            (first_end, second_end)
+        };
+
+        let intact = dump(&wal_path).unwrap();
+        assert!(intact.is_intact());
+        assert_eq!(intact.items.len(), 3);
+        assert_eq!(intact.corrupt_regions().count(), 0);
+        assert_eq!(
+            intact.items[1],
+            WalDumpItem::Record {
+                offset: first_end,
+                len: second_end - first_end,
+                record: WALRecord::new(b"key2".to_vec(), Some(b"value2".to_vec()), 2, 2),
+            }
+        );
+        assert_eq!(repair(&wal_path).unwrap().saved_to, None);
+
+        // An impossible key length in the second record
+        let mut contents = std::fs::read(&wal_path).unwrap();
+        contents[first_end as usize..first_end as usize + 4]
+            .copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+        contents.extend_from_slice(b"garbage");
+        std::fs::write(&wal_path, &contents).unwrap();
+
+        let damaged = dump(&wal_path).unwrap();
+        assert!(!damaged.is_intact());
+        assert_eq!(damaged.valid_len, first_end);
+        assert_eq!(damaged.items[1].offset(), first_end);
+        assert!(matches!(damaged.items[1], WalDumpItem::Corrupt { .. }));
+        assert!(matches!(
+            damaged.items.last().unwrap(),
+            WalDumpItem::Corrupt { .. }
+        ));
+
+        let repaired = repair(&wal_path).unwrap();
+        assert_eq!(repaired.original_len, contents.len() as u64);
+        assert_eq!(repaired.repaired_len, first_end);
+        let saved = std::fs::read(repaired.saved_to.unwrap()).unwrap();
+        assert_eq!(saved, &contents[first_end as usize..]);
+        assert!(dump(&wal_path).unwrap().is_intact());
+
+        let mut wal = WAL::new(&wal_path).unwrap();
+        wal.put(b"key4", b"value4", 4).unwrap();
+        let memtable = MemTable::new(1024 * 1024);
+        wal.recover(&memtable).unwrap();
+        assert_eq!(memtable.len(), 2);
+        assert!(memtable.get(b"key2").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_wal_repair_drops_unfinished_batch() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("wal.log");
+
+        let before_batch = {
+            let mut wal = WAL::new(&wal_path).unwrap();
+            wal.put(b"before", b"value", 1).unwrap();
+            let before_batch = wal.file_size().unwrap();
+            let batch = [
+                WALRecord::new(b"a".to_vec(), Some(b"1".to_vec()), 2, 2),
+                WALRecord::new(b"b".to_vec(), Some(b"2".to_vec()), 2, 3),
+            ];
+            wal.write_batch(&batch).unwrap();
+            before_batch
+        };
+
+        // Cut the batch after its first record
+        let full_len = std::fs::metadata(&wal_path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&wal_path).unwrap();
+        file.set_len(full_len - 20).unwrap();
+        drop(file);
+
+        let torn = dump(&wal_path).unwrap();
+        assert_eq!(torn.valid_len, before_batch);
+        assert!(torn.items.iter().any(|item| matches!(
+            item,
+            WalDumpItem::Record { record, .. } if record.record_type == RecordType::BatchBegin
+        )));
+
+        let repaired = repair(&wal_path).unwrap();
+        assert_eq!(repaired.repaired_len, before_batch);
+        assert!(repaired.records_dropped >= 2);
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), before_batch);
+    }
+
+    #[test]
+    fn test_wal_tail() {
+        let temp_dir = tempdir().unwrap();
+        let wal_path = temp_dir.path().join("tail.wal");
+        let mut wal = WAL::new(&wal_path).unwrap();
+        wal.put(b"key1", b"value1", 1).unwrap();
+
+        let (records, offset) = WAL::tail(&wal_path, 0).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(offset, wal.file_size().unwrap());
+        assert!(WAL::tail(&wal_path, offset).unwrap().0.is_empty());
+
+        // A record cut off mid-write is left for the next read
+        wal.put(b"key2", b"value2", 2).unwrap();
+        let full = std::fs::read(&wal_path).unwrap();
+        let partial = temp_dir.path().join("partial.wal");
+        std::fs::write(&partial, &full[..full.len() - 3]).unwrap();
+        assert_eq!(WAL::tail(&partial, offset).unwrap(), (Vec::new(), offset));
+
+        let (records, end) = WAL::tail(&wal_path, offset).unwrap();
+        assert_eq!(records[0].key, b"key2");
+        assert_eq!(end, full.len() as u64);
     }
 
     #[test]
@@ -571,8 +1987,45 @@ mod tests {
 
         let entry = record.to_entry();
         assert_eq!(entry.key, b"test_key");
-        assert_eq!(entry.value, Some(b"test_value".to_vec()));
+        assert_eq!(entry.value, Some(Bytes::from_static(b"test_value")));
         assert_eq!(entry.timestamp, 1234567890);
         assert_eq!(entry.sequence_number, 42);
     }
+
+    #[tokio::test]
+    async fn test_wal_syncer_acknowledges_in_batches() {
+        let (mut wal, temp_dir) = create_test_wal();
+        let syncer = WalSyncer::start(&wal, Duration::from_millis(5), 0).unwrap();
+
+        let mut tickets = Vec::new();
+        for i in 0..5 {
+            wal.put(format!("key{}", i).as_bytes(), b"value", 1000)
+                .unwrap();
+            syncer.note_write(i + 1);
+            tickets.push(syncer.ticket());
+        }
+        for ticket in tickets {
+            ticket.wait().await.unwrap();
+        }
+        assert_eq!(*syncer.shared.synced.borrow(), 5);
+
+        // Rotation syncs the outgoing file and keeps counting on the new one
+        let mut next = WAL::new(temp_dir.path().join("next.wal")).unwrap();
+        syncer.switch_file(&next).unwrap();
+        next.put(b"key5", b"value", 1000).unwrap();
+        syncer.note_write(6);
+        syncer.sync_now().unwrap();
+        assert_eq!(*syncer.shared.synced.borrow(), 6);
+        syncer.ticket().wait().await.unwrap();
+        syncer.ticket_for(3).wait().await.unwrap();
+        assert_eq!(syncer.durable_sequence(), 6);
+
+        // Writes synced elsewhere resolve their tickets without an fsync
+        let ticket = syncer.ticket_for(9);
+        syncer.mark_durable(9);
+        ticket.wait().await.unwrap();
+        assert_eq!(syncer.durable_sequence(), 9);
+
+        SyncTicket::ready().wait().await.unwrap();
+    }
 }
@@ -0,0 +1,375 @@
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep_until};
+use tracing::{debug, trace, warn};
+
+use crate::batch::WriteBatch;
+use crate::engine::{Engine, EngineError, EngineResult};
+
+/// Maximum number of queued requests applied before acknowledging them
+const MAX_PIPELINE_BATCH: usize = 256;
+
+/// A write waiting for the writer task
+enum WriteRequest {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ack: oneshot::Sender<EngineResult<u64>>,
+    },
+    Delete {
+        key: Vec<u8>,
+        ack: oneshot::Sender<EngineResult<u64>>,
+    },
+    DeleteIf {
+        key: Vec<u8>,
+        expected: Vec<u8>,
+        ack: oneshot::Sender<EngineResult<Option<u64>>>,
+    },
+    Batch {
+        batch: WriteBatch,
+        ack: oneshot::Sender<EngineResult<u64>>,
+    },
+}
+
+/// Outcome of an applied write, held back until its group is committed
+enum PendingAck {
+    Sequence(oneshot::Sender<EngineResult<u64>>, EngineResult<u64>),
+    Conditional(
+        oneshot::Sender<EngineResult<Option<u64>>>,
+        EngineResult<Option<u64>>,
+    ),
+}
+
+impl PendingAck {
+    /// Acknowledge the write, failing it if the group's fsync failed
+    fn send(self, committed: &EngineResult<()>) {
+        match self {
+            Self::Sequence(ack, result) => {
+                let _ = ack.send(settle(result, committed));
+            }
+            Self::Conditional(ack, result) => {
+                let _ = ack.send(settle(result, committed));
+            }
+        }
+    }
+}
+
+/// A write applied in a group whose fsync failed is not durable
+fn settle<T>(result: EngineResult<T>, committed: &EngineResult<()>) -> EngineResult<T> {
+    match committed {
+        Err(e) if result.is_ok() => Err(EngineError::Io(std::io::Error::other(format!(
+            "WAL fsync failed: {}",
+            e
+        )))),
+        _ => result,
+    }
+}
+
+/// Cloneable handle that funnels writes through a single writer task
+///
+/// The writer owns the [`Engine`] and drains a bounded channel, applying
+/// requests in arrival order (WAL append, then MemTable insert) and
+/// acknowledging each one once it has been applied; under
+/// [`crate::wal::SyncPolicy::Always`] the writes drained together share one
+/// fsync before any is acknowledged. Callers never contend
+/// on the engine, and a full channel applies backpressure. When the engine
+/// has `maintenance` configured, the writer also runs
+/// [`Engine::run_maintenance`] between batches.
+#[derive(Clone)]
+pub struct WriteQueue {
+    sender: mpsc::Sender<WriteRequest>,
+}
+
+impl WriteQueue {
+    /// Move `engine` into a writer task accepting up to `capacity` queued writes
+    ///
+    /// The task hands the engine back once every handle has been dropped.
+    pub fn spawn(engine: Engine, capacity: usize) -> (Self, JoinHandle<Engine>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let writer = tokio::spawn(run_writer(engine, receiver));
+        (Self { sender }, writer)
+    }
+
+    /// Queue a put and wait until it has been applied, returning its sequence number
+    pub async fn put(&self, key: &[u8], value: &[u8]) -> EngineResult<u64> {
+        let (ack, done) = oneshot::channel();
+        self.submit(
+            WriteRequest::Put {
+                key: key.to_vec(),
+                value: value.to_vec(),
+                ack,
+            },
+            done,
+        )
+        .await
+    }
+
+    /// Queue a delete and wait until it has been applied, returning its sequence number
+    pub async fn delete(&self, key: &[u8]) -> EngineResult<u64> {
+        let (ack, done) = oneshot::channel();
+        self.submit(
+            WriteRequest::Delete {
+                key: key.to_vec(),
+                ack,
+            },
+            done,
+        )
+        .await
+    }
+
+    /// Queue a conditional delete and wait for its outcome
+    ///
+    /// See [`Engine::delete_if`]; the check runs on the writer task, after
+    /// every write queued before it.
+    pub async fn delete_if(&self, key: &[u8], expected: &[u8]) -> EngineResult<Option<u64>> {
+        let (ack, done) = oneshot::channel();
+        self.submit(
+            WriteRequest::DeleteIf {
+                key: key.to_vec(),
+                expected: expected.to_vec(),
+                ack,
+            },
+            done,
+        )
+        .await
+    }
+
+    /// Queue a write batch and wait until all of it has been applied
+    ///
+    /// See [`Engine::write_batch`].
+    pub async fn write_batch(&self, batch: WriteBatch) -> EngineResult<u64> {
+        let (ack, done) = oneshot::channel();
+        self.submit(WriteRequest::Batch { batch, ack }, done).await
+    }
+
+    async fn submit<T>(
+        &self,
+        request: WriteRequest,
+        done: oneshot::Receiver<EngineResult<T>>,
+    ) -> EngineResult<T> {
+        self.sender
+            .send(request)
+            .await
+            .map_err(|_| EngineError::WriterClosed)?;
+        done.await.map_err(|_| EngineError::WriterClosed)?
+    }
+}
+
+/// Apply queued writes in order until every handle is gone
+///
+/// Maintenance runs every `interval`, and once writes have paused for
+/// `idle_after` after any activity since the last run.
+async fn run_writer(mut engine: Engine, mut receiver: mpsc::Receiver<WriteRequest>) -> Engine {
+    let maintenance = engine.config().maintenance.clone();
+    let mut next_run = maintenance.as_ref().map(|m| Instant::now() + m.interval);
+    let mut idle_deadline = None;
+
+    let mut batch = Vec::with_capacity(MAX_PIPELINE_BATCH);
+    loop {
+        let deadline = match (next_run, idle_deadline) {
+            (Some(next), Some(idle)) => Some(next.min(idle)),
+            (next, idle) => next.or(idle),
+        };
+        let received = tokio::select! {
+            received = receiver.recv_many(&mut batch, MAX_PIPELINE_BATCH) => received,
+            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                if let Err(e) = engine.run_maintenance().await {
+                    warn!("Maintenance failed: {}", e);
+                }
+                next_run = maintenance.as_ref().map(|m| Instant::now() + m.interval);
+                idle_deadline = None;
+                continue;
+            }
+        };
+        if received == 0 {
+            break;
+        }
+        idle_deadline = maintenance
+            .as_ref()
+            .and_then(|m| m.idle_after)
+            .map(|idle| Instant::now() + idle);
+
+        trace!("Writer applying {} queued writes", batch.len());
+        // Under `SyncPolicy::Always` the drained writes share one fsync, and
+        // none is acknowledged before it
+        engine.begin_group_commit();
+        let mut acks = Vec::with_capacity(batch.len());
+        for request in batch.drain(..) {
+            acks.push(match request {
+                WriteRequest::Put { key, value, ack } => {
+                    PendingAck::Sequence(ack, engine.put(&key, &value).await)
+                }
+                WriteRequest::Delete { key, ack } => {
+                    PendingAck::Sequence(ack, engine.delete(&key).await)
+                }
+                WriteRequest::DeleteIf { key, expected, ack } => {
+                    PendingAck::Conditional(ack, engine.delete_if(&key, &expected).await)
+                }
+                WriteRequest::Batch { batch, ack } => {
+                    PendingAck::Sequence(ack, engine.write_batch(batch).await)
+                }
+            });
+        }
+        let committed = engine.commit_group();
+        if let Err(e) = &committed {
+            warn!("Group commit failed: {}", e);
+        }
+        for ack in acks {
+            ack.send(&committed);
+        }
+    }
+
+    debug!("Write queue closed, writer task exiting");
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compaction::CompactionOptions;
+    use crate::engine::{EngineConfig, MaintenanceOptions};
+    use crate::wal::SyncPolicy;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_concurrent_writers() {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        let (queue, writer) = WriteQueue::spawn(engine, 16);
+
+        let mut tasks = Vec::new();
+        for task in 0..8 {
+            let queue = queue.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..50 {
+                    let key = format!("task{}:key{:02}", task, i);
+                    queue.put(key.as_bytes(), b"value").await.unwrap();
+                }
+                queue
+                    .delete(format!("task{}:key00", task).as_bytes())
+                    .await
+                    .unwrap();
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Errors from the engine reach the caller that sent the write
+        assert!(queue.put(b"", b"value").await.is_err());
+        let mut batch = WriteBatch::new();
+        batch.put(b"task0:key00", b"value").delete(b"task1:key01");
+        queue.write_batch(batch).await.unwrap();
+
+        drop(queue);
+        let engine = writer.await.unwrap();
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 8 * 49);
+        assert_eq!(engine.get(b"task3:key00").await.unwrap(), None);
+        assert!(engine.get(b"task0:key00").await.unwrap().is_some());
+        assert!(engine.get(b"task3:key49").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_group_commit_acknowledges_durable_writes() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            wal_sync_policy: SyncPolicy::Always,
+            env_overrides: false,
+            ..Default::default()
+        };
+        let engine = Engine::with_config(config).await.unwrap();
+        let (queue, writer) = WriteQueue::spawn(engine, 64);
+
+        let mut tasks = Vec::new();
+        for task in 0..8 {
+            let queue = queue.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut sequences = Vec::new();
+                for i in 0..20 {
+                    let key = format!("task{}:key{:02}", task, i);
+                    sequences.push(queue.put(key.as_bytes(), b"value").await.unwrap());
+                }
+                sequences
+            }));
+        }
+        let mut last = 0;
+        for task in tasks {
+            last = last.max(*task.await.unwrap().iter().max().unwrap());
+        }
+        drop(queue);
+
+        let engine = writer.await.unwrap();
+        assert_eq!(last, 160);
+        assert_eq!(engine.last_durable_sequence(), last);
+    }
+
+    #[tokio::test]
+    async fn test_queued_delete_if() {
+        let temp_dir = tempdir().unwrap();
+        let engine = Engine::new(temp_dir.path()).await.unwrap();
+        let (queue, writer) = WriteQueue::spawn(engine, 16);
+        queue.put(b"lease", b"owner-3").await.unwrap();
+
+        // Only the holder's release goes through
+        let mut tasks = Vec::new();
+        for owner in 0..8 {
+            let queue = queue.clone();
+            tasks.push(tokio::spawn(async move {
+                let expected = format!("owner-{}", owner);
+                queue
+                    .delete_if(b"lease", expected.as_bytes())
+                    .await
+                    .unwrap()
+            }));
+        }
+        let mut released = Vec::new();
+        for task in tasks {
+            released.push(task.await.unwrap());
+        }
+        assert_eq!(released.iter().filter(|r| r.is_some()).count(), 1);
+        assert!(released[3].is_some());
+
+        drop(queue);
+        let engine = writer.await.unwrap();
+        assert_eq!(engine.get(b"lease").await.unwrap(), None);
+    }
+
+    // The clock only advances while every task waits, so slow writes never
+    // count as idle time
+    #[tokio::test(start_paused = true)]
+    async fn test_writer_runs_maintenance_when_idle() {
+        let temp_dir = tempdir().unwrap();
+        let config = EngineConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            memtable_size: 256,
+            compaction: CompactionOptions {
+                level0_file_trigger: 2,
+                auto_compactions: 0,
+                ..Default::default()
+            },
+            maintenance: Some(MaintenanceOptions {
+                interval: Duration::from_secs(3600),
+                idle_after: Some(Duration::from_millis(20)),
+                max_compactions: 4,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let engine = Engine::with_config(config).await.unwrap();
+        let (queue, writer) = WriteQueue::spawn(engine, 16);
+
+        for i in 0..50 {
+            let key = format!("key{:02}", i);
+            queue.put(key.as_bytes(), &[b'x'; 32]).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        drop(queue);
+        let engine = writer.await.unwrap();
+        // Several flushes were merged once the writes paused
+        assert!(engine.stats().sstable_count <= 2);
+        assert_eq!(engine.scan::<&[u8], _>(..).await.unwrap().len(), 50);
+    }
+}
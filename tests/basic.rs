@@ -1,12 +1,13 @@
 //! Basic integration tests for RustEdgeDB Engine
-//! 
+//!
 //! Tests basic functionality, persistence, compaction, and consistency
 //! across MemTable and SSTable components.
 
+use bytes::Bytes;
+use rustedgedb::compaction::CompactionOptions;
 use rustedgedb::engine::{Engine, EngineConfig};
 use rustedgedb::sstable::CompressionType;
 use tempfile::tempdir;
-use tokio;
 
 /// Test basic put/get/delete operations
 #[tokio::test]
@@ -25,15 +26,15 @@ async fn test_basic_operations() {
     // Test get operations
     assert_eq!(
         engine.get(b"user:1").await.unwrap(),
-        Some(b"John Doe".to_vec())
+        Some(Bytes::from_static(b"John Doe"))
     );
     assert_eq!(
         engine.get(b"user:2").await.unwrap(),
-        Some(b"Jane Smith".to_vec())
+        Some(Bytes::from_static(b"Jane Smith"))
     );
     assert_eq!(
         engine.get(b"config:theme").await.unwrap(),
-        Some(b"dark".to_vec())
+        Some(Bytes::from_static(b"dark"))
     );
 
     // Test delete operation
@@ -44,7 +45,7 @@ async fn test_basic_operations() {
     engine.put(b"user:2", b"Jane Doe").await.unwrap();
     assert_eq!(
         engine.get(b"user:2").await.unwrap(),
-        Some(b"Jane Doe".to_vec())
+        Some(Bytes::from_static(b"Jane Doe"))
     );
 
     // Test non-existent key
@@ -60,63 +61,75 @@ async fn test_persistence_across_restart() {
     // Create engine and add data
     {
         let mut engine = Engine::new(engine_path).await.unwrap();
-        
+
         // Add enough data to ensure MemTable is actually used
         for i in 0..100 {
             let key = format!("persistent:{}", i);
             let value = format!("value{}", i);
             engine.put(key.as_bytes(), value.as_bytes()).await.unwrap();
         }
-        
+
         // Check stats before flush
         let stats_before = engine.stats();
-        println!("Stats before flush: sstable_count={}, memtable_size={}", 
-                 stats_before.sstable_count, stats_before.memtable_size);
-        
+        println!(
+            "Stats before flush: sstable_count={}, memtable_size={}",
+            stats_before.sstable_count, stats_before.memtable_size
+        );
+
         // Force flush to ensure data is persisted
         engine.force_flush().await.unwrap();
-        
+
         // Check stats after flush
         let stats_after = engine.stats();
-        println!("Stats after flush: sstable_count={}, memtable_size={}", 
-                 stats_after.sstable_count, stats_after.memtable_size);
-        
+        println!(
+            "Stats after flush: sstable_count={}, memtable_size={}",
+            stats_after.sstable_count, stats_after.memtable_size
+        );
+
         // Try to get data from SSTable
         let value0_from_sstable = engine.get(b"persistent:0").await.unwrap();
-        println!("Retrieved persistent:0 from SSTable: {:?}", value0_from_sstable);
-        
+        println!(
+            "Retrieved persistent:0 from SSTable: {:?}",
+            value0_from_sstable
+        );
+
         // Verify data before close
         assert_eq!(
             engine.get(b"persistent:0").await.unwrap(),
-            Some(b"value0".to_vec())
+            Some(Bytes::from_static(b"value0"))
         );
         assert_eq!(
             engine.get(b"persistent:99").await.unwrap(),
-            Some(b"value99".to_vec())
+            Some(Bytes::from_static(b"value99"))
         );
-        
+
         engine.close().await.unwrap();
     }
 
     // Reopen engine and verify persistence
     let engine = Engine::new(engine_path).await.unwrap();
-    
+
     // Check stats first
     let stats = engine.stats();
-    println!("Engine stats after restart: sstable_count={}, memtable_size={}", 
-             stats.sstable_count, stats.memtable_size);
-    
+    println!(
+        "Engine stats after restart: sstable_count={}, memtable_size={}",
+        stats.sstable_count, stats.memtable_size
+    );
+
     // Check that data persisted
     let value0 = engine.get(b"persistent:0").await.unwrap();
     println!("Retrieved persistent:0 = {:?}", value0);
-    assert_eq!(value0, Some(b"value0".to_vec()));
-    
+    assert_eq!(value0, Some(Bytes::from_static(b"value0")));
+
     let value99 = engine.get(b"persistent:99").await.unwrap();
     println!("Retrieved persistent:99 = {:?}", value99);
-    assert_eq!(value99, Some(b"value99".to_vec()));
+    assert_eq!(value99, Some(Bytes::from_static(b"value99")));
 
     // Check stats
-    assert!(stats.sstable_count > 0, "Data should have been flushed to SSTable");
+    assert!(
+        stats.sstable_count > 0,
+        "Data should have been flushed to SSTable"
+    );
 }
 
 /// Test compaction correctness
@@ -131,6 +144,7 @@ async fn test_compaction_correctness() {
         memtable_size: 1024 * 1024, // 1MB to allow more entries
         compression: CompressionType::None,
         max_levels: 7,
+        ..Default::default()
     };
 
     let mut engine = Engine::with_config(config).await.unwrap();
@@ -142,7 +156,7 @@ async fn test_compaction_correctness() {
             let value = format!("value{}", i);
             engine.put(key.as_bytes(), value.as_bytes()).await.unwrap();
         }
-        
+
         // Force flush to create new SSTable
         engine.force_flush().await.unwrap();
     }
@@ -152,12 +166,13 @@ async fn test_compaction_correctness() {
         for i in 0..5 {
             let key = format!("batch{}:key{}", batch, i);
             let expected_value = format!("value{}", i);
-            
+
             let actual_value = engine.get(key.as_bytes()).await.unwrap();
             assert_eq!(
                 actual_value,
-                Some(expected_value.as_bytes().to_vec()),
-                "Key: {}", key
+                Some(Bytes::from(expected_value)),
+                "Key: {}",
+                key
             );
         }
     }
@@ -169,10 +184,10 @@ async fn test_compaction_correctness() {
     // Test deletion and re-insertion
     engine.delete(b"batch0:key0").await.unwrap();
     engine.put(b"batch0:key0", b"new_value").await.unwrap();
-    
+
     assert_eq!(
         engine.get(b"batch0:key0").await.unwrap(),
-        Some(b"new_value".to_vec())
+        Some(Bytes::from_static(b"new_value"))
     );
 }
 
@@ -186,21 +201,21 @@ async fn test_crash_recovery() {
     let mut engine = Engine::new(engine_path).await.unwrap();
     engine.put(b"recovery:1", b"data1").await.unwrap();
     engine.put(b"recovery:2", b"data2").await.unwrap();
-    
+
     // Simulate crash by dropping engine without proper close
     drop(engine);
 
     // Reopen engine and verify recovery
     let engine = Engine::new(engine_path).await.unwrap();
-    
+
     // Check that data was recovered
     assert_eq!(
         engine.get(b"recovery:1").await.unwrap(),
-        Some(b"data1".to_vec())
+        Some(Bytes::from_static(b"data1"))
     );
     assert_eq!(
         engine.get(b"recovery:2").await.unwrap(),
-        Some(b"data2".to_vec())
+        Some(Bytes::from_static(b"data2"))
     );
 }
 
@@ -221,25 +236,27 @@ async fn test_sequence_number_continuity() {
         (b"seq:1", Some(b"new_value1")), // Re-insert
     ];
 
-    for (key, value) in operations {
-        match value {
+    // Each write is assigned the next sequence number
+    for (expected, (key, value)) in (1..).zip(operations) {
+        let sequence = match value {
             Some(val) => engine.put(key, val).await.unwrap(),
             None => engine.delete(key).await.unwrap(),
-        }
+        };
+        assert_eq!(sequence, expected);
     }
 
     // Verify final state
     assert_eq!(
         engine.get(b"seq:1").await.unwrap(),
-        Some(b"new_value1".to_vec())
+        Some(Bytes::from_static(b"new_value1"))
     );
     assert_eq!(
         engine.get(b"seq:2").await.unwrap(),
-        Some(b"value2".to_vec())
+        Some(Bytes::from_static(b"value2"))
     );
     assert_eq!(
         engine.get(b"seq:3").await.unwrap(),
-        Some(b"value3".to_vec())
+        Some(Bytes::from_static(b"value3"))
     );
 }
 
@@ -256,13 +273,13 @@ async fn test_concurrent_access() {
         for i in 0..10 {
             let key = format!("task{}:key{}", task_id, i);
             let value = format!("value{}", i);
-            
+
             // Write operation
             engine.put(key.as_bytes(), value.as_bytes()).await.unwrap();
-            
+
             // Read operation
             let retrieved = engine.get(key.as_bytes()).await.unwrap();
-            assert_eq!(retrieved, Some(value.as_bytes().to_vec()));
+            assert_eq!(retrieved, Some(Bytes::from(value)));
         }
     }
 
@@ -271,12 +288,13 @@ async fn test_concurrent_access() {
         for i in 0..10 {
             let key = format!("task{}:key{}", task_id, i);
             let expected_value = format!("value{}", i);
-            
+
             let actual_value = engine.get(key.as_bytes()).await.unwrap();
             assert_eq!(
                 actual_value,
-                Some(expected_value.as_bytes().to_vec()),
-                "Key: {}", key
+                Some(Bytes::from(expected_value)),
+                "Key: {}",
+                key
             );
         }
     }
@@ -296,14 +314,17 @@ async fn test_edge_cases() {
 
     // Test empty value (should work)
     engine.put(b"empty_value", b"").await.unwrap();
-    assert_eq!(engine.get(b"empty_value").await.unwrap(), Some(b"".to_vec()));
+    assert_eq!(
+        engine.get(b"empty_value").await.unwrap(),
+        Some(Bytes::from_static(b""))
+    );
 
     // Test very long key (within limits)
     let long_key = vec![b'x'; 1000];
     engine.put(&long_key, b"long_key_value").await.unwrap();
     assert_eq!(
         engine.get(&long_key).await.unwrap(),
-        Some(b"long_key_value".to_vec())
+        Some(Bytes::from_static(b"long_key_value"))
     );
 
     // Test very long value (within limits)
@@ -311,7 +332,7 @@ async fn test_edge_cases() {
     engine.put(b"long_value_key", &long_value).await.unwrap();
     assert_eq!(
         engine.get(b"long_value_key").await.unwrap(),
-        Some(long_value)
+        Some(Bytes::from(long_value))
     );
 }
 
@@ -327,6 +348,12 @@ async fn test_wal_rotation_and_flushing() {
         memtable_size: 1024, // 1KB to ensure multiple flushes
         compression: CompressionType::None,
         max_levels: 7,
+        // Keep every flushed table in level 0
+        compaction: CompactionOptions {
+            auto_compactions: 0,
+            ..Default::default()
+        },
+        ..Default::default()
     };
 
     let mut engine = Engine::with_config(config).await.unwrap();
@@ -336,7 +363,7 @@ async fn test_wal_rotation_and_flushing() {
         let key = format!("flush:key{}", i);
         let value = format!("value{}", i);
         engine.put(key.as_bytes(), value.as_bytes()).await.unwrap();
-        
+
         // Force flush every 5 entries to ensure multiple SSTables
         if (i + 1) % 5 == 0 {
             engine.force_flush().await.unwrap();
@@ -350,18 +377,22 @@ async fn test_wal_rotation_and_flushing() {
     for i in 0..20 {
         let key = format!("flush:key{}", i);
         let expected_value = format!("value{}", i);
-        
+
         let actual_value = engine.get(key.as_bytes()).await.unwrap();
         assert_eq!(
             actual_value,
-            Some(expected_value.as_bytes().to_vec()),
-            "Key: {}", key
+            Some(Bytes::from(expected_value)),
+            "Key: {}",
+            key
         );
     }
 
     // Check that we have multiple SSTables
     let stats = engine.stats();
-    assert!(stats.sstable_count > 1, "Should have multiple SSTables from flushes");
+    assert!(
+        stats.sstable_count > 1,
+        "Should have multiple SSTables from flushes"
+    );
 }
 
 /// Test data consistency across MemTable and SSTables
@@ -373,44 +404,56 @@ async fn test_data_consistency() {
     let mut engine = Engine::new(engine_path).await.unwrap();
 
     // Add data to MemTable
-    engine.put(b"consistency:1", b"memtable_value").await.unwrap();
-    engine.put(b"consistency:2", b"memtable_value2").await.unwrap();
+    engine
+        .put(b"consistency:1", b"memtable_value")
+        .await
+        .unwrap();
+    engine
+        .put(b"consistency:2", b"memtable_value2")
+        .await
+        .unwrap();
 
     // Force flush to SSTable
     engine.force_flush().await.unwrap();
 
     // Add more data to new MemTable
-    engine.put(b"consistency:3", b"new_memtable_value").await.unwrap();
-    engine.put(b"consistency:1", b"updated_value").await.unwrap(); // Update existing key
+    engine
+        .put(b"consistency:3", b"new_memtable_value")
+        .await
+        .unwrap();
+    engine
+        .put(b"consistency:1", b"updated_value")
+        .await
+        .unwrap(); // Update existing key
 
     // Verify consistency: MemTable should override SSTable
     assert_eq!(
         engine.get(b"consistency:1").await.unwrap(),
-        Some(b"updated_value".to_vec())
+        Some(Bytes::from_static(b"updated_value"))
     );
     assert_eq!(
         engine.get(b"consistency:2").await.unwrap(),
-        Some(b"memtable_value2".to_vec())
+        Some(Bytes::from_static(b"memtable_value2"))
     );
     assert_eq!(
         engine.get(b"consistency:3").await.unwrap(),
-        Some(b"new_memtable_value".to_vec())
+        Some(Bytes::from_static(b"new_memtable_value"))
     );
 
     // Force another flush and verify consistency
     engine.force_flush().await.unwrap();
-    
+
     assert_eq!(
         engine.get(b"consistency:1").await.unwrap(),
-        Some(b"updated_value".to_vec())
+        Some(Bytes::from_static(b"updated_value"))
     );
     assert_eq!(
         engine.get(b"consistency:2").await.unwrap(),
-        Some(b"memtable_value2".to_vec())
+        Some(Bytes::from_static(b"memtable_value2"))
     );
     assert_eq!(
         engine.get(b"consistency:3").await.unwrap(),
-        Some(b"new_memtable_value".to_vec())
+        Some(Bytes::from_static(b"new_memtable_value"))
     );
 }
 
@@ -437,18 +480,22 @@ async fn test_large_dataset() {
     for i in 0..num_keys {
         let key = format!("large:key{}", i);
         let expected_value = format!("value{}_with_some_additional_data_to_make_it_larger", i);
-        
+
         let actual_value = engine.get(key.as_bytes()).await.unwrap();
         assert_eq!(
             actual_value,
-            Some(expected_value.as_bytes().to_vec()),
-            "Key: {}", key
+            Some(Bytes::from(expected_value)),
+            "Key: {}",
+            key
         );
     }
 
     // Check stats
     let stats = engine.stats();
-    assert!(stats.sstable_count > 0, "Large dataset should have been flushed");
+    assert!(
+        stats.sstable_count > 0,
+        "Large dataset should have been flushed"
+    );
 }
 
 /// Test configuration options
@@ -458,26 +505,34 @@ async fn test_configuration_options() {
     let engine_path = temp_dir.path();
 
     // Test different compression types
-    for compression in [CompressionType::None, CompressionType::LZ4, CompressionType::Zstd] {
+    for compression in [
+        CompressionType::None,
+        CompressionType::LZ4,
+        CompressionType::Zstd,
+    ] {
         let config = EngineConfig {
             data_dir: engine_path.join(format!("compression_{:?}", compression)),
             memtable_size: 1024 * 1024, // 1MB
             compression,
             max_levels: 5,
+            ..Default::default()
         };
 
         let mut engine = Engine::with_config(config).await.unwrap();
-        
+
         // Add some data
-        engine.put(b"config:test", b"compression_test").await.unwrap();
+        engine
+            .put(b"config:test", b"compression_test")
+            .await
+            .unwrap();
         engine.force_flush().await.unwrap();
-        
+
         // Verify data
         assert_eq!(
             engine.get(b"config:test").await.unwrap(),
-            Some(b"compression_test".to_vec())
+            Some(Bytes::from_static(b"compression_test"))
         );
-        
+
         engine.close().await.unwrap();
     }
 }
@@ -504,7 +559,7 @@ async fn test_error_handling() {
     engine.put(b"valid:key", b"valid_value").await.unwrap();
     assert_eq!(
         engine.get(b"valid:key").await.unwrap(),
-        Some(b"valid_value".to_vec())
+        Some(Bytes::from_static(b"valid_value"))
     );
 }
 
@@ -524,13 +579,13 @@ async fn test_engine_statistics() {
     // Add data and check stats
     engine.put(b"stats:1", b"value1").await.unwrap();
     engine.put(b"stats:2", b"value2").await.unwrap();
-    
+
     let stats_after_data = engine.stats();
     assert!(stats_after_data.memtable_size > 0);
 
     // Force flush and check stats
     engine.force_flush().await.unwrap();
-    
+
     let stats_after_flush = engine.stats();
     assert!(stats_after_flush.sstable_count > 0);
     assert_eq!(stats_after_flush.memtable_size, 0); // New MemTable should be empty
@@ -552,13 +607,13 @@ async fn test_graceful_shutdown() {
 
     // Reopen and verify data persisted
     let engine = Engine::new(engine_path).await.unwrap();
-    
+
     assert_eq!(
         engine.get(b"shutdown:1").await.unwrap(),
-        Some(b"value1".to_vec())
+        Some(Bytes::from_static(b"value1"))
     );
     assert_eq!(
         engine.get(b"shutdown:2").await.unwrap(),
-        Some(b"value2".to_vec())
+        Some(Bytes::from_static(b"value2"))
     );
 }
@@ -26,6 +26,7 @@ mod tests {
 
     #[test]
     fn test_sstable_workflow() {
+        use bytes::Bytes;
         use rustedgedb::memtable::MemTable;
         use rustedgedb::sstable::{CompressionType, SSTable};
         use tempfile::tempdir;
@@ -41,18 +42,18 @@ mod tests {
 
         // Flush to SSTable
         let sstable_path = temp_dir.path().join("users.sst");
-        let mut sstable =
+        let sstable =
             SSTable::from_memtable(&sstable_path, &memtable, CompressionType::None).unwrap();
 
         // Verify data persistence
         assert_eq!(sstable.get(b"user:1").unwrap(), None); // Deleted
         assert_eq!(
             sstable.get(b"user:2").unwrap(),
-            Some(b"Jane Smith".to_vec())
+            Some(Bytes::from_static(b"Jane Smith"))
         );
         assert_eq!(
             sstable.get(b"config:theme").unwrap(),
-            Some(b"dark".to_vec())
+            Some(Bytes::from_static(b"dark"))
         );
 
         // Test non-existent key